@@ -0,0 +1,114 @@
+#[cfg(any(feature = "grpc", feature = "mqtt"))]
+use std::collections::VecDeque;
+use crate::validation::RejectReason;
+use crate::MainArg;
+
+/// What became of a value a network source handed on, once the worker committed to it.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum AckOutcome {
+    /// Classified and sent on to the logger.
+    #[default]
+    Committed,
+    /// Sent to the rejects side channel instead.
+    Rejected(RejectReason),
+    /// Dropped as a showstopper after crashing the worker too often.
+    Dropped,
+    /// Left unprocessed when the worker's drain deadline passed, and written to the backlog file if there is one.
+    Spilled,
+}
+
+/// The worker's word on one value, sent back to the source with `--ack-after-commit`.
+/// `seq` counts the values the worker has taken from its input since the start, which is the
+/// same number the source gave the value when it sent it, as the channel between them keeps order.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceAck {
+    pub seq: u64,
+    pub outcome: AckOutcome,
+}
+
+/// Whether the worker owes the source acknowledgements: asked for, and a network source feeds it.
+pub(crate) fn enabled(args: &MainArg) -> bool {
+    #[cfg(any(feature = "grpc", feature = "mqtt"))]
+    if args.ack_after_commit {
+        return !matches!(crate::wiring::source(args), crate::wiring::Source::Generator | crate::wiring::Source::Queue | crate::wiring::Source::Tcp);
+    }
+    let _ = args; // only read when a network source is compiled in
+    false
+}
+
+/// Values a source keeps waiting on the worker at once. Past it the oldest is evicted and its
+/// producer answered as if acknowledgements were off, so a stalled worker cannot grow the table forever.
+#[cfg(any(feature = "grpc", feature = "mqtt"))]
+const MAX_AWAITING: usize = 4096;
+
+/// Values a source has sent on but not heard back about, oldest first, each with what it needs
+/// to answer its producer. Lives in the source's state so a restart still answers them.
+#[cfg(any(feature = "grpc", feature = "mqtt"))]
+pub(crate) struct AwaitingAck<T> {
+    waiting: VecDeque<(u64, T)>,
+    pub(crate) evicted: u64,
+}
+
+#[cfg(any(feature = "grpc", feature = "mqtt"))]
+impl<T> AwaitingAck<T> {
+    pub(crate) fn new() -> Self {
+        AwaitingAck { waiting: VecDeque::new(), evicted: 0 }
+    }
+
+    /// Adds a value to wait on, handing back the oldest, with its sequence, if that makes one too
+    /// many. Its value is still in the pipeline; only its answer no longer waits for the commit.
+    pub(crate) fn push(&mut self, seq: u64, producer: T) -> Option<(u64, T)> {
+        self.waiting.push_back((seq, producer));
+        if self.waiting.len() <= MAX_AWAITING {
+            return None;
+        }
+        self.evicted += 1;
+        self.waiting.pop_front()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.waiting.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.waiting.is_empty()
+    }
+
+    /// Takes out everything `ack` settles with its outcome. The worker acknowledges in order,
+    /// so anything older still waiting has had its ack lost and can only have been committed.
+    pub(crate) fn settle(&mut self, ack: SourceAck) -> Vec<(T, AckOutcome)> {
+        let mut settled = Vec::new();
+        while let Some(&(seq, _)) = self.waiting.front()
+            && seq <= ack.seq {
+            let (_, producer) = self.waiting.pop_front().expect("checked above");
+            settled.push((producer, if seq == ack.seq { ack.outcome } else { AckOutcome::Committed }));
+        }
+        settled
+    }
+}
+
+#[cfg(all(test, any(feature = "grpc", feature = "mqtt")))]
+pub(crate) mod ack_tests {
+    use super::*;
+
+    #[test]
+    fn test_settle_in_order() {
+        let mut awaiting = AwaitingAck::new();
+        for seq in 1..=4 {
+            awaiting.push(seq, seq * 10);
+        }
+        let rejected = AckOutcome::Rejected(RejectReason::OutOfRange);
+        assert_eq!(awaiting.settle(SourceAck { seq: 2, outcome: rejected }), [(10, AckOutcome::Committed), (20, rejected)]);
+        // a repeat, as after a worker restart, settles nothing twice
+        assert_eq!(awaiting.settle(SourceAck { seq: 2, outcome: rejected }), []);
+        assert_eq!(awaiting.settle(SourceAck { seq: 3, outcome: AckOutcome::Spilled }), [(30, AckOutcome::Spilled)]);
+        assert_eq!(awaiting.len(), 1);
+
+        // past the cap the oldest is handed back, and its late ack settles nothing
+        let mut crowded = AwaitingAck::new();
+        assert!((1..=MAX_AWAITING as u64).all(|seq| crowded.push(seq, seq).is_none()));
+        assert_eq!(crowded.push(MAX_AWAITING as u64 + 1, 0), Some((1, 1)));
+        assert_eq!((crowded.len(), crowded.evicted), (MAX_AWAITING, 1));
+        assert_eq!(crowded.settle(SourceAck { seq: 1, outcome: AckOutcome::Committed }), []);
+    }
+}
@@ -0,0 +1,153 @@
+use std::fmt::Debug;
+use std::sync::{Arc, Condvar, Mutex};
+use steady_state::*;
+
+/// One message an `AssertSink` waits for.
+#[derive(Clone)]
+enum Expectation<T> {
+    Equals(T),
+    Matches(&'static str, Arc<dyn Fn(&T) -> bool + Send + Sync>),
+}
+
+impl<T: Debug + PartialEq> Expectation<T> {
+    fn check(&self, message: &T) -> Result<(), String> {
+        match self {
+            Expectation::Equals(expected) if expected == message => Ok(()),
+            Expectation::Equals(expected) => Err(format!("expected {:?}, got {:?}", expected, message)),
+            Expectation::Matches(_, predicate) if predicate(message) => Ok(()),
+            Expectation::Matches(description, _) => Err(format!("expected {}, got {:?}", description, message)),
+        }
+    }
+}
+
+/// How far the sink got: expectations met so far, and the first one broken.
+#[derive(Default)]
+struct Progress {
+    met: usize,
+    failure: Option<String>,
+}
+
+/// A test actor that reads a channel and checks each message against an expected sequence
+/// as it arrives. Unlike `assert_steady_rx_eq_take!`, which needs the graph stopped first,
+/// a test can wait on the sink's handle while the graph runs. The wait ends as soon as the
+/// sequence is complete or a message breaks it, and a message past the end breaks it too.
+#[derive(Clone)]
+pub(crate) struct AssertSink<T> {
+    expected: Vec<Expectation<T>>,
+}
+
+impl<T: Clone + Debug + PartialEq + Send + Sync + 'static> AssertSink<T> {
+    pub(crate) fn new() -> Self {
+        AssertSink { expected: Vec::new() }
+    }
+
+    /// The next message must equal `message`.
+    pub(crate) fn expect(mut self, message: T) -> Self {
+        self.expected.push(Expectation::Equals(message));
+        self
+    }
+
+    pub(crate) fn expect_all(mut self, messages: impl IntoIterator<Item = T>) -> Self {
+        self.expected.extend(messages.into_iter().map(Expectation::Equals));
+        self
+    }
+
+    /// The next message must pass `predicate`; `description` names it in the failure.
+    pub(crate) fn expect_that(mut self, description: &'static str, predicate: impl Fn(&T) -> bool + Send + Sync + 'static) -> Self {
+        self.expected.push(Expectation::Matches(description, Arc::new(predicate)));
+        self
+    }
+
+    /// Adds the sink to the graph as an actor named `name` reading `rx`. The progress lives
+    /// in the returned handle, so a restarted sink carries on where it was.
+    pub(crate) fn build(self, graph: &mut Graph, name: &'static str, rx: LazySteadyRx<T>) -> AssertHandle {
+        let handle = AssertHandle { total: self.expected.len(), progress: Default::default() };
+        let progress = handle.progress.clone();
+        graph.actor_builder().with_name(name)
+            .build(move |context| run(context, rx.clone(), self.clone(), progress.clone()), SoloAct);
+        handle
+    }
+}
+
+async fn run<A: SteadyActor, T: Debug + PartialEq>(
+    mut actor: A,
+    rx: SteadyRx<T>,
+    sink: AssertSink<T>,
+    progress: Arc<(Mutex<Progress>, Condvar)>,
+) -> Result<(), Box<dyn Error>> {
+    let mut rx = rx.lock().await;
+    while actor.is_running(|| rx.is_closed_and_empty()) {
+        await_for_all!(actor.wait_avail(&mut rx, 1));
+        while let Some(message) = actor.try_take(&mut rx) {
+            let (lock, changed) = &*progress;
+            let mut progress = lock.lock().expect("assert sink progress");
+            if progress.failure.is_some() {
+                continue;
+            }
+            let outcome = match sink.expected.get(progress.met) {
+                Some(expectation) => expectation.check(&message),
+                None => Err(format!("unexpected {:?} after all {} expected messages", message, sink.expected.len())),
+            };
+            match outcome {
+                Ok(()) => progress.met += 1,
+                Err(e) => progress.failure = Some(format!("message #{}: {}", progress.met + 1, e)),
+            }
+            changed.notify_all();
+        }
+    }
+    Ok(())
+}
+
+/// The test's side of an `AssertSink`.
+pub(crate) struct AssertHandle {
+    total: usize,
+    progress: Arc<(Mutex<Progress>, Condvar)>,
+}
+
+impl AssertHandle {
+    /// Blocks until every expected message arrived, one broke the sequence, or `timeout` passed.
+    /// Called again after the graph stops, it also catches messages past the end.
+    pub(crate) fn wait(&self, timeout: Duration) -> Result<(), String> {
+        let (lock, changed) = &*self.progress;
+        let progress = lock.lock().expect("assert sink progress");
+        let (progress, _) = changed
+            .wait_timeout_while(progress, timeout, |p| p.failure.is_none() && p.met < self.total)
+            .expect("assert sink progress");
+        if let Some(failure) = &progress.failure {
+            return Err(failure.clone());
+        }
+        if progress.met < self.total {
+            return Err(format!("only {} of {} expected messages arrived within {:?}", progress.met, self.total, timeout));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod assert_sink_tests {
+    use super::*;
+    use crate::actor::scripted::ScriptedSource;
+
+    #[test]
+    fn test_sink_fails_fast_on_mismatch() -> Result<(), Box<dyn Error>> {
+        let mut graph = GraphBuilder::for_testing().build(crate::MainArg::default());
+        let (good_tx, good_rx) = graph.channel_builder().build::<u64>();
+        let (bad_tx, bad_rx) = graph.channel_builder().build::<u64>();
+        ScriptedSource::new().emit_all_at(0, [1, 2, 3]).emit_at(200, 4).build(&mut graph, "Good", good_tx);
+        ScriptedSource::new().emit_all_at(0, [1, 5]).emit_at(10_000, 3).build(&mut graph, "Bad", bad_tx);
+        let good = AssertSink::new().expect(1).expect_that("an even number", |n| n % 2 == 0).expect(3)
+            .build(&mut graph, "ExpectGood", good_rx);
+        let bad = AssertSink::new().expect_all([1, 2, 3]).build(&mut graph, "ExpectBad", bad_rx);
+
+        graph.start();
+        let started = Instant::now();
+        assert_eq!(bad.wait(Duration::from_secs(5)), Err("message #2: expected 2, got 5".to_string()));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        good.wait(Duration::from_secs(1))?;
+        std::thread::sleep(Duration::from_millis(300));
+        graph.request_shutdown();
+        graph.block_until_stopped(Duration::from_secs(1))?;
+        assert_eq!(good.wait(Duration::ZERO), Err("message #4: unexpected 4 after all 3 expected messages".to_string()));
+        Ok(())
+    }
+}
@@ -0,0 +1,672 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use serde_json::json;
+use steady_state::*;
+use crate::state_lock::lock_state;
+use crate::actor::heartbeat::PipelineCommand;
+use crate::actor::reconciler::{StatusReply, StatusRequest, DATA_CHANNELS, LANE_NAMES};
+use crate::build_info::BUILD;
+use crate::events;
+use crate::tenant;
+use steady_state::actor_builder::{ActorBuilder, ScheduleAs};
+use crate::wiring::{RobustActor, Wiring};
+
+/// How often the listener is checked for new connections.
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long a status query waits for the reconciler before answering without it.
+const STATUS_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// A connection that sends nothing for this long is closed, so an idle client
+/// cannot keep the actor from noticing shutdown.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// One line of the control protocol.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum ControlCommand {
+    /// Stop sending heartbeats, which holds every stage behind them.
+    Pause,
+    Resume,
+    /// Stop the logger writing to its sink, e.g. while an external tool rotates it.
+    /// Messages wait in the logger's channel and are caught up on `resume-logger`.
+    PauseLogger,
+    ResumeLogger,
+    /// Reply with a JSON document of live counters, backlogs and restarts.
+    Status,
+    /// As status, and also write it to the log so it is kept with the run.
+    Snapshot,
+    /// Stop the pipeline gracefully; the final report is still written.
+    Kill,
+    /// Snapshot the status to the log, then stop as `kill` does, with intake first and every
+    /// message in flight flushed to the sinks; the final report counts those flushed.
+    Drain,
+}
+
+impl ControlCommand {
+    /// The access a client needs to run the command.
+    pub(crate) fn needs(self) -> Access {
+        match self {
+            ControlCommand::Status | ControlCommand::Snapshot => Access::Read,
+            ControlCommand::Kill | ControlCommand::Drain => Access::Admin,
+            _ => Access::Operate,
+        }
+    }
+}
+
+/// What a control client may do, each level allowing everything below it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Access {
+    /// status and snapshot.
+    Read,
+    /// Pausing and resuming the pipeline or the logger as well.
+    Operate,
+    /// Stopping the run as well.
+    Admin,
+}
+
+impl Access {
+    fn name(self) -> &'static str {
+        match self {
+            Access::Read => "read",
+            Access::Operate => "operate",
+            Access::Admin => "admin",
+        }
+    }
+}
+
+/// Parses a `--control-tokens` file: one `LEVEL TOKEN` per line, LEVEL being read, operate or admin.
+/// Blank lines and lines starting with `#` are skipped.
+pub(crate) fn parse_tokens(text: &str) -> Result<Vec<(Access, String)>, String> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(n, line)| {
+            let bad = || format!("line {}: expected 'read|operate|admin TOKEN'", n + 1);
+            let (level, token) = line.trim().split_once(char::is_whitespace).ok_or_else(bad)?;
+            let access = match level.to_ascii_lowercase().as_str() {
+                "read" => Access::Read,
+                "operate" => Access::Operate,
+                "admin" => Access::Admin,
+                _ => return Err(bad()),
+            };
+            Ok((access, token.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Reads the token file. A file others may read is used, but warned about.
+fn load_tokens(path: &Path) -> io::Result<Vec<(Access, String)>> {
+    let text = fs::read_to_string(path)?;
+    if fs::metadata(path)?.permissions().mode() & 0o077 != 0 {
+        warn!("Control tokens in {} can be read by other users; chmod 600 it", path.display());
+    }
+    parse_tokens(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))
+}
+
+/// The access a token grants, if any. Every token is compared over the whole offered token,
+/// whatever its own length and with no early exit, so how long this takes tells a guesser
+/// neither how close they came nor how long the token is.
+fn grant(tokens: &[(Access, String)], offered: &str) -> Option<Access> {
+    let same = |token: &str| {
+        let token = token.as_bytes();
+        // A shorter token wraps around, and the length check settles it.
+        if token.is_empty() {
+            return false;
+        }
+        let diff = offered.bytes().enumerate().fold((token.len() != offered.len()) as u8, |diff, (i, b)| diff | (token[i % token.len()] ^ b));
+        diff == 0
+    };
+    tokens.iter().filter(|(_, token)| same(token)).map(|(access, _)| *access).max()
+}
+
+/// The token in an `auth TOKEN` line.
+fn auth_token(line: &str) -> Option<&str> {
+    let (word, token) = line.trim().split_once(char::is_whitespace)?;
+    word.eq_ignore_ascii_case("auth").then(|| token.trim())
+}
+
+/// Commands are single case-insensitive words, one per line.
+pub(crate) fn parse_command(line: &str) -> Option<ControlCommand> {
+    match line.trim().to_ascii_lowercase().as_str() {
+        "pause" => Some(ControlCommand::Pause),
+        "resume" => Some(ControlCommand::Resume),
+        "pause-logger" => Some(ControlCommand::PauseLogger),
+        "resume-logger" => Some(ControlCommand::ResumeLogger),
+        "status" => Some(ControlCommand::Status),
+        "snapshot" => Some(ControlCommand::Snapshot),
+        "kill" => Some(ControlCommand::Kill),
+        "drain" => Some(ControlCommand::Drain),
+        _ => None,
+    }
+}
+
+/// ControlState holds state for the control actor.
+/// The listener lives here so the socket is bound once and survives restarts.
+pub struct ControlState {
+    pub(crate) commands: u64,
+    /// Commands refused for want of access, and connections closed for a wrong token.
+    pub(crate) refused: u64,
+    /// What the operator last asked for; the heartbeat applies it on its next beat.
+    pub(crate) paused: bool,
+    /// Whether the operator last paused or resumed the logger's sink writes.
+    pub(crate) logger_paused: bool,
+    pub(crate) restart_count: u64,
+    /// Ids handed to status queries, so a late reply to an abandoned one is ignored.
+    status_queries: u64,
+    started: Instant,
+    listener: Option<UnixListener>,
+}
+
+/// Entry point for the control actor.
+/// Serves operator commands on a Unix domain socket, which file permissions can
+/// restrict to local users more simply than an HTTP port, and `--control-tokens`
+/// can restrict further by command.
+pub async fn run(
+    actor: SteadyActorShadow,
+    control_tx: SteadyTx<PipelineCommand>,
+    logger_tx: SteadyTx<PipelineCommand>,
+    status_tx: SteadyTx<StatusRequest>,
+    status_rx: SteadyRx<StatusReply>,
+    path: PathBuf,
+    state: SteadyState<ControlState>,
+) -> Result<(), Box<dyn Error>> {
+    let actor = actor.into_spotlight([&status_rx], [&control_tx, &logger_tx, &status_tx]);
+    if actor.use_internal_behavior {
+        internal_behavior(actor, control_tx, logger_tx, status_tx, status_rx, path, state).await
+    } else {
+        actor.simulated_behavior(vec!()).await
+    }
+}
+
+/// The control socket as a pipeline stage, with `--control-socket`.
+pub struct Control;
+
+impl RobustActor for Control {
+    fn name(&self) -> &'static str {
+        crate::NAME_CONTROL
+    }
+    fn inputs(&self) -> &'static [&'static str] {
+        &["status_reply"]
+    }
+    fn outputs(&self) -> &'static [&'static str] {
+        &["control", "logger_control", "status_request"]
+    }
+    fn enabled(&self, args: &crate::MainArg) -> bool {
+        args.control_socket.is_some()
+    }
+    fn build(&self, builder: ActorBuilder, wiring: &Wiring, schedule: ScheduleAs<'_>) {
+        let Some(path) = wiring.args.control_socket.clone() else {
+            return;
+        };
+        let (control_tx, logger_tx, status_tx) = (wiring.control.tx.clone(), wiring.logger_control.tx.clone(), wiring.status_request.tx.clone());
+        let status_rx = wiring.status_reply.rx.clone();
+        let state = wiring.state.control.clone();
+        builder.build(move |context| {
+            crate::priority::apply(&context, crate::NAME_CONTROL);
+            run(context, control_tx.clone(), logger_tx.clone(), status_tx.clone(), status_rx.clone(), path.clone(), state.clone())
+        }, schedule);
+    }
+}
+
+async fn internal_behavior<A: SteadyActor>(
+    mut actor: A,
+    control_tx: SteadyTx<PipelineCommand>,
+    logger_tx: SteadyTx<PipelineCommand>,
+    status_tx: SteadyTx<StatusRequest>,
+    status_rx: SteadyRx<StatusReply>,
+    path: PathBuf,
+    state: SteadyState<ControlState>,
+) -> Result<(), Box<dyn Error>> {
+    let Some(mut state) = lock_state(&actor, crate::NAME_CONTROL, &state, || ControlState {
+        commands: 0,
+        refused: 0,
+        paused: false,
+        logger_paused: false,
+        restart_count: 0,
+        status_queries: 0,
+        started: Instant::now(),
+        listener: None,
+    }).await else {
+        return Ok(());
+    };
+    state.restart_count += 1;
+    let tokens_path = actor.args::<crate::MainArg>().expect("unable to downcast").control_tokens.clone();
+    if state.listener.is_none() {
+        state.listener = Some(bind(&path)?);
+        match &tokens_path {
+            Some(tokens) => match load_tokens(tokens) {
+                Ok(tokens) => info!("Control listening on {}, {} token(s) required", path.display(), tokens.len()),
+                Err(e) => warn!("Control listening on {}, but refusing every client until its tokens can be read: {}", path.display(), e),
+            },
+            None => info!("Control listening on {}, open to anyone the socket's permissions let in", path.display()),
+        }
+    }
+    info!(
+        "Control starting (restart #{}) with commands: {}, paused: {}",
+        state.restart_count, state.commands, state.paused
+    );
+
+    let mut control_tx = control_tx.lock().await;
+    let mut logger_tx = logger_tx.lock().await;
+    let mut status_tx = status_tx.lock().await;
+    let mut status_rx = status_rx.lock().await;
+    let mut channels = ControlChannels {
+        commands: &mut control_tx,
+        logger: &mut logger_tx,
+        status_tx: &mut status_tx,
+        status_rx: &mut status_rx,
+    };
+
+    while actor.is_running(|| channels.commands.mark_closed() && channels.logger.mark_closed() && channels.status_tx.mark_closed()) {
+        await_for_all!(actor.wait_periodic(ACCEPT_INTERVAL));
+
+        loop {
+            let stream = match state.listener.as_ref().expect("listener bound").accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    warn!("Control accept failed: {}", e);
+                    break;
+                }
+            };
+            if let Err(e) = serve(&mut actor, stream, tokens_path.as_deref(), &mut channels, &mut state).await {
+                warn!("Control connection ended: {}", e);
+            }
+        }
+    }
+
+    let _ = fs::remove_file(&path);
+    info!("Control shutting down. Commands: {}, refused: {}", state.commands, state.refused);
+    Ok(())
+}
+
+/// Binds the socket, replacing one left behind by an earlier run. Anything else
+/// at the path is left alone and fails the bind. Only the owner may connect, with or without
+/// `--control-tokens`: the socket is bound inside a directory only the owner can enter, made
+/// owner-only there, and then linked at `path`, so no one else can reach it at any point.
+/// The staging names are short, so a `path` that fits a socket address still does staged.
+fn bind(path: &Path) -> io::Result<UnixListener> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => fs::remove_file(path)?,
+        Ok(_) => return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{:?} exists and is not a socket", path))),
+        Err(_) => {}
+    }
+    let parent = path.parent().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "control socket path has no file name"))?;
+    let private = parent.join(format!(".c{:x}", std::process::id()));
+    fs::DirBuilder::new().mode(0o700).create(&private)?;
+    let staged = private.join("s");
+    // A link never replaces what may have appeared at `path` since the check above.
+    let bound = UnixListener::bind(&staged)
+        .and_then(|listener| fs::set_permissions(&staged, fs::Permissions::from_mode(0o600)).map(|_| listener))
+        .and_then(|listener| fs::hard_link(&staged, path).map(|_| listener));
+    let _ = fs::remove_file(&staged);
+    fs::remove_dir(&private)?;
+    let listener = bound?;
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+/// The control actor's locked channels, passed down to each command as one.
+struct ControlChannels<'a> {
+    commands: &'a mut Tx<PipelineCommand>,
+    logger: &'a mut Tx<PipelineCommand>,
+    status_tx: &'a mut Tx<StatusRequest>,
+    status_rx: &'a mut Rx<StatusReply>,
+}
+
+/// Answers each line on one connection until the client closes it or goes quiet.
+/// With `--control-tokens` the tokens are read again for every connection, so one taken
+/// out of the file stops working at once, and a client can do nothing until `auth TOKEN`.
+async fn serve<A: SteadyActor>(
+    actor: &mut A,
+    stream: UnixStream,
+    tokens_path: Option<&Path>,
+    channels: &mut ControlChannels<'_>,
+    state: &mut ControlState,
+) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+    let mut reply_to = stream.try_clone()?;
+    let tokens = match tokens_path.map(load_tokens).transpose() {
+        Ok(tokens) => tokens,
+        Err(e) => {
+            warn!("Control refused a client, its tokens could not be read: {}", e);
+            return writeln!(reply_to, "error control tokens are unavailable");
+        }
+    };
+    // Without tokens the socket's permissions are the only guard, and whoever gets in may do anything.
+    let mut access = tokens.is_none().then_some(Access::Admin);
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(token) = auth_token(&line) {
+            match tokens.as_deref().map_or(Some(Access::Admin), |tokens| grant(tokens, token)) {
+                Some(granted) => {
+                    access = Some(granted);
+                    writeln!(reply_to, "ok {}", granted.name())?;
+                    continue;
+                }
+                None => {
+                    state.refused += 1;
+                    warn!("Control refused a client with an unknown token");
+                    events::emit("alert", json!({"kind": "control_unauthorized", "refused": state.refused}));
+                    return writeln!(reply_to, "error unknown token");
+                }
+            }
+        }
+        let reply = match parse_command(&line) {
+            Some(command) if access.is_some_and(|access| access >= command.needs()) => {
+                state.commands += 1;
+                execute(actor, command, channels, state).await
+            }
+            Some(command) => {
+                state.refused += 1;
+                let hint = if access.is_none() { ", send 'auth TOKEN' first" } else { "" };
+                format!("error {} needs {} access{}", line.trim().to_ascii_lowercase(), command.needs().name(), hint)
+            }
+            None => format!("error unknown command '{}', expected auth|pause|resume|pause-logger|resume-logger|status|snapshot|kill|drain", line.trim()),
+        };
+        writeln!(reply_to, "{}", reply)?;
+    }
+    Ok(())
+}
+
+async fn execute<A: SteadyActor>(
+    actor: &mut A,
+    command: ControlCommand,
+    channels: &mut ControlChannels<'_>,
+    state: &mut ControlState,
+) -> String {
+    match command {
+        ControlCommand::Pause | ControlCommand::Resume => {
+            let (pipeline_command, paused) = match command {
+                ControlCommand::Pause => (PipelineCommand::Pause, true),
+                _ => (PipelineCommand::Resume, false),
+            };
+            if actor.try_send(channels.commands, pipeline_command).is_sent() {
+                state.paused = paused;
+                info!("Control: {:?} requested", command);
+                format!("ok {}", if paused { "paused" } else { "running" })
+            } else {
+                "error heartbeat is not taking commands, retry".to_string()
+            }
+        }
+        ControlCommand::PauseLogger | ControlCommand::ResumeLogger => {
+            let paused = command == ControlCommand::PauseLogger;
+            let pipeline_command = if paused { PipelineCommand::Pause } else { PipelineCommand::Resume };
+            if actor.try_send(channels.logger, pipeline_command).is_sent() {
+                state.logger_paused = paused;
+                info!("Control: {:?} requested", command);
+                format!("ok logger {}", if paused { "paused" } else { "running" })
+            } else {
+                "error logger is not taking commands, retry".to_string()
+            }
+        }
+        ControlCommand::Status => {
+            let reply = query_status(actor, channels, state).await;
+            format!("ok {}", status_json(state, reply.as_ref()))
+        }
+        ControlCommand::Snapshot => {
+            let reply = query_status(actor, channels, state).await;
+            let status = status_json(state, reply.as_ref());
+            info!("Control snapshot: {}", status);
+            format!("ok {}", status)
+        }
+        ControlCommand::Kill => {
+            info!("Control: shutdown requested");
+            actor.request_shutdown().await;
+            "ok stopping".to_string()
+        }
+        ControlCommand::Drain => {
+            // The logger marks where the drain began before anything stops, so the count is exact.
+            if !actor.try_send(channels.logger, PipelineCommand::Drain).is_sent() {
+                return "error logger is not taking commands, retry".to_string();
+            }
+            let reply = query_status(actor, channels, state).await;
+            info!("Control drain snapshot: {}", status_json(state, reply.as_ref()));
+            events::emit("shutdown", json!({"phase": "drain_requested"}));
+            actor.request_shutdown().await;
+            "ok draining".to_string()
+        }
+    }
+}
+
+/// Asks the reconciler for the latest counters. None if it is not running or too slow to answer.
+async fn query_status<A: SteadyActor>(
+    actor: &mut A,
+    channels: &mut ControlChannels<'_>,
+    state: &mut ControlState,
+) -> Option<StatusReply> {
+    state.status_queries += 1;
+    let id = state.status_queries;
+    if !actor.try_send(channels.status_tx, StatusRequest { id }).is_sent() {
+        return None;
+    }
+    let deadline = Instant::now() + STATUS_TIMEOUT;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let reply = actor.take_async_with_timeout(channels.status_rx, remaining).await?;
+        if reply.id == id {
+            return Some(reply);
+        }
+    }
+}
+
+/// Renders the status document. Stages that have not published a sample yet are
+/// null, as is everything the reconciler reports when it did not answer.
+/// `backlog` is how many messages sat in the stage's input channels when sampled,
+/// `tenants` the messages logged for each tenant a source labeled, and `channels` the
+/// bytes written to, waiting in and flowing through each data channel. The reconciler's
+/// `in_flight` counts messages between generation and logger commit, null until every lane has reported.
+fn status_json(state: &ControlState, reply: Option<&StatusReply>) -> String {
+    let (stages, tenants, channels, reconciler) = match reply {
+        Some(reply) => {
+            let stages: Vec<String> = LANE_NAMES.iter().zip(reply.stages.iter())
+                .map(|(name, sample)| match sample {
+                    Some(s) => format!(
+                        "\"{}\":{{\"consumed\":{},\"produced\":{},\"backlog\":{},\"starts\":{},\"sample_age_ms\":{}}}",
+                        name, s.consumed, s.produced, s.backlog, s.starts, s.at.elapsed().as_millis()
+                    ),
+                    None => format!("\"{}\":null", name),
+                })
+                .collect();
+            let tenants: Vec<String> = reply.tenants.iter()
+                .map(|t| format!("{}:{}", serde_json::Value::from(tenant::name(t.tenant)), t.logged))
+                .collect();
+            let channels: Vec<String> = DATA_CHANNELS.iter().zip(reply.channels.iter())
+                .map(|((name, _, _), c)| format!(
+                    "\"{}\":{{\"produced_bytes\":{},\"in_flight_bytes\":{},\"in_flight_bytes_max\":{},\"bytes_per_sec\":{},\"bytes_per_sec_max\":{}}}",
+                    name, c.produced, c.in_flight, c.in_flight_max, c.per_sec, c.per_sec_max
+                ))
+                .collect();
+            (
+                format!("{{{}}}", stages.join(",")),
+                format!("{{{}}}", tenants.join(",")),
+                format!("{{{}}}", channels.join(",")),
+                format!(
+                    "{{\"checks\":{},\"inconsistencies\":{},\"starts\":{},\"in_flight\":{},\"in_flight_max\":{}}}",
+                    reply.checks, reply.inconsistencies, reply.starts,
+                    reply.in_flight.map_or("null".to_string(), |count| count.to_string()), reply.in_flight_max
+                ),
+            )
+        }
+        None => ("null".to_string(), "null".to_string(), "null".to_string(), "null".to_string()),
+    };
+    format!(
+        "{{\"build\":{},\"uptime_ms\":{},\"paused\":{},\"logger_paused\":{},\"control\":{{\"commands\":{},\"refused\":{},\"starts\":{}}},\"stages\":{},\"tenants\":{},\"channels\":{},\"reconciler\":{}}}",
+        BUILD.json(), state.started.elapsed().as_millis(), state.paused, state.logger_paused, state.commands, state.refused, state.restart_count, stages, tenants, channels, reconciler
+    )
+}
+
+#[cfg(test)]
+pub(crate) mod control_tests {
+    use std::thread::sleep;
+    use steady_state::*;
+    use crate::actor::reconciler::{ChannelBytes, CounterSample};
+    use crate::tenant::TenantSample;
+    use crate::arg::MainArg;
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command("pause"), Some(ControlCommand::Pause));
+        assert_eq!(parse_command(" Status\r"), Some(ControlCommand::Status));
+        assert_eq!(parse_command("KILL"), Some(ControlCommand::Kill));
+        assert_eq!(parse_command("Pause-Logger"), Some(ControlCommand::PauseLogger));
+        assert_eq!(parse_command("drain"), Some(ControlCommand::Drain));
+        assert_eq!(parse_command("restart"), None);
+    }
+
+    #[test]
+    fn test_tokens_grant_levels() {
+        let tokens = parse_tokens("# operators\nread r-token\n\nOPERATE  o-token\nadmin a-token\n").expect("valid");
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(grant(&tokens, "o-token"), Some(Access::Operate));
+        assert_eq!(grant(&tokens, "a-token"), Some(Access::Admin));
+        assert_eq!(grant(&tokens, "a-toke"), None);
+        assert_eq!(grant(&tokens, "a-tokena-token"), None);
+        assert_eq!(grant(&tokens, ""), None);
+        assert!(parse_tokens("root r-token").is_err());
+        assert!(parse_tokens("read").is_err());
+        assert_eq!(auth_token(" AUTH  r-token "), Some("r-token"));
+        assert_eq!(auth_token("status"), None);
+        assert!(Access::Admin >= ControlCommand::Kill.needs() && Access::Operate < ControlCommand::Kill.needs());
+        assert_eq!(ControlCommand::Snapshot.needs(), Access::Read);
+        assert_eq!(ControlCommand::PauseLogger.needs(), Access::Operate);
+        assert_eq!(ControlCommand::Drain.needs(), Access::Admin);
+    }
+
+    #[test]
+    fn test_bind_replaces_only_a_socket() -> Result<(), Box<dyn Error>> {
+        let path = std::env::temp_dir().join(format!("robust-control-{}.sock", crate::arg::new_run_id()));
+        fs::write(&path, "not a socket")?;
+        assert_eq!(bind(&path).map(|_| ()).map_err(|e| e.kind()), Err(io::ErrorKind::AlreadyExists));
+        assert_eq!(fs::read_to_string(&path)?, "not a socket");
+        fs::remove_file(&path)?;
+
+        // a socket left behind by an earlier run is replaced, owner-only
+        drop(UnixListener::bind(&path)?);
+        let listener = bind(&path)?;
+        assert_eq!(fs::symlink_metadata(&path)?.permissions().mode() & 0o777, 0o600);
+        UnixStream::connect(&path)?;
+        assert!(listener.accept().is_ok());
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_control_socket() -> Result<(), Box<dyn Error>> {
+        let path = std::env::temp_dir().join(format!("robust-control-{}.sock", crate::arg::new_run_id()));
+        let mut graph = GraphBuilder::for_testing().build(MainArg::default());
+        let (control_tx, control_rx) = graph.channel_builder().build();
+        let (logger_tx, logger_rx) = graph.channel_builder().build();
+        let (status_tx, status_request_rx) = graph.channel_builder().build();
+        let (status_reply_tx, status_rx) = graph.channel_builder().build();
+
+        // stands in for the reconciler's answer to the first query
+        let generator = CounterSample::new(5, 5, 0).with_starts(2);
+        status_reply_tx.testing_send_all(vec![StatusReply {
+            id: 1,
+            stages: [Some(generator), None, None],
+            tenants: vec![TenantSample { tenant: crate::tenant::id("control-test-acme"), logged: 9 }],
+            checks: 3,
+            inconsistencies: 0,
+            starts: 1,
+            channels: [ChannelBytes { produced: 160, in_flight: 32, in_flight_max: 64, per_sec: 800, per_sec_max: 1600 }, ChannelBytes::default()],
+            in_flight: Some(4),
+            in_flight_max: 12,
+        }], false);
+
+        let state = new_state();
+        let socket = path.clone();
+        graph.actor_builder()
+            .with_name("UnitTest")
+            .build(move |context| internal_behavior(context, control_tx.clone(), logger_tx.clone(), status_tx.clone(), status_rx.clone(), socket.clone(), state.clone()), SoloAct);
+        graph.start();
+        sleep(Duration::from_millis(200));
+
+        // owner-only from the start, and the directory it was bound in is gone
+        assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o600);
+        let mut private = path.as_os_str().to_owned();
+        private.push(format!(".bind-{}", std::process::id()));
+        assert!(!Path::new(&private).exists());
+        let mut client = UnixStream::connect(&path)?;
+        client.write_all(b"pause\nstatus\nbogus\nresume\nstatus\npause-logger\nresume-logger\n")?;
+        client.shutdown(std::net::Shutdown::Write)?;
+        let replies: Vec<String> = BufReader::new(client).lines().collect::<Result<_, _>>()?;
+
+        graph.request_shutdown();
+        graph.block_until_stopped(Duration::from_secs(1))?;
+
+        assert_eq!(replies.len(), 7);
+        assert_eq!(replies[0], "ok paused");
+        assert!(replies[1].starts_with(&format!("ok {{\"build\":{},\"uptime_ms\":", BUILD.json())));
+        assert!(replies[1].contains("\"paused\":true"));
+        assert!(replies[1].contains("\"generator\":{\"consumed\":5,\"produced\":5,\"backlog\":0,\"starts\":2,"));
+        assert!(replies[1].contains("\"worker\":null"));
+        assert!(replies[1].contains("\"tenants\":{\"control-test-acme\":9}"));
+        assert!(replies[1].contains("\"channels\":{\"generated\":{\"produced_bytes\":160,\"in_flight_bytes\":32,\"in_flight_bytes_max\":64,\"bytes_per_sec\":800,\"bytes_per_sec_max\":1600},\"classified\":{\"produced_bytes\":0,"));
+        assert!(replies[1].ends_with("\"reconciler\":{\"checks\":3,\"inconsistencies\":0,\"starts\":1,\"in_flight\":4,\"in_flight_max\":12}}"));
+        assert!(replies[2].starts_with("error unknown command 'bogus'"));
+        assert_eq!(replies[3], "ok running");
+        // nobody answers the second query, so only the control endpoint's own fields are known
+        assert!(replies[4].ends_with("\"stages\":null,\"tenants\":null,\"channels\":null,\"reconciler\":null}"));
+        assert_eq!(replies[5..], ["ok logger paused", "ok logger running"]);
+        assert_eq!(control_rx.testing_take_all(), vec![PipelineCommand::Pause, PipelineCommand::Resume]);
+        assert_eq!(logger_rx.testing_take_all(), vec![PipelineCommand::Pause, PipelineCommand::Resume]);
+        assert_eq!(status_request_rx.testing_take_all(), vec![StatusRequest { id: 1 }, StatusRequest { id: 2 }]);
+        assert!(!path.exists());
+        Ok(())
+    }
+
+    /// With tokens, a client must authenticate, may run only what its token allows, and is cut
+    /// off for a token the file does not hold.
+    #[test]
+    fn test_control_socket_requires_tokens() -> Result<(), Box<dyn Error>> {
+        let run = crate::arg::new_run_id();
+        let path = std::env::temp_dir().join(format!("robust-control-{}.sock", run));
+        let tokens = std::env::temp_dir().join(format!("robust-control-{}.tokens", run));
+        fs::write(&tokens, "read r-token\nadmin a-token\n")?;
+        fs::set_permissions(&tokens, fs::Permissions::from_mode(0o600))?;
+        let mut graph = GraphBuilder::for_testing().build(MainArg { control_socket: Some(path.clone()), control_tokens: Some(tokens.clone()), ..Default::default() });
+        let (control_tx, control_rx) = graph.channel_builder().build();
+        let (logger_tx, _logger_rx) = graph.channel_builder().build();
+        let (status_tx, _status_request_rx) = graph.channel_builder().build();
+        let (_status_reply_tx, status_rx) = graph.channel_builder().build();
+
+        let state = new_state();
+        let socket = path.clone();
+        graph.actor_builder()
+            .with_name("UnitTest")
+            .build(move |context| internal_behavior(context, control_tx.clone(), logger_tx.clone(), status_tx.clone(), status_rx.clone(), socket.clone(), state.clone()), SoloAct);
+        graph.start();
+        sleep(Duration::from_millis(200));
+        assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o600);
+
+        let talk = |lines: &str| -> Result<Vec<String>, Box<dyn Error>> {
+            let mut client = UnixStream::connect(&path)?;
+            client.write_all(lines.as_bytes())?;
+            client.shutdown(std::net::Shutdown::Write)?;
+            Ok(BufReader::new(client).lines().collect::<Result<_, _>>()?)
+        };
+        let reader = talk("pause\nauth r-token\nstatus\npause\nkill\n")?;
+        let guesser = talk("auth guess\nstatus\n")?;
+
+        graph.request_shutdown();
+        graph.block_until_stopped(Duration::from_secs(1))?;
+        let _ = fs::remove_file(&tokens);
+
+        assert_eq!(reader[0], "error pause needs operate access, send 'auth TOKEN' first");
+        assert_eq!(reader[1], "ok read");
+        assert!(reader[2].contains("\"control\":{\"commands\":1,\"refused\":1,"), "{}", reader[2]);
+        assert_eq!(reader[3..], ["error pause needs operate access", "error kill needs admin access"]);
+        assert_eq!(guesser, ["error unknown token"]);
+        assert!(control_rx.testing_take_all().is_empty());
+        Ok(())
+    }
+}
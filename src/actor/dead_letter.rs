@@ -0,0 +1,221 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use serde_json::json;
+use steady_state::*;
+use steady_state::actor_builder::{ActorBuilder, ScheduleAs};
+use crate::state_lock::lock_state;
+use crate::timing::Stamp;
+use crate::wiring::{RobustActor, Wiring};
+
+/// What was dropped: the worker drops a value before it is classified, the logger a classified
+/// record, kept as its message prints.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Poisoned {
+    Value(u64),
+    Record(String),
+}
+
+impl Default for Poisoned {
+    fn default() -> Self {
+        Poisoned::Value(0)
+    }
+}
+
+/// A showstopper the worker or the logger dropped, with what is known of how it got there.
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+pub struct DeadLetter {
+    /// The actor that dropped it.
+    pub(crate) source: &'static str,
+    /// How many times it was peeked without being handled before it was dropped.
+    pub(crate) peeks: usize,
+    /// When it was dropped, in microseconds since the Unix epoch.
+    pub(crate) dropped_micros: u64,
+    /// Its sequence number on the way to the logger; zero for a value the worker never sent.
+    pub(crate) seq: u64,
+    /// Its route with `--workers`, telling the fan-in not to wait for it; 0 otherwise.
+    pub(crate) route: u64,
+    pub(crate) message: Poisoned,
+}
+
+impl DeadLetter {
+    pub(crate) fn new(source: &'static str, peeks: usize, seq: u64, message: Poisoned) -> Self {
+        DeadLetter { source, peeks, dropped_micros: Stamp::now().wall_micros, seq, route: 0, message }
+    }
+
+    pub(crate) fn with_route(mut self, route: u64) -> Self {
+        self.route = route;
+        self
+    }
+
+    /// The letter as one JSON line. A value keeps its number under `value`, so the worker's
+    /// letters can be picked out and fed back in with `--backfill`.
+    pub(crate) fn json(&self) -> String {
+        let mut line = json!({"source": self.source, "peeks": self.peeks, "dropped_micros": self.dropped_micros, "seq": self.seq});
+        match &self.message {
+            Poisoned::Value(value) => line["value"] = (*value).into(),
+            Poisoned::Record(record) => line["record"] = record.as_str().into(),
+        }
+        line.to_string()
+    }
+}
+
+/// Persistent state of the dead-letter actor, so the counts cover the whole run.
+pub struct DeadLetterState {
+    /// Letters taken from the worker and the logger.
+    pub(crate) received: u64,
+    /// Letters written to the `--dead-letter-file`, and those that could not be.
+    pub(crate) persisted: u64,
+    pub(crate) write_failures: u64,
+    pub(crate) restart_count: u64,
+}
+
+/// Entry point for the dead-letter actor.
+/// Collects the showstoppers the worker and the logger drop, one channel from each, and keeps
+/// them in `--dead-letter-file` for an operator to inspect and replay.
+pub async fn run(
+    actor: SteadyActorShadow,
+    worker_rx: SteadyRx<DeadLetter>,
+    logger_rx: SteadyRx<DeadLetter>,
+    state: SteadyState<DeadLetterState>,
+) -> Result<(), Box<dyn Error>> {
+    let actor = actor.into_spotlight([&worker_rx, &logger_rx], []);
+    if actor.use_internal_behavior {
+        internal_behavior(actor, worker_rx, logger_rx, state).await
+    } else {
+        actor.simulated_behavior(vec!(&worker_rx, &logger_rx)).await
+    }
+}
+
+/// The dead-letter actor as a pipeline stage.
+pub struct DeadLetters;
+
+impl RobustActor for DeadLetters {
+    fn name(&self) -> &'static str {
+        crate::NAME_DEAD_LETTER
+    }
+    fn inputs(&self) -> &'static [&'static str] {
+        &["worker_dead_letter", "logger_dead_letter"]
+    }
+    fn outputs(&self) -> &'static [&'static str] {
+        &[]
+    }
+    fn build(&self, builder: ActorBuilder, wiring: &Wiring, schedule: ScheduleAs<'_>) {
+        let (worker_rx, logger_rx) = (wiring.worker_dead_letter.rx.clone(), wiring.logger_dead_letter.rx.clone());
+        let state = wiring.state.dead_letter.clone();
+        builder.build(move |context| {
+            crate::priority::apply(&context, crate::NAME_DEAD_LETTER);
+            run(context, worker_rx.clone(), logger_rx.clone(), state.clone())
+        }, schedule);
+    }
+}
+
+async fn internal_behavior<A: SteadyActor>(
+    mut actor: A,
+    worker_rx: SteadyRx<DeadLetter>,
+    logger_rx: SteadyRx<DeadLetter>,
+    state: SteadyState<DeadLetterState>,
+) -> Result<(), Box<dyn Error>> {
+    let path = actor.args::<crate::MainArg>().expect("unable to downcast").dead_letter_file.clone();
+    let Some(mut state) = lock_state(&actor, crate::NAME_DEAD_LETTER, &state, || DeadLetterState {
+        received: 0,
+        persisted: 0,
+        write_failures: 0,
+        restart_count: 0,
+    }).await else {
+        return Ok(());
+    };
+    state.restart_count += 1;
+    info!(
+        "Dead letters starting (restart #{}) with received: {}, persisted: {}",
+        state.restart_count, state.received, state.persisted
+    );
+    let wait = crate::wait::strategy(&actor);
+
+    let mut worker_rx = worker_rx.lock().await;
+    let mut logger_rx = logger_rx.lock().await;
+    while actor.is_running(|| i!(worker_rx.is_closed_and_empty()) && i!(logger_rx.is_closed_and_empty())) {
+        await_for_all!(wait.wait(&actor, async { await_for_any!(actor.wait_avail(&mut worker_rx, 1), actor.wait_avail(&mut logger_rx, 1)) }));
+
+        // Written before it is taken, so a restart mid-write keeps the letter rather than losing it.
+        for rx in [&mut worker_rx, &mut logger_rx] {
+            while let Some(letter) = actor.try_peek(rx).cloned() {
+                keep(path.as_deref(), &letter, &mut state);
+                actor.try_take(rx);
+            }
+        }
+    }
+    info!(
+        "Dead letters shutting down. Received: {}, persisted: {}, write failures: {}",
+        state.received, state.persisted, state.write_failures
+    );
+    Ok(())
+}
+
+/// Appends the letter to the file, or only logs it when there is none or the write fails.
+fn keep(path: Option<&Path>, letter: &DeadLetter, state: &mut DeadLetterState) {
+    state.received += 1;
+    let line = letter.json();
+    let Some(path) = path else {
+        warn!("Dead letter (no --dead-letter-file): {}", line);
+        return;
+    };
+    let written = OpenOptions::new().create(true).append(true).open(path).and_then(|mut file| {
+        writeln!(file, "{}", line)?;
+        file.sync_data()
+    });
+    match written {
+        Ok(()) => {
+            state.persisted += 1;
+            info!("Dead letter from {} saved to {:?}", letter.source, path);
+        }
+        Err(e) => {
+            state.write_failures += 1;
+            error!("Dead letter could not be saved to {:?}, kept in the log only: {}: {}", path, line, e);
+        }
+    }
+}
+
+/// The value of one of the worker's letters in a dead-letter file, for `--backfill` to replay.
+/// None for the logger's letters and for anything that is not a letter.
+pub(crate) fn replay_value(line: &str) -> Option<u64> {
+    let letter: serde_json::Value = serde_json::from_str(line).ok()?;
+    (letter["source"] == crate::NAME_WORKER).then(|| letter["value"].as_u64()).flatten()
+}
+
+#[cfg(test)]
+pub(crate) mod dead_letter_tests {
+    use crate::state_lock::settled;
+    use super::*;
+
+    #[test]
+    fn test_dead_letters_are_persisted() -> Result<(), Box<dyn Error>> {
+        let path = std::env::temp_dir().join(format!("robust-dead-letters-{}.jsonl", crate::arg::new_run_id()));
+        let mut graph = GraphBuilder::for_testing().build(crate::MainArg { dead_letter_file: Some(path.clone()), ..Default::default() });
+        let (worker_tx, worker_rx) = graph.channel_builder().build();
+        let (logger_tx, logger_rx) = graph.channel_builder().build();
+        let state = new_state();
+        let probe = state.clone();
+        graph.actor_builder().with_name("UnitTest")
+            .build(move |context| internal_behavior(context, worker_rx.clone(), logger_rx.clone(), state.clone()), SoloAct);
+
+        worker_tx.testing_send_all(vec![DeadLetter::new(crate::NAME_WORKER, 3, 0, Poisoned::Value(33))], true);
+        logger_tx.testing_send_all(vec![DeadLetter::new(crate::NAME_LOGGER, 3, 41, Poisoned::Record("Value(41)".to_string()))], true);
+        graph.start();
+        std::thread::sleep(Duration::from_millis(200));
+        graph.request_shutdown();
+        graph.block_until_stopped(Duration::from_secs(1))?;
+
+        let lines: Vec<serde_json::Value> = std::fs::read_to_string(&path)?.lines().map(serde_json::from_str).collect::<Result<_, _>>()?;
+        assert_eq!(lines.len(), 2);
+        assert_eq!((&lines[0]["source"], &lines[0]["peeks"], &lines[0]["value"]), (&json!("WORKER"), &json!(3), &json!(33)));
+        assert_eq!((&lines[1]["seq"], &lines[1]["record"]), (&json!(41), &json!("Value(41)")));
+        assert!(lines[1]["dropped_micros"].as_u64() > Some(0));
+        let replayed: Vec<_> = std::fs::read_to_string(&path)?.lines().filter_map(replay_value).collect();
+        assert_eq!(replayed, [33]);
+        let state = settled(&probe).expect("dead letter state");
+        assert_eq!((state.received, state.persisted, state.write_failures), (2, 2, 0));
+        let _ = std::fs::remove_file(&path);
+        Ok(())
+    }
+}
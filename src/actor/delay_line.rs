@@ -0,0 +1,113 @@
+use std::collections::VecDeque;
+use steady_state::*;
+use steady_state::actor_builder::ActorBuilder;
+use steady_state::channel_builder::ChannelBuilder;
+
+/// Entry point for a delay line: a relay spliced into one channel by `--channel-delay`, which
+/// passes every message on in order once it has waited `delay` since it arrived. Used to check
+/// timeouts, deadlines and stall detection under a known latency. It runs the same in tests.
+///
+/// Messages are only peeked until they are forwarded, so a restart delays the ones it finds
+/// again from scratch but never loses one.
+pub async fn run<T: Copy + Send + Sync + 'static>(
+    actor: SteadyActorShadow,
+    rx: SteadyRx<T>,
+    tx: SteadyTx<T>,
+    delay: Duration,
+) -> Result<(), Box<dyn Error>> {
+    internal_behavior(actor.into_spotlight([&rx], [&tx]), rx, tx, delay).await
+}
+
+/// Splices a delay line named `name` into the channel that `rx` reads, when there is a delay,
+/// and returns the receiver downstream should read instead.
+pub(crate) fn splice<T: Copy + Send + Sync + 'static>(
+    actor_builder: &ActorBuilder,
+    channel_builder: &ChannelBuilder,
+    name: &'static str,
+    rx: LazySteadyRx<T>,
+    delay: Option<Duration>,
+) -> LazySteadyRx<T> {
+    let Some(delay) = delay else {
+        return rx;
+    };
+    let (delayed_tx, delayed_rx) = channel_builder.build();
+    actor_builder.with_name(name)
+        .build(move |context| run(context, rx.clone(), delayed_tx.clone(), delay), SoloAct);
+    delayed_rx
+}
+
+async fn internal_behavior<A: SteadyActor, T: Copy>(
+    mut actor: A,
+    rx: SteadyRx<T>,
+    tx: SteadyTx<T>,
+    delay: Duration,
+) -> Result<(), Box<dyn Error>> {
+    let mut rx = rx.lock().await;
+    let mut tx = tx.lock().await;
+    // When each message still in the input channel was first seen, oldest first.
+    let mut arrivals: VecDeque<Instant> = VecDeque::new();
+
+    while actor.is_running(|| i!(rx.is_closed_and_empty()) && i!(tx.mark_closed())) {
+        let now = Instant::now();
+        arrivals.resize(actor.avail_units(&mut rx).max(arrivals.len()), now);
+
+        while let Some(&arrived) = arrivals.front()
+            && arrived + delay <= now
+            && actor.vacant_units(&mut tx) > 0 {
+            let Some(&message) = actor.try_peek(&mut rx) else {
+                break;
+            };
+            if !actor.try_send(&mut tx, message).is_sent() {
+                break;
+            }
+            actor.try_take(&mut rx);
+            arrivals.pop_front();
+        }
+
+        match arrivals.front() {
+            // Wake for the next message falling due, or for a new one to start its clock sooner.
+            Some(&arrived) if actor.vacant_units(&mut tx) > 0 => {
+                let due = (arrived + delay).saturating_duration_since(Instant::now());
+                await_for_any!(async { actor.wait(due).await; true }, actor.wait_avail(&mut rx, arrivals.len() + 1));
+            }
+            Some(_) => {
+                await_for_all!(actor.wait_vacant(&mut tx, 1));
+            }
+            None => {
+                await_for_all!(actor.wait_avail(&mut rx, 1));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod delay_line_tests {
+    use std::thread::sleep;
+    use super::*;
+    use crate::actor::assert_sink::AssertSink;
+    use crate::actor::scripted::ScriptedSource;
+
+    #[test]
+    fn test_delay_line_holds_each_message() -> Result<(), Box<dyn Error>> {
+        let mut graph = GraphBuilder::for_testing().build(crate::MainArg::default());
+        let (source_tx, source_rx) = graph.channel_builder().build::<u64>();
+        let (delayed_tx, delayed_rx) = graph.channel_builder().build::<u64>();
+        ScriptedSource::new().emit_all_at(0, [1, 2]).emit_at(100, 3).then_close().build(&mut graph, "Script", source_tx);
+        graph.actor_builder().with_name("UnitTest")
+            .build(move |context| internal_behavior(context, source_rx.clone(), delayed_tx.clone(), Duration::from_millis(200)), SoloAct);
+        let delayed = AssertSink::new().expect_all([1, 2, 3]).build(&mut graph, "Expect", delayed_rx);
+
+        graph.start();
+        let started = Instant::now();
+        sleep(Duration::from_millis(150));
+        // nothing has waited long enough yet
+        assert!(delayed.wait(Duration::ZERO).is_err_and(|e| e.starts_with("only 0 of 3")));
+        delayed.wait(Duration::from_secs(1))?;
+        // the last one was sent 100ms in, so it is due 300ms in
+        assert!(started.elapsed() >= Duration::from_millis(290), "{:?}", started.elapsed());
+        graph.request_shutdown();
+        graph.block_until_stopped(Duration::from_secs(1))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+use steady_state::*;
+use steady_state::actor_builder::{ActorBuilder, ScheduleAs};
+use crate::enrich::{self, Derived};
+use crate::state_lock::lock_state;
+use crate::wiring::{RobustActor, Wiring};
+
+/// The worker asking for the derived data of one value. `id` ties the reply to the request.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub struct LookupRequest {
+    pub(crate) id: u64,
+    pub(crate) value: u64,
+}
+
+/// The enricher's answer, under the id of the request it answers.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub struct LookupReply {
+    pub(crate) id: u64,
+    pub(crate) derived: Derived,
+}
+
+/// Persistent state of the enricher, so its count covers the whole run.
+pub struct EnricherState {
+    pub(crate) answered: u64,
+    pub(crate) restart_count: u64,
+}
+
+/// Entry point for the enricher actor.
+/// Answers the worker's lookups with the `--compute` modules, one reply per request, and
+/// shows how a round trip between two actors fits peek-before-commit: a request is only
+/// taken once its reply is sent, so a restart in between answers it again.
+pub async fn run(
+    actor: SteadyActorShadow,
+    request_rx: SteadyRx<LookupRequest>,
+    reply_tx: SteadyTx<LookupReply>,
+    state: SteadyState<EnricherState>,
+) -> Result<(), Box<dyn Error>> {
+    let actor = actor.into_spotlight([&request_rx], [&reply_tx]);
+    if actor.use_internal_behavior {
+        internal_behavior(actor, request_rx, reply_tx, state).await
+    } else {
+        actor.simulated_behavior(vec!(&request_rx, &reply_tx)).await
+    }
+}
+
+/// The enricher as a pipeline stage, with `--enricher-timeout-ms`.
+pub struct Enricher;
+
+impl RobustActor for Enricher {
+    fn name(&self) -> &'static str {
+        crate::NAME_ENRICHER
+    }
+    fn inputs(&self) -> &'static [&'static str] {
+        &["lookup_request"]
+    }
+    fn outputs(&self) -> &'static [&'static str] {
+        &["lookup_reply"]
+    }
+    fn enabled(&self, args: &crate::MainArg) -> bool {
+        args.enricher_timeout_ms.is_some()
+    }
+    fn build(&self, builder: ActorBuilder, wiring: &Wiring, schedule: ScheduleAs<'_>) {
+        let (request_rx, reply_tx) = (wiring.lookup_request.rx.clone(), wiring.lookup_reply.tx.clone());
+        let state = wiring.state.enricher.clone();
+        builder.build(move |context| {
+            crate::priority::apply(&context, crate::NAME_ENRICHER);
+            run(context, request_rx.clone(), reply_tx.clone(), state.clone())
+        }, schedule);
+    }
+}
+
+async fn internal_behavior<A: SteadyActor>(
+    mut actor: A,
+    request_rx: SteadyRx<LookupRequest>,
+    reply_tx: SteadyTx<LookupReply>,
+    state: SteadyState<EnricherState>,
+) -> Result<(), Box<dyn Error>> {
+    let enricher = enrich::Enricher::from_args(actor.args::<crate::MainArg>().expect("unable to downcast"));
+    let Some(mut state) = lock_state(&actor, crate::NAME_ENRICHER, &state, || EnricherState {
+        answered: 0,
+        restart_count: 0,
+    }).await else {
+        return Ok(());
+    };
+    state.restart_count += 1;
+    info!("Enricher starting (restart #{}) with answered: {}", state.restart_count, state.answered);
+    let wait = crate::wait::strategy(&actor);
+
+    let mut request_rx = request_rx.lock().await;
+    let mut reply_tx = reply_tx.lock().await;
+    while actor.is_running(|| i!(request_rx.is_closed_and_empty()) && i!(reply_tx.mark_closed())) {
+        await_for_all!(wait.wait(&actor, async { await_for_all!(actor.wait_avail(&mut request_rx, 1), actor.wait_vacant(&mut reply_tx, 1)) }));
+
+        while let Some(&request) = actor.try_peek(&mut request_rx) {
+            let reply = LookupReply { id: request.id, derived: enricher.compute(request.value) };
+            if !actor.try_send(&mut reply_tx, reply).is_sent() {
+                break;
+            }
+            actor.try_take(&mut request_rx);
+            state.answered += 1;
+        }
+    }
+    info!("Enricher shutting down. Answered: {}", state.answered);
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod enricher_tests {
+    use crate::enrich::ComputeModule;
+    use crate::state_lock::settled;
+    use super::*;
+
+    #[test]
+    fn test_enricher_answers_each_request() -> Result<(), Box<dyn Error>> {
+        let args = crate::MainArg { compute: vec![ComputeModule::DigitSum], enricher_timeout_ms: Some(50), ..Default::default() };
+        let mut graph = GraphBuilder::for_testing().build(args);
+        let (request_tx, request_rx) = graph.channel_builder().build();
+        let (reply_tx, reply_rx) = graph.channel_builder().build();
+        let state = new_state();
+        let probe = state.clone();
+        graph.actor_builder().with_name("UnitTest")
+            .build(move |context| internal_behavior(context, request_rx.clone(), reply_tx.clone(), state.clone()), SoloAct);
+
+        request_tx.testing_send_all(vec![LookupRequest { id: 7, value: 123 }, LookupRequest { id: 8, value: 45 }], true);
+        graph.start();
+        std::thread::sleep(Duration::from_millis(200));
+        graph.request_shutdown();
+        graph.block_until_stopped(Duration::from_secs(1))?;
+
+        let digit_sum = |sum| Derived { digit_sum: Some(sum), ..Default::default() };
+        assert_eq!(reply_rx.testing_take_all(), vec![LookupReply { id: 7, derived: digit_sum(6) }, LookupReply { id: 8, derived: digit_sum(9) }]);
+        assert_eq!(settled(&probe).expect("enricher state").answered, 2);
+        Ok(())
+    }
+}
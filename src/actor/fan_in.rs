@@ -0,0 +1,332 @@
+use std::collections::BTreeSet;
+use steady_state::*;
+use steady_state::actor_builder::{ActorBuilder, ScheduleAs};
+use crate::actor::dead_letter::DeadLetter;
+use crate::actor::reconciler::{ChannelDepth, CounterSample, SamplePacer, METRICS_WORKER, SAMPLED_INPUTS};
+use crate::actor::worker::FizzBuzzMessage;
+use crate::state_lock::lock_state;
+use crate::timing::Envelope;
+use crate::validation::RejectedValue;
+use crate::wiring::{RobustActor, Wiring};
+
+/// How often the fan-in looks again while it holds a gap.
+const HOLD_POLL: Duration = Duration::from_millis(5);
+
+/// Persistent state of the fan-in, so the numbering and the order survive restarts.
+pub struct FanInState {
+    /// Messages sent on to the logger, which get their sequence numbers here: the next is `forwarded + 1`.
+    pub(crate) forwarded: u64,
+    /// Bytes of those messages, and rejects passed through, for the worker's metrics lane.
+    pub(crate) bytes_forwarded: u64,
+    pub(crate) rejects: u64,
+    /// The highest route sent on, and the latest from each worker. A worker's output arrives in
+    /// route order, so one at or below its latest is a re-send and is dropped.
+    pub(crate) last_route: u64,
+    pub(crate) worker_routes: Vec<u64>,
+    /// Routes the workers consumed without output, from their rejects and dead letters, that
+    /// `last_route` has not reached yet.
+    pub(crate) skipped: BTreeSet<u64>,
+    pub(crate) duplicates: u64,
+    /// Messages whose route came after a later one had already gone out.
+    pub(crate) out_of_order: u64,
+    pub(crate) restart_count: u64,
+}
+
+/// Entry point for the fan-in actor.
+/// Merges the `--workers` pool's output back into one stream for the logger in the order the
+/// router handed the values out, and passes the pool's rejects, dead letters and metrics on
+/// where the single worker's would have gone.
+#[allow(clippy::too_many_arguments)] // one parameter per channel keeps the wiring explicit
+pub async fn run(
+    actor: SteadyActorShadow,
+    classified_rx: Vec<SteadyRx<Envelope<FizzBuzzMessage>>>,
+    rejected_rx: Vec<SteadyRx<RejectedValue>>,
+    dead_letter_rx: Vec<SteadyRx<DeadLetter>>,
+    metrics_rx: Vec<SteadyRx<CounterSample>>,
+    logger_tx: SteadyTx<Envelope<FizzBuzzMessage>>,
+    rejected_tx: SteadyTx<RejectedValue>,
+    dead_letter_tx: SteadyTx<DeadLetter>,
+    metrics_tx: SteadyTx<CounterSample>,
+    state: SteadyState<FanInState>,
+) -> Result<(), Box<dyn Error>> {
+    // The spotlight takes a fixed number of channels, so the per-worker ones go unmonitored.
+    let actor = actor.into_spotlight([], [&logger_tx, &rejected_tx, &dead_letter_tx, &metrics_tx]);
+    if actor.use_internal_behavior {
+        internal_behavior(actor, classified_rx, rejected_rx, dead_letter_rx, metrics_rx, logger_tx, rejected_tx, dead_letter_tx, metrics_tx, state).await
+    } else {
+        actor.simulated_behavior(vec!(&logger_tx, &rejected_tx, &dead_letter_tx)).await
+    }
+}
+
+/// The fan-in as a pipeline stage, with `--workers` above 1.
+pub struct FanIn;
+
+impl RobustActor for FanIn {
+    fn name(&self) -> &'static str {
+        crate::NAME_FAN_IN
+    }
+    fn inputs(&self) -> &'static [&'static str] {
+        &["shard_classified", "shard_rejected", "shard_metrics", "shard_dead_letter"]
+    }
+    fn outputs(&self) -> &'static [&'static str] {
+        &["classified", "rejected", "metrics", "worker_dead_letter"]
+    }
+    fn enabled(&self, args: &crate::MainArg) -> bool {
+        args.workers > 1
+    }
+    fn depends_on(&self, _args: &crate::MainArg) -> &'static [&'static str] {
+        &[crate::NAME_LOGGER]
+    }
+    fn build(&self, builder: ActorBuilder, wiring: &Wiring, schedule: ScheduleAs<'_>) {
+        let classified_rx: Vec<_> = wiring.pool.iter().map(|links| links.classified.rx.clone()).collect();
+        let rejected_rx: Vec<_> = wiring.pool.iter().map(|links| links.rejected.rx.clone()).collect();
+        let dead_letter_rx: Vec<_> = wiring.pool.iter().map(|links| links.dead_letter.rx.clone()).collect();
+        let metrics_rx: Vec<_> = wiring.pool.iter().map(|links| links.metrics.rx.clone()).collect();
+        let (logger_tx, rejected_tx) = (wiring.classified.tx.clone(), wiring.rejected.tx.clone());
+        let (dead_letter_tx, metrics_tx) = (wiring.worker_dead_letter.tx.clone(), wiring.metrics_tx[METRICS_WORKER].clone());
+        let state = wiring.state.fan_in.clone();
+        builder.build(move |context| {
+            crate::priority::apply(&context, crate::NAME_FAN_IN);
+            run(context, classified_rx.clone(), rejected_rx.clone(), dead_letter_rx.clone(), metrics_rx.clone(),
+                logger_tx.clone(), rejected_tx.clone(), dead_letter_tx.clone(), metrics_tx.clone(), state.clone())
+        }, schedule);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn internal_behavior<A: SteadyActor>(
+    mut actor: A,
+    classified_rx: Vec<SteadyRx<Envelope<FizzBuzzMessage>>>,
+    rejected_rx: Vec<SteadyRx<RejectedValue>>,
+    dead_letter_rx: Vec<SteadyRx<DeadLetter>>,
+    metrics_rx: Vec<SteadyRx<CounterSample>>,
+    logger: SteadyTx<Envelope<FizzBuzzMessage>>,
+    rejected_out: SteadyTx<RejectedValue>,
+    dead_letter_out: SteadyTx<DeadLetter>,
+    metrics_out: SteadyTx<CounterSample>,
+    state: SteadyState<FanInState>,
+) -> Result<(), Box<dyn Error>> {
+    let workers = classified_rx.len();
+    let Some(mut state) = lock_state(&actor, crate::NAME_FAN_IN, &state, || FanInState {
+        forwarded: 0,
+        bytes_forwarded: 0,
+        rejects: 0,
+        last_route: 0,
+        worker_routes: vec![0; workers],
+        skipped: BTreeSet::new(),
+        duplicates: 0,
+        out_of_order: 0,
+        restart_count: 0,
+    }).await else {
+        return Ok(());
+    };
+    state.restart_count += 1;
+    info!("Fan-in starting (restart #{}) with {} workers, forwarded: {}", state.restart_count, workers, state.forwarded);
+
+    let mut classified = Vec::with_capacity(workers);
+    for rx in &classified_rx {
+        classified.push(rx.lock().await);
+    }
+    let mut rejected = Vec::with_capacity(workers);
+    for rx in &rejected_rx {
+        rejected.push(rx.lock().await);
+    }
+    let mut dead_letters = Vec::with_capacity(workers);
+    for rx in &dead_letter_rx {
+        dead_letters.push(rx.lock().await);
+    }
+    let mut metrics = Vec::with_capacity(workers);
+    for rx in &metrics_rx {
+        metrics.push(rx.lock().await);
+    }
+    let mut logger = logger.lock().await;
+    let mut rejected_out = rejected_out.lock().await;
+    let mut dead_letter_out = dead_letter_out.lock().await;
+    let mut metrics_out = metrics_out.lock().await;
+    let mut latest: Vec<Option<CounterSample>> = vec![None; workers];
+    let mut pacer = SamplePacer::default();
+    let mut holding = false;
+    let ones = vec![1; workers];
+
+    while actor.is_running(|| i!(classified.iter_mut().all(|rx| rx.is_closed_and_empty()))
+                              && i!(rejected.iter_mut().all(|rx| rx.is_closed_and_empty()))
+                              && i!(dead_letters.iter_mut().all(|rx| rx.is_closed_and_empty()))
+                              && i!(logger.mark_closed())
+                              && i!(rejected_out.mark_closed())
+                              && i!(dead_letter_out.mark_closed())
+                              && i!(metrics_out.mark_closed())) {
+        let drained = classified.iter_mut().all(|rx| rx.is_closed_and_empty());
+        if holding || drained {
+            // The heads are there but wait on a missing route, or the workers are done and the
+            // waits below would return at once; look again shortly rather than spin.
+            await_for_all!(actor.wait_periodic(HOLD_POLL));
+        } else {
+            await_for_all!(actor.wait_vacant(&mut logger, 1),
+                           async { await_for_any!(async { actor.wait_avail_index(&mut classified, &ones).await.is_some() },
+                                                  async { actor.wait_avail_index(&mut rejected, &ones).await.is_some() },
+                                                  async { actor.wait_avail_index(&mut dead_letters, &ones).await.is_some() },
+                                                  async { actor.wait_avail_index(&mut metrics, &ones).await.is_some() }) });
+        }
+
+        // Rejects and dead letters need no order; they pass straight through, and their routes
+        // will never come out of a worker.
+        for rx in rejected.iter_mut() {
+            while let Some(&reject) = actor.try_peek(rx) {
+                if !actor.try_send(&mut rejected_out, reject).is_sent() {
+                    break;
+                }
+                actor.try_take(rx);
+                state.rejects += 1;
+                skip_route(&mut state, reject.route);
+            }
+        }
+        for rx in dead_letters.iter_mut() {
+            while let Some(letter) = actor.try_peek(rx).cloned() {
+                let route = letter.route;
+                if !actor.try_send(&mut dead_letter_out, letter).is_sent() {
+                    break;
+                }
+                actor.try_take(rx);
+                skip_route(&mut state, route);
+            }
+        }
+
+        // Each worker's output is in route order, so the lowest head is next unless a worker
+        // with nothing to show yet still owes a lower one. The next route in line goes at once,
+        // as does one the workers consumed without output. Past a gap, the heads wait until every
+        // worker has shown its hand: a worker whose head is later has consumed the missing route.
+        loop {
+            while let Some(&skipped) = state.skipped.first()
+                && skipped <= state.last_route + 1 {
+                state.skipped.pop_first();
+                state.last_route = state.last_route.max(skipped);
+            }
+            for (w, rx) in classified.iter_mut().enumerate() {
+                while let Some(&envelope) = actor.try_peek(rx)
+                    && envelope.route <= state.worker_routes[w] {
+                    actor.try_take(rx);
+                    state.duplicates += 1;
+                    warn!("Fan-in dropped re-sent route {} from worker {}", envelope.route, w);
+                }
+            }
+            let heads: Vec<Option<u64>> = classified.iter_mut().map(|rx| actor.try_peek(rx).map(|e| e.route)).collect();
+            let Some((w, route)) = heads.iter().enumerate().filter_map(|(w, head)| head.map(|route| (w, route))).min_by_key(|&(_, route)| route) else {
+                holding = false;
+                break;
+            };
+            let complete = heads.iter().zip(classified.iter_mut()).all(|(head, rx)| head.is_some() || rx.is_closed_and_empty());
+            holding = route != state.last_route + 1 && !complete;
+            if holding {
+                break;
+            }
+            let Some(&envelope) = actor.try_peek(&mut classified[w]) else {
+                break;
+            };
+            let message = envelope.with_seq(state.forwarded + 1);
+            if !actor.try_send(&mut logger, message).is_sent() {
+                break;
+            }
+            actor.try_take(&mut classified[w]);
+            state.forwarded += 1;
+            state.bytes_forwarded += message.wire_bytes();
+            state.worker_routes[w] = route;
+            if route < state.last_route {
+                state.out_of_order += 1;
+                warn!("Fan-in sent route {} from worker {} after route {}, out of order", route, w, state.last_route);
+            }
+            state.last_route = state.last_route.max(route);
+        }
+
+        // The pool reports as one worker: what it took in and holds, from each worker's latest
+        // sample, and what it put out, counted here as it reaches the logger's channels.
+        for (w, rx) in metrics.iter_mut().enumerate() {
+            while let Some(sample) = actor.try_take(rx) {
+                latest[w] = Some(sample);
+            }
+        }
+        if pacer.is_due() && latest.iter().any(Option::is_some) {
+            let pool = latest.iter().flatten();
+            let (consumed, backlog) = pool.clone().fold((0, 0), |(c, b), s| (c + s.consumed, b + s.backlog));
+            let (starts, dropped) = pool.clone().fold((0, 0), |(st, d), s| (st + s.starts, d + s.dropped));
+            let backlog_bytes = pool.clone().map(|s| s.backlog_bytes).sum();
+            // The deepest of the pool's channels, each of which has the capacity of one.
+            let depths = pool.fold([ChannelDepth::default(); SAMPLED_INPUTS], |deepest, s| {
+                std::array::from_fn(|input| deepest[input].deepest(s.depths[input]))
+            });
+            let sample = CounterSample::new(consumed, state.forwarded + state.rejects, backlog)
+                .with_starts(starts)
+                .with_dropped(dropped)
+                .with_bytes(state.bytes_forwarded, backlog_bytes)
+                .with_seq(state.forwarded)
+                .with_depths(depths);
+            let _ = actor.try_send(&mut metrics_out, sample);
+        }
+    }
+    info!(
+        "Fan-in shutting down. Forwarded: {}, rejects: {}, duplicates: {}, out of order: {}",
+        state.forwarded, state.rejects, state.duplicates, state.out_of_order
+    );
+    Ok(())
+}
+
+/// Notes a route its worker consumed without output, unless the order has already passed it.
+fn skip_route(state: &mut FanInState, route: u64) {
+    if route > state.last_route {
+        state.skipped.insert(route);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod fan_in_tests {
+    use crate::state_lock::settled;
+    use super::*;
+
+    #[test]
+    fn test_fan_in_restores_route_order() -> Result<(), Box<dyn Error>> {
+        let mut graph = GraphBuilder::for_testing().build(crate::MainArg { workers: 2, ..Default::default() });
+        let (classified_tx, classified_rx): (Vec<_>, Vec<_>) = (0..2).map(|_| graph.channel_builder().build::<Envelope<FizzBuzzMessage>>()).unzip();
+        let (rejected_tx, rejected_rx): (Vec<_>, Vec<_>) = (0..2).map(|_| graph.channel_builder().build::<RejectedValue>()).unzip();
+        let (letters_tx, letters_rx): (Vec<_>, Vec<_>) = (0..2).map(|_| graph.channel_builder().build::<DeadLetter>()).unzip();
+        let (samples_tx, samples_rx): (Vec<_>, Vec<_>) = (0..2).map(|_| graph.channel_builder().build::<CounterSample>()).unzip();
+        let (logger_tx, logger_rx) = graph.channel_builder().build::<Envelope<FizzBuzzMessage>>();
+        let (rejected_out_tx, rejected_out_rx) = graph.channel_builder().build::<RejectedValue>();
+        let (letter_out_tx, _letter_out_rx) = graph.channel_builder().build::<DeadLetter>();
+        let (metrics_out_tx, _metrics_out_rx) = graph.channel_builder().build::<CounterSample>();
+        let state = new_state();
+        let probe = state.clone();
+        graph.actor_builder().with_name("UnitTest")
+            .build(move |context| internal_behavior(context, classified_rx.iter().map(|rx| rx.clone()).collect(), rejected_rx.iter().map(|rx| rx.clone()).collect(),
+                                                    letters_rx.iter().map(|rx| rx.clone()).collect(), samples_rx.iter().map(|rx| rx.clone()).collect(),
+                                                    logger_tx.clone(), rejected_out_tx.clone(), letter_out_tx.clone(), metrics_out_tx.clone(),
+                                                    state.clone()), SoloAct);
+
+        let routed = |value, route| Envelope::unstamped(FizzBuzzMessage::Value(value)).with_seq(1).with_route(route);
+        let reject = RejectedValue::new(3, crate::validation::RejectReason::MultipleOf(3)).with_route(3);
+        // worker 1 rejected route 3 and re-sent route 4 after a restart; worker 0 owes route 5
+        classified_tx[0].testing_send_all(vec![routed(2, 2)], false);
+        classified_tx[1].testing_send_all(vec![routed(1, 1), routed(4, 4), routed(4, 4), routed(6, 6)], false);
+        rejected_tx[1].testing_send_all(vec![reject], true);
+        rejected_tx[0].testing_send_all(vec![], true);
+        letters_tx.iter().for_each(|tx| tx.testing_send_all(vec![], true));
+        samples_tx.iter().for_each(|tx| tx.testing_send_all(vec![], true));
+        graph.start();
+        // the reported reject lets route 4 through, but route 6 waits on worker 0 however long it takes
+        std::thread::sleep(Duration::from_millis(500));
+        let received: Vec<_> = logger_rx.testing_take_all().into_iter().map(|e| (e.route, e.seq)).collect();
+        assert_eq!(received, [(1, 1), (2, 2), (4, 3)]);
+
+        // worker 0 finishing without route 5 shows it will never come
+        classified_tx.iter().for_each(|tx| tx.testing_send_all(vec![], true));
+        std::thread::sleep(Duration::from_millis(100));
+        graph.request_shutdown();
+        graph.block_until_stopped(Duration::from_secs(1))?;
+
+        let received: Vec<_> = logger_rx.testing_take_all().into_iter().map(|e| (e.route, e.seq)).collect();
+        assert_eq!(received, [(6, 4)]);
+        assert_eq!(rejected_out_rx.testing_take_all(), [reject]);
+        let state = settled(&probe).expect("fan-in state");
+        assert_eq!((state.forwarded, state.rejects, state.duplicates, state.out_of_order), (4, 1, 1, 0));
+        assert!(state.skipped.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,515 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use steady_state::*;
+use crate::state_lock::lock_state;
+use tokio::sync::{mpsc, oneshot};
+use tonic::codegen::{http, Body, BoxFuture, Context, Poll, Service, StdError};
+use tonic::server::{Grpc, NamedService, UnaryService};
+use tonic::transport::server::TcpIncoming;
+use tonic::{Code, Request, Response, Status};
+use tonic_prost::ProstCodec;
+use crate::ack::{AckOutcome, AwaitingAck, SourceAck};
+use crate::admission::Admission;
+use crate::quota::Quotas;
+use crate::actor::reconciler::{CounterSample, SamplePacer, METRICS_GENERATOR};
+use crate::tenant;
+use crate::timing::Envelope;
+use crate::trace::{self, TraceSample};
+use steady_state::actor_builder::{ActorBuilder, ScheduleAs};
+use crate::startup::StartGate;
+use crate::wiring::{RobustActor, Wiring};
+
+/// Submissions the server may hold while the actor catches up. Beyond this the
+/// caller is told to back off rather than being queued without bound.
+const INGEST_QUEUE: usize = 64;
+
+const SUBMIT_VALUE_PATH: &str = "/robust.Ingest/SubmitValue";
+
+/// Matches `SubmitValueRequest` in proto/robust.proto.
+#[derive(Clone, Copy, PartialEq, prost::Message)]
+pub(crate) struct SubmitValueRequest {
+    #[prost(uint64, tag = "1")]
+    pub(crate) value: u64,
+}
+
+/// Matches `SubmitValueReply` in proto/robust.proto.
+#[derive(Clone, Copy, PartialEq, prost::Message)]
+pub(crate) struct SubmitValueReply {
+    #[prost(uint64, tag = "1")]
+    pub(crate) accepted_total: u64,
+}
+
+/// One SubmitValue call waiting for the actor to accept or refuse it.
+pub(crate) struct Submission {
+    value: u64,
+    /// The caller's IP address, which admission control keys on.
+    client: String,
+    reply: oneshot::Sender<Result<u64, Status>>,
+}
+
+/// GrpcSourceState holds state for the gRPC source actor.
+/// The submission queue lives here so the server, started once, keeps feeding
+/// the actor across restarts instead of being rebound.
+pub struct GrpcSourceState {
+    /// Values passed on to the worker, and the bytes they came to.
+    pub(crate) accepted: u64,
+    pub(crate) accepted_bytes: u64,
+    /// Calls answered with RESOURCE_EXHAUSTED because the generator channel was full.
+    pub(crate) refused: u64,
+    /// Calls answered with RESOURCE_EXHAUSTED because their client was over `--ingest-rate`.
+    pub(crate) over_rate: u64,
+    pub(crate) admission: Admission,
+    /// `--tenant-quota` by client; a call cannot be held, so over-quota calls are always refused.
+    pub(crate) quotas: Quotas,
+    pub(crate) restart_count: u64,
+    submissions: Option<mpsc::Receiver<Submission>>,
+    /// With `--ack-after-commit`, calls whose value is in the pipeline but not yet committed.
+    pub(crate) awaiting: AwaitingAck<oneshot::Sender<Result<u64, Status>>>,
+}
+
+/// Entry point for the gRPC source actor.
+/// Stands in for the generator, feeding values submitted by external systems into its channel.
+pub async fn run(
+    actor: SteadyActorShadow,
+    generated_tx: SteadyTx<Envelope<u64>>,
+    metrics_tx: SteadyTx<CounterSample>,
+    acks_rx: SteadyRx<SourceAck>,
+    addr: SocketAddr,
+    gate: StartGate,
+    state: SteadyState<GrpcSourceState>,
+) -> Result<(), Box<dyn Error>> {
+    let actor = actor.into_spotlight([&acks_rx], [&generated_tx, &metrics_tx]);
+    if actor.use_internal_behavior {
+        gate.wait(&actor).await;
+        internal_behavior(actor, generated_tx, metrics_tx, acks_rx, addr, state).await
+    } else {
+        actor.simulated_behavior(vec!(&generated_tx)).await
+    }
+}
+
+/// The gRPC source as a pipeline stage, in the generator's place with `--grpc-listen`.
+pub struct GrpcSource;
+
+impl RobustActor for GrpcSource {
+    fn name(&self) -> &'static str {
+        crate::NAME_GRPC_SOURCE
+    }
+    fn inputs(&self) -> &'static [&'static str] {
+        &["acks"]
+    }
+    fn outputs(&self) -> &'static [&'static str] {
+        &["generated", "metrics"]
+    }
+    fn enabled(&self, args: &crate::MainArg) -> bool {
+        crate::wiring::source(args) == crate::wiring::Source::Grpc
+    }
+    fn depends_on(&self, _args: &crate::MainArg) -> &'static [&'static str] {
+        &[crate::NAME_LOGGER] // values are taken in once the sink is connected
+    }
+    fn build(&self, builder: ActorBuilder, wiring: &Wiring, schedule: ScheduleAs<'_>) {
+        let Some(addr) = wiring.args.grpc_listen else {
+            return;
+        };
+        let generated_tx = wiring.generated.tx.clone();
+        let (metrics_tx, acks_rx) = (wiring.metrics_tx[METRICS_GENERATOR].clone(), wiring.acks.rx.clone());
+        let (gate, state) = (wiring.start_gate(self), wiring.state.grpc_source.clone());
+        builder.build(move |context| {
+            crate::priority::apply(&context, crate::NAME_GRPC_SOURCE);
+            run(context, generated_tx.clone(), metrics_tx.clone(), acks_rx.clone(), addr, gate.clone(), state.clone())
+        }, schedule);
+    }
+}
+
+async fn internal_behavior<A: SteadyActor>(
+    mut actor: A,
+    generated_tx: SteadyTx<Envelope<u64>>,
+    metrics_tx: SteadyTx<CounterSample>,
+    acks_rx: SteadyRx<SourceAck>,
+    addr: SocketAddr,
+    state: SteadyState<GrpcSourceState>,
+) -> Result<(), Box<dyn Error>> {
+    let args = actor.args::<crate::MainArg>().expect("unable to downcast");
+    let sample = TraceSample::from_args(args);
+    let acking = crate::ack::enabled(args);
+    let Some(mut state) = lock_state(&actor, crate::NAME_GRPC_SOURCE, &state, || GrpcSourceState {
+        accepted: 0,
+        accepted_bytes: 0,
+        refused: 0,
+        over_rate: 0,
+        admission: Admission::from_args(args),
+        quotas: Quotas::from_args(args),
+        restart_count: 0,
+        submissions: None,
+        awaiting: AwaitingAck::new(),
+    }).await else {
+        return Ok(());
+    };
+    state.restart_count += 1;
+    if state.submissions.is_none() {
+        state.submissions = Some(start_server(addr, args)?);
+        info!("gRPC source listening on {}{}", addr, if serves_tls(args) { " over TLS" } else { "" });
+    }
+    info!(
+        "gRPC source starting (restart #{}) with accepted: {}, refused: {}, over rate: {}, awaiting commit: {}",
+        state.restart_count, state.accepted, state.refused, state.over_rate, state.awaiting.len()
+    );
+
+    let mut generated_tx = generated_tx.lock().await;
+    let mut metrics_tx = metrics_tx.lock().await;
+    let mut acks_rx = acks_rx.lock().await;
+    let mut pacer = SamplePacer::default();
+
+    // Calls still waiting on the worker hold up shutdown until it has answered them all.
+    while actor.is_running(|| i!(generated_tx.mark_closed())
+                              && i!(metrics_tx.mark_closed())
+                              && i!(state.awaiting.is_empty() || acks_rx.is_closed_and_empty())) {
+        while let Some(ack) = actor.try_take(&mut acks_rx) {
+            for (reply, outcome) in state.awaiting.settle(ack) {
+                let _ = reply.send(answer(ack.seq, outcome));
+            }
+        }
+        let submissions = state.submissions.as_mut().expect("server started");
+        let next = await_for_any!(submissions.recv(), async {
+            actor.wait_avail(&mut acks_rx, 1).await;
+            None
+        }, async {
+            actor.wait_shutdown().await;
+            None
+        });
+        let Some(Submission { value, client, reply }) = next else {
+            continue;
+        };
+
+        // Never wait for room: a full channel is the backpressure signal the caller needs.
+        let tenant_id = tenant::id(&client);
+        let envelope = Envelope::new(value)
+            .with_trace(sample.map_or(0, |s| s.trace_id(state.accepted)))
+            .with_tenant(tenant_id);
+        let now = Instant::now();
+        let verdict = if state.admission.admit(&client, now).is_err() {
+            state.over_rate += 1;
+            Err(Status::resource_exhausted("client is over the ingest rate, retry later"))
+        } else if state.quotas.admit(tenant_id, now).is_err() {
+            state.quotas.rejected += 1;
+            Err(Status::resource_exhausted("tenant is over its quota, retry later"))
+        } else {
+            match actor.try_send(&mut generated_tx, envelope) {
+                SendOutcome::Success => {
+                    state.accepted += 1;
+                    state.accepted_bytes += envelope.wire_bytes();
+                    trace::event("GRPC_SOURCE", "accepted", &envelope);
+                    Ok(state.accepted)
+                }
+                SendOutcome::Closed(_) => Err(Status::unavailable("pipeline is shutting down")),
+                _ => {
+                    state.refused += 1;
+                    Err(Status::resource_exhausted("generator channel is full, retry later"))
+                }
+            }
+        };
+        if acking && verdict.is_ok() {
+            // Answered once the worker acknowledges the value, not as soon as it is in the channel.
+            let seq = state.accepted;
+            // Too many waiting: the oldest is answered now, as it would be without --ack-after-commit.
+            if let Some((evicted, reply)) = state.awaiting.push(seq, reply) {
+                let _ = reply.send(Ok(evicted));
+            }
+        } else {
+            // The caller may have gone away; the value's fate is already decided either way.
+            let _ = reply.send(verdict);
+        }
+
+        if pacer.is_due() {
+            let sample = CounterSample::new(state.accepted, state.accepted, 0)
+                .with_starts(state.restart_count)
+                .with_bytes(state.accepted_bytes, 0);
+            let _ = actor.try_send(&mut metrics_tx, sample);
+        }
+    }
+
+    info!(
+        "gRPC source shutting down. Accepted: {}, refused: {}, over rate: {}, over quota: {}, never acknowledged: {}, clients: {}",
+        state.accepted, state.refused, state.over_rate, state.quotas.rejected, state.awaiting.len(), state.admission.summary()
+    );
+    Ok(())
+}
+
+/// The reply to a call once the worker has acknowledged its value: the value's number among
+/// those accepted when it made it through, else why it did not.
+fn answer(seq: u64, outcome: AckOutcome) -> Result<u64, Status> {
+    match outcome {
+        AckOutcome::Committed => Ok(seq),
+        AckOutcome::Rejected(reason) => Err(Status::invalid_argument(format!("value rejected: {:?}", reason))),
+        AckOutcome::Dropped => Err(Status::aborted("value was dropped after repeatedly crashing the worker")),
+        AckOutcome::Spilled => Err(Status::unavailable("pipeline shut down before processing the value, retry")),
+    }
+}
+
+/// Binds the listener and serves the Ingest service on its own Tokio runtime thread.
+/// Binding and reading the certificates happen here so a bad address or certificate fails
+/// the actor start, not a background thread.
+fn start_server(addr: SocketAddr, args: &crate::MainArg) -> Result<mpsc::Receiver<Submission>, Box<dyn Error>> {
+    let mut server = tonic::transport::Server::builder();
+    #[cfg(feature = "tls")]
+    if let Some(config) = crate::tls::TlsFiles::from_args(args).server()? {
+        server = server.tls_config(config)?;
+    }
+    let _ = args; // only read with the tls feature
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .thread_name("grpc-ingest")
+        .enable_all()
+        .build()?;
+    let incoming = {
+        let _guard = runtime.enter();
+        TcpIncoming::bind(addr)?
+    };
+    let (sender, receiver) = mpsc::channel(INGEST_QUEUE);
+    std::thread::Builder::new()
+        .name("grpc-ingest".into())
+        .spawn(move || {
+            let server = server
+                .add_service(IngestServer { submissions: sender })
+                .serve_with_incoming(incoming);
+            if let Err(e) = runtime.block_on(server) {
+                error!("gRPC source server stopped: {}", e);
+            }
+        })?;
+    Ok(receiver)
+}
+
+fn serves_tls(_args: &crate::MainArg) -> bool {
+    #[cfg(feature = "tls")]
+    if _args.tls_cert.is_some() {
+        return true;
+    }
+    false
+}
+
+/// Hands one call to the actor and waits for its verdict.
+async fn submit(submissions: &mpsc::Sender<Submission>, value: u64, client: String) -> Result<u64, Status> {
+    let (reply, verdict) = oneshot::channel();
+    match submissions.try_send(Submission { value, client, reply }) {
+        Ok(()) => {}
+        Err(mpsc::error::TrySendError::Full(_)) => {
+            return Err(Status::resource_exhausted("ingest queue is full, retry later"));
+        }
+        Err(mpsc::error::TrySendError::Closed(_)) => {
+            return Err(Status::unavailable("pipeline is not accepting values"));
+        }
+    }
+    verdict.await.unwrap_or_else(|_| Err(Status::unavailable("gRPC source restarted, retry")))
+}
+
+/// The Ingest service, written out by hand in the shape tonic-build generates.
+#[derive(Clone)]
+struct IngestServer {
+    submissions: mpsc::Sender<Submission>,
+}
+
+impl NamedService for IngestServer {
+    const NAME: &'static str = "robust.Ingest";
+}
+
+struct SubmitValueSvc(Arc<mpsc::Sender<Submission>>);
+
+impl UnaryService<SubmitValueRequest> for SubmitValueSvc {
+    type Response = SubmitValueReply;
+    type Future = BoxFuture<Response<SubmitValueReply>, Status>;
+
+    fn call(&mut self, request: Request<SubmitValueRequest>) -> Self::Future {
+        let submissions = self.0.clone();
+        Box::pin(async move {
+            let client = request.remote_addr().map_or_else(|| "unknown".to_string(), |addr| addr.ip().to_string());
+            let accepted_total = submit(&submissions, request.into_inner().value, client).await?;
+            Ok(Response::new(SubmitValueReply { accepted_total }))
+        })
+    }
+}
+
+impl<B> Service<http::Request<B>> for IngestServer
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::Body>;
+    type Error = std::convert::Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        if req.uri().path() == SUBMIT_VALUE_PATH {
+            let method = SubmitValueSvc(Arc::new(self.submissions.clone()));
+            Box::pin(async move {
+                let mut grpc = Grpc::new(ProstCodec::default());
+                Ok(grpc.unary(method, req).await)
+            })
+        } else {
+            Box::pin(async move {
+                let mut response = http::Response::new(tonic::body::Body::default());
+                let headers = response.headers_mut();
+                headers.insert(Status::GRPC_STATUS, (Code::Unimplemented as i32).into());
+                headers.insert(http::header::CONTENT_TYPE, tonic::metadata::GRPC_CONTENT_TYPE);
+                Ok(response)
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod grpc_source_tests {
+    use std::thread::sleep;
+    use steady_state::*;
+    use tonic::transport::Channel;
+    use crate::arg::MainArg;
+    use super::*;
+
+    async fn call(channel: Channel, value: u64) -> Result<SubmitValueReply, Status> {
+        let mut grpc = tonic::client::Grpc::new(channel);
+        grpc.ready().await.map_err(|e| Status::unknown(e.to_string()))?;
+        let path = http::uri::PathAndQuery::from_static(SUBMIT_VALUE_PATH);
+        grpc.unary(Request::new(SubmitValueRequest { value }), path, ProstCodec::default())
+            .await
+            .map(Response::into_inner)
+    }
+
+    #[test]
+    fn test_submit_value_until_full() -> Result<(), Box<dyn Error>> {
+        let addr: SocketAddr = "127.0.0.1:50517".parse()?;
+        let mut graph = GraphBuilder::for_testing().build(MainArg::default());
+        let (generated_tx, generated_rx) = graph.channel_builder().with_capacity(2).build();
+        let (metrics_tx, _metrics_rx) = graph.channel_builder().build::<CounterSample>();
+        let (_acks_tx, acks_rx) = graph.channel_builder().build::<SourceAck>();
+
+        let state = new_state();
+        graph.actor_builder()
+            .with_name("UnitTest")
+            .build(move |context| internal_behavior(context, generated_tx.clone(), metrics_tx.clone(), acks_rx.clone(), addr, state.clone()), SoloAct);
+        graph.start();
+        sleep(Duration::from_millis(200));
+
+        let client = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        let outcomes: Vec<Result<u64, Code>> = client.block_on(async {
+            let channel = Channel::from_shared(format!("http://{}", addr))
+                .expect("valid uri")
+                .connect()
+                .await
+                .expect("connect");
+            let mut outcomes = Vec::new();
+            for value in [7, 8, 9] {
+                outcomes.push(call(channel.clone(), value).await.map(|r| r.accepted_total).map_err(|s| s.code()));
+            }
+            outcomes
+        });
+
+        graph.request_shutdown();
+        graph.block_until_stopped(Duration::from_secs(1))?;
+
+        // two slots in the channel, so the third call is pushed back
+        assert_eq!(outcomes, vec![Ok(1), Ok(2), Err(Code::ResourceExhausted)]);
+        let values: Vec<u64> = generated_rx.testing_take_all().into_iter().map(|e| e.payload).collect();
+        assert_eq!(values, vec![7, 8]);
+        Ok(())
+    }
+
+    /// With --ack-after-commit each call is answered by the worker's acknowledgement of its value.
+    #[test]
+    fn test_calls_wait_for_commit() -> Result<(), Box<dyn Error>> {
+        let addr: SocketAddr = "127.0.0.1:50518".parse()?;
+        let mut graph = GraphBuilder::for_testing().build(MainArg { grpc_listen: Some(addr), ack_after_commit: true, ..Default::default() });
+        let (generated_tx, generated_rx) = graph.channel_builder().build();
+        let (metrics_tx, _metrics_rx) = graph.channel_builder().build::<CounterSample>();
+        let (acks_tx, acks_rx) = graph.channel_builder().build::<SourceAck>();
+
+        let state = new_state();
+        graph.actor_builder()
+            .with_name("UnitTest")
+            .build(move |context| internal_behavior(context, generated_tx.clone(), metrics_tx.clone(), acks_rx.clone(), addr, state.clone()), SoloAct);
+        graph.start();
+        sleep(Duration::from_millis(200));
+
+        let worker = std::thread::spawn(move || {
+            // both calls are in the channel, unanswered, before the worker gets to them
+            sleep(Duration::from_millis(300));
+            acks_tx.testing_send_all(vec![SourceAck { seq: 1, outcome: AckOutcome::Committed },
+                                          SourceAck { seq: 2, outcome: AckOutcome::Rejected(crate::validation::RejectReason::OutOfRange) }], true);
+        });
+        let client = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        let started = Instant::now();
+        let outcomes: Vec<Result<u64, Code>> = client.block_on(async {
+            let channel = Channel::from_shared(format!("http://{}", addr))
+                .expect("valid uri")
+                .connect()
+                .await
+                .expect("connect");
+            let (first, second) = tokio::join!(call(channel.clone(), 7), async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                call(channel.clone(), 8).await
+            });
+            [first, second].into_iter().map(|r| r.map(|r| r.accepted_total).map_err(|s| s.code())).collect()
+        });
+        worker.join().expect("worker thread");
+
+        graph.request_shutdown();
+        graph.block_until_stopped(Duration::from_secs(1))?;
+
+        assert!(started.elapsed() >= Duration::from_millis(250), "{:?}", started.elapsed());
+        assert_eq!(outcomes, vec![Ok(1), Err(Code::InvalidArgument)]);
+        let values: Vec<u64> = generated_rx.testing_take_all().into_iter().map(|e| e.payload).collect();
+        assert_eq!(values, vec![7, 8]);
+        Ok(())
+    }
+
+    /// With the tls feature the source serves TLS, here on IPv6 loopback, and with `--tls-ca`
+    /// takes calls only from clients whose certificate the CA signed.
+    #[cfg(feature = "tls")]
+    #[test]
+    fn test_serves_tls_to_known_clients() -> Result<(), Box<dyn Error>> {
+        use tonic::transport::{Certificate, ClientTlsConfig, Identity};
+        let addr: SocketAddr = "[::1]:50519".parse()?;
+        let files = crate::tls::tls_tests::test_files(false);
+        let (cert, key) = files.identity.clone().expect("server identity");
+        let args = MainArg { grpc_listen: Some(addr), tls_cert: Some(cert), tls_key: Some(key), tls_ca: files.roots.clone(), ..Default::default() };
+        let mut graph = GraphBuilder::for_testing().build(args);
+        let (generated_tx, generated_rx) = graph.channel_builder().build();
+        let (metrics_tx, _metrics_rx) = graph.channel_builder().build::<CounterSample>();
+        let (_acks_tx, acks_rx) = graph.channel_builder().build::<SourceAck>();
+
+        let state = new_state();
+        graph.actor_builder()
+            .with_name("UnitTest")
+            .build(move |context| internal_behavior(context, generated_tx.clone(), metrics_tx.clone(), acks_rx.clone(), addr, state.clone()), SoloAct);
+        graph.start();
+        sleep(Duration::from_millis(200));
+
+        let ca = Certificate::from_pem(std::fs::read(files.roots.expect("roots"))?);
+        let (client_cert, client_key) = crate::tls::tls_tests::test_files(true).identity.expect("client identity");
+        let known = ClientTlsConfig::new().ca_certificate(ca.clone()).domain_name("localhost")
+            .identity(Identity::from_pem(std::fs::read(client_cert)?, std::fs::read(client_key)?));
+        let unknown = ClientTlsConfig::new().ca_certificate(ca).domain_name("localhost");
+        let client = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        let outcomes: Vec<bool> = client.block_on(async {
+            let mut outcomes = Vec::new();
+            for (tls, value) in [(known, 7), (unknown, 8)] {
+                let channel = Channel::from_shared(format!("https://{}", addr)).expect("valid uri").tls_config(tls).expect("tls config");
+                let accepted = match channel.connect().await {
+                    Ok(channel) => call(channel, value).await.is_ok(),
+                    Err(_) => false,
+                };
+                outcomes.push(accepted);
+            }
+            outcomes
+        });
+
+        graph.request_shutdown();
+        graph.block_until_stopped(Duration::from_secs(1))?;
+
+        assert_eq!(outcomes, vec![true, false]);
+        let values: Vec<u64> = generated_rx.testing_take_all().into_iter().map(|e| e.payload).collect();
+        assert_eq!(values, vec![7]);
+        Ok(())
+    }
+}
@@ -1,104 +1,361 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use steady_state::*;
+use serde_json::json;
+use crate::clock::{Clock, JumpGuard, SystemClock};
+use crate::event_time::{self, BeatTime};
+use crate::events;
+use crate::failure::{self, BeatFault, ChaosSwitch};
+use crate::fault::FaultPlan;
+use crate::restart::{RestartCauses, StartTimes};
+use crate::persistence;
+use crate::resume::{self, ResumePoint};
+use crate::state_lock::lock_state;
+use crate::window::WindowGate;
+use steady_state::actor_builder::{ActorBuilder, ScheduleAs};
+use crate::wiring::{RobustActor, Wiring};
+
+/// How often a heartbeat waiting on a full channel looks for a Ctrl-C.
+const INTERRUPT_POLL: Duration = Duration::from_millis(100);
 
 /// HeartbeatState holds state for the Heartbeat actor.
 /// All fields are preserved across panics, ensuring
 /// that timing and beat counts are never lost.
-pub(crate) struct HeartbeatState {
+pub struct HeartbeatState {
     /// The current beat count.
     pub(crate) count: u64,
+    /// The beats the run stops at: `--beats`, or the target of the run resumed with `--restore-from` or `--state-dir`.
+    pub(crate) beats_target: u64,
+    /// The count a `--restore-from` file or a `--state-dir` checkpoint gave the first start, when it had one.
+    pub(crate) resumed_from: Option<u64>,
     /// The total number of beats sent.
     pub(crate) beats_sent: u64,
+    /// Beats left out and beats sent twice under `--heartbeat-fault`.
+    pub(crate) beats_skipped: u64,
+    pub(crate) beats_doubled: u64,
+    /// Beats a `--workers` replica went without, its channel full of beats it had yet to spend.
+    pub(crate) replica_beats_dropped: u64,
     /// Number of times this actor has restarted (for robustness tracking).
     pub(crate) restart_count: u64,
+    /// Restarts by cause; `restart_count` also counts the first start.
+    pub(crate) restarts: RestartCauses,
+    /// Cold and warm starts, and how soon each got its first message out.
+    pub(crate) starts: StartTimes,
+    /// The faults injected into this actor, its demonstration panic among them, fixed at first start.
+    pub(crate) faults: FaultPlan,
+    /// Held here so a restart does not quietly resume a pipeline an operator paused.
+    pub(crate) paused: bool,
+    /// Held by the logger while its sink is down; independent of an operator pause.
+    pub(crate) held: bool,
+    /// Outside the `--active-window`; tracked so each crossing is logged once.
+    pub(crate) off_hours: bool,
+    /// Watches for wall-clock jumps and keeps beat times from running backwards across them.
+    pub(crate) clock: JumpGuard,
+}
+
+/// Operator commands for the heartbeat, which paces the whole pipeline.
+/// While paused no beats are sent, so the worker releases nothing and sources back up.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PipelineCommand {
+    Pause,
+    Resume,
+    /// Sent to the logger alone, just before an operator drain stops the pipeline, so it can
+    /// count what it flushes from there on.
+    Drain,
+}
+
+/// Whether the heartbeat is holding back beats on purpose: paused from the control socket,
+/// held by the logger during a sink outage, or outside the `--active-window`. Clones share one
+/// flag, so the worker can tell a deliberate pause from a stall.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PipelineHold(Arc<AtomicBool>);
+
+impl PipelineHold {
+    pub(crate) fn is_held(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set(&self, held: bool) {
+        self.0.store(held, Ordering::Relaxed);
+    }
+}
+
+impl PartialEq for PipelineHold {
+    fn eq(&self, other: &Self) -> bool {
+        self.is_held() == other.is_held()
+    }
 }
 
 /// Entry point for the Heartbeat actor.
 /// Demonstrates robust timing, state, and automatic restart.
 pub async fn run(
     actor: SteadyActorShadow,
-    heartbeat_tx: SteadyTx<u64>,
+    control_rx: SteadyRx<PipelineCommand>,
+    flow_rx: SteadyRx<PipelineCommand>,
+    heartbeat_tx: SteadyTx<BeatTime>,
+    replica_tx: Vec<SteadyTx<BeatTime>>,
     state: SteadyState<HeartbeatState>,
 ) -> Result<(), Box<dyn Error>> {
-    let actor = actor.into_spotlight([], [&heartbeat_tx]);
+    // The spotlight takes a fixed number of channels, so the per-worker ones go unmonitored.
+    let actor = actor.into_spotlight([&control_rx, &flow_rx], [&heartbeat_tx]);
     if actor.use_internal_behavior {
-        internal_behavior(actor, heartbeat_tx, state).await
+        internal_behavior(actor, SystemClock, control_rx, flow_rx, heartbeat_tx, replica_tx, state).await
     } else {
         actor.simulated_behavior(vec!(&heartbeat_tx)).await
     }
 }
 
+/// The heartbeat as a pipeline stage; left out with `--no-heartbeat`, when nothing writes
+/// the worker's heartbeat channel and the worker is told not to wait on it.
+pub struct Heartbeat;
+
+impl RobustActor for Heartbeat {
+    fn name(&self) -> &'static str {
+        crate::NAME_HEARTBEAT
+    }
+    fn inputs(&self) -> &'static [&'static str] {
+        &["control", "heartbeat_flow"]
+    }
+    fn outputs(&self) -> &'static [&'static str] {
+        &["heartbeat", "shard_heartbeat"]
+    }
+    fn enabled(&self, args: &crate::MainArg) -> bool {
+        !args.no_heartbeat
+    }
+    fn shares_thread(&self, _args: &crate::MainArg) -> bool {
+        true
+    }
+    fn build(&self, builder: ActorBuilder, wiring: &Wiring, schedule: ScheduleAs<'_>) {
+        let (control_rx, flow_rx, heartbeat_tx) = (wiring.control.rx.clone(), wiring.heartbeat_flow.rx.clone(), wiring.heartbeat.tx.clone());
+        let replica_tx: Vec<_> = wiring.pool.iter().map(|links| links.heartbeat.tx.clone()).collect();
+        let state = wiring.state.heartbeat.clone();
+        builder.build(move |context| {
+            crate::priority::apply(&context, crate::NAME_HEARTBEAT);
+            run(context, control_rx.clone(), flow_rx.clone(), heartbeat_tx.clone(), replica_tx.clone(), state.clone())
+        }, schedule);
+    }
+}
+
 /// Internal behavior for the Heartbeat actor.
 /// Demonstrates robust periodic signaling and intentional failure injection.
 /// State is always updated only after a successful send.
-async fn internal_behavior<A: SteadyActor>(
+/// With a `--workers` pool each beat also goes to every replica on its own channel, while the
+/// one on `heartbeat_tx` paces the router.
+async fn internal_behavior<A: SteadyActor, C: Clock>(
     mut actor: A,
-    heartbeat_tx: SteadyTx<u64>,
+    clock: C,
+    control_rx: SteadyRx<PipelineCommand>,
+    flow_rx: SteadyRx<PipelineCommand>,
+    heartbeat_tx: SteadyTx<BeatTime>,
+    replica_tx: Vec<SteadyTx<BeatTime>>,
     state: SteadyState<HeartbeatState>,
 ) -> Result<(), Box<dyn Error>> {
     let args = actor.args::<crate::MainArg>().expect("unable to downcast"); //#!#//
     let rate = Duration::from_millis(args.rate_ms);
-    let beats = args.beats;
+    let restore_from = args.restore_from.clone();
+    // Beats only carry the wall clock when something downstream keeps event time.
+    let timed = event_time::window_micros(args).is_some();
+    let mut window = WindowGate::from_args(args);
+    let faults = args.heartbeat_fault.clone();
+    let chaos = ChaosSwitch::from_args(args);
+    let hold = args.hold.clone();
 
-    let mut state = state.lock(|| HeartbeatState {
-        count: 0,
-        beats_sent: 0,
-        restart_count: 0, // using this pattern, we can detect our own restarts //#!#//
-    }).await;
+    let Some(mut state) = lock_state(&actor, crate::NAME_HEARTBEAT, &state, || {
+        // Read once, on the first start; a restart within the process keeps its own count.
+        let resumed = restore_from.as_deref().and_then(|path| resume::load(path).unwrap_or_else(|e| {
+            warn!("Heartbeat cannot resume from {:?}, starting from beat 0: {}", path, e);
+            None
+        })).or_else(|| persistence::restored().map(|snapshot| ResumePoint {
+            // The barrier beat itself released values past the cut, so it is sent again.
+            count: snapshot.checkpoint.barrier,
+            beats: snapshot.beats,
+        }));
+        HeartbeatState {
+            count: resumed.map_or(0, |point| point.count),
+            beats_target: resumed.map_or(args.beats, |point| point.beats),
+            resumed_from: resumed.map(|point| point.count),
+            beats_sent: 0,
+            beats_skipped: 0,
+            beats_doubled: 0,
+            replica_beats_dropped: 0,
+            restart_count: 0, // using this pattern, we can detect our own restarts //#!#//
+            restarts: RestartCauses::default(),
+            starts: StartTimes::default(),
+            faults: FaultPlan::for_actor(args, "heartbeat"),
+            paused: false,
+            held: false,
+            off_hours: false,
+            clock: JumpGuard::from_args(args),
+        }
+    }).await else {
+        return Ok(());
+    };
 
     // Track restarts for resilience metrics.
     state.restart_count += 1;
+    if state.restart_count > 1 {
+        let (cause, starts) = (state.restart_cause(), state.restart_count);
+        state.restarts.record(crate::NAME_HEARTBEAT, cause, starts);
+    }
+    let (warm, sent) = (state.is_warm(), state.beats_sent);
+    state.starts.begin(crate::NAME_HEARTBEAT, warm, sent);
+    let beats = state.beats_target;
+    if let Some(count) = state.resumed_from && state.restart_count == 1 {
+        info!("Heartbeat resuming at count {} of {} beats", count, beats);
+        if beats != args.beats {
+            warn!("Heartbeat keeps the resumed run's target of {} beats, not --beats {}", beats, args.beats);
+        }
+    }
     info!(
-        "Heartbeat starting (restart #{}) with count: {}, beats_sent: {}, rate: {:?}, beats_desired: {}",
-        state.restart_count, state.count, state.beats_sent, rate, beats
+        "Heartbeat starting (restart #{}) with count: {}, beats_sent: {}, rate: {:?}, beats_desired: {}, paused: {}",
+        state.restart_count, state.count, state.beats_sent, rate, beats, state.paused
     );
 
+    let mut control_rx = control_rx.lock().await;
+    let mut flow_rx = flow_rx.lock().await;
     let mut heartbeat_tx = heartbeat_tx.lock().await;
+    let mut replicas = Vec::with_capacity(replica_tx.len());
+    for tx in &replica_tx {
+        replicas.push(tx.lock().await);
+    }
+    if beats > 0 && state.count >= beats {
+        info!("Heartbeat had already completed {} beats, requesting graph stop", beats);
+        actor.request_shutdown().await;
+    }
 
-    while actor.is_running(|| heartbeat_tx.mark_closed()) {
-        // Wait for both the periodic timer and channel space.
-        await_for_all!(  //#!#//
-            actor.wait_periodic(rate),
-            actor.wait_vacant(&mut heartbeat_tx, 1)
-        );
+    while actor.is_running(|| i!(heartbeat_tx.mark_closed()) && i!(replicas.iter_mut().all(|tx| tx.mark_closed()))) {
+        let sent = state.beats_sent;
+        state.starts.progress(crate::NAME_HEARTBEAT, sent);
+        // Wait for the periodic timer, then for channel space a poll at a time, so a Ctrl-C is
+        // still seen while a backed-up pipeline keeps the channel full.
+        await_for_all!(actor.wait_periodic(rate));  //#!#//
+        while actor.vacant_units(&mut heartbeat_tx) == 0
+            && !crate::signals::interrupted()
+            && !actor.is_liveliness_stop_requested() {
+            await_for_any!(actor.wait_vacant(&mut heartbeat_tx, 1), async { actor.wait(INTERRUPT_POLL).await; false });
+        }
+        // Ctrl-C stops the pipeline the same way finishing the beats does, so it drains.
+        if crate::signals::interrupted() {
+            info!("Heartbeat interrupted at count {}, requesting graph stop", state.count);
+            actor.request_shutdown().await;
+            continue;
+        }
 
         // --- Robustness Demonstration: Intentional Panic ---
-        #[cfg(not(test))]
-        if state.count == 7 && state.restart_count == 1 {
+        let count = state.count;
+        let strike = state.faults.strike(count);
+        if strike.panic {
             error!(
                 "Heartbeat intentionally panicking at count {} to demonstrate robustness!",
                 state.count
             );
             panic!("Intentional panic for robustness demonstration - DO NOT COPY THIS PATTERN!");
         }
-      
+        if let Some(delay) = strike.delay {
+            warn!("Heartbeat holding {:?} at count {} (injected fault)", delay, state.count);
+            actor.wait(delay).await;
+        }
+        if strike.send_fail {
+            warn!("Heartbeat dropping the tick at count {} (injected send failure)", state.count);
+            continue;
+        }
         // --- End Robustness Demonstration ---
 
+        // Commands are read once per beat, so a pause takes effect within one period.
+        while let Some(command) = actor.try_take(&mut control_rx) {
+            let paused = command == PipelineCommand::Pause;
+            if paused != state.paused {
+                info!("Heartbeat {} at count {}", if paused { "paused" } else { "resumed" }, state.count);
+            }
+            state.paused = paused;
+        }
+        while let Some(command) = actor.try_take(&mut flow_rx) {
+            let held = command == PipelineCommand::Pause;
+            if held != state.held {
+                info!("Heartbeat {} by the logger at count {}", if held { "held" } else { "released" }, state.count);
+            }
+            state.held = held;
+        }
+        // Pacing is on the monotonic clock and never sees a jump; beat times and logs do.
+        let (wall_micros, jump) = state.clock.read(&clock);
+        if let Some(step) = jump {
+            warn!(
+                "Heartbeat saw the wall clock jump {}ms {} at count {}, re-anchoring beat times",
+                step.unsigned_abs() / 1000, if step < 0 { "back" } else { "forward" }, state.count
+            );
+            events::emit("alert", json!({"kind": "clock_jump", "step_micros": step, "beat": state.count}));
+        }
+        let off_hours = !window.is_open();
+        if off_hours != state.off_hours {
+            info!("Heartbeat {} the active window at count {}", if off_hours { "paused outside" } else { "resumed inside" }, state.count);
+            state.off_hours = off_hours;
+        }
+        hold.set(state.paused || state.held || state.off_hours);
+        if hold.is_held() {
+            continue;
+        }
+
+        // A run resumed after it finished its beats sends no more while it stops.
+        if beats > 0 && state.count >= beats {
+            continue;
+        }
+
         // Prepare the beat value, attempt to send, then update state only on success.
         let beat_value = state.count;
-        match actor.try_send(&mut heartbeat_tx, beat_value) {
-            SendOutcome::Success => {
-                state.count += 1;
-                state.beats_sent += 1;
-                trace!("Heartbeat sent: {}, total beats: {}", beat_value, state.beats_sent);
-
-                if beats == state.count {
-                    info!("Heartbeat completed {} beats, requesting graph stop", beats);
-                    actor.request_shutdown().await;
+        let beat = if timed { BeatTime::at(beat_value, wall_micros) } else { BeatTime::untimed(beat_value) };
+        let fault = failure::beat_fault_at(&faults, beat_value).filter(|_| chaos.is_on());
+        if fault == Some(BeatFault::Skip) {
+            // The tick passes as if the timer never fired; the count moves on, leaving a gap.
+            state.count += 1;
+            state.beats_skipped += 1;
+            warn!("Heartbeat skipping beat {} on purpose (--heartbeat-fault)", beat_value);
+        } else {
+            match actor.try_send(&mut heartbeat_tx, beat) {
+                SendOutcome::Success => {
+                    state.count += 1;
+                    state.beats_sent += 1;
+                    trace!("Heartbeat sent: {}, total beats: {}", beat_value, state.beats_sent);
+                    // Only the router's channel is waited on. A replica with a full channel has
+                    // beats in hand already, so it goes without this one rather than hold up the rest.
+                    for replica in replicas.iter_mut() {
+                        if !actor.try_send(replica, beat).is_sent() {
+                            state.replica_beats_dropped += 1;
+                        }
+                    }
+                    // A second beat for the same tick, only if there is room; the first one counted already.
+                    if fault == Some(BeatFault::Stutter) && actor.try_send(&mut heartbeat_tx, beat).is_sent() {
+                        for replica in replicas.iter_mut() {
+                            let _ = actor.try_send(replica, beat);
+                        }
+                        state.beats_sent += 1;
+                        state.beats_doubled += 1;
+                        warn!("Heartbeat sent beat {} twice on purpose (--heartbeat-fault)", beat_value);
+                    }
                 }
+                SendOutcome::Blocked(_) => {
+                    // Channel is full, try again next loop.
+                    continue;
+                }
+
+                SendOutcome::Timeout(_) => {continue;}
+                SendOutcome::Closed(_) => {continue;}
             }
-            SendOutcome::Blocked(_) => {
-                // Channel is full, try again next loop.
-                continue;
-            }
+        }
 
-            SendOutcome::Timeout(_) => {continue;}
-            SendOutcome::Closed(_) => {continue;}
+        if let Some(path) = &restore_from
+            && let Err(e) = resume::save(path, ResumePoint { count: state.count, beats }) {
+            warn!("Heartbeat could not save its count {} to {:?}: {}", state.count, path, e);
+        }
+        if beats == state.count {
+            info!("Heartbeat completed {} beats, requesting graph stop", beats);
+            events::emit("shutdown", json!({"phase": "requested", "reason": "beats_completed", "beats": beats}));
+            actor.request_shutdown().await;
         }
     }
 
     info!(
-        "Heartbeat shutting down. Final count: {}, total beats sent: {}",
-        state.count, state.beats_sent
+        "Heartbeat shutting down. Final count: {}, total beats sent: {}, skipped: {}, doubled: {}, dropped by replicas: {}, clock jumps: {}",
+        state.count, state.beats_sent, state.beats_skipped, state.beats_doubled, state.replica_beats_dropped, state.clock.jumps
     );
     Ok(())
 }
@@ -115,21 +372,185 @@ pub(crate) mod heartbeat_tests {
         let mut graph = GraphBuilder::for_testing().build(MainArg {
             rate_ms: 0,
             beats: 0,
+            ..Default::default()
         });
+        let (_control_tx, control_rx) = graph.channel_builder().build();
+        let (_flow_tx, flow_rx) = graph.channel_builder().build();
         let (heartbeat_tx, heartbeat_rx) = graph.channel_builder().build();
 
         let state = new_state();
         graph.actor_builder()
             .with_name("UnitTest")
             .build(move |context|
-                       internal_behavior(context, heartbeat_tx.clone(), state.clone())
+                       internal_behavior(context, SystemClock, control_rx.clone(), flow_rx.clone(), heartbeat_tx.clone(), Vec::new(), state.clone())
                    , SoloAct);
 
         graph.start();
         sleep(Duration::from_millis(1000 * 3));
         graph.request_shutdown();
         graph.block_until_stopped(Duration::from_secs(1))?;
-        assert_steady_rx_eq_take!(&heartbeat_rx, vec!(0,1));
+        assert_steady_rx_eq_take!(&heartbeat_rx, [0,1].map(BeatTime::untimed).to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn test_heartbeat_paused() -> Result<(), Box<dyn Error>> {
+        let args = MainArg {
+            rate_ms: 10,
+            beats: 0,
+            ..Default::default()
+        };
+        let hold = args.hold.clone();
+        let mut graph = GraphBuilder::for_testing().build(args);
+        let (control_tx, control_rx) = graph.channel_builder().build();
+        let (_flow_tx, flow_rx) = graph.channel_builder().build();
+        let (heartbeat_tx, heartbeat_rx) = graph.channel_builder().build::<BeatTime>();
+
+        let state = new_state();
+        let probe = state.clone();
+        graph.actor_builder()
+            .with_name("UnitTest")
+            .build(move |context|
+                       internal_behavior(context, SystemClock, control_rx.clone(), flow_rx.clone(), heartbeat_tx.clone(), Vec::new(), state.clone())
+                   , SoloAct);
+
+        control_tx.testing_send_all(vec![PipelineCommand::Pause], false);
+        graph.start();
+        sleep(Duration::from_millis(200));
+        graph.request_shutdown();
+        graph.block_until_stopped(Duration::from_secs(1))?;
+        assert!(heartbeat_rx.testing_take_all().is_empty());
+        assert!(probe.try_lock_sync().expect("state").paused);
+        // the worker is told the missing beats are on purpose
+        assert!(hold.is_held());
+        Ok(())
+    }
+
+    #[test]
+    fn test_heartbeat_skips_and_stutters() -> Result<(), Box<dyn Error>> {
+        let mut graph = GraphBuilder::for_testing().build(MainArg {
+            rate_ms: 5,
+            beats: 10,
+            heartbeat_fault: ["skip=3", "stutter=4"].iter()
+                .map(|f| failure::parse_heartbeat_fault(f).expect("fault")).collect(),
+            ..Default::default()
+        });
+        let (_control_tx, control_rx) = graph.channel_builder().build();
+        let (_flow_tx, flow_rx) = graph.channel_builder().build();
+        let (heartbeat_tx, heartbeat_rx) = graph.channel_builder().build::<BeatTime>();
+
+        let state = new_state();
+        let probe = state.clone();
+        graph.actor_builder()
+            .with_name("UnitTest")
+            .build(move |context|
+                       internal_behavior(context, SystemClock, control_rx.clone(), flow_rx.clone(), heartbeat_tx.clone(), Vec::new(), state.clone())
+                   , SoloAct);
+
+        graph.start();
+        graph.block_until_stopped(Duration::from_secs(2))?;
+        assert_steady_rx_eq_take!(&heartbeat_rx, [0,1,2,4,4,5,7,8,8].map(BeatTime::untimed).to_vec());
+        let state = crate::state_lock::settled(&probe).expect("heartbeat state");
+        assert_eq!((state.beats_sent, state.beats_skipped, state.beats_doubled), (9, 3, 2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_heartbeat_sends_every_beat_to_every_replica() -> Result<(), Box<dyn Error>> {
+        let mut graph = GraphBuilder::for_testing().build(MainArg { rate_ms: 5, beats: 4, workers: 2, ..Default::default() });
+        let (_control_tx, control_rx) = graph.channel_builder().build();
+        let (_flow_tx, flow_rx) = graph.channel_builder().build();
+        let (heartbeat_tx, heartbeat_rx) = graph.channel_builder().build::<BeatTime>();
+        let (replica_tx, replica_rx) = graph.channel_builder().build::<BeatTime>();
+        // this replica is not spending its beats, so its channel fills
+        let (full_tx, full_rx) = graph.channel_builder().with_capacity(2).build::<BeatTime>();
+
+        let state = new_state();
+        let probe = state.clone();
+        graph.actor_builder()
+            .with_name("UnitTest")
+            .build(move |context|
+                       internal_behavior(context, SystemClock, control_rx.clone(), flow_rx.clone(), heartbeat_tx.clone(),
+                                         vec![replica_tx.clone(), full_tx.clone()], state.clone())
+                   , SoloAct);
+
+        graph.start();
+        graph.block_until_stopped(Duration::from_secs(2))?;
+        assert_steady_rx_eq_take!(&heartbeat_rx, [0,1,2,3].map(BeatTime::untimed).to_vec());
+        assert_steady_rx_eq_take!(&replica_rx, [0,1,2,3].map(BeatTime::untimed).to_vec());
+        assert_steady_rx_eq_take!(&full_rx, [0,1].map(BeatTime::untimed).to_vec());
+        let state = crate::state_lock::settled(&probe).expect("heartbeat state");
+        assert_eq!((state.beats_sent, state.replica_beats_dropped), (4, 2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_heartbeat_rides_out_a_clock_jump() -> Result<(), Box<dyn Error>> {
+        let mut graph = GraphBuilder::for_testing().build(MainArg {
+            rate_ms: 10,
+            beats: 30,
+            event_window_ms: 1000,
+            clock_jump_ms: 1000,
+            ..Default::default()
+        });
+        let (_control_tx, control_rx) = graph.channel_builder().build();
+        let (_flow_tx, flow_rx) = graph.channel_builder().build();
+        let (heartbeat_tx, heartbeat_rx) = graph.channel_builder().build::<BeatTime>();
+
+        let clock = crate::clock::SteppedClock::default();
+        let stepper = clock.clone();
+        let state = new_state();
+        let probe = state.clone();
+        graph.actor_builder()
+            .with_name("UnitTest")
+            .build(move |context|
+                       internal_behavior(context, clock.clone(), control_rx.clone(), flow_rx.clone(), heartbeat_tx.clone(), Vec::new(), state.clone())
+                   , SoloAct);
+
+        graph.start();
+        sleep(Duration::from_millis(100));
+        stepper.step(-3_600_000_000);
+        graph.block_until_stopped(Duration::from_secs(2))?;
+        let beats = heartbeat_rx.testing_take_all();
+        // every beat still sent once, and their times never went back
+        assert_eq!(beats.iter().map(|b| b.beat).collect::<Vec<_>>(), (0..30).collect::<Vec<_>>());
+        assert!(beats.windows(2).all(|pair| pair[0].wall_micros <= pair[1].wall_micros));
+        let state = crate::state_lock::settled(&probe).expect("heartbeat state");
+        assert_eq!(state.clock.jumps, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_heartbeat_resumes_from_file() -> Result<(), Box<dyn Error>> {
+        let path = std::env::temp_dir().join(format!("robust-heartbeat-{}.json", crate::arg::new_run_id()));
+        // an earlier process with 7 beats to go got through 5 of them
+        resume::save(&path, ResumePoint { count: 5, beats: 7 })?;
+        let mut graph = GraphBuilder::for_testing().build(MainArg {
+            rate_ms: 5,
+            beats: 120,
+            restore_from: Some(path.clone()),
+            ..Default::default()
+        });
+        let (_control_tx, control_rx) = graph.channel_builder().build();
+        let (_flow_tx, flow_rx) = graph.channel_builder().build();
+        let (heartbeat_tx, heartbeat_rx) = graph.channel_builder().build::<BeatTime>();
+
+        let state = new_state();
+        let probe = state.clone();
+        graph.actor_builder()
+            .with_name("UnitTest")
+            .build(move |context|
+                       internal_behavior(context, SystemClock, control_rx.clone(), flow_rx.clone(), heartbeat_tx.clone(), Vec::new(), state.clone())
+                   , SoloAct);
+
+        graph.start();
+        graph.block_until_stopped(Duration::from_secs(2))?;
+        // the original target ends the run, not the new --beats
+        assert_steady_rx_eq_take!(&heartbeat_rx, [5,6].map(BeatTime::untimed).to_vec());
+        let state = crate::state_lock::settled(&probe).expect("heartbeat state");
+        assert_eq!((state.count, state.beats_sent, state.resumed_from), (7, 2, Some(5)));
+        assert_eq!(resume::load(&path)?, Some(ResumePoint { count: 7, beats: 7 }));
+        std::fs::remove_file(&path)?;
         Ok(())
     }
 }
@@ -1,145 +1,1020 @@
 use steady_state::*;
+use serde_json::json;
+use crate::event_time::{self, ClosedWindow, EventWindows};
+use crate::events;
+use crate::fault::{FaultPlan, Strike};
+use crate::fsync::Fsync;
+use crate::restart::{RestartCauses, StartTimes};
+use crate::state_lock::lock_state;
+use crate::actor::dead_letter::{DeadLetter, Poisoned};
+use crate::actor::heartbeat::PipelineCommand;
 use crate::actor::worker::FizzBuzzMessage;
+use crate::classify::{Classify, Filing, FizzBuzz};
+use crate::timing::{Envelope, Stamp};
+use crate::trace;
+use crate::troupe::{Fairness, TurnBudget};
+use crate::validation::RejectedValue;
+use crate::actor::reconciler::{backlog_bytes, ChannelDepth, CounterSample, SamplePacer, METRICS_LOGGER};
+use crate::checkpoint::{Checkpoint, WorkerCheckpoint};
+use crate::persistence::{self, StateDir};
+use crate::rate_limit::{buckets_from_args, kind_of, TokenBucket, RECORD_KINDS};
+use crate::record::{RecordFields, RecordFormat, CSV_COLUMNS};
+use crate::rules::{RuleMatches, RuleSet};
+use crate::drain::{persist_residue, DrainDeadline};
+use crate::sink::{Sink, SinkFailure};
+use crate::tenant::{self, TenantSample};
+use crate::slo::{LatencyHistogram, LatencyReport, LatencySlo};
+use crate::stage_time::{IterationClock, StageTime};
+use crate::throttle::CommitFeedback;
+use crate::wal::{Wal, WalCodec};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::io;
+use steady_state::actor_builder::{ActorBuilder, ScheduleAs};
+use crate::startup::StartGate;
+use crate::wiring::{RobustActor, Wiring};
 
 /// LoggerState holds state for the Logger actor.
 /// All fields are preserved across panics, ensuring
 /// that no data is lost and the logger can resume exactly where it left off.
-pub(crate) struct LoggerState {
+pub struct LoggerState {
     pub(crate) messages_logged: u64,
     pub(crate) fizz_count: u64,
     pub(crate) buzz_count: u64,
     pub(crate) fizzbuzz_count: u64,
     pub(crate) value_count: u64,
+    /// Messages classified by `--rule` rules, in all and by the rules they matched; the
+    /// latter gives each record its running total.
+    pub(crate) rule_count: u64,
+    pub(crate) rule_totals: BTreeMap<RuleMatches, u64>,
+    /// Values the worker's validation rules diverted to the side channel.
+    pub(crate) rejected_count: u64,
+    /// Messages dropped after repeatedly failing processing.
+    pub(crate) showstoppers_dropped: u64,
+    /// Sequence of the last worker message handled, and re-sent copies dropped for being
+    /// at or below it. Kept here so a logger restart still recognizes them.
+    pub(crate) last_seq: u64,
+    pub(crate) duplicates_suppressed: u64,
+    /// Sequence the next WAL append will receive; everything below it is durable.
+    pub(crate) wal_seq: u64,
+    /// WAL entries the sink has accepted. Lags `wal_seq` while the sink is down.
+    pub(crate) sink_seq: u64,
+    /// WAL entries delivered by catch-up replay rather than on the live path.
+    pub(crate) replayed: u64,
+    pub(crate) sink_failures: u64,
+    /// Committed records not yet forced onto disk under `--fsync`.
+    pub(crate) fsync: Fsync,
+    /// Coordinated checkpoints completed by barriers reaching the logger, and the latest one.
+    pub(crate) checkpoints_completed: u64,
+    pub(crate) checkpoints_torn: u64,
+    pub(crate) last_checkpoint: Option<Checkpoint>,
+    /// Latency SLO totals: messages measured and late, windows closed and failed.
+    pub(crate) slo_measured: u64,
+    pub(crate) slo_late: u64,
+    pub(crate) slo_windows: u64,
+    pub(crate) slo_windows_failed: u64,
+    /// Generation-to-commit latency of every logged message, with or without an SLO.
+    pub(crate) latency: LatencyHistogram,
+    /// The same since the last `--latency-report-ms` report, the reports made and the latest.
+    pub(crate) latency_period: LatencyHistogram,
+    pub(crate) latency_reports: u64,
+    pub(crate) last_latency_report: Option<LatencyReport>,
+    /// The same latency by the `--priority` the messages carried, to show none waits without end.
+    pub(crate) latency_by_priority: BTreeMap<u8, LatencyHistogram>,
+    /// Time per loop iteration spent logging and spent waiting on the channels or the sink retry timer.
+    pub(crate) time: StageTime,
+    /// Use of the shared thread, against `--troupe-budget` in troupe mode.
+    pub(crate) fairness: Fairness,
+    /// Tumbling `--event-window-ms` windows over the messages' event time.
+    pub(crate) event_windows: EventWindows,
+    /// Messages logged per tenant, for values a source labeled.
+    pub(crate) tenant_logged: BTreeMap<u32, u64>,
+    /// Worker messages still waiting when the drain deadline passed, moved to the backlog file.
+    pub(crate) residue: u64,
+    /// Whether the logger is holding the heartbeat and generator for a sink outage, and how
+    /// often it has. Kept here so a restart releases them once the sink answers again.
+    pub(crate) flow_paused: bool,
+    pub(crate) flow_pauses: u64,
+    /// Whether the operator has paused sink writes from the control socket, how often, and
+    /// the deepest the input channel got meanwhile. Kept here so a restart stays paused.
+    pub(crate) sink_paused: bool,
+    pub(crate) sink_pauses: u64,
+    pub(crate) paused_backlog_max: u64,
+    /// Messages logged when an operator `drain` reached the logger, so the final report can tell
+    /// how many were flushed after intake stopped.
+    pub(crate) drain_from: Option<u64>,
+    /// Token buckets for `--log-rate-limit`, one per record kind, and the records they held back.
+    /// Kept here so a restart cannot refill them and flood the output.
+    pub(crate) rate_limits: [Option<TokenBucket>; RECORD_KINDS.len()],
+    pub(crate) rate_limited: u64,
     pub(crate) restart_count: u64,
+    /// Restarts by cause; `restart_count` also counts the first start.
+    pub(crate) restarts: RestartCauses,
+    /// Cold and warm starts, and how soon each got its first message out.
+    pub(crate) starts: StartTimes,
+    /// The faults injected into this actor, its demonstration panic among them, fixed at first start.
+    pub(crate) faults: FaultPlan,
+}
+
+/// Signals from the logger to other actors: flow control back up the pipeline, one channel
+/// per upstream actor, tenant counts for the reconciler and dropped showstoppers.
+#[derive(Clone)]
+pub struct FlowTx {
+    pub(crate) heartbeat: SteadyTx<PipelineCommand>,
+    pub(crate) generator: SteadyTx<PipelineCommand>,
+    /// Commit feedback for the generator's throttle, sent only with `--flow-target-inflight`.
+    pub(crate) commits: SteadyTx<CommitFeedback>,
+    /// Per-tenant logged counts for the reconciler, sent only for labeled values.
+    pub(crate) tenants: SteadyTx<TenantSample>,
+    /// Records dropped as showstoppers, for the dead-letter actor.
+    pub(crate) dead_letter: SteadyTx<DeadLetter>,
 }
 
 /// Entry point for the Logger actor.
 /// Demonstrates robust, persistent state, peek-before-commit, and automatic restart.
+#[allow(clippy::too_many_arguments)] // one parameter per channel keeps the wiring explicit
 pub async fn run(
     actor: SteadyActorShadow,
-    fizz_buzz_rx: SteadyRx<FizzBuzzMessage>,
+    fizz_buzz_rx: SteadyRx<Envelope<FizzBuzzMessage>>,
+    rejected_rx: SteadyRx<RejectedValue>,
+    metrics_tx: SteadyTx<CounterSample>,
+    checkpoint_rx: SteadyRx<WorkerCheckpoint>,
+    control_rx: SteadyRx<PipelineCommand>,
+    flow_tx: FlowTx,
+    gate: StartGate,
+    state: SteadyState<LoggerState>,
+) -> Result<(), Box<dyn Error>> {
+    let classifier = FizzBuzz::from_args(actor.args::<crate::MainArg>().expect("unable to downcast"));
+    run_with(actor, classifier, fizz_buzz_rx, rejected_rx, metrics_tx, checkpoint_rx, control_rx, flow_tx, gate, state).await
+}
+
+/// The logger for any domain: `classifier` files each message, and everything else, the sink,
+/// the WAL, checkpoints and flow control, is the same as for FizzBuzz.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_with<C: Classify>(
+    actor: SteadyActorShadow,
+    classifier: C,
+    fizz_buzz_rx: SteadyRx<Envelope<C::Message>>,
+    rejected_rx: SteadyRx<RejectedValue>,
+    metrics_tx: SteadyTx<CounterSample>,
+    checkpoint_rx: SteadyRx<WorkerCheckpoint>,
+    control_rx: SteadyRx<PipelineCommand>,
+    flow_tx: FlowTx,
+    gate: StartGate,
     state: SteadyState<LoggerState>,
 ) -> Result<(), Box<dyn Error>> {
-    let actor = actor.into_spotlight([&fizz_buzz_rx], []);
+    let actor = actor.into_spotlight([&fizz_buzz_rx, &rejected_rx, &checkpoint_rx, &control_rx], [&metrics_tx, &flow_tx.heartbeat, &flow_tx.generator, &flow_tx.commits, &flow_tx.tenants, &flow_tx.dead_letter]);
     if actor.use_internal_behavior {
-        internal_behavior(actor, fizz_buzz_rx, state).await
+        // Opened on every start so a restart never reuses a handle that failed.
+        let args = actor.args::<crate::MainArg>().expect("unable to downcast");
+        let sink = args.sink.open(args)?;
+        // The stages that wait for the logger start once its sink is open.
+        gate.ready();
+        internal_behavior(actor, classifier, fizz_buzz_rx, rejected_rx, metrics_tx, checkpoint_rx, control_rx, flow_tx, sink, state).await
+    } else {
+        gate.ready();
+        actor.simulated_behavior(vec!(&fizz_buzz_rx, &rejected_rx, &checkpoint_rx)).await
+    }
+}
+
+/// The logger as a pipeline stage.
+pub struct Logger;
+
+impl RobustActor for Logger {
+    fn name(&self) -> &'static str {
+        crate::NAME_LOGGER
+    }
+    fn inputs(&self) -> &'static [&'static str] {
+        &["classified", "rejected", "checkpoint", "logger_control"]
+    }
+    fn outputs(&self) -> &'static [&'static str] {
+        &["metrics", "heartbeat_flow", "generator_flow", "commit_feedback", "tenants", "logger_dead_letter"]
+    }
+    fn shares_thread(&self, _args: &crate::MainArg) -> bool {
+        true // with the heartbeat
+    }
+    fn announces_ready(&self) -> bool {
+        true
+    }
+    fn build(&self, builder: ActorBuilder, wiring: &Wiring, schedule: ScheduleAs<'_>) {
+        let (classified_rx, rejected_rx) = (wiring.classified.rx.clone(), wiring.rejected.rx.clone());
+        let (checkpoint_rx, control_rx) = (wiring.checkpoint.rx.clone(), wiring.logger_control.rx.clone());
+        let metrics_tx = wiring.metrics_tx[METRICS_LOGGER].clone();
+        let flow_tx = FlowTx {
+            heartbeat: wiring.heartbeat_flow.tx.clone(),
+            generator: wiring.generator_flow.tx.clone(),
+            commits: wiring.commit_feedback.tx.clone(),
+            tenants: wiring.tenants.tx.clone(),
+            dead_letter: wiring.logger_dead_letter.tx.clone(),
+        };
+        let (gate, state) = (wiring.start_gate(self), wiring.state.logger.clone());
+        builder.build(move |context| {
+            crate::priority::apply(&context, crate::NAME_LOGGER);
+            run(context, classified_rx.clone(), rejected_rx.clone(), metrics_tx.clone(), checkpoint_rx.clone(), control_rx.clone(), flow_tx.clone(), gate.clone(), state.clone())
+        }, schedule);
+    }
+}
+
+/// Tracks whether the sink is in an outage so a failing sink is retried on a
+/// schedule instead of on every wake-up.
+/// Held as a local: after a restart the sink is assumed healthy and tried at once.
+struct SinkGate {
+    sink: Box<dyn Sink>,
+    retry_interval: Duration,
+    down_since: Option<Instant>,
+    next_attempt: Instant,
+    /// `--sink-failure` for this sink, and the writes that have failed in a row against it.
+    on_failure: SinkFailure,
+    failed_in_a_row: u32,
+    /// Set by an injected send failure: the next write fails as if the sink had refused it.
+    fail_next: bool,
+}
+
+impl SinkGate {
+    fn new(sink: Box<dyn Sink>, retry_interval: Duration, on_failure: SinkFailure) -> Self {
+        SinkGate { sink, retry_interval, down_since: None, next_attempt: Instant::now(), on_failure, failed_in_a_row: 0, fail_next: false }
+    }
+
+    fn is_down(&self) -> bool {
+        self.down_since.is_some()
+    }
+
+    /// How long the current outage has lasted, if there is one.
+    fn down_for(&self) -> Option<Duration> {
+        self.down_since.map(|since| since.elapsed())
+    }
+
+    fn can_attempt(&self) -> bool {
+        !self.is_down() || Instant::now() >= self.next_attempt
+    }
+
+    /// Writes one record, logging the start and end of an outage. Returns true if the sink took it.
+    fn write(&mut self, record: &str, state: &mut LoggerState) -> bool {
+        let written = if std::mem::take(&mut self.fail_next) {
+            Err(std::io::Error::other("injected send failure"))
+        } else {
+            self.sink.write_record(record)
+        };
+        match written {
+            Ok(()) => {
+                self.failed_in_a_row = 0;
+                if let Some(since) = self.down_since.take() {
+                    info!("Logger sink recovered after {:?}", since.elapsed());
+                }
+                true
+            }
+            Err(e) => {
+                state.sink_failures += 1;
+                self.failed_in_a_row += 1;
+                if let SinkFailure::Panic(after) = self.on_failure
+                    && self.failed_in_a_row >= after {
+                    error!("Logger sink failed {} writes in a row, panicking to restart with a fresh sink: {}", after, e);
+                    panic!("Logger sink failed {} writes in a row (--sink-failure panic)", after);
+                }
+                if self.down_since.is_none() {
+                    warn!("Logger sink write failed, holding delivery: {}", e);
+                    self.down_since = Some(Instant::now());
+                }
+                self.next_attempt = Instant::now() + self.retry_interval;
+                false
+            }
+        }
+    }
+}
+
+/// Messages written between progress reports while the logger catches up after a pause.
+const CATCH_UP_BATCH: u64 = 256;
+
+/// The messages that built up while sink writes were paused, being written out after a resume.
+/// Held as a local: a restart mid catch-up simply carries on at the live pace.
+struct CatchUp {
+    buffered: usize,
+    logged_before: u64,
+    reported: u64,
+    started: Instant,
+}
+
+/// Upper bound on WAL entries replayed per loop pass, so a long catch-up still
+/// lets the actor notice shutdown and keep its metrics flowing.
+const REPLAY_BATCH: u64 = 1024;
+
+/// Delivers WAL entries the sink has not accepted yet, oldest first, stopping at the first failure.
+fn replay(wal: &Wal, gate: &mut SinkGate, state: &mut LoggerState) -> io::Result<()> {
+    let batch_end = state.wal_seq.min(state.sink_seq + REPLAY_BATCH);
+    info!(
+        "Logger replaying WAL to sink: seq {}..{} of {} ({} behind)",
+        state.sink_seq, batch_end, state.wal_seq, state.wal_seq - state.sink_seq
+    );
+    for entry in wal.reader_from(state.sink_seq)? {
+        let (seq, record) = entry?;
+        if seq >= batch_end {
+            break;
+        }
+        if !gate.write(&record, state) {
+            warn!("Logger replay interrupted at seq {}, will retry", seq);
+            return Ok(());
+        }
+        state.sink_seq = seq + 1;
+        state.replayed += 1;
+    }
+    if state.sink_seq == state.wal_seq {
+        info!("Logger replay complete at seq {}, resuming live traffic", state.sink_seq);
+    }
+    Ok(())
+}
+
+/// Counts a committed record toward `--fsync` and syncs the files when it is due.
+fn sync_committed(state: &mut LoggerState, gate: &mut SinkGate, wal: Option<&mut Wal>) -> io::Result<()> {
+    if state.fsync.committed() {
+        sync_files(state, gate, wal)?;
+    }
+    Ok(())
+}
+
+/// Forces the WAL and the sink onto disk. The WAL is the commit point when there is one, so
+/// only its failure is an error; a sink that cannot sync leaves its records counted at risk.
+fn sync_files(state: &mut LoggerState, gate: &mut SinkGate, wal: Option<&mut Wal>) -> io::Result<()> {
+    let sink = gate.sink.sync();
+    if let Err(e) = &sink {
+        warn!("Logger could not fsync the sink: {}", e);
+    }
+    match wal {
+        Some(wal) => wal.sync()?,
+        None if sink.is_err() => {
+            state.fsync.sync_failed();
+            return Ok(());
+        }
+        None => {}
+    }
+    state.fsync.synced();
+    Ok(())
+}
+
+/// Formats the sink record for a message using the total it will have once committed,
+/// followed by its tenant when a source labeled it and its derived data as JSON when `--compute` produced any.
+/// `--record-format json` or `csv` writes the same as structured fields instead.
+fn render_record<M: Debug>(state: &LoggerState, rules: &RuleSet, format: RecordFormat, envelope: &Envelope<M>, filing: Filing) -> String {
+    let msg = &envelope.payload;
+    if format != RecordFormat::Text {
+        let (kind, value, total) = match filing {
+            Filing::Record { kind, label, value } => (label.to_string(), value, kind_total(state, kind)),
+            Filing::Matched(matches) => (rules.label(matches), None, state.rule_totals.get(&matches).copied().unwrap_or_default()),
+            Filing::Barrier(_) => unreachable!("barriers complete a checkpoint, they are never records"),
+        };
+        let fields = RecordFields {
+            seq: envelope.seq,
+            kind,
+            value,
+            total: total + 1,
+            logged: state.messages_logged + 1,
+            ts: RecordFields::timestamp(Stamp::now().wall_micros),
+            tenant: (envelope.tenant != tenant::UNTAGGED).then(|| tenant::name(envelope.tenant)),
+            derived: (!envelope.derived.is_empty()).then_some(envelope.derived),
+        };
+        return if format == RecordFormat::Json { fields.json() } else { fields.csv() };
+    }
+    let record = match filing {
+        Filing::Record { kind, label, .. } => format!("Msg {:?} ({} total: {})", msg, label, kind_total(state, kind) + 1),
+        Filing::Matched(matches) => {
+            let label = rules.label(matches);
+            format!("Msg {} ({} total: {})", label, label, state.rule_totals.get(&matches).copied().unwrap_or_default() + 1)
+        }
+        Filing::Barrier(_) => unreachable!("barriers complete a checkpoint, they are never records"),
+    };
+    let record = if envelope.tenant == tenant::UNTAGGED { record } else { format!("{} tenant={}", record, tenant::name(envelope.tenant)) };
+    if envelope.derived.is_empty() { record } else { format!("{} {}", record, envelope.derived.json()) }
+}
+
+/// Messages committed so far of `RECORD_KINDS[kind]`.
+fn kind_total(state: &LoggerState, kind: usize) -> u64 {
+    [state.fizz_count, state.buzz_count, state.fizzbuzz_count, state.value_count, state.rule_count][kind]
+}
+
+/// Logs the latency of the messages committed since the last report beside that of the whole run,
+/// and starts the next period. A quiet period is not reported.
+fn report_latency(state: &mut LoggerState) {
+    let period = std::mem::take(&mut state.latency_period);
+    if period.count() == 0 {
+        return;
+    }
+    let report = LatencyReport::of(&period, &state.latency);
+    info!("Logger latency, {}", report);
+    state.latency_reports += 1;
+    state.last_latency_report = Some(report);
+}
+
+/// Counts a committed message and records its latency from generation, checking it against the SLO when one is set.
+/// A message the worker dated also counts toward its event-time window.
+fn count_message<M: Debug>(state: &mut LoggerState, envelope: Envelope<M>, filing: Filing, slo: &mut Option<LatencySlo>) {
+    let latency = envelope.stamp.elapsed();
+    state.latency.record(latency);
+    state.latency_period.record(latency);
+    state.latency_by_priority.entry(envelope.priority).or_default().record(latency);
+    if let Some(slo) = slo {
+        state.slo_measured += 1;
+        if slo.observe(latency) {
+            state.slo_late += 1;
+        }
+    }
+    if envelope.event_micros != 0
+        && let Some(window) = state.event_windows.observe(envelope.event_micros) {
+        log_window(window);
+    }
+    match filing {
+        Filing::Record { kind: 0, .. } => state.fizz_count += 1,
+        Filing::Record { kind: 1, .. } => state.buzz_count += 1,
+        Filing::Record { kind: 2, .. } => state.fizzbuzz_count += 1,
+        Filing::Record { kind: 3, .. } => state.value_count += 1,
+        Filing::Record { .. } => state.rule_count += 1,
+        Filing::Matched(matches) => {
+            state.rule_count += 1;
+            *state.rule_totals.entry(matches).or_default() += 1;
+        }
+        Filing::Barrier(_) => unreachable!("barriers complete a checkpoint, they are never records"),
+    }
+    state.messages_logged += 1;
+    if envelope.tenant != tenant::UNTAGGED {
+        *state.tenant_logged.entry(envelope.tenant).or_default() += 1;
+    }
+    mark_handled(state, &envelope);
+    trace::event("LOGGER", "committed", &envelope);
+}
+
+fn log_window(window: ClosedWindow) {
+    info!("Event window {} closed with {} messages", window.span(), window.messages);
+}
+
+/// Records that a worker message has been dealt with, whatever its fate.
+fn mark_handled<M>(state: &mut LoggerState, envelope: &Envelope<M>) {
+    if envelope.seq != 0 {
+        state.last_seq = envelope.seq;
+    }
+}
+
+/// Completes the checkpoint for a barrier by adding the logger's own side of the cut
+/// to the snapshot the worker took when the same barrier passed it.
+/// Under `--state-dir` a consistent checkpoint is also saved for a later process to resume from.
+fn complete_checkpoint(state: &mut LoggerState, worker: WorkerCheckpoint, state_dir: Option<&StateDir>) {
+    let checkpoint = Checkpoint {
+        barrier: worker.barrier,
+        worker,
+        messages_logged: state.messages_logged,
+        fizz_count: state.fizz_count,
+        buzz_count: state.buzz_count,
+        fizzbuzz_count: state.fizzbuzz_count,
+        value_count: state.value_count,
+        rule_count: state.rule_count,
+        showstoppers_dropped: state.showstoppers_dropped,
+        wal_seq: state.wal_seq,
+        sink_seq: state.sink_seq,
+    };
+    state.checkpoints_completed += 1;
+    if checkpoint.is_consistent() {
+        info!(
+            "Checkpoint {} complete: generator at {}, worker sent {} (rejected {}), logger logged {} (F:{}, B:{}, FB:{}, V:{})",
+            checkpoint.barrier, worker.values_processed, worker.messages_sent, worker.values_rejected,
+            checkpoint.messages_logged, checkpoint.fizz_count, checkpoint.buzz_count,
+            checkpoint.fizzbuzz_count, checkpoint.value_count
+        );
+        if let Some(dir) = state_dir
+            && let Err(e) = dir.save(checkpoint) {
+            warn!("Checkpoint {} not saved, a restart resumes from the one before: {}", checkpoint.barrier, e);
+        }
     } else {
-        actor.simulated_behavior(vec!(&fizz_buzz_rx)).await
+        state.checkpoints_torn += 1;
+        error!("Checkpoint {} is torn: {:?}", checkpoint.barrier, checkpoint);
+        events::emit("alert", json!({"kind": "torn_checkpoint", "barrier": checkpoint.barrier}));
     }
+    state.last_checkpoint = Some(checkpoint);
 }
 
 /// Internal behavior for the Logger actor.
 /// Demonstrates robust message processing, showstopper detection, and intentional failure injection.
 /// The peek-before-commit pattern ensures that no message is lost or duplicated, even across panics.
-async fn internal_behavior<A: SteadyActor>(
+///
+/// A message is committed once the sink accepts it, or with `--wal` once it is in the WAL.
+/// Without a WAL a sink outage holds messages in the channel; with one, intake continues
+/// and the outage backlog is replayed from the WAL in order before live traffic resumes.
+///
+/// An operator pause from the control socket holds messages in the channel the same way,
+/// so the sink can be rotated or serviced; on resume the logger catches up without waiting between messages.
+#[allow(clippy::too_many_arguments)]
+async fn internal_behavior<A: SteadyActor, C: Classify>(
     mut actor: A,
-    rx: SteadyRx<FizzBuzzMessage>,
+    classifier: C,
+    rx: SteadyRx<Envelope<C::Message>>,
+    rejected_rx: SteadyRx<RejectedValue>,
+    metrics_tx: SteadyTx<CounterSample>,
+    checkpoint_rx: SteadyRx<WorkerCheckpoint>,
+    control_rx: SteadyRx<PipelineCommand>,
+    flow_tx: FlowTx,
+    sink: Box<dyn Sink>,
     state: SteadyState<LoggerState>,
 ) -> Result<(), Box<dyn Error>> {
-    let mut state = state.lock(|| LoggerState {
-        messages_logged: 0,
-        fizz_count: 0,
-        buzz_count: 0,
-        fizzbuzz_count: 0,
-        value_count: 0,
+    let args = actor.args::<crate::MainArg>().expect("unable to downcast");
+    let retry_interval = Duration::from_millis(args.sink_retry_ms);
+    let on_failure = SinkFailure::from_args(args);
+    let wal_path = args.wal.clone();
+    let rules = RuleSet::from_args(args);
+    let record_format = args.record_format;
+    let wal_codec = WalCodec::from_args(args);
+    let backlog_file = args.backlog_file.clone();
+    let pause_after = args.pause_on_sink_outage_ms.map(Duration::from_millis);
+    let latency_report = (args.latency_report_ms > 0).then(|| Duration::from_millis(args.latency_report_ms));
+    // The heartbeat confirms a pause took hold; without one the generator has to.
+    let heartbeat_present = !args.no_heartbeat;
+    let throttled = args.flow_target_inflight.is_some();
+    // The logger's window opens when the worker's closes, so it gets both.
+    let mut drain = DrainDeadline::new(Duration::from_millis(args.drain_worker_ms + args.drain_logger_ms));
+
+    let state_dir = StateDir::from_args(args);
+    // A process resumed from --state-dir starts at the logger's side of the checkpoint's cut.
+    let resumed = persistence::restored().map(|snapshot| snapshot.checkpoint);
+    let cut = resumed.unwrap_or_default();
+    let Some(mut state) = lock_state(&actor, crate::NAME_LOGGER, &state, || LoggerState {
+        messages_logged: cut.messages_logged,
+        fizz_count: cut.fizz_count,
+        buzz_count: cut.buzz_count,
+        fizzbuzz_count: cut.fizzbuzz_count,
+        value_count: cut.value_count,
+        rule_count: cut.rule_count,
+        // Per-rule totals are not part of a checkpoint and count this process only.
+        rule_totals: BTreeMap::new(),
+        rejected_count: 0,
+        showstoppers_dropped: cut.showstoppers_dropped,
+        last_seq: cut.worker.seq,
+        duplicates_suppressed: 0,
+        wal_seq: cut.wal_seq,
+        sink_seq: cut.sink_seq,
+        replayed: 0,
+        sink_failures: 0,
+        fsync: Fsync::new(args.fsync),
+        checkpoints_completed: 0,
+        checkpoints_torn: 0,
+        last_checkpoint: resumed,
+        slo_measured: 0,
+        slo_late: 0,
+        slo_windows: 0,
+        slo_windows_failed: 0,
+        latency: LatencyHistogram::default(),
+        latency_period: LatencyHistogram::default(),
+        latency_reports: 0,
+        last_latency_report: None,
+        latency_by_priority: BTreeMap::new(),
+        time: StageTime::default(),
+        fairness: Fairness::default(),
+        event_windows: EventWindows::new(event_time::window_micros(args).unwrap_or_default()),
+        tenant_logged: BTreeMap::new(),
+        residue: 0,
+        flow_paused: false,
+        flow_pauses: 0,
+        sink_paused: false,
+        sink_pauses: 0,
+        paused_backlog_max: 0,
+        drain_from: None,
+        rate_limits: buckets_from_args(args),
+        rate_limited: 0,
         restart_count: 0,
-    }).await;
+        restarts: RestartCauses::default(),
+        starts: StartTimes::default(),
+        faults: FaultPlan::for_actor(args, "logger"),
+    }).await else {
+        return Ok(());
+    };
 
     state.restart_count += 1;
+    if state.restart_count > 1 {
+        let (cause, starts) = (state.restart_cause(), state.restart_count);
+        state.restarts.record(crate::NAME_LOGGER, cause, starts);
+    }
+    let (warm, sent) = (state.is_warm(), state.messages_logged);
+    state.starts.begin(crate::NAME_LOGGER, warm, sent);
     info!(
-        "Logger starting (restart #{}) with {} messages logged (F:{}, B:{}, FB:{}, V:{})",
+        "Logger starting (restart #{}) with {} messages logged (F:{}, B:{}, FB:{}, V:{}, R:{})",
         state.restart_count, state.messages_logged, state.fizz_count, state.buzz_count,
-        state.fizzbuzz_count, state.value_count
+        state.fizzbuzz_count, state.value_count, state.rule_count
     );
+    let wait_strategy = crate::wait::strategy(&actor);
+    if record_format == RecordFormat::Csv {
+        info!("Logger writing CSV records with columns {}", CSV_COLUMNS.join(","));
+    }
+
+    let mut wal = match &wal_path {
+        // A resumed run keeps the log up to the cut and delivers from where the sink was then;
+        // anything past the cut is logged again as the pipeline redoes it.
+        Some(path) if resumed.is_some() && state.restart_count == 1 => {
+            let wal = Wal::open_before(path, state.wal_seq, wal_codec)?;
+            state.wal_seq = wal.next_seq();
+            state.sink_seq = state.sink_seq.min(state.wal_seq);
+            if state.sink_seq < state.wal_seq {
+                info!("Logger resumed with WAL entries {} to {} still to deliver", state.sink_seq, state.wal_seq);
+            }
+            Some(wal)
+        }
+        Some(path) => Some(Wal::open(path, state.wal_seq, wal_codec)?),
+        None => None,
+    };
+    // A discarding sink with no WAL never looks at the record, so do not build one.
+    let render = wal.is_some() || !sink.discards();
+    let mut gate = SinkGate::new(sink, retry_interval, on_failure);
+    // Without a WAL, the record the sink refused; retried without re-peeking so an
+    // outage is not mistaken for a showstopper message.
+    let mut pending: Option<(Envelope<C::Message>, String)> = None;
+    let mut slo = LatencySlo::from_args(args);
 
     let mut rx = rx.lock().await;
+    let mut rejected_rx = rejected_rx.lock().await;
+    let mut metrics_tx = metrics_tx.lock().await;
+    let mut checkpoint_rx = checkpoint_rx.lock().await;
+    let mut control_rx = control_rx.lock().await;
+    let mut heartbeat_flow_tx = flow_tx.heartbeat.lock().await;
+    let mut generator_flow_tx = flow_tx.generator.lock().await;
+    let mut commits_tx = flow_tx.commits.lock().await;
+    let mut tenants_tx = flow_tx.tenants.lock().await;
+    let mut dead_letter_tx = flow_tx.dead_letter.lock().await;
+    // Tenant counts as last published; reset by a restart, which just publishes them all again.
+    let mut tenants_published: HashMap<u32, u64> = HashMap::new();
+    let mut pacer = SamplePacer::default();
+    // When and at what total the last commit feedback was measured, to turn totals into a rate.
+    let mut last_settled: Option<(Instant, u64)> = None;
+    let mut catch_up: Option<CatchUp> = None;
+    let mut last_latency_report = Instant::now();
+    let mut clock = IterationClock::new();
+    let mut budget = TurnBudget::from_args(args);
+
+    while actor.is_running(|| i!(rx.is_closed_and_empty())
+                              && i!(rejected_rx.is_closed_and_empty())
+                              && i!(checkpoint_rx.is_closed_and_empty())
+                              && i!(metrics_tx.mark_closed())
+                              && i!(heartbeat_flow_tx.mark_closed())
+                              && i!(generator_flow_tx.mark_closed())
+                              && i!(commits_tx.mark_closed())
+                              && i!(tenants_tx.mark_closed())
+                              && i!(dead_letter_tx.mark_closed())) {
+        clock.lap(&mut state.time);
+        let sent = state.messages_logged;
+        state.starts.progress(crate::NAME_LOGGER, sent);
+        // Catching up, the logger does not wait at all; the budget keeps it from holding a shared thread.
+        let ready = pending.is_none() && !state.sink_paused && (catch_up.is_some() || actor.avail_units(&mut rx) > 0);
+        if budget.spend(ready, &mut state.fairness) {
+            yield_now().await;
+        }
+        let wait = Instant::now();
+        if pending.is_some() || state.sink_paused {
+            // The channel cannot drain until the sink is back, so only the retry timer matters.
+            await_for_any!(actor.wait_periodic(retry_interval), actor.wait_avail(&mut rejected_rx, 1));
+        } else if catch_up.is_none() {
+            await_for_all!(wait_strategy.wait(&actor, async {
+                await_for_any!(actor.wait_avail(&mut rx, 1),
+                               actor.wait_avail(&mut rejected_rx, 1),
+                               actor.wait_periodic(retry_interval))
+            }));
+        }
+        clock.waited(wait);
 
-    while actor.is_running(|| rx.is_closed_and_empty()) {
-        await_for_all!(actor.wait_avail(&mut rx, 1));
+        // Operator pause and resume of sink writes; a stop lifts the pause so the channel can drain.
+        while let Some(command) = actor.try_take(&mut control_rx) {
+            if command == PipelineCommand::Drain {
+                // The stop right behind it lifts any pause, below.
+                if state.drain_from.is_none() {
+                    info!("Logger draining for the operator with {} messages buffered", actor.avail_units(&mut rx));
+                    state.drain_from = Some(state.messages_logged);
+                }
+                continue;
+            }
+            let paused = command == PipelineCommand::Pause;
+            if paused == state.sink_paused {
+                continue;
+            }
+            state.sink_paused = paused;
+            let buffered = actor.avail_units(&mut rx);
+            if paused {
+                state.sink_pauses += 1;
+                catch_up = None;
+                info!("Logger paused sink writes by operator request with {} messages buffered", buffered);
+            } else {
+                info!("Logger resumed sink writes, catching up on {} buffered messages", buffered);
+                catch_up = Some(CatchUp { buffered, logged_before: state.messages_logged, reported: 0, started: Instant::now() });
+            }
+        }
+        if state.sink_paused && actor.is_liveliness_stop_requested() {
+            state.sink_paused = false;
+            info!("Logger lifting its pause to drain for shutdown");
+        }
 
+        // Out of time to flush: what the worker sent but the logger could not commit is spilled,
+        // including a record held for a failing sink. Snapshots without their barrier are moot.
+        if drain.expired(actor.is_liveliness_stop_requested()) {
+            pending = None;
+            let mut residue = Vec::new();
+            while let Some(envelope) = actor.try_take(&mut rx) {
+                trace::event("LOGGER", "spilled", &envelope);
+                residue.push(format!("{} {:?}", envelope.seq, envelope.payload));
+            }
+            while actor.try_take(&mut checkpoint_rx).is_some() {}
+            state.residue += residue.len() as u64;
+            persist_residue(backlog_file.as_deref(), "LOGGER", &residue);
+        }
 
-        // // Showstopper detection: if this message has been peeked N times, drop it and log.
-        if actor.is_showstopper(&mut rx, 3) {                           //#!#//
-            // This same peeked message caused us to panic 7 times in a row, so we drop it.
-            // we could log it or save it off to another channel.
-            actor.try_take(&mut rx).expect("internal error");
-            continue; // Back to top of loop
+        // Rejects are a side output with nothing downstream, so logging them is the commit.
+        while let Some(rejected) = actor.try_take(&mut rejected_rx) {
+            state.rejected_count += 1;
+            info!("Rejected {} ({:?}) (Rejected total: {})", rejected.value, rejected.reason, state.rejected_count);
         }
-     
 
-        // Peek-before-commit: Only after successful processing do we advance the read position.
-        if let Some(peeked_msg) = actor.try_peek(&mut rx) {   //#!#//
-            let msg = *peeked_msg;
+        // Catch the sink up on the WAL before any new message can reach it.
+        if let Some(wal) = &wal
+            && !state.sink_paused
+            && state.sink_seq < state.wal_seq
+            && gate.can_attempt() {
+            replay(wal, &mut gate, &mut state)?;
+        }
 
-            // --- Robustness Demonstration: Intentional Panic ---
-            #[cfg(not(test))]
-            if FizzBuzzMessage::Value(41).eq(peeked_msg) {
-                error!(
-                        "Logger intentionally panicking at {:?} messages to demonstrate robustness!", msg
-                    );
-                panic!("Intentional panic for robustness demonstration - DO NOT COPY THIS PATTERN!");
+        if state.sink_paused {
+            // Nothing is written, but the depth behind the pause is still watched.
+            state.paused_backlog_max = state.paused_backlog_max.max(actor.avail_units(&mut rx) as u64);
+        } else if let Some((envelope, record)) = pending.take() {
+            if gate.can_attempt() && gate.write(&record, &mut state) {
+                sync_committed(&mut state, &mut gate, None)?;
+                if actor.advance_take_index(&mut rx, 1).item_count() > 0 {
+                    count_message(&mut state, envelope, classifier.file(envelope.payload), &mut slo);
+                }
+            } else {
+                pending = Some((envelope, record));
             }
-            // --- End Robustness Demonstration ---
+        } else {
+            // // Showstopper detection: if this message has been peeked N times, drop it and log.
+            const SHOWSTOPPER_THRESHOLD: usize = 3;
+            if actor.is_showstopper(&mut rx, SHOWSTOPPER_THRESHOLD) {       //#!#//
+                // This same peeked message caused us to panic 7 times in a row, so we drop it.
+                // we could log it or save it off to another channel.
+                let dropped = actor.try_take(&mut rx).expect("internal error");
+                state.showstoppers_dropped += 1;
+                mark_handled(&mut state, &dropped);
+                trace::event("LOGGER", "dropped", &dropped);
+                events::emit("showstopper", json!({"actor": "LOGGER", "message": format!("{:?}", dropped.payload), "seq": dropped.seq}));
+                let letter = DeadLetter::new(crate::NAME_LOGGER, SHOWSTOPPER_THRESHOLD, dropped.seq, Poisoned::Record(format!("{:?}", dropped.payload)));
+                if !actor.try_send(&mut dead_letter_tx, letter).is_sent() {
+                    warn!("Dead-letter channel full, showstopper {:?} is only in the log", dropped.payload);
+                }
+                continue; // Back to top of loop
+            }
+
+            // Peek-before-commit: Only after successful processing do we advance the read position.
+            if let Some(peeked_msg) = actor.try_peek(&mut rx) {   //#!#//
+                let envelope = *peeked_msg;
+                let msg = envelope.payload;
+                let filing = classifier.file(msg);
 
+                // A worker restart between its send and its commit re-sends the same message
+                // under the same sequence number; the first copy has already been handled.
+                if envelope.seq != 0 && envelope.seq <= state.last_seq {
+                    actor.advance_take_index(&mut rx, 1);
+                    state.duplicates_suppressed += 1;
+                    trace::event("LOGGER", "duplicate", &envelope);
+                    warn!("Logger suppressed duplicate {:?} (seq {}, last handled {})", msg, envelope.seq, state.last_seq);
+                    continue;
+                }
 
-            // Process the message (this is our "work" that we don't want to lose)
-            match msg {
-                FizzBuzzMessage::Fizz => {
-                    state.fizz_count += 1;
-                    info!("Msg {:?} (Fizz total: {})", msg, state.fizz_count);
+                // Everything before the barrier is committed, so this is the logger's point in the cut.
+                if let Filing::Barrier(beat) = filing {
+                    match actor.try_take(&mut checkpoint_rx) {
+                        Some(worker) => complete_checkpoint(&mut state, worker, state_dir.as_ref()),
+                        None => error!("Checkpoint barrier {} arrived without the worker snapshot", beat),
+                    }
+                    actor.advance_take_index(&mut rx, 1);
+                    mark_handled(&mut state, &envelope);
+                    continue;
                 }
-                FizzBuzzMessage::Buzz => {
-                    state.buzz_count += 1;
-                    info!("Msg {:?} (Buzz total: {})", msg, state.buzz_count);
+
+                // --- Robustness Demonstration: Intentional Panic ---
+                let strike = match filing {
+                    Filing::Record { value: Some(value), .. } => state.faults.strike(value),
+                    _ => Strike::default(),
+                };
+                if strike.panic {
+                    error!(
+                            "Logger intentionally panicking at {:?} messages to demonstrate robustness!", msg
+                        );
+                    panic!("Intentional panic for robustness demonstration - DO NOT COPY THIS PATTERN!");
                 }
-                FizzBuzzMessage::FizzBuzz => {
-                    state.fizzbuzz_count += 1;
-                    info!("Msg {:?} (FizzBuzz total: {})", msg, state.fizzbuzz_count);
+                if let Some(delay) = strike.delay {
+                    warn!("Logger holding {:?} on {:?} (injected fault)", delay, msg);
+                    actor.wait(delay).await;
                 }
-                FizzBuzzMessage::Value(_v) => {
-                    state.value_count += 1;
-                    info!("Msg {:?} (Value total: {})", msg, state.value_count);
+                if strike.send_fail {
+                    warn!("Logger failing the next sink write for {:?} (injected fault)", msg);
+                    gate.fail_next = true;
                 }
-            }
+                // --- End Robustness Demonstration ---
 
-            // Only after successful processing do we advance the read position
-            let advanced = actor.advance_take_index(&mut rx, 1).item_count(); //#!#//
-            if advanced > 0 {
-                state.messages_logged += 1;
+                // Over its kind's rate a console sink skips the record; the WAL still takes it and
+                // the totals still count it. Durable sinks are never thinned out.
+                let throttled = gate.sink.is_console()
+                    && kind_of(filing).and_then(|kind| state.rate_limits[kind].as_mut())
+                        .is_some_and(|bucket| !bucket.try_take(Instant::now()));
 
+                // Process the message (this is our "work" that we don't want to lose)
+                let record = if render { render_record(&state, &rules, record_format, &envelope, filing) } else { String::new() };
+                match wal.as_mut() {
+                    Some(wal) => {
+                        // Durable in the WAL is committed; the sink is fed from here on.
+                        let seq = wal.append(&record)?;
+                        sync_committed(&mut state, &mut gate, Some(&mut *wal))?;
+                        if actor.advance_take_index(&mut rx, 1).item_count() > 0 { //#!#//
+                            count_message(&mut state, envelope, filing, &mut slo);
+                            if throttled {
+                                trace::event("LOGGER", "rate_limited", &envelope);
+                                state.rate_limited += 1;
+                            }
+                        }
+                        state.wal_seq = wal.next_seq();
+                        // Live delivery only when nothing older is still waiting for the sink.
+                        if state.sink_seq == seq && !gate.is_down() && (throttled || gate.write(&record, &mut state)) {
+                            state.sink_seq = seq + 1;
+                        }
+                    }
+                    None if throttled => {
+                        if actor.advance_take_index(&mut rx, 1).item_count() > 0 {
+                            trace::event("LOGGER", "rate_limited", &envelope);
+                            count_message(&mut state, envelope, filing, &mut slo);
+                            state.rate_limited += 1;
+                        }
+                    }
+                    None => {
+                        if gate.write(&record, &mut state) {
+                            sync_committed(&mut state, &mut gate, None)?;
+                            // Only after successful processing do we advance the read position
+                            if actor.advance_take_index(&mut rx, 1).item_count() > 0 { //#!#//
+                                count_message(&mut state, envelope, filing, &mut slo);
+                            }
+                        } else {
+                            pending = Some((envelope, record));
+                        }
+                    }
+                }
                 trace!(
-                    "Logger advanced read position, total messages: {}",
-                    state.messages_logged
+                    "Logger advanced read position, total messages: {}, {:?} since generation at {:?}",
+                    state.messages_logged, envelope.stamp.elapsed(), envelope.stamp.wall_time()
                 );
             }
         }
+
+        if let Some(progress) = &mut catch_up {
+            let written = state.messages_logged - progress.logged_before;
+            if actor.avail_units(&mut rx) == 0 {
+                info!("Logger caught up on {} messages in {:?}", written, progress.started.elapsed());
+                catch_up = None;
+            } else if written >= progress.reported + CATCH_UP_BATCH {
+                progress.reported = written;
+                info!("Logger catching up: {} of {} buffered messages written", written, progress.buffered);
+            }
+        }
+
+        // A long outage would only grow the backlog, so stop new work at the source until the sink is back.
+        if let Some(pause_after) = pause_after {
+            let hold = gate.down_for().is_some_and(|down| down >= pause_after);
+            if hold != state.flow_paused {
+                let command = if hold { PipelineCommand::Pause } else { PipelineCommand::Resume };
+                // Only the source that is running reads its channel, so a refusal there is harmless.
+                let generator_sent = actor.try_send(&mut generator_flow_tx, command).is_sent();
+                let confirmed = if heartbeat_present {
+                    actor.try_send(&mut heartbeat_flow_tx, command).is_sent()
+                } else {
+                    generator_sent
+                };
+                if confirmed {
+                    state.flow_paused = hold;
+                    if hold {
+                        state.flow_pauses += 1;
+                        warn!("Logger sink down for over {:?}, pausing heartbeat and generator", pause_after);
+                    } else {
+                        info!("Logger sink is back, resuming heartbeat and generator");
+                    }
+                }
+            }
+        }
+
+        if let Some(slo) = &mut slo
+            && let Some(verdict) = slo.roll() {
+            state.slo_windows += 1;
+            if !verdict.passed {
+                state.slo_windows_failed += 1;
+                error!(
+                    "Latency SLO alert: {} of {} messages ({:.2}%) took longer than {:?}, limit is {:.2}%",
+                    verdict.late, verdict.measured, verdict.ratio * 100.0, slo.slo, slo.max_ratio * 100.0
+                );
+                events::emit("alert", json!({"kind": "latency_slo", "late": verdict.late, "measured": verdict.measured}));
+            }
+        }
+
+        if latency_report.is_some_and(|every| last_latency_report.elapsed() >= every) {
+            last_latency_report = Instant::now();
+            report_latency(&mut state);
+        }
+
+        let sample_due = pacer.is_due();
+        // Only tenants whose count moved, and on every pass while stopping so the reconciler
+        // sees the final counts; a full channel leaves the rest for the next time.
+        if sample_due || actor.is_liveliness_stop_requested() {
+            for (&tenant, &logged) in &state.tenant_logged {
+                if tenants_published.get(&tenant) != Some(&logged) {
+                    if !actor.try_send(&mut tenants_tx, TenantSample { tenant, logged }).is_sent() {
+                        break;
+                    }
+                    tenants_published.insert(tenant, logged);
+                }
+            }
+        }
+
+        if sample_due {
+            let backlog = (actor.avail_units(&mut rx) + actor.avail_units(&mut rejected_rx)) as u64;
+            // Suppressed duplicates are left out: the worker never counted them as produced.
+            let consumed = state.messages_logged + state.rejected_count + state.showstoppers_dropped
+                + state.checkpoints_completed + state.residue;
+            let sample = CounterSample::new(consumed, state.messages_logged, backlog)
+                .with_starts(state.restart_count)
+                .with_dropped(state.showstoppers_dropped)
+                .with_bytes(0, backlog_bytes(&actor, &mut rx))
+                .with_seq(state.last_seq)
+                .with_depths([ChannelDepth::of(&actor, &mut rx), ChannelDepth::of(&actor, &mut rejected_rx)]);
+            let _ = actor.try_send(&mut metrics_tx, sample);
+
+            // Barriers are the heartbeat's, so only the generator's values count toward its in-flight depth.
+            if throttled {
+                let now = Instant::now();
+                let settled = consumed - state.checkpoints_completed;
+                if let Some((at, before)) = last_settled {
+                    let per_sec = settled.saturating_sub(before) as f64 / now.duration_since(at).as_secs_f64();
+                    let _ = actor.try_send(&mut commits_tx, CommitFeedback { settled, per_sec });
+                }
+                last_settled = Some((now, settled));
+            }
+        }
     }
 
+    // Nothing more can arrive for the open window once the input has drained.
+    if let Some(window) = state.event_windows.close() {
+        log_window(window);
+    }
     info!(
-        "Logger shutting down. Total: {} (F:{}, B:{}, FB:{}, V:{})",
+        "Logger shutting down. Total: {} (F:{}, B:{}, FB:{}, V:{}, R:{}), rejected: {}, duplicates suppressed: {}, rate-limited: {}, undrained: {}, operator pauses: {}, event windows: {} (late: {}), by tenant: {}",
         state.messages_logged, state.fizz_count, state.buzz_count,
-        state.fizzbuzz_count, state.value_count
+        state.fizzbuzz_count, state.value_count, state.rule_count, state.rejected_count, state.duplicates_suppressed,
+        state.rate_limited, state.residue, state.sink_pauses, state.event_windows.closed, state.event_windows.late,
+        tenant::summary(&state.tenant_logged)
     );
+    if state.sink_seq < state.wal_seq {
+        warn!(
+            "Logger stopping with {} WAL entries not yet delivered to the sink (seq {}..{})",
+            state.wal_seq - state.sink_seq, state.sink_seq, state.wal_seq
+        );
+    }
+    if state.fsync.due_at_stop() {
+        sync_files(&mut state, &mut gate, wal.as_mut())?;
+    }
     Ok(())
 }
 
+#[cfg(test)]
+use crate::actor::scripted::ScriptedSource;
+
+/// Flow control channels for a logger under test, with the receiving ends for inspection.
+#[cfg(test)]
+fn flow_channels(graph: &mut Graph) -> (FlowTx, LazySteadyRx<PipelineCommand>, LazySteadyRx<PipelineCommand>) {
+    let (heartbeat, heartbeat_rx) = graph.channel_builder().build();
+    let (generator, generator_rx) = graph.channel_builder().build();
+    let (commits, _commits_rx) = graph.channel_builder().build();
+    let (tenants, _tenants_rx) = graph.channel_builder().build();
+    let (dead_letter, _dead_letter_rx) = graph.channel_builder().build();
+    let flow_tx = FlowTx { heartbeat: heartbeat.clone(), generator: generator.clone(), commits: commits.clone(), tenants: tenants.clone(), dead_letter: dead_letter.clone() };
+    (flow_tx, heartbeat_rx, generator_rx)
+}
+
 #[test]
 fn test_logger() -> Result<(), Box<dyn std::error::Error>> {
     use steady_logger::*;
     let _guard = start_log_capture();           //#!#//
 
-    let mut graph = GraphBuilder::for_testing().build(());
+    let mut graph = GraphBuilder::for_testing().build(crate::MainArg::default());
     let (fizz_buzz_tx, fizz_buzz_rx) = graph.channel_builder().build();
+    let (rejected_tx, rejected_rx) = graph.channel_builder().build::<RejectedValue>();
+    let (metrics_tx, _metrics_rx) = graph.channel_builder().build::<CounterSample>();
+    let (checkpoint_tx, checkpoint_rx) = graph.channel_builder().build::<WorkerCheckpoint>();
+    let (flow_tx, _heartbeat_flow_rx, _generator_flow_rx) = flow_channels(&mut graph);
+    let (_control_tx, control_rx) = graph.channel_builder().build::<PipelineCommand>();
 
     let state = new_state();
     graph.actor_builder().with_name("UnitTest")
         .build(move |context| {
-            internal_behavior(context, fizz_buzz_rx.clone(), state.clone())
+            internal_behavior(context, FizzBuzz::default(), fizz_buzz_rx.clone(), rejected_rx.clone(), metrics_tx.clone(), checkpoint_rx.clone(), control_rx.clone(), flow_tx.clone(), Box::new(crate::sink::LogSink), state.clone())
         }
                , SoloAct);
 
     graph.start();
-    fizz_buzz_tx.testing_send_all(vec![FizzBuzzMessage::Fizz],true);
+    fizz_buzz_tx.testing_send_all([FizzBuzzMessage::Fizz].map(Envelope::unstamped).to_vec(), true);
+    rejected_tx.testing_send_all(vec![], true);
+    checkpoint_tx.testing_send_all(vec![], true);
     std::thread::sleep(Duration::from_millis(300));
     graph.request_shutdown();
     graph.block_until_stopped(Duration::from_secs(10000))?;
@@ -147,3 +1022,702 @@ fn test_logger() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn test_logger_completes_checkpoint() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir().join(format!("robust-state-{}", crate::arg::new_run_id()));
+    std::fs::create_dir_all(&dir)?;
+    let mut graph = GraphBuilder::for_testing().build(crate::MainArg { state_dir: Some(dir.clone()), beats: 20, ..Default::default() });
+    let (fizz_buzz_tx, fizz_buzz_rx) = graph.channel_builder().build();
+    let (rejected_tx, rejected_rx) = graph.channel_builder().build::<RejectedValue>();
+    let (metrics_tx, _metrics_rx) = graph.channel_builder().build::<CounterSample>();
+    let (checkpoint_tx, checkpoint_rx) = graph.channel_builder().build::<WorkerCheckpoint>();
+    let (flow_tx, _heartbeat_flow_rx, _generator_flow_rx) = flow_channels(&mut graph);
+    let (_control_tx, control_rx) = graph.channel_builder().build::<PipelineCommand>();
+
+    let state = new_state();
+    let probe = state.clone();
+    graph.actor_builder().with_name("UnitTest")
+        .build(move |context| {
+            internal_behavior(context, FizzBuzz::default(), fizz_buzz_rx.clone(), rejected_rx.clone(), metrics_tx.clone(), checkpoint_rx.clone(), control_rx.clone(), flow_tx.clone(), Box::new(crate::sink::LogSink), state.clone())
+        }
+               , SoloAct);
+
+    graph.start();
+    rejected_tx.testing_send_all(vec![], true);
+    let worker = WorkerCheckpoint { barrier: 10, heartbeats_processed: 2, values_processed: 2, messages_sent: 2, values_rejected: 0, seq: 3 };
+    checkpoint_tx.testing_send_all(vec![worker], true);
+    fizz_buzz_tx.testing_send_all([FizzBuzzMessage::Fizz, FizzBuzzMessage::Value(1),
+                                       FizzBuzzMessage::Barrier(10), FizzBuzzMessage::Buzz].map(Envelope::unstamped).to_vec(), true);
+    std::thread::sleep(Duration::from_millis(300));
+    graph.request_shutdown();
+    graph.block_until_stopped(Duration::from_secs(1))?;
+
+    let state = probe.try_lock_sync().expect("logger state");
+    assert_eq!((state.checkpoints_completed, state.checkpoints_torn), (1, 0));
+    // the snapshot stops at the barrier even though the logger carried on past it
+    let checkpoint = state.last_checkpoint.expect("checkpoint");
+    assert_eq!((checkpoint.messages_logged, checkpoint.fizz_count, checkpoint.value_count), (2, 1, 1));
+    assert_eq!(state.messages_logged, 3);
+    // and saved where a later process resumes from
+    assert_eq!(persistence::load_snapshot(&dir)?, Some(persistence::Snapshot { beats: 20, checkpoint }));
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_logger_null_sink_still_counts() -> Result<(), Box<dyn std::error::Error>> {
+    let mut graph = GraphBuilder::for_testing().build(crate::MainArg::default());
+    let (fizz_buzz_tx, fizz_buzz_rx) = graph.channel_builder().build();
+    let (rejected_tx, rejected_rx) = graph.channel_builder().build::<RejectedValue>();
+    let (metrics_tx, _metrics_rx) = graph.channel_builder().build::<CounterSample>();
+    let (checkpoint_tx, checkpoint_rx) = graph.channel_builder().build::<WorkerCheckpoint>();
+    let (flow_tx, _heartbeat_flow_rx, _generator_flow_rx) = flow_channels(&mut graph);
+    let (_control_tx, control_rx) = graph.channel_builder().build::<PipelineCommand>();
+
+    let state = new_state();
+    let probe = state.clone();
+    graph.actor_builder().with_name("UnitTest")
+        .build(move |context| {
+            internal_behavior(context, FizzBuzz::default(), fizz_buzz_rx.clone(), rejected_rx.clone(), metrics_tx.clone(), checkpoint_rx.clone(), control_rx.clone(), flow_tx.clone(), Box::new(crate::sink::NullSink), state.clone())
+        }
+               , SoloAct);
+
+    graph.start();
+    rejected_tx.testing_send_all(vec![], true);
+    checkpoint_tx.testing_send_all(vec![], true);
+    fizz_buzz_tx.testing_send_all([FizzBuzzMessage::Fizz, FizzBuzzMessage::Buzz,
+                                       FizzBuzzMessage::FizzBuzz, FizzBuzzMessage::Value(7)].map(Envelope::unstamped).to_vec(), true);
+    std::thread::sleep(Duration::from_millis(300));
+    graph.request_shutdown();
+    graph.block_until_stopped(Duration::from_secs(1))?;
+
+    let state = probe.try_lock_sync().expect("logger state");
+    assert_eq!(state.messages_logged, 4);
+    assert_eq!((state.fizz_count, state.buzz_count, state.fizzbuzz_count, state.value_count), (1, 1, 1, 1));
+    Ok(())
+}
+
+#[test]
+fn test_logger_suppresses_duplicates() -> Result<(), Box<dyn std::error::Error>> {
+    let mut graph = GraphBuilder::for_testing().build(crate::MainArg::default());
+    let (fizz_buzz_tx, fizz_buzz_rx) = graph.channel_builder().build();
+    let (rejected_tx, rejected_rx) = graph.channel_builder().build::<RejectedValue>();
+    let (metrics_tx, _metrics_rx) = graph.channel_builder().build::<CounterSample>();
+    let (checkpoint_tx, checkpoint_rx) = graph.channel_builder().build::<WorkerCheckpoint>();
+    let (flow_tx, _heartbeat_flow_rx, _generator_flow_rx) = flow_channels(&mut graph);
+    let (_control_tx, control_rx) = graph.channel_builder().build::<PipelineCommand>();
+
+    let state = new_state();
+    let probe = state.clone();
+    graph.actor_builder().with_name("UnitTest")
+        .build(move |context| {
+            internal_behavior(context, FizzBuzz::default(), fizz_buzz_rx.clone(), rejected_rx.clone(), metrics_tx.clone(), checkpoint_rx.clone(), control_rx.clone(), flow_tx.clone(), Box::new(crate::sink::NullSink), state.clone())
+        }
+               , SoloAct);
+
+    graph.start();
+    rejected_tx.testing_send_all(vec![], true);
+    checkpoint_tx.testing_send_all(vec![], true);
+    // the worker restarted after sending seq 2 but before committing it, so seq 2 comes again
+    fizz_buzz_tx.testing_send_all(vec![Envelope::unstamped(FizzBuzzMessage::Fizz).with_seq(1),
+                                       Envelope::unstamped(FizzBuzzMessage::Buzz).with_seq(2),
+                                       Envelope::unstamped(FizzBuzzMessage::Buzz).with_seq(2),
+                                       Envelope::unstamped(FizzBuzzMessage::Value(7)).with_seq(3)], true);
+    std::thread::sleep(Duration::from_millis(300));
+    graph.request_shutdown();
+    graph.block_until_stopped(Duration::from_secs(1))?;
+
+    let state = probe.try_lock_sync().expect("logger state");
+    assert_eq!((state.messages_logged, state.buzz_count), (3, 1));
+    assert_eq!((state.duplicates_suppressed, state.last_seq), (1, 3));
+    Ok(())
+}
+
+/// Test sink that refuses every write until `down_until`, and while the test holds it `down`,
+/// recording what it accepts.
+#[cfg(test)]
+#[derive(Clone)]
+struct FlakySink {
+    delivered: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+    down_until: Instant,
+    down: std::sync::Arc<std::sync::atomic::AtomicBool>,
+}
+
+#[cfg(test)]
+impl Sink for FlakySink {
+    fn write_record(&mut self, record: &str) -> io::Result<()> {
+        if Instant::now() < self.down_until || self.down.load(std::sync::atomic::Ordering::Relaxed) {
+            return Err(io::Error::other("sink offline"));
+        }
+        self.delivered.lock().expect("sink lock").push(record.to_string());
+        Ok(())
+    }
+}
+
+/// Polls `done` until it holds, for at most `within`; whether it did.
+#[cfg(test)]
+fn wait_until(within: Duration, done: impl Fn() -> bool) -> bool {
+    let until = Instant::now() + within;
+    while !done() {
+        if Instant::now() >= until {
+            return false;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    true
+}
+
+/// Test sink standing in for the console, the only kind `--log-rate-limit` applies to.
+#[cfg(test)]
+struct ConsoleSink(FlakySink);
+
+#[cfg(test)]
+impl Sink for ConsoleSink {
+    fn write_record(&mut self, record: &str) -> io::Result<()> {
+        self.0.write_record(record)
+    }
+
+    fn is_console(&self) -> bool {
+        true
+    }
+}
+
+#[test]
+fn test_logger_replays_wal_after_sink_outage() -> Result<(), Box<dyn std::error::Error>> {
+    let wal_path = std::env::temp_dir().join(format!("robust-logger-wal-{}.log", crate::arg::new_run_id()));
+    let mut graph = GraphBuilder::for_testing().build(crate::MainArg {
+        wal: Some(wal_path.clone()),
+        sink_retry_ms: 200,
+        ..Default::default()
+    });
+    let (fizz_buzz_tx, fizz_buzz_rx) = graph.channel_builder().build();
+    let (rejected_tx, rejected_rx) = graph.channel_builder().build::<RejectedValue>();
+    let (metrics_tx, _metrics_rx) = graph.channel_builder().build::<CounterSample>();
+    let (checkpoint_tx, checkpoint_rx) = graph.channel_builder().build::<WorkerCheckpoint>();
+    let (flow_tx, _heartbeat_flow_rx, _generator_flow_rx) = flow_channels(&mut graph);
+    let (_control_tx, control_rx) = graph.channel_builder().build::<PipelineCommand>();
+
+    // the sink is unreachable until the test brings it back, standing in for a ten second outage
+    let sink = FlakySink {
+        delivered: Default::default(),
+        down_until: Instant::now(),
+        down: std::sync::Arc::new(std::sync::atomic::AtomicBool::new(true)),
+    };
+    let (delivered, down) = (sink.delivered.clone(), sink.down.clone());
+    let state = new_state();
+    let probe = state.clone();
+    graph.actor_builder().with_name("UnitTest")
+        .build(move |context| {
+            internal_behavior(context, FizzBuzz::default(), fizz_buzz_rx.clone(), rejected_rx.clone(), metrics_tx.clone(), checkpoint_rx.clone(), control_rx.clone(), flow_tx.clone(), Box::new(sink.clone()), state.clone())
+        }
+               , SoloAct);
+
+    // three messages during the outage, and one after it, once the WAL has been replayed
+    fizz_buzz_tx.testing_send_all([FizzBuzzMessage::Fizz, FizzBuzzMessage::Buzz, FizzBuzzMessage::Value(1)].map(Envelope::unstamped).to_vec(), false);
+    rejected_tx.testing_send_all(vec![], true);
+    checkpoint_tx.testing_send_all(vec![], true);
+    graph.start();
+    let wal_lines = || std::fs::read_to_string(&wal_path).map_or(0, |wal| wal.lines().count());
+    let delivered_count = || delivered.lock().expect("sink lock").len();
+    // intake carried on into the WAL while the sink was down
+    assert!(wait_until(Duration::from_secs(2), || wal_lines() == 3), "WAL lines: {}", wal_lines());
+    assert_eq!(delivered_count(), 0);
+    down.store(false, std::sync::atomic::Ordering::Relaxed);
+    assert!(wait_until(Duration::from_secs(2), || delivered_count() == 3), "delivered: {}", delivered_count());
+    fizz_buzz_tx.testing_send_all(vec![Envelope::unstamped(FizzBuzzMessage::FizzBuzz)], true);
+    assert!(wait_until(Duration::from_secs(2), || delivered_count() == 4), "delivered: {}", delivered_count());
+    graph.request_shutdown();
+    graph.block_until_stopped(Duration::from_secs(5))?;
+
+    assert_eq!(*delivered.lock().expect("sink lock"), vec![
+        "Msg Fizz (Fizz total: 1)",
+        "Msg Buzz (Buzz total: 1)",
+        "Msg Value(1) (Value total: 1)",
+        "Msg FizzBuzz (FizzBuzz total: 1)",
+    ]);
+    let state = crate::state_lock::settled(&probe).expect("logger state");
+    assert_eq!((state.wal_seq, state.sink_seq, state.replayed), (4, 4, 3));
+    assert!(state.sink_failures > 0);
+    std::fs::remove_file(wal_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_logger_holds_sources_through_sink_outage() -> Result<(), Box<dyn std::error::Error>> {
+    let mut graph = GraphBuilder::for_testing().build(crate::MainArg {
+        pause_on_sink_outage_ms: Some(100),
+        sink_retry_ms: 50,
+        ..Default::default()
+    });
+    let (fizz_buzz_tx, fizz_buzz_rx) = graph.channel_builder().build();
+    let (rejected_tx, rejected_rx) = graph.channel_builder().build::<RejectedValue>();
+    let (metrics_tx, _metrics_rx) = graph.channel_builder().build::<CounterSample>();
+    let (checkpoint_tx, checkpoint_rx) = graph.channel_builder().build::<WorkerCheckpoint>();
+    let (flow_tx, heartbeat_flow_rx, generator_flow_rx) = flow_channels(&mut graph);
+    let (_control_tx, control_rx) = graph.channel_builder().build::<PipelineCommand>();
+
+    // down long enough to trip the pause, then back before the test ends
+    let sink = FlakySink {
+        delivered: Default::default(),
+        down_until: Instant::now() + Duration::from_millis(600),
+        down: Default::default(),
+    };
+    let state = new_state();
+    let probe = state.clone();
+    graph.actor_builder().with_name("UnitTest")
+        .build(move |context| {
+            internal_behavior(context, FizzBuzz::default(), fizz_buzz_rx.clone(), rejected_rx.clone(), metrics_tx.clone(), checkpoint_rx.clone(), control_rx.clone(), flow_tx.clone(), Box::new(sink.clone()), state.clone())
+        }
+               , SoloAct);
+
+    graph.start();
+    rejected_tx.testing_send_all(vec![], true);
+    checkpoint_tx.testing_send_all(vec![], true);
+    fizz_buzz_tx.testing_send_all([FizzBuzzMessage::Fizz].map(Envelope::unstamped).to_vec(), true);
+    std::thread::sleep(Duration::from_millis(1200));
+    graph.request_shutdown();
+    graph.block_until_stopped(Duration::from_secs(1))?;
+
+    let held_and_released = vec![PipelineCommand::Pause, PipelineCommand::Resume];
+    assert_eq!(heartbeat_flow_rx.testing_take_all(), held_and_released);
+    assert_eq!(generator_flow_rx.testing_take_all(), held_and_released);
+    let state = probe.try_lock_sync().expect("logger state");
+    assert_eq!((state.messages_logged, state.flow_pauses, state.flow_paused), (1, 1, false));
+    // degraded in place under the default --sink-failure, never restarted
+    assert_eq!(state.restart_count, 1);
+    Ok(())
+}
+
+/// The other strategy for the same outage: instead of holding the record, the logger panics
+/// after two failed writes and its restart gets the record through once the sink is back.
+#[test]
+fn test_logger_panics_on_persistent_sink_failure() -> Result<(), Box<dyn std::error::Error>> {
+    let mut graph = GraphBuilder::for_testing().build(crate::MainArg {
+        sink_retry_ms: 100,
+        sink_failure: vec![crate::sink::parse_sink_failure("log=panic:2")?],
+        ..Default::default()
+    });
+    let (fizz_buzz_tx, fizz_buzz_rx) = graph.channel_builder().build();
+    let (rejected_tx, rejected_rx) = graph.channel_builder().build::<RejectedValue>();
+    let (metrics_tx, _metrics_rx) = graph.channel_builder().build::<CounterSample>();
+    let (checkpoint_tx, checkpoint_rx) = graph.channel_builder().build::<WorkerCheckpoint>();
+    let (flow_tx, _heartbeat_flow_rx, _generator_flow_rx) = flow_channels(&mut graph);
+    let (_control_tx, control_rx) = graph.channel_builder().build::<PipelineCommand>();
+
+    // down for the first two attempts only, so one restart is enough
+    let sink = FlakySink {
+        delivered: Default::default(),
+        down_until: Instant::now() + Duration::from_millis(150),
+        down: Default::default(),
+    };
+    let delivered = sink.delivered.clone();
+    let state = new_state();
+    let probe = state.clone();
+    graph.actor_builder().with_name("UnitTest")
+        .build(move |context| {
+            internal_behavior(context, FizzBuzz::default(), fizz_buzz_rx.clone(), rejected_rx.clone(), metrics_tx.clone(), checkpoint_rx.clone(), control_rx.clone(), flow_tx.clone(), Box::new(sink.clone()), state.clone())
+        }
+               , SoloAct);
+
+    graph.start();
+    rejected_tx.testing_send_all(vec![], true);
+    checkpoint_tx.testing_send_all(vec![], true);
+    fizz_buzz_tx.testing_send_all([FizzBuzzMessage::Fizz].map(Envelope::unstamped).to_vec(), true);
+    std::thread::sleep(Duration::from_millis(800));
+    graph.request_shutdown();
+    graph.block_until_stopped(Duration::from_secs(1))?;
+
+    assert_eq!(*delivered.lock().expect("sink lock"), vec!["Msg Fizz (Fizz total: 1)"]);
+    let state = crate::state_lock::settled(&probe).expect("logger state");
+    assert_eq!((state.messages_logged, state.restart_count, state.showstoppers_dropped), (1, 2, 0));
+    assert!(state.sink_failures >= 2);
+    Ok(())
+}
+
+#[test]
+fn test_logger_rate_limits_one_kind() -> Result<(), Box<dyn std::error::Error>> {
+    let mut graph = GraphBuilder::for_testing().build(crate::MainArg {
+        log_rate_limit: vec![crate::rate_limit::parse_rate_limit("value=2").expect("valid limit")],
+        ..Default::default()
+    });
+    let (fizz_buzz_tx, fizz_buzz_rx) = graph.channel_builder().build();
+    let (rejected_tx, rejected_rx) = graph.channel_builder().build::<RejectedValue>();
+    let (metrics_tx, _metrics_rx) = graph.channel_builder().build::<CounterSample>();
+    let (checkpoint_tx, checkpoint_rx) = graph.channel_builder().build::<WorkerCheckpoint>();
+    let (flow_tx, _heartbeat_flow_rx, _generator_flow_rx) = flow_channels(&mut graph);
+    let (_control_tx, control_rx) = graph.channel_builder().build::<PipelineCommand>();
+
+    let sink = FlakySink { delivered: Default::default(), down_until: Instant::now(), down: Default::default() };
+    let delivered = sink.delivered.clone();
+    let state = new_state();
+    let probe = state.clone();
+    graph.actor_builder().with_name("UnitTest")
+        .build(move |context| {
+            internal_behavior(context, FizzBuzz::default(), fizz_buzz_rx.clone(), rejected_rx.clone(), metrics_tx.clone(), checkpoint_rx.clone(), control_rx.clone(), flow_tx.clone(), Box::new(ConsoleSink(sink.clone())), state.clone())
+        }
+               , SoloAct);
+
+    graph.start();
+    rejected_tx.testing_send_all(vec![], true);
+    checkpoint_tx.testing_send_all(vec![], true);
+    fizz_buzz_tx.testing_send_all([FizzBuzzMessage::Value(1), FizzBuzzMessage::Value(2), FizzBuzzMessage::Value(4),
+                                       FizzBuzzMessage::Fizz, FizzBuzzMessage::Value(7)].map(Envelope::unstamped).to_vec(), true);
+    std::thread::sleep(Duration::from_millis(300));
+    graph.request_shutdown();
+    graph.block_until_stopped(Duration::from_secs(1))?;
+
+    // the third and fourth values arrive within the same second as the first two
+    assert_eq!(*delivered.lock().expect("sink lock"), vec![
+        "Msg Value(1) (Value total: 1)",
+        "Msg Value(2) (Value total: 2)",
+        "Msg Fizz (Fizz total: 1)",
+    ]);
+    let state = crate::state_lock::settled(&probe).expect("logger state");
+    assert_eq!((state.messages_logged, state.value_count, state.rate_limited), (5, 4, 2));
+    Ok(())
+}
+
+#[test]
+fn test_logger_rate_limit_spares_wal_and_durable_sinks() -> Result<(), Box<dyn std::error::Error>> {
+    let wal_path = std::env::temp_dir().join(format!("robust-logger-wal-{}.log", crate::arg::new_run_id()));
+    let mut graph = GraphBuilder::for_testing().build(crate::MainArg {
+        wal: Some(wal_path.clone()),
+        log_rate_limit: vec![crate::rate_limit::parse_rate_limit("value=1").expect("valid limit")],
+        ..Default::default()
+    });
+    let (fizz_buzz_tx, fizz_buzz_rx) = graph.channel_builder().build();
+    let (rejected_tx, rejected_rx) = graph.channel_builder().build::<RejectedValue>();
+    let (metrics_tx, _metrics_rx) = graph.channel_builder().build::<CounterSample>();
+    let (checkpoint_tx, checkpoint_rx) = graph.channel_builder().build::<WorkerCheckpoint>();
+    let (flow_tx, _heartbeat_flow_rx, _generator_flow_rx) = flow_channels(&mut graph);
+    let (_control_tx, control_rx) = graph.channel_builder().build::<PipelineCommand>();
+
+    let sink = FlakySink { delivered: Default::default(), down_until: Instant::now(), down: Default::default() };
+    let delivered = sink.delivered.clone();
+    let state = new_state();
+    let probe = state.clone();
+    graph.actor_builder().with_name("UnitTest")
+        .build(move |context| {
+            internal_behavior(context, FizzBuzz::default(), fizz_buzz_rx.clone(), rejected_rx.clone(), metrics_tx.clone(), checkpoint_rx.clone(), control_rx.clone(), flow_tx.clone(), Box::new(sink.clone()), state.clone())
+        }
+               , SoloAct);
+
+    graph.start();
+    rejected_tx.testing_send_all(vec![], true);
+    checkpoint_tx.testing_send_all(vec![], true);
+    fizz_buzz_tx.testing_send_all([FizzBuzzMessage::Value(1), FizzBuzzMessage::Value(2), FizzBuzzMessage::Value(4)]
+                                      .map(Envelope::unstamped).to_vec(), true);
+    std::thread::sleep(Duration::from_millis(300));
+    graph.request_shutdown();
+    graph.block_until_stopped(Duration::from_secs(1))?;
+
+    // a file or MQTT sink gets every record, however many a console would have skipped
+    assert_eq!(delivered.lock().expect("sink lock").len(), 3);
+    assert_eq!(std::fs::read_to_string(&wal_path)?.lines().count(), 3);
+    let state = crate::state_lock::settled(&probe).expect("logger state");
+    assert_eq!((state.messages_logged, state.rate_limited), (3, 0));
+    std::fs::remove_file(wal_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_logger_pauses_and_catches_up() -> Result<(), Box<dyn std::error::Error>> {
+    let mut graph = GraphBuilder::for_testing().build(crate::MainArg {
+        sink_retry_ms: 50,
+        ..Default::default()
+    });
+    let (fizz_buzz_tx, fizz_buzz_rx) = graph.channel_builder().build();
+    let (rejected_tx, rejected_rx) = graph.channel_builder().build::<RejectedValue>();
+    let (metrics_tx, _metrics_rx) = graph.channel_builder().build::<CounterSample>();
+    let (checkpoint_tx, checkpoint_rx) = graph.channel_builder().build::<WorkerCheckpoint>();
+    let (flow_tx, _heartbeat_flow_rx, _generator_flow_rx) = flow_channels(&mut graph);
+    let (control_tx, control_rx) = graph.channel_builder().build::<PipelineCommand>();
+
+    let sink = FlakySink { delivered: Default::default(), down_until: Instant::now(), down: Default::default() };
+    let delivered = sink.delivered.clone();
+    let state = new_state();
+    let probe = state.clone();
+    graph.actor_builder().with_name("UnitTest")
+        .build(move |context| {
+            internal_behavior(context, FizzBuzz::default(), fizz_buzz_rx.clone(), rejected_rx.clone(), metrics_tx.clone(), checkpoint_rx.clone(), control_rx.clone(), flow_tx.clone(), Box::new(sink.clone()), state.clone())
+        }
+               , SoloAct);
+
+    // paused from the start, so every message waits in the channel until the resume
+    control_tx.testing_send_all(vec![PipelineCommand::Pause], false);
+    ScriptedSource::new().emit_at(300, PipelineCommand::Resume).then_close().build(&mut graph, "Operator", control_tx);
+    rejected_tx.testing_send_all(vec![], true);
+    checkpoint_tx.testing_send_all(vec![], true);
+    fizz_buzz_tx.testing_send_all([FizzBuzzMessage::Fizz, FizzBuzzMessage::Buzz, FizzBuzzMessage::Value(1)].map(Envelope::unstamped).to_vec(), true);
+    graph.start();
+    std::thread::sleep(Duration::from_millis(200));
+    assert!(delivered.lock().expect("sink lock").is_empty());
+    std::thread::sleep(Duration::from_millis(400));
+    graph.request_shutdown();
+    graph.block_until_stopped(Duration::from_secs(1))?;
+
+    assert_eq!(*delivered.lock().expect("sink lock"), vec![
+        "Msg Fizz (Fizz total: 1)",
+        "Msg Buzz (Buzz total: 1)",
+        "Msg Value(1) (Value total: 1)",
+    ]);
+    let state = crate::state_lock::settled(&probe).expect("logger state");
+    assert_eq!((state.sink_paused, state.sink_pauses, state.paused_backlog_max), (false, 1, 3));
+    Ok(())
+}
+
+#[test]
+fn test_logger_counts_what_a_drain_flushes() -> Result<(), Box<dyn std::error::Error>> {
+    let mut graph = GraphBuilder::for_testing().build(crate::MainArg::default());
+    let (fizz_buzz_tx, fizz_buzz_rx) = graph.channel_builder().build();
+    let (rejected_tx, rejected_rx) = graph.channel_builder().build::<RejectedValue>();
+    let (metrics_tx, _metrics_rx) = graph.channel_builder().build::<CounterSample>();
+    let (checkpoint_tx, checkpoint_rx) = graph.channel_builder().build::<WorkerCheckpoint>();
+    let (flow_tx, _heartbeat_flow_rx, _generator_flow_rx) = flow_channels(&mut graph);
+    let (control_tx, control_rx) = graph.channel_builder().build::<PipelineCommand>();
+
+    let sink = FlakySink { delivered: Default::default(), down_until: Instant::now(), down: Default::default() };
+    let delivered = sink.delivered.clone();
+    let state = new_state();
+    let probe = state.clone();
+    graph.actor_builder().with_name("UnitTest")
+        .build(move |context| {
+            internal_behavior(context, FizzBuzz::default(), fizz_buzz_rx.clone(), rejected_rx.clone(), metrics_tx.clone(), checkpoint_rx.clone(), control_rx.clone(), flow_tx.clone(), Box::new(sink.clone()), state.clone())
+        }
+               , SoloAct);
+
+    // paused with everything buffered when the drain arrives, so all of it is flushed after the mark
+    control_tx.testing_send_all(vec![PipelineCommand::Pause, PipelineCommand::Drain, PipelineCommand::Drain], true);
+    rejected_tx.testing_send_all(vec![], true);
+    checkpoint_tx.testing_send_all(vec![], true);
+    fizz_buzz_tx.testing_send_all([FizzBuzzMessage::Fizz, FizzBuzzMessage::Buzz, FizzBuzzMessage::Value(1)].map(Envelope::unstamped).to_vec(), true);
+    graph.start();
+    std::thread::sleep(Duration::from_millis(200));
+    assert!(delivered.lock().expect("sink lock").is_empty());
+    graph.request_shutdown();
+    graph.block_until_stopped(Duration::from_secs(1))?;
+
+    assert_eq!(delivered.lock().expect("sink lock").len(), 3);
+    let state = crate::state_lock::settled(&probe).expect("logger state");
+    assert_eq!((state.drain_from, state.messages_logged), (Some(0), 3));
+    Ok(())
+}
+
+#[test]
+fn test_logger_labels_tenants() -> Result<(), Box<dyn std::error::Error>> {
+    let mut graph = GraphBuilder::for_testing().build(crate::MainArg::default());
+    let (fizz_buzz_tx, fizz_buzz_rx) = graph.channel_builder().build();
+    let (rejected_tx, rejected_rx) = graph.channel_builder().build::<RejectedValue>();
+    let (metrics_tx, _metrics_rx) = graph.channel_builder().build::<CounterSample>();
+    let (checkpoint_tx, checkpoint_rx) = graph.channel_builder().build::<WorkerCheckpoint>();
+    let (flow_tx, _heartbeat_flow_rx, _generator_flow_rx) = flow_channels(&mut graph);
+    let (_control_tx, control_rx) = graph.channel_builder().build::<PipelineCommand>();
+
+    let sink = FlakySink { delivered: Default::default(), down_until: Instant::now(), down: Default::default() };
+    let delivered = sink.delivered.clone();
+    let state = new_state();
+    let probe = state.clone();
+    graph.actor_builder().with_name("UnitTest")
+        .build(move |context| {
+            internal_behavior(context, FizzBuzz::default(), fizz_buzz_rx.clone(), rejected_rx.clone(), metrics_tx.clone(), checkpoint_rx.clone(), control_rx.clone(), flow_tx.clone(), Box::new(sink.clone()), state.clone())
+        }
+               , SoloAct);
+
+    let acme = tenant::id("logger-test-acme");
+    graph.start();
+    rejected_tx.testing_send_all(vec![], true);
+    checkpoint_tx.testing_send_all(vec![], true);
+    fizz_buzz_tx.testing_send_all(vec![Envelope::unstamped(FizzBuzzMessage::Fizz).with_tenant(acme),
+                                       Envelope::unstamped(FizzBuzzMessage::Value(1)),
+                                       Envelope::unstamped(FizzBuzzMessage::Buzz).with_tenant(acme)], true);
+    std::thread::sleep(Duration::from_millis(300));
+    graph.request_shutdown();
+    graph.block_until_stopped(Duration::from_secs(1))?;
+
+    // unlabeled values keep their plain record
+    assert_eq!(*delivered.lock().expect("sink lock"), vec![
+        "Msg Fizz (Fizz total: 1) tenant=logger-test-acme",
+        "Msg Value(1) (Value total: 1)",
+        "Msg Buzz (Buzz total: 1) tenant=logger-test-acme",
+    ]);
+    let state = probe.try_lock_sync().expect("logger state");
+    assert_eq!(state.tenant_logged, BTreeMap::from([(acme, 2)]));
+    Ok(())
+}
+
+#[test]
+fn test_logger_reports_latency_periodically() -> Result<(), Box<dyn std::error::Error>> {
+    let mut graph = GraphBuilder::for_testing().build(crate::MainArg {
+        latency_report_ms: 100,
+        sink_retry_ms: 20,
+        ..Default::default()
+    });
+    let (fizz_buzz_tx, fizz_buzz_rx) = graph.channel_builder().build();
+    let (rejected_tx, rejected_rx) = graph.channel_builder().build::<RejectedValue>();
+    let (metrics_tx, _metrics_rx) = graph.channel_builder().build::<CounterSample>();
+    let (checkpoint_tx, checkpoint_rx) = graph.channel_builder().build::<WorkerCheckpoint>();
+    let (flow_tx, _heartbeat_flow_rx, _generator_flow_rx) = flow_channels(&mut graph);
+    let (_control_tx, control_rx) = graph.channel_builder().build::<PipelineCommand>();
+
+    let sink = FlakySink { delivered: Default::default(), down_until: Instant::now(), down: Default::default() };
+    let state = new_state();
+    let probe = state.clone();
+    graph.actor_builder().with_name("UnitTest")
+        .build(move |context| {
+            internal_behavior(context, FizzBuzz::default(), fizz_buzz_rx.clone(), rejected_rx.clone(), metrics_tx.clone(), checkpoint_rx.clone(), control_rx.clone(), flow_tx.clone(), Box::new(sink.clone()), state.clone())
+        }
+               , SoloAct);
+
+    graph.start();
+    rejected_tx.testing_send_all(vec![], true);
+    checkpoint_tx.testing_send_all(vec![], true);
+    // three fresh messages make up the first period
+    fizz_buzz_tx.testing_send_all([FizzBuzzMessage::Fizz, FizzBuzzMessage::Buzz, FizzBuzzMessage::Value(1)].map(Envelope::new).to_vec(), false);
+    std::thread::sleep(Duration::from_millis(300));
+    // then one generated 200ms before it is sent, after quiet periods that are not reported
+    let late = Envelope::new(FizzBuzzMessage::Value(2));
+    std::thread::sleep(Duration::from_millis(200));
+    fizz_buzz_tx.testing_send_all(vec![late], true);
+    std::thread::sleep(Duration::from_millis(300));
+    graph.request_shutdown();
+    graph.block_until_stopped(Duration::from_secs(1))?;
+
+    let state = crate::state_lock::settled(&probe).expect("logger state");
+    assert_eq!(state.latency_reports, 2);
+    let report = state.last_latency_report.expect("a latency report");
+    // the last period holds only the late one, while the run's median is still a fresh message
+    assert_eq!((report.messages, report.run_messages), (1, 4));
+    assert!(report.p50_us >= 200_000 && report.p99_us >= report.p50_us, "{}", report);
+    assert!(report.run_p50_us < 200_000 && report.run_p99_us >= 200_000, "{}", report);
+    assert_eq!((state.latency.count(), state.latency_period.count()), (4, 0));
+    Ok(())
+}
+
+#[test]
+fn test_logger_labels_rule_matches() -> Result<(), Box<dyn std::error::Error>> {
+    let rule = |spec| crate::rules::parse_rule(spec).expect("valid rule");
+    let args = crate::MainArg { rule: vec![rule("3=Fizz"), rule("7=Bazz")], ..Default::default() };
+    let mut graph = GraphBuilder::for_testing().build(args);
+    let (fizz_buzz_tx, fizz_buzz_rx) = graph.channel_builder().build();
+    let (rejected_tx, rejected_rx) = graph.channel_builder().build::<RejectedValue>();
+    let (metrics_tx, _metrics_rx) = graph.channel_builder().build::<CounterSample>();
+    let (checkpoint_tx, checkpoint_rx) = graph.channel_builder().build::<WorkerCheckpoint>();
+    let (flow_tx, _heartbeat_flow_rx, _generator_flow_rx) = flow_channels(&mut graph);
+    let (_control_tx, control_rx) = graph.channel_builder().build::<PipelineCommand>();
+
+    let sink = FlakySink { delivered: Default::default(), down_until: Instant::now(), down: Default::default() };
+    let delivered = sink.delivered.clone();
+    let state = new_state();
+    let probe = state.clone();
+    graph.actor_builder().with_name("UnitTest")
+        .build(move |context| {
+            internal_behavior(context, FizzBuzz::default(), fizz_buzz_rx.clone(), rejected_rx.clone(), metrics_tx.clone(), checkpoint_rx.clone(), control_rx.clone(), flow_tx.clone(), Box::new(sink.clone()), state.clone())
+        }
+               , SoloAct);
+
+    graph.start();
+    rejected_tx.testing_send_all(vec![], true);
+    checkpoint_tx.testing_send_all(vec![], true);
+    fizz_buzz_tx.testing_send_all(vec![Envelope::unstamped(FizzBuzzMessage::Matched(RuleMatches(0b10))),
+                                       Envelope::unstamped(FizzBuzzMessage::Value(1)),
+                                       Envelope::unstamped(FizzBuzzMessage::Matched(RuleMatches(0b11))),
+                                       Envelope::unstamped(FizzBuzzMessage::Matched(RuleMatches(0b10)))], true);
+    std::thread::sleep(Duration::from_millis(300));
+    graph.request_shutdown();
+    graph.block_until_stopped(Duration::from_secs(1))?;
+
+    // each combination of labels keeps its own total
+    assert_eq!(*delivered.lock().expect("sink lock"), vec![
+        "Msg Bazz (Bazz total: 1)",
+        "Msg Value(1) (Value total: 1)",
+        "Msg FizzBazz (FizzBazz total: 1)",
+        "Msg Bazz (Bazz total: 2)",
+    ]);
+    let state = crate::state_lock::settled(&probe).expect("logger state");
+    assert_eq!(state.rule_count, 3);
+    Ok(())
+}
+
+#[test]
+fn test_logger_writes_json_records() -> Result<(), Box<dyn std::error::Error>> {
+    let args = crate::MainArg { record_format: RecordFormat::Json, ..Default::default() };
+    let mut graph = GraphBuilder::for_testing().build(args);
+    let (fizz_buzz_tx, fizz_buzz_rx) = graph.channel_builder().build();
+    let (rejected_tx, rejected_rx) = graph.channel_builder().build::<RejectedValue>();
+    let (metrics_tx, _metrics_rx) = graph.channel_builder().build::<CounterSample>();
+    let (checkpoint_tx, checkpoint_rx) = graph.channel_builder().build::<WorkerCheckpoint>();
+    let (flow_tx, _heartbeat_flow_rx, _generator_flow_rx) = flow_channels(&mut graph);
+    let (_control_tx, control_rx) = graph.channel_builder().build::<PipelineCommand>();
+
+    let sink = FlakySink { delivered: Default::default(), down_until: Instant::now(), down: Default::default() };
+    let delivered = sink.delivered.clone();
+    let state = new_state();
+    graph.actor_builder().with_name("UnitTest")
+        .build(move |context| {
+            internal_behavior(context, FizzBuzz::default(), fizz_buzz_rx.clone(), rejected_rx.clone(), metrics_tx.clone(), checkpoint_rx.clone(), control_rx.clone(), flow_tx.clone(), Box::new(sink.clone()), state.clone())
+        }
+               , SoloAct);
+
+    graph.start();
+    rejected_tx.testing_send_all(vec![], true);
+    checkpoint_tx.testing_send_all(vec![], true);
+    fizz_buzz_tx.testing_send_all(vec![Envelope::unstamped(FizzBuzzMessage::Fizz).with_seq(1),
+                                       Envelope::unstamped(FizzBuzzMessage::Value(4)).with_seq(2)], true);
+    std::thread::sleep(Duration::from_millis(300));
+    graph.request_shutdown();
+    graph.block_until_stopped(Duration::from_secs(1))?;
+
+    let records: Vec<serde_json::Value> = delivered.lock().expect("sink lock").iter()
+        .map(|record| serde_json::from_str(record).expect("one JSON object per record"))
+        .collect();
+    assert_eq!(records.len(), 2);
+    let field = |i: usize, key: &str| records[i][key].clone();
+    assert_eq!([field(0, "seq"), field(0, "kind"), field(0, "value"), field(0, "total")], [json!(1), json!("Fizz"), json!(null), json!(1)]);
+    assert_eq!([field(1, "seq"), field(1, "kind"), field(1, "value"), field(1, "logged")], [json!(2), json!("Value"), json!(4), json!(2)]);
+    assert!(field(1, "ts").as_str().is_some_and(|ts| ts.ends_with('Z')));
+    Ok(())
+}
+
+/// The same logger runs another domain: the classifier's filing labels and counts the records,
+/// and its barrier marker completes a checkpoint.
+#[test]
+fn test_logger_runs_another_domain() -> Result<(), Box<dyn std::error::Error>> {
+    use crate::classify::classify_tests::{Alarm, Threshold};
+    let mut graph = GraphBuilder::for_testing().build(crate::MainArg::default());
+    let (alarm_tx, alarm_rx) = graph.channel_builder().build();
+    let (rejected_tx, rejected_rx) = graph.channel_builder().build::<RejectedValue>();
+    let (metrics_tx, _metrics_rx) = graph.channel_builder().build::<CounterSample>();
+    let (checkpoint_tx, checkpoint_rx) = graph.channel_builder().build::<WorkerCheckpoint>();
+    let (flow_tx, _heartbeat_flow_rx, _generator_flow_rx) = flow_channels(&mut graph);
+    let (_control_tx, control_rx) = graph.channel_builder().build::<PipelineCommand>();
+
+    let sink = FlakySink { delivered: Default::default(), down_until: Instant::now(), down: Default::default() };
+    let delivered = sink.delivered.clone();
+    let state = new_state();
+    let probe = state.clone();
+    graph.actor_builder().with_name("UnitTest")
+        .build(move |context| {
+            internal_behavior(context, Threshold(80), alarm_rx.clone(), rejected_rx.clone(), metrics_tx.clone(), checkpoint_rx.clone(), control_rx.clone(), flow_tx.clone(), Box::new(sink.clone()), state.clone())
+        }
+               , SoloAct);
+
+    graph.start();
+    rejected_tx.testing_send_all(vec![], true);
+    let worker = WorkerCheckpoint { barrier: 2, heartbeats_processed: 2, values_processed: 2, messages_sent: 2, values_rejected: 0, seq: 3 };
+    checkpoint_tx.testing_send_all(vec![worker], true);
+    alarm_tx.testing_send_all([Alarm::Normal(1), Alarm::Hot(2), Alarm::Barrier(2), Alarm::Hot(3)].map(Envelope::unstamped).to_vec(), true);
+    std::thread::sleep(Duration::from_millis(300));
+    graph.request_shutdown();
+    graph.block_until_stopped(Duration::from_secs(1))?;
+
+    assert_eq!(*delivered.lock().expect("sink lock"), vec![
+        "Msg Normal(1) (Normal total: 1)",
+        "Msg Hot(2) (Hot total: 1)",
+        "Msg Hot(3) (Hot total: 2)",
+    ]);
+    let state = crate::state_lock::settled(&probe).expect("logger state");
+    assert_eq!((state.messages_logged, state.value_count, state.rule_count), (3, 1, 2));
+    assert_eq!((state.checkpoints_completed, state.checkpoints_torn), (1, 0));
+    Ok(())
+}
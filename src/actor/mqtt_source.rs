@@ -0,0 +1,279 @@
+use rumqttc::Publish;
+use steady_state::*;
+use crate::state_lock::lock_state;
+use crate::ack::{AckOutcome, AwaitingAck, SourceAck};
+use crate::admission::Admission;
+use crate::actor::reconciler::{CounterSample, SamplePacer, METRICS_GENERATOR};
+use crate::mqtt::{MqttEndpoint, MqttLink};
+use crate::quota::{OverQuota, Quotas};
+use crate::tenant;
+use crate::timing::Envelope;
+use crate::trace::{self, TraceSample};
+use steady_state::actor_builder::{ActorBuilder, ScheduleAs};
+use crate::startup::StartGate;
+use crate::wiring::{RobustActor, Wiring};
+
+/// Admission control key for everything arriving through the broker.
+const BROKER_CLIENT: &str = "broker";
+
+/// MqttSourceState holds state for the MQTT source actor.
+/// The broker link lives here so the session, its subscription and its reconnect
+/// loop carry on across restarts, and so does the message being handed on.
+pub struct MqttSourceState {
+    /// Values passed on to the worker, and the bytes they came to.
+    pub(crate) accepted: u64,
+    pub(crate) accepted_bytes: u64,
+    /// Payloads that were not a plain unsigned number; acknowledged and dropped.
+    pub(crate) invalid: u64,
+    /// Times a message was held back, unacknowledged, for being over `--ingest-rate`.
+    pub(crate) over_rate: u64,
+    pub(crate) admission: Admission,
+    /// `--tenant-quota` by topic.
+    pub(crate) quotas: Quotas,
+    pub(crate) restart_count: u64,
+    /// Broker sessions re-established after a drop, as of the last start or shutdown.
+    pub(crate) reconnects: u64,
+    link: Option<MqttLink>,
+    /// Received but not yet in the generator channel, and so not yet acknowledged.
+    pending: Option<Publish>,
+    /// With `--ack-after-commit`, messages in the pipeline that the worker has not committed yet.
+    pub(crate) awaiting: AwaitingAck<Publish>,
+    /// Messages left unacknowledged because the worker spilled them, for the broker to redeliver.
+    pub(crate) left_for_redelivery: u64,
+}
+
+/// Entry point for the MQTT source actor.
+/// Stands in for the generator, feeding numbers published to a topic into its channel.
+pub async fn run(
+    actor: SteadyActorShadow,
+    generated_tx: SteadyTx<Envelope<u64>>,
+    metrics_tx: SteadyTx<CounterSample>,
+    acks_rx: SteadyRx<SourceAck>,
+    endpoint: MqttEndpoint,
+    gate: StartGate,
+    state: SteadyState<MqttSourceState>,
+) -> Result<(), Box<dyn Error>> {
+    let actor = actor.into_spotlight([&acks_rx], [&generated_tx, &metrics_tx]);
+    if actor.use_internal_behavior {
+        gate.wait(&actor).await;
+        internal_behavior(actor, generated_tx, metrics_tx, acks_rx, endpoint, state).await
+    } else {
+        actor.simulated_behavior(vec!(&generated_tx)).await
+    }
+}
+
+/// The MQTT source as a pipeline stage, in the generator's place with `--mqtt-source`.
+pub struct MqttSource;
+
+impl RobustActor for MqttSource {
+    fn name(&self) -> &'static str {
+        crate::NAME_MQTT_SOURCE
+    }
+    fn inputs(&self) -> &'static [&'static str] {
+        &["acks"]
+    }
+    fn outputs(&self) -> &'static [&'static str] {
+        &["generated", "metrics"]
+    }
+    fn enabled(&self, args: &crate::MainArg) -> bool {
+        crate::wiring::source(args) == crate::wiring::Source::Mqtt
+    }
+    fn depends_on(&self, _args: &crate::MainArg) -> &'static [&'static str] {
+        &[crate::NAME_LOGGER] // values are taken in once the sink is connected
+    }
+    fn build(&self, builder: ActorBuilder, wiring: &Wiring, schedule: ScheduleAs<'_>) {
+        let Some(endpoint) = wiring.args.mqtt_source.clone() else {
+            return;
+        };
+        let generated_tx = wiring.generated.tx.clone();
+        let (metrics_tx, acks_rx) = (wiring.metrics_tx[METRICS_GENERATOR].clone(), wiring.acks.rx.clone());
+        let (gate, state) = (wiring.start_gate(self), wiring.state.mqtt_source.clone());
+        builder.build(move |context| {
+            crate::priority::apply(&context, crate::NAME_MQTT_SOURCE);
+            run(context, generated_tx.clone(), metrics_tx.clone(), acks_rx.clone(), endpoint.clone(), gate.clone(), state.clone())
+        }, schedule);
+    }
+}
+
+async fn internal_behavior<A: SteadyActor>(
+    mut actor: A,
+    generated_tx: SteadyTx<Envelope<u64>>,
+    metrics_tx: SteadyTx<CounterSample>,
+    acks_rx: SteadyRx<SourceAck>,
+    endpoint: MqttEndpoint,
+    state: SteadyState<MqttSourceState>,
+) -> Result<(), Box<dyn Error>> {
+    let args = actor.args::<crate::MainArg>().expect("unable to downcast");
+    let acking = crate::ack::enabled(args);
+    let client_id = format!("{}-source", args.mqtt_client_id);
+    let sample = TraceSample::from_args(args);
+    let Some(mut state) = lock_state(&actor, crate::NAME_MQTT_SOURCE, &state, || MqttSourceState {
+        accepted: 0,
+        accepted_bytes: 0,
+        invalid: 0,
+        over_rate: 0,
+        admission: Admission::from_args(args),
+        quotas: Quotas::from_args(args),
+        restart_count: 0,
+        reconnects: 0,
+        link: None,
+        pending: None,
+        awaiting: AwaitingAck::new(),
+        left_for_redelivery: 0,
+    }).await else {
+        return Ok(());
+    };
+    state.restart_count += 1;
+    if state.link.is_none() {
+        state.link = Some(MqttLink::start(&endpoint, &client_id, true, args)?);
+        info!("MQTT source subscribing to {} on {}:{}", endpoint.topic, endpoint.host, endpoint.port);
+    }
+    let incoming = state.link.as_ref().expect("link started").incoming.clone();
+    state.reconnects = state.link.as_ref().expect("link started").reconnects();
+    info!(
+        "MQTT source starting (restart #{}) with accepted: {}, invalid: {}, reconnects: {}, holding a message: {}, awaiting commit: {}",
+        state.restart_count, state.accepted, state.invalid, state.reconnects, state.pending.is_some(), state.awaiting.len()
+    );
+
+    let mut generated_tx = generated_tx.lock().await;
+    let mut metrics_tx = metrics_tx.lock().await;
+    let mut acks_rx = acks_rx.lock().await;
+    let mut pacer = SamplePacer::default();
+
+    // Messages still waiting on the worker hold up shutdown until it has answered them all.
+    while actor.is_running(|| i!(generated_tx.mark_closed())
+                              && i!(metrics_tx.mark_closed())
+                              && i!(state.awaiting.is_empty() || acks_rx.is_closed_and_empty())) {
+        while let Some(ack) = actor.try_take(&mut acks_rx) {
+            for (publish, outcome) in state.awaiting.settle(ack) {
+                // Spilled values were never processed; the broker delivers them again next session.
+                if outcome == AckOutcome::Spilled {
+                    state.left_for_redelivery += 1;
+                    continue;
+                }
+                if let Err(e) = state.link.as_ref().expect("link started").client.try_ack(&publish) {
+                    warn!("MQTT source could not acknowledge message, broker may redeliver it: {}", e);
+                }
+            }
+        }
+        if state.pending.is_none() {
+            state.pending = await_for_any!(async { incoming.recv_async().await.ok() }, async {
+                actor.wait_avail(&mut acks_rx, 1).await;
+                None
+            }, async {
+                actor.wait_shutdown().await;
+                None
+            });
+        }
+        let Some(publish) = state.pending.as_ref() else {
+            continue;
+        };
+
+        let sent = match parse_payload(&publish.payload) {
+            Some(value) => {
+                // The topic names the tenant, so one wildcard subscription can serve many.
+                let tenant_id = tenant::id(&publish.topic);
+                // The broker is the only client there is; waiting out its allowance leaves the
+                // message unacknowledged, which slows the broker like a full channel does.
+                if let Err(mut wait) = state.admission.admit(BROKER_CLIENT, Instant::now()) {
+                    state.over_rate += 1;
+                    loop {
+                        actor.wait(wait).await;
+                        match state.admission.retry(BROKER_CLIENT, Instant::now()) {
+                            Ok(()) => break,
+                            Err(again) => wait = again,
+                        }
+                    }
+                }
+                // Over its topic's quota: throttling holds the acknowledgement like the ingest rate
+                // does, rejecting acknowledges and drops it.
+                if let Err(mut wait) = state.quotas.admit(tenant_id, Instant::now()) {
+                    if state.quotas.action == OverQuota::Reject {
+                        state.quotas.rejected += 1;
+                        let publish = state.pending.take().expect("checked above");
+                        trace!("MQTT source rejected a value on {} over quota", publish.topic);
+                        if let Err(e) = state.link.as_ref().expect("link started").client.try_ack(&publish) {
+                            warn!("MQTT source could not acknowledge message, broker may redeliver it: {}", e);
+                        }
+                        continue;
+                    }
+                    state.quotas.throttled += 1;
+                    loop {
+                        actor.wait(wait).await;
+                        match state.quotas.admit(tenant_id, Instant::now()) {
+                            Ok(()) => break,
+                            Err(again) => wait = again,
+                        }
+                    }
+                }
+                // Wait for room rather than refuse: holding the acknowledgement is what
+                // makes the broker stop sending until the pipeline catches up.
+                await_for_all!(actor.wait_vacant(&mut generated_tx, 1));
+                let envelope = Envelope::new(value)
+                    .with_trace(sample.map_or(0, |s| s.trace_id(state.accepted)))
+                    .with_tenant(tenant_id);
+                if actor.try_send(&mut generated_tx, envelope).is_sent() {
+                    state.accepted += 1;
+                    state.accepted_bytes += envelope.wire_bytes();
+                    trace::event("MQTT_SOURCE", "accepted", &envelope);
+                    true
+                } else {
+                    continue;
+                }
+            }
+            None => {
+                warn!("MQTT source dropping non-numeric payload on {}: {:?}", publish.topic, String::from_utf8_lossy(&publish.payload));
+                state.invalid += 1;
+                false
+            }
+        };
+
+        // Only now is the message safe to forget; the broker redelivers anything unacknowledged.
+        // With --ack-after-commit a value in the pipeline is only safe once the worker commits it.
+        let publish = state.pending.take().expect("checked above");
+        if acking && sent {
+            let seq = state.accepted;
+            // Too many waiting: the oldest is acknowledged now, as it would be without --ack-after-commit.
+            if let Some((_, evicted)) = state.awaiting.push(seq, publish)
+                && let Err(e) = state.link.as_ref().expect("link started").client.try_ack(&evicted) {
+                warn!("MQTT source could not acknowledge message, broker may redeliver it: {}", e);
+            }
+        } else if let Err(e) = state.link.as_ref().expect("link started").client.try_ack(&publish) {
+            warn!("MQTT source could not acknowledge message, broker may redeliver it: {}", e);
+        }
+
+        if pacer.is_due() {
+            let sample = CounterSample::new(state.accepted, state.accepted, 0)
+                .with_starts(state.restart_count)
+                .with_bytes(state.accepted_bytes, 0);
+            let _ = actor.try_send(&mut metrics_tx, sample);
+        }
+    }
+
+    state.reconnects = state.link.as_ref().expect("link started").reconnects();
+    info!(
+        "MQTT source shutting down. Accepted: {}, invalid: {}, reconnects: {}, over rate: {}, over quota rejected/throttled: {}, left for redelivery: {}, clients: {}",
+        state.accepted, state.invalid, state.reconnects, state.over_rate, state.quotas.summary(),
+        state.left_for_redelivery + state.awaiting.len() as u64, state.admission.summary()
+    );
+    Ok(())
+}
+
+/// Reads a payload as a decimal number, allowing surrounding whitespace.
+fn parse_payload(payload: &[u8]) -> Option<u64> {
+    std::str::from_utf8(payload).ok()?.trim().parse().ok()
+}
+
+#[cfg(test)]
+pub(crate) mod mqtt_source_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_payload() {
+        assert_eq!(parse_payload(b"15"), Some(15));
+        assert_eq!(parse_payload(b" 42\n"), Some(42));
+        assert_eq!(parse_payload(b"-1"), None);
+        assert_eq!(parse_payload(b"fizz"), None);
+        assert_eq!(parse_payload(&[0xff, 0xfe]), None);
+    }
+}
@@ -0,0 +1,190 @@
+use steady_state::*;
+use steady_state::actor_builder::{ActorBuilder, ScheduleAs};
+use crate::actor::reconciler::{CounterSample, SamplePacer, METRICS_GENERATOR};
+use crate::queue::{self, QueueCursor, QueueReader};
+use crate::startup::StartGate;
+use crate::state_lock::lock_state;
+use crate::timing::Envelope;
+use crate::trace;
+use crate::urgency::Priorities;
+use crate::wiring::{RobustActor, Wiring};
+
+/// How often the source looks for values appended since it last found the queue empty.
+const QUEUE_POLL: Duration = Duration::from_millis(20);
+
+/// QueueSourceState holds state for the queue source actor.
+/// The reader lives here so a restart carries on from the entry it was at; the cursor saved in
+/// the queue directory does the same for the next process.
+pub struct QueueSourceState {
+    /// Values passed on to the worker, and the bytes they came to.
+    pub(crate) accepted: u64,
+    pub(crate) accepted_bytes: u64,
+    /// Where this process started reading the queue.
+    pub(crate) started_at: QueueCursor,
+    /// Entries that would not parse where the cursor said the next one starts.
+    pub(crate) bad_entries: u64,
+    pub(crate) restart_count: u64,
+    reader: Option<QueueReader>,
+}
+
+/// Entry point for the queue source actor.
+/// Stands in for the generator, feeding the values the ingest graph queued into its channel.
+pub async fn run(
+    actor: SteadyActorShadow,
+    generated_tx: SteadyTx<Envelope<u64>>,
+    metrics_tx: SteadyTx<CounterSample>,
+    gate: StartGate,
+    state: SteadyState<QueueSourceState>,
+) -> Result<(), Box<dyn Error>> {
+    let actor = actor.into_spotlight([], [&generated_tx, &metrics_tx]);
+    if actor.use_internal_behavior {
+        gate.wait(&actor).await;
+        internal_behavior(actor, generated_tx, metrics_tx, state).await
+    } else {
+        actor.simulated_behavior(vec!(&generated_tx)).await
+    }
+}
+
+/// The queue source as a pipeline stage, in the generator's place with `--queue-dir`.
+pub struct QueueSource;
+
+impl RobustActor for QueueSource {
+    fn name(&self) -> &'static str {
+        crate::NAME_QUEUE_SOURCE
+    }
+    fn inputs(&self) -> &'static [&'static str] {
+        &[]
+    }
+    fn outputs(&self) -> &'static [&'static str] {
+        &["generated", "metrics"]
+    }
+    fn enabled(&self, args: &crate::MainArg) -> bool {
+        crate::wiring::source(args) == crate::wiring::Source::Queue
+    }
+    fn depends_on(&self, _args: &crate::MainArg) -> &'static [&'static str] {
+        &[crate::NAME_LOGGER] // values are read once the sink is connected
+    }
+    fn build(&self, builder: ActorBuilder, wiring: &Wiring, schedule: ScheduleAs<'_>) {
+        let (generated_tx, metrics_tx) = (wiring.generated.tx.clone(), wiring.metrics_tx[METRICS_GENERATOR].clone());
+        let (gate, state) = (wiring.start_gate(self), wiring.state.queue_source.clone());
+        builder.build(move |context| {
+            crate::priority::apply(&context, crate::NAME_QUEUE_SOURCE);
+            run(context, generated_tx.clone(), metrics_tx.clone(), gate.clone(), state.clone())
+        }, schedule);
+    }
+}
+
+async fn internal_behavior<A: SteadyActor>(
+    mut actor: A,
+    generated_tx: SteadyTx<Envelope<u64>>,
+    metrics_tx: SteadyTx<CounterSample>,
+    state: SteadyState<QueueSourceState>,
+) -> Result<(), Box<dyn Error>> {
+    let args = actor.args::<crate::MainArg>().expect("unable to downcast");
+    let Some(dir) = args.queue_dir.clone() else {
+        return Ok(());
+    };
+    let priorities = Priorities::from_args(args);
+    let Some(mut state) = lock_state(&actor, crate::NAME_QUEUE_SOURCE, &state, || QueueSourceState {
+        accepted: 0,
+        accepted_bytes: 0,
+        started_at: QueueCursor::default(),
+        bad_entries: 0,
+        restart_count: 0,
+        reader: None,
+    }).await else {
+        return Ok(());
+    };
+    state.restart_count += 1;
+    if state.reader.is_none() {
+        let cursor = queue::load_cursor(&dir)?;
+        state.reader = Some(QueueReader::open(&dir, cursor)?);
+        if state.restart_count == 1 {
+            state.started_at = cursor;
+        }
+    }
+    info!("Queue source starting (restart #{}) with accepted: {}, reading {:?} from seq {}",
+          state.restart_count, state.accepted, dir, state.reader.as_ref().expect("opened above").cursor().next_seq);
+
+    let mut generated_tx = generated_tx.lock().await;
+    let mut metrics_tx = metrics_tx.lock().await;
+    let mut pacer = SamplePacer::default();
+
+    // Whatever is still queued stays there for the next run; only what was handed on is the pipeline's.
+    while actor.is_running(|| i!(generated_tx.mark_closed()) && i!(metrics_tx.mark_closed())) {
+        await_for_all!(actor.wait_vacant(&mut generated_tx, 1));
+        let reader = state.reader.as_mut().expect("opened above");
+        let envelope = match reader.peek() {
+            // The queue keeps no priority; the ingest graph gave it by the same `--priority` rules.
+            Ok(Some(envelope)) => envelope.with_priority(priorities.of(envelope.payload)),
+            Ok(None) => {
+                await_for_any!(actor.wait_periodic(QUEUE_POLL), actor.wait_shutdown());
+                continue;
+            }
+            Err(e) => {
+                warn!("Queue source reading {:?} again from its cursor: {}", dir, e);
+                state.bad_entries += 1;
+                await_for_any!(actor.wait_periodic(QUEUE_POLL), actor.wait_shutdown());
+                continue;
+            }
+        };
+        if actor.try_send(&mut generated_tx, envelope).is_sent() {
+            // Sent, so past the cursor; a failed save leaves it to be read again after a restart.
+            if let Err(e) = reader.commit() {
+                warn!("Queue source could not save its cursor in {:?}, a later run may read this value again: {}", dir, e);
+            }
+            state.accepted += 1;
+            state.accepted_bytes += envelope.wire_bytes();
+            trace::event("QUEUE_SOURCE", "accepted", &envelope);
+        }
+
+        if pacer.is_due() {
+            let sample = CounterSample::new(state.accepted, state.accepted, 0)
+                .with_starts(state.restart_count)
+                .with_bytes(state.accepted_bytes, 0);
+            let _ = actor.try_send(&mut metrics_tx, sample);
+        }
+    }
+
+    let cursor = state.reader.as_ref().map(QueueReader::cursor).unwrap_or_default();
+    info!("Queue source shutting down. Accepted: {}, bad entries: {}, next seq: {}", state.accepted, state.bad_entries, cursor.next_seq);
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod queue_source_tests {
+    use crate::queue::QueueLog;
+    use crate::state_lock::settled;
+    use super::*;
+
+    /// The source hands on what is queued, and a second run with a fresh state resumes after it.
+    #[test]
+    fn test_queue_source_resumes_after_cursor() -> Result<(), Box<dyn Error>> {
+        let dir = std::env::temp_dir().join(format!("robust-queue-source-{}", crate::arg::new_run_id()));
+        let mut log = QueueLog::open(&dir)?;
+        for value in [3u64, 5, 15] {
+            log.append(&Envelope::new(value))?;
+        }
+        let args = crate::MainArg { queue_dir: Some(dir.clone()), ..Default::default() };
+        for expected in [vec![3, 5, 15], vec![21]] {
+            let mut graph = GraphBuilder::for_testing().build(args.clone());
+            let (generated_tx, generated_rx) = graph.channel_builder().build();
+            let (metrics_tx, _metrics_rx) = graph.channel_builder().build();
+            let state = new_state();
+            let probe = state.clone();
+            graph.actor_builder().with_name("UnitTest")
+                .build(move |context| internal_behavior(context, generated_tx.clone(), metrics_tx.clone(), state.clone()), SoloAct);
+            graph.start();
+            std::thread::sleep(Duration::from_millis(200));
+            graph.request_shutdown();
+            graph.block_until_stopped(Duration::from_secs(1))?;
+
+            let values: Vec<u64> = generated_rx.testing_take_all().iter().map(|e: &Envelope<u64>| e.payload).collect();
+            assert_eq!(values, expected);
+            assert_eq!(settled(&probe).expect("queue source state").accepted, expected.len() as u64);
+            log.append(&Envelope::new(21))?;
+        }
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+use steady_state::*;
+use steady_state::actor_builder::{ActorBuilder, ScheduleAs};
+use crate::fsync::Fsync;
+use crate::multi_graph::{self, GraphRole};
+use crate::queue::QueueLog;
+use crate::startup::StartGate;
+use crate::state_lock::lock_state;
+use crate::timing::Envelope;
+use crate::wiring::{RobustActor, Wiring};
+
+/// How often the writer looks to see whether the pipeline beside it has stopped.
+const STOP_POLL: Duration = Duration::from_millis(100);
+
+/// Persistent state of the queue writer. The open queue lives here so a restart appends on
+/// rather than recovering the log again.
+pub struct QueueWriterState {
+    /// Values appended in this run, and the values in the queue, read or not.
+    pub(crate) written: u64,
+    pub(crate) queued: u64,
+    pub(crate) restart_count: u64,
+    /// `--fsync` for the queue, so a power failure loses no more of it than the policy allows.
+    pub(crate) fsync: Fsync,
+    log: Option<QueueLog>,
+}
+
+/// Entry point for the queue writer actor.
+/// The ingest graph's consumer: appends each generated value to the `--queue-dir` queue, and
+/// takes it off its channel only once it is there.
+pub async fn run(
+    actor: SteadyActorShadow,
+    generated_rx: SteadyRx<Envelope<u64>>,
+    gate: StartGate,
+    state: SteadyState<QueueWriterState>,
+) -> Result<(), Box<dyn Error>> {
+    let actor = actor.into_spotlight([&generated_rx], []);
+    if actor.use_internal_behavior {
+        internal_behavior(actor, generated_rx, gate, state).await
+    } else {
+        actor.simulated_behavior(vec!(&generated_rx)).await
+    }
+}
+
+/// The queue writer as a stage of the ingest graph of a `multi-graph` run.
+pub struct QueueWriter;
+
+impl RobustActor for QueueWriter {
+    fn name(&self) -> &'static str {
+        crate::NAME_QUEUE_WRITER
+    }
+    fn inputs(&self) -> &'static [&'static str] {
+        &["generated"]
+    }
+    fn outputs(&self) -> &'static [&'static str] {
+        &[]
+    }
+    fn enabled(&self, args: &crate::MainArg) -> bool {
+        args.graph == GraphRole::Ingest
+    }
+    fn announces_ready(&self) -> bool {
+        true // once the queue is open
+    }
+    fn build(&self, builder: ActorBuilder, wiring: &Wiring, schedule: ScheduleAs<'_>) {
+        let generated_rx = wiring.generated.rx.clone();
+        let (gate, state) = (wiring.start_gate(self), wiring.state.queue_writer.clone());
+        builder.build(move |context| {
+            crate::priority::apply(&context, crate::NAME_QUEUE_WRITER);
+            run(context, generated_rx.clone(), gate.clone(), state.clone())
+        }, schedule);
+    }
+}
+
+async fn internal_behavior<A: SteadyActor>(
+    mut actor: A,
+    generated_rx: SteadyRx<Envelope<u64>>,
+    gate: StartGate,
+    state: SteadyState<QueueWriterState>,
+) -> Result<(), Box<dyn Error>> {
+    let args = actor.args::<crate::MainArg>().expect("unable to downcast");
+    let (beats, policy) = (args.beats, args.fsync);
+    let Some(dir) = args.queue_dir.clone() else {
+        return Ok(());
+    };
+    let Some(mut state) = lock_state(&actor, crate::NAME_QUEUE_WRITER, &state, || QueueWriterState {
+        written: 0,
+        queued: 0,
+        restart_count: 0,
+        fsync: Fsync::new(policy),
+        log: None,
+    }).await else {
+        return Ok(());
+    };
+    state.restart_count += 1;
+    if state.log.is_none() {
+        let log = QueueLog::open(&dir)?;
+        state.queued = log.len();
+        state.log = Some(log);
+    }
+    info!("Queue writer starting (restart #{}) with written: {}, appending to {:?} after {} queued values",
+          state.restart_count, state.written, dir, state.queued);
+    gate.ready();
+
+    let mut generated_rx = generated_rx.lock().await;
+    let mut stop_requested = false;
+    while actor.is_running(|| i!(generated_rx.is_closed_and_empty())) {
+        await_for_any!(actor.wait_avail(&mut generated_rx, 1), actor.wait_periodic(STOP_POLL));
+
+        while let Some(&envelope) = actor.try_peek(&mut generated_rx) {
+            let QueueWriterState { log, fsync, .. } = &mut *state;
+            let queue = log.as_mut().expect("opened above");
+            // A failed append may have left part of an entry; the queue is opened again on restart, which cuts it off.
+            if let Err(e) = queue.append(&envelope) {
+                *log = None;
+                return Err(format!("cannot append to the queue in {:?}: {}", dir, e).into());
+            }
+            if fsync.committed() {
+                match queue.sync() {
+                    Ok(()) => fsync.synced(),
+                    Err(e) => {
+                        warn!("Queue writer could not sync the queue, {} values at risk: {}", fsync.at_risk, e);
+                        fsync.sync_failed();
+                    }
+                }
+            }
+            actor.try_take(&mut generated_rx);
+            state.written += 1;
+            state.queued += 1;
+        }
+
+        // Done once --beats values are queued, or when the pipeline beside it is.
+        let done = beats > 0 && state.written >= beats;
+        if !stop_requested && (done || multi_graph::pipeline_stopped() || crate::signals::interrupted()) {
+            info!("Queue writer {} after {} values, requesting ingest graph stop",
+                  if done { "finished" } else { "stopping with the pipeline" }, state.written);
+            stop_requested = true;
+            actor.request_shutdown().await;
+        }
+    }
+
+    if state.fsync.due_at_stop() {
+        let QueueWriterState { log, fsync, .. } = &mut *state;
+        match log.as_mut().map(QueueLog::sync) {
+            Some(Ok(())) => fsync.synced(),
+            Some(Err(e)) => warn!("Queue writer could not sync the queue at stop: {}", e),
+            None => {}
+        }
+    }
+    info!("Queue writer shutting down. Written: {}, queued: {}", state.written, state.queued);
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod queue_writer_tests {
+    use crate::queue::{QueueCursor, QueueReader};
+    use crate::state_lock::settled;
+    use super::*;
+
+    #[test]
+    fn test_queue_writer_appends_in_order() -> Result<(), Box<dyn Error>> {
+        let dir = std::env::temp_dir().join(format!("robust-queue-writer-{}", crate::arg::new_run_id()));
+        let args = crate::MainArg { queue_dir: Some(dir.clone()), graph: GraphRole::Ingest, beats: 0, ..Default::default() };
+        let mut graph = GraphBuilder::for_testing().build(args.clone());
+        let (generated_tx, generated_rx) = graph.channel_builder().build();
+        let state = new_state();
+        let probe = state.clone();
+        let gate = crate::startup::Readiness::default().gate(crate::NAME_QUEUE_WRITER, &[]);
+        graph.actor_builder().with_name("UnitTest")
+            .build(move |context| internal_behavior(context, generated_rx.clone(), gate.clone(), state.clone()), SoloAct);
+
+        generated_tx.testing_send_all(vec![Envelope::new(3u64), Envelope::new(5u64)], true);
+        graph.start();
+        std::thread::sleep(Duration::from_millis(200));
+        graph.request_shutdown();
+        graph.block_until_stopped(Duration::from_secs(1))?;
+
+        let written = settled(&probe).expect("queue writer state").written;
+        assert_eq!(written, 2);
+        let mut reader = QueueReader::open(&dir, QueueCursor::default())?;
+        let mut read = Vec::new();
+        while let Some(envelope) = reader.peek()? {
+            read.push(envelope.payload);
+            reader.commit()?;
+        }
+        assert_eq!(read, [3, 5]);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
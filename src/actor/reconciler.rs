@@ -0,0 +1,710 @@
+use steady_state::*;
+use serde_json::json;
+use crate::anomaly::AnomalyWatch;
+use crate::backpressure::FillWatch;
+use crate::classify::Payload;
+use crate::events;
+use crate::failure::BudgetLedger;
+use std::collections::BTreeMap;
+use crate::state_lock::lock_state;
+use crate::tenant::{self, TenantSample};
+use crate::timing::Envelope;
+use steady_state::actor_builder::{ActorBuilder, ScheduleAs};
+use crate::wiring::{RobustActor, Wiring};
+
+/// Lane of the metrics bundle each counting actor publishes on.
+pub(crate) const METRICS_GENERATOR: usize = 0;
+pub(crate) const METRICS_WORKER: usize = 1;
+pub(crate) const METRICS_LOGGER: usize = 2;
+pub(crate) const METRICS_LANES: usize = 3;
+/// Stage names for each lane, as they appear in status documents.
+pub(crate) const LANE_NAMES: [&str; METRICS_LANES] = ["generator", "worker", "logger"];
+/// The data channels whose bytes are tracked, with the lanes of the stages that write and read them.
+pub(crate) const DATA_CHANNELS: [(&str, usize, usize); 2] =
+    [("generated", METRICS_GENERATOR, METRICS_WORKER), ("classified", METRICS_WORKER, METRICS_LOGGER)];
+/// Most inputs a stage reports the depth of in its `CounterSample::depths`.
+pub(crate) const SAMPLED_INPUTS: usize = 2;
+/// The channels whose depth is tracked, with the lane of the stage reading each and where it
+/// is in that stage's `CounterSample::depths`.
+pub(crate) const DEPTH_CHANNELS: [(&str, usize, usize); 4] = [
+    ("generated", METRICS_WORKER, 0), ("heartbeat", METRICS_WORKER, 1),
+    ("classified", METRICS_LOGGER, 0), ("rejected", METRICS_LOGGER, 1),
+];
+
+/// Minimum spacing between samples from one actor, keeping the metrics channel
+/// far quieter than the data path it describes.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Point-in-time counters published by a pipeline stage.
+/// All three numbers are read together on the publishing thread, so they are
+/// mutually consistent as of `at`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CounterSample {
+    /// Messages taken from the stage's inputs, whatever their fate.
+    pub(crate) consumed: u64,
+    /// Messages committed to the stage's outputs.
+    pub(crate) produced: u64,
+    /// Messages waiting in the stage's input channels when sampled.
+    pub(crate) backlog: u64,
+    /// Times the publishing actor has started, so restarts show up in status queries.
+    pub(crate) starts: u64,
+    /// Messages the stage dropped as showstoppers, for the failure budget.
+    pub(crate) dropped: u64,
+    /// Bytes committed to the stage's data output, and waiting in its data input when sampled,
+    /// as `Envelope::wire_bytes` counts them.
+    pub(crate) produced_bytes: u64,
+    pub(crate) backlog_bytes: u64,
+    /// Sequence high-water mark: the last seq the worker sent on, or the last the logger
+    /// committed; 0 from stages that do not number their messages.
+    pub(crate) seq: u64,
+    /// Depth of each of the stage's inputs, in the order `DEPTH_CHANNELS` gives; zeros from
+    /// stages with no tracked input.
+    pub(crate) depths: [ChannelDepth; SAMPLED_INPUTS],
+    pub(crate) at: Instant,
+}
+
+impl CounterSample {
+    pub(crate) fn new(consumed: u64, produced: u64, backlog: u64) -> Self {
+        CounterSample { consumed, produced, backlog, starts: 0, dropped: 0, produced_bytes: 0, backlog_bytes: 0, seq: 0, depths: Default::default(), at: Instant::now() }
+    }
+
+    pub(crate) fn with_starts(mut self, starts: u64) -> Self {
+        self.starts = starts;
+        self
+    }
+
+    pub(crate) fn with_dropped(mut self, dropped: u64) -> Self {
+        self.dropped = dropped;
+        self
+    }
+
+    pub(crate) fn with_bytes(mut self, produced_bytes: u64, backlog_bytes: u64) -> Self {
+        self.produced_bytes = produced_bytes;
+        self.backlog_bytes = backlog_bytes;
+        self
+    }
+
+    pub(crate) fn with_seq(mut self, seq: u64) -> Self {
+        self.seq = seq;
+        self
+    }
+
+    pub(crate) fn with_depths(mut self, depths: [ChannelDepth; SAMPLED_INPUTS]) -> Self {
+        self.depths = depths;
+        self
+    }
+}
+
+/// Items waiting in one channel and the slots it has. As a high-water mark, `items` is the
+/// most seen, and a mark at `capacity` means the channel was full at some sample.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChannelDepth {
+    pub(crate) items: u64,
+    pub(crate) capacity: u64,
+}
+
+impl ChannelDepth {
+    /// The depth of `rx` now, for a stage's `CounterSample::depths`.
+    pub(crate) fn of<A: SteadyActor, T>(actor: &A, rx: &mut Rx<T>) -> Self {
+        ChannelDepth { items: actor.avail_units(rx) as u64, capacity: rx.capacity() as u64 }
+    }
+
+    /// Keeps the deeper of the two; the capacity is that of the latest sample.
+    pub(crate) fn deepest(self, sample: ChannelDepth) -> Self {
+        ChannelDepth { items: self.items.max(sample.items), capacity: sample.capacity }
+    }
+}
+
+/// Byte telemetry for one data channel, for capacity planning in bytes rather than slots.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChannelBytes {
+    /// Bytes written to the channel so far.
+    pub(crate) produced: u64,
+    /// Bytes waiting in it at the reader's latest sample, and the most seen waiting.
+    pub(crate) in_flight: u64,
+    pub(crate) in_flight_max: u64,
+    /// Bytes per second written between the writer's two latest samples, and the fastest seen.
+    pub(crate) per_sec: u64,
+    pub(crate) per_sec_max: u64,
+}
+
+impl ChannelBytes {
+    /// Takes in a sample from the channel's writer, `previous` being the one before it.
+    fn written(&mut self, previous: Option<&CounterSample>, sample: &CounterSample) {
+        if let Some(previous) = previous
+            && sample.at > previous.at {
+            let bytes = sample.produced_bytes.saturating_sub(previous.produced_bytes);
+            self.per_sec = (bytes as f64 / sample.at.duration_since(previous.at).as_secs_f64()) as u64;
+            self.per_sec_max = self.per_sec_max.max(self.per_sec);
+        }
+        self.produced = self.produced.max(sample.produced_bytes);
+    }
+
+    /// Takes in a sample from the channel's reader.
+    fn read(&mut self, sample: &CounterSample) {
+        self.in_flight = sample.backlog_bytes;
+        self.in_flight_max = self.in_flight_max.max(sample.backlog_bytes);
+    }
+}
+
+/// Bytes waiting in `rx`, for a stage's `CounterSample::backlog_bytes`.
+pub(crate) fn backlog_bytes<A: SteadyActor, T: Payload>(actor: &A, rx: &mut Rx<Envelope<T>>) -> u64 {
+    actor.try_peek_iter(rx).map(Envelope::wire_bytes).sum()
+}
+
+/// Asks the reconciler for its current view of the pipeline.
+/// The id comes back in the reply so a late answer to an abandoned query is recognized.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StatusRequest {
+    pub(crate) id: u64,
+}
+
+/// The reconciler's answer to a `StatusRequest`: the latest sample from every
+/// lane, drained just before replying, plus its own audit totals and the per-tenant counts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StatusReply {
+    pub(crate) id: u64,
+    pub(crate) stages: [Option<CounterSample>; METRICS_LANES],
+    pub(crate) tenants: Vec<TenantSample>,
+    pub(crate) checks: u64,
+    pub(crate) inconsistencies: u64,
+    pub(crate) starts: u64,
+    /// Bytes on each of the `DATA_CHANNELS`.
+    pub(crate) channels: [ChannelBytes; DATA_CHANNELS.len()],
+    /// Messages between generation and logger commit at the latest samples, and the most seen.
+    pub(crate) in_flight: Option<u64>,
+    pub(crate) in_flight_max: u64,
+}
+
+/// Paces how often an actor publishes a `CounterSample`.
+/// Held as a local in `internal_behavior`, so a restart simply publishes sooner.
+#[derive(Default)]
+pub(crate) struct SamplePacer {
+    last: Option<Instant>,
+}
+
+impl SamplePacer {
+    pub(crate) fn is_due(&mut self) -> bool {
+        let now = Instant::now();
+        if self.last.is_none_or(|last| now.duration_since(last) >= SAMPLE_INTERVAL) {
+            self.last = Some(now);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Checks one edge of the pipeline.
+/// Everything the downstream stage has consumed or still holds in its inputs must
+/// have been produced upstream. This is only decidable when the upstream sample is
+/// at least as new as the downstream one, since counters only ever grow.
+/// Returns the number of messages that appeared from nowhere.
+pub(crate) fn edge_excess(upstream: &CounterSample, downstream: &CounterSample) -> Option<u64> {
+    if upstream.at < downstream.at {
+        return None;
+    }
+    let seen_downstream = downstream.consumed + downstream.backlog;
+    (seen_downstream > upstream.produced).then(|| seen_downstream - upstream.produced)
+}
+
+/// Messages between generation and logger commit, from two pairs of high-water marks: values
+/// the generator sent that the worker has not taken, and seqs the worker sent that the logger
+/// has not committed. Four counters, however many messages there are. Values the worker rejects
+/// leave the count when it takes them. Needs a sample from all three lanes.
+pub(crate) fn in_flight(latest: &[Option<CounterSample>; METRICS_LANES]) -> Option<u64> {
+    let [Some(generator), Some(worker), Some(logger)] = latest else {
+        return None;
+    };
+    Some(generator.produced.saturating_sub(worker.consumed) + worker.seq.saturating_sub(logger.seq))
+}
+
+/// ReconcilerState holds state for the Reconciler actor.
+/// Audit totals survive restarts so the final report covers the whole run.
+pub struct ReconcilerState {
+    pub(crate) checks: u64,
+    pub(crate) inconsistencies: u64,
+    /// Sudden changes in throughput or restart rate flagged under `--anomaly-sigma`.
+    pub(crate) anomalies: u64,
+    pub(crate) restart_count: u64,
+    /// Deepest each of the `DEPTH_CHANNELS` has been, as seen at the readers' sample cadence.
+    pub(crate) depth_max: [ChannelDepth; DEPTH_CHANNELS.len()],
+    /// Bytes on each of the `DATA_CHANNELS`, kept with the high-water marks so a restart does not reset them.
+    pub(crate) channels: [ChannelBytes; DATA_CHANNELS.len()],
+    /// Latest logged count per tenant, as the logger publishes it.
+    pub(crate) tenants: BTreeMap<u32, u64>,
+    /// With `--no-heartbeat` nothing counts beats, so the reconciler ends the run at this
+    /// instant instead. Fixed at first start so a restart does not extend the run.
+    pub(crate) run_until: Option<Instant>,
+    /// Set once the `--failure-budget` was used up and the demonstration failures turned off.
+    pub(crate) budget_spent: bool,
+    /// Most messages seen in flight between generation and logger commit.
+    pub(crate) in_flight_max: u64,
+    /// Times the in-flight count rose past `--inflight-alert`, and whether it is past it now.
+    pub(crate) in_flight_alerts: u64,
+    pub(crate) in_flight_over: bool,
+    /// Times a channel stayed past its `--fill-alert` threshold for the hold.
+    pub(crate) fill_alerts: u64,
+}
+
+/// Entry point for the Reconciler actor.
+/// Continuously audits the exactly-once claims using the counters every stage publishes.
+pub async fn run(
+    actor: SteadyActorShadow,
+    metrics_rx: SteadyRxBundle<CounterSample, METRICS_LANES>,
+    tenants_rx: SteadyRx<TenantSample>,
+    status_rx: SteadyRx<StatusRequest>,
+    status_tx: SteadyTx<StatusReply>,
+    state: SteadyState<ReconcilerState>,
+) -> Result<(), Box<dyn Error>> {
+    let [generator, worker, logger] = metrics_rx.meta_data();
+    let actor = actor.into_spotlight([generator, worker, logger, &tenants_rx, &status_rx], [&status_tx]);
+    if actor.use_internal_behavior {
+        internal_behavior(actor, metrics_rx, tenants_rx, status_rx, status_tx, state).await
+    } else {
+        actor.simulated_behavior(vec!()).await
+    }
+}
+
+/// The reconciler as a pipeline stage.
+pub struct Reconciler;
+
+impl RobustActor for Reconciler {
+    fn name(&self) -> &'static str {
+        crate::NAME_RECONCILER
+    }
+    fn inputs(&self) -> &'static [&'static str] {
+        &["metrics", "tenants", "status_request"]
+    }
+    fn outputs(&self) -> &'static [&'static str] {
+        &["status_reply"]
+    }
+    fn build(&self, builder: ActorBuilder, wiring: &Wiring, schedule: ScheduleAs<'_>) {
+        let (metrics_rx, tenants_rx) = (wiring.metrics_rx.clone(), wiring.tenants.rx.clone());
+        let (status_rx, status_tx) = (wiring.status_request.rx.clone(), wiring.status_reply.tx.clone());
+        let state = wiring.state.reconciler.clone();
+        builder.build(move |context| {
+            crate::priority::apply(&context, crate::NAME_RECONCILER);
+            run(context, metrics_rx.clone(), tenants_rx.clone(), status_rx.clone(), status_tx.clone(), state.clone())
+        }, schedule);
+    }
+}
+
+async fn internal_behavior<A: SteadyActor>(
+    mut actor: A,
+    metrics_rx: SteadyRxBundle<CounterSample, METRICS_LANES>,
+    tenants_rx: SteadyRx<TenantSample>,
+    status_rx: SteadyRx<StatusRequest>,
+    status_tx: SteadyTx<StatusReply>,
+    state: SteadyState<ReconcilerState>,
+) -> Result<(), Box<dyn Error>> {
+    let args = actor.args::<crate::MainArg>().expect("unable to downcast");
+    let interval = Duration::from_millis(args.reconcile_ms);
+
+    let Some(mut state) = lock_state(&actor, crate::NAME_RECONCILER, &state, || ReconcilerState {
+        checks: 0,
+        inconsistencies: 0,
+        anomalies: 0,
+        restart_count: 0,
+        depth_max: Default::default(),
+        channels: Default::default(),
+        tenants: BTreeMap::new(),
+        run_until: args.no_heartbeat.then(|| Instant::now() + Duration::from_millis(args.rate_ms * args.beats)),
+        budget_spent: false,
+        in_flight_max: 0,
+        in_flight_alerts: 0,
+        in_flight_over: false,
+        fill_alerts: 0,
+    }).await else {
+        return Ok(());
+    };
+    state.restart_count += 1;
+    info!(
+        "Reconciler starting (restart #{}) with checks: {}, inconsistencies: {}",
+        state.restart_count, state.checks, state.inconsistencies
+    );
+
+    let mut metrics_rx = metrics_rx.lock().await;
+    let mut tenants_rx = tenants_rx.lock().await;
+    let mut status_rx = status_rx.lock().await;
+    let mut status_tx = status_tx.lock().await;
+    // Latest sample per lane; only the newest matters since counters never shrink.
+    let mut latest: [Option<CounterSample>; METRICS_LANES] = [None; METRICS_LANES];
+    let mut anomaly_watch = AnomalyWatch::from_args(args);
+    let mut fill_watch = FillWatch::from_args(args);
+    let budget = args.failure_budget.clone();
+    let inflight_alert = args.inflight_alert;
+    let mut ledger = BudgetLedger::new(Instant::now());
+
+    while actor.is_running(|| metrics_rx.iter_mut().all(|rx| rx.is_closed_and_empty())
+                              && tenants_rx.is_closed_and_empty()
+                              && status_tx.mark_closed()) {
+        // A status query cuts the wait short; auditing early does no harm.
+        await_for_any!(actor.wait_periodic(interval), actor.wait_avail(&mut status_rx, 1));
+
+        let mut fresh = [false; METRICS_LANES];
+        for (lane, rx) in metrics_rx.iter_mut().enumerate() {
+            while let Some(sample) = actor.try_take(rx) {
+                if let Some(previous) = latest[lane]
+                    && (sample.consumed < previous.consumed || sample.produced < previous.produced) {
+                    state.inconsistencies += 1;
+                    error!("Reconciler: counters on lane {} went backwards {:?} -> {:?}", lane, previous, sample);
+                    events::emit("alert", json!({"kind": "counters_backwards", "lane": lane}));
+                }
+                for (channel, &(name, reader, input)) in DEPTH_CHANNELS.iter().enumerate() {
+                    if lane != reader {
+                        continue;
+                    }
+                    let depth = sample.depths[input];
+                    state.depth_max[channel] = state.depth_max[channel].deepest(depth);
+                    // Channels fill up as the run winds down, which is no backpressure.
+                    if actor.is_liveliness_stop_requested() {
+                        continue;
+                    }
+                    let (raised, cleared) = fill_watch.observe(channel, depth, sample.at);
+                    for alert in raised {
+                        state.fill_alerts += 1;
+                        warn!(
+                            "Reconciler: {} channel at {} of {} for {}ms, past the alert at {}% for {}s",
+                            name, depth.items, depth.capacity, alert.held.as_millis(), alert.rule.percent, alert.rule.hold.as_secs()
+                        );
+                        events::emit("alert", json!({
+                            "kind": "channel_fill", "channel": name, "items": depth.items, "capacity": depth.capacity,
+                            "percent": alert.rule.percent, "held_ms": alert.held.as_millis() as u64,
+                        }));
+                    }
+                    for rule in cleared {
+                        info!("Reconciler: {} channel back to {} of {}, under {}% full", name, depth.items, depth.capacity, rule.percent);
+                    }
+                }
+                for (channel, &(_, writer, reader)) in DATA_CHANNELS.iter().enumerate() {
+                    if lane == writer {
+                        state.channels[channel].written(latest[lane].as_ref(), &sample);
+                    }
+                    if lane == reader {
+                        state.channels[channel].read(&sample);
+                    }
+                }
+                latest[lane] = Some(sample);
+                fresh[lane] = true;
+            }
+        }
+
+        // Samples can only trail the logger's counts, so the larger one is the newer.
+        while let Some(sample) = actor.try_take(&mut tenants_rx) {
+            let logged = state.tenants.entry(sample.tenant).or_default();
+            *logged = (*logged).max(sample.logged);
+        }
+
+        let edges = [("generator->worker", METRICS_GENERATOR, METRICS_WORKER),
+                     ("worker->logger", METRICS_WORKER, METRICS_LOGGER)];
+        for (name, up, down) in edges {
+            // Re-checking the same pair of samples would only repeat the last verdict.
+            if !(fresh[up] || fresh[down]) {
+                continue;
+            }
+            if let (Some(upstream), Some(downstream)) = (latest[up], latest[down]) {
+                state.checks += 1;
+                if let Some(excess) = edge_excess(&upstream, &downstream) {
+                    state.inconsistencies += 1;
+                    error!(
+                        "Reconciler: {} edge out of balance by {} (produced {}, consumed {} + backlog {})",
+                        name, excess, upstream.produced, downstream.consumed, downstream.backlog
+                    );
+                    events::emit("alert", json!({"kind": "edge_imbalance", "edge": name, "excess": excess}));
+                }
+            }
+        }
+
+        // A stage that silently stops committing shows up here well before anything runs dry.
+        let in_flight = in_flight(&latest);
+        if let Some(count) = in_flight {
+            state.in_flight_max = state.in_flight_max.max(count);
+            if let Some(bound) = inflight_alert
+                && !actor.is_liveliness_stop_requested() {
+                if count > bound && !state.in_flight_over {
+                    state.in_flight_over = true;
+                    state.in_flight_alerts += 1;
+                    warn!("Reconciler: {} messages in flight between generator and logger, over the bound of {}", count, bound);
+                    events::emit("alert", json!({"kind": "in_flight_high", "in_flight": count, "bound": bound}));
+                } else if count <= bound && state.in_flight_over {
+                    state.in_flight_over = false;
+                    info!("Reconciler: in-flight messages back to {}, within the bound of {}", count, bound);
+                }
+            }
+        }
+
+        // Throughput falls away as the run winds down, which is no anomaly.
+        if let Some(watch) = anomaly_watch.as_mut()
+            && !actor.is_liveliness_stop_requested() {
+            let logged = latest[METRICS_LOGGER].map_or(0, |sample| sample.produced);
+            let starts = latest.iter().flatten().map(|sample| sample.starts).sum();
+            for (series, anomaly) in watch.observe(logged, starts, Instant::now()) {
+                state.anomalies += 1;
+                warn!(
+                    "Anomaly: {} {} to {:.1}/s, expected about {:.1}/s",
+                    series, anomaly.direction.as_str(), anomaly.value, anomaly.expected
+                );
+                events::emit("alert", json!({
+                    "kind": "anomaly", "series": series, "direction": anomaly.direction.as_str(),
+                    "rate": anomaly.value, "expected": anomaly.expected,
+                }));
+            }
+        }
+
+        // The failure budget: once the demonstration failures have cost what they may, they stop.
+        if let Some(budget) = &budget
+            && budget.chaos.is_on() {
+            let restarts = latest.iter().flatten().map(|sample| sample.starts.saturating_sub(1)).sum();
+            let dropped = latest.iter().flatten().map(|sample| sample.dropped).sum();
+            let (restarts, dropped) = ledger.observe(restarts, dropped, Instant::now());
+            if let Some(allowance) = budget.used_up(restarts, dropped) {
+                budget.chaos.turn_off();
+                state.budget_spent = true;
+                error!(
+                    "Failure budget for {} used up ({} restarts, {} drops in the last hour), demonstration failures are off for the rest of the run",
+                    allowance, restarts, dropped
+                );
+                events::emit("alert", json!({"kind": "failure_budget", "allowance": allowance, "restarts": restarts, "drops": dropped}));
+            }
+        }
+
+        while let Some(request) = actor.try_take(&mut status_rx) {
+            let reply = StatusReply {
+                id: request.id,
+                stages: latest,
+                tenants: state.tenants.iter().map(|(&tenant, &logged)| TenantSample { tenant, logged }).collect(),
+                checks: state.checks,
+                inconsistencies: state.inconsistencies,
+                starts: state.restart_count,
+                channels: state.channels,
+                in_flight,
+                in_flight_max: state.in_flight_max,
+            };
+            // The asker gives up after a while, so a full reply channel just drops the answer.
+            let _ = actor.try_send(&mut status_tx, reply);
+        }
+
+        // Standing in for the heartbeat: stop when its beats would have run out, or on Ctrl-C.
+        if let Some(until) = state.run_until
+            && (Instant::now() >= until || crate::signals::interrupted())
+            && !actor.is_liveliness_stop_requested() {
+            info!("Reconciler ending the heartbeat-less run, requesting graph stop");
+            actor.request_shutdown().await;
+        }
+    }
+
+    info!(
+        "Reconciler shutting down. Checks: {}, inconsistencies: {}, anomalies: {}, in flight max: {} (alerts: {}), fill alerts: {}, logged by tenant: {}",
+        state.checks, state.inconsistencies, state.anomalies, state.in_flight_max, state.in_flight_alerts, state.fill_alerts, tenant::summary(&state.tenants)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod reconciler_tests {
+    use std::thread::sleep;
+    use steady_state::*;
+    use crate::arg::MainArg;
+    use super::*;
+
+    #[test]
+    fn test_edge_excess() {
+        let up = CounterSample::new(0, 10, 0);
+        sleep(Duration::from_millis(1));
+        let newer_up = CounterSample::new(0, 10, 0);
+        let down = CounterSample { at: up.at, ..CounterSample::new(8, 8, 2) };
+        assert_eq!(edge_excess(&up, &down), None);
+        let too_many = CounterSample { at: up.at, ..CounterSample::new(9, 9, 3) };
+        assert_eq!(edge_excess(&up, &too_many), Some(2));
+        // a downstream sample newer than the upstream one proves nothing
+        assert_eq!(edge_excess(&up, &CounterSample::new(50, 50, 0)), None);
+        assert_eq!(edge_excess(&newer_up, &too_many), Some(2));
+    }
+
+    #[test]
+    fn test_channel_bytes() {
+        // a fixed-size payload is its slot; a word adds the text it refers to
+        let value = Envelope::new(7u64);
+        assert_eq!(value.wire_bytes(), size_of::<Envelope<u64>>() as u64);
+        assert_eq!(Envelope::new("fizz").wire_bytes(), (size_of::<Envelope<&str>>() + 4) as u64);
+
+        let mut channel = ChannelBytes::default();
+        let first = CounterSample::new(0, 10, 0).with_bytes(1_000, 0);
+        let second = CounterSample { at: first.at + Duration::from_millis(500), ..CounterSample::new(0, 20, 0).with_bytes(3_000, 0) };
+        channel.written(None, &first);
+        channel.written(Some(&first), &second);
+        channel.read(&CounterSample::new(5, 5, 4).with_bytes(0, 256));
+        channel.read(&CounterSample::new(9, 9, 0).with_bytes(0, 0));
+        assert_eq!(channel, ChannelBytes { produced: 3_000, in_flight: 0, in_flight_max: 256, per_sec: 4_000, per_sec_max: 4_000 });
+        // a writer that restarted without its byte count never winds the total back
+        channel.written(Some(&second), &CounterSample { at: second.at + Duration::from_secs(1), ..first });
+        assert_eq!((channel.produced, channel.per_sec, channel.per_sec_max), (3_000, 0, 4_000));
+    }
+
+    #[test]
+    fn test_reconciler() -> Result<(), Box<dyn Error>> {
+        let mut graph = GraphBuilder::for_testing().build(MainArg {
+            reconcile_ms: 10,
+            ..Default::default()
+        });
+        let (metrics_tx, metrics_rx) = graph.channel_builder().build_channel_bundle::<CounterSample, METRICS_LANES>();
+        let (tenants_tx, tenants_rx) = graph.channel_builder().build();
+        let (status_request_tx, status_rx) = graph.channel_builder().build();
+        let (status_tx, status_reply_rx) = graph.channel_builder().build();
+
+        let state = new_state();
+        let probe = state.clone();
+        graph.actor_builder()
+            .with_name("UnitTest")
+            .build(move |context| internal_behavior(context, metrics_rx.clone(), tenants_rx.clone(), status_rx.clone(), status_tx.clone(), state.clone()), SoloAct);
+
+        // the worker claims more than the generator ever produced
+        let depth = |items, capacity| ChannelDepth { items, capacity };
+        let deep = CounterSample::new(12, 12, 5).with_depths([depth(5, 64), depth(1, 8)]);
+        let worker = CounterSample::new(12, 12, 0).with_depths([depth(0, 64), depth(2, 8)]);
+        let logger = CounterSample::new(0, 0, 10).with_depths([depth(3, 64), depth(7, 16)]);
+        sleep(Duration::from_millis(1));
+        let generator = CounterSample::new(0, 10, 0);
+        metrics_tx[METRICS_WORKER].testing_send_all(vec![deep, worker], true);
+        metrics_tx[METRICS_GENERATOR].testing_send_all(vec![generator], true);
+        metrics_tx[METRICS_LOGGER].testing_send_all(vec![logger], true);
+        // a late sample never winds a tenant's count back
+        let acme = tenant::id("reconciler-test-acme");
+        tenants_tx.testing_send_all(vec![TenantSample { tenant: acme, logged: 4 }, TenantSample { tenant: acme, logged: 3 }], true);
+
+        graph.start();
+        sleep(Duration::from_millis(100));
+        status_request_tx.testing_send_all(vec![StatusRequest { id: 7 }], true);
+        sleep(Duration::from_millis(100));
+        graph.request_shutdown();
+        graph.block_until_stopped(Duration::from_secs(1))?;
+
+        let state = crate::state_lock::settled(&probe).expect("reconciler ran");
+        assert!(state.checks > 0);
+        assert_eq!(state.inconsistencies, 1);
+        // each channel keeps its own mark, which outlives the depth that set it
+        assert_eq!(state.depth_max, [depth(5, 64), depth(2, 8), depth(3, 64), depth(7, 16)]);
+        let replies = status_reply_rx.testing_take_all();
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].id, 7);
+        assert_eq!(replies[0].stages[METRICS_WORKER], Some(worker));
+        assert_eq!(replies[0].stages[METRICS_LOGGER], Some(logger));
+        assert_eq!(replies[0].inconsistencies, 1);
+        assert_eq!(replies[0].tenants, [TenantSample { tenant: acme, logged: 4 }]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_in_flight_alert() -> Result<(), Box<dyn Error>> {
+        let mut graph = GraphBuilder::for_testing().build(MainArg { reconcile_ms: 10, inflight_alert: Some(10), ..Default::default() });
+        let (metrics_tx, metrics_rx) = graph.channel_builder().build_channel_bundle::<CounterSample, METRICS_LANES>();
+        let (tenants_tx, tenants_rx) = graph.channel_builder().build();
+        let (_status_request_tx, status_rx) = graph.channel_builder().build();
+        let (status_tx, _status_reply_rx) = graph.channel_builder().build();
+
+        let state = new_state();
+        let probe = state.clone();
+        graph.actor_builder()
+            .with_name("UnitTest")
+            .build(move |context| internal_behavior(context, metrics_rx.clone(), tenants_rx.clone(), status_rx.clone(), status_tx.clone(), state.clone()), SoloAct);
+
+        // 8 values wait for the worker and 6 of its messages for the logger
+        let generator = CounterSample::new(0, 20, 0);
+        let worker = CounterSample::new(12, 11, 8).with_seq(10);
+        let logger = CounterSample::new(4, 4, 6).with_seq(4);
+        assert_eq!(in_flight(&[Some(generator), Some(worker), Some(logger)]), Some(14));
+        assert_eq!(in_flight(&[Some(generator), Some(worker), None]), None);
+        metrics_tx[METRICS_GENERATOR].testing_send_all(vec![generator], true);
+        metrics_tx[METRICS_WORKER].testing_send_all(vec![worker], true);
+        metrics_tx[METRICS_LOGGER].testing_send_all(vec![logger], true);
+        tenants_tx.testing_send_all(vec![], true);
+
+        graph.start();
+        sleep(Duration::from_millis(100));
+        graph.request_shutdown();
+        graph.block_until_stopped(Duration::from_secs(1))?;
+
+        // the count stays over the bound, which raises one alert rather than one per audit
+        let state = crate::state_lock::settled(&probe).expect("reconciler ran");
+        assert_eq!((state.in_flight_max, state.in_flight_alerts, state.in_flight_over), (14, 1, true));
+        Ok(())
+    }
+
+    #[test]
+    fn test_fill_alert() -> Result<(), Box<dyn Error>> {
+        let mut graph = GraphBuilder::for_testing().build(MainArg {
+            reconcile_ms: 10,
+            fill_alert: vec![crate::backpressure::parse_fill_rule("classified=90/5").expect("valid rule")],
+            ..Default::default()
+        });
+        let (metrics_tx, metrics_rx) = graph.channel_builder().build_channel_bundle::<CounterSample, METRICS_LANES>();
+        let (tenants_tx, tenants_rx) = graph.channel_builder().build();
+        let (_status_request_tx, status_rx) = graph.channel_builder().build();
+        let (status_tx, _status_reply_rx) = graph.channel_builder().build();
+
+        let state = new_state();
+        let probe = state.clone();
+        graph.actor_builder()
+            .with_name("UnitTest")
+            .build(move |context| internal_behavior(context, metrics_rx.clone(), tenants_rx.clone(), status_rx.clone(), status_tx.clone(), state.clone()), SoloAct);
+
+        // the logger's classified input sampled 60 of 64 full over six seconds, its rejects input nearly empty
+        let start = Instant::now();
+        let full = |secs| CounterSample { at: start + Duration::from_secs(secs), ..CounterSample::new(0, 0, 61) }
+            .with_depths([ChannelDepth { items: 60, capacity: 64 }, ChannelDepth { items: 1, capacity: 64 }]);
+        metrics_tx[METRICS_LOGGER].testing_send_all(vec![full(0), full(3), full(6), full(9)], true);
+        metrics_tx[METRICS_GENERATOR].testing_send_all(vec![], true);
+        metrics_tx[METRICS_WORKER].testing_send_all(vec![], true);
+        tenants_tx.testing_send_all(vec![], true);
+
+        graph.start();
+        sleep(Duration::from_millis(100));
+        graph.request_shutdown();
+        graph.block_until_stopped(Duration::from_secs(1))?;
+
+        // one alert once the hold passed, however long the channel then stays full
+        let state = crate::state_lock::settled(&probe).expect("reconciler ran");
+        assert_eq!(state.fill_alerts, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_failure_budget_turns_chaos_off() -> Result<(), Box<dyn Error>> {
+        let budget = crate::failure::parse_failure_budget("restarts=5,drops=2").expect("valid");
+        let chaos = budget.chaos.clone();
+        let mut graph = GraphBuilder::for_testing().build(MainArg {
+            reconcile_ms: 10,
+            failure_budget: Some(budget),
+            ..Default::default()
+        });
+        let (metrics_tx, metrics_rx) = graph.channel_builder().build_channel_bundle::<CounterSample, METRICS_LANES>();
+        let (tenants_tx, tenants_rx) = graph.channel_builder().build();
+        let (_status_request_tx, status_rx) = graph.channel_builder().build();
+        let (status_tx, _status_reply_rx) = graph.channel_builder().build();
+
+        let state = new_state();
+        let probe = state.clone();
+        graph.actor_builder()
+            .with_name("UnitTest")
+            .build(move |context| internal_behavior(context, metrics_rx.clone(), tenants_rx.clone(), status_rx.clone(), status_tx.clone(), state.clone()), SoloAct);
+
+        // two restarts are within the allowance, but the worker and the logger each dropped a showstopper
+        let worker = CounterSample::new(10, 10, 0).with_starts(3).with_dropped(1);
+        let logger = CounterSample::new(9, 9, 0).with_dropped(1);
+        metrics_tx[METRICS_GENERATOR].testing_send_all(vec![CounterSample::new(0, 10, 0).with_starts(1)], true);
+        metrics_tx[METRICS_WORKER].testing_send_all(vec![worker], true);
+        metrics_tx[METRICS_LOGGER].testing_send_all(vec![logger], true);
+        tenants_tx.testing_send_all(vec![], true);
+
+        graph.start();
+        sleep(Duration::from_millis(100));
+        graph.request_shutdown();
+        graph.block_until_stopped(Duration::from_secs(1))?;
+
+        assert!(!chaos.is_on());
+        let state = crate::state_lock::settled(&probe).expect("reconciler ran");
+        assert!(state.budget_spent);
+        Ok(())
+    }
+}
@@ -0,0 +1,169 @@
+use steady_state::*;
+use steady_state::actor_builder::{ActorBuilder, ScheduleAs};
+use crate::event_time::BeatTime;
+use crate::state_lock::lock_state;
+use crate::timing::Envelope;
+use crate::wiring::{RobustActor, Wiring};
+
+/// Persistent state of the router, so routes are never reused across restarts.
+pub struct RouterState {
+    /// Values routed so far; the next one gets route `routed + 1`.
+    pub(crate) routed: u64,
+    /// Values sent to each worker, by shard.
+    pub(crate) per_shard: Vec<u64>,
+    pub(crate) restart_count: u64,
+}
+
+/// The shard of the pool that handles `value`.
+pub(crate) fn shard_of(value: u64, workers: usize) -> usize {
+    (value % workers as u64) as usize
+}
+
+/// Entry point for the router actor.
+/// Splits the generator's values over the `--workers` pool by `value % N`. Each value keeps
+/// its place in the stream as its route. The router releases one value per beat, while the
+/// heartbeat hands every beat to every worker itself, so the pool as a whole is paced as the
+/// single worker is.
+pub async fn run(
+    actor: SteadyActorShadow,
+    heartbeat_rx: SteadyRx<BeatTime>,
+    generated_rx: SteadyRx<Envelope<u64>>,
+    shard_tx: Vec<SteadyTx<Envelope<u64>>>,
+    state: SteadyState<RouterState>,
+) -> Result<(), Box<dyn Error>> {
+    // The spotlight takes a fixed number of channels, so the per-worker ones go unmonitored.
+    let actor = actor.into_spotlight([&heartbeat_rx, &generated_rx], []);
+    if actor.use_internal_behavior {
+        internal_behavior(actor, heartbeat_rx, generated_rx, shard_tx, state).await
+    } else {
+        actor.simulated_behavior(vec!(&heartbeat_rx, &generated_rx)).await
+    }
+}
+
+/// The router as a pipeline stage, with `--workers` above 1.
+pub struct Router;
+
+impl RobustActor for Router {
+    fn name(&self) -> &'static str {
+        crate::NAME_ROUTER
+    }
+    fn inputs(&self) -> &'static [&'static str] {
+        &["heartbeat", "generated"]
+    }
+    fn outputs(&self) -> &'static [&'static str] {
+        &["shard"]
+    }
+    fn enabled(&self, args: &crate::MainArg) -> bool {
+        args.workers > 1
+    }
+    fn build(&self, builder: ActorBuilder, wiring: &Wiring, schedule: ScheduleAs<'_>) {
+        let (heartbeat_rx, generated_rx) = (wiring.heartbeat.rx.clone(), wiring.generated.rx.clone());
+        let shard_tx: Vec<_> = wiring.pool.iter().map(|links| links.generated.tx.clone()).collect();
+        let state = wiring.state.router.clone();
+        builder.build(move |context| {
+            crate::priority::apply(&context, crate::NAME_ROUTER);
+            run(context, heartbeat_rx.clone(), generated_rx.clone(), shard_tx.clone(), state.clone())
+        }, schedule);
+    }
+}
+
+async fn internal_behavior<A: SteadyActor>(
+    mut actor: A,
+    heartbeat: SteadyRx<BeatTime>,
+    generated: SteadyRx<Envelope<u64>>,
+    shard_tx: Vec<SteadyTx<Envelope<u64>>>,
+    state: SteadyState<RouterState>,
+) -> Result<(), Box<dyn Error>> {
+    let paced = !actor.args::<crate::MainArg>().expect("unable to downcast").no_heartbeat;
+    let workers = shard_tx.len();
+    let Some(mut state) = lock_state(&actor, crate::NAME_ROUTER, &state, || RouterState {
+        routed: 0,
+        per_shard: vec![0; workers],
+        restart_count: 0,
+    }).await else {
+        return Ok(());
+    };
+    state.restart_count += 1;
+    info!("Router starting (restart #{}) with {} workers, routed: {}", state.restart_count, workers, state.routed);
+
+    let mut heartbeat = heartbeat.lock().await;
+    let mut generated = generated.lock().await;
+    let mut shard = Vec::with_capacity(workers);
+    for tx in &shard_tx {
+        shard.push(tx.lock().await);
+    }
+    while actor.is_running(|| i!(!paced || heartbeat.is_closed_and_empty())
+                              && i!(generated.is_closed_and_empty())
+                              && i!(shard.iter_mut().all(|tx| tx.mark_closed()))) {
+        let clean = await_for_all!(async { !paced || actor.wait_avail(&mut heartbeat, 1).await },
+                                   actor.wait_avail(&mut generated, 1));
+
+        // Once nothing is left to route, beats still arriving during shutdown release nothing.
+        if generated.is_closed_and_empty() {
+            while actor.try_take(&mut heartbeat).is_some() {}
+            continue;
+        }
+        // Peek, send to the shard, and only then take the value and its beat, so a restart in
+        // between sends the value again under the same route rather than losing it.
+        while let Some(&envelope) = actor.try_peek(&mut generated) {
+            let to = shard_of(envelope.payload, workers);
+            // Without a beat the value waits, unless shutdown has cut the beats off.
+            if paced && actor.avail_units(&mut heartbeat) == 0 && clean {
+                break;
+            }
+            if actor.vacant_units(&mut shard[to]) == 0 {
+                await_for_all!(actor.wait_vacant(&mut shard[to], 1));
+                break;
+            }
+            let route = state.routed + 1;
+            if !actor.try_send(&mut shard[to], envelope.with_route(route)).is_sent() {
+                break;
+            }
+            if paced {
+                actor.try_take(&mut heartbeat);
+            }
+            actor.try_take(&mut generated);
+            state.routed = route;
+            state.per_shard[to] += 1;
+            trace!("Router sent value {} to worker {} as route {}", envelope.payload, to, route);
+            if paced {
+                break; // one value per beat
+            }
+        }
+    }
+    info!("Router shutting down. Routed: {}, per worker: {:?}", state.routed, state.per_shard);
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod router_tests {
+    use crate::state_lock::settled;
+    use super::*;
+
+    #[test]
+    fn test_router_shards_values_by_route() -> Result<(), Box<dyn Error>> {
+        let mut graph = GraphBuilder::for_testing().build(crate::MainArg { workers: 2, ..Default::default() });
+        let (heartbeat_tx, heartbeat_rx) = graph.channel_builder().build();
+        let (generated_tx, generated_rx) = graph.channel_builder().build();
+        let (shard_tx, shard_rx): (Vec<_>, Vec<_>) = (0..2).map(|_| graph.channel_builder().build::<Envelope<u64>>()).unzip();
+        let state = new_state();
+        let probe = state.clone();
+        graph.actor_builder().with_name("UnitTest")
+            .build(move |context| internal_behavior(context, heartbeat_rx.clone(), generated_rx.clone(),
+                                                    shard_tx.iter().map(|tx| tx.clone()).collect(), state.clone()), SoloAct);
+
+        generated_tx.testing_send_all([4, 7, 9, 10].map(Envelope::unstamped).to_vec(), true);
+        heartbeat_tx.testing_send_all([1, 2, 3, 4].map(BeatTime::untimed).to_vec(), true);
+        graph.start();
+        std::thread::sleep(Duration::from_millis(200));
+        graph.request_shutdown();
+        graph.block_until_stopped(Duration::from_secs(1))?;
+
+        let routed = |rx: &LazySteadyRx<Envelope<u64>>| rx.testing_take_all().into_iter().map(|e| (e.payload, e.route)).collect::<Vec<_>>();
+        assert_eq!(routed(&shard_rx[0]), [(4, 1), (10, 4)]);
+        assert_eq!(routed(&shard_rx[1]), [(7, 2), (9, 3)]);
+        let state = settled(&probe).expect("router state");
+        assert_eq!((state.routed, state.per_shard.as_slice()), (4, [2, 2].as_slice()));
+        Ok(())
+    }
+}
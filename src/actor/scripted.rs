@@ -0,0 +1,89 @@
+use std::collections::VecDeque;
+use steady_state::*;
+
+/// A test source that sends values on a schedule, standing in for an upstream actor.
+/// Describing a test's input as `emit 5 at t+100ms, emit 7 at t+250ms` keeps the timing
+/// in one place, rather than spread over `testing_send_all` calls and sleeps.
+/// Times count from the start of the source, which is when the graph starts.
+#[derive(Clone, Debug)]
+pub(crate) struct ScriptedSource<T> {
+    emissions: Vec<(Duration, T)>,
+    close: bool,
+}
+
+impl<T: Clone + Send + Sync + 'static> ScriptedSource<T> {
+    pub(crate) fn new() -> Self {
+        ScriptedSource { emissions: Vec::new(), close: false }
+    }
+
+    /// Sends `value` `ms` milliseconds after the start. Emissions go out in time order,
+    /// and those due at the same time in the order they were added.
+    pub(crate) fn emit_at(mut self, ms: u64, value: T) -> Self {
+        self.emissions.push((Duration::from_millis(ms), value));
+        self
+    }
+
+    pub(crate) fn emit_all_at(mut self, ms: u64, values: impl IntoIterator<Item = T>) -> Self {
+        self.emissions.extend(values.into_iter().map(|value| (Duration::from_millis(ms), value)));
+        self
+    }
+
+    /// Closes the channel after the last emission, as a finished upstream would.
+    pub(crate) fn then_close(mut self) -> Self {
+        self.close = true;
+        self
+    }
+
+    /// Adds the source to the graph as an actor named `name` sending on `tx`.
+    pub(crate) fn build(mut self, graph: &mut Graph, name: &'static str, tx: LazySteadyTx<T>) {
+        self.emissions.sort_by_key(|(at, _)| *at);
+        graph.actor_builder().with_name(name)
+            .build(move |context| run(context, tx.clone(), self.clone()), SoloAct);
+    }
+}
+
+async fn run<A: SteadyActor, T>(mut actor: A, tx: SteadyTx<T>, script: ScriptedSource<T>) -> Result<(), Box<dyn Error>> {
+    let started = Instant::now();
+    let mut tx = tx.lock().await;
+    let mut emissions = VecDeque::from(script.emissions);
+    while actor.is_running(|| tx.mark_closed()) {
+        let Some(&(at, _)) = emissions.front() else {
+            if script.close {
+                tx.mark_closed();
+            }
+            await_for_all!(actor.wait_shutdown());
+            continue;
+        };
+        let due = at.saturating_sub(started.elapsed());
+        if !due.is_zero() {
+            await_for_any!(async { actor.wait(due).await; false }, actor.wait_shutdown());
+            continue;
+        }
+        let (_, value) = emissions.pop_front().expect("front was checked");
+        actor.send_async(&mut tx, value, SendSaturation::AwaitForRoom).await;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod scripted_tests {
+    use std::thread::sleep;
+    use super::*;
+
+    #[test]
+    fn test_emits_on_schedule() -> Result<(), Box<dyn Error>> {
+        let mut graph = GraphBuilder::for_testing().build(crate::MainArg::default());
+        let (tx, rx) = graph.channel_builder().build::<u64>();
+        ScriptedSource::new().emit_at(250, 7).emit_at(0, 5).emit_all_at(250, [8, 9]).then_close()
+            .build(&mut graph, "Script", tx);
+
+        graph.start();
+        sleep(Duration::from_millis(100));
+        assert_eq!(rx.testing_take_all(), [5]);
+        sleep(Duration::from_millis(300));
+        graph.request_shutdown();
+        graph.block_until_stopped(Duration::from_secs(1))?;
+        assert_eq!(rx.testing_take_all(), [7, 8, 9]);
+        Ok(())
+    }
+}
@@ -0,0 +1,392 @@
+use std::io::{self, BufRead, BufReader, Read};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError};
+use std::sync::Arc;
+use steady_state::*;
+use crate::state_lock::lock_state;
+use crate::actor::reconciler::{CounterSample, SamplePacer, METRICS_GENERATOR};
+use crate::quota::{OverQuota, Quotas};
+use crate::tenant;
+use crate::timing::Envelope;
+use crate::trace::{self, TraceSample};
+use steady_state::actor_builder::{ActorBuilder, ScheduleAs};
+use crate::startup::StartGate;
+use crate::wiring::{RobustActor, Wiring};
+
+/// Lines read from clients that may wait for the actor. A connection whose line finds this full
+/// waits, reading nothing more, so TCP flow control holds its client back until the pipeline catches up.
+const INGEST_QUEUE: usize = 64;
+
+/// Longest line a client may send, in bytes. A value is at most 20 digits; this leaves room for
+/// comments while keeping what one connection can make the source buffer small.
+const MAX_LINE: usize = 1024;
+
+/// Connections served at once, each on a thread of its own. Past it a new client is closed at once.
+const MAX_CONNECTIONS: u64 = 64;
+
+/// How often the listener and each connection look to see whether the source is closing, and
+/// how often the actor looks for lines when none are waiting.
+const POLL: Duration = Duration::from_millis(20);
+
+/// One line from a client: its value, or the text that was not one.
+struct Line {
+    client: SocketAddr,
+    value: Result<u64, String>,
+}
+
+/// The listening socket and its connections, each on a thread of its own, handing their lines
+/// to the actor through one bounded queue.
+struct TcpIngest {
+    lines: Receiver<Line>,
+    /// Set to stop accepting and close every connection; the queue ends once they are all gone.
+    closing: Arc<AtomicBool>,
+    /// Connections accepted so far, and those still open.
+    connections: Arc<AtomicU64>,
+    open: Arc<AtomicU64>,
+    addr: SocketAddr,
+}
+
+impl TcpIngest {
+    /// Binds here so a bad or busy address fails the actor start, not a background thread.
+    fn start(addr: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let (lines_tx, lines) = mpsc::sync_channel(INGEST_QUEUE);
+        let ingest = TcpIngest {
+            lines,
+            closing: Arc::new(AtomicBool::new(false)),
+            connections: Arc::new(AtomicU64::new(0)),
+            open: Arc::new(AtomicU64::new(0)),
+            addr: listener.local_addr()?,
+        };
+        let (closing, connections, open) = (ingest.closing.clone(), ingest.connections.clone(), ingest.open.clone());
+        std::thread::Builder::new().name("tcp-ingest".to_string()).spawn(move || {
+            while !closing.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((stream, client)) if open.load(Ordering::SeqCst) >= MAX_CONNECTIONS => {
+                        warn!("TCP source refused {}, already serving {} connections", client, MAX_CONNECTIONS);
+                        let _ = stream.shutdown(Shutdown::Both);
+                    }
+                    Ok((stream, client)) => {
+                        connections.fetch_add(1, Ordering::SeqCst);
+                        open.fetch_add(1, Ordering::SeqCst);
+                        let (lines_tx, closing, still_open) = (lines_tx.clone(), closing.clone(), open.clone());
+                        let read = std::thread::Builder::new().name(format!("tcp-{}", client)).spawn(move || {
+                            if let Err(e) = read_lines(stream, client, &lines_tx, &closing) {
+                                warn!("TCP source connection from {} failed: {}", client, e);
+                            }
+                            still_open.fetch_sub(1, Ordering::SeqCst);
+                        });
+                        if let Err(e) = read {
+                            warn!("TCP source could not serve {}, closing it: {}", client, e);
+                            open.fetch_sub(1, Ordering::SeqCst);
+                        }
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => std::thread::sleep(POLL),
+                    Err(e) => {
+                        warn!("TCP source accept failed: {}", e);
+                        std::thread::sleep(POLL);
+                    }
+                }
+            }
+        })?;
+        Ok(ingest)
+    }
+
+    fn close(&self) {
+        self.closing.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Hands on each line of one connection until the client closes it or the source closes.
+/// A line cut short by the read timeout is kept and finished by the next read. A line over
+/// `MAX_LINE` is never held whole: it is handed on as invalid and the rest of it skipped.
+fn read_lines(stream: TcpStream, client: SocketAddr, lines: &SyncSender<Line>, closing: &AtomicBool) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(POLL))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = Vec::new();
+    let mut skipping = false;
+    while !closing.load(Ordering::SeqCst) {
+        let room = (MAX_LINE + 1 - line.len()) as u64;
+        match reader.by_ref().take(room).read_until(b'\n', &mut line) {
+            Ok(0) => break,
+            // Past MAX_LINE with no end in sight.
+            Ok(_) if line.len() > MAX_LINE && line.last() != Some(&b'\n') => {
+                if !skipping {
+                    skipping = true;
+                    let value = Err(format!("{}... (over {} bytes)", String::from_utf8_lossy(&line[..20]), MAX_LINE));
+                    if lines.send(Line { client, value }).is_err() {
+                        break;
+                    }
+                }
+                line.clear();
+            }
+            // The end of a line too long to take.
+            Ok(_) if skipping => {
+                skipping = line.last() != Some(&b'\n');
+                line.clear();
+            }
+            Ok(_) => {
+                let text = String::from_utf8_lossy(&line);
+                let text = text.trim();
+                if !text.is_empty() && !text.starts_with('#') {
+                    let value = text.parse().map_err(|_| text.to_string());
+                    // Blocks while the queue is full: the backpressure the client feels.
+                    if lines.send(Line { client, value }).is_err() {
+                        break;
+                    }
+                }
+                line.clear();
+            }
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    let _ = stream.shutdown(Shutdown::Both);
+    Ok(())
+}
+
+/// TcpSourceState holds state for the TCP source actor.
+/// The listener lives here so the port is bound once and its clients stay connected across
+/// restarts, and so does the line being handed on.
+pub struct TcpSourceState {
+    /// Values passed on to the worker, and the bytes they came to.
+    pub(crate) accepted: u64,
+    pub(crate) accepted_bytes: u64,
+    /// Lines that were not a plain unsigned number; dropped.
+    pub(crate) invalid: u64,
+    /// `--tenant-quota` by client address.
+    pub(crate) quotas: Quotas,
+    /// Connections accepted, as of the last start or shutdown.
+    pub(crate) connections: u64,
+    pub(crate) restart_count: u64,
+    ingest: Option<TcpIngest>,
+    /// Read but not yet in the generator channel.
+    pending: Option<Line>,
+    /// Every connection has closed since shutdown began, and every line they read is handed on.
+    drained: bool,
+}
+
+/// Entry point for the TCP source actor.
+/// Stands in for the generator, feeding newline-delimited values from TCP clients into its channel.
+pub async fn run(
+    actor: SteadyActorShadow,
+    generated_tx: SteadyTx<Envelope<u64>>,
+    metrics_tx: SteadyTx<CounterSample>,
+    addr: SocketAddr,
+    gate: StartGate,
+    state: SteadyState<TcpSourceState>,
+) -> Result<(), Box<dyn Error>> {
+    let actor = actor.into_spotlight([], [&generated_tx, &metrics_tx]);
+    if actor.use_internal_behavior {
+        gate.wait(&actor).await;
+        internal_behavior(actor, generated_tx, metrics_tx, addr, state).await
+    } else {
+        actor.simulated_behavior(vec!(&generated_tx)).await
+    }
+}
+
+/// The TCP source as a pipeline stage, in the generator's place with `--tcp-listen`.
+pub struct TcpSource;
+
+impl RobustActor for TcpSource {
+    fn name(&self) -> &'static str {
+        crate::NAME_TCP_SOURCE
+    }
+    fn inputs(&self) -> &'static [&'static str] {
+        &[]
+    }
+    fn outputs(&self) -> &'static [&'static str] {
+        &["generated", "metrics"]
+    }
+    fn enabled(&self, args: &crate::MainArg) -> bool {
+        crate::wiring::source(args) == crate::wiring::Source::Tcp
+    }
+    fn depends_on(&self, _args: &crate::MainArg) -> &'static [&'static str] {
+        &[crate::NAME_LOGGER] // values are taken in once the sink is connected
+    }
+    fn build(&self, builder: ActorBuilder, wiring: &Wiring, schedule: ScheduleAs<'_>) {
+        let Some(addr) = wiring.args.tcp_listen else {
+            return;
+        };
+        let (generated_tx, metrics_tx) = (wiring.generated.tx.clone(), wiring.metrics_tx[METRICS_GENERATOR].clone());
+        let (gate, state) = (wiring.start_gate(self), wiring.state.tcp_source.clone());
+        builder.build(move |context| {
+            crate::priority::apply(&context, crate::NAME_TCP_SOURCE);
+            run(context, generated_tx.clone(), metrics_tx.clone(), addr, gate.clone(), state.clone())
+        }, schedule);
+    }
+}
+
+async fn internal_behavior<A: SteadyActor>(
+    mut actor: A,
+    generated_tx: SteadyTx<Envelope<u64>>,
+    metrics_tx: SteadyTx<CounterSample>,
+    addr: SocketAddr,
+    state: SteadyState<TcpSourceState>,
+) -> Result<(), Box<dyn Error>> {
+    let args = actor.args::<crate::MainArg>().expect("unable to downcast");
+    let sample = TraceSample::from_args(args);
+    let Some(mut state) = lock_state(&actor, crate::NAME_TCP_SOURCE, &state, || TcpSourceState {
+        accepted: 0,
+        accepted_bytes: 0,
+        invalid: 0,
+        quotas: Quotas::from_args(args),
+        connections: 0,
+        restart_count: 0,
+        ingest: None,
+        pending: None,
+        drained: false,
+    }).await else {
+        return Ok(());
+    };
+    state.restart_count += 1;
+    if state.ingest.is_none() {
+        let ingest = TcpIngest::start(addr)?;
+        info!("TCP source listening on {}", ingest.addr);
+        state.ingest = Some(ingest);
+    }
+    info!(
+        "TCP source starting (restart #{}) with accepted: {}, invalid: {}, connections: {}, holding a line: {}",
+        state.restart_count, state.accepted, state.invalid, state.connections, state.pending.is_some()
+    );
+
+    let mut generated_tx = generated_tx.lock().await;
+    let mut metrics_tx = metrics_tx.lock().await;
+    let mut pacer = SamplePacer::default();
+
+    // Shutdown closes the connections, then waits until every line they had read is handed on.
+    while actor.is_running(|| i!(state.drained && state.pending.is_none())
+                              && i!(generated_tx.mark_closed())
+                              && i!(metrics_tx.mark_closed())) {
+        let stopping = actor.is_liveliness_stop_requested();
+        if stopping {
+            state.ingest.as_ref().expect("listener bound").close();
+        }
+        if state.pending.is_none() {
+            match state.ingest.as_ref().expect("listener bound").lines.try_recv() {
+                Ok(line) => state.pending = Some(line),
+                Err(TryRecvError::Empty) if stopping => {
+                    actor.wait(POLL).await;
+                    continue;
+                }
+                Err(TryRecvError::Empty) => {
+                    await_for_any!(actor.wait_periodic(POLL), actor.wait_shutdown());
+                    continue;
+                }
+                // Only once closed: the listener holds the queue open until then.
+                Err(TryRecvError::Disconnected) => {
+                    state.drained = true;
+                    actor.wait(POLL).await;
+                    continue;
+                }
+            }
+        }
+        let Some(Line { client, value }) = state.pending.as_ref() else {
+            continue;
+        };
+        let (client, value) = (*client, value.clone());
+
+        match value {
+            Ok(value) => {
+                // The client's address names the tenant, as the gRPC source does.
+                let tenant_id = tenant::id(&client.ip().to_string());
+                // Over its quota: throttling holds the line, and with it the client, rejecting drops it.
+                if let Err(mut wait) = state.quotas.admit(tenant_id, Instant::now()) {
+                    if state.quotas.action == OverQuota::Reject {
+                        state.quotas.rejected += 1;
+                        state.pending = None;
+                        trace!("TCP source rejected a value from {} over quota", client);
+                        continue;
+                    }
+                    state.quotas.throttled += 1;
+                    loop {
+                        actor.wait(wait).await;
+                        match state.quotas.admit(tenant_id, Instant::now()) {
+                            Ok(()) => break,
+                            Err(again) => wait = again,
+                        }
+                    }
+                }
+                // Wait for room rather than drop: the line stays unread behind it until there is some.
+                await_for_all!(actor.wait_vacant(&mut generated_tx, 1));
+                let envelope = Envelope::new(value)
+                    .with_trace(sample.map_or(0, |s| s.trace_id(state.accepted)))
+                    .with_tenant(tenant_id);
+                if !actor.try_send(&mut generated_tx, envelope).is_sent() {
+                    continue;
+                }
+                state.accepted += 1;
+                state.accepted_bytes += envelope.wire_bytes();
+                trace::event("TCP_SOURCE", "accepted", &envelope);
+            }
+            Err(text) => {
+                warn!("TCP source dropping a line from {} that is not a value: {:?}", client, text);
+                state.invalid += 1;
+            }
+        }
+        state.pending = None;
+
+        if pacer.is_due() {
+            let sample = CounterSample::new(state.accepted, state.accepted, 0)
+                .with_starts(state.restart_count)
+                .with_bytes(state.accepted_bytes, 0);
+            let _ = actor.try_send(&mut metrics_tx, sample);
+        }
+    }
+
+    let ingest = state.ingest.as_ref().expect("listener bound");
+    let (connections, open) = (ingest.connections.load(Ordering::SeqCst), ingest.open.load(Ordering::SeqCst));
+    state.connections = connections;
+    info!(
+        "TCP source shutting down. Accepted: {}, invalid: {}, connections: {} ({} still open), over quota rejected/throttled: {}",
+        state.accepted, state.invalid, connections, open, state.quotas.summary()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tcp_source_tests {
+    use std::io::{Read, Write};
+    use crate::state_lock::settled;
+    use super::*;
+
+    #[test]
+    fn test_tcp_source_feeds_lines_and_closes_clients() -> Result<(), Box<dyn Error>> {
+        // A port free a moment ago, as the actor reports the one it bound only in its log.
+        let addr = TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+        let mut graph = GraphBuilder::for_testing().build(crate::MainArg { tcp_listen: Some(addr), ..Default::default() });
+        let (generated_tx, generated_rx) = graph.channel_builder().build::<Envelope<u64>>();
+        let (metrics_tx, _metrics_rx) = graph.channel_builder().build::<CounterSample>();
+        let state = new_state();
+        let probe = state.clone();
+        graph.actor_builder().with_name("UnitTest")
+            .build(move |context| internal_behavior(context, generated_tx.clone(), metrics_tx.clone(), addr, state.clone()), SoloAct);
+        graph.start();
+
+        let mut client = (0..50).find_map(|_| TcpStream::connect(addr).ok().or_else(|| {
+            std::thread::sleep(Duration::from_millis(10));
+            None
+        })).expect("source listening");
+        // a line over MAX_LINE is one invalid line, and the last value arrives in two writes
+        client.write_all(b"3\n\nfizz\n")?;
+        client.write_all(&[b'9'; 3 * MAX_LINE])?;
+        client.write_all(b"\n# note\n5\n1")?;
+        std::thread::sleep(Duration::from_millis(50));
+        client.write_all(b"5\n")?;
+        std::thread::sleep(Duration::from_millis(200));
+        graph.request_shutdown();
+        graph.block_until_stopped(Duration::from_secs(1))?;
+
+        // shutdown closed the connection rather than leaving the client hanging
+        client.set_read_timeout(Some(Duration::from_secs(1)))?;
+        assert_eq!(client.read(&mut [0u8; 8])?, 0);
+        let received: Vec<u64> = generated_rx.testing_take_all().into_iter().map(|e| e.payload).collect();
+        assert_eq!(received, [3, 5, 15]);
+        let state = settled(&probe).expect("tcp source state");
+        assert_eq!((state.accepted, state.invalid, state.connections), (3, 2, 1));
+        Ok(())
+    }
+}
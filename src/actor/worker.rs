@@ -1,18 +1,48 @@
+use std::cmp::Reverse;
+use std::collections::VecDeque;
 use steady_state::*;
+use serde_json::json;
+use crate::ack::{AckOutcome, SourceAck};
+use crate::actor::dead_letter::{DeadLetter, Poisoned};
+use crate::actor::enricher::{LookupReply, LookupRequest};
+use crate::deadline::{DeadlineAction, DeadlineCounts, ProcessingDeadline};
+use crate::event_time::BeatTime;
+use crate::events;
+use crate::fault::FaultPlan;
+use crate::restart::{RestartCauses, StartTimes};
+use crate::stage_time::{IterationClock, StageTime};
+use crate::state_lock::lock_state;
+use crate::persistence;
+use crate::enrich::{Derived, Enricher};
+use crate::validation::{RejectReason, RejectedValue, ValidationRules};
+use crate::actor::reconciler::{backlog_bytes, ChannelDepth, CounterSample, SamplePacer, METRICS_WORKER};
+use crate::checkpoint::{is_barrier, WorkerCheckpoint};
+use crate::drain::{persist_residue, DrainDeadline};
+use crate::timing::Envelope;
+use crate::trace;
+use crate::urgency::{Aging, WaitingSlot};
+use steady_state::actor_builder::{ActorBuilder, ScheduleAs};
+use crate::startup::StartGate;
+use crate::wiring::{RobustActor, Wiring};
+use crate::classify::{Classify, FizzBuzz, Payload};
+use crate::rules::RuleMatches;
 
 /// FizzBuzzMessage is a compact enum for FizzBuzz logic.
 /// The #[repr(u64)] ensures all variants fit in 8 bytes for efficient channel transport.
 #[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
 #[repr(u64)]
-pub(crate) enum FizzBuzzMessage {
+pub enum FizzBuzzMessage {
     #[default]
     FizzBuzz = 15,         // Discriminant is 15 - for multiples of 15
     Fizz = 3,              // Discriminant is 3 - for multiples of 3 (not 5)
     Buzz = 5,              // Discriminant is 5 - for multiples of 5 (not 3)
     Value(u64),            // For all other values
+    Barrier(u64),          // Checkpoint barrier carrying its heartbeat beat, never a record
+    Matched(RuleMatches),  // The `--rule` rules a value matched, when they replace Fizz and Buzz
 }
 
 impl FizzBuzzMessage {
+    /// The built-in rules; `RuleSet::classify` applies any `--rule` instead.
     pub fn new(value: u64) -> Self {
         match (value % 3, value % 5) {
             (0, 0) => FizzBuzzMessage::FizzBuzz,    // Multiple of 15
@@ -26,93 +56,798 @@ impl FizzBuzzMessage {
 /// WorkerState holds state for the Worker actor.
 /// All fields are preserved across panics, ensuring
 /// that no data is lost and the worker can resume exactly where it left off.
-pub(crate) struct WorkerState {
+pub struct WorkerState {
     pub(crate) heartbeats_processed: u64,
     pub(crate) values_processed: u64,
     pub(crate) messages_sent: u64,
+    /// Bytes of the messages and barrier markers sent to the logger.
+    pub(crate) bytes_sent: u64,
+    /// Values diverted to the rejects side channel by the validation rules.
+    pub(crate) values_rejected: u64,
+    /// Checkpoint barriers forwarded to the logger, and the beat of the latest one.
+    /// Remembering the beat keeps a restart from forwarding the same barrier twice.
+    pub(crate) barriers_sent: u64,
+    pub(crate) last_barrier: u64,
+    /// Wall time of the latest heartbeat, given to the values it releases as their event time;
+    /// 0 while heartbeats are untimed. Kept so a value retried after a restart keeps its beat's time.
+    pub(crate) beat_micros: u64,
+    /// Batches flushed to the logger in `--batch-size` mode.
+    pub(crate) batches_flushed: u64,
+    /// Values dropped as showstoppers; counted in `values_processed` too.
+    pub(crate) showstoppers_dropped: u64,
+    /// Generator values still waiting when the drain deadline passed, moved to the backlog file.
+    pub(crate) residue: u64,
+    /// Waits that ran past `--worker-stall-ms` without all of the worker's inputs ready.
+    pub(crate) stalls: u64,
+    /// The latest beat taken, and beats that never arrived or arrived again, from gaps
+    /// and repeats in the beat numbers.
+    pub(crate) last_beat: Option<u64>,
+    pub(crate) beats_missed: u64,
+    pub(crate) beats_repeated: u64,
+    /// Beats a `--workers` replica spent with no value of its shard waiting.
+    pub(crate) beats_idle: u64,
+    /// Values picked up past `--processing-deadline-ms`, by the action taken on them.
+    pub(crate) overdue: DeadlineCounts,
+    /// Time per loop iteration spent processing and spent waiting on the channels.
+    pub(crate) time: StageTime,
+    /// Acknowledgements owed to the source with `--ack-after-commit` and not sent yet.
+    /// Kept here so a restart still sends them, as the values they answer are already taken.
+    pub(crate) acks: VecDeque<SourceAck>,
+    /// Values taken off the input under `--priority-window` and not sent yet. Kept here so a
+    /// restart still sends them, as they are no longer in the channel.
+    pub(crate) waiting: WaitingSlot,
+    /// Id of the latest lookup sent to the enricher. Never reused, even across restarts, so a
+    /// reply that turns up after its request timed out cannot be taken for a later one.
+    pub(crate) lookup_id: u64,
+    /// Lookups the enricher answered in time, and those classified without its answer.
+    pub(crate) lookups_answered: u64,
+    pub(crate) lookups_timed_out: u64,
     pub(crate) restart_count: u64,
+    /// Restarts by cause; `restart_count` also counts the first start.
+    pub(crate) restarts: RestartCauses,
+    /// Cold and warm starts, and how soon each got its first message out.
+    pub(crate) starts: StartTimes,
+    /// The faults injected into this actor, its demonstration panic among them, fixed at first start.
+    pub(crate) faults: FaultPlan,
+}
+
+impl WorkerState {
+    /// Sequence number for the next message to the logger. Derived from counters that
+    /// only move once a send succeeds, so a message re-sent after a restart keeps its number.
+    fn next_seq(&self) -> u64 {
+        self.messages_sent + self.barriers_sent + 1
+    }
+
+    /// Checks a beat taken from the heartbeat against the one before it. Every beat still
+    /// releases its value; a gap or a repeat is only counted and reported.
+    fn note_beat(&mut self, beat: u64) {
+        match self.last_beat {
+            Some(last) if beat <= last => {
+                self.beats_repeated += 1;
+                trace!("Worker got beat {} again after beat {}", beat, last);
+            }
+            Some(last) if beat > last + 1 => {
+                let missed = beat - last - 1;
+                self.beats_missed += missed;
+                warn!("Worker missed {} beat(s) between {} and {}", missed, last, beat);
+                events::emit("alert", json!({"kind": "beats_missed", "after": last, "beat": beat, "missed": missed}));
+            }
+            _ => {}
+        }
+        self.last_beat = Some(self.last_beat.map_or(beat, |last| last.max(beat)));
+    }
+
+    /// Owes the source an acknowledgement for the value just taken. Everything taken is counted
+    /// in `values_processed` or `residue`, so together they number it the way the source did.
+    fn ack(&mut self, outcome: AckOutcome) {
+        let seq = self.values_processed + self.residue;
+        self.acks.push_back(SourceAck { seq, outcome });
+    }
+}
+
+/// Asks the enricher for the derived data of `value` and waits up to `timeout` for the reply
+/// with the request's id. Replies with any other id answer requests that already timed out,
+/// here or before a restart, and are discarded.
+async fn lookup<A: SteadyActor>(
+    actor: &mut A,
+    request: &mut Tx<LookupRequest>,
+    reply: &mut Rx<LookupReply>,
+    state: &mut WorkerState,
+    value: u64,
+    timeout: Duration,
+) -> Option<Derived> {
+    state.lookup_id += 1;
+    let id = state.lookup_id;
+    let deadline = Instant::now() + timeout;
+    let answer = async {
+        if !actor.try_send(request, LookupRequest { id, value }).is_sent() {
+            return None;
+        }
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match actor.take_async_with_timeout(reply, remaining).await {
+                Some(answer) if answer.id == id => return Some(answer.derived),
+                Some(answer) => trace!("Worker discarded stale lookup reply {} while waiting for {}", answer.id, id),
+                None if Instant::now() >= deadline => return None,
+                // The take also gives up when telemetry is due; relay it and keep waiting.
+                None => {
+                    actor.relay_stats_smartly();
+                }
+            }
+        }
+    }.await;
+    match answer {
+        Some(_) => state.lookups_answered += 1,
+        None => {
+            state.lookups_timed_out += 1;
+            warn!("Worker lookup {} for value {} not answered within {:?}, classified without it", id, value, timeout);
+        }
+    }
+    answer
+}
+
+/// Classified values waiting for the next flush in `--batch-size` mode.
+/// The values are only peeked, never taken, until the flush commits them, so a
+/// restart rebuilds the same batch from the generator channel and no flush is
+/// ever partially applied or repeated.
+struct Batch<M> {
+    values: usize,
+    messages: Vec<Envelope<M>>,
+    /// Kept in their envelopes until the flush so traced rejects can still be reported.
+    rejects: Vec<Envelope<RejectedValue>>,
+    /// What each value in the batch came to, in channel order, for the source's acknowledgements.
+    outcomes: Vec<AckOutcome>,
+    /// Overdue values in the batch, counted into the worker state by the flush.
+    overdue: DeadlineCounts,
+    /// Under `--priority-window`, where the batch's values are among those waiting.
+    picked: Vec<usize>,
+}
+
+impl<M> Default for Batch<M> {
+    fn default() -> Self {
+        Batch { values: 0, messages: Vec::new(), rejects: Vec::new(), outcomes: Vec::new(), overdue: DeadlineCounts::default(), picked: Vec::new() }
+    }
+}
+
+impl<M> Batch<M> {
+    fn is_empty(&self) -> bool {
+        self.values == 0
+    }
+
+    /// Classifies `fresh` values into the batch: those the generator channel holds past the batch,
+    /// or those picked from the values waiting under `--priority-window`.
+    fn fill<C: Classify<Message = M>>(&mut self, classifier: &C, fresh: Vec<Envelope<C::Payload>>,
+                                      rules: &ValidationRules, enricher: &Enricher, deadline: Option<ProcessingDeadline>) {
+        for envelope in fresh {
+            let value = classifier.key(envelope.payload);
+            let overdue = deadline.and_then(|d| d.overdue(envelope.stamp.elapsed()));
+            if let Some(action) = overdue {
+                self.overdue.count(action);
+                if action == DeadlineAction::Log {
+                    warn!("Worker picked up value {} {:?} after it was generated, past the processing deadline", value, envelope.stamp.elapsed());
+                }
+            }
+            let check = if overdue == Some(DeadlineAction::DeadLetter) { Some(RejectReason::Expired) } else { rules.check(value) };
+            self.outcomes.push(check.map_or(AckOutcome::Committed, AckOutcome::Rejected));
+            match check {
+                Some(reason) => self.rejects.push(envelope.carry(RejectedValue::new(value, reason).with_route(envelope.route))),
+                None if overdue == Some(DeadlineAction::FastPath) => self.messages.push(envelope.carry(classifier.classify(envelope.payload))),
+                None => self.messages.push(envelope.carry(classifier.classify(envelope.payload)).with_derived(enricher.compute(value))),
+            }
+            self.values += 1;
+        }
+    }
+}
+
+/// Forwards the checkpoint barrier when the next heartbeat is one, snapshotting the
+/// worker before the values that heartbeat releases are processed. The snapshot goes
+/// out first so the logger finds it when the in-band marker arrives.
+/// Returns false when a barrier is due but the outputs have no room for it yet.
+fn forward_barrier<A: SteadyActor, M: Payload>(
+    actor: &mut A,
+    heartbeat: &mut Rx<BeatTime>,
+    logger: &mut Tx<Envelope<M>>,
+    marker: impl Fn(u64) -> M,
+    checkpoint: &mut Tx<WorkerCheckpoint>,
+    state: &mut WorkerState,
+    every: u64,
+) -> bool {
+    if let Some(&BeatTime { beat, .. }) = actor.try_peek(heartbeat)
+        && is_barrier(beat, every)
+        && beat > state.last_barrier {
+        if actor.vacant_units(logger) == 0 || actor.vacant_units(checkpoint) == 0 {
+            return false;
+        }
+        let snapshot = WorkerCheckpoint {
+            barrier: beat,
+            heartbeats_processed: state.heartbeats_processed,
+            values_processed: state.values_processed,
+            messages_sent: state.messages_sent,
+            values_rejected: state.values_rejected,
+            seq: state.next_seq(),
+        };
+        let _ = actor.try_send(checkpoint, snapshot);
+        let barrier = Envelope::new(marker(beat)).with_seq(snapshot.seq);
+        let _ = actor.try_send(logger, barrier);
+        state.barriers_sent += 1;
+        state.bytes_sent += barrier.wire_bytes();
+        state.last_barrier = beat;
+        trace!("Worker forwarded checkpoint barrier {}", beat);
+    }
+    true
+}
+
+/// Test-only fault injection, so paths that only a crash reaches can be unit-tested
+/// deterministically instead of relying on the demonstration panics tests compile out.
+#[cfg(test)]
+pub(crate) mod faults {
+    use std::sync::Mutex;
+
+    /// Where in the worker an armed fault strikes.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub(crate) enum FaultPoint {
+        /// After the value is peeked, before anything is sent or taken for it.
+        BeforeSend,
+    }
+
+    /// Armed faults as (point, value, panics left). Shared by the whole test binary,
+    /// so each test arms a value no other test sends.
+    static ARMED: Mutex<Vec<(FaultPoint, u64, usize)>> = Mutex::new(Vec::new());
+
+    /// Makes the worker panic the next `times` times it reaches `point` holding `value`.
+    pub(crate) fn arm(point: FaultPoint, value: u64, times: usize) {
+        ARMED.lock().expect("fault registry").push((point, value, times));
+    }
+
+    /// Panics left for `value` at `point`.
+    pub(crate) fn remaining(point: FaultPoint, value: u64) -> usize {
+        let armed = ARMED.lock().expect("fault registry");
+        armed.iter().find(|(p, v, _)| *p == point && *v == value).map_or(0, |(_, _, left)| *left)
+    }
+
+    pub(crate) fn strike(point: FaultPoint, value: u64) {
+        let hit = {
+            let mut armed = ARMED.lock().expect("fault registry");
+            match armed.iter_mut().find(|(p, v, left)| *p == point && *v == value && *left > 0) {
+                Some((_, _, left)) => {
+                    *left -= 1;
+                    true
+                }
+                None => false,
+            }
+        };
+        // the registry lock is released first, so the panic cannot poison it
+        if hit {
+            panic!("injected fault at {:?} on value {}", point, value);
+        }
+    }
 }
 
 /// Entry point for the Worker actor.
 /// Demonstrates robust, persistent state, peek-before-commit, and automatic restart.
+#[allow(clippy::too_many_arguments)] // one parameter per channel keeps the wiring explicit
 pub async fn run(
     actor: SteadyActorShadow,
-    heartbeat_rx: SteadyRx<u64>,
-    generator_rx: SteadyRx<u64>,
-    logger_tx: SteadyTx<FizzBuzzMessage>,
+    heartbeat_rx: SteadyRx<BeatTime>,
+    generator_rx: SteadyRx<Envelope<u64>>,
+    logger_tx: SteadyTx<Envelope<FizzBuzzMessage>>,
+    rejected_tx: SteadyTx<RejectedValue>,
+    metrics_tx: SteadyTx<CounterSample>,
+    checkpoint_tx: SteadyTx<WorkerCheckpoint>,
+    acks_tx: SteadyTx<SourceAck>,
+    dead_letter_tx: SteadyTx<DeadLetter>,
+    lookup_tx: SteadyTx<LookupRequest>,
+    lookup_rx: SteadyRx<LookupReply>,
+    gate: StartGate,
+    state: SteadyState<WorkerState>,
+) -> Result<(), Box<dyn Error>> {
+    let actor = actor.into_spotlight([&heartbeat_rx, &generator_rx, &lookup_rx], [&logger_tx, &rejected_tx, &metrics_tx, &checkpoint_tx, &acks_tx, &dead_letter_tx, &lookup_tx]);
+    // Test graphs run the worker for real unless told otherwise, so stage tests can swap it out
+    // like any other actor and drive both its inputs and its outputs.
+    if actor.use_internal_behavior {
+        gate.wait(&actor).await;
+        let classifier = FizzBuzz::from_args(actor.args::<crate::MainArg>().expect("unable to downcast"));
+        internal_behavior(actor, classifier, heartbeat_rx, generator_rx, logger_tx, rejected_tx, metrics_tx, checkpoint_tx, acks_tx, dead_letter_tx, lookup_tx, lookup_rx, state).await
+    } else {
+        actor.simulated_behavior(vec!(&heartbeat_rx, &generator_rx, &logger_tx, &rejected_tx, &checkpoint_tx, &acks_tx, &dead_letter_tx, &lookup_tx, &lookup_rx)).await
+    }
+}
+
+/// The worker for any domain: `classifier` decides what each payload becomes, and everything
+/// else, pacing, validation, batching, barriers and restarts, is the same as for FizzBuzz.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_with<C: Classify>(
+    actor: SteadyActorShadow,
+    classifier: C,
+    heartbeat_rx: SteadyRx<BeatTime>,
+    generator_rx: SteadyRx<Envelope<C::Payload>>,
+    logger_tx: SteadyTx<Envelope<C::Message>>,
+    rejected_tx: SteadyTx<RejectedValue>,
+    metrics_tx: SteadyTx<CounterSample>,
+    checkpoint_tx: SteadyTx<WorkerCheckpoint>,
+    acks_tx: SteadyTx<SourceAck>,
+    dead_letter_tx: SteadyTx<DeadLetter>,
+    lookup_tx: SteadyTx<LookupRequest>,
+    lookup_rx: SteadyRx<LookupReply>,
     state: SteadyState<WorkerState>,
 ) -> Result<(), Box<dyn Error>> {
     internal_behavior(                                             //#!#//
-                                                                   actor.into_spotlight([&heartbeat_rx, &generator_rx], [&logger_tx]),
+                                                                   actor.into_spotlight([&heartbeat_rx, &generator_rx, &lookup_rx], [&logger_tx, &rejected_tx, &metrics_tx, &checkpoint_tx, &acks_tx, &dead_letter_tx, &lookup_tx]),
+                                                                   classifier,
                                                                    heartbeat_rx,
                                                                    generator_rx,
                                                                    logger_tx,
+                                                                   rejected_tx,
+                                                                   metrics_tx,
+                                                                   checkpoint_tx,
+                                                                   acks_tx,
+                                                                   dead_letter_tx,
+                                                                   lookup_tx,
+                                                                   lookup_rx,
                                                                    state,
     )
         .await
 }
 
+/// The worker as a pipeline stage, unless `--workers` runs a pool in its place.
+pub struct Worker;
+
+impl RobustActor for Worker {
+    fn name(&self) -> &'static str {
+        crate::NAME_WORKER
+    }
+    fn inputs(&self) -> &'static [&'static str] {
+        &["heartbeat", "generated", "lookup_reply"]
+    }
+    fn outputs(&self) -> &'static [&'static str] {
+        &["classified", "rejected", "metrics", "checkpoint", "acks", "worker_dead_letter", "lookup_request"]
+    }
+    fn enabled(&self, args: &crate::MainArg) -> bool {
+        args.workers <= 1
+    }
+    fn shares_thread(&self, args: &crate::MainArg) -> bool {
+        args.troupe
+    }
+    fn depends_on(&self, _args: &crate::MainArg) -> &'static [&'static str] {
+        &[crate::NAME_LOGGER] // nothing is classified before there is somewhere to log it
+    }
+    fn build(&self, builder: ActorBuilder, wiring: &Wiring, schedule: ScheduleAs<'_>) {
+        let (heartbeat_rx, generated_rx) = (wiring.heartbeat.rx.clone(), wiring.generated.rx.clone());
+        let (logger_tx, rejected_tx, checkpoint_tx) = (wiring.classified.tx.clone(), wiring.rejected.tx.clone(), wiring.checkpoint.tx.clone());
+        let (metrics_tx, acks_tx) = (wiring.metrics_tx[METRICS_WORKER].clone(), wiring.acks.tx.clone());
+        let dead_letter_tx = wiring.worker_dead_letter.tx.clone();
+        let (lookup_tx, lookup_rx) = (wiring.lookup_request.tx.clone(), wiring.lookup_reply.rx.clone());
+        let (gate, state) = (wiring.start_gate(self), wiring.state.worker.clone());
+        builder.build(move |context| {
+            crate::priority::apply(&context, crate::NAME_WORKER);
+            run(context, heartbeat_rx.clone(), generated_rx.clone(), logger_tx.clone(), rejected_tx.clone(), metrics_tx.clone(), checkpoint_tx.clone(), acks_tx.clone(), dead_letter_tx.clone(), lookup_tx.clone(), lookup_rx.clone(), gate.clone(), state.clone())
+        }, schedule);
+    }
+}
+
+/// One worker of a `--workers` pool, by shard: the same actor as the single worker, fed by
+/// the router and drained by the fan-in, with a state of its own.
+pub struct PoolWorker(pub usize);
+
+impl RobustActor for PoolWorker {
+    fn name(&self) -> &'static str {
+        crate::NAME_POOL[self.0]
+    }
+    fn inputs(&self) -> &'static [&'static str] {
+        &["shard_heartbeat", "shard"]
+    }
+    fn outputs(&self) -> &'static [&'static str] {
+        &["shard_classified", "shard_rejected", "shard_metrics", "shard_dead_letter"]
+    }
+    fn enabled(&self, args: &crate::MainArg) -> bool {
+        args.workers > 1 && (self.0 as u64) < args.workers
+    }
+    fn shares_thread(&self, args: &crate::MainArg) -> bool {
+        args.troupe
+    }
+    fn depends_on(&self, _args: &crate::MainArg) -> &'static [&'static str] {
+        &[crate::NAME_FAN_IN]
+    }
+    fn build(&self, builder: ActorBuilder, wiring: &Wiring, schedule: ScheduleAs<'_>) {
+        let links = &wiring.pool[self.0];
+        let (heartbeat_rx, generated_rx) = (links.heartbeat.rx.clone(), links.generated.rx.clone());
+        let (logger_tx, rejected_tx, checkpoint_tx) = (links.classified.tx.clone(), links.rejected.tx.clone(), links.checkpoint.tx.clone());
+        let (metrics_tx, acks_tx, dead_letter_tx) = (links.metrics.tx.clone(), links.acks.tx.clone(), links.dead_letter.tx.clone());
+        let (lookup_tx, lookup_rx) = (links.lookup_request.tx.clone(), links.lookup_reply.rx.clone());
+        let (gate, state, name) = (wiring.start_gate(self), wiring.state.pool[self.0].clone(), self.name());
+        builder.build(move |context| {
+            crate::priority::apply(&context, name);
+            run(context, heartbeat_rx.clone(), generated_rx.clone(), logger_tx.clone(), rejected_tx.clone(), metrics_tx.clone(), checkpoint_tx.clone(), acks_tx.clone(), dead_letter_tx.clone(), lookup_tx.clone(), lookup_rx.clone(), gate.clone(), state.clone())
+        }, schedule);
+    }
+}
+
 /// Internal behavior for the Worker actor.
 /// Demonstrates robust message processing, showstopper detection, and intentional failure injection.
 /// The peek-before-commit pattern ensures that no message is lost or duplicated, even across panics.
-async fn internal_behavior<A: SteadyActor>(
+#[allow(clippy::too_many_arguments)]
+async fn internal_behavior<A: SteadyActor, C: Classify>(
     mut actor: A,
-    heartbeat: SteadyRx<u64>,
-    generator: SteadyRx<u64>,
-    logger: SteadyTx<FizzBuzzMessage>,
+    classifier: C,
+    heartbeat: SteadyRx<BeatTime>,
+    generator: SteadyRx<Envelope<C::Payload>>,
+    logger: SteadyTx<Envelope<C::Message>>,
+    rejected: SteadyTx<RejectedValue>,
+    metrics: SteadyTx<CounterSample>,
+    checkpoint: SteadyTx<WorkerCheckpoint>,
+    acks: SteadyTx<SourceAck>,
+    dead_letter: SteadyTx<DeadLetter>,
+    lookup_request: SteadyTx<LookupRequest>,
+    lookup_reply: SteadyRx<LookupReply>,
     state: SteadyState<WorkerState>,
 ) -> Result<(), Box<dyn Error>> {
-    let mut state = state.lock(|| WorkerState {
-        heartbeats_processed: 0,
-        values_processed: 0,
-        messages_sent: 0,
+    let args = actor.args::<crate::MainArg>().expect("unable to downcast");
+    let acking = crate::ack::enabled(args);
+    let rules = ValidationRules::from_args(args);
+    let enricher = Enricher::from_args(args);
+    let lookup_timeout = args.enricher_timeout_ms.map(Duration::from_millis);
+    let checkpoint_every = args.checkpoint_every;
+    let batch_size = args.batch_size;
+    let window = args.priority_window;
+    let (ranked, aging) = (!args.priority.is_empty(), Aging::from_args(args));
+    let deadline = ProcessingDeadline::from_args(args);
+    let stall_after = (args.worker_stall_ms > 0).then(|| Duration::from_millis(args.worker_stall_ms));
+    let hold = args.hold.clone();
+    // Without a heartbeat actor nothing paces the worker; it runs on generator availability alone.
+    let paced = !args.no_heartbeat;
+    // In a pool every replica hears every beat but gets only its shard of the values.
+    let pooled = args.workers > 1;
+    let backlog_file = args.backlog_file.clone();
+    let mut drain = DrainDeadline::new(Duration::from_millis(args.drain_worker_ms));
+
+    // A process resumed from --state-dir starts at the worker's side of the checkpoint's cut.
+    let resumed = persistence::restored().map(|snapshot| snapshot.checkpoint.worker).unwrap_or_default();
+    let Some(mut state) = lock_state(&actor, crate::NAME_WORKER, &state, || WorkerState {
+        heartbeats_processed: resumed.heartbeats_processed,
+        values_processed: resumed.values_processed,
+        messages_sent: resumed.messages_sent,
+        bytes_sent: 0,
+        values_rejected: resumed.values_rejected,
+        // The barrier's seq was the last one sent, so the next message carries on after it.
+        barriers_sent: resumed.seq.saturating_sub(resumed.messages_sent),
+        last_barrier: resumed.barrier,
+        beat_micros: 0,
+        batches_flushed: 0,
+        showstoppers_dropped: 0,
+        residue: 0,
+        stalls: 0,
+        last_beat: None,
+        beats_missed: 0,
+        beats_repeated: 0,
+        beats_idle: 0,
+        overdue: DeadlineCounts::default(),
+        time: StageTime::default(),
+        acks: VecDeque::new(),
+        waiting: WaitingSlot::default(),
+        lookup_id: 0,
+        lookups_answered: 0,
+        lookups_timed_out: 0,
         restart_count: 0,
-    }).await;
+        restarts: RestartCauses::default(),
+        starts: StartTimes::default(),
+        faults: FaultPlan::for_actor(args, "worker"),
+    }).await else {
+        return Ok(());
+    };
 
     state.restart_count += 1;
+    if state.restart_count > 1 {
+        let (cause, starts) = (state.restart_cause(), state.restart_count);
+        state.restarts.record(crate::NAME_WORKER, cause, starts);
+    }
+    let (warm, sent) = (state.is_warm(), state.messages_sent);
+    state.starts.begin(crate::NAME_WORKER, warm, sent);
     info!(
-        "Worker starting (restart #{}) with heartbeats: {}, values: {}, messages: {}",
-        state.restart_count, state.heartbeats_processed, state.values_processed, state.messages_sent
+        "Worker starting (restart #{}) with heartbeats: {}, values: {}, messages: {}, rejected: {}",
+        state.restart_count, state.heartbeats_processed, state.values_processed, state.messages_sent,
+        state.values_rejected
     );
+    let wait_strategy = crate::wait::strategy(&actor);
 
 
     let mut heartbeat = heartbeat.lock().await;
     let mut generator = generator.lock().await;
     let mut logger = logger.lock().await;
+    let mut rejected = rejected.lock().await;
+    let mut metrics = metrics.lock().await;
+    let mut checkpoint = checkpoint.lock().await;
+    let mut acks = acks.lock().await;
+    let mut dead_letter = dead_letter.lock().await;
+    let mut lookup_request = lookup_request.lock().await;
+    let mut lookup_reply = lookup_reply.lock().await;
+    let mut pacer = SamplePacer::default();
+    let mut batch = Batch::default();
+    let mut clock = IterationClock::new();
 
     // we are using a more complex veto closure so we put eyes on each part with the i! macro which
     // will capture which expression stopped the shutdown and report it upon unclean shutdown.
     while actor.is_running(
-                            || i!(heartbeat.is_closed_and_empty())
+                            || i!(!paced || heartbeat.is_closed_and_empty())
                             && i!(generator.is_closed_and_empty())
+                            && i!(state.waiting.len() == 0)
                             && i!(logger.mark_closed())
+                            && i!(rejected.mark_closed())
+                            && i!(metrics.mark_closed())
+                            && i!(checkpoint.mark_closed())
+                            && i!(state.acks.is_empty() && acks.mark_closed())
+                            && i!(dead_letter.mark_closed())
+                            && i!(lookup_request.mark_closed())
                         ) {
+        clock.lap(&mut state.time);
+        let sent = state.messages_sent;
+        state.starts.progress(crate::NAME_WORKER, sent);
+        while let Some(&ack) = state.acks.front()
+            && actor.try_send(&mut acks, ack).is_sent() {
+            state.acks.pop_front();
+        }
+        // Out of time to drain: whatever the generator left is spilled rather than processed,
+        // and heartbeats no longer matter.
+        if drain.expired(actor.is_liveliness_stop_requested()) {
+            let mut residue = Vec::new();
+            let mut waiting = state.waiting.get::<C::Payload>().take_all().into_iter();
+            while let Some(envelope) = waiting.next().or_else(|| actor.try_take(&mut generator)) {
+                trace::event("WORKER", "spilled", &envelope);
+                residue.push(format!("{:?}", envelope.payload));
+                state.residue += 1;
+                if acking {
+                    state.ack(AckOutcome::Spilled);
+                }
+            }
+            while actor.try_take(&mut heartbeat).is_some() {}
+            batch = Batch::default();
+            persist_residue(backlog_file.as_deref(), "WORKER", &residue);
+            let wait = Instant::now();
+            await_for_all!(actor.wait_periodic(Duration::from_millis(10)));
+            clock.waited(wait);
+            continue;
+        }
+
+        if batch_size > 0 {
+            // Batch mode: classify as values arrive, commit them on the heartbeat or a full batch.
+            let wait = Instant::now();
+            if paced {
+                await_for_all!(wait_strategy.wait(&actor, async {
+                    await_for_any!(actor.wait_avail(&mut heartbeat, 1), actor.wait_avail(&mut generator, batch_size))
+                }));
+            } else {
+                await_for_all!(wait_strategy.wait(&actor, actor.wait_avail(&mut generator, 1)));
+            }
+            clock.waited(wait);
+            if !forward_barrier(&mut actor, &mut heartbeat, &mut logger, |beat| classifier.barrier(beat), &mut checkpoint, &mut state, checkpoint_every) {
+                continue;
+            }
+            if window > 0 {
+                // Values waiting for their turn are taken, so the window can rank them against newer ones.
+                let waiting = state.waiting.get::<C::Payload>();
+                while waiting.len() < window && let Some(envelope) = actor.try_take(&mut generator) {
+                    waiting.push(envelope);
+                }
+            } else {
+                let fresh = actor.try_peek_iter(&mut generator)
+                    .skip(batch.values)
+                    .take(batch_size.saturating_sub(batch.values))
+                    .copied()
+                    .collect();
+                batch.fill(&classifier, fresh, &rules, &enricher, deadline);
+            }
+
+            // Unpaced, the batch goes out once it holds everything the generator has offered so far.
+            let held = if window > 0 { 0 } else { batch.values };
+            let beat_due = if paced {
+                actor.avail_units(&mut heartbeat) > 0
+            } else {
+                actor.avail_units(&mut generator) <= held
+            };
+            // With no heartbeats left to come, flush whatever is there so shutdown can drain.
+            let ending = if paced { heartbeat.is_closed_and_empty() } else { actor.is_liveliness_stop_requested() };
+            let full = if window > 0 { state.waiting.len() >= window } else { batch.values >= batch_size };
+            if !(beat_due || ending || full) {
+                continue;
+            }
+            // The batch goes out on this beat, so its values take the beat's time.
+            if paced && beat_due && let Some(beat) = actor.try_peek(&mut heartbeat) {
+                state.beat_micros = beat.wall_micros;
+            }
+            if window > 0 && batch.is_empty() {
+                let waiting = state.waiting.get::<C::Payload>();
+                batch.picked = waiting.next(batch_size, aging);
+                let picked = batch.picked.iter().map(|&index| waiting.get(index)).collect();
+                batch.fill(&classifier, picked, &rules, &enricher, deadline);
+            }
+            if !batch.is_empty() {
+                if actor.vacant_units(&mut logger) < batch.messages.len()
+                    || actor.vacant_units(&mut rejected) < batch.rejects.len() {
+                    let wait = Instant::now();
+                    await_for_all!(actor.wait_vacant(&mut logger, batch.messages.len()),
+                                   actor.wait_vacant(&mut rejected, batch.rejects.len()));
+                    clock.waited(wait);
+                    continue;
+                }
+                // Higher priorities first; the window's picks are in that order already, as far as
+                // the aging since allows.
+                if ranked {
+                    batch.messages.sort_by_key(|message| Reverse(aging.rank(message)));
+                }
+                // Send and commit together: the batch leaves the generator channel only once it is out.
+                let messages = batch.messages.len();
+                let rejects = batch.rejects.len();
+                let first_seq = state.next_seq();
+                let event_micros = state.beat_micros;
+                let mut bytes = 0;
+                actor.send_iter_until_full(&mut logger, batch.messages.drain(..)
+                    .zip(first_seq..)
+                    .map(|(message, seq)| message.with_seq(seq).with_event_time(event_micros))
+                    .inspect(|message| bytes += message.wire_bytes())
+                    .inspect(|message| trace::event("WORKER", "classified", message)));
+                actor.send_iter_until_full(&mut rejected, batch.rejects.drain(..)
+                    .inspect(|reject| trace::event("WORKER", "rejected", reject))
+                    .map(|reject| reject.payload));
+                if window > 0 {
+                    state.waiting.get::<C::Payload>().remove(&batch.picked);
+                    batch.picked.clear();
+                } else {
+                    actor.advance_take_index(&mut generator, batch.values);
+                }
+                for outcome in batch.outcomes.drain(..) {
+                    state.values_processed += 1;
+                    if acking {
+                        state.ack(outcome);
+                    }
+                }
+                state.messages_sent += messages as u64;
+                state.bytes_sent += bytes;
+                state.values_rejected += rejects as u64;
+                state.batches_flushed += 1;
+                state.overdue.add(batch.overdue);
+                batch.overdue = DeadlineCounts::default();
+                trace!("Worker flushed batch of {} values ({} rejected)", batch.values, rejects);
+                batch.values = 0;
+            }
+            if paced && beat_due && let Some(beat) = actor.try_take(&mut heartbeat) {
+                state.note_beat(beat.beat);
+                state.heartbeats_processed += 1;
+            }
+
+            if pacer.is_due() {
+                let backlog = (actor.avail_units(&mut generator) + state.waiting.len()) as u64;
+                let sample = CounterSample::new(state.values_processed + state.residue,
+                                                state.messages_sent + state.values_rejected + state.barriers_sent,
+                                                backlog)
+                    .with_starts(state.restart_count)
+                    .with_dropped(state.showstoppers_dropped)
+                    .with_bytes(state.bytes_sent, backlog_bytes(&actor, &mut generator))
+                    .with_seq(state.next_seq() - 1)
+                    .with_depths([ChannelDepth::of(&actor, &mut generator), ChannelDepth::of(&actor, &mut heartbeat)]);
+                let _ = actor.try_send(&mut metrics, sample);
+            }
+            continue;
+        }
+
         // Wait for both inputs to have data and logger to have space
-        let clean = await_for_all!(
-                                    actor.wait_avail(&mut heartbeat, 1),
-                                    actor.wait_avail(&mut generator, 1),
-                                    actor.wait_vacant(&mut logger, 1)
+        let wait = Instant::now();
+        let ready = wait_for_all!(
+                            async {
+                                if !paced {
+                                    actor.wait_avail(&mut generator, 1).await
+                                } else if pooled {
+                                    // a second beat before a value wakes the replica to spend the first idle
+                                    actor.wait_avail(&mut heartbeat, 1).await
+                                        && await_for_any!(actor.wait_avail(&mut generator, 1), actor.wait_avail(&mut heartbeat, 2))
+                                } else {
+                                    await_for_all!(actor.wait_avail(&mut heartbeat, 1), actor.wait_avail(&mut generator, 1))
+                                }
+                            },
+                            actor.wait_vacant(&mut logger, 1)
         );
+        let ready = wait_strategy.wait(&actor, ready);
+        let clean = match stall_after {
+            None => {
+                let clean = await_for_all!(ready);
+                clock.waited(wait);
+                clean
+            }
+            Some(limit) => {
+                let waited = await_for_any!(async { Some(ready.await) }, async {
+                    actor.wait(limit).await;
+                    None
+                });
+                clock.waited(wait);
+                match waited {
+                    Some(clean) => clean,
+                    // Shutdown ends waits on its own; only a wait the timer had to end is a stall.
+                    None if actor.is_liveliness_stop_requested() => continue,
+                    // Nor is waiting out a pause the heartbeat holds on purpose.
+                    None if hold.is_held() => continue,
+                    None => {
+                        let missing: Vec<&str> = [
+                            (paced && actor.avail_units(&mut heartbeat) == 0, "heartbeat avail"),
+                            (actor.avail_units(&mut generator) == 0, "generator avail"),
+                            (actor.vacant_units(&mut logger) == 0, "logger vacant"),
+                        ].into_iter().filter_map(|(unmet, name)| unmet.then_some(name)).collect();
+                        // Everything turned up just as the timer fired; that is no stall.
+                        if missing.is_empty() {
+                            continue;
+                        }
+                        state.stalls += 1;
+                        warn!("Worker stalled for {:?} waiting on {} (stall #{})", limit, missing.join(", "), state.stalls);
+                        events::emit("alert", json!({"kind": "worker_stall", "waiting_on": missing, "stalls": state.stalls}));
+                        continue;
+                    }
+                }
+            }
+        };
+
+        // A pool replica keeps no more beats than it has values to release, and one to wait on,
+        // so beats never bank up against values still to come.
+        if pooled && paced {
+            let mut idle = false;
+            while actor.avail_units(&mut heartbeat) > actor.avail_units(&mut generator).max(1)
+                && let Some(beat) = actor.try_take(&mut heartbeat) {
+                state.note_beat(beat.beat);
+                state.beats_idle += 1;
+                idle = true;
+            }
+            if idle && actor.avail_units(&mut generator) == 0 {
+                continue;
+            }
+        }
 
         // if clean {
         //     // Showstopper detection: if this value has been peeked N times, drop it and log.
         //
         // }
 
-        // Only proceed if we have a heartbeat or if not all conditions were met (to avoid starvation)
-        if actor.try_take(&mut heartbeat).is_some() || !clean {
+        if !forward_barrier(&mut actor, &mut heartbeat, &mut logger, |beat| classifier.barrier(beat), &mut checkpoint, &mut state, checkpoint_every) {
+            continue; // forward the barrier once both channels have room
+        }
+
+        // Shutdown cuts the waits short, so the logger may still be full. Peeking then would only
+        // count toward the showstopper threshold and drop good values; wait for room or the deadline.
+        if actor.vacant_units(&mut logger) == 0 {
+            continue;
+        }
+
+        // Only proceed if we have a heartbeat or if not all conditions were met (to avoid starvation).
+        // Unpaced there is no heartbeat to spend, and every available value is processed.
+        let beat = if paced { actor.try_take(&mut heartbeat) } else { None };
+        if let Some(beat) = beat {
+            state.beat_micros = beat.wall_micros;
+            state.note_beat(beat.beat);
+        }
+        if !paced || beat.is_some() || !clean {
 
             // Peek at the next generator value (do not take yet) !!!!!!!!!!!!!!!
-            if let Some(&value) = actor.try_peek(&mut generator) {               //#!#//
+            if let Some(&envelope) = actor.try_peek(&mut generator) {               //#!#//
+                let value = classifier.key(envelope.payload);
 
                 const SHOWSTOPPER_THRESHOLD: usize = 3;
                 if actor.is_showstopper(&mut generator, SHOWSTOPPER_THRESHOLD) {  //#!#//
-                    if let Some(value) = actor.try_take(&mut generator) {
+                    // In a pool the fan-in learns of the dropped route from its dead letter, so that must go out.
+                    if envelope.route != 0 && actor.vacant_units(&mut dead_letter) == 0 {
+                        continue;
+                    }
+                    if let Some(dropped) = actor.try_take(&mut generator) {
+                        trace::event("WORKER", "dropped", &dropped);
                         warn!(
-                            "Showstopper detected: value {} has blocked the worker {} times, dropping it.",
-                            value, SHOWSTOPPER_THRESHOLD
+                            "Showstopper detected: value {:?} has blocked the worker {} times, dropping it.",
+                            dropped.payload, SHOWSTOPPER_THRESHOLD
                         );
+                        events::emit("showstopper", json!({"actor": "WORKER", "value": classifier.key(dropped.payload)}));
+                        let letter = DeadLetter::new(crate::NAME_WORKER, SHOWSTOPPER_THRESHOLD, 0, Poisoned::Value(classifier.key(dropped.payload)))
+                            .with_route(dropped.route);
+                        if !actor.try_send(&mut dead_letter, letter).is_sent() {
+                            warn!("Dead-letter channel full, showstopper {:?} is only in the log", dropped.payload);
+                        }
                         state.values_processed += 1;
+                        state.showstoppers_dropped += 1;
+                        if acking {
+                            state.ack(AckOutcome::Dropped);
+                        }
                         //  cleared after next peek.
                        // actor.try_peek(&mut generator);
                        // assert_eq!(false, actor.is_showstopper(&mut generator, SHOWSTOPPER_THRESHOLD), "showstopper cleared");
@@ -124,40 +859,98 @@ async fn internal_behavior<A: SteadyActor>(
 
                 // --- Robustness Demonstration: Intentional Panic ---
                 // This panic is injected to demonstrate automatic actor restart and state preservation.
-                #[cfg(not(test))]
-                if value == 33  {
+                let strike = state.faults.strike(value);
+                if strike.panic {
                     error!(
                             "Worker intentionally panicking after {} heartbeats to demonstrate robustness!",
                            value
                         );
                     panic!("Intentional panic for robustness demonstration - DO NOT COPY THIS PATTERN!");
                 }
+                if let Some(delay) = strike.delay {
+                    warn!("Worker holding {:?} on value {} (injected fault)", delay, value);
+                    actor.wait(delay).await;
+                }
+                if strike.send_fail {
+                    // As if the logger channel were full: the value stays put and is retried.
+                    warn!("Worker send of value {} refused (injected fault), will retry", value);
+                    continue;
+                }
                 // --- End Robustness Demonstration ---
+                #[cfg(test)]
+                faults::strike(faults::FaultPoint::BeforeSend, value);
 
+                // A value past the processing deadline is dead-lettered, fast-pathed or just noted.
+                let overdue = deadline.and_then(|d| d.overdue(envelope.stamp.elapsed()));
+                let check = if overdue == Some(DeadlineAction::DeadLetter) { Some(RejectReason::Expired) } else { rules.check(value) };
 
-
-                // Process the value and send to logger
-                let fizz_buzz_msg = FizzBuzzMessage::new(value);
-                match actor.try_send(&mut logger, fizz_buzz_msg) {
-                    SendOutcome::Success => {
-                        // Only now do we take the value from the generator !!!!!!!!!!!!!!!
-                        actor.try_take(&mut generator).expect("internal error"); //#!#//
-                        state.values_processed += 1;
-                        state.messages_sent += 1;
-                        trace!(
-                            "Worker sent FizzBuzz message for value: {} -> {:?}",
-                            value,
-                            fizz_buzz_msg
-                        );
+                // Validation runs before classification; rejects go to the side channel
+                // and are committed from the generator only once that send succeeds.
+                if let Some(reason) = check {
+                    let reject = RejectedValue::new(value, reason).with_route(envelope.route);
+                    match actor.try_send(&mut rejected, reject) {
+                        SendOutcome::Success => {
+                            actor.try_take(&mut generator).expect("internal error");
+                            trace::event("WORKER", "rejected", &envelope.carry(reject));
+                            state.values_processed += 1;
+                            state.values_rejected += 1;
+                            if acking {
+                                state.ack(AckOutcome::Rejected(reason));
+                            }
+                            if let Some(action) = overdue {
+                                state.overdue.count(action);
+                            }
+                            trace!("Worker rejected value: {} ({:?})", value, reason);
+                        }
+                        // Side channel full or closing, retry this value next loop
+                        _ => continue,
                     }
-                    SendOutcome::Blocked(_) => {
-                        // If we can't send, try again later
-                        warn!("Worker logger channel blocked, will retry");
-                        // Do not take the value, so we will try again next loop
-                        continue;
+                } else {
+                    // Process the value and send to logger
+                    let fizz_buzz_msg = classifier.classify(envelope.payload);
+                    let derived = match (overdue, lookup_timeout) {
+                        (Some(DeadlineAction::FastPath), _) => Default::default(),
+                        (_, None) => enricher.compute(value),
+                        // Asked of the enricher; past the timeout the value goes on without it.
+                        (_, Some(timeout)) => lookup(&mut actor, &mut lookup_request, &mut lookup_reply, &mut state, value, timeout).await
+                            .unwrap_or_default(),
+                    };
+                    let message = envelope.carry(fizz_buzz_msg)
+                        .with_seq(state.next_seq())
+                        .with_derived(derived)
+                        .with_event_time(state.beat_micros);
+                    match actor.try_send(&mut logger, message) {
+                        SendOutcome::Success => {
+                            // Only now do we take the value from the generator !!!!!!!!!!!!!!!
+                            actor.try_take(&mut generator).expect("internal error"); //#!#//
+                            trace::event("WORKER", "classified", &message);
+                            state.values_processed += 1;
+                            state.messages_sent += 1;
+                            state.bytes_sent += message.wire_bytes();
+                            if acking {
+                                state.ack(AckOutcome::Committed);
+                            }
+                            if let Some(action) = overdue {
+                                state.overdue.count(action);
+                                if action == DeadlineAction::Log {
+                                    warn!("Worker processed value {} {:?} after it was generated, past the processing deadline", value, envelope.stamp.elapsed());
+                                }
+                            }
+                            trace!(
+                                "Worker sent message for value: {} -> {:?}",
+                                value,
+                                fizz_buzz_msg
+                            );
+                        }
+                        SendOutcome::Blocked(_) => {
+                            // If we can't send, try again later
+                            warn!("Worker logger channel blocked, will retry");
+                            // Do not take the value, so we will try again next loop
+                            continue;
+                        }
+                        SendOutcome::Timeout(_) => {continue;}
+                        SendOutcome::Closed(_) => {continue;}
                     }
-                    SendOutcome::Timeout(_) => {continue;}
-                    SendOutcome::Closed(_) => {continue;}
                 }
             }
 
@@ -167,12 +960,26 @@ async fn internal_behavior<A: SteadyActor>(
                 "Worker processed heartbeat total: {}",
                 state.heartbeats_processed
             );
+
+            if pacer.is_due() {
+                let backlog = actor.avail_units(&mut generator) as u64;
+                let sample = CounterSample::new(state.values_processed + state.residue,
+                                                state.messages_sent + state.values_rejected + state.barriers_sent,
+                                                backlog)
+                    .with_starts(state.restart_count)
+                    .with_dropped(state.showstoppers_dropped)
+                    .with_bytes(state.bytes_sent, backlog_bytes(&actor, &mut generator))
+                    .with_seq(state.next_seq() - 1)
+                    .with_depths([ChannelDepth::of(&actor, &mut generator), ChannelDepth::of(&actor, &mut heartbeat)]);
+                let _ = actor.try_send(&mut metrics, sample);
+            }
         }
     }
 
     info!(
-        "Worker shutting down. Heartbeats: {}, Values: {}, Messages: {}",
-        state.heartbeats_processed, state.values_processed, state.messages_sent
+        "Worker shutting down. Heartbeats: {}, Values: {}, Messages: {}, Rejected: {}, Batches: {}, Undrained: {}, Overdue (fast-path/dead-letter/log): {}/{}/{}",
+        state.heartbeats_processed, state.values_processed, state.messages_sent, state.values_rejected,
+        state.batches_flushed, state.residue, state.overdue.fast_path, state.overdue.dead_lettered, state.overdue.logged
     );
     Ok(())
 }
@@ -181,39 +988,559 @@ async fn internal_behavior<A: SteadyActor>(
 pub(crate) mod worker_tests {
     use std::thread::sleep;
     use steady_state::*;
+    use crate::arg::MainArg;
+    use crate::actor::assert_sink::AssertSink;
+    use crate::enrich::ComputeModule;
+    use crate::classify::classify_tests::{Alarm, Reading, Threshold};
     use super::*;
 
+    /// Unstamped envelopes numbered from 1, as the worker sends them to the logger.
+    fn sequenced<const N: usize>(messages: [FizzBuzzMessage; N]) -> Vec<Envelope<FizzBuzzMessage>> {
+        messages.into_iter().zip(1..).map(|(m, seq)| Envelope::unstamped(m).with_seq(seq)).collect()
+    }
+
+    /// Both ends of every worker channel, with the worker already built on the far side.
+    struct Harness<C: Classify> {
+        graph: Graph,
+        generate_tx: LazySteadyTx<Envelope<C::Payload>>,
+        heartbeat_tx: LazySteadyTx<BeatTime>,
+        logger_rx: LazySteadyRx<Envelope<C::Message>>,
+        rejected_rx: LazySteadyRx<RejectedValue>,
+        checkpoint_rx: LazySteadyRx<WorkerCheckpoint>,
+        // only read by the gRPC acknowledgement test
+        #[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+        acks_rx: LazySteadyRx<SourceAck>,
+        dead_letter_rx: LazySteadyRx<DeadLetter>,
+        lookup_request_rx: LazySteadyRx<LookupRequest>,
+        lookup_reply_tx: LazySteadyTx<LookupReply>,
+        probe: SteadyState<WorkerState>,
+    }
+
+    impl<C: Classify> Harness<C> {
+        fn spawn(args: MainArg, classifier: C) -> Self {
+            Self::build(args, classifier, None)
+        }
+
+        /// A small logger capacity, with nothing reading the logger, holds the worker up on sends.
+        fn spawn_with_logger_capacity(args: MainArg, classifier: C, logger_capacity: usize) -> Self {
+            Self::build(args, classifier, Some(logger_capacity))
+        }
+
+        fn build(args: MainArg, classifier: C, logger_capacity: Option<usize>) -> Self {
+            let mut graph = GraphBuilder::for_testing().build(args);
+            let (generate_tx, generate_rx) = graph.channel_builder().build();
+            let (heartbeat_tx, heartbeat_rx) = graph.channel_builder().build();
+            let logger = graph.channel_builder();
+            let logger = match logger_capacity { Some(capacity) => logger.with_capacity(capacity), None => logger };
+            let (logger_tx, logger_rx) = logger.build();
+            let (rejected_tx, rejected_rx) = graph.channel_builder().build();
+            let (metrics_tx, _metrics_rx) = graph.channel_builder().build::<CounterSample>();
+            let (checkpoint_tx, checkpoint_rx) = graph.channel_builder().build();
+            let (acks_tx, acks_rx) = graph.channel_builder().build();
+            let (dead_letter_tx, dead_letter_rx) = graph.channel_builder().build();
+            let (lookup_tx, lookup_request_rx) = graph.channel_builder().build();
+            let (lookup_reply_tx, lookup_rx) = graph.channel_builder().build();
+
+            let state = new_state();
+            let probe = state.clone();
+            graph.actor_builder().with_name("UnitTest")
+                .build(move |context| internal_behavior(context, classifier.clone()
+                                                        , heartbeat_rx.clone()
+                                                        , generate_rx.clone()
+                                                        , logger_tx.clone()
+                                                        , rejected_tx.clone()
+                                                        , metrics_tx.clone()
+                                                        , checkpoint_tx.clone()
+                                                        , acks_tx.clone()
+                                                        , dead_letter_tx.clone()
+                                                        , lookup_tx.clone()
+                                                        , lookup_rx.clone()
+                                                        , state.clone())
+                       , SoloAct
+                );
+            Harness { graph, generate_tx, heartbeat_tx, logger_rx, rejected_rx, checkpoint_rx, acks_rx
+                    , dead_letter_rx, lookup_request_rx, lookup_reply_tx, probe }
+        }
+    }
+
     #[test]
     fn test_worker() -> Result<(), Box<dyn Error>> {
-        let mut graph = GraphBuilder::for_testing().build(());
-        let (generate_tx, generate_rx) = graph.channel_builder().build();
-        let (heartbeat_tx, heartbeat_rx) = graph.channel_builder().build();
-        let (logger_tx, logger_rx) = graph.channel_builder().build::<FizzBuzzMessage>();
-
-        let state = new_state();
-        graph.actor_builder().with_name("UnitTest")
-            .build(move |context| internal_behavior(context
-                                                    , heartbeat_rx.clone()
-                                                    , generate_rx.clone()
-                                                    , logger_tx.clone()
-                                                    , state.clone())
-                   , SoloAct
-            );
+        let mut h = Harness::spawn(MainArg::default(), FizzBuzz::default());
+        let logged = AssertSink::new().expect_all(sequenced([FizzBuzzMessage::FizzBuzz
+                                                            ,FizzBuzzMessage::Value(1)
+                                                            ,FizzBuzzMessage::Value(2)
+                                                            ,FizzBuzzMessage::Fizz
+                                                            ,FizzBuzzMessage::Value(4)
+                                                            ,FizzBuzzMessage::Buzz]))
+            .build(&mut h.graph, "ExpectLogged", h.logger_rx);
+
+        h.generate_tx.testing_send_all([0,1,2,3,4,5].map(Envelope::unstamped).to_vec(), true);
+        h.heartbeat_tx.testing_send_all([0].map(BeatTime::untimed).to_vec(), true);
+        h.graph.start();
+
+        logged.wait(Duration::from_secs(1))?;
+
+        h.graph.request_shutdown();
+        h.graph.block_until_stopped(Duration::from_secs(1))?;
+        logged.wait(Duration::ZERO)?;
+        Ok(())
+    }
+
+    /// A value that crashes the worker every time it is handled is peeked again after each
+    /// restart, until the showstopper threshold drops it and the value behind it flows on.
+    #[test]
+    fn test_worker_drops_showstopper_after_injected_panics() -> Result<(), Box<dyn Error>> {
+        // no other test sends this value, and it is armed to panic more often than the threshold allows
+        const POISON: u64 = 9_001;
+        faults::arm(faults::FaultPoint::BeforeSend, POISON, 10);
+
+        let mut h = Harness::spawn(MainArg::default(), FizzBuzz::default());
+
+        h.generate_tx.testing_send_all([POISON, 15].map(Envelope::unstamped).to_vec(), true);
+        h.heartbeat_tx.testing_send_all((0..10).map(BeatTime::untimed).collect(), true);
+        h.graph.start();
+
+        sleep(Duration::from_millis(500));
+
+        h.graph.request_shutdown();
+        h.graph.block_until_stopped(Duration::from_secs(1))?;
+        assert_steady_rx_eq_take!(&h.logger_rx, sequenced([FizzBuzzMessage::FizzBuzz]));
+        let state = crate::state_lock::settled(&h.probe).expect("worker state");
+        // two peeks ended in a panic and a restart, and the third found the showstopper
+        assert_eq!(state.restart_count, 3);
+        let letters = h.dead_letter_rx.testing_take_all();
+        assert_eq!(letters.len(), 1);
+        assert_eq!((letters[0].source, letters[0].peeks, &letters[0].message), (crate::NAME_WORKER, 3, &Poisoned::Value(POISON)));
+        let starts = state.starts.summary();
+        assert_eq!((starts.cold, starts.warm), (1, 2));
+        assert_eq!(faults::remaining(faults::FaultPoint::BeforeSend, POISON), 8);
+        assert_eq!((state.values_processed, state.messages_sent), (2, 1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_worker_reports_stall() -> Result<(), Box<dyn Error>> {
+        // room for one message and a logger that never reads, so the worker waits on it
+        let mut h = Harness::spawn_with_logger_capacity(MainArg {
+            worker_stall_ms: 50,
+            drain_worker_ms: 50,
+            ..Default::default()
+        }, FizzBuzz::default(), 1);
+
+        h.generate_tx.testing_send_all([1,2,3].map(Envelope::unstamped).to_vec(), true);
+        h.heartbeat_tx.testing_send_all([0,1,2].map(BeatTime::untimed).to_vec(), true);
+        h.graph.start();
+
+        sleep(Duration::from_millis(300));
+
+        h.graph.request_shutdown();
+        h.graph.block_until_stopped(Duration::from_secs(1))?;
+        assert_steady_rx_eq_take!(&h.logger_rx, sequenced([FizzBuzzMessage::Value(1)]));
+        let state = crate::state_lock::settled(&h.probe).expect("worker state");
+        // every 50ms spent waiting for room at the logger is reported, then waited out again
+        assert!(state.stalls >= 2, "stalls: {}", state.stalls);
+        assert_eq!(state.values_processed, 1);
+        Ok(())
+    }
+
+    /// No beats while the heartbeat holds the pipeline on purpose is a pause, not a stall.
+    #[test]
+    fn test_worker_waits_out_a_hold_without_stalling() -> Result<(), Box<dyn Error>> {
+        let args = MainArg { worker_stall_ms: 50, ..Default::default() };
+        args.hold.set(true);
+        let mut h = Harness::spawn(args, FizzBuzz::default());
+
+        h.generate_tx.testing_send_all([1,2,3].map(Envelope::unstamped).to_vec(), true);
+        h.graph.start();
+
+        sleep(Duration::from_millis(300));
+
+        // the heartbeat stops with the run, having sent nothing
+        h.heartbeat_tx.testing_send_all(Vec::new(), true);
+        h.graph.request_shutdown();
+        h.graph.block_until_stopped(Duration::from_secs(1))?;
+        let state = crate::state_lock::settled(&h.probe).expect("worker state");
+        assert_eq!(state.stalls, 0);
+        Ok(())
+    }
+
+    /// Beats from a skipping, stuttering heartbeat still release one value each; the gap and
+    /// the repeat are counted.
+    #[test]
+    fn test_worker_notes_missed_and_repeated_beats() -> Result<(), Box<dyn Error>> {
+        let mut h = Harness::spawn(MainArg::default(), FizzBuzz::default());
+        let logged = AssertSink::new().expect_all(sequenced([1, 2, 4, 7].map(FizzBuzzMessage::Value)))
+            .build(&mut h.graph, "ExpectLogged", h.logger_rx);
+
+        h.generate_tx.testing_send_all([1,2,4,7].map(Envelope::unstamped).to_vec(), true);
+        h.heartbeat_tx.testing_send_all([0,1,4,4].map(BeatTime::untimed).to_vec(), true);
+        h.graph.start();
+
+        logged.wait(Duration::from_secs(1))?;
+        h.graph.request_shutdown();
+        h.graph.block_until_stopped(Duration::from_secs(1))?;
+        let state = crate::state_lock::settled(&h.probe).expect("worker state");
+        assert_eq!((state.last_beat, state.beats_missed, state.beats_repeated), (Some(4), 2, 1));
+        Ok(())
+    }
+
+    /// A pool worker hears every beat but gets only its shard's values; the beats beyond its
+    /// values are spent idle, leaving one beat per value.
+    #[test]
+    fn test_pool_worker_spends_surplus_beats_idle() -> Result<(), Box<dyn Error>> {
+        let mut h = Harness::spawn(MainArg { workers: 2, ..Default::default() }, FizzBuzz::default());
+        let logged = AssertSink::new().expect_all(sequenced([1, 7].map(FizzBuzzMessage::Value)))
+            .build(&mut h.graph, "ExpectLogged", h.logger_rx);
+
+        h.generate_tx.testing_send_all([1,7].map(Envelope::unstamped).to_vec(), true);
+        h.heartbeat_tx.testing_send_all([0,1,2,3,4,5].map(BeatTime::untimed).to_vec(), true);
+        h.graph.start();
+
+        logged.wait(Duration::from_secs(1))?;
+        h.graph.request_shutdown();
+        h.graph.block_until_stopped(Duration::from_secs(1))?;
+        let state = crate::state_lock::settled(&h.probe).expect("worker state");
+        assert_eq!((state.values_processed, state.heartbeats_processed, state.beats_idle, state.beats_missed), (2, 2, 4, 0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_worker_without_heartbeat() -> Result<(), Box<dyn Error>> {
+        // the heartbeat is never written or closed, as when build_graph leaves it out
+        let mut h = Harness::spawn(MainArg { no_heartbeat: true, ..Default::default() }, FizzBuzz::default());
+
+        h.generate_tx.testing_send_all([0,1,2,3,4,5].map(Envelope::unstamped).to_vec(), true);
+        h.graph.start();
+
+        sleep(Duration::from_millis(100));
+
+        h.graph.request_shutdown();
+        h.graph.block_until_stopped(Duration::from_secs(1))?;
+        assert_steady_rx_eq_take!(&h.logger_rx, sequenced([FizzBuzzMessage::FizzBuzz
+                                                        ,FizzBuzzMessage::Value(1)
+                                                        ,FizzBuzzMessage::Value(2)
+                                                        ,FizzBuzzMessage::Fizz
+                                                        ,FizzBuzzMessage::Value(4)
+                                                        ,FizzBuzzMessage::Buzz]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_worker_validation() -> Result<(), Box<dyn Error>> {
+        let mut h = Harness::spawn(MainArg {
+            accept_range: Some(1..=30),
+            reject_multiples_of: vec![11],
+            ..Default::default()
+        }, FizzBuzz::default());
+
+        h.generate_tx.testing_send_all([0,3,22,31,5].map(Envelope::unstamped).to_vec(), true);
+        h.heartbeat_tx.testing_send_all([0].map(BeatTime::untimed).to_vec(), true);
+        h.graph.start();
+
+        sleep(Duration::from_millis(100));
+
+        h.graph.request_shutdown();
+        h.graph.block_until_stopped(Duration::from_secs(1))?;
+        assert_steady_rx_eq_take!(&h.logger_rx, sequenced([FizzBuzzMessage::Fizz
+                                                        ,FizzBuzzMessage::Buzz]));
+        assert_steady_rx_eq_take!(&h.rejected_rx, [RejectedValue::new(0, RejectReason::OutOfRange)
+                                                ,RejectedValue::new(22, RejectReason::MultipleOf(11))
+                                                ,RejectedValue::new(31, RejectReason::OutOfRange)]);
+        Ok(())
+    }
+
+    /// Fed by a network source with --ack-after-commit, the worker acknowledges every value it
+    /// takes, numbered as the source sent them, whatever became of it.
+    #[cfg(feature = "grpc")]
+    #[test]
+    fn test_worker_acknowledges_each_value() -> Result<(), Box<dyn Error>> {
+        let mut h = Harness::spawn(MainArg {
+            grpc_listen: Some("127.0.0.1:50519".parse()?),
+            ack_after_commit: true,
+            accept_range: Some(0..=30),
+            ..Default::default()
+        }, FizzBuzz::default());
+
+        h.generate_tx.testing_send_all([3, 40, 5].map(Envelope::unstamped).to_vec(), true);
+        h.heartbeat_tx.testing_send_all((1..=3).map(BeatTime::untimed).collect(), true);
+        h.graph.start();
+
+        sleep(Duration::from_millis(100));
+
+        h.graph.request_shutdown();
+        h.graph.block_until_stopped(Duration::from_secs(1))?;
+        assert_steady_rx_eq_take!(&h.logger_rx, sequenced([FizzBuzzMessage::Fizz, FizzBuzzMessage::Buzz]));
+        assert_steady_rx_eq_take!(&h.acks_rx, [SourceAck { seq: 1, outcome: AckOutcome::Committed }
+                                            ,SourceAck { seq: 2, outcome: AckOutcome::Rejected(RejectReason::OutOfRange) }
+                                            ,SourceAck { seq: 3, outcome: AckOutcome::Committed }]);
+        Ok(())
+    }
+
+    /// The same worker runs another domain: validation sees the classifier's key, and
+    /// barriers arrive as that domain's marker.
+    #[test]
+    fn test_worker_runs_another_domain() -> Result<(), Box<dyn Error>> {
+        let mut h = Harness::spawn(MainArg {
+            accept_range: Some(0..=200),
+            checkpoint_every: 2,
+            ..Default::default()
+        }, Threshold(80));
+
+        let readings = [(1, 20), (2, 95), (3, 500)].map(|(sensor, celsius)| Envelope::unstamped(Reading { sensor, celsius }));
+        h.generate_tx.testing_send_all(readings.to_vec(), true);
+        h.heartbeat_tx.testing_send_all((1..=3).map(BeatTime::untimed).collect(), true);
+        h.graph.start();
+
+        sleep(Duration::from_millis(100));
 
-        generate_tx.testing_send_all(vec![0,1,2,3,4,5], true);
-        heartbeat_tx.testing_send_all(vec![0], true);
-        graph.start();
+        h.graph.request_shutdown();
+        h.graph.block_until_stopped(Duration::from_secs(1))?;
+        let received: Vec<Alarm> = h.logger_rx.testing_take_all().into_iter().map(|e| e.payload).collect();
+        assert_eq!(received, [Alarm::Normal(1), Alarm::Barrier(2), Alarm::Hot(2)]);
+        assert_steady_rx_eq_take!(&h.rejected_rx, [RejectedValue::new(500, RejectReason::OutOfRange)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_worker_forwards_barrier() -> Result<(), Box<dyn Error>> {
+        let mut h = Harness::spawn(MainArg {
+            checkpoint_every: 2,
+            ..Default::default()
+        }, FizzBuzz::default());
+
+        // one value per heartbeat, so the barrier lands after exactly two values
+        h.generate_tx.testing_send_all([1,2,3].map(Envelope::unstamped).to_vec(), true);
+        h.heartbeat_tx.testing_send_all([0,1,2].map(BeatTime::untimed).to_vec(), true);
+        h.graph.start();
+
+        sleep(Duration::from_millis(100));
+
+        h.graph.request_shutdown();
+        h.graph.block_until_stopped(Duration::from_secs(1))?;
+        // the barrier is stamped when forwarded, so compare payloads and sequence numbers only
+        let received: Vec<_> = h.logger_rx.testing_take_all().into_iter().map(|e| (e.payload, e.seq)).collect();
+        assert_eq!(received, [(FizzBuzzMessage::Value(1), 1)
+                             ,(FizzBuzzMessage::Value(2), 2)
+                             ,(FizzBuzzMessage::Barrier(2), 3)
+                             ,(FizzBuzzMessage::Fizz, 4)]);
+        assert_steady_rx_eq_take!(&h.checkpoint_rx, [WorkerCheckpoint {
+            barrier: 2,
+            heartbeats_processed: 2,
+            values_processed: 2,
+            messages_sent: 2,
+            values_rejected: 0,
+            seq: 3,
+        }]);
+        Ok(())
+    }
+
+    /// Each value takes the wall time of the heartbeat that released it as its event time.
+    #[test]
+    fn test_worker_dates_values_by_heartbeat() -> Result<(), Box<dyn Error>> {
+        let mut h = Harness::spawn(MainArg { event_window_ms: 1, ..Default::default() }, FizzBuzz::default());
+
+        h.generate_tx.testing_send_all([1,2].map(Envelope::unstamped).to_vec(), true);
+        h.heartbeat_tx.testing_send_all(vec![BeatTime { beat: 0, wall_micros: 5_000 }, BeatTime { beat: 1, wall_micros: 7_000 }], true);
+        h.graph.start();
 
         sleep(Duration::from_millis(100));
 
-        graph.request_shutdown();
-        graph.block_until_stopped(Duration::from_secs(1))?;
-        assert_steady_rx_eq_take!(&logger_rx, [FizzBuzzMessage::FizzBuzz
-                                              ,FizzBuzzMessage::Value(1)
-                                              ,FizzBuzzMessage::Value(2)
-                                              ,FizzBuzzMessage::Fizz
-                                              ,FizzBuzzMessage::Value(4)
-                                              ,FizzBuzzMessage::Buzz]);
+        h.graph.request_shutdown();
+        h.graph.block_until_stopped(Duration::from_secs(1))?;
+        let received: Vec<_> = h.logger_rx.testing_take_all().into_iter().map(|e| (e.payload, e.event_micros)).collect();
+        assert_eq!(received, [(FizzBuzzMessage::Value(1), 5_000), (FizzBuzzMessage::Value(2), 7_000)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_worker_batches() -> Result<(), Box<dyn Error>> {
+        let mut h = Harness::spawn(MainArg {
+            batch_size: 4,
+            reject_multiples_of: vec![5],
+            ..Default::default()
+        }, FizzBuzz::default());
+
+        // one full batch, then the remainder flushed once no heartbeats are left
+        h.generate_tx.testing_send_all([1,2,3,4,5,6].map(Envelope::unstamped).to_vec(), true);
+        h.heartbeat_tx.testing_send_all([0].map(BeatTime::untimed).to_vec(), true);
+        h.graph.start();
+
+        sleep(Duration::from_millis(100));
+
+        h.graph.request_shutdown();
+        h.graph.block_until_stopped(Duration::from_secs(1))?;
+        assert_steady_rx_eq_take!(&h.logger_rx, sequenced([FizzBuzzMessage::Value(1)
+                                                        ,FizzBuzzMessage::Value(2)
+                                                        ,FizzBuzzMessage::Fizz
+                                                        ,FizzBuzzMessage::Value(4)
+                                                        ,FizzBuzzMessage::Fizz]));
+        assert_steady_rx_eq_take!(&h.rejected_rx, [RejectedValue::new(5, RejectReason::MultipleOf(5))]);
+        let state = crate::state_lock::settled(&h.probe).expect("worker state");
+        assert_eq!((state.values_processed, state.batches_flushed, state.heartbeats_processed), (6, 2, 1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_worker_sends_priorities_first_from_its_window() -> Result<(), Box<dyn Error>> {
+        let mut h = Harness::spawn(MainArg {
+            batch_size: 2,
+            priority: vec![crate::urgency::parse_priority_rule("3=5").expect("rule")],
+            priority_window: 4,
+            priority_aging_ms: 60_000,
+            ..Default::default()
+        }, FizzBuzz::default());
+
+        // the multiples of 3 go ahead of whatever waits beside them in the window of 4
+        let values = (1..=6u64).map(|value| Envelope::new(value).with_priority(if value.is_multiple_of(3) { 5 } else { 0 })).collect();
+        h.generate_tx.testing_send_all(values, true);
+        h.heartbeat_tx.testing_send_all([0].map(BeatTime::untimed).to_vec(), true);
+        h.graph.start();
+
+        sleep(Duration::from_millis(100));
+
+        h.graph.request_shutdown();
+        h.graph.block_until_stopped(Duration::from_secs(1))?;
+        let received: Vec<_> = h.logger_rx.testing_take_all().into_iter().map(|e| (e.payload, e.seq)).collect();
+        assert_eq!(received, [(FizzBuzzMessage::Fizz, 1), (FizzBuzzMessage::Value(1), 2), (FizzBuzzMessage::Fizz, 3),
+                              (FizzBuzzMessage::Value(2), 4), (FizzBuzzMessage::Value(4), 5), (FizzBuzzMessage::Buzz, 6)]);
+        let state = crate::state_lock::settled(&h.probe).expect("worker state");
+        assert_eq!((state.values_processed, state.batches_flushed, state.waiting.len()), (6, 3, 0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_worker_fast_paths_overdue_values() -> Result<(), Box<dyn Error>> {
+        let mut h = Harness::spawn(MainArg {
+            compute: vec![ComputeModule::DigitSum],
+            processing_deadline_ms: Some(200),
+            deadline_action: DeadlineAction::FastPath,
+            ..Default::default()
+        }, FizzBuzz::default());
+
+        // two values generated well before the deadline, then one fresh
+        let mut values = vec![Envelope::new(3), Envelope::new(4)];
+        sleep(Duration::from_millis(300));
+        values.push(Envelope::new(7));
+        h.generate_tx.testing_send_all(values, true);
+        h.heartbeat_tx.testing_send_all([0,1,2].map(BeatTime::untimed).to_vec(), true);
+        h.graph.start();
+
+        sleep(Duration::from_millis(100));
+
+        h.graph.request_shutdown();
+        h.graph.block_until_stopped(Duration::from_secs(1))?;
+        // overdue values are classified without the compute modules
+        let received: Vec<_> = h.logger_rx.testing_take_all().into_iter().map(|e| (e.payload, e.derived.digit_sum)).collect();
+        assert_eq!(received, [(FizzBuzzMessage::Fizz, None), (FizzBuzzMessage::Value(4), None), (FizzBuzzMessage::Value(7), Some(7))]);
+        let state = crate::state_lock::settled(&h.probe).expect("worker state");
+        assert_eq!(state.overdue, DeadlineCounts { fast_path: 2, ..Default::default() });
+        Ok(())
+    }
+
+    #[test]
+    fn test_worker_dead_letters_overdue_batch_values() -> Result<(), Box<dyn Error>> {
+        let mut h = Harness::spawn(MainArg {
+            batch_size: 4,
+            processing_deadline_ms: Some(200),
+            deadline_action: DeadlineAction::DeadLetter,
+            ..Default::default()
+        }, FizzBuzz::default());
+
+        let mut values = vec![Envelope::new(1), Envelope::new(2)];
+        sleep(Duration::from_millis(300));
+        values.push(Envelope::new(3));
+        h.generate_tx.testing_send_all(values, true);
+        h.heartbeat_tx.testing_send_all([0].map(BeatTime::untimed).to_vec(), true);
+        h.graph.start();
+
+        sleep(Duration::from_millis(100));
+
+        h.graph.request_shutdown();
+        h.graph.block_until_stopped(Duration::from_secs(1))?;
+        let received: Vec<_> = h.logger_rx.testing_take_all().into_iter().map(|e| e.payload).collect();
+        assert_eq!(received, [FizzBuzzMessage::Fizz]);
+        assert_steady_rx_eq_take!(&h.rejected_rx, [RejectedValue::new(1, RejectReason::Expired)
+                                                ,RejectedValue::new(2, RejectReason::Expired)]);
+        let state = crate::state_lock::settled(&h.probe).expect("worker state");
+        assert_eq!(state.overdue, DeadlineCounts { dead_lettered: 2, ..Default::default() });
+        assert_eq!((state.values_processed, state.values_rejected), (3, 2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_worker_spills_residue_after_drain_deadline() -> Result<(), Box<dyn Error>> {
+        let backlog = std::env::temp_dir().join(format!("robust-backlog-{}.txt", crate::arg::new_run_id()));
+        // room for two messages and a logger that never reads, so the rest cannot drain
+        let mut h = Harness::spawn_with_logger_capacity(MainArg {
+            drain_worker_ms: 50,
+            backlog_file: Some(backlog.clone()),
+            ..Default::default()
+        }, FizzBuzz::default(), 2);
+
+        h.generate_tx.testing_send_all([1,2,3,4,5].map(Envelope::unstamped).to_vec(), true);
+        h.heartbeat_tx.testing_send_all([0,1,2,3,4].map(BeatTime::untimed).to_vec(), true);
+        h.graph.start();
+
+        sleep(Duration::from_millis(100));
+
+        h.graph.request_shutdown();
+        h.graph.block_until_stopped(Duration::from_secs(1))?;
+        assert_steady_rx_eq_take!(&h.logger_rx, sequenced([FizzBuzzMessage::Value(1)
+                                                        ,FizzBuzzMessage::Value(2)]));
+        let state = crate::state_lock::settled(&h.probe).expect("worker state");
+        assert_eq!((state.values_processed, state.residue), (2, 3));
+        let saved = std::fs::read_to_string(&backlog)?;
+        let _ = std::fs::remove_file(&backlog);
+        assert_eq!(saved, "WORKER 3\nWORKER 4\nWORKER 5\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_worker_traces_sampled_values() -> Result<(), Box<dyn Error>> {
+        use steady_logger::*;
+        let _guard = start_log_capture();
+
+        let mut h = Harness::spawn(MainArg::default(), FizzBuzz::default());
+
+        // only the second value was sampled at its source
+        h.generate_tx.testing_send_all(vec![Envelope::unstamped(1), Envelope::unstamped(3).with_trace(2)], true);
+        h.heartbeat_tx.testing_send_all([0].map(BeatTime::untimed).to_vec(), true);
+        h.graph.start();
+
+        sleep(Duration::from_millis(100));
+
+        h.graph.request_shutdown();
+        h.graph.block_until_stopped(Duration::from_secs(1))?;
+        let received: Vec<_> = h.logger_rx.testing_take_all().into_iter().map(|e| (e.payload, e.seq, e.trace)).collect();
+        assert_eq!(received, [(FizzBuzzMessage::Value(1), 1, 0), (FizzBuzzMessage::Fizz, 2, 2)]);
+        assert_in_logs!(["trace id=2 stage=WORKER event=classified payload=Fizz seq=2"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_worker_asks_the_enricher_and_times_out() -> Result<(), Box<dyn Error>> {
+        let mut h = Harness::spawn(MainArg {
+            compute: vec![ComputeModule::DigitSum],
+            enricher_timeout_ms: Some(50),
+            ..Default::default()
+        }, FizzBuzz::default());
+
+        // a stale reply from before a restart, then the answer to the first lookup; the second is never answered
+        let answer = Derived { digit_sum: Some(42), ..Default::default() };
+        h.lookup_reply_tx.testing_send_all(vec![LookupReply { id: 0, derived: Default::default() }, LookupReply { id: 1, derived: answer }], false);
+        h.generate_tx.testing_send_all(vec![Envelope::new(3), Envelope::new(4)], true);
+        h.heartbeat_tx.testing_send_all([0,1].map(BeatTime::untimed).to_vec(), true);
+        h.graph.start();
+
+        sleep(Duration::from_millis(300));
+
+        h.graph.request_shutdown();
+        h.graph.block_until_stopped(Duration::from_secs(1))?;
+        assert_eq!(h.lookup_request_rx.testing_take_all(), [LookupRequest { id: 1, value: 3 }, LookupRequest { id: 2, value: 4 }]);
+        let received: Vec<_> = h.logger_rx.testing_take_all().into_iter().map(|e| (e.payload, e.derived.digit_sum)).collect();
+        assert_eq!(received, [(FizzBuzzMessage::Fizz, Some(42)), (FizzBuzzMessage::Value(4), None)]);
+        let state = crate::state_lock::settled(&h.probe).expect("worker state");
+        assert_eq!((state.lookup_id, state.lookups_answered, state.lookups_timed_out), (2, 1, 1));
         Ok(())
     }
 }
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use crate::rate_limit::TokenBucket;
+use crate::MainArg;
+
+/// Distinct clients tracked at once; the one heard from least recently makes room for a new one.
+const MAX_CLIENTS: usize = 1024;
+
+/// How one external client has been sending.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct ClientRate {
+    bucket: Option<TokenBucket>,
+    pub(crate) arrivals: u64,
+    /// Arrivals turned away or held back for being over `--ingest-rate`.
+    pub(crate) over_rate: u64,
+    /// Arrivals per second over the last full one-second window.
+    pub(crate) per_sec: f64,
+    window_start: Instant,
+    window_arrivals: u64,
+    last_seen: Instant,
+}
+
+impl ClientRate {
+    fn take(&mut self, now: Instant) -> Result<(), Duration> {
+        let Some(bucket) = &mut self.bucket else {
+            return Ok(());
+        };
+        if bucket.try_take(now) { Ok(()) } else { Err(bucket.delay()) }
+    }
+}
+
+/// Admission control for the external sources. Each client's arrival rate is measured,
+/// and with `--ingest-rate` each gets its own token bucket of that many values a second,
+/// so one busy producer cannot take the whole channel from the rest.
+/// Lives in the source actor's state, so a restart neither forgets rates nor refills buckets.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Admission {
+    per_sec: Option<u32>,
+    clients: HashMap<String, ClientRate>,
+    /// Clients dropped to make room under `MAX_CLIENTS`; one heard from again starts afresh.
+    pub(crate) evicted: u64,
+}
+
+impl Admission {
+    pub(crate) fn new(per_sec: Option<u32>) -> Self {
+        Admission { per_sec, clients: HashMap::new(), evicted: 0 }
+    }
+
+    pub(crate) fn from_args(args: &MainArg) -> Self {
+        Admission::new(args.ingest_rate)
+    }
+
+    /// Counts one arrival from `client` for its rate, and decides whether it may enter now.
+    /// When it may not, returns how long until it could; the caller refuses or waits.
+    pub(crate) fn admit(&mut self, client: &str, now: Instant) -> Result<(), Duration> {
+        let rate = self.client(client, now);
+        rate.arrivals += 1;
+        rate.last_seen = now;
+        let elapsed = now.saturating_duration_since(rate.window_start);
+        if elapsed >= Duration::from_secs(1) {
+            rate.per_sec = rate.window_arrivals as f64 / elapsed.as_secs_f64();
+            rate.window_start = now;
+            rate.window_arrivals = 0;
+        }
+        rate.window_arrivals += 1;
+        let verdict = rate.take(now);
+        if verdict.is_err() {
+            rate.over_rate += 1;
+        }
+        verdict
+    }
+
+    /// Asks again for an arrival that `admit` held back, without counting it twice.
+    pub(crate) fn retry(&mut self, client: &str, now: Instant) -> Result<(), Duration> {
+        self.client(client, now).take(now)
+    }
+
+    fn client(&mut self, client: &str, now: Instant) -> &mut ClientRate {
+        if !self.clients.contains_key(client) {
+            if self.clients.len() >= MAX_CLIENTS
+                && let Some(idle) = self.clients.iter().min_by_key(|(_, r)| r.last_seen).map(|(c, _)| c.clone()) {
+                self.clients.remove(&idle);
+                self.evicted += 1;
+            }
+            let rate = ClientRate {
+                bucket: self.per_sec.map(TokenBucket::new),
+                arrivals: 0,
+                over_rate: 0,
+                per_sec: 0.0,
+                window_start: now,
+                window_arrivals: 0,
+                last_seen: now,
+            };
+            self.clients.insert(client.to_string(), rate);
+        }
+        self.clients.get_mut(client).expect("inserted above")
+    }
+
+    /// One `client: arrivals (over rate), rate` entry per client, busiest first, for the shutdown log.
+    pub(crate) fn summary(&self) -> String {
+        let mut clients: Vec<_> = self.clients.iter().collect();
+        clients.sort_by(|a, b| b.1.arrivals.cmp(&a.1.arrivals).then(a.0.cmp(b.0)));
+        let entries: Vec<String> = clients
+            .iter()
+            .map(|(client, r)| format!("{}: {} ({} over rate), {:.1}/s", client, r.arrivals, r.over_rate, r.per_sec))
+            .collect();
+        if entries.is_empty() { "none".to_string() } else { entries.join("; ") }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod admission_tests {
+    use super::*;
+
+    #[test]
+    fn test_admission_per_client() {
+        let start = Instant::now();
+        let mut admission = Admission::new(Some(2));
+        assert_eq!(admission.admit("a", start), Ok(()));
+        assert_eq!(admission.admit("a", start), Ok(()));
+        let wait = admission.admit("a", start).expect_err("over rate");
+        assert_eq!(wait, Duration::from_millis(500));
+        // another client has its own allowance
+        assert_eq!(admission.admit("b", start), Ok(()));
+        assert_eq!(admission.admit("a", start + Duration::from_millis(500)), Ok(()));
+        assert_eq!(admission.admit("a", start + Duration::from_secs(2)), Ok(()));
+        assert_eq!(admission.summary(), "a: 5 (1 over rate), 2.0/s; b: 1 (0 over rate), 0.0/s");
+        // a held-back arrival asks again without being counted twice
+        assert!(admission.admit("b", start).is_ok() && admission.admit("b", start).is_err());
+        assert!(admission.retry("b", start).is_err());
+        assert_eq!(admission.retry("b", start + Duration::from_millis(500)), Ok(()));
+        assert_eq!((admission.clients["b"].arrivals, admission.clients["b"].over_rate), (3, 1));
+
+        let mut unlimited = Admission::new(None);
+        assert!((0..100).all(|_| unlimited.admit("a", start).is_ok()));
+        assert_eq!(Admission::default().summary(), "none");
+
+        // past the cap the client heard from least recently makes room
+        let mut crowded = Admission::new(None);
+        for client in 0..MAX_CLIENTS + 2 {
+            assert!(crowded.admit(&client.to_string(), start + Duration::from_millis(client as u64)).is_ok());
+        }
+        assert_eq!((crowded.clients.len(), crowded.evicted), (MAX_CLIENTS, 2));
+        assert!(!crowded.clients.contains_key("0") && crowded.clients.contains_key("2"));
+    }
+}
@@ -0,0 +1,171 @@
+use std::time::{Duration, Instant};
+use crate::MainArg;
+
+/// Samples a detector learns from before it judges any, so the ramp-up at the start
+/// of a run (or of a restarted reconciler) does not alert.
+const WARMUP: u32 = 5;
+/// Smallest spread a detector allows, as a share of its mean, so a very steady series
+/// does not alert on ordinary jitter.
+const MIN_SPREAD_RATIO: f64 = 0.05;
+/// Smallest spread in messages per second for throughput, and in restarts per second.
+const THROUGHPUT_FLOOR: f64 = 1.0;
+const RESTARTS_FLOOR: f64 = 0.1;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Direction {
+    Spike,
+    Drop,
+}
+
+impl Direction {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Spike => "spike",
+            Direction::Drop => "drop",
+        }
+    }
+}
+
+/// A value too far from what its series has been doing lately.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct Anomaly {
+    pub(crate) value: f64,
+    /// The series' moving average just before this value.
+    pub(crate) expected: f64,
+    pub(crate) direction: Direction,
+}
+
+/// Exponentially weighted mean and variance of one series. A value more than `sigma`
+/// standard deviations from the mean is an anomaly. Anomalies are learned like any other
+/// value, so a lasting change alerts for a few samples and then becomes the new normal.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct EwmaDetector {
+    alpha: f64,
+    sigma: f64,
+    /// Smallest spread in the series' own units, for series that sit at zero.
+    floor: f64,
+    mean: f64,
+    variance: f64,
+    seen: u32,
+}
+
+impl EwmaDetector {
+    pub(crate) fn new(alpha: f64, sigma: f64, floor: f64) -> Self {
+        EwmaDetector { alpha, sigma, floor, mean: 0.0, variance: 0.0, seen: 0 }
+    }
+
+    pub(crate) fn observe(&mut self, value: f64) -> Option<Anomaly> {
+        self.seen += 1;
+        if self.seen == 1 {
+            self.mean = value;
+            return None;
+        }
+        let deviation = value - self.mean;
+        let spread = self.variance.sqrt().max(MIN_SPREAD_RATIO * self.mean.abs()).max(self.floor);
+        let anomaly = (self.seen > WARMUP && deviation.abs() > self.sigma * spread).then_some(Anomaly {
+            value,
+            expected: self.mean,
+            direction: if deviation > 0.0 { Direction::Spike } else { Direction::Drop },
+        });
+        self.mean += self.alpha * deviation;
+        self.variance = (1.0 - self.alpha) * (self.variance + self.alpha * deviation * deviation);
+        anomaly
+    }
+}
+
+/// Turns a counter that only grows into a rate per second, at most one per `period`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct RateSeries {
+    period: Duration,
+    last: Option<(u64, Instant)>,
+}
+
+impl RateSeries {
+    pub(crate) fn new(period: Duration) -> Self {
+        RateSeries { period, last: None }
+    }
+
+    /// The rate since the previous sample, or None for the first call and until a period has passed.
+    pub(crate) fn sample(&mut self, count: u64, now: Instant) -> Option<f64> {
+        let Some((last_count, last_at)) = self.last else {
+            self.last = Some((count, now));
+            return None;
+        };
+        let elapsed = now.duration_since(last_at);
+        if elapsed < self.period || elapsed.is_zero() {
+            return None;
+        }
+        self.last = Some((count, now));
+        Some(count.saturating_sub(last_count) as f64 / elapsed.as_secs_f64())
+    }
+}
+
+/// Watches logged throughput and the restart rate across all stages for sudden changes,
+/// under `--anomaly-sigma`. Held as a local in the reconciler, so a restart learns afresh.
+pub(crate) struct AnomalyWatch {
+    throughput: (RateSeries, EwmaDetector),
+    restarts: (RateSeries, EwmaDetector),
+}
+
+impl AnomalyWatch {
+    /// None unless `--anomaly-sigma` was given. Rates are taken once per reconcile interval.
+    pub(crate) fn from_args(args: &MainArg) -> Option<Self> {
+        let period = Duration::from_millis(args.reconcile_ms);
+        args.anomaly_sigma.map(|sigma| AnomalyWatch {
+            throughput: (RateSeries::new(period), EwmaDetector::new(args.anomaly_alpha, sigma, THROUGHPUT_FLOOR)),
+            restarts: (RateSeries::new(period), EwmaDetector::new(args.anomaly_alpha, sigma, RESTARTS_FLOOR)),
+        })
+    }
+
+    /// Feeds the messages logged so far and the starts of every stage so far,
+    /// returning the anomalies found, named by series.
+    pub(crate) fn observe(&mut self, logged: u64, starts: u64, now: Instant) -> Vec<(&'static str, Anomaly)> {
+        let mut found = Vec::new();
+        for (name, (series, detector), count) in [("throughput", &mut self.throughput, logged), ("restarts", &mut self.restarts, starts)] {
+            if let Some(anomaly) = series.sample(count, now).and_then(|rate| detector.observe(rate)) {
+                found.push((name, anomaly));
+            }
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod anomaly_tests {
+    use super::*;
+
+    #[test]
+    fn test_detector_flags_sudden_changes() {
+        let mut detector = EwmaDetector::new(0.2, 4.0, THROUGHPUT_FLOOR);
+        // jitter around 100/s is normal, during warm-up or after
+        for value in [100.0, 180.0, 100.0, 98.0, 103.0, 99.0, 101.0, 97.0, 102.0] {
+            assert_eq!(detector.observe(value), None, "{}", value);
+        }
+        let drop = detector.observe(20.0).expect("drop");
+        assert_eq!(drop.direction, Direction::Drop);
+        assert!((95.0..105.0).contains(&drop.expected));
+        // a lasting change stops alerting once learned
+        let alerts = (0..30).filter(|_| detector.observe(20.0).is_some()).count();
+        assert!(alerts < 10, "{}", alerts);
+        assert_eq!(detector.observe(20.0), None);
+        assert_eq!(detector.observe(200.0).map(|a| a.direction), Some(Direction::Spike));
+    }
+
+    #[test]
+    fn test_restart_spike_from_zero() {
+        let args = MainArg { anomaly_sigma: Some(4.0), reconcile_ms: 1000, ..Default::default() };
+        let mut watch = AnomalyWatch::from_args(&args).expect("enabled");
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        for second in 0..10 {
+            assert!(watch.observe(100 * second, 3, at(second)).is_empty());
+        }
+        // half a second is too soon for another rate
+        assert!(watch.observe(1000, 3, start + Duration::from_millis(9500)).is_empty());
+        let found = watch.observe(1000, 4, at(10));
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, "restarts");
+        assert_eq!(found[0].1.direction, Direction::Spike);
+        assert!(AnomalyWatch::from_args(&MainArg::default()).is_none());
+    }
+}
@@ -1,8 +1,37 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::actor::heartbeat::PipelineHold;
+use crate::backfill::{parse_backfill_transform, BackfillTransform};
+use crate::backpressure::{parse_fill_rule, FillRule};
+use crate::deadline::{parse_deadline_action, DeadlineAction};
+use crate::delay::{parse_channel_delay, ChannelDelay};
+use crate::enrich::{parse_compute, ComputeModule};
+use crate::expect::{parse_expectation, Expectation};
+use crate::failure::{parse_fail_at, parse_failure_budget, parse_heartbeat_fault, FailureBudget, FailurePoint, HeartbeatFault};
+use crate::fault::{parse_fault, parse_fault_plan, Fault, FaultPlanFile};
+use crate::fsync::{parse_fsync, FsyncPolicy};
+use crate::input::{parse_generator_source, GeneratorSource};
+use crate::logging::{parse_log_format, parse_log_levels, LogFormat, LogLevels};
+use crate::multi_graph::GraphRole;
+use crate::priority::{parse_nice, NiceSetting};
+use crate::wait::{parse_wait, WaitSetting};
+use crate::record::{parse_record_format, RecordFormat};
+use crate::rules::{parse_rule, parse_rules_file, Rule, RulesFile};
+use crate::quota::{parse_over_quota, parse_tenant_quota, OverQuota, TenantQuota};
+use crate::rate_limit::{parse_rate_limit, RateLimit};
+use crate::sink::{parse_sink, parse_sink_failure, SinkFailurePolicy, SinkSpec};
+use crate::slo::parse_ratio;
+use crate::state_lock::{parse_lock_policy, LockPolicy};
+use crate::trace::{parse_trace_sample, TraceSample};
+use crate::urgency::{parse_priority_rule, PriorityRule};
+use crate::validation::parse_accept_range;
+use crate::window::{parse_active_window, ActiveWindow};
 
 /// Command-line arguments for the Steady State application
 #[derive(Parser, Debug, PartialEq, Clone)]
-pub(crate) struct MainArg {
+pub struct MainArg {
     /// Rate in milliseconds between actor operations (e.g., heartbeats)
     #[arg(short = 'r', long = "rate", default_value = "1000")]
     pub(crate) rate_ms: u64,
@@ -10,6 +39,509 @@ pub(crate) struct MainArg {
     /// Number of beats (loop iterations before shutdown)
     #[arg(short = 'b', long = "beats", default_value = "120")]
     pub(crate) beats: u64,
+
+    /// Unique id for this run, stamped on every log line and the final report.
+    /// Generated at startup unless supplied, so overlapping soak runs can be told apart.
+    #[arg(long = "run-id", default_value_t = new_run_id())]
+    pub(crate) run_id: String,
+
+    /// Read settings from this TOML file, keyed by long option name and optionally grouped in
+    /// sections named for stages, e.g. `[logger]`; options on the command line override it
+    #[arg(long = "config")]
+    pub(crate) config: Option<PathBuf>,
+
+    /// Only values inside this range are classified, e.g. `0..1000000` or `0..=999999`.
+    /// Values outside it go to the rejects side channel.
+    #[arg(long = "accept-range", value_parser = parse_accept_range)]
+    pub(crate) accept_range: Option<RangeInclusive<u64>>,
+
+    /// Reject values that are multiples of this divisor; may be repeated.
+    #[arg(long = "reject-multiples-of", value_parser = clap::value_parser!(u64).range(1..))]
+    pub(crate) reject_multiples_of: Vec<u64>,
+
+    /// Classify by this rule instead of the built-in Fizz and Buzz, as `DIVISOR=LABEL`, e.g.
+    /// `7=Bazz`; may be repeated. A value gets the labels of every rule it matches, in order
+    #[arg(long = "rule", value_parser = parse_rule)]
+    pub(crate) rule: Vec<Rule>,
+
+    /// Read more `--rule` settings from a TOML file of `[[rule]]` tables with a `divisor` and a
+    /// `label`; they come before those given with `--rule`
+    #[arg(long = "rules-file", value_parser = parse_rules_file)]
+    pub(crate) rules_file: Option<RulesFile>,
+
+    /// Compute modules whose results travel with each classification, e.g.
+    /// `digit-sum,parity,hash`. Log records append the results as JSON
+    #[arg(long = "compute", value_delimiter = ',', value_parser = parse_compute)]
+    pub(crate) compute: Vec<ComputeModule>,
+
+    /// Run the `--compute` modules in an ENRICHER actor the worker asks for each value's results,
+    /// waiting at most this many milliseconds for the answer; a value it does not answer in time
+    /// is classified without them. Not with `--batch-size`
+    #[arg(long = "enricher-timeout-ms", value_parser = clap::value_parser!(u64).range(1..), conflicts_with = "batch_size")]
+    pub(crate) enricher_timeout_ms: Option<u64>,
+
+    /// Milliseconds an actor waits to lock its state before reporting a `state-lock event=timeout`
+    #[arg(long = "state-lock-timeout-ms", default_value = "5000")]
+    pub(crate) state_lock_timeout_ms: u64,
+
+    /// What an actor does when its state lock times out, or the instance before it panicked while
+    /// using the state: `recover` reports it and carries on, `abort` keeps the actor down and
+    /// stops the pipeline as Ctrl-C would
+    #[arg(long = "state-lock-policy", default_value = "recover", value_parser = parse_lock_policy)]
+    pub(crate) state_lock_policy: LockPolicy,
+
+    /// Reschedule an actor's demonstration panic as `ACTOR=N`, or turn it off with `ACTOR=off`;
+    /// may be repeated. Defaults are heartbeat=7 (beat), generator=13 (message), worker=33 (value)
+    /// and logger=41 (value)
+    #[arg(long = "fail-at", value_parser = parse_fail_at)]
+    pub(crate) fail_at: Vec<FailurePoint>,
+
+    /// Make the heartbeat misbehave like a faulty timer every Nth beat, as `skip=N` to leave the
+    /// beat out or `stutter=N` to send it twice; may be repeated. For checking how the worker copes
+    #[arg(long = "heartbeat-fault", value_parser = parse_heartbeat_fault)]
+    pub(crate) heartbeat_fault: Vec<HeartbeatFault>,
+
+    /// Inject a fault for chaos testing as `ACTOR=ACTION@WHEN`: ACTION is `panic`, `delay:MS` or
+    /// `send-fail`, and WHEN a step `N` or `every:N` of that actor; may be repeated.
+    /// E.g. `worker=delay:25ms@every:10`. Added to the `--fail-at` panics
+    #[arg(long = "fault", value_parser = parse_fault)]
+    pub(crate) fault: Vec<Fault>,
+
+    /// Read more `--fault` settings from a file, one per line, with `#` comments
+    #[arg(long = "fault-plan", value_parser = parse_fault_plan)]
+    pub(crate) fault_plan: Option<FaultPlanFile>,
+
+    /// Restarts and showstopper drops the demonstration failures may cost in any hour, as
+    /// `restarts=N`, `drops=N` or both comma separated. Once either is used up the reconciler
+    /// raises an alert and turns `--fail-at`, `--fault` and `--heartbeat-fault` off for the rest of the run
+    #[arg(long = "failure-budget", value_parser = parse_failure_budget)]
+    pub(crate) failure_budget: Option<FailureBudget>,
+
+    /// Hold every message on one channel for this long before passing it on, as `CHANNEL=MS`
+    /// with CHANNEL one of heartbeat-worker, generator-worker or worker-logger; may be repeated.
+    /// For checking timeouts, deadlines and stall detection under a known latency
+    #[arg(long = "channel-delay", value_parser = parse_channel_delay)]
+    pub(crate) channel_delay: Vec<ChannelDelay>,
+
+    /// Have the generator first replay the values in this file, one per line, before it generates
+    /// live ones. Lines of the worker's in a `--backlog-file` replay too
+    #[arg(long = "backfill")]
+    pub(crate) backfill: Option<PathBuf>,
+
+    /// Replay only these lines of the `--backfill` file, numbered from 0, e.g. `100..200`
+    #[arg(long = "backfill-range", value_parser = parse_accept_range, requires = "backfill")]
+    pub(crate) backfill_range: Option<RangeInclusive<u64>>,
+
+    /// Change each value the `--backfill` replays before it is sent, as `clamp:LO..HI` or
+    /// `mask:BITS`; may be repeated, applied in order. For fixing poisoned values replayed from
+    /// a `--dead-letter-file`
+    #[arg(long = "backfill-transform", value_parser = parse_backfill_transform, requires = "backfill")]
+    pub(crate) backfill_transform: Vec<BackfillTransform>,
+
+    /// The value the generator goes live at once the backfill is done; by default one past the
+    /// last value replayed, so live values carry on where the history left off
+    #[arg(long = "live-from", requires = "backfill")]
+    pub(crate) live_from: Option<u64>,
+
+    /// Where the generator's live values come from: `sequence`, the counter it always counted,
+    /// `stdin`, or `file:<path>`, one value per line. Blank lines and `#` comments are skipped;
+    /// once the input runs out the generator waits for the run to end
+    #[arg(long = "source", default_value = "sequence", value_parser = parse_generator_source)]
+    pub(crate) source: GeneratorSource,
+
+    /// Milliseconds between reconciler audits of the published stage counters
+    #[arg(long = "reconcile-ms", default_value = "1000")]
+    pub(crate) reconcile_ms: u64,
+
+    /// Where the logger delivers records: `log`, `stdout` (bare records, diagnostics stay on
+    /// stderr), `null` or `none` (count only), `loopback` (checked against the generator's
+    /// values), `file:<path>`, or `mqtt:<host:port/topic>` with the mqtt feature. Also accepted
+    /// as `--data-stream`
+    #[arg(long = "sink", visible_alias = "data-stream", default_value = "log", value_parser = parse_sink)]
+    pub(crate) sink: SinkSpec,
+
+    /// How records are written to the sink and the WAL: `text` lines for people, `json` for
+    /// one object per record, or `csv` rows of seq, kind, value, total, logged, ts, tenant and
+    /// derived. Unrelated to `--log-format`, which formats diagnostics
+    #[arg(long = "record-format", default_value = "text", value_parser = parse_record_format)]
+    pub(crate) record_format: RecordFormat,
+
+    /// Write-ahead log for logger records. When set, records are committed here first
+    /// and replayed to the sink in order once it recovers from an outage.
+    #[arg(long = "wal")]
+    pub(crate) wal: Option<PathBuf>,
+
+    /// Compress the WAL with zstd at this level, 1 to 22, one frame per record so every frame
+    /// ends at a commit point; a frame cut short by a crash is truncated when the logger restarts
+    #[cfg(feature = "zstd")]
+    #[arg(long = "wal-zstd-level", value_parser = clap::value_parser!(i32).range(1..=22))]
+    pub(crate) wal_zstd_level: Option<i32>,
+
+    /// When the file sink, the WAL and a `multi-graph` queue are forced onto disk: `never` leaves it to the operating
+    /// system, `interval:<ms>` syncs at most that often and at a clean stop, `every-message`
+    /// syncs each record before it counts as committed. The final report gives how many
+    /// committed records a power failure could have taken
+    #[arg(long = "fsync", default_value = "never", value_parser = parse_fsync)]
+    pub(crate) fsync: FsyncPolicy,
+
+    /// Milliseconds the worker gets after shutdown is requested to drain its input;
+    /// whatever is left after that goes to the backlog file
+    #[arg(long = "drain-worker-ms", default_value = "400")]
+    pub(crate) drain_worker_ms: u64,
+
+    /// Further milliseconds the logger gets, after the worker's deadline, to flush its input
+    #[arg(long = "drain-logger-ms", default_value = "400")]
+    pub(crate) drain_logger_ms: u64,
+
+    /// File that collects items still undrained when a stage's deadline passes, one
+    /// `STAGE item` line each; without it they are counted and dropped
+    #[arg(long = "backlog-file")]
+    pub(crate) backlog_file: Option<PathBuf>,
+
+    /// File the showstoppers dropped by the worker and the logger are appended to, one JSON
+    /// line each with the actor, peek count, drop time and sequence number; without it they are
+    /// only logged. `--backfill` replays the worker's values from it
+    #[arg(long = "dead-letter-file")]
+    pub(crate) dead_letter_file: Option<PathBuf>,
+
+    /// File that framework events (startup, restarts, showstoppers, shutdown phases, alerts)
+    /// are appended to as NDJSON, one JSON object per line, apart from the message output
+    #[arg(long = "events-file")]
+    pub(crate) events_file: Option<PathBuf>,
+
+    /// Once the sink has been failing this many milliseconds, the logger pauses the heartbeat and
+    /// generator so no backlog builds up, and releases them when the sink recovers; unset disables this
+    #[arg(long = "pause-on-sink-outage-ms")]
+    pub(crate) pause_on_sink_outage_ms: Option<u64>,
+
+    /// Pace the generator so about this many values are in flight between it and the logger,
+    /// steering by the commit rate the logger reports back; unset leaves the generator unthrottled
+    #[arg(long = "flow-target-inflight", value_parser = clap::value_parser!(u64).range(1..))]
+    pub(crate) flow_target_inflight: Option<u64>,
+
+    /// Proportional gain of the flow controller, in values per second per value of in-flight error;
+    /// the default of 10 closes a gap in about the 100ms between the logger's reports
+    #[arg(long = "flow-kp", default_value = "10.0")]
+    pub(crate) flow_kp: f64,
+
+    /// Integral gain of the flow controller, applied to the accumulated in-flight error
+    #[arg(long = "flow-ki", default_value = "1.0")]
+    pub(crate) flow_ki: f64,
+
+    /// Cap records of one kind at N per second as `KIND=N`, e.g. `value=10`; may be repeated.
+    /// Kinds are fizz, buzz, fizzbuzz, value and rule, the last for records a `--rule` matched. Records over the cap are counted but not shown;
+    /// only the log and stdout sinks are capped, and the WAL and durable sinks take every record
+    #[arg(long = "log-rate-limit", value_parser = parse_rate_limit)]
+    pub(crate) log_rate_limit: Vec<RateLimit>,
+
+    /// Milliseconds between attempts to reach a sink that is failing
+    #[arg(long = "sink-retry-ms", default_value = "500")]
+    pub(crate) sink_retry_ms: u64,
+
+    /// What the logger does when a kind of sink keeps failing, as `SINK=STRATEGY`; may be repeated.
+    /// `degrade`, the default, holds the record and retries; `panic` or `panic:N` panics after
+    /// 3 or N failed writes in a row so the logger restarts with a fresh sink, e.g. `file=panic:5`
+    #[arg(long = "sink-failure", value_parser = parse_sink_failure)]
+    pub(crate) sink_failure: Vec<SinkFailurePolicy>,
+
+    /// Run only inside this local time-of-day window, e.g. `08:00-18:00`; the heartbeat and generator
+    /// pause outside it and resume when it opens again. A start after the end wraps past midnight
+    #[arg(long = "active-window", value_parser = parse_active_window)]
+    pub(crate) active_window: Option<ActiveWindow>,
+
+    /// Leave the heartbeat out of the graph: the worker takes values as soon as they arrive,
+    /// and the run ends after `--rate` x `--beats` milliseconds or on Ctrl-C.
+    /// Checkpoints and event time need the heartbeat and are refused here; control socket pause/resume does nothing
+    #[arg(long = "no-heartbeat")]
+    pub(crate) no_heartbeat: bool,
+
+    /// File the heartbeat keeps its count in after every beat. When it already exists at
+    /// startup, the heartbeat resumes from that count and still stops at the `--beats` the
+    /// run was first started with, so a restarted process finishes the run rather than redoing it
+    #[arg(long = "restore-from")]
+    pub(crate) restore_from: Option<PathBuf>,
+
+    /// Directory the logger keeps the last consistent checkpoint in, written atomically as each
+    /// one completes. When a checkpoint is already there at startup, every stage resumes from
+    /// it, so a killed process carries on from its last cut rather than from zero. Needs
+    /// `--checkpoint-every`; whatever passed the cut before the kill is processed again
+    #[arg(long = "state-dir")]
+    pub(crate) state_dir: Option<PathBuf>,
+
+    /// Runs the generator and worker on the heartbeat and logger's shared thread as well,
+    /// so the whole pipeline takes one core; a stage given its own `--nice` still gets its own thread
+    #[arg(long = "troupe")]
+    pub(crate) troupe: bool,
+
+    /// In troupe mode, how many iterations in a row the generator or logger may run with work
+    /// ready before yielding the shared thread to the others; 0 leaves them unbounded
+    #[arg(long = "troupe-budget", default_value = "64")]
+    pub(crate) troupe_budget: u64,
+
+    /// Every Nth heartbeat becomes a checkpoint barrier that flows down the pipeline
+    /// and snapshots each stage as it passes; 0 disables checkpoints
+    #[arg(long = "checkpoint-every", default_value = "0")]
+    pub(crate) checkpoint_every: u64,
+
+    /// Width in milliseconds of tumbling event-time windows; 0 leaves event time off. The heartbeat
+    /// stamps each beat with the wall clock, the worker dates the values a beat releases by it, and
+    /// the logger closes a window once a value dated past its end arrives
+    #[arg(long = "event-window-ms", default_value = "0")]
+    pub(crate) event_window_ms: u64,
+
+    /// The heartbeat treats a wall-clock step larger than this many milliseconds, against the
+    /// monotonic clock, as a clock jump: it is logged and beat times re-anchor instead of
+    /// running backwards; 0 turns detection off
+    #[arg(long = "clock-jump-ms", default_value = "1000")]
+    pub(crate) clock_jump_ms: u64,
+
+    /// Worker batches up to this many classified values and flushes them to the logger on
+    /// each heartbeat or when the batch fills; 0 keeps one value per heartbeat
+    #[arg(long = "batch-size", default_value = "0")]
+    pub(crate) batch_size: usize,
+
+    /// Give values that are multiples of MULTIPLE the priority LEVEL, 0 to 255, higher first;
+    /// repeatable, the highest level a value matches wins and values matching none have 0.
+    /// Within each batch the worker sends higher priorities first
+    #[arg(long = "priority", value_name = "MULTIPLE=LEVEL", value_parser = parse_priority_rule)]
+    pub(crate) priority: Vec<PriorityRule>,
+
+    /// In batch mode, the worker takes up to this many values off its input and sends the
+    /// `--batch-size` best ranked of them on each flush, leaving the rest to wait; 0 ranks only
+    /// within the batch. Not with `--checkpoint-every` or `--ack-after-commit`
+    #[arg(long = "priority-window", default_value = "0", conflicts_with = "checkpoint_every")]
+    pub(crate) priority_window: usize,
+
+    /// Milliseconds a value waits for each level its rank rises above its priority, so values
+    /// held back by higher priorities still go in the end
+    #[arg(long = "priority-aging-ms", default_value = "100")]
+    pub(crate) priority_aging_ms: u64,
+
+    /// Run this many workers, each with its own state: a ROUTER sends each value to worker
+    /// `value % N` and a FANIN merges their output back into generation order for the logger.
+    /// 1 keeps the single WORKER. Not with `--batch-size`, `--checkpoint-every`, `--enricher-timeout-ms` or `--ack-after-commit`
+    #[arg(long = "workers", default_value = "1", value_parser = clap::value_parser!(u64).range(1..=crate::NAME_POOL.len() as u64),
+          conflicts_with_all = ["batch_size", "checkpoint_every", "enricher_timeout_ms"])]
+    pub(crate) workers: u64,
+
+    /// Milliseconds the worker waits for a heartbeat, a value and room at the logger before it
+    /// reports which of them it is still missing, counts a stall and waits again; 0 waits silently
+    #[arg(long = "worker-stall-ms", default_value = "10000")]
+    pub(crate) worker_stall_ms: u64,
+
+    /// Milliseconds a value may wait between the generator and the worker; older values
+    /// get the `--deadline-action` when the worker picks them up
+    #[arg(long = "processing-deadline-ms", value_parser = clap::value_parser!(u64).range(1..))]
+    pub(crate) processing_deadline_ms: Option<u64>,
+
+    /// What the worker does with a value past `--processing-deadline-ms`: `fast-path` classifies it
+    /// without the `--compute` modules, `dead-letter` rejects it as expired, `log` warns and processes it
+    #[arg(long = "deadline-action", default_value = "log", value_parser = parse_deadline_action)]
+    pub(crate) deadline_action: DeadlineAction,
+
+    /// Milliseconds between reports of drift between the wall and monotonic clocks
+    #[arg(long = "clock-report-ms", default_value = "10000")]
+    pub(crate) clock_report_ms: u64,
+
+    /// Milliseconds between the logger's reports of generation-to-commit latency, p50 and p99 over
+    /// the period beside those of the run so far; 0 leaves latency to the final report
+    #[arg(long = "latency-report-ms", default_value = "10000")]
+    pub(crate) latency_report_ms: u64,
+
+    /// Latency SLO in milliseconds from generation to logger commit; unset disables SLO checks
+    #[arg(long = "latency-slo-ms")]
+    pub(crate) latency_slo_ms: Option<u64>,
+
+    /// Largest fraction of messages per window allowed to miss the SLO before alerting
+    #[arg(long = "latency-slo-ratio", default_value = "0.01", value_parser = parse_ratio)]
+    pub(crate) latency_slo_ratio: f64,
+
+    /// Milliseconds in each SLO evaluation window
+    #[arg(long = "latency-slo-window-ms", default_value = "1000")]
+    pub(crate) latency_slo_window_ms: u64,
+
+    /// Alert when logged throughput or the restart rate moves more than this many standard
+    /// deviations from its moving average; unset disables anomaly detection
+    #[arg(long = "anomaly-sigma")]
+    pub(crate) anomaly_sigma: Option<f64>,
+
+    /// Weight of each new rate in the anomaly detector's moving average and variance;
+    /// higher follows changes sooner but tolerates less
+    #[arg(long = "anomaly-alpha", default_value = "0.2", value_parser = parse_ratio)]
+    pub(crate) anomaly_alpha: f64,
+
+    /// Alert when more than this many messages are between generation and logger commit, an
+    /// early sign that a stage has stopped committing; unset only tracks the high-water mark
+    #[arg(long = "inflight-alert", value_parser = clap::value_parser!(u64).range(1..))]
+    pub(crate) inflight_alert: Option<u64>,
+
+    /// Alert when a channel stays at least PERCENT full for SECONDS, as `CHANNEL=PERCENT/SECONDS`,
+    /// e.g. `generated=90/5`; may be repeated. The channels are generated, heartbeat, classified
+    /// and rejected, checked at the reconciler's samples of their depth
+    #[arg(long = "fill-alert", value_parser = parse_fill_rule)]
+    pub(crate) fill_alert: Vec<FillRule>,
+
+    /// Trace K of every N generated messages end to end, e.g. `1/1000`. Each stage logs a
+    /// `trace id=...` event as a sampled message passes, so its path can be rebuilt from the log
+    #[arg(long = "trace-sample", value_parser = parse_trace_sample)]
+    pub(crate) trace_sample: Option<TraceSample>,
+
+    /// Label generated values with one of N tenants, `partition-K` for value modulo N, to try
+    /// per-tenant output and counters without an external source; 0 leaves them unlabeled.
+    /// The gRPC source labels by client address and the MQTT source by topic regardless
+    #[arg(long = "tenant-partitions", default_value = "0")]
+    pub(crate) tenant_partitions: u32,
+
+    /// Most values per second a tenant may bring in, as `TENANT=N` with `*` for every tenant
+    /// without its own; may be repeated. Applied where values enter: the generator and the TCP,
+    /// gRPC and MQTT sources. Unlabeled values are never limited
+    #[arg(long = "tenant-quota", value_parser = parse_tenant_quota)]
+    pub(crate) tenant_quota: Vec<TenantQuota>,
+
+    /// What happens to a value over its tenant's quota: `throttle` holds it until the quota
+    /// allows it (the gRPC source, which cannot hold a call, refuses it either way), `reject` drops it
+    #[arg(long = "over-quota", default_value = "throttle", value_parser = parse_over_quota)]
+    pub(crate) over_quota: OverQuota,
+
+    /// OS niceness for one actor's thread as `ACTOR=N`, e.g. `logger=10`; may be repeated.
+    /// Higher is lower priority; going below 0 needs CAP_SYS_NICE. Linux only.
+    #[arg(long = "nice", value_parser = parse_nice)]
+    pub(crate) nice: Vec<NiceSetting>,
+
+    /// How one actor waits for work, as `ACTOR=STRATEGY`; may be repeated. `event` (the default)
+    /// wakes only when its channels are ready, `poll:MS` wakes every MS milliseconds whatever its
+    /// channels hold, `hybrid:MS` wakes on either. Applies to the generator, worker (pool workers
+    /// by name, e.g. `worker_1`), logger, dead_letter and enricher
+    #[arg(long = "wait", value_parser = parse_wait)]
+    pub(crate) wait: Vec<WaitSetting>,
+
+    /// Write the Prometheus `build_info` gauge to this file at startup, for a textfile
+    /// collector to pick up beside the telemetry server's own metrics
+    #[arg(long = "build-info-prom")]
+    pub(crate) build_info_prom: Option<PathBuf>,
+
+    /// Diagnostic log levels, as a level for everything and `ACTOR=LEVEL` entries for single
+    /// actors, comma separated: `worker=trace,logger=info` turns the worker up without the rest
+    #[arg(long = "log-level", default_value = "info", value_parser = parse_log_levels)]
+    pub(crate) log_level: LogLevels,
+
+    /// How diagnostics are written to stderr: `text` for people, or `json` for one object
+    /// per line with level, target, actor, run_id and fields, for container log pipelines
+    #[arg(long = "log-format", default_value = "text", value_parser = parse_log_format)]
+    pub(crate) log_format: LogFormat,
+
+    /// Keep each actor's recent log records and events in memory, and on a panic write them
+    /// with the backtrace to a crash file in this directory, for post-mortems of soak runs
+    #[arg(long = "crash-dir")]
+    pub(crate) crash_dir: Option<PathBuf>,
+
+    /// Log records and events kept per actor for a crash file
+    #[arg(long = "crash-events", default_value = "64", value_parser = clap::value_parser!(u32).range(1..))]
+    pub(crate) crash_events: u32,
+
+    /// Save the final report to this file as JSON when the run ends, for `compare-runs`
+    #[arg(long = "report-file")]
+    pub(crate) report_file: Option<PathBuf>,
+
+    /// Conditions the final report must meet, e.g. `generated=1000,restarts<=5,loss=0`; the run
+    /// fails if any does not. Operators are =, <=, >=, < and >; may be repeated
+    #[arg(long = "expect", value_delimiter = ',', value_parser = parse_expectation)]
+    pub(crate) expect: Vec<Expectation>,
+
+    /// Accept pause, resume, status, snapshot and kill commands on this Unix socket
+    #[cfg(unix)]
+    #[arg(long = "control-socket")]
+    pub(crate) control_socket: Option<PathBuf>,
+
+    /// File of `LEVEL TOKEN` lines, LEVEL being read, operate or admin. Each control connection
+    /// must then send `auth TOKEN` first, and may run only what its level allows: status and
+    /// snapshot need read, the pause and resume commands operate, kill admin
+    #[cfg(unix)]
+    #[arg(long = "control-tokens", requires = "control_socket")]
+    pub(crate) control_tokens: Option<PathBuf>,
+
+    /// Read values from the durable queue in this directory in place of the built-in generator,
+    /// picking up after the last one read; the `multi-graph` subcommand fills it from the same process
+    #[arg(long = "queue-dir")]
+    pub(crate) queue_dir: Option<PathBuf>,
+
+    /// Which of the graphs of a `multi-graph` run these arguments build; set by the subcommand.
+    #[arg(skip)]
+    pub(crate) graph: GraphRole,
+
+    /// Raised by the heartbeat while it holds back beats on purpose, for the worker's stall check.
+    #[arg(skip)]
+    pub(crate) hold: PipelineHold,
+
+    /// Listen on this address for TCP clients sending newline-delimited values, in place of the
+    /// built-in generator; a full pipeline stops reading from them until it catches up
+    #[arg(long = "tcp-listen")]
+    pub(crate) tcp_listen: Option<std::net::SocketAddr>,
+
+    /// Serve the gRPC Ingest API on this address in place of the built-in generator; IPv6
+    /// addresses go in brackets, and `[::]:PORT` takes IPv4 clients too
+    #[cfg(feature = "grpc")]
+    #[arg(long = "grpc-listen")]
+    pub(crate) grpc_listen: Option<std::net::SocketAddr>,
+
+    /// Subscribe to numeric payloads on this MQTT `host:port/topic` in place of the built-in generator
+    #[cfg(feature = "mqtt")]
+    #[arg(long = "mqtt-source", value_parser = crate::mqtt::parse_endpoint)]
+    pub(crate) mqtt_source: Option<crate::mqtt::MqttEndpoint>,
+
+    /// Most values per second each external client may submit. The gRPC source refuses the
+    /// excess with RESOURCE_EXHAUSTED; the MQTT source, whose broker is its one client, holds back
+    /// its acknowledgement instead. Unset admits everything
+    #[cfg(any(feature = "grpc", feature = "mqtt"))]
+    #[arg(long = "ingest-rate", value_parser = clap::value_parser!(u32).range(1..))]
+    pub(crate) ingest_rate: Option<u32>,
+
+    /// Answer each gRPC call, or acknowledge each MQTT message, only once the worker has committed
+    /// the value, reporting whether it was classified, rejected, dropped or spilled, instead of as
+    /// soon as it is in the generator channel
+    #[cfg(any(feature = "grpc", feature = "mqtt"))]
+    #[arg(long = "ack-after-commit", conflicts_with_all = ["workers", "priority_window"])]
+    pub(crate) ack_after_commit: bool,
+
+    /// Run as a testing build with every actor but the worker simulated, and let an external harness
+    /// drive them over a line protocol on this loopback address until it sends `bow`
+    #[cfg(feature = "stage-control")]
+    #[arg(long = "stage-listen", value_parser = crate::stage_remote::parse_stage_listen)]
+    pub(crate) stage_listen: Option<std::net::SocketAddr>,
+
+    /// Prefix for MQTT client ids. Keep it stable so the broker resumes the same sessions
+    /// across runs, and unique per pipeline sharing a broker
+    #[cfg(feature = "mqtt")]
+    #[arg(long = "mqtt-client-id", default_value = "steady-state-robust")]
+    pub(crate) mqtt_client_id: String,
+
+    /// PEM certificate chain the gRPC source serves TLS with, and the MQTT links present to the
+    /// broker; needs --tls-key
+    #[cfg(all(feature = "tls", any(feature = "grpc", feature = "mqtt")))]
+    #[arg(long = "tls-cert", requires = "tls_key")]
+    pub(crate) tls_cert: Option<PathBuf>,
+
+    /// PEM private key for --tls-cert
+    #[cfg(all(feature = "tls", any(feature = "grpc", feature = "mqtt")))]
+    #[arg(long = "tls-key", requires = "tls_cert")]
+    pub(crate) tls_key: Option<PathBuf>,
+
+    /// PEM roots: the MQTT links connect over TLS and check the broker against them, and the gRPC
+    /// source, serving TLS, accepts only clients with a certificate they sign
+    #[cfg(all(feature = "tls", any(feature = "grpc", feature = "mqtt")))]
+    #[arg(long = "tls-ca")]
+    pub(crate) tls_ca: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+}
+
+impl MainArg {
+    /// Parses the process's command line like `parse`, filling in from a `--config` file every
+    /// option the command line leaves out, and exits with clap's message if either is wrong.
+    pub fn parse_with_config() -> Self {
+        crate::config::parse_from(std::env::args_os()).unwrap_or_else(|e| e.exit())
+    }
 }
 
 impl Default for MainArg {
@@ -17,6 +549,176 @@ impl Default for MainArg {
         MainArg {
             rate_ms: 1000,
             beats: 120,
+            run_id: new_run_id(),
+            config: None,
+            accept_range: None,
+            reject_multiples_of: Vec::new(),
+            rule: Vec::new(),
+            rules_file: None,
+            compute: Vec::new(),
+            enricher_timeout_ms: None,
+            state_lock_timeout_ms: 5000,
+            state_lock_policy: LockPolicy::Recover,
+            fail_at: Vec::new(),
+            heartbeat_fault: Vec::new(),
+            fault: Vec::new(),
+            fault_plan: None,
+            failure_budget: None,
+            channel_delay: Vec::new(),
+            backfill: None,
+            backfill_range: None,
+            backfill_transform: Vec::new(),
+            live_from: None,
+            source: GeneratorSource::Sequence,
+            reconcile_ms: 1000,
+            sink: SinkSpec::Log,
+            record_format: RecordFormat::Text,
+            wal: None,
+            #[cfg(feature = "zstd")]
+            wal_zstd_level: None,
+            fsync: FsyncPolicy::Never,
+            drain_worker_ms: 400,
+            drain_logger_ms: 400,
+            backlog_file: None,
+            dead_letter_file: None,
+            events_file: None,
+            pause_on_sink_outage_ms: None,
+            flow_target_inflight: None,
+            flow_kp: 10.0,
+            flow_ki: 1.0,
+            log_rate_limit: Vec::new(),
+            sink_retry_ms: 500,
+            sink_failure: Vec::new(),
+            active_window: None,
+            no_heartbeat: false,
+            restore_from: None,
+            state_dir: None,
+            troupe: false,
+            troupe_budget: 64,
+            checkpoint_every: 0,
+            event_window_ms: 0,
+            clock_jump_ms: 1000,
+            batch_size: 0,
+            priority: Vec::new(),
+            priority_window: 0,
+            priority_aging_ms: 100,
+            workers: 1,
+            worker_stall_ms: 10000,
+            processing_deadline_ms: None,
+            deadline_action: DeadlineAction::Log,
+            clock_report_ms: 10000,
+            latency_report_ms: 10000,
+            latency_slo_ms: None,
+            latency_slo_ratio: 0.01,
+            latency_slo_window_ms: 1000,
+            anomaly_sigma: None,
+            anomaly_alpha: 0.2,
+            inflight_alert: None,
+            fill_alert: Vec::new(),
+            trace_sample: None,
+            tenant_partitions: 0,
+            tenant_quota: Vec::new(),
+            over_quota: OverQuota::Throttle,
+            nice: Vec::new(),
+            wait: Vec::new(),
+            build_info_prom: None,
+            log_level: LogLevels::default(),
+            crash_dir: None,
+            crash_events: 64,
+            log_format: LogFormat::Text,
+            report_file: None,
+            expect: Vec::new(),
+            #[cfg(unix)]
+            control_socket: None,
+            #[cfg(unix)]
+            control_tokens: None,
+            queue_dir: None,
+            graph: GraphRole::Pipeline,
+            hold: PipelineHold::default(),
+            tcp_listen: None,
+            #[cfg(feature = "grpc")]
+            grpc_listen: None,
+            #[cfg(feature = "mqtt")]
+            mqtt_source: None,
+            #[cfg(any(feature = "grpc", feature = "mqtt"))]
+            ingest_rate: None,
+            #[cfg(any(feature = "grpc", feature = "mqtt"))]
+            ack_after_commit: false,
+            #[cfg(feature = "stage-control")]
+            stage_listen: None,
+            #[cfg(feature = "mqtt")]
+            mqtt_client_id: "steady-state-robust".to_string(),
+            #[cfg(all(feature = "tls", any(feature = "grpc", feature = "mqtt")))]
+            tls_cert: None,
+            #[cfg(all(feature = "tls", any(feature = "grpc", feature = "mqtt")))]
+            tls_key: None,
+            #[cfg(all(feature = "tls", any(feature = "grpc", feature = "mqtt")))]
+            tls_ca: None,
+            command: None,
         }
     }
 }
+
+/// Tools that run instead of the pipeline.
+#[derive(Subcommand, Debug, PartialEq, Clone)]
+pub(crate) enum Command {
+    /// Compare two reports saved with `--report-file` and list what regressed;
+    /// exits with status 1 if anything did
+    CompareRuns {
+        /// Report of the reference run
+        baseline: PathBuf,
+        /// Report of the run under test
+        candidate: PathBuf,
+        /// Percent a metric may worsen before it counts as a regression
+        #[arg(long = "tolerance-pct", default_value = "5")]
+        tolerance_pct: f64,
+    },
+    /// Run the pipeline as a child process and restart it whenever it exits abnormally, waiting
+    /// longer after each failure in a row; the pipeline's own arguments follow `--`
+    Supervise {
+        /// Abnormal exits in a row before giving up, leaving with the child's exit code
+        #[arg(long = "max-restarts", default_value = "5")]
+        max_restarts: u32,
+        /// Milliseconds before the first restart, doubled after each failure in a row
+        #[arg(long = "backoff-ms", default_value = "1000")]
+        backoff_ms: u64,
+        /// Longest wait between restarts, in milliseconds
+        #[arg(long = "backoff-max-ms", default_value = "60000")]
+        backoff_max_ms: u64,
+        /// A child that stayed up this many milliseconds was healthy, so its exit resets the count and the backoff
+        #[arg(long = "healthy-ms", default_value = "60000")]
+        healthy_ms: u64,
+        /// Crash dumps to keep in the child's `--crash-dir`; older ones are deleted after each exit
+        #[arg(long = "keep-crash-dumps", default_value = "20")]
+        keep_crash_dumps: usize,
+        /// Arguments for the pipeline
+        #[arg(last = true)]
+        child_args: Vec<String>,
+    },
+    /// Run the pipeline with the logger's records fed back to a verifier that checks them
+    /// against the generator's values as they arrive, instead of to a sink; the run fails
+    /// if any record does not match
+    Loopback,
+    /// Run two graphs in one process: an ingest graph whose generator fills a durable queue in
+    /// `--queue-dir`, and the pipeline, reading that queue in the generator's place
+    MultiGraph {
+        /// Directory of the queue between the two graphs, created if need be
+        #[arg(long = "queue-dir")]
+        queue_dir: PathBuf,
+    },
+}
+
+/// Derives a run id from the wall clock and process id.
+/// Sixteen hex digits is plenty to keep concurrent runs on one host distinct.
+pub(crate) fn new_run_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default();
+    // splitmix64 finalizer spreads the clock/pid bits across the whole id
+    let mut seed = nanos ^ ((std::process::id() as u64) << 32);
+    seed = (seed ^ (seed >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    seed = (seed ^ (seed >> 27)).wrapping_mul(0x94d049bb133111eb);
+    seed ^= seed >> 31;
+    format!("{:016x}", seed)
+}
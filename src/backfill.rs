@@ -0,0 +1,192 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Lines};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use steady_state::*;
+use crate::MainArg;
+
+/// Where the generator is in a `--backfill` run. Kept in its state, so a restart during the
+/// backfill picks up at the next historical line and one after the switch stays live.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GeneratorPhase {
+    /// Replaying the history file; `next` is the line number to send next.
+    Backfill { next: u64 },
+    Live,
+}
+
+impl GeneratorPhase {
+    pub(crate) fn from_args(args: &MainArg) -> Self {
+        match (&args.backfill, &args.backfill_range) {
+            (Some(_), Some(range)) => GeneratorPhase::Backfill { next: *range.start() },
+            (Some(_), None) => GeneratorPhase::Backfill { next: 0 },
+            (None, _) => GeneratorPhase::Live,
+        }
+    }
+}
+
+/// A `--backfill-transform`, applied to each value replayed, so poisoned values from a
+/// dead-letter file can be fixed on their way back in rather than dropped again.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BackfillTransform {
+    /// `clamp:LO..HI`: values outside the range become its nearest end.
+    Clamp(RangeInclusive<u64>),
+    /// `mask:BITS`: only these bits of the value are kept, stripping the rest.
+    Mask(u64),
+}
+
+impl BackfillTransform {
+    pub(crate) fn apply(&self, value: u64) -> u64 {
+        match self {
+            BackfillTransform::Clamp(range) => value.clamp(*range.start(), *range.end()),
+            BackfillTransform::Mask(bits) => value & bits,
+        }
+    }
+}
+
+/// Parses a `--backfill-transform`: `clamp:LO..HI` or `mask:BITS`, the bits in decimal or `0x` hex.
+pub(crate) fn parse_backfill_transform(text: &str) -> Result<BackfillTransform, String> {
+    let (kind, spec) = text
+        .split_once(':')
+        .ok_or_else(|| format!("expected clamp:LO..HI or mask:BITS, got '{}'", text))?;
+    match kind.trim().to_ascii_lowercase().as_str() {
+        "clamp" => Ok(BackfillTransform::Clamp(crate::validation::parse_accept_range(spec)?)),
+        "mask" => {
+            let spec = spec.trim();
+            let bits = match spec.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16),
+                None => spec.parse(),
+            };
+            bits.map(BackfillTransform::Mask).map_err(|e| format!("bad mask in '{}': {}", text, e))
+        }
+        other => Err(format!("unknown backfill transform '{}', expected clamp or mask", other)),
+    }
+}
+
+/// Reads historical values for the generator, one per line and numbered by line from 0.
+/// A line may carry a `WORKER ` prefix, so the worker's lines of a `--backlog-file` replay as
+/// they are, and the worker's letters of a `--dead-letter-file` replay too. Blank lines, comments and anything else are skipped, keeping their numbers.
+pub(crate) struct Backfill {
+    lines: Lines<BufReader<File>>,
+    /// Number of the line `lines` yields next.
+    position: u64,
+    /// The last line of the range to replay.
+    last: u64,
+    /// The most recent value read, with its line number, until the generator moves past it.
+    peeked: Option<(u64, u64)>,
+    /// Applied in order to each value read.
+    transforms: Vec<BackfillTransform>,
+    pub(crate) skipped: u64,
+    /// Values a transform changed.
+    pub(crate) transformed: u64,
+}
+
+impl Backfill {
+    pub(crate) fn open(path: &Path, range: Option<&RangeInclusive<u64>>, transforms: &[BackfillTransform]) -> io::Result<Self> {
+        let file = File::open(path)?;
+        Ok(Backfill {
+            lines: BufReader::new(file).lines(),
+            position: 0,
+            last: range.map_or(u64::MAX, |r| *r.end()),
+            peeked: None,
+            transforms: transforms.to_vec(),
+            skipped: 0,
+            transformed: 0,
+        })
+    }
+
+    /// The first value at line `from` or after it, with its line number, or None once the range
+    /// or the file is done. Asking again for the same line gives the same value, so a value is
+    /// only left behind once the generator asks for a later line.
+    pub(crate) fn value_at(&mut self, from: u64) -> io::Result<Option<(u64, u64)>> {
+        if let Some((line, value)) = self.peeked
+            && line >= from {
+            return Ok(Some((line, value)));
+        }
+        self.peeked = None;
+        while self.position <= self.last {
+            let Some(text) = self.lines.next().transpose()? else {
+                return Ok(None);
+            };
+            let line = self.position;
+            self.position += 1;
+            if line < from {
+                continue;
+            }
+            match parse_line(&text) {
+                Some(read) => {
+                    let value = self.transforms.iter().fold(read, |value, transform| transform.apply(value));
+                    if value != read {
+                        self.transformed += 1;
+                        debug!("Backfill line {} transformed from {} to {}", line, read, value);
+                    }
+                    self.peeked = Some((line, value));
+                    return Ok(self.peeked);
+                }
+                // The logger's dead letters are records with no value to replay.
+                None if text.trim().is_empty() || text.trim_start().starts_with(['#', '{']) => {}
+                None => {
+                    self.skipped += 1;
+                    warn!("Backfill skipping line {}, not a value: {:?}", line, text);
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn parse_line(text: &str) -> Option<u64> {
+    let text = text.trim();
+    if text.starts_with('{') {
+        return crate::actor::dead_letter::replay_value(text);
+    }
+    text.strip_prefix("WORKER ").unwrap_or(text).trim().parse().ok()
+}
+
+#[cfg(test)]
+pub(crate) mod backfill_tests {
+    use std::io::Write;
+    use super::*;
+
+    #[test]
+    fn test_value_at_reads_the_range() -> io::Result<()> {
+        let path = std::env::temp_dir().join(format!("robust-backfill-{}.txt", std::process::id()));
+        let mut file = File::create(&path)?;
+        write!(file, "10\n11\n# gap\nWORKER 13\nLOGGER 4 Fizz\n15\n16\n")?;
+        drop(file);
+
+        let mut backfill = Backfill::open(&path, Some(&(1..=5)), &[])?;
+        assert_eq!(backfill.value_at(1)?, Some((1, 11)));
+        // not moved past yet, as when the send did not go through
+        assert_eq!(backfill.value_at(1)?, Some((1, 11)));
+        assert_eq!(backfill.value_at(2)?, Some((3, 13)));
+        assert_eq!(backfill.value_at(4)?, Some((5, 15)));
+        assert_eq!(backfill.value_at(6)?, None);
+        assert_eq!(backfill.skipped, 1);
+
+        // a restart reopens the file and resumes at the line the state says is next
+        let mut resumed = Backfill::open(&path, None, &[])?;
+        assert_eq!(resumed.value_at(4)?, Some((5, 15)));
+        assert_eq!(resumed.value_at(6)?, Some((6, 16)));
+        assert_eq!(resumed.value_at(7)?, None);
+        std::fs::remove_file(&path)
+    }
+
+    /// A poisoned letter from a dead-letter file goes back in clamped, with its flag bits stripped.
+    #[test]
+    fn test_value_at_applies_transforms() -> io::Result<()> {
+        let path = std::env::temp_dir().join(format!("robust-backfill-transform-{}.txt", std::process::id()));
+        let mut file = File::create(&path)?;
+        file.write_all(concat!(r#"{"source":"WORKER","value":4294967396,"peeks":3}"#, "\n7\n",
+                               r#"{"source":"LOGGER","value":5,"peeks":3}"#, "\n").as_bytes())?;
+        drop(file);
+
+        let transforms = ["mask:0xffffffff", "clamp:0..=50"].map(|t| parse_backfill_transform(t).expect("transform"));
+        let mut backfill = Backfill::open(&path, None, &transforms)?;
+        assert_eq!(backfill.value_at(0)?, Some((0, 50)));
+        assert_eq!(backfill.value_at(1)?, Some((1, 7)));
+        assert_eq!(backfill.value_at(2)?, None);
+        assert_eq!((backfill.transformed, backfill.skipped), (1, 0));
+        assert!(parse_backfill_transform("round:10").is_err());
+        std::fs::remove_file(&path)
+    }
+}
@@ -0,0 +1,119 @@
+use std::time::{Duration, Instant};
+use crate::actor::reconciler::{ChannelDepth, DEPTH_CHANNELS};
+use crate::MainArg;
+
+/// One `--fill-alert` setting: alert when a channel stays at least `percent` full for `hold`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct FillRule {
+    /// The channel, by its place in `DEPTH_CHANNELS`.
+    pub(crate) channel: usize,
+    pub(crate) percent: u64,
+    pub(crate) hold: Duration,
+}
+
+/// Parses `CHANNEL=PERCENT/SECONDS`, e.g. `generated=90/5` or `generated=90%/5s`.
+pub(crate) fn parse_fill_rule(text: &str) -> Result<FillRule, String> {
+    let (channel, rest) = text
+        .split_once('=')
+        .ok_or_else(|| format!("expected CHANNEL=PERCENT/SECONDS, got '{}'", text))?;
+    let names: Vec<&str> = DEPTH_CHANNELS.iter().map(|&(name, _, _)| name).collect();
+    let channel = channel.trim().to_ascii_lowercase();
+    let channel = names
+        .iter()
+        .position(|&name| name == channel)
+        .ok_or_else(|| format!("unknown channel '{}', expected one of {}", channel, names.join(", ")))?;
+    let (percent, seconds) = rest
+        .split_once('/')
+        .ok_or_else(|| format!("expected PERCENT/SECONDS after the channel in '{}'", text))?;
+    let percent = percent.trim();
+    let percent = match percent.strip_suffix('%').unwrap_or(percent).parse() {
+        Ok(percent @ 1..=100) => percent,
+        Ok(_) => return Err(format!("percent must be 1 to 100 in '{}'", text)),
+        Err(e) => return Err(format!("bad percent in '{}': {}", text, e)),
+    };
+    let seconds = seconds.trim();
+    let seconds: u64 = seconds.strip_suffix('s').unwrap_or(seconds).parse().map_err(|e| format!("bad seconds in '{}': {}", text, e))?;
+    Ok(FillRule { channel, percent, hold: Duration::from_secs(seconds) })
+}
+
+/// A channel that has stayed past its `--fill-alert` threshold for the hold.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct FillAlert {
+    pub(crate) rule: FillRule,
+    pub(crate) depth: ChannelDepth,
+    /// How long it has been past the threshold, from the first sample there.
+    pub(crate) held: Duration,
+}
+
+/// Tracks each `--fill-alert` rule against the depth samples of its channel. A rule alerts once
+/// per stretch over its threshold, and again only after the channel has been back under it.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FillWatch {
+    rules: Vec<(FillRule, Stretch)>,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+struct Stretch {
+    /// When the channel's samples first reached the threshold, while they still do.
+    since: Option<Instant>,
+    alerted: bool,
+}
+
+impl FillWatch {
+    pub(crate) fn from_args(args: &MainArg) -> Self {
+        FillWatch { rules: args.fill_alert.iter().map(|&rule| (rule, Stretch::default())).collect() }
+    }
+
+    /// Takes in the depth of `channel` sampled `at`, returning the alerts it raises, and the
+    /// rules whose channel dropped back under their threshold after an alert.
+    pub(crate) fn observe(&mut self, channel: usize, depth: ChannelDepth, at: Instant) -> (Vec<FillAlert>, Vec<FillRule>) {
+        let (mut raised, mut cleared) = (Vec::new(), Vec::new());
+        for (rule, stretch) in self.rules.iter_mut().filter(|(rule, _)| rule.channel == channel) {
+            // A channel of no capacity was never sampled; it cannot be full.
+            if depth.capacity > 0 && depth.items * 100 >= rule.percent * depth.capacity {
+                let since = *stretch.since.get_or_insert(at);
+                let held = at.saturating_duration_since(since);
+                if held >= rule.hold && !stretch.alerted {
+                    stretch.alerted = true;
+                    raised.push(FillAlert { rule: *rule, depth, held });
+                }
+            } else {
+                if stretch.alerted {
+                    cleared.push(*rule);
+                }
+                *stretch = Stretch::default();
+            }
+        }
+        (raised, cleared)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod backpressure_tests {
+    use super::*;
+
+    #[test]
+    fn test_fill_alert_needs_the_hold() {
+        let rule = parse_fill_rule("generated=90%/5s").expect("valid rule");
+        assert_eq!(rule, FillRule { channel: 0, percent: 90, hold: Duration::from_secs(5) });
+        assert_eq!(parse_fill_rule("rejected=50/0").map(|rule| rule.channel), Ok(3));
+        assert!(parse_fill_rule("nowhere=90/5").is_err());
+        assert!(parse_fill_rule("generated=0/5").is_err());
+        assert!(parse_fill_rule("generated=90").is_err());
+
+        let mut watch = FillWatch { rules: vec![(rule, Stretch::default())] };
+        let (full, low) = (ChannelDepth { items: 60, capacity: 64 }, ChannelDepth { items: 10, capacity: 64 });
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        assert_eq!(watch.observe(0, full, at(0)), (vec![], vec![]));
+        // a dip under the threshold starts the hold over
+        assert_eq!(watch.observe(0, low, at(3)), (vec![], vec![]));
+        assert_eq!(watch.observe(0, full, at(4)), (vec![], vec![]));
+        // another channel's samples leave this rule alone
+        assert_eq!(watch.observe(2, full, at(8)), (vec![], vec![]));
+        let alert = FillAlert { rule, depth: full, held: Duration::from_secs(5) };
+        assert_eq!(watch.observe(0, full, at(9)), (vec![alert], vec![]));
+        assert_eq!(watch.observe(0, full, at(20)), (vec![], vec![]));
+        assert_eq!(watch.observe(0, low, at(21)), (vec![], vec![rule]));
+    }
+}
@@ -0,0 +1,55 @@
+/// Identifies the build that produced a run, stamped by build.rs at compile time.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct BuildInfo {
+    pub(crate) version: &'static str,
+    /// Short commit hash, or `unknown` when built outside a git checkout.
+    pub(crate) git_hash: &'static str,
+    /// Seconds since the Unix epoch; honours SOURCE_DATE_EPOCH.
+    pub(crate) built_at: &'static str,
+    /// Cargo profile the binary was compiled with, such as `debug` or `release`.
+    pub(crate) profile: &'static str,
+}
+
+pub(crate) const BUILD: BuildInfo = BuildInfo {
+    version: env!("CARGO_PKG_VERSION"),
+    git_hash: env!("ROBUST_GIT_HASH"),
+    built_at: env!("ROBUST_BUILT_AT"),
+    profile: env!("ROBUST_PROFILE"),
+};
+
+impl BuildInfo {
+    /// One-line form for logs and the final report.
+    pub(crate) fn summary(&self) -> String {
+        format!("{} ({}, {}, built {})", self.version, self.git_hash, self.profile, self.built_at)
+    }
+
+    /// JSON object for the status document.
+    pub(crate) fn json(&self) -> String {
+        format!(
+            "{{\"version\":\"{}\",\"git_hash\":\"{}\",\"built_at\":{},\"profile\":\"{}\"}}",
+            self.version, self.git_hash, self.built_at, self.profile
+        )
+    }
+
+    /// Prometheus exposition of the constant `build_info` gauge.
+    pub(crate) fn prometheus(&self) -> String {
+        format!(
+            "# HELP build_info Build that produced this process.\n# TYPE build_info gauge\nbuild_info{{version=\"{}\",git_hash=\"{}\",built_at=\"{}\",profile=\"{}\"}} 1\n",
+            self.version, self.git_hash, self.built_at, self.profile
+        )
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod build_info_tests {
+    use super::*;
+
+    #[test]
+    fn test_build_info_formats() {
+        let info = BuildInfo { version: "1.2.3", git_hash: "abc123", built_at: "1700000000", profile: "release" };
+        assert_eq!(info.summary(), "1.2.3 (abc123, release, built 1700000000)");
+        assert_eq!(info.json(), r#"{"version":"1.2.3","git_hash":"abc123","built_at":1700000000,"profile":"release"}"#);
+        assert!(info.prometheus().ends_with("build_info{version=\"1.2.3\",git_hash=\"abc123\",built_at=\"1700000000\",profile=\"release\"} 1\n"));
+        assert_eq!(BUILD.version, env!("CARGO_PKG_VERSION"));
+    }
+}
@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+
+/// True when this heartbeat beat is a checkpoint barrier.
+/// Beat zero never is, and `every` of zero turns checkpointing off.
+pub(crate) fn is_barrier(beat: u64, every: u64) -> bool {
+    every > 0 && beat > 0 && beat.is_multiple_of(every)
+}
+
+/// The worker's side of a checkpoint, taken as the barrier heartbeat passes it.
+/// Sent on the checkpoint channel just ahead of the in-band barrier marker, so the
+/// logger always finds it waiting when the marker arrives.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkerCheckpoint {
+    pub(crate) barrier: u64,
+    pub(crate) heartbeats_processed: u64,
+    /// Also the generator's side of the cut: everything it sent past this
+    /// position was still in flight when the barrier passed.
+    pub(crate) values_processed: u64,
+    pub(crate) messages_sent: u64,
+    pub(crate) values_rejected: u64,
+    /// Sequence number of the barrier marker, the last message the logger handles before the cut.
+    pub(crate) seq: u64,
+}
+
+/// A coordinated snapshot of the whole pipeline, completed when the barrier reaches the logger.
+/// Every stage is captured at the same point in the message stream: nothing
+/// before the barrier is missing and nothing after it is included.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Checkpoint {
+    /// The heartbeat beat that served as the barrier; also the heartbeat's side of the cut.
+    pub(crate) barrier: u64,
+    pub(crate) worker: WorkerCheckpoint,
+    pub(crate) messages_logged: u64,
+    pub(crate) fizz_count: u64,
+    pub(crate) buzz_count: u64,
+    pub(crate) fizzbuzz_count: u64,
+    pub(crate) value_count: u64,
+    pub(crate) rule_count: u64,
+    pub(crate) showstoppers_dropped: u64,
+    pub(crate) wal_seq: u64,
+    /// WAL entries the sink had accepted, where a resumed run picks up delivery.
+    #[serde(default)]
+    pub(crate) sink_seq: u64,
+}
+
+impl Checkpoint {
+    /// Messages the worker had sent to the logger before the barrier must all
+    /// have been logged or dropped before it; anything else means the cut is torn.
+    pub(crate) fn is_consistent(&self) -> bool {
+        self.worker.messages_sent == self.messages_logged + self.showstoppers_dropped
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod checkpoint_tests {
+    use super::*;
+
+    #[test]
+    fn test_is_barrier() {
+        assert!(!is_barrier(0, 10));
+        assert!(!is_barrier(7, 10));
+        assert!(is_barrier(10, 10));
+        assert!(is_barrier(20, 10));
+        assert!(!is_barrier(10, 0));
+    }
+}
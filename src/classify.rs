@@ -0,0 +1,178 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+use crate::actor::worker::FizzBuzzMessage;
+use crate::rules::{RuleMatches, RuleSet};
+use crate::MainArg;
+
+/// What can travel the pipeline's channels: copied by value, printable for traces, and with
+/// a default and equality so test graphs can simulate the stages that send and take it.
+pub trait Payload: Copy + Send + Sync + Debug + Default + Eq + 'static {
+    /// Bytes one payload stands for in a channel, for byte-based capacity planning. The default
+    /// is its size in the slot; a payload that refers to data held elsewhere, such as a
+    /// `&'static str` word, adds that data's length.
+    fn wire_bytes(&self) -> u64 {
+        size_of::<Self>() as u64
+    }
+}
+
+impl Payload for u64 {}
+
+impl Payload for FizzBuzzMessage {}
+
+impl Payload for &'static str {
+    fn wire_bytes(&self) -> u64 {
+        (size_of::<Self>() + self.len()) as u64
+    }
+}
+
+/// What the logger makes of a message.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Filing {
+    /// The marker for checkpoint barrier `beat`; it completes a checkpoint and is never written.
+    Barrier(u64),
+    /// A record labeled `label`, counted and rate-limited under `kind`, an index into
+    /// `rate_limit::RECORD_KINDS`, with the number it stands for when JSON and CSV records should show one.
+    Record { kind: usize, label: &'static str, value: Option<u64> },
+    /// A record of the `--rule` rules a value matched, labeled and counted by them.
+    Matched(RuleMatches),
+}
+
+/// The pipeline's domain logic: what the generator sends, how the worker turns a payload into
+/// the message the logger gets, and how the logger files that message. Everything else,
+/// pacing, peek-before-commit, batching, checkpoints and restarts, is the same for any
+/// strategy, so another domain only implements this.
+pub trait Classify: Clone + Send + Sync + 'static {
+    type Payload: Payload;
+    type Message: Payload;
+
+    /// The payload the generator sends for `value`, the next number of its sequence, its
+    /// `--backfill` or its `--source`. `key` gives the number back.
+    fn generate(&self, value: u64) -> Self::Payload;
+
+    fn classify(&self, payload: Self::Payload) -> Self::Message;
+
+    /// The number `--accept-range`, `--reject-multiples-of`, `--compute`, rejects and the
+    /// failure schedule see for `payload`.
+    fn key(&self, payload: Self::Payload) -> u64;
+
+    /// The in-band marker that carries checkpoint barrier `beat` to the logger.
+    fn barrier(&self, beat: u64) -> Self::Message;
+
+    /// How the logger counts and writes `message`; a barrier marker must file as `Filing::Barrier`.
+    fn file(&self, message: Self::Message) -> Filing;
+}
+
+/// The pipeline's own domain: u64 values classified as Fizz, Buzz, FizzBuzz or the value itself,
+/// or by the `--rule` list when one is given.
+#[derive(Clone, Debug, Default)]
+pub struct FizzBuzz {
+    rules: Arc<RuleSet>,
+}
+
+impl FizzBuzz {
+    pub(crate) fn from_args(args: &MainArg) -> Self {
+        FizzBuzz { rules: Arc::new(RuleSet::from_args(args)) }
+    }
+}
+
+impl Classify for FizzBuzz {
+    type Payload = u64;
+    type Message = FizzBuzzMessage;
+
+    fn generate(&self, value: u64) -> u64 {
+        value
+    }
+
+    fn classify(&self, payload: u64) -> FizzBuzzMessage {
+        self.rules.classify(payload)
+    }
+
+    fn key(&self, payload: u64) -> u64 {
+        payload
+    }
+
+    fn barrier(&self, beat: u64) -> FizzBuzzMessage {
+        FizzBuzzMessage::Barrier(beat)
+    }
+
+    fn file(&self, message: FizzBuzzMessage) -> Filing {
+        match message {
+            FizzBuzzMessage::Fizz => Filing::Record { kind: 0, label: "Fizz", value: None },
+            FizzBuzzMessage::Buzz => Filing::Record { kind: 1, label: "Buzz", value: None },
+            FizzBuzzMessage::FizzBuzz => Filing::Record { kind: 2, label: "FizzBuzz", value: None },
+            FizzBuzzMessage::Value(value) => Filing::Record { kind: 3, label: "Value", value: Some(value) },
+            FizzBuzzMessage::Matched(matches) => Filing::Matched(matches),
+            FizzBuzzMessage::Barrier(beat) => Filing::Barrier(beat),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod classify_tests {
+    use super::*;
+
+    /// A reading from some other domain, classified against a threshold.
+    #[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+    pub(crate) struct Reading {
+        pub(crate) sensor: u8,
+        pub(crate) celsius: u16,
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub(crate) enum Alarm {
+        Normal(u8),
+        Hot(u8),
+        Barrier(u64),
+    }
+
+    impl Default for Alarm {
+        fn default() -> Self {
+            Alarm::Normal(0)
+        }
+    }
+
+    impl Payload for Reading {}
+    impl Payload for Alarm {}
+
+    /// Readings over the threshold are hot. The generator's sequence is read as degrees from
+    /// one sensor, and the logger counts normal readings as values and hot ones as rule matches.
+    #[derive(Copy, Clone)]
+    pub(crate) struct Threshold(pub(crate) u16);
+
+    impl Classify for Threshold {
+        type Payload = Reading;
+        type Message = Alarm;
+
+        fn generate(&self, value: u64) -> Reading {
+            Reading { sensor: 1, celsius: value as u16 }
+        }
+        fn classify(&self, reading: Reading) -> Alarm {
+            if reading.celsius > self.0 { Alarm::Hot(reading.sensor) } else { Alarm::Normal(reading.sensor) }
+        }
+        fn key(&self, reading: Reading) -> u64 {
+            reading.celsius as u64
+        }
+        fn barrier(&self, beat: u64) -> Alarm {
+            Alarm::Barrier(beat)
+        }
+        fn file(&self, alarm: Alarm) -> Filing {
+            match alarm {
+                Alarm::Normal(sensor) => Filing::Record { kind: 3, label: "Normal", value: Some(sensor as u64) },
+                Alarm::Hot(sensor) => Filing::Record { kind: 4, label: "Hot", value: Some(sensor as u64) },
+                Alarm::Barrier(beat) => Filing::Barrier(beat),
+            }
+        }
+    }
+
+    #[test]
+    fn test_fizz_buzz_files_records_and_barriers() {
+        let fizz_buzz = FizzBuzz::default();
+        assert_eq!(fizz_buzz.generate(7), 7);
+        assert_eq!(fizz_buzz.file(fizz_buzz.classify(9)), Filing::Record { kind: 0, label: "Fizz", value: None });
+        assert_eq!(fizz_buzz.file(fizz_buzz.classify(7)), Filing::Record { kind: 3, label: "Value", value: Some(7) });
+        assert_eq!(fizz_buzz.file(fizz_buzz.barrier(4)), Filing::Barrier(4));
+        let threshold = Threshold(80);
+        assert_eq!(threshold.key(threshold.generate(95)), 95);
+        assert_eq!(threshold.file(threshold.barrier(4)), Filing::Barrier(4));
+    }
+}
@@ -0,0 +1,132 @@
+use std::time::Instant;
+use crate::timing::Stamp;
+use crate::MainArg;
+
+/// Both clocks, read together. The heartbeat takes its time from one of these, so a test can
+/// step the wall clock the way NTP or a suspend and resume would.
+pub trait Clock: Send + 'static {
+    /// The monotonic clock, and the wall clock in microseconds since the Unix epoch.
+    fn now(&self) -> (Instant, u64);
+}
+
+/// The clocks of the machine.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> (Instant, u64) {
+        (Instant::now(), Stamp::now().wall_micros)
+    }
+}
+
+/// Watches the wall clock against the monotonic one and hands out wall times that never run
+/// backwards. Kept in the heartbeat's state, so a restart goes on from the last beat time.
+#[derive(Clone, Debug)]
+pub(crate) struct JumpGuard {
+    /// From `--clock-jump-ms`; None leaves the wall clock unwatched.
+    threshold_micros: Option<u64>,
+    /// Both clocks at the last read, what the next wall reading is checked against.
+    last: Option<(Instant, u64)>,
+    /// The last wall time handed out.
+    given: u64,
+    pub(crate) jumps: u64,
+}
+
+impl JumpGuard {
+    pub(crate) fn from_args(args: &MainArg) -> Self {
+        JumpGuard {
+            threshold_micros: (args.clock_jump_ms > 0).then(|| args.clock_jump_ms * 1000),
+            last: None,
+            given: 0,
+            jumps: 0,
+        }
+    }
+
+    /// A wall time for now, and how far the wall clock stepped since the last read if that was
+    /// a jump, in microseconds and negative when it went back. After a step forward times follow
+    /// the wall clock from where it landed; after a step back they go on counting from the last
+    /// time given out by the monotonic clock until the wall clock catches up, so windows already
+    /// closed are not reopened and event time does not stand still meanwhile.
+    pub(crate) fn read(&mut self, clock: &impl Clock) -> (u64, Option<i64>) {
+        let (mono, wall) = clock.now();
+        let Some(threshold) = self.threshold_micros else {
+            return (wall, None);
+        };
+        let (wall_time, jump) = match self.last {
+            Some((then, wall_then)) => {
+                let elapsed = mono.saturating_duration_since(then).as_micros() as u64;
+                let step = wall as i64 - (wall_then + elapsed) as i64;
+                (wall.max(self.given + elapsed), (step.unsigned_abs() > threshold).then_some(step))
+            }
+            None => (wall.max(self.given), None),
+        };
+        if jump.is_some() {
+            self.jumps += 1;
+        }
+        self.last = Some((mono, wall));
+        self.given = wall_time;
+        (wall_time, jump)
+    }
+}
+
+/// The machine's clocks with the wall clock moved by an offset a test can change while running.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub(crate) struct SteppedClock {
+    pub(crate) offset_micros: std::sync::Arc<std::sync::atomic::AtomicI64>,
+}
+
+#[cfg(test)]
+impl SteppedClock {
+    pub(crate) fn step(&self, micros: i64) {
+        self.offset_micros.fetch_add(micros, std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+impl Clock for SteppedClock {
+    fn now(&self) -> (Instant, u64) {
+        let (mono, wall) = SystemClock.now();
+        (mono, wall.saturating_add_signed(self.offset_micros.load(std::sync::atomic::Ordering::SeqCst)))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod clock_tests {
+    use super::*;
+
+    const HOUR_MICROS: i64 = 3_600_000_000;
+
+    #[test]
+    fn test_jump_guard_re_anchors() {
+        let clock = SteppedClock::default();
+        let mut guard = JumpGuard::from_args(&MainArg { clock_jump_ms: 1000, ..Default::default() });
+        let (first, jump) = guard.read(&clock);
+        assert_eq!(jump, None);
+
+        // back an hour: reported, and times keep moving forward from the last one
+        clock.step(-HOUR_MICROS);
+        let (second, jump) = guard.read(&clock);
+        assert!(jump.is_some_and(|step| (step + HOUR_MICROS).abs() < 1_000_000), "{:?}", jump);
+        assert!(second >= first && second - first < 1_000_000);
+        // no second report while the wall clock is still behind
+        let (third, jump) = guard.read(&clock);
+        assert_eq!(jump, None);
+        assert!(third >= second);
+
+        // forward two hours: reported, and times follow the wall clock again
+        clock.step(2 * HOUR_MICROS);
+        let (fourth, jump) = guard.read(&clock);
+        assert!(jump.is_some_and(|step| step > 0));
+        assert!(fourth >= third + HOUR_MICROS as u64 - 1_000_000);
+        assert_eq!(guard.jumps, 2);
+
+        // off, the wall clock is passed through as it is
+        let mut off = JumpGuard::from_args(&MainArg { clock_jump_ms: 0, ..Default::default() });
+        let (before, _) = off.read(&clock);
+        clock.step(-HOUR_MICROS);
+        let (after, jump) = off.read(&clock);
+        assert_eq!(jump, None);
+        assert!(after < before);
+    }
+}
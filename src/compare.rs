@@ -0,0 +1,117 @@
+use std::path::Path;
+use steady_state::*;
+use crate::report::RunReport;
+
+/// Which way a metric moves when a run gets better.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Better {
+    Higher,
+    Lower,
+}
+
+/// A metric `compare-runs` judges, read from a saved report.
+struct Metric {
+    name: &'static str,
+    better: Better,
+    read: fn(&RunReport) -> f64,
+}
+
+const METRICS: [Metric; 6] = [
+    Metric { name: "throughput/s", better: Better::Higher, read: |r| r.throughput() },
+    Metric { name: "restarts", better: Better::Lower, read: |r| r.restarts() as f64 },
+    Metric { name: "latency p50 us", better: Better::Lower, read: |r| r.latency_p50_us as f64 },
+    Metric { name: "latency p99 us", better: Better::Lower, read: |r| r.latency_p99_us as f64 },
+    Metric { name: "latency max us", better: Better::Lower, read: |r| r.latency_max_us as f64 },
+    Metric { name: "lost", better: Better::Lower, read: |r| r.lost() as f64 },
+];
+
+/// One metric of two runs side by side.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Delta {
+    pub(crate) metric: &'static str,
+    pub(crate) baseline: f64,
+    pub(crate) candidate: f64,
+    /// Worse than the baseline by more than the tolerance.
+    pub(crate) regressed: bool,
+}
+
+impl Delta {
+    /// Relative change in percent; None when the baseline is zero.
+    pub(crate) fn change_pct(&self) -> Option<f64> {
+        (self.baseline != 0.0).then(|| (self.candidate - self.baseline) / self.baseline * 100.0)
+    }
+}
+
+/// Judges every metric of `candidate` against `baseline`. A metric regresses when it is worse
+/// by more than `tolerance_pct` percent; from a zero baseline, any worsening counts.
+pub(crate) fn compare(baseline: &RunReport, candidate: &RunReport, tolerance_pct: f64) -> Vec<Delta> {
+    let slack = tolerance_pct / 100.0;
+    METRICS
+        .iter()
+        .map(|metric| {
+            let (b, c) = ((metric.read)(baseline), (metric.read)(candidate));
+            let regressed = match metric.better {
+                Better::Higher => c < b * (1.0 - slack),
+                Better::Lower => c > b * (1.0 + slack),
+            };
+            Delta { metric: metric.name, baseline: b, candidate: c, regressed }
+        })
+        .collect()
+}
+
+/// The `compare-runs` subcommand: prints the metrics of both reports and the regressions.
+/// Returns true if anything regressed, so the caller can fail a CI gate.
+pub(crate) fn run(baseline: &Path, candidate: &Path, tolerance_pct: f64) -> Result<bool, Box<dyn Error>> {
+    let (base, cand) = (RunReport::load(baseline)?, RunReport::load(candidate)?);
+    println!("baseline:  run {} on build {}", base.run_id, base.build);
+    println!("candidate: run {} on build {}", cand.run_id, cand.build);
+    println!("{:<16} {:>12} {:>12} {:>9}", "metric", "baseline", "candidate", "change");
+    let deltas = compare(&base, &cand, tolerance_pct);
+    for delta in &deltas {
+        let change = delta.change_pct().map_or_else(|| "-".to_string(), |pct| format!("{:+.1}%", pct));
+        let flag = if delta.regressed { "  REGRESSION" } else { "" };
+        println!("{:<16} {:>12.1} {:>12.1} {:>9}{}", delta.metric, delta.baseline, delta.candidate, change, flag);
+    }
+    let regressed: Vec<_> = deltas.iter().filter(|d| d.regressed).map(|d| d.metric).collect();
+    if regressed.is_empty() {
+        println!("no regressions (tolerance {}%)", tolerance_pct);
+    } else {
+        println!("{} regressed (tolerance {}%): {}", regressed.len(), tolerance_pct, regressed.join(", "));
+    }
+    Ok(!regressed.is_empty())
+}
+
+#[cfg(test)]
+pub(crate) mod compare_tests {
+    use super::*;
+    use crate::restart::RestartCauses;
+
+    #[test]
+    fn test_compare_flags_regressions() {
+        let baseline = RunReport {
+            duration_ms: 10_000, generated: 1_000, logged: 1_000,
+            latency_p50_us: 100, latency_p99_us: 1_000, latency_max_us: 5_000,
+            ..Default::default()
+        };
+        let candidate = RunReport {
+            duration_ms: 10_000, generated: 1_000, logged: 980,
+            latency_p50_us: 104, latency_p99_us: 1_200, latency_max_us: 4_000,
+            worker_restarts: RestartCauses { panics: 1, errors: 0 },
+            ..Default::default()
+        };
+        let deltas = compare(&baseline, &candidate, 5.0);
+        let regressed: Vec<_> = deltas.iter().filter(|d| d.regressed).map(|d| d.metric).collect();
+        assert_eq!(regressed, ["restarts", "latency p99 us", "lost"]);
+        assert_eq!(deltas[0].change_pct(), Some(-2.0));
+        assert_eq!(deltas[1].change_pct(), None);
+        assert!(compare(&baseline, &baseline, 0.0).iter().all(|d| !d.regressed));
+
+        // a report saved by this build loads back unchanged, and older ones miss fields harmlessly
+        let path = std::env::temp_dir().join(format!("robust-report-{}.json", crate::arg::new_run_id()));
+        candidate.save(&path).expect("save");
+        assert_eq!(RunReport::load(&path).expect("load"), candidate);
+        std::fs::write(&path, r#"{"run_id":"old","logged":7}"#).expect("write");
+        assert_eq!(RunReport::load(&path).expect("load").logged, 7);
+        let _ = std::fs::remove_file(&path);
+    }
+}
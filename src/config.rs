@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use clap::{ArgMatches, CommandFactory, Parser};
+use clap::parser::ValueSource;
+use serde::Deserialize;
+use crate::MainArg;
+
+/// One value in a `--config` file, as TOML gives it.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ConfigValue {
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    Text(String),
+    List(Vec<ConfigValue>),
+    Table(BTreeMap<String, ConfigValue>),
+}
+
+/// The command-line tokens one setting of the file stands for, by the clap id of its option.
+#[derive(Debug, PartialEq)]
+struct ConfigArg {
+    id: String,
+    tokens: Vec<OsString>,
+}
+
+/// Reads a `--config` file into the options it sets. Each key is the long name of an option,
+/// with `-` or `_` between words, and its value is what would follow it on the command line: a
+/// string or number, `true` for a flag, or a list for an option that may be repeated. Keys may
+/// also sit in a table named for a stage, e.g. `[logger]`, which groups them without changing
+/// what they mean; every actor still reads the merged arguments through `actor.args()`.
+fn read(file: &Path) -> Result<Vec<ConfigArg>, String> {
+    let path = file.display();
+    let text = std::fs::read_to_string(file).map_err(|e| format!("cannot read config file {}: {}", path, e))?;
+    let table: BTreeMap<String, ConfigValue> = basic_toml::from_str(&text).map_err(|e| format!("{}: {}", path, e))?;
+    let stages: Vec<String> = crate::wiring::stages().iter().map(|stage| stage.name().to_ascii_lowercase()).collect();
+    let command = MainArg::command();
+    let mut settings: Vec<ConfigArg> = Vec::new();
+    let mut add = |key: &str, value: &ConfigValue| -> Result<(), String> {
+        let long = key.replace('_', "-");
+        let arg = command.get_arguments()
+            .find(|arg| arg.get_long() == Some(long.as_str()) && !matches!(long.as_str(), "config" | "help" | "version"))
+            .ok_or_else(|| format!("{}: unknown setting '{}'", path, key))?;
+        let id = arg.get_id().to_string();
+        if settings.iter().any(|setting| setting.id == id) {
+            return Err(format!("{}: '{}' is set more than once", path, key));
+        }
+        let tokens = if arg.get_action().takes_values() {
+            let values = match value {
+                ConfigValue::List(items) => items.iter().map(|item| scalar(item).ok_or(())).collect::<Result<Vec<_>, _>>(),
+                single => scalar(single).map(|text| vec![text]).ok_or(()),
+            }.map_err(|_| format!("{}: '{}' must be a string, a number or a list of them", path, key))?;
+            values.into_iter().map(|text| OsString::from(format!("--{}={}", long, text))).collect()
+        } else {
+            match value {
+                ConfigValue::Bool(true) => vec![OsString::from(format!("--{}", long))],
+                ConfigValue::Bool(false) => Vec::new(),
+                _ => return Err(format!("{}: '{}' is a flag, so true or false", path, key)),
+            }
+        };
+        settings.push(ConfigArg { id, tokens });
+        Ok(())
+    };
+    for (key, value) in &table {
+        match value {
+            ConfigValue::Table(section) if stages.contains(key) => {
+                for (key, value) in section {
+                    add(key, value)?;
+                }
+            }
+            ConfigValue::Table(_) => return Err(format!("{}: unknown section [{}]; sections are named for stages, e.g. [logger]", path, key)),
+            value => add(key, value)?,
+        }
+    }
+    Ok(settings)
+}
+
+fn scalar(value: &ConfigValue) -> Option<String> {
+    match value {
+        ConfigValue::Bool(flag) => Some(flag.to_string()),
+        ConfigValue::Integer(number) => Some(number.to_string()),
+        ConfigValue::Float(number) => Some(number.to_string()),
+        ConfigValue::Text(text) => Some(text.clone()),
+        ConfigValue::List(_) | ConfigValue::Table(_) => None,
+    }
+}
+
+/// Parses a command line, filling in from its `--config` file every option the command line
+/// leaves out. An option given on the command line replaces the file's setting whole, so a
+/// repeated option such as `--rule` takes the command line's list rather than adding to the file's.
+pub(crate) fn parse_from<I, T>(argv: I) -> Result<MainArg, clap::Error>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let argv: Vec<OsString> = argv.into_iter().map(Into::into).collect();
+    let matches = MainArg::command().try_get_matches_from(&argv)?;
+    let Some(path) = matches.get_one::<PathBuf>("config") else {
+        return <MainArg as clap::FromArgMatches>::from_arg_matches(&matches);
+    };
+    let settings = read(path).map_err(|e| MainArg::command().error(clap::error::ErrorKind::ValueValidation, e))?;
+    let from_file = settings.into_iter()
+        .filter(|setting| !on_command_line(&matches, &setting.id))
+        .flat_map(|setting| setting.tokens);
+    // The file's options go before the command line's, so a subcommand still comes last.
+    let merged: Vec<OsString> = argv.iter().take(1).cloned().chain(from_file).chain(argv.iter().skip(1).cloned()).collect();
+    MainArg::try_parse_from(merged)
+}
+
+fn on_command_line(matches: &ArgMatches, id: &str) -> bool {
+    matches.value_source(id) == Some(ValueSource::CommandLine)
+}
+
+#[cfg(test)]
+pub(crate) mod config_tests {
+    use super::*;
+    use crate::rules::parse_rule;
+    use crate::sink::SinkSpec;
+
+    fn config_file(text: &str) -> String {
+        let path = std::env::temp_dir().join(format!("robust-config-{}.toml", crate::arg::new_run_id()));
+        std::fs::write(&path, text).expect("write");
+        path.to_str().expect("utf-8").to_string()
+    }
+
+    #[test]
+    fn test_command_line_overrides_config_file() {
+        let path = config_file("rate = 50\nbeats = 10\nrule = [\"3=Fizz\", \"7=Bazz\"]\ntroupe = true\n\n\
+                                [logger]\nsink = \"null\"\n\n[worker]\nbatch_size = 8\n");
+        let args = parse_from(["robust", "--config", &path, "--beats", "7", "--rule", "5=Buzz"]).expect("merged");
+        assert_eq!((args.rate_ms, args.beats, args.batch_size, args.troupe), (50, 7, 8, true));
+        assert_eq!(args.sink, SinkSpec::Null);
+        // the command line's list replaces the file's
+        assert_eq!(args.rule, vec![parse_rule("5=Buzz").expect("rule")]);
+
+        // a subcommand after the options still parses
+        let args = parse_from(["robust", "--config", &path, "loopback"]).expect("merged");
+        assert_eq!((args.rate_ms, args.rule.len(), args.command), (50, 2, Some(crate::arg::Command::Loopback)));
+        std::fs::remove_file(&path).expect("remove");
+    }
+
+    #[test]
+    fn test_config_file_errors() {
+        let error = |text: &str| {
+            let path = config_file(text);
+            let message = parse_from(["robust", "--config", &path]).expect_err("refused").to_string();
+            std::fs::remove_file(&path).expect("remove");
+            message
+        };
+        assert!(error("ratee = 5\n").contains("unknown setting 'ratee'"));
+        assert!(error("[loggr]\nsink = \"null\"\n").contains("unknown section [loggr]"));
+        assert!(error("troupe = 3\n").contains("'troupe' is a flag"));
+        assert!(error("beats = 3\n[worker]\nbeats = 4\n").contains("'beats' is set more than once"));
+        assert!(error("beats = \"many\"\n").contains("invalid value 'many'"));
+        assert!(parse_from(["robust", "--config", "/nonexistent/robust.toml"]).expect_err("missing").to_string().contains("cannot read config file"));
+    }
+}
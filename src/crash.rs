@@ -0,0 +1,156 @@
+use std::backtrace::Backtrace;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use steady_state::*;
+use crate::timing::Stamp;
+use crate::MainArg;
+
+/// Recent events for each actor, once `init` has run with a `--crash-dir`; without it nothing is kept.
+static RECENT: OnceLock<(PathBuf, Mutex<Recent>)> = OnceLock::new();
+
+/// The last `capacity` events of every actor, oldest first.
+#[derive(Debug, Default)]
+pub(crate) struct Recent {
+    capacity: usize,
+    by_actor: HashMap<String, VecDeque<String>>,
+    /// Events each actor has had in all, kept or not.
+    seen: HashMap<String, u64>,
+}
+
+impl Recent {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Recent { capacity, ..Default::default() }
+    }
+
+    pub(crate) fn push(&mut self, actor: &str, event: String) {
+        let ring = self.by_actor.entry(actor.to_string()).or_default();
+        if ring.len() >= self.capacity {
+            ring.pop_front();
+        }
+        ring.push_back(event);
+        *self.seen.entry(actor.to_string()).or_default() += 1;
+    }
+
+    /// The actor's kept events, oldest first, and how many it had in all.
+    pub(crate) fn of(&self, actor: &str) -> (Vec<String>, u64) {
+        let kept = self.by_actor.get(actor).map(|ring| ring.iter().cloned().collect()).unwrap_or_default();
+        (kept, self.seen.get(actor).copied().unwrap_or(0))
+    }
+}
+
+/// Starts keeping `--crash-events` events per actor when `--crash-dir` is set, and dumps them
+/// on panic. Call once logging has started, so the panic hook chains to the one logging installs
+/// and the panic is still reported as usual.
+pub(crate) fn init(args: &MainArg) -> std::io::Result<()> {
+    let Some(dir) = &args.crash_dir else {
+        return Ok(());
+    };
+    fs::create_dir_all(dir)?;
+    if RECENT.set((dir.clone(), Mutex::new(Recent::new(args.crash_events as usize)))).is_err() {
+        return Ok(());
+    }
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let (message, location) = describe(info);
+        match dump(&current_actor(), &message, &location) {
+            Some(Ok(path)) => warn!("Crash dump written to {}", path.display()),
+            Some(Err(e)) => warn!("Could not write crash dump: {}", e),
+            None => {}
+        }
+        previous(info)
+    }));
+    Ok(())
+}
+
+/// A panic's message and `file:line`, for the hooks that report it.
+pub(crate) fn describe(info: &std::panic::PanicHookInfo) -> (String, String) {
+    let message = info
+        .payload()
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| info.payload().downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "panic".to_string());
+    let location = info.location().map_or_else(|| "-".to_string(), |l| format!("{}:{}", l.file(), l.line()));
+    (message, location)
+}
+
+/// The actor that last used its state on this thread, which tells troupe members apart, or else the thread's name.
+pub(crate) fn current_actor() -> String {
+    crate::state_lock::active_actor()
+        .map(str::to_string)
+        .or_else(|| thread::current().name().map(str::to_string))
+        .unwrap_or_else(|| "<unnamed>".to_string())
+}
+
+/// Keeps one event for the actor; does nothing unless `--crash-dir` is set.
+pub(crate) fn remember(actor: &str, event: impl FnOnce() -> String) {
+    if let Some((_, recent)) = RECENT.get()
+        && let Ok(mut recent) = recent.lock() {
+        recent.push(actor, event());
+    }
+}
+
+pub(crate) fn is_enabled() -> bool {
+    RECENT.get().is_some()
+}
+
+/// Writes the crash file for a panic in `actor`; None when crash dumps are off.
+fn dump(actor: &str, message: &str, location: &str) -> Option<std::io::Result<PathBuf>> {
+    let (dir, recent) = RECENT.get()?;
+    // A panic while the lock was held must not stop the dump; the events are still intact.
+    let (events, seen) = recent.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).of(actor);
+    let text = report(crate::logging::run_id(), actor, message, location, &Backtrace::force_capture().to_string(), &events, seen);
+    Some(write(dir, actor, &text))
+}
+
+/// The crash file: who panicked, where and why, the backtrace, then the actor's recent events.
+pub(crate) fn report(run_id: &str, actor: &str, message: &str, location: &str, backtrace: &str, events: &[String], seen: u64) -> String {
+    let mut text = format!(
+        "run: {}\nactor: {}\npanic: {}\nlocation: {}\n\nbacktrace:\n{}\n\nlast {} of {} events for {}, oldest first:\n",
+        run_id, actor, message, location, backtrace.trim_end(), events.len(), seen, actor
+    );
+    for event in events {
+        text.push_str(event);
+        text.push('\n');
+    }
+    text
+}
+
+/// `crash-<run>-<actor>-<wall us>.txt` in the crash directory, so repeated panics never overwrite each other.
+fn write(dir: &Path, actor: &str, text: &str) -> std::io::Result<PathBuf> {
+    let name: String = actor.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' }).collect();
+    let path = dir.join(format!("crash-{}-{}-{}.txt", crate::logging::run_id(), name, Stamp::now().wall_micros));
+    fs::write(&path, text)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+pub(crate) mod crash_tests {
+    use super::*;
+
+    #[test]
+    fn test_recent_events() {
+        let mut recent = Recent::new(2);
+        for n in 0..3 {
+            recent.push("WORKER", format!("w{}", n));
+        }
+        recent.push("LOGGER", "l0".to_string());
+        assert_eq!(recent.of("WORKER"), (vec!["w1".to_string(), "w2".to_string()], 3));
+        assert_eq!(recent.of("HEARTBEAT"), (Vec::new(), 0));
+
+        let (events, seen) = recent.of("WORKER");
+        let text = report("soak-1", "WORKER", "boom", "src/actor/worker.rs:1", "0: main\n", &events, seen);
+        assert!(text.starts_with("run: soak-1\nactor: WORKER\npanic: boom\n"));
+        assert!(text.ends_with("last 2 of 3 events for WORKER, oldest first:\nw1\nw2\n"));
+
+        let dir = std::env::temp_dir().join(format!("robust-crash-{}", crate::arg::new_run_id()));
+        fs::create_dir_all(&dir).expect("dir");
+        let path = write(&dir, "Troupe 3", &text).expect("write");
+        assert!(path.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.contains("-Troupe_3-")));
+        assert_eq!(fs::read_to_string(&path).expect("read"), text);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
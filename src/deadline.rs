@@ -0,0 +1,91 @@
+use std::time::Duration;
+use crate::MainArg;
+
+/// What the worker does with a value older than `--processing-deadline-ms` when it picks it up.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) enum DeadlineAction {
+    /// Classify it but skip the `--compute` modules, to catch up sooner.
+    FastPath,
+    /// Send it to the rejects side channel as expired instead of classifying it.
+    DeadLetter,
+    /// Warn about it and process it as usual.
+    #[default]
+    Log,
+}
+
+pub(crate) fn parse_deadline_action(text: &str) -> Result<DeadlineAction, String> {
+    match text.trim().to_ascii_lowercase().as_str() {
+        "fast-path" => Ok(DeadlineAction::FastPath),
+        "dead-letter" => Ok(DeadlineAction::DeadLetter),
+        "log" => Ok(DeadlineAction::Log),
+        other => Err(format!("unknown deadline action '{}', expected fast-path, dead-letter or log", other)),
+    }
+}
+
+/// How many overdue values took each way out. Counted only once the value is committed,
+/// so a value retried after a full channel or a restart counts once.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct DeadlineCounts {
+    pub(crate) fast_path: u64,
+    pub(crate) dead_lettered: u64,
+    pub(crate) logged: u64,
+}
+
+impl DeadlineCounts {
+    pub(crate) fn count(&mut self, action: DeadlineAction) {
+        match action {
+            DeadlineAction::FastPath => self.fast_path += 1,
+            DeadlineAction::DeadLetter => self.dead_lettered += 1,
+            DeadlineAction::Log => self.logged += 1,
+        }
+    }
+
+    pub(crate) fn add(&mut self, other: DeadlineCounts) {
+        self.fast_path += other.fast_path;
+        self.dead_lettered += other.dead_lettered;
+        self.logged += other.logged;
+    }
+}
+
+/// The `--processing-deadline-ms` setting and what to do past it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ProcessingDeadline {
+    pub(crate) limit: Duration,
+    pub(crate) action: DeadlineAction,
+}
+
+impl ProcessingDeadline {
+    /// None unless `--processing-deadline-ms` was given.
+    pub(crate) fn from_args(args: &MainArg) -> Option<Self> {
+        args.processing_deadline_ms.map(|ms| ProcessingDeadline {
+            limit: Duration::from_millis(ms),
+            action: args.deadline_action,
+        })
+    }
+
+    /// The action for a value of this age, or None while it is within the deadline.
+    pub(crate) fn overdue(&self, age: Duration) -> Option<DeadlineAction> {
+        (age > self.limit).then_some(self.action)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod deadline_tests {
+    use super::*;
+
+    #[test]
+    fn test_deadline_actions() {
+        assert_eq!(parse_deadline_action("Dead-Letter"), Ok(DeadlineAction::DeadLetter));
+        assert!(parse_deadline_action("drop").is_err());
+
+        let deadline = ProcessingDeadline { limit: Duration::from_millis(100), action: DeadlineAction::FastPath };
+        assert_eq!(deadline.overdue(Duration::from_millis(100)), None);
+        assert_eq!(deadline.overdue(Duration::from_millis(101)), Some(DeadlineAction::FastPath));
+
+        let mut counts = DeadlineCounts::default();
+        counts.count(DeadlineAction::DeadLetter);
+        counts.add(DeadlineCounts { fast_path: 2, dead_lettered: 1, logged: 3 });
+        assert_eq!(counts, DeadlineCounts { fast_path: 2, dead_lettered: 2, logged: 3 });
+        assert_eq!(ProcessingDeadline::from_args(&MainArg::default()), None);
+    }
+}
@@ -0,0 +1,60 @@
+use std::time::Duration;
+use crate::MainArg;
+
+/// The channels a `--channel-delay` can slow down, named `FROM-TO`.
+pub(crate) const DELAYABLE_CHANNELS: [&str; 3] = ["heartbeat-worker", "generator-worker", "worker-logger"];
+
+/// One `--channel-delay CHANNEL=MS` setting.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ChannelDelay {
+    /// Index into `DELAYABLE_CHANNELS`.
+    pub(crate) channel: usize,
+    pub(crate) delay: Duration,
+}
+
+/// Parses `CHANNEL=MS`, with an optional `ms` suffix, e.g. `generator-worker=20ms`.
+pub(crate) fn parse_channel_delay(text: &str) -> Result<ChannelDelay, String> {
+    let (channel, ms) = text
+        .split_once('=')
+        .ok_or_else(|| format!("expected CHANNEL=MS, got '{}'", text))?;
+    let channel = channel.trim().to_ascii_lowercase();
+    let channel = DELAYABLE_CHANNELS
+        .iter()
+        .position(|&c| c == channel)
+        .ok_or_else(|| format!("unknown channel '{}', expected one of {}", channel, DELAYABLE_CHANNELS.join(", ")))?;
+    let ms = ms.trim();
+    let ms: u64 = ms.strip_suffix("ms").unwrap_or(ms).parse().map_err(|e| format!("bad delay in '{}': {}", text, e))?;
+    Ok(ChannelDelay { channel, delay: Duration::from_millis(ms) })
+}
+
+/// The delay asked for on `channel`, the last setting winning; None for no delay line at all.
+pub(crate) fn delay_for(args: &MainArg, channel: &str) -> Option<Duration> {
+    args.channel_delay
+        .iter()
+        .rev()
+        .find(|setting| DELAYABLE_CHANNELS[setting.channel] == channel)
+        .map(|setting| setting.delay)
+        .filter(|delay| !delay.is_zero())
+}
+
+#[cfg(test)]
+pub(crate) mod delay_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_lookup() {
+        assert_eq!(parse_channel_delay("Generator-Worker=20ms"), Ok(ChannelDelay { channel: 1, delay: Duration::from_millis(20) }));
+        assert_eq!(parse_channel_delay("worker-logger=5"), Ok(ChannelDelay { channel: 2, delay: Duration::from_millis(5) }));
+        assert!(parse_channel_delay("logger-sink=5").is_err());
+        assert!(parse_channel_delay("worker-logger=soon").is_err());
+
+        let args = MainArg {
+            channel_delay: ["worker-logger=5", "worker-logger=30", "heartbeat-worker=0"]
+                .iter().map(|s| parse_channel_delay(s).expect("delay")).collect(),
+            ..Default::default()
+        };
+        assert_eq!(delay_for(&args, "worker-logger"), Some(Duration::from_millis(30)));
+        assert_eq!(delay_for(&args, "heartbeat-worker"), None);
+        assert_eq!(delay_for(&args, "generator-worker"), None);
+    }
+}
@@ -0,0 +1,70 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use steady_state::*;
+use serde_json::json;
+use crate::events;
+
+/// One stage's share of shutdown. The clock starts the first time the stage
+/// sees shutdown requested; once it runs out the stage stops waiting for its
+/// input to drain and spills what is left to the backlog file instead.
+/// Held as a local, so a restart during shutdown starts the clock again.
+pub struct DrainDeadline {
+    budget: Duration,
+    since: Option<Instant>,
+}
+
+impl DrainDeadline {
+    pub fn new(budget: Duration) -> Self {
+        DrainDeadline { budget, since: None }
+    }
+
+    /// True once shutdown has been requested for longer than the budget.
+    pub fn expired(&mut self, stopping: bool) -> bool {
+        if !stopping {
+            return false;
+        }
+        self.since.get_or_insert_with(Instant::now).elapsed() >= self.budget
+    }
+}
+
+/// Appends a stage's undrained items to the backlog file as `STAGE item` lines.
+/// Without a file they are only counted, and the loss is logged.
+pub fn persist_residue(path: Option<&Path>, stage: &str, items: &[String]) {
+    if items.is_empty() {
+        return;
+    }
+    events::emit("shutdown", json!({"phase": "drain_deadline", "stage": stage, "undrained": items.len()}));
+    let Some(path) = path else {
+        warn!("{} drain deadline passed, dropping {} undrained items (no --backlog-file)", stage, items.len());
+        return;
+    };
+    let written = OpenOptions::new().create(true).append(true).open(path).and_then(|mut file| {
+        for item in items {
+            writeln!(file, "{} {}", stage, item)?;
+        }
+        file.sync_data()
+    });
+    match written {
+        Ok(()) => warn!("{} drain deadline passed, {} undrained items saved to {:?}", stage, items.len(), path),
+        Err(e) => error!("{} drain deadline passed, could not save {} undrained items to {:?}: {}", stage, items.len(), path, e),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod drain_tests {
+    use super::*;
+
+    #[test]
+    fn test_deadline_starts_at_shutdown() {
+        let mut deadline = DrainDeadline::new(Duration::from_millis(20));
+        assert!(!deadline.expired(false));
+        assert!(!deadline.expired(true));
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(deadline.expired(true));
+
+        let mut immediate = DrainDeadline::new(Duration::ZERO);
+        assert!(!immediate.expired(false));
+        assert!(immediate.expired(true));
+    }
+}
@@ -0,0 +1,117 @@
+use serde::Serialize;
+use crate::MainArg;
+
+/// A computation the worker can run on each accepted value, next to classifying it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum ComputeModule {
+    DigitSum,
+    Parity,
+    Hash,
+}
+
+/// Parses one `--compute` module name.
+pub(crate) fn parse_compute(text: &str) -> Result<ComputeModule, String> {
+    match text.trim().to_ascii_lowercase().as_str() {
+        "digit-sum" => Ok(ComputeModule::DigitSum),
+        "parity" => Ok(ComputeModule::Parity),
+        "hash" => Ok(ComputeModule::Hash),
+        other => Err(format!("unknown compute module '{}', expected digit-sum, parity or hash", other)),
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Parity {
+    Even,
+    Odd,
+}
+
+/// Data derived from a value by the configured compute modules, carried with its
+/// classification. A field is `None` when its module is off, and is left out of the JSON.
+/// Kept `Copy` so it rides the logger channel in place, like the rest of the envelope.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct Derived {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) digit_sum: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) parity: Option<Parity>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) hash: Option<u64>,
+}
+
+impl Derived {
+    pub(crate) fn is_empty(&self) -> bool {
+        *self == Derived::default()
+    }
+
+    /// JSON object for log records, e.g. `{"digit_sum":6,"parity":"odd"}`.
+    pub(crate) fn json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// The compute modules enabled with `--compute`, run in the worker after validation.
+#[derive(Clone, Default, Debug, PartialEq)]
+pub(crate) struct Enricher {
+    modules: Vec<ComputeModule>,
+}
+
+impl Enricher {
+    pub(crate) fn from_args(args: &MainArg) -> Self {
+        Enricher { modules: args.compute.clone() }
+    }
+
+    pub(crate) fn compute(&self, value: u64) -> Derived {
+        let mut derived = Derived::default();
+        for module in &self.modules {
+            match module {
+                ComputeModule::DigitSum => derived.digit_sum = Some(digit_sum(value)),
+                ComputeModule::Parity => {
+                    derived.parity = Some(if value.is_multiple_of(2) { Parity::Even } else { Parity::Odd })
+                }
+                ComputeModule::Hash => derived.hash = Some(mix(value)),
+            }
+        }
+        derived
+    }
+}
+
+fn digit_sum(mut value: u64) -> u32 {
+    let mut sum = 0;
+    while value > 0 {
+        sum += (value % 10) as u32;
+        value /= 10;
+    }
+    sum
+}
+
+/// SplitMix64 finalizer: cheap, stable across runs and builds, and well spread.
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+pub(crate) mod enrich_tests {
+    use super::*;
+
+    #[test]
+    fn test_compute_modules() {
+        assert_eq!(parse_compute("Digit-Sum"), Ok(ComputeModule::DigitSum));
+        assert!(parse_compute("sha256").is_err());
+
+        let none = Enricher::default();
+        assert!(none.compute(123).is_empty());
+
+        let enricher = Enricher { modules: vec![ComputeModule::DigitSum, ComputeModule::Parity] };
+        let derived = enricher.compute(123);
+        assert_eq!(derived, Derived { digit_sum: Some(6), parity: Some(Parity::Odd), hash: None });
+        assert_eq!(derived.json(), r#"{"digit_sum":6,"parity":"odd"}"#);
+
+        let hashed = Enricher { modules: vec![ComputeModule::Hash] };
+        assert_eq!(hashed.compute(7), hashed.compute(7));
+        assert_ne!(hashed.compute(7), hashed.compute(8));
+    }
+}
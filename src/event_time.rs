@@ -0,0 +1,129 @@
+use chrono::{DateTime, SecondsFormat};
+use crate::timing::Stamp;
+use crate::MainArg;
+
+/// The heartbeat's wall clock when it sent `beat`, for `--event-window-ms`.
+/// The worker takes it as the authoritative time of every value that beat releases.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub struct BeatTime {
+    pub beat: u64,
+    /// Microseconds since the Unix epoch.
+    pub wall_micros: u64,
+}
+
+impl BeatTime {
+    /// A beat stamped with the wall clock now.
+    pub fn now(beat: u64) -> Self {
+        BeatTime { beat, wall_micros: Stamp::now().wall_micros }
+    }
+
+    /// A beat stamped with a wall time read elsewhere.
+    pub fn at(beat: u64, wall_micros: u64) -> Self {
+        BeatTime { beat, wall_micros }
+    }
+
+    /// A beat without a time, as the heartbeat sends them when event time is off.
+    pub fn untimed(beat: u64) -> Self {
+        BeatTime { beat, wall_micros: 0 }
+    }
+}
+
+/// Event-time width from `--event-window-ms`; None when event time is off.
+pub(crate) fn window_micros(args: &MainArg) -> Option<u64> {
+    (args.event_window_ms > 0).then(|| args.event_window_ms * 1000)
+}
+
+/// One event-time window the logger closed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ClosedWindow {
+    /// Inclusive start and exclusive end, in wall microseconds.
+    pub(crate) start: u64,
+    pub(crate) end: u64,
+    pub(crate) messages: u64,
+}
+
+impl ClosedWindow {
+    /// `start..end` as RFC 3339 times for the logs.
+    pub(crate) fn span(&self) -> String {
+        let at = |micros: u64| DateTime::from_timestamp_micros(micros as i64)
+            .map_or_else(|| micros.to_string(), |t| t.to_rfc3339_opts(SecondsFormat::Millis, true));
+        format!("{}..{}", at(self.start), at(self.end))
+    }
+}
+
+/// Tumbling event-time windows over the logger's messages.
+/// Messages arrive in the order their beats released them, so the newest event time seen
+/// is the watermark: a message past the open window's end closes it. One stamped before
+/// the open window is late and only counted. Lives in the logger's state, so a restart
+/// neither reopens a closed window nor loses the open one's count.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct EventWindows {
+    width: u64,
+    open: Option<(u64, u64)>,
+    pub(crate) closed: u64,
+    pub(crate) late: u64,
+}
+
+impl EventWindows {
+    /// Windows `width` microseconds wide, aligned to the Unix epoch.
+    pub(crate) fn new(width: u64) -> Self {
+        EventWindows { width, ..Default::default() }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.width > 0
+    }
+
+    /// Counts one message stamped `event_micros`, returning the window it closed, if any.
+    pub(crate) fn observe(&mut self, event_micros: u64) -> Option<ClosedWindow> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let start = event_micros - event_micros % self.width;
+        match self.open {
+            Some((open, ref mut messages)) if start == open => {
+                *messages += 1;
+                None
+            }
+            Some((open, _)) if start < open => {
+                self.late += 1;
+                None
+            }
+            _ => {
+                let closed = self.close();
+                self.open = Some((start, 1));
+                closed
+            }
+        }
+    }
+
+    /// Closes the open window, as the logger does when it stops.
+    pub(crate) fn close(&mut self) -> Option<ClosedWindow> {
+        let (start, messages) = self.open.take()?;
+        self.closed += 1;
+        Some(ClosedWindow { start, end: start + self.width, messages })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod event_time_tests {
+    use super::*;
+
+    #[test]
+    fn test_event_windows() {
+        let mut windows = EventWindows::new(1_000);
+        assert_eq!(windows.observe(10_100), None);
+        assert_eq!(windows.observe(10_999), None);
+        // a later window closes the open one, an earlier message is only late
+        assert_eq!(windows.observe(12_000), Some(ClosedWindow { start: 10_000, end: 11_000, messages: 2 }));
+        assert_eq!(windows.observe(11_500), None);
+        assert_eq!(windows.close(), Some(ClosedWindow { start: 12_000, end: 13_000, messages: 1 }));
+        assert_eq!((windows.closed, windows.late, windows.close()), (2, 1, None));
+        assert_eq!(EventWindows::default().observe(10_000), None);
+
+        let window = ClosedWindow { start: 1_700_000_000_000_000, end: 1_700_000_001_000_000, messages: 3 };
+        assert_eq!(window.span(), "2023-11-14T22:13:20.000Z..2023-11-14T22:13:21.000Z");
+        assert_eq!(window_micros(&MainArg { event_window_ms: 250, ..Default::default() }), Some(250_000));
+        assert_eq!(window_micros(&MainArg::default()), None);
+    }
+}
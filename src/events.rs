@@ -0,0 +1,69 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use serde_json::Value;
+use steady_state::*;
+use crate::timing::Stamp;
+
+/// The `--events-file`, once `open` has run; without it every event is skipped.
+static EVENTS: OnceLock<Mutex<BufWriter<File>>> = OnceLock::new();
+
+/// Opens the events file for appending, so runs sharing a file stay apart by run id.
+pub(crate) fn open(path: &Path) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let _ = EVENTS.set(Mutex::new(BufWriter::new(file)));
+    Ok(())
+}
+
+/// One framework event as an NDJSON line: `ts_us`, `run` and `event` first, then the keys of `fields`.
+fn line(ts_us: u64, run: &str, event: &str, fields: Value) -> String {
+    let mut text = format!("{{\"ts_us\":{},\"run\":{},\"event\":{}", ts_us, Value::from(run), Value::from(event));
+    if let Value::Object(fields) = fields {
+        for (key, value) in fields {
+            text.push_str(&format!(",{}:{}", Value::from(key), value));
+        }
+    }
+    text.push('}');
+    text
+}
+
+/// Appends an event to the events file and flushes it, so the timeline survives an abort.
+/// Events are `startup`, `restart`, `showstopper`, `shutdown`, `alert` and `backfill`.
+/// A failed write is logged and otherwise ignored; the events file never stops the pipeline.
+/// Each event is also kept for crash dumps, under the actor that emitted it.
+pub(crate) fn emit(event: &str, fields: Value) {
+    if EVENTS.get().is_none() && !crate::crash::is_enabled() {
+        return;
+    }
+    let text = line(Stamp::now().wall_micros, crate::logging::run_id(), event, fields);
+    crate::crash::remember(&crate::crash::current_actor(), || text.clone());
+    let Some(events) = EVENTS.get() else {
+        return;
+    };
+    let written = events.lock().map_err(|_| io::Error::other("events file lock poisoned")).and_then(|mut out| {
+        writeln!(out, "{}", text)?;
+        out.flush()
+    });
+    if let Err(e) = written {
+        warn!("Could not write {} event to the events file: {}", event, e);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod events_tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_event_line() {
+        let text = line(1_700_000_000_000_000, "soak-1", "restart", json!({"actor": "WORKER", "cause": "panic", "starts": 2}));
+        assert_eq!(
+            text,
+            r#"{"ts_us":1700000000000000,"run":"soak-1","event":"restart","actor":"WORKER","cause":"panic","starts":2}"#
+        );
+        assert!(!text.contains('\n'));
+        // nothing to write to without --events-file
+        emit("alert", json!({"kind": "test"}));
+    }
+}
@@ -0,0 +1,137 @@
+use std::fmt;
+use crate::report::RunReport;
+
+/// Reads one number from the final report.
+type Read = fn(&RunReport) -> f64;
+
+/// The final report's numbers `--expect` can check, by the name it takes on the command line.
+const METRICS: [(&str, Read); 17] = [
+    ("generated", |r| r.generated as f64),
+    ("processed", |r| r.worker_values_processed as f64),
+    ("logged", |r| r.logged as f64),
+    ("rejected", |r| r.rejected as f64),
+    ("loss", |r| r.lost() as f64),
+    ("restarts", |r| r.restarts() as f64),
+    ("showstoppers", |r| (r.worker_showstoppers + r.logger_showstoppers) as f64),
+    ("duplicates", |r| r.logger_duplicates as f64),
+    ("sink_failures", |r| r.sink_failures as f64),
+    ("undrained", |r| (r.worker_residue + r.logger_residue) as f64),
+    ("channel_residue", |r| r.channel_residue.iter().map(|c| c.items).sum::<u64>() as f64),
+    ("throughput", RunReport::throughput),
+    ("latency_p50_us", |r| r.latency_p50_us as f64),
+    ("latency_p99_us", |r| r.latency_p99_us as f64),
+    ("latency_max_us", |r| r.latency_max_us as f64),
+    ("loopback_mismatches", |r| r.loopback_mismatched as f64),
+    ("fill_alerts", |r| r.fill_alerts as f64),
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Op {
+    Eq,
+    Le,
+    Ge,
+    Lt,
+    Gt,
+}
+
+impl Op {
+    fn holds(self, actual: f64, expected: f64) -> bool {
+        match self {
+            Op::Eq => actual == expected,
+            Op::Le => actual <= expected,
+            Op::Ge => actual >= expected,
+            Op::Lt => actual < expected,
+            Op::Gt => actual > expected,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Op::Eq => "=",
+            Op::Le => "<=",
+            Op::Ge => ">=",
+            Op::Lt => "<",
+            Op::Gt => ">",
+        }
+    }
+}
+
+/// One `--expect` condition on the final report, such as `restarts<=5`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct Expectation {
+    /// Index into `METRICS`.
+    metric: usize,
+    op: Op,
+    value: f64,
+}
+
+impl fmt::Display for Expectation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}{}", METRICS[self.metric].0, self.op.symbol(), self.value)
+    }
+}
+
+/// Parses `METRIC=N`, `METRIC<=N`, `METRIC>=N`, `METRIC<N` or `METRIC>N`.
+pub(crate) fn parse_expectation(text: &str) -> Result<Expectation, String> {
+    let at = text.find(['=', '<', '>']).ok_or_else(|| format!("expected METRIC=N, METRIC<=N or METRIC>=N, got '{}'", text))?;
+    let (name, rest) = text.split_at(at);
+    let (op, value) = [("<=", Op::Le), (">=", Op::Ge), ("=", Op::Eq), ("<", Op::Lt), (">", Op::Gt)]
+        .into_iter()
+        .find_map(|(symbol, op)| rest.strip_prefix(symbol).map(|value| (op, value)))
+        .expect("starts with one of the operators");
+    let name = name.trim().to_ascii_lowercase();
+    let metric = METRICS
+        .iter()
+        .position(|(metric, _)| *metric == name)
+        .ok_or_else(|| {
+            let names: Vec<&str> = METRICS.iter().map(|(metric, _)| *metric).collect();
+            format!("unknown metric '{}', expected one of {}", name, names.join(", "))
+        })?;
+    let value = value.trim().parse().map_err(|e| format!("bad number in '{}': {}", text, e))?;
+    Ok(Expectation { metric, op, value })
+}
+
+/// The `--expect` conditions a run did not meet, each with the value it had.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct UnmetExpectations(pub(crate) Vec<(Expectation, f64)>);
+
+impl fmt::Display for UnmetExpectations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unmet: Vec<String> = self.0.iter().map(|(expected, actual)| format!("{} (was {})", expected, actual)).collect();
+        write!(f, "Run did not meet {} expectation(s): {}", unmet.len(), unmet.join(", "))
+    }
+}
+
+impl std::error::Error for UnmetExpectations {}
+
+/// Checks the report against every expectation.
+pub(crate) fn check(report: &RunReport, expectations: &[Expectation]) -> Result<(), UnmetExpectations> {
+    let unmet: Vec<(Expectation, f64)> = expectations
+        .iter()
+        .map(|expected| (*expected, (METRICS[expected.metric].1)(report)))
+        .filter(|(expected, actual)| !expected.op.holds(*actual, expected.value))
+        .collect();
+    if unmet.is_empty() { Ok(()) } else { Err(UnmetExpectations(unmet)) }
+}
+
+#[cfg(test)]
+pub(crate) mod expect_tests {
+    use super::*;
+
+    #[test]
+    fn test_expectations() {
+        let parse = |text: &str| parse_expectation(text).expect("valid");
+        assert_eq!(parse("generated=1000"), Expectation { metric: 0, op: Op::Eq, value: 1000.0 });
+        assert_eq!(parse("Restarts <= 5").op, Op::Le);
+        assert_eq!(parse("throughput>12.5").value, 12.5);
+        assert!(parse_expectation("speed=3").is_err());
+        assert!(parse_expectation("loss=").is_err());
+        assert!(parse_expectation("loss").is_err());
+
+        let report = RunReport { generated: 1000, logged: 990, rejected: 10, ..Default::default() };
+        let expectations = ["generated=1000", "loss=0", "restarts<=5"].map(parse);
+        assert_eq!(check(&report, &expectations), Ok(()));
+        let unmet = check(&report, &["logged>=1000", "loss=0"].map(parse)).expect_err("unmet");
+        assert_eq!(unmet.to_string(), "Run did not meet 1 expectation(s): logged>=1000 (was 990)");
+    }
+}
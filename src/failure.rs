@@ -0,0 +1,276 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use crate::MainArg;
+
+/// The actors that panic on purpose to demonstrate recovery.
+pub(crate) const FAILING_ACTORS: [&str; 4] = ["heartbeat", "generator", "worker", "logger"];
+
+/// One `--fail-at ACTOR=N` setting, or `ACTOR=off` to keep that actor from failing.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct FailurePoint {
+    /// Index into `FAILING_ACTORS`.
+    pub(crate) actor: usize,
+    pub(crate) at: Option<u64>,
+}
+
+/// Parses `ACTOR=N` or `ACTOR=off`, with the actor in any case.
+pub(crate) fn parse_fail_at(text: &str) -> Result<FailurePoint, String> {
+    let (actor, at) = text
+        .split_once('=')
+        .ok_or_else(|| format!("expected ACTOR=N or ACTOR=off, got '{}'", text))?;
+    let actor = actor.trim().to_ascii_lowercase();
+    let actor = FAILING_ACTORS
+        .iter()
+        .position(|&a| a == actor)
+        .ok_or_else(|| format!("unknown actor '{}', expected one of {}", actor, FAILING_ACTORS.join(", ")))?;
+    let at = match at.trim() {
+        "off" => None,
+        n => Some(n.parse().map_err(|e| format!("bad trigger in '{}': {}", text, e))?),
+    };
+    Ok(FailurePoint { actor, at })
+}
+
+/// When each actor panics on purpose. Every trigger fires once per run:
+/// - heartbeat: at this beat count, on its first start only
+/// - generator: on this message, counting from 1
+/// - worker: on this value, until the showstopper threshold drops it
+/// - logger: on the record for this plain value, until its showstopper threshold drops it
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct FailureSchedule {
+    pub(crate) heartbeat: Option<u64>,
+    pub(crate) generator: Option<u64>,
+    pub(crate) worker: Option<u64>,
+    pub(crate) logger: Option<u64>,
+}
+
+impl Default for FailureSchedule {
+    /// The lesson's original demonstration timings.
+    fn default() -> Self {
+        FailureSchedule { heartbeat: Some(7), generator: Some(13), worker: Some(33), logger: Some(41) }
+    }
+}
+
+impl FailureSchedule {
+    pub(crate) const NONE: FailureSchedule = FailureSchedule { heartbeat: None, generator: None, worker: None, logger: None };
+
+    /// The default schedule with `--fail-at` applied. Unit tests start from no failures
+    /// instead, and inject faults exactly where they need them.
+    pub(crate) fn from_args(args: &MainArg) -> Self {
+        let base = if cfg!(test) { FailureSchedule::NONE } else { FailureSchedule::default() };
+        base.with(&args.fail_at)
+    }
+
+    /// The trigger for the actor at `index` into `FAILING_ACTORS`.
+    pub(crate) fn at(&self, index: usize) -> Option<u64> {
+        [self.heartbeat, self.generator, self.worker, self.logger][index]
+    }
+
+    /// Applies each setting in order, so the last one for an actor wins.
+    pub(crate) fn with(mut self, points: &[FailurePoint]) -> Self {
+        for point in points {
+            let slot = match point.actor {
+                0 => &mut self.heartbeat,
+                1 => &mut self.generator,
+                2 => &mut self.worker,
+                _ => &mut self.logger,
+            };
+            *slot = point.at;
+        }
+        self
+    }
+}
+
+/// A timer misbehavior the heartbeat can fake.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum BeatFault {
+    /// The tick passes without a beat, leaving a gap in the beat numbers.
+    Skip,
+    /// The tick fires twice, sending the same beat number back to back.
+    Stutter,
+}
+
+/// One `--heartbeat-fault KIND=N` setting: the fault strikes every Nth beat.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct HeartbeatFault {
+    pub(crate) kind: BeatFault,
+    pub(crate) every: u64,
+}
+
+/// Parses `skip=N` or `stutter=N`, with N at least 1.
+pub(crate) fn parse_heartbeat_fault(text: &str) -> Result<HeartbeatFault, String> {
+    let (kind, every) = text
+        .split_once('=')
+        .ok_or_else(|| format!("expected skip=N or stutter=N, got '{}'", text))?;
+    let kind = match kind.trim().to_ascii_lowercase().as_str() {
+        "skip" => BeatFault::Skip,
+        "stutter" => BeatFault::Stutter,
+        other => return Err(format!("unknown heartbeat fault '{}', expected skip or stutter", other)),
+    };
+    let every: u64 = every.trim().parse().map_err(|e| format!("bad interval in '{}': {}", text, e))?;
+    if every == 0 {
+        return Err(format!("interval in '{}' must be at least 1", text));
+    }
+    Ok(HeartbeatFault { kind, every })
+}
+
+/// The fault striking beat `beat`, if any. Beat 0 is never faulted, and a skip wins over a stutter.
+pub(crate) fn beat_fault_at(faults: &[HeartbeatFault], beat: u64) -> Option<BeatFault> {
+    let strikes = |kind| faults.iter().any(|f| f.kind == kind && beat > 0 && beat.is_multiple_of(f.every));
+    [BeatFault::Skip, BeatFault::Stutter].into_iter().find(|&kind| strikes(kind))
+}
+
+/// The span a `--failure-budget` covers: restarts and drops older than this no longer count.
+pub(crate) const BUDGET_WINDOW: Duration = Duration::from_secs(3600);
+
+/// Whether the injected failures, `--fail-at`, `--fault` and `--heartbeat-fault`, may still strike.
+/// Clones share one switch, so when the reconciler turns it off every actor sees it at once.
+/// It never turns back on.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ChaosSwitch(Arc<AtomicBool>);
+
+impl ChaosSwitch {
+    /// The run's switch, or one that is never turned off when there is no `--failure-budget`.
+    pub(crate) fn from_args(args: &MainArg) -> Self {
+        args.failure_budget.as_ref().map(|budget| budget.chaos.clone()).unwrap_or_default()
+    }
+
+    pub(crate) fn is_on(&self) -> bool {
+        !self.0.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn turn_off(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+impl PartialEq for ChaosSwitch {
+    fn eq(&self, other: &Self) -> bool {
+        self.is_on() == other.is_on()
+    }
+}
+
+/// `--failure-budget`: the restarts and showstopper drops the demonstration failures may cost
+/// in any hour. Once either is used up the reconciler turns them off for the rest of the run.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct FailureBudget {
+    pub(crate) restarts: Option<u64>,
+    pub(crate) drops: Option<u64>,
+    pub(crate) chaos: ChaosSwitch,
+}
+
+impl FailureBudget {
+    /// Which allowance the last hour's restarts and drops have used up, if either.
+    pub(crate) fn used_up(&self, restarts: u64, drops: u64) -> Option<&'static str> {
+        if self.restarts.is_some_and(|allowed| restarts >= allowed) {
+            Some("restarts")
+        } else if self.drops.is_some_and(|allowed| drops >= allowed) {
+            Some("drops")
+        } else {
+            None
+        }
+    }
+}
+
+/// Parses `restarts=N`, `drops=N` or both, comma separated, e.g. `restarts=10,drops=2`.
+pub(crate) fn parse_failure_budget(text: &str) -> Result<FailureBudget, String> {
+    let mut budget = FailureBudget::default();
+    for part in text.split(',') {
+        let (kind, allowed) = part
+            .split_once('=')
+            .ok_or_else(|| format!("expected restarts=N and/or drops=N, got '{}'", part))?;
+        let allowed: u64 = allowed.trim().parse().map_err(|e| format!("bad allowance in '{}': {}", part, e))?;
+        match kind.trim().to_ascii_lowercase().as_str() {
+            "restarts" => budget.restarts = Some(allowed),
+            "drops" => budget.drops = Some(allowed),
+            other => return Err(format!("unknown allowance '{}', expected restarts or drops", other)),
+        }
+    }
+    Ok(budget)
+}
+
+/// Run totals of restarts and drops as the reconciler samples them, kept for `BUDGET_WINDOW` so
+/// the budget is spent only by what happened within it. Held as a local: a restarted reconciler
+/// treats everything so far as recent, erring toward turning the failures off sooner.
+pub(crate) struct BudgetLedger {
+    /// Oldest first; the first entry is the last one at least a window old, the baseline.
+    samples: VecDeque<(Instant, u64, u64)>,
+}
+
+impl BudgetLedger {
+    pub(crate) fn new(now: Instant) -> Self {
+        BudgetLedger { samples: VecDeque::from([(now, 0, 0)]) }
+    }
+
+    /// Takes the run's totals at `now` and returns the restarts and drops within the last window.
+    pub(crate) fn observe(&mut self, restarts: u64, drops: u64, now: Instant) -> (u64, u64) {
+        self.samples.push_back((now, restarts, drops));
+        while self.samples.len() > 1 && now.duration_since(self.samples[1].0) >= BUDGET_WINDOW {
+            self.samples.pop_front();
+        }
+        let (_, restarts_before, drops_before) = self.samples[0];
+        (restarts.saturating_sub(restarts_before), drops.saturating_sub(drops_before))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod failure_tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule_from_args() {
+        assert_eq!(parse_fail_at("Worker=50"), Ok(FailurePoint { actor: 2, at: Some(50) }));
+        assert_eq!(parse_fail_at("logger=off"), Ok(FailurePoint { actor: 3, at: None }));
+        assert!(parse_fail_at("reconciler=3").is_err());
+        assert!(parse_fail_at("heartbeat").is_err());
+        assert!(parse_fail_at("generator=soon").is_err());
+
+        let points = ["worker=50", "logger=off", "worker=60"].map(|p| parse_fail_at(p).expect("valid"));
+        let schedule = FailureSchedule::default().with(&points);
+        assert_eq!(schedule, FailureSchedule { heartbeat: Some(7), generator: Some(13), worker: Some(60), logger: None });
+        // tests only fail where they ask to
+        let args = MainArg { fail_at: points[..1].to_vec(), ..Default::default() };
+        assert_eq!(FailureSchedule::from_args(&args), FailureSchedule { worker: Some(50), ..FailureSchedule::NONE });
+    }
+
+    #[test]
+    fn test_heartbeat_faults() {
+        assert_eq!(parse_heartbeat_fault("Skip=10"), Ok(HeartbeatFault { kind: BeatFault::Skip, every: 10 }));
+        assert!(parse_heartbeat_fault("skip=0").is_err());
+        assert!(parse_heartbeat_fault("drift=5").is_err());
+
+        let faults = ["skip=3", "stutter=2"].map(|f| parse_heartbeat_fault(f).expect("valid"));
+        let struck: Vec<_> = (0..7).map(|beat| beat_fault_at(&faults, beat)).collect();
+        assert_eq!(struck, [None, None, Some(BeatFault::Stutter), Some(BeatFault::Skip), Some(BeatFault::Stutter), None, Some(BeatFault::Skip)]);
+    }
+
+    #[test]
+    fn test_failure_budget() {
+        let budget = parse_failure_budget("restarts=5, Drops=1").expect("valid");
+        assert_eq!((budget.restarts, budget.drops), (Some(5), Some(1)));
+        assert_eq!(parse_failure_budget("drops=0").map(|b| b.restarts), Ok(None));
+        assert!(parse_failure_budget("restarts").is_err());
+        assert!(parse_failure_budget("panics=3").is_err());
+        assert_eq!(budget.used_up(4, 0), None);
+        assert_eq!(budget.used_up(5, 0), Some("restarts"));
+        assert_eq!(budget.used_up(0, 1), Some("drops"));
+
+        // only the last hour counts: the baseline is the last total sampled an hour or more ago
+        let start = Instant::now();
+        let mut ledger = BudgetLedger::new(start);
+        let at = |minutes: u64| start + Duration::from_secs(minutes * 60);
+        assert_eq!(ledger.observe(3, 1, at(10)), (3, 1));
+        assert_eq!(ledger.observe(4, 1, at(50)), (4, 1));
+        assert_eq!(ledger.observe(6, 1, at(75)), (3, 0));
+        assert_eq!(ledger.observe(6, 2, at(120)), (2, 1));
+
+        // every clone of the arguments shares the switch
+        let args = MainArg { failure_budget: Some(budget), ..Default::default() };
+        let (chaos, copy) = (ChaosSwitch::from_args(&args), ChaosSwitch::from_args(&args.clone()));
+        assert!(copy.is_on());
+        chaos.turn_off();
+        assert!(!copy.is_on());
+        assert!(ChaosSwitch::from_args(&MainArg::default()).is_on());
+    }
+}
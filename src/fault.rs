@@ -0,0 +1,237 @@
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use crate::failure::{ChaosSwitch, FailureSchedule, FAILING_ACTORS};
+use crate::MainArg;
+
+/// What an injected fault does to the actor it strikes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum FaultAction {
+    /// Panics, so the actor is restarted with its state.
+    Panic,
+    /// Holds the actor this long before it goes on, as a slow dependency would.
+    Delay(Duration),
+    /// Refuses the actor's next send, as a full channel or a failing sink would, so it retries.
+    SendFail,
+}
+
+/// The steps of its actor a fault strikes at.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum FaultWhen {
+    At(u64),
+    Every(u64),
+}
+
+impl FaultWhen {
+    fn is_due(self, step: u64) -> bool {
+        match self {
+            FaultWhen::At(at) => step == at,
+            FaultWhen::Every(every) => step > 0 && step.is_multiple_of(every),
+        }
+    }
+}
+
+/// One scheduled fault, from `--fault`, a `--fault-plan` file or `--fail-at`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Fault {
+    /// Index into `FAILING_ACTORS`.
+    pub(crate) actor: usize,
+    pub(crate) action: FaultAction,
+    pub(crate) when: FaultWhen,
+}
+
+/// Parses `ACTOR=ACTION@WHEN`: ACTION is `panic`, `delay:MS` or `send-fail`, and WHEN is a step
+/// `N` or `every:N`, e.g. `worker=delay:25ms@every:10`.
+pub(crate) fn parse_fault(text: &str) -> Result<Fault, String> {
+    let (actor, rest) = text
+        .split_once('=')
+        .ok_or_else(|| format!("expected ACTOR=ACTION@WHEN, got '{}'", text))?;
+    let actor = actor.trim().to_ascii_lowercase();
+    let actor = FAILING_ACTORS
+        .iter()
+        .position(|&a| a == actor)
+        .ok_or_else(|| format!("unknown actor '{}', expected one of {}", actor, FAILING_ACTORS.join(", ")))?;
+    let (action, when) = rest
+        .split_once('@')
+        .ok_or_else(|| format!("expected ACTION@WHEN after the actor in '{}'", text))?;
+    let action = match action.trim().to_ascii_lowercase().as_str() {
+        "panic" => FaultAction::Panic,
+        "send-fail" => FaultAction::SendFail,
+        other => {
+            let ms = other
+                .strip_prefix("delay:")
+                .ok_or_else(|| format!("unknown fault '{}', expected panic, delay:MS or send-fail", other))?;
+            let ms: u64 = ms.strip_suffix("ms").unwrap_or(ms).parse().map_err(|e| format!("bad delay in '{}': {}", text, e))?;
+            FaultAction::Delay(Duration::from_millis(ms))
+        }
+    };
+    let when = when.trim().to_ascii_lowercase();
+    let when = match when.strip_prefix("every:") {
+        Some(every) => {
+            let every: u64 = every.parse().map_err(|e| format!("bad interval in '{}': {}", text, e))?;
+            if every == 0 {
+                return Err(format!("interval in '{}' must be at least 1", text));
+            }
+            FaultWhen::Every(every)
+        }
+        None => FaultWhen::At(when.parse().map_err(|e| format!("bad step in '{}': {}", text, e))?),
+    };
+    Ok(Fault { actor, action, when })
+}
+
+/// The faults of a `--fault-plan` file, read when the arguments are parsed.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct FaultPlanFile {
+    pub(crate) path: PathBuf,
+    pub(crate) faults: Vec<Fault>,
+}
+
+/// Reads a fault plan: one `--fault` setting per line, with blank lines and `#` comments skipped.
+pub(crate) fn parse_fault_plan(path: &str) -> Result<FaultPlanFile, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("cannot read fault plan {:?}: {}", path, e))?;
+    let faults = text
+        .lines()
+        .enumerate()
+        .map(|(n, line)| (n + 1, line.split('#').next().unwrap_or_default().trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(n, line)| parse_fault(line).map_err(|e| format!("{}:{}: {}", path, n, e)))
+        .collect::<Result<_, _>>()?;
+    Ok(FaultPlanFile { path: PathBuf::from(path), faults })
+}
+
+/// Everything due at one step, taken together.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Strike {
+    pub(crate) panic: bool,
+    pub(crate) delay: Option<Duration>,
+    pub(crate) send_fail: bool,
+}
+
+/// Faults struck so far, by kind, for the final report.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct FaultCounts {
+    pub(crate) panics: u64,
+    pub(crate) delays: u64,
+    pub(crate) send_failures: u64,
+}
+
+impl FaultCounts {
+    pub(crate) fn add(&mut self, other: FaultCounts) {
+        self.panics += other.panics;
+        self.delays += other.delays;
+        self.send_failures += other.send_failures;
+    }
+}
+
+/// One actor's faults, kept in its persistent state so a restart neither replays nor forgets them.
+/// Steps are the actor's own count: the heartbeat's beat count, the generator's message number,
+/// the worker's value, and the logger's plain value (Fizz, Buzz and FizzBuzz records carry none).
+/// A fault strikes once per step it is due at, except a worker or logger panic: those strike every
+/// time the actor comes back to the value, until its showstopper threshold drops it.
+#[derive(Clone, Debug)]
+pub(crate) struct FaultPlan {
+    /// Each fault with the step it last struck at.
+    faults: Vec<(Fault, Option<u64>)>,
+    repeat_panics: bool,
+    chaos: ChaosSwitch,
+    pub(crate) injected: FaultCounts,
+}
+
+impl FaultPlan {
+    /// The faults for `actor`, one of `FAILING_ACTORS`: its `--fail-at` panic, then those of the
+    /// `--fault-plan` file, then each `--fault`.
+    pub(crate) fn for_actor(args: &MainArg, actor: &str) -> Self {
+        let index = FAILING_ACTORS.iter().position(|&a| a == actor).expect("a failing actor");
+        let schedule = FailureSchedule::from_args(args);
+        let demonstration = schedule.at(index).map(|at| Fault { actor: index, action: FaultAction::Panic, when: FaultWhen::At(at) });
+        let planned = args.fault_plan.iter().flat_map(|plan| plan.faults.iter()).chain(args.fault.iter());
+        FaultPlan {
+            faults: demonstration.into_iter().chain(planned.copied()).filter(|f| f.actor == index).map(|f| (f, None)).collect(),
+            repeat_panics: matches!(actor, "worker" | "logger"),
+            chaos: ChaosSwitch::from_args(args),
+            injected: FaultCounts::default(),
+        }
+    }
+
+    /// What strikes the actor at `step`. Nothing does once `--failure-budget` turned the faults off.
+    pub(crate) fn strike(&mut self, step: u64) -> Strike {
+        let mut strike = Strike::default();
+        if !self.chaos.is_on() {
+            return strike;
+        }
+        for (fault, last) in self.faults.iter_mut() {
+            let repeats = fault.action == FaultAction::Panic && self.repeat_panics;
+            if !fault.when.is_due(step) || (*last == Some(step) && !repeats) {
+                continue;
+            }
+            *last = Some(step);
+            match fault.action {
+                FaultAction::Panic => {
+                    strike.panic = true;
+                    self.injected.panics += 1;
+                }
+                FaultAction::Delay(delay) => {
+                    strike.delay = Some(strike.delay.unwrap_or_default() + delay);
+                    self.injected.delays += 1;
+                }
+                FaultAction::SendFail => {
+                    strike.send_fail = true;
+                    self.injected.send_failures += 1;
+                }
+            }
+        }
+        strike
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod fault_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_faults() {
+        assert_eq!(parse_fault("Worker=delay:25ms@every:10"),
+                   Ok(Fault { actor: 2, action: FaultAction::Delay(Duration::from_millis(25)), when: FaultWhen::Every(10) }));
+        assert_eq!(parse_fault("generator=panic@13"), Ok(Fault { actor: 1, action: FaultAction::Panic, when: FaultWhen::At(13) }));
+        assert_eq!(parse_fault("logger=send-fail@EVERY:50").map(|f| f.action), Ok(FaultAction::SendFail));
+        for bad in ["worker", "worker=panic", "reconciler=panic@3", "worker=explode@3", "worker=panic@every:0", "worker=delay:soon@3"] {
+            assert!(parse_fault(bad).is_err(), "{}", bad);
+        }
+
+        let path = std::env::temp_dir().join(format!("robust-faults-{}.txt", crate::arg::new_run_id()));
+        std::fs::write(&path, "# chaos for the soak\nworker=send-fail@every:5\n\nheartbeat=delay:100@4  # a slow tick\n").expect("write");
+        let plan = parse_fault_plan(path.to_str().expect("utf-8")).expect("valid plan");
+        assert_eq!(plan.faults.len(), 2);
+        std::fs::write(&path, "worker=panic@3\nworker=panic\n").expect("write");
+        let err = parse_fault_plan(path.to_str().expect("utf-8")).expect_err("line 2 is bad");
+        assert!(err.ends_with(":2: expected ACTION@WHEN after the actor in 'worker=panic'"), "{}", err);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_plan_strikes() {
+        let faults = ["worker=delay:10@every:2", "worker=delay:5@4", "worker=send-fail@every:3", "generator=panic@2"]
+            .map(|f| parse_fault(f).expect("valid")).to_vec();
+        let args = MainArg { fault: faults, fail_at: vec![crate::failure::parse_fail_at("worker=5").expect("valid")], ..Default::default() };
+        let mut worker = FaultPlan::for_actor(&args, "worker");
+        assert_eq!(worker.strike(1), Strike::default());
+        assert_eq!(worker.strike(4), Strike { delay: Some(Duration::from_millis(15)), ..Default::default() });
+        // coming back to a step does not strike again, except with a worker panic
+        assert_eq!(worker.strike(4), Strike::default());
+        assert_eq!(worker.strike(6), Strike { delay: Some(Duration::from_millis(10)), send_fail: true, ..Default::default() });
+        assert!(worker.strike(5).panic && worker.strike(5).panic);
+        assert_eq!(worker.injected, FaultCounts { panics: 2, delays: 3, send_failures: 1 });
+
+        let mut generator = FaultPlan::for_actor(&args, "generator");
+        assert!(generator.strike(2).panic);
+        assert!(!generator.strike(2).panic);
+        assert_eq!(FaultPlan::for_actor(&args, "logger").faults.len(), 0);
+
+        // a spent failure budget turns every fault off
+        let args = MainArg { failure_budget: Some(crate::failure::parse_failure_budget("restarts=1").expect("valid")), ..args };
+        let mut worker = FaultPlan::for_actor(&args, "worker");
+        ChaosSwitch::from_args(&args).turn_off();
+        assert_eq!(worker.strike(6), Strike::default());
+    }
+}
@@ -0,0 +1,120 @@
+use std::fmt;
+use steady_state::*;
+
+/// When the logger forces the file sink and the WAL onto disk with fsync. A flushed write only
+/// reaches the operating system, which survives the process dying but not the power failing.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) enum FsyncPolicy {
+    /// Leaves it to the operating system, as the logger always has.
+    #[default]
+    Never,
+    /// Syncs at the first commit once this long has passed since the last sync, and at a clean stop.
+    Interval(Duration),
+    /// Syncs every record before it counts as committed.
+    EveryMessage,
+}
+
+impl fmt::Display for FsyncPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FsyncPolicy::Never => write!(f, "never"),
+            FsyncPolicy::Interval(every) => write!(f, "interval:{}", every.as_millis()),
+            FsyncPolicy::EveryMessage => write!(f, "every-message"),
+        }
+    }
+}
+
+/// Parses `never`, `interval:MS` or `every-message`.
+pub(crate) fn parse_fsync(text: &str) -> Result<FsyncPolicy, String> {
+    match text.trim().to_ascii_lowercase().as_str() {
+        "never" => Ok(FsyncPolicy::Never),
+        "every-message" => Ok(FsyncPolicy::EveryMessage),
+        other => match other.strip_prefix("interval:").map(str::parse::<u64>) {
+            Some(Ok(ms)) if ms > 0 => Ok(FsyncPolicy::Interval(Duration::from_millis(ms))),
+            _ => Err(format!("expected 'never', 'interval:MS' with MS > 0 or 'every-message', got '{}'", text)),
+        },
+    }
+}
+
+/// The records a power failure could still take: those committed since the files were last
+/// synced. Kept in the logger's state, so the count carries across its restarts.
+#[derive(Clone, Debug)]
+pub(crate) struct Fsync {
+    pub(crate) policy: FsyncPolicy,
+    last_sync: Instant,
+    /// Committed records not yet synced, and the most there have been at once.
+    pub(crate) at_risk: u64,
+    pub(crate) at_risk_max: u64,
+    pub(crate) syncs: u64,
+}
+
+impl Fsync {
+    pub(crate) fn new(policy: FsyncPolicy) -> Self {
+        Fsync { policy, last_sync: Instant::now(), at_risk: 0, at_risk_max: 0, syncs: 0 }
+    }
+
+    /// Counts a committed record. True when the policy wants the files synced now, before the
+    /// record counts as committed, so it is never left at risk.
+    pub(crate) fn committed(&mut self) -> bool {
+        self.at_risk += 1;
+        let due = match self.policy {
+            FsyncPolicy::Never => false,
+            FsyncPolicy::Interval(every) => self.last_sync.elapsed() >= every,
+            FsyncPolicy::EveryMessage => true,
+        };
+        if !due {
+            self.at_risk_max = self.at_risk_max.max(self.at_risk);
+        }
+        due
+    }
+
+    /// True at a clean stop when records are still at risk under a policy that syncs at all.
+    pub(crate) fn due_at_stop(&self) -> bool {
+        self.policy != FsyncPolicy::Never && self.at_risk > 0
+    }
+
+    /// Records that a sync failed, which leaves the records it was for at risk.
+    pub(crate) fn sync_failed(&mut self) {
+        self.at_risk_max = self.at_risk_max.max(self.at_risk);
+    }
+
+    /// Records that the files were synced, so nothing committed so far is at risk.
+    pub(crate) fn synced(&mut self) {
+        self.at_risk = 0;
+        self.last_sync = Instant::now();
+        self.syncs += 1;
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod fsync_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_fsync() {
+        assert_eq!(parse_fsync("never"), Ok(FsyncPolicy::Never));
+        assert_eq!(parse_fsync("Every-Message"), Ok(FsyncPolicy::EveryMessage));
+        assert_eq!(parse_fsync("interval:50"), Ok(FsyncPolicy::Interval(Duration::from_millis(50))));
+        assert_eq!(FsyncPolicy::Interval(Duration::from_millis(50)).to_string(), "interval:50");
+        assert!(parse_fsync("interval:0").is_err());
+        assert!(parse_fsync("interval").is_err());
+        assert!(parse_fsync("always").is_err());
+    }
+
+    #[test]
+    fn test_records_at_risk() {
+        let mut every = Fsync::new(FsyncPolicy::EveryMessage);
+        assert!(every.committed());
+        every.synced();
+        assert_eq!((every.at_risk, every.at_risk_max, every.syncs), (0, 0, 1));
+
+        let mut never = Fsync::new(FsyncPolicy::Never);
+        assert!(!(0..3).any(|_| never.committed()));
+        assert_eq!((never.at_risk, never.at_risk_max), (3, 3));
+        assert!(!never.due_at_stop());
+
+        let mut interval = Fsync::new(FsyncPolicy::Interval(Duration::from_secs(3600)));
+        assert!(!interval.committed());
+        assert!(interval.due_at_stop());
+    }
+}
@@ -0,0 +1,198 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use steady_state::*;
+
+/// Lines the reader thread reads ahead of the generator.
+const READ_AHEAD: usize = 1024;
+
+/// Where the generator's live values come from.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) enum GeneratorSource {
+    /// The incrementing counter the generator always produced.
+    #[default]
+    Sequence,
+    /// Newline-delimited values piped to the process.
+    Stdin,
+    /// Newline-delimited values read from a file.
+    File(PathBuf),
+}
+
+impl fmt::Display for GeneratorSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GeneratorSource::Sequence => write!(f, "sequence"),
+            GeneratorSource::Stdin => write!(f, "stdin"),
+            GeneratorSource::File(path) => write!(f, "file:{}", path.display()),
+        }
+    }
+}
+
+/// Parses `sequence`, `stdin` or `file:<path>`.
+pub(crate) fn parse_generator_source(text: &str) -> Result<GeneratorSource, String> {
+    match text.trim() {
+        "sequence" => Ok(GeneratorSource::Sequence),
+        "stdin" => Ok(GeneratorSource::Stdin),
+        other => match other.strip_prefix("file:") {
+            Some(path) if !path.is_empty() => Ok(GeneratorSource::File(PathBuf::from(path))),
+            _ => Err(format!("expected 'sequence', 'stdin' or 'file:<path>', got '{}'", text)),
+        },
+    }
+}
+
+/// Reads values for the generator from stdin or a file, one per line. A thread reads ahead so the
+/// generator never blocks on the input; the value it hands out stays put until `commit`, so one
+/// whose send did not go through is handed out again. Kept in the generator's state, so a restart
+/// carries on from the value it was at.
+pub(crate) struct InputReader {
+    lines: Receiver<String>,
+    /// The value at the offset once parsed, and its length in the input.
+    next: Option<(u64, u64)>,
+    /// Bytes of input behind the values handed on: where a file is reopened.
+    pub(crate) offset: u64,
+    /// Values handed on, and lines that held no value.
+    pub(crate) read: u64,
+    pub(crate) skipped: u64,
+    /// The input has run out, and whether that has been reported yet.
+    exhausted: bool,
+    reported: bool,
+}
+
+impl InputReader {
+    /// Opens the input at `offset` bytes in; stdin cannot seek, so it carries on from wherever it is.
+    /// None for the sequence, which needs no reader.
+    pub(crate) fn open(source: &GeneratorSource, offset: u64) -> io::Result<Option<Self>> {
+        let input: Box<dyn BufRead + Send> = match source {
+            GeneratorSource::Sequence => return Ok(None),
+            GeneratorSource::Stdin => Box::new(BufReader::new(io::stdin())),
+            GeneratorSource::File(path) => {
+                let mut file = File::open(path)?;
+                file.seek(SeekFrom::Start(offset))?;
+                Box::new(BufReader::new(file))
+            }
+        };
+        Ok(Some(InputReader {
+            lines: read_ahead(input, source.to_string())?,
+            next: None,
+            offset,
+            read: 0,
+            skipped: 0,
+            exhausted: false,
+            reported: false,
+        }))
+    }
+
+    /// The value at the offset, None while the next line has not arrived or once the input is done.
+    pub(crate) fn peek(&mut self) -> Option<u64> {
+        loop {
+            if let Some((value, _)) = self.next {
+                return Some(value);
+            }
+            let line = match self.lines.try_recv() {
+                Ok(line) => line,
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => {
+                    self.exhausted = true;
+                    return None;
+                }
+            };
+            let text = line.trim();
+            match text.parse() {
+                Ok(value) => self.next = Some((value, line.len() as u64)),
+                Err(_) => {
+                    if !text.is_empty() && !text.starts_with('#') {
+                        self.skipped += 1;
+                        warn!("Generator input skipping a line at byte {}, not a value: {:?}", self.offset, text);
+                    }
+                    self.offset += line.len() as u64;
+                }
+            }
+        }
+    }
+
+    /// Moves past the value `peek` returned, once it has been sent.
+    pub(crate) fn commit(&mut self) {
+        if let Some((_, len)) = self.next.take() {
+            self.offset += len;
+            self.read += 1;
+        }
+    }
+
+    /// True the first time it is asked after the input has run out, so that is reported once.
+    pub(crate) fn just_exhausted(&mut self) -> bool {
+        let first = self.exhausted && !self.reported;
+        self.reported |= self.exhausted;
+        first
+    }
+}
+
+/// Reads `input` line by line on a thread of its own, ending the channel at the end of the input
+/// or at the first error reading it.
+fn read_ahead(mut input: Box<dyn BufRead + Send>, source: String) -> io::Result<Receiver<String>> {
+    let (lines_tx, lines_rx) = mpsc::sync_channel(READ_AHEAD);
+    std::thread::Builder::new().name("generator-input".to_string()).spawn(move || loop {
+        let mut line = String::new();
+        match input.read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => if lines_tx.send(line).is_err() {
+                break;
+            },
+            Err(e) => {
+                warn!("Generator input {} failed, treating it as ended: {}", source, e);
+                break;
+            }
+        }
+    })?;
+    Ok(lines_rx)
+}
+
+#[cfg(test)]
+pub(crate) mod input_tests {
+    use super::*;
+
+    /// Waits out the reader thread for the next value.
+    fn next(reader: &mut InputReader) -> Option<u64> {
+        for _ in 0..100 {
+            if let Some(value) = reader.peek() {
+                return Some(value);
+            }
+            if reader.exhausted {
+                return None;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        None
+    }
+
+    #[test]
+    fn test_input_reader_resumes_at_offset() -> io::Result<()> {
+        assert_eq!(parse_generator_source("file:in.txt"), Ok(GeneratorSource::File("in.txt".into())));
+        assert_eq!(parse_generator_source("stdin"), Ok(GeneratorSource::Stdin));
+        assert!(parse_generator_source("file:").is_err());
+
+        let path = std::env::temp_dir().join(format!("robust-input-{}.txt", crate::arg::new_run_id()));
+        std::fs::write(&path, "7\n\n# note\neight\n9\n10")?;
+        let source = GeneratorSource::File(path.clone());
+        let mut reader = InputReader::open(&source, 0)?.expect("file reader");
+        assert_eq!(next(&mut reader), Some(7));
+        // not committed, as when the send did not go through
+        assert_eq!(next(&mut reader), Some(7));
+        reader.commit();
+        assert_eq!(next(&mut reader), Some(9));
+        assert_eq!((reader.offset, reader.skipped), (16, 1));
+
+        // reopened at the offset, the uncommitted value comes again and the last line needs no newline
+        let mut resumed = InputReader::open(&source, reader.offset)?.expect("file reader");
+        assert_eq!(next(&mut resumed), Some(9));
+        resumed.commit();
+        assert_eq!(next(&mut resumed), Some(10));
+        resumed.commit();
+        assert_eq!(next(&mut resumed), None);
+        assert!(resumed.just_exhausted());
+        assert!(!resumed.just_exhausted());
+        assert_eq!(resumed.offset, std::fs::metadata(&path)?.len());
+        std::fs::remove_file(&path)
+    }
+}
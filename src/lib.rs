@@ -0,0 +1,634 @@
+//! A robust actor pipeline on steady_state: a heartbeat paces a worker that classifies
+//! generated values and hands them to a logger, every actor restarting from its persisted
+//! state after a panic. The actors, the message envelopes and the resilience helpers are
+//! public so other projects can build pipelines of their own from them; `run` is the
+//! whole command-line program, which the `robust` binary calls.
+use steady_state::*;
+use steady_state::actor_builder::ActorBuilder;
+use arg::Command;
+pub use arg::MainArg;
+use actor::heartbeat::HeartbeatState;
+use actor::generator::GeneratorState;
+use actor::worker::WorkerState;
+use actor::logger::LoggerState;
+use actor::reconciler::ReconcilerState;
+use actor::loopback::LoopbackState;
+use report::RunReport;
+use wiring::Wiring;
+use serde_json::json;
+pub mod ack;
+#[cfg(any(feature = "grpc", feature = "mqtt"))]
+mod admission;
+mod anomaly;
+pub mod arg;
+pub mod backfill;
+mod backpressure;
+mod build_info;
+pub mod classify;
+pub mod checkpoint;
+pub mod clock;
+mod compare;
+mod config;
+mod crash;
+mod deadline;
+mod delay;
+pub mod drain;
+mod enrich;
+pub mod event_time;
+mod events;
+mod expect;
+mod failure;
+mod fault;
+mod fsync;
+mod input;
+mod logging;
+mod loopback;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+mod multi_graph;
+mod preflight;
+mod persistence;
+mod priority;
+mod queue;
+mod quota;
+mod rate_limit;
+mod record;
+mod report;
+mod residue;
+pub mod restart;
+mod resume;
+pub mod rules;
+mod signals;
+pub mod throttle;
+pub mod timing;
+mod trace;
+mod troupe;
+mod urgency;
+mod sink;
+mod slo;
+mod stage_time;
+pub mod startup;
+pub mod stats;
+#[cfg(feature = "stage-control")]
+mod stage_remote;
+pub mod state_lock;
+mod supervise;
+pub mod tenant;
+#[cfg(all(feature = "tls", any(feature = "grpc", feature = "mqtt")))]
+mod tls;
+pub mod validation;
+mod wait;
+mod wal;
+mod window;
+pub mod wiring;
+
+// The actor module contains all the actor implementations for this robust pipeline.
+// Each actor is in its own submodule for clarity and separation of concerns.
+pub mod actor {
+    pub mod heartbeat;
+    pub mod generator;
+    pub mod worker;
+    pub mod logger;
+    pub mod reconciler;
+    pub mod delay_line;
+    pub mod loopback;
+    pub mod dead_letter;
+    pub mod enricher;
+    pub mod router;
+    pub mod fan_in;
+    pub mod queue_source;
+    pub mod queue_writer;
+    pub mod tcp_source;
+    #[cfg(test)]
+    pub(crate) mod assert_sink;
+    #[cfg(test)]
+    pub(crate) mod scripted;
+    #[cfg(unix)]
+    pub mod control;
+    #[cfg(feature = "grpc")]
+    pub mod grpc_source;
+    #[cfg(feature = "mqtt")]
+    pub mod mqtt_source;
+}
+
+/// Runs the command-line program with its parsed arguments: a subcommand, or the pipeline
+/// until it stops. Returns the code the process should exit with, or an error when the run
+/// failed; the process itself is left to the caller.
+pub fn run(mut cli_args: MainArg) -> Result<i32, Box<dyn Error>> {
+    if let Some(Command::CompareRuns { baseline, candidate, tolerance_pct }) = &cli_args.command {
+        let regressed = compare::run(baseline, candidate, *tolerance_pct)?;
+        return Ok(if regressed { 1 } else { 0 });
+    }
+    if let Some(Command::Loopback) = &cli_args.command {
+        loopback::enable(&mut cli_args);
+    }
+    let mut ingest_args = None;
+    if let Some(Command::MultiGraph { queue_dir }) = &cli_args.command {
+        let queue_dir = queue_dir.clone();
+        ingest_args = Some(multi_graph::enable(&mut cli_args, queue_dir));
+    }
+    // Every conflict is reported at once, before anything starts, rather than one per attempt or mid-run.
+    if cli_args.command.is_none()
+        && let Err(problems) = preflight::check(&cli_args) {
+        eprintln!("error: {}", problems);
+        return Ok(2);
+    }
+    let started = Instant::now();
+    let report_file = cli_args.report_file.clone();
+    let expectations = cli_args.expect.clone();
+    let run_id = cli_args.run_id.clone();
+    // The logger's deadline follows the worker's, so together they bound how long stopping takes.
+    let stop_timeout = Duration::from_millis(cli_args.drain_worker_ms + cli_args.drain_logger_ms) + SHUTDOWN_GRACE;
+
+    // Our own logger so every line carries the run id; keep the handle alive until exit.
+    let _log_handle = logging::init(&cli_args.log_level, &run_id, cli_args.log_format)?;
+    if let Some(Command::Supervise { max_restarts, backoff_ms, backoff_max_ms, healthy_ms, keep_crash_dumps, child_args }) = &cli_args.command {
+        let policy = supervise::RestartPolicy {
+            max_restarts: *max_restarts,
+            backoff: Duration::from_millis(*backoff_ms),
+            backoff_max: Duration::from_millis(*backoff_max_ms),
+            healthy_after: Duration::from_millis(*healthy_ms),
+            keep_crash_dumps: *keep_crash_dumps,
+        };
+        return supervise::run(policy, child_args);
+    }
+    if cli_args.sink == sink::SinkSpec::Stdout {
+        sink::claim_stdout()?;
+    }
+    crash::init(&cli_args)?;
+    persistence::init(&cli_args)?;
+    info!("Starting run {} on build {}", run_id, build_info::BUILD.summary());
+    if let Err(e) = signals::install() {
+        warn!("Ctrl-C handler not installed, a second Ctrl-C will not abort the drain: {}", e);
+    }
+    if let Some(path) = &cli_args.events_file {
+        events::open(path)?;
+    }
+    let build = build_info::BUILD;
+    events::emit("startup", json!({
+        "version": build.version, "git_hash": build.git_hash, "profile": build.profile,
+        "rate_ms": cli_args.rate_ms, "beats": cli_args.beats,
+    }));
+    if let Some(path) = &cli_args.build_info_prom {
+        std::fs::write(path, build_info::BUILD.prometheus())?;
+    }
+
+    // Staging needs a testing build, where every actor but the worker is simulated.
+    #[cfg(feature = "stage-control")]
+    let stage_listen = cli_args.stage_listen;
+    #[cfg(not(feature = "stage-control"))]
+    let stage_listen: Option<std::net::SocketAddr> = None;
+    let runner = if stage_listen.is_some() { SteadyRunner::test_build() } else { SteadyRunner::release_build() };
+
+    runner
+        .with_telemetry_rate_ms(200) // slower telemetry frame rate, //##!##//
+        .run(cli_args, move |mut graph| {
+
+            // Construct the full actor pipeline and channel topology.
+            let state = build_graph(&mut graph);
+            signals::watch(&run_id, &state);
+            // After the graph is built, so it chains to the hook steady_state installs.
+            state_lock::install_panic_hook();
+
+            // Start the entire actor system. All actors and channels are now live.
+            graph.start();
+            // With `multi-graph` the ingest graph runs beside the pipeline, filling the queue it reads.
+            // Started second, it leaves the telemetry port to the pipeline.
+            let ingest = ingest_args.map(multi_graph::spawn_ingest);
+
+            // A staged run lasts as long as the harness drives it.
+            #[cfg(feature = "stage-control")]
+            if let Some(addr) = stage_listen {
+                let stage_manager = graph.stage_manager();
+                if let Err(e) = std::net::TcpListener::bind(addr).map_err(Box::from).and_then(|listener| stage_remote::serve(&stage_manager, listener)) {
+                    error!("Staging on {} failed: {}", addr, e);
+                }
+                stage_manager.final_bow();
+                graph.request_shutdown();
+            }
+
+            // The system runs until an actor requests shutdown. Stages then drain in order,
+            // generator first, within their own deadlines, so this timeout is only a backstop.
+            let result = graph.block_until_stopped(stop_timeout);
+            // The pipeline has stopped, so the ingest graph stops too, however much it has queued.
+            let result = result.and(ingest.map_or(Ok(()), multi_graph::stop));
+            events::emit("shutdown", json!({"phase": "stopped", "clean": result.is_ok()}));
+
+            // Actor state outlives the actors, so the totals are final once the graph stops.
+            let mut report = RunReport::collect(&run_id, &state);
+            report.duration_ms = started.elapsed().as_millis() as u64;
+            report.log();
+            if let Some(flushed) = report.drain_flushed {
+                let undrained = report.worker_residue + report.logger_residue;
+                info!("Drain complete: {} messages flushed to the sink after the drain began, {} left undrained", flushed, undrained);
+                events::emit("shutdown", json!({"phase": "drained", "flushed": flushed, "undrained": undrained}));
+            }
+            // A run that stopped cleanly must account for every value; one that did not has already failed.
+            let result = result.and_then(|()| {
+                if !report.unread.is_empty() {
+                    warn!("Conservation check skipped, final state unreadable for: {}", report.unread.join(", "));
+                    return Ok(());
+                }
+                report.conservation().map_err(|e| {
+                    error!("{}", e);
+                    events::emit("alert", json!({"kind": "conservation", "detail": e.to_string()}));
+                    Box::new(e) as Box<dyn Error>
+                })
+            }).and_then(|()| {
+                // --expect turns the run into its own test: unmet expectations fail it like a lost value does.
+                if expectations.is_empty() {
+                    return Ok(());
+                }
+                if !report.unread.is_empty() {
+                    warn!("Expectations not checked, final state unreadable for: {}", report.unread.join(", "));
+                    return Ok(());
+                }
+                match expect::check(&report, &expectations) {
+                    Ok(()) => {
+                        info!("Run met all {} expectations", expectations.len());
+                        Ok(())
+                    }
+                    Err(e) => {
+                        error!("{}", e);
+                        events::emit("alert", json!({"kind": "expectations", "detail": e.to_string()}));
+                        Err(Box::new(e) as Box<dyn Error>)
+                    }
+                }
+            });
+            if let Some(path) = &report_file
+                && let Err(e) = report.save(path) {
+                error!("Could not save the final report to {:?}: {}", path, e);
+            }
+            result
+        })?;
+    Ok(0)
+}
+
+/// Time allowed past the last drain deadline for stages to spill their residue and close.
+const SHUTDOWN_GRACE: Duration = Duration::from_millis(200);
+
+// Actor names for use in graph construction and testing.
+const NAME_HEARTBEAT: &str = "HEARTBEAT";
+const NAME_GENERATOR: &str = "GENERATOR";
+const NAME_WORKER: &str = "WORKER";
+const NAME_LOGGER: &str = "LOGGER";
+const NAME_RECONCILER: &str = "RECONCILER";
+const NAME_LOOPBACK: &str = "LOOPBACK";
+const NAME_DEAD_LETTER: &str = "DEAD_LETTER";
+const NAME_ENRICHER: &str = "ENRICHER";
+const NAME_ROUTER: &str = "ROUTER";
+const NAME_FAN_IN: &str = "FANIN";
+const NAME_QUEUE_SOURCE: &str = "QUEUE_SOURCE";
+const NAME_QUEUE_WRITER: &str = "QUEUE_WRITER";
+const NAME_TCP_SOURCE: &str = "TCP_SOURCE";
+/// Workers of a `--workers` pool, by shard; its length caps the pool.
+const NAME_POOL: [&str; 8] = ["WORKER_0", "WORKER_1", "WORKER_2", "WORKER_3", "WORKER_4", "WORKER_5", "WORKER_6", "WORKER_7"];
+/// Delay lines, in the order of `delay::DELAYABLE_CHANNELS`.
+const NAME_DELAY: [&str; 3] = ["DELAY_HEARTBEAT_WORKER", "DELAY_GENERATOR_WORKER", "DELAY_WORKER_LOGGER"];
+#[cfg(unix)]
+const NAME_CONTROL: &str = "CONTROL";
+#[cfg(feature = "grpc")]
+const NAME_GRPC_SOURCE: &str = "GRPC_SOURCE";
+#[cfg(feature = "mqtt")]
+const NAME_MQTT_SOURCE: &str = "MQTT_SOURCE";
+
+/// Handles to every actor's persistent state.
+/// build_graph hands these back so main can read the final totals after shutdown.
+#[derive(Clone)]
+pub struct PipelineState {
+    pub heartbeat: SteadyState<HeartbeatState>,
+    pub generator: SteadyState<GeneratorState>,
+    pub worker: SteadyState<WorkerState>,
+    pub logger: SteadyState<LoggerState>,
+    pub reconciler: SteadyState<ReconcilerState>,
+    pub loopback: SteadyState<LoopbackState>,
+    pub dead_letter: SteadyState<actor::dead_letter::DeadLetterState>,
+    pub enricher: SteadyState<actor::enricher::EnricherState>,
+    pub router: SteadyState<actor::router::RouterState>,
+    pub fan_in: SteadyState<actor::fan_in::FanInState>,
+    pub queue_source: SteadyState<actor::queue_source::QueueSourceState>,
+    pub queue_writer: SteadyState<actor::queue_writer::QueueWriterState>,
+    pub tcp_source: SteadyState<actor::tcp_source::TcpSourceState>,
+    /// One state per worker with `--workers`, in shard order; empty for the single worker.
+    pub pool: Vec<SteadyState<WorkerState>>,
+    /// The channels the stages read, to tell what was left in each once the graph stops.
+    pub channels: residue::ChannelProbes,
+    #[cfg(unix)]
+    pub control: SteadyState<actor::control::ControlState>,
+    #[cfg(feature = "grpc")]
+    pub grpc_source: SteadyState<actor::grpc_source::GrpcSourceState>,
+    #[cfg(feature = "mqtt")]
+    pub mqtt_source: SteadyState<actor::mqtt_source::MqttSourceState>,
+}
+
+impl PipelineState {
+    fn new(workers: usize) -> Self {
+        PipelineState {
+            heartbeat: new_state(),
+            generator: new_state(),
+            worker: new_state(),
+            logger: new_state(),
+            reconciler: new_state(),
+            loopback: new_state(),
+            dead_letter: new_state(),
+            enricher: new_state(),
+            router: new_state(),
+            fan_in: new_state(),
+            queue_source: new_state(),
+            queue_writer: new_state(),
+            tcp_source: new_state(),
+            pool: if workers > 1 { (0..workers).map(|_| new_state()).collect() } else { Vec::new() },
+            channels: residue::ChannelProbes::default(),
+            #[cfg(unix)]
+            control: new_state(),
+            #[cfg(feature = "grpc")]
+            grpc_source: new_state(),
+            #[cfg(feature = "mqtt")]
+            mqtt_source: new_state(),
+        }
+    }
+
+    /// The state of every worker that ran: the pool's, or the single worker's.
+    pub fn workers(&self) -> &[SteadyState<WorkerState>] {
+        if self.pool.is_empty() { std::slice::from_ref(&self.worker) } else { &self.pool }
+    }
+}
+
+/// Builds the robust actor pipeline and connects all channels.
+/// This function demonstrates the robust architecture:
+/// - Each actor is built with persistent state, enabling automatic restart and state recovery.
+/// - Channels are created for each stage of the pipeline.
+/// - Each actor is built as a SoloAct, running on its own thread for failure isolation.
+///
+/// The stages themselves come from `wiring::stages`; this only makes the channels and states
+/// they share and builds those the arguments enable.
+pub fn build_graph(graph: &mut Graph) -> PipelineState {
+    let args = graph.args::<MainArg>().cloned().unwrap_or_default();
+    let channel_builder = graph.channel_builder();
+    let mut wiring = Wiring::new(&channel_builder, args);
+
+    let actor_builder = graph.actor_builder()
+        .with_thread_info()
+        .with_load_avg()
+        .with_mcpu_avg();
+
+    // A --channel-delay puts a delay line between the two ends of its channel.
+    let delays = delay::DELAYABLE_CHANNELS.map(|channel| delay::delay_for(&wiring.args, channel));
+    wiring.heartbeat.rx = actor::delay_line::splice(&actor_builder, &channel_builder, NAME_DELAY[0], wiring.heartbeat.rx, delays[0]);
+    wiring.generated.rx = actor::delay_line::splice(&actor_builder, &channel_builder, NAME_DELAY[1], wiring.generated.rx, delays[1]);
+    wiring.classified.rx = actor::delay_line::splice(&actor_builder, &channel_builder, NAME_DELAY[2], wiring.classified.rx, delays[2]);
+    // With the loopback sink, a verifier relays the generated values and checks the sink's records against them.
+    wiring.generated.rx = actor::loopback::splice(&actor_builder, &channel_builder, &wiring.args, wiring.generated.rx, wiring.state.loopback.clone());
+
+    build_stages(graph, &actor_builder, &wiring, &wiring::stages());
+
+    wiring.state
+}
+
+/// Builds those of `stages` the arguments enable, in order, on the links and states of `wiring`.
+fn build_stages(graph: &mut Graph, actor_builder: &ActorBuilder, wiring: &Wiring, stages: &[Box<dyn wiring::RobustActor>]) {
+    // Preflight refuses a bad startup order with the same error before the graph is built.
+    if let Err(e) = startup::check(stages, &wiring.args) {
+        panic!("invalid startup dependencies: {}", e);
+    }
+    wiring.readiness.prepare(stages, &wiring.args);
+    // Each actor is built as a SoloAct, running on its own thread for maximum failure isolation,
+    // except those that share the troupe's thread. Each actor's state is persistent and survives restarts.
+    // Niceness belongs to a thread, so a troupe member given its own is moved onto a thread of its own.
+    let mut shared_troupe = graph.actor_troupe();
+    for stage in stages.iter().filter(|stage| stage.enabled(&wiring.args)) {
+        let shared = stage.shares_thread(&wiring.args) && priority::nice_for(&wiring.args, stage.name()).is_none();
+        let schedule = if shared { MemberOf(&mut shared_troupe) } else { SoloAct };
+        stage.build(actor_builder.with_name(stage.name()), wiring, schedule);
+    }
+    wiring.probe_residue(stages);
+}
+
+#[cfg(test)]
+pub(crate) mod main_tests {
+    use steady_state::*;
+    use steady_state::graph_testing::*;
+    use crate::actor::worker::FizzBuzzMessage;
+    use crate::event_time::BeatTime;
+    use crate::timing::Envelope;
+    use crate::validation::{RejectReason, RejectedValue};
+    use super::*;
+
+    /// This test demonstrates orchestrated, multi-actor testing using the stage manager.
+    /// It allows precise control over actor behavior and verification of system interactions.
+    #[test]
+    fn graph_test() -> Result<(), Box<dyn Error>> {
+
+        SteadyRunner::test_build()
+            .with_logging(LogLevel::Info)
+            .with_telemetry_rate_ms(200) // slower telemetry frame rate, //##!##//
+            .run(MainArg::default(), move |mut graph| {
+                build_graph(&mut graph);
+                graph.start();
+
+                // Stage management provides orchestrated testing of multi-actor scenarios.
+                // This enables precise control over actor behavior and verification of
+                // complex system interactions without manual coordination complexity.
+                let stage_manager = graph.stage_manager();
+                // The worker keeps the generator's stamp, so the logger sees the same one back,
+                // numbered as the first message in the worker's output stream.
+                let generated = Envelope::new(15u64);
+                stage_manager.actor_perform(NAME_GENERATOR, StageDirection::Echo(generated))?;
+                stage_manager.actor_perform(NAME_HEARTBEAT, StageDirection::Echo(BeatTime::untimed(100)))?;
+                stage_manager.actor_perform(NAME_LOGGER,    StageWaitFor::Message(generated.carry(FizzBuzzMessage::FizzBuzz).with_seq(1)
+                                                                                  , Duration::from_secs(2)))?;
+                // ...
+                stage_manager.final_bow();
+
+                graph.request_shutdown(); //essential for test to finish
+
+                graph.block_until_stopped(Duration::from_secs(5))
+            })
+
+
+    }
+
+    /// The worker spends one heartbeat per value: two values waiting in the generator channel
+    /// reach the logger in order as the beats for them arrive.
+    #[test]
+    fn graph_test_heartbeat_paces_worker() -> Result<(), Box<dyn Error>> {
+        SteadyRunner::test_build()
+            .with_logging(LogLevel::Info)
+            .with_telemetry_rate_ms(200)
+            .run(MainArg::default(), move |mut graph| {
+                let state = build_graph(&mut graph);
+                graph.start();
+
+                let stage_manager = graph.stage_manager();
+                let (fizz, buzz) = (Envelope::new(3u64), Envelope::new(5u64));
+                stage_manager.actor_perform(NAME_GENERATOR, StageDirection::Echo(fizz))?;
+                stage_manager.actor_perform(NAME_GENERATOR, StageDirection::Echo(buzz))?;
+                stage_manager.actor_perform(NAME_HEARTBEAT, StageDirection::Echo(BeatTime::untimed(1)))?;
+                stage_manager.actor_perform(NAME_LOGGER,    StageWaitFor::Message(fizz.carry(FizzBuzzMessage::Fizz).with_seq(1)
+                                                                                  , Duration::from_secs(2)))?;
+                stage_manager.actor_perform(NAME_HEARTBEAT, StageDirection::Echo(BeatTime::untimed(2)))?;
+                stage_manager.actor_perform(NAME_LOGGER,    StageWaitFor::Message(buzz.carry(FizzBuzzMessage::Buzz).with_seq(2)
+                                                                                  , Duration::from_secs(2)))?;
+                stage_manager.final_bow();
+
+                graph.request_shutdown();
+
+                graph.block_until_stopped(Duration::from_secs(5))?;
+                // the staged actors are simulated and count nothing; the worker ran for real
+                let stats = state.stats();
+                assert_eq!((stats.processed, stats.classified, stats.rejected), (2, 2, 0));
+                assert_eq!((stats.beats_sent, stats.logged), (0, stats::VariantCounts::default()));
+                assert_eq!(stats.unread, ["logger"]);
+                Ok(())
+            })
+    }
+
+    /// Rejected values reach the logger on the side channel. Once that channel is full, the next
+    /// reject is peeked on every beat without being taken, until the worker drops it as a
+    /// showstopper and moves on to the value behind it.
+    #[test]
+    fn graph_test_rejects_and_showstopper() -> Result<(), Box<dyn Error>> {
+        // the side channel's capacity: the simulated logger reads nothing until asked to
+        const REJECTS_HELD: usize = 64;
+        SteadyRunner::test_build()
+            .with_logging(LogLevel::Info)
+            .with_telemetry_rate_ms(200)
+            .run(MainArg { reject_multiples_of: vec![7], ..Default::default() }, move |mut graph| {
+                build_graph(&mut graph);
+                graph.start();
+
+                let stage_manager = graph.stage_manager();
+                for beat in 0..REJECTS_HELD as u64 {
+                    stage_manager.actor_perform(NAME_GENERATOR, StageDirection::Echo(Envelope::new(7u64)))?;
+                    stage_manager.actor_perform(NAME_HEARTBEAT, StageDirection::Echo(BeatTime::untimed(beat)))?;
+                }
+                // the side channel is full, so this reject can never be committed
+                let accepted = Envelope::new(15u64);
+                stage_manager.actor_perform(NAME_GENERATOR, StageDirection::Echo(Envelope::new(14u64)))?;
+                stage_manager.actor_perform(NAME_GENERATOR, StageDirection::Echo(accepted))?;
+                for beat in 0..5u64 {
+                    stage_manager.actor_perform(NAME_HEARTBEAT, StageDirection::Echo(BeatTime::untimed(REJECTS_HELD as u64 + beat)))?;
+                }
+                stage_manager.actor_perform(NAME_LOGGER,    StageWaitFor::Message(accepted.carry(FizzBuzzMessage::FizzBuzz).with_seq(1)
+                                                                                  , Duration::from_secs(2)))?;
+                stage_manager.actor_perform(NAME_LOGGER,    StageWaitFor::Message(RejectedValue::new(7, RejectReason::MultipleOf(7))
+                                                                                  , Duration::from_secs(2)))?;
+                stage_manager.final_bow();
+
+                graph.request_shutdown();
+
+                graph.block_until_stopped(Duration::from_secs(5))
+            })
+    }
+
+    /// The same pipeline with the heartbeat left out: values flow on generator availability alone.
+    #[test]
+    fn graph_test_without_heartbeat() -> Result<(), Box<dyn Error>> {
+        SteadyRunner::test_build()
+            .with_logging(LogLevel::Info)
+            .with_telemetry_rate_ms(200)
+            .run(MainArg { no_heartbeat: true, ..Default::default() }, move |mut graph| {
+                build_graph(&mut graph);
+                graph.start();
+
+                let stage_manager = graph.stage_manager();
+                let generated = Envelope::new(15u64);
+                stage_manager.actor_perform(NAME_GENERATOR, StageDirection::Echo(generated))?;
+                stage_manager.actor_perform(NAME_LOGGER,    StageWaitFor::Message(generated.carry(FizzBuzzMessage::FizzBuzz).with_seq(1)
+                                                                                  , Duration::from_secs(2)))?;
+                stage_manager.final_bow();
+
+                graph.request_shutdown();
+
+                graph.block_until_stopped(Duration::from_secs(5))
+            })
+    }
+
+    /// In troupe mode the generator and worker join the heartbeat and logger on one thread,
+    /// and values still flow through with every stage sharing it.
+    #[test]
+    fn graph_test_in_troupe() -> Result<(), Box<dyn Error>> {
+        SteadyRunner::test_build()
+            .with_logging(LogLevel::Info)
+            .with_telemetry_rate_ms(200)
+            .run(MainArg { troupe: true, troupe_budget: 4, ..Default::default() }, move |mut graph| {
+                build_graph(&mut graph);
+                graph.start();
+
+                let stage_manager = graph.stage_manager();
+                let generated = Envelope::new(15u64);
+                stage_manager.actor_perform(NAME_GENERATOR, StageDirection::Echo(generated))?;
+                stage_manager.actor_perform(NAME_HEARTBEAT, StageDirection::Echo(BeatTime::untimed(1)))?;
+                stage_manager.actor_perform(NAME_LOGGER,    StageWaitFor::Message(generated.carry(FizzBuzzMessage::FizzBuzz).with_seq(1)
+                                                                                  , Duration::from_secs(2)))?;
+                stage_manager.final_bow();
+
+                graph.request_shutdown();
+
+                graph.block_until_stopped(Duration::from_secs(5))
+            })
+    }
+
+    /// With the worker simulated too, the stage manager checks what reaches it on both inputs and
+    /// speaks for it on its outputs, so the logger's side can be driven without the real classifier.
+    #[test]
+    fn graph_test_with_simulated_worker() -> Result<(), Box<dyn Error>> {
+        SteadyRunner::test_build()
+            .without_default_test_pipeline_worker()
+            .with_logging(LogLevel::Info)
+            .with_telemetry_rate_ms(200)
+            .run(MainArg::default(), move |mut graph| {
+                build_graph(&mut graph);
+                graph.start();
+
+                let stage_manager = graph.stage_manager();
+                let generated = Envelope::new(15u64);
+                stage_manager.actor_perform(NAME_GENERATOR, StageDirection::Echo(generated))?;
+                stage_manager.actor_perform(NAME_HEARTBEAT, StageDirection::Echo(BeatTime::untimed(1)))?;
+                stage_manager.actor_perform(NAME_WORKER,    StageWaitFor::Message(generated, Duration::from_secs(2)))?;
+                stage_manager.actor_perform(NAME_WORKER,    StageWaitFor::Message(BeatTime::untimed(1), Duration::from_secs(2)))?;
+                // an answer the real worker would never give, to show the logger took it from the stage
+                let classified = generated.carry(FizzBuzzMessage::Value(16)).with_seq(1);
+                let rejected = RejectedValue::new(14, RejectReason::MultipleOf(7));
+                stage_manager.actor_perform(NAME_WORKER,    StageDirection::Echo(classified))?;
+                stage_manager.actor_perform(NAME_WORKER,    StageDirection::Echo(rejected))?;
+                stage_manager.actor_perform(NAME_LOGGER,    StageWaitFor::Message(classified, Duration::from_secs(2)))?;
+                stage_manager.actor_perform(NAME_LOGGER,    StageWaitFor::Message(rejected, Duration::from_secs(2)))?;
+                stage_manager.final_bow();
+
+                graph.request_shutdown();
+
+                graph.block_until_stopped(Duration::from_secs(5))
+            })
+    }
+
+    /// A delay line between generator and worker holds the value back, and then it flows on as usual.
+    #[test]
+    fn graph_test_with_channel_delay() -> Result<(), Box<dyn Error>> {
+        let args = MainArg {
+            channel_delay: vec![delay::parse_channel_delay("generator-worker=300ms")?],
+            ..Default::default()
+        };
+        SteadyRunner::test_build()
+            .with_logging(LogLevel::Info)
+            .with_telemetry_rate_ms(200)
+            .run(args, move |mut graph| {
+                build_graph(&mut graph);
+                graph.start();
+
+                let stage_manager = graph.stage_manager();
+                let generated = Envelope::new(15u64);
+                let sent = Instant::now();
+                stage_manager.actor_perform(NAME_HEARTBEAT, StageDirection::Echo(BeatTime::untimed(1)))?;
+                stage_manager.actor_perform(NAME_GENERATOR, StageDirection::Echo(generated))?;
+                stage_manager.actor_perform(NAME_LOGGER,    StageWaitFor::Message(generated.carry(FizzBuzzMessage::FizzBuzz).with_seq(1)
+                                                                                  , Duration::from_secs(2)))?;
+                assert!(sent.elapsed() >= Duration::from_millis(300), "{:?}", sent.elapsed());
+                stage_manager.final_bow();
+
+                graph.request_shutdown();
+
+                graph.block_until_stopped(Duration::from_secs(5))
+            })
+    }
+}
@@ -0,0 +1,255 @@
+use std::sync::OnceLock;
+use std::thread;
+use flexi_logger::{style, DeferredNow, Logger, LoggerHandle, LogSpecification, WriteMode};
+use flexi_logger::{LevelFilter, Record};
+use log::kv::{Key, Value, VisitSource};
+use serde_json::{json, Map};
+use steady_state::*;
+
+/// Run id stamped on every record; set once by `init` before the graph starts.
+static RUN_ID: OnceLock<String> = OnceLock::new();
+
+/// Timestamp layout matching the steady_state console format.
+const TS_DASHES: &str = "%Y-%m-%d %H:%M:%S%.6f %:z";
+
+/// How diagnostics are written to stderr.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) enum LogFormat {
+    /// Colored lines in the steady_state console layout, for people.
+    #[default]
+    Text,
+    /// One JSON object per line, for container log pipelines.
+    Json,
+}
+
+pub(crate) fn parse_log_format(text: &str) -> Result<LogFormat, String> {
+    match text.trim().to_ascii_lowercase().as_str() {
+        "text" => Ok(LogFormat::Text),
+        "json" => Ok(LogFormat::Json),
+        other => Err(format!("unknown log format '{}', expected text or json", other)),
+    }
+}
+
+/// Actors whose log lines `--log-level` can filter, by the name used on the command line.
+/// Each maps to the module the actor lives in, which is the target of every line it logs.
+const ACTOR_MODULES: [(&str, &str); 8] = [
+    ("heartbeat", "robust::actor::heartbeat"),
+    ("generator", "robust::actor::generator"),
+    ("worker", "robust::actor::worker"),
+    ("logger", "robust::actor::logger"),
+    ("reconciler", "robust::actor::reconciler"),
+    ("control", "robust::actor::control"),
+    ("grpc-source", "robust::actor::grpc_source"),
+    ("mqtt-source", "robust::actor::mqtt_source"),
+];
+
+/// The `--log-level` setting: a level for everything, and levels for single actors.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct LogLevels {
+    pub(crate) default: LevelFilter,
+    /// Actor module and its level, in the order given; a later entry for the same actor wins.
+    pub(crate) actors: Vec<(&'static str, LevelFilter)>,
+}
+
+impl Default for LogLevels {
+    fn default() -> Self {
+        LogLevels { default: LevelFilter::Info, actors: Vec::new() }
+    }
+}
+
+impl LogLevels {
+    fn specification(&self) -> LogSpecification {
+        let mut builder = LogSpecification::builder();
+        builder.default(self.default);
+        for (module, level) in &self.actors {
+            builder.module(module, *level);
+        }
+        builder.build()
+    }
+}
+
+/// Parses comma-separated `LEVEL` and `ACTOR=LEVEL` entries, e.g. `warn,worker=trace`.
+/// Actors not named log at the bare level, or at info without one.
+pub(crate) fn parse_log_levels(text: &str) -> Result<LogLevels, String> {
+    let level = |text: &str| -> Result<LevelFilter, String> {
+        text.trim().parse().map_err(|_| format!("unknown log level '{}', expected off, error, warn, info, debug or trace", text.trim()))
+    };
+    let mut levels = LogLevels::default();
+    for entry in text.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        match entry.split_once('=') {
+            None => levels.default = level(entry)?,
+            Some((actor, filter)) => {
+                let actor = actor.trim().to_ascii_lowercase().replace('_', "-");
+                let (_, module) = ACTOR_MODULES.iter().find(|(name, _)| *name == actor).ok_or_else(|| {
+                    let names: Vec<&str> = ACTOR_MODULES.iter().map(|(name, _)| *name).collect();
+                    format!("unknown actor '{}', expected one of {}", actor, names.join(", "))
+                })?;
+                levels.actors.push((module, level(filter)?));
+            }
+        }
+    }
+    Ok(levels)
+}
+
+/// Installs the process-wide logger used by the release binary.
+/// The text format mirrors the steady_state console format but adds `run=<id>` to every line
+/// so artifacts from overlapping soak runs can be correlated; the JSON format carries the same.
+/// The per-actor levels are module filters, fixed here before the graph starts.
+/// Keep the returned handle alive for the life of the process.
+pub(crate) fn init(levels: &LogLevels, run_id: &str, format: LogFormat) -> Result<LoggerHandle, Box<dyn Error>> {
+    let _ = RUN_ID.set(run_id.to_string());
+    Logger::with(levels.specification())
+        .log_to_stderr()
+        .format(match format {
+            LogFormat::Text => colored_with_run_id,
+            LogFormat::Json => json_with_run_id,
+        })
+        .write_mode(WriteMode::Direct)
+        .start()
+        .map_err(|e| Box::new(e) as Box<dyn Error>)
+        .inspect(|_| {
+            if format == LogFormat::Json {
+                log_panics();
+            }
+        })
+}
+
+/// Reports panics as JSON records instead of the default multi-line text, which would break
+/// line-by-line parsing of stderr. The backtrace, when enabled, goes into the record's fields.
+fn log_panics() {
+    std::panic::set_hook(Box::new(|info| {
+        let (message, location) = crate::crash::describe(info);
+        let backtrace = std::backtrace::Backtrace::capture().to_string();
+        error!(panic_location = location.as_str(), backtrace = backtrace.as_str(); "panicked: {}", message);
+    }));
+}
+
+pub(crate) fn run_id() -> &'static str {
+    RUN_ID.get().map(String::as_str).unwrap_or("-")
+}
+
+/// Console format: steady_state's colored layout plus the run id.
+fn colored_with_run_id(
+    w: &mut dyn std::io::Write,
+    now: &mut DeferredNow,
+    record: &Record,
+) -> Result<(), std::io::Error> {
+    remember(now, record);
+    let style = style(record.level());
+    write!(
+        w,
+        "[{}] T[{}] {} [{}:{}] run={} {}",
+        style.paint(now.format(TS_DASHES).to_string()),
+        style.paint(thread::current().name().unwrap_or("<unnamed>")),
+        style.paint(record.level().to_string()),
+        record.file().unwrap_or("<unnamed>"),
+        record.line().unwrap_or(0),
+        run_id(),
+        style.paint(record.args().to_string())
+    )
+}
+
+/// JSON format: one object per line with the timestamp, level, target, actor, run id, source location,
+/// message, and any structured key-values under `fields`. The actor is the one that last used its state
+/// on this thread, which tells troupe members apart, or else the thread's name.
+fn json_with_run_id(
+    w: &mut dyn std::io::Write,
+    now: &mut DeferredNow,
+    record: &Record,
+) -> Result<(), std::io::Error> {
+    remember(now, record);
+    write!(w, "{}", json_record(&now.format(TS_RFC3339).to_string(), &crate::crash::current_actor(), record))
+}
+
+/// Keeps the record for crash dumps, as JSON whatever the console format.
+fn remember(now: &mut DeferredNow, record: &Record) {
+    if crate::crash::is_enabled() {
+        let actor = crate::crash::current_actor();
+        crate::crash::remember(&actor, || json_record(&now.format(TS_RFC3339).to_string(), &actor, record));
+    }
+}
+
+/// Timestamp layout for JSON records, which parsers read most reliably as RFC 3339.
+const TS_RFC3339: &str = "%Y-%m-%dT%H:%M:%S%.6f%:z";
+
+fn json_record(ts: &str, actor: &str, record: &Record) -> String {
+    let mut fields = Fields(Map::new());
+    // Collecting into a map cannot fail; a record whose fields somehow would still gets logged.
+    let _ = record.key_values().visit(&mut fields);
+    json!({
+        "ts": ts,
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "actor": actor,
+        "run_id": run_id(),
+        "file": record.file(),
+        "line": record.line(),
+        "msg": record.args().to_string(),
+        "fields": fields.0,
+    })
+    .to_string()
+}
+
+/// Gathers a record's key-values, keeping numbers and booleans as JSON numbers and booleans.
+struct Fields(Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), log::kv::Error> {
+        let value = if let Some(n) = value.to_u64() {
+            json!(n)
+        } else if let Some(n) = value.to_i64() {
+            json!(n)
+        } else if let Some(n) = value.to_f64() {
+            json!(n)
+        } else if let Some(b) = value.to_bool() {
+            json!(b)
+        } else {
+            json!(value.to_string())
+        };
+        self.0.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod logging_tests {
+    use super::*;
+
+    #[test]
+    fn test_json_record() {
+        let kvs = [("beat", Value::from(7u64)), ("stage", Value::from("worker"))];
+        let record = Record::builder()
+            .args(format_args!("Worker stalled"))
+            .level(log::Level::Warn)
+            .target("robust::actor::worker")
+            .file(Some("src/actor/worker.rs"))
+            .line(Some(418))
+            .key_values(&kvs)
+            .build();
+        let line: serde_json::Value = serde_json::from_str(&json_record("2026-01-02T03:04:05.000006+00:00", "WORKER", &record))
+            .expect("valid json");
+        assert_eq!(line["level"], "WARN");
+        assert_eq!(line["actor"], "WORKER");
+        assert_eq!(line["target"], "robust::actor::worker");
+        assert_eq!(line["msg"], "Worker stalled");
+        assert_eq!(line["line"], 418);
+        assert_eq!(line["fields"], json!({"beat": 7, "stage": "worker"}));
+        assert!(line["run_id"].is_string());
+        assert_eq!(parse_log_format("JSON"), Ok(LogFormat::Json));
+        assert!(parse_log_format("xml").is_err());
+    }
+
+    #[test]
+    fn test_log_levels() {
+        let levels = parse_log_levels("worker=trace, Logger=info,warn").expect("valid");
+        assert_eq!(levels.default, LevelFilter::Warn);
+        assert_eq!(levels.actors, [("robust::actor::worker", LevelFilter::Trace), ("robust::actor::logger", LevelFilter::Info)]);
+        let spec = levels.specification();
+        let worker = spec.module_filters().iter().find(|f| f.module_name.as_deref() == Some("robust::actor::worker"));
+        assert_eq!(worker.map(|f| f.level_filter), Some(LevelFilter::Trace));
+        assert_eq!(parse_log_levels("mqtt_source=debug").map(|l| l.actors[0].0), Ok("robust::actor::mqtt_source"));
+        assert_eq!(parse_log_levels("debug").map(|l| l.default), Ok(LevelFilter::Debug));
+        assert!(parse_log_levels("sink=trace").is_err());
+        assert!(parse_log_levels("worker=loud").is_err());
+    }
+}
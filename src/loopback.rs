@@ -0,0 +1,179 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex};
+use serde_json::json;
+use steady_state::*;
+use crate::actor::worker::FizzBuzzMessage;
+use crate::events;
+use crate::expect::parse_expectation;
+use crate::rules::RuleSet;
+use crate::sink::{Sink, SinkSpec};
+use crate::validation::ValidationRules;
+use crate::MainArg;
+
+/// Records the loopback sink holds before the verifier takes them. Past this it refuses
+/// writes, and the logger rides out a verifier that fell behind like any sink outage.
+const QUEUE_CAPACITY: usize = 4096;
+
+/// Records the loopback sink accepted that the verifier has not checked yet. Clones share one
+/// queue, so the logger's sink and the verifier both reach it through the parsed arguments.
+#[derive(Clone, Default)]
+pub(crate) struct LoopbackQueue(Arc<Mutex<VecDeque<String>>>);
+
+impl LoopbackQueue {
+    fn records(&self) -> std::sync::MutexGuard<'_, VecDeque<String>> {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub(crate) fn take_all(&self) -> VecDeque<String> {
+        std::mem::take(&mut *self.records())
+    }
+}
+
+impl PartialEq for LoopbackQueue {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for LoopbackQueue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LoopbackQueue({} queued)", self.records().len())
+    }
+}
+
+/// Hands each record to the loopback verifier instead of writing it anywhere.
+pub(crate) struct LoopbackSink(pub(crate) LoopbackQueue);
+
+impl Sink for LoopbackSink {
+    fn write_record(&mut self, record: &str) -> io::Result<()> {
+        let mut records = self.0.records();
+        if records.len() >= QUEUE_CAPACITY {
+            return Err(io::Error::new(io::ErrorKind::WouldBlock, "loopback verifier is behind"));
+        }
+        records.push_back(record.to_string());
+        Ok(())
+    }
+}
+
+/// Turns a `loopback` subcommand into the pipeline run it stands for: the loopback sink, and
+/// an expectation that fails the run on any mismatch.
+pub(crate) fn enable(args: &mut MainArg) {
+    args.sink = SinkSpec::Loopback(LoopbackQueue::default());
+    args.expect.push(parse_expectation("loopback_mismatches=0").expect("a known metric"));
+    args.command = None;
+}
+
+/// A value the generator emitted that the sink should get back, as the message it classifies to.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Expected {
+    value: u64,
+    message: FizzBuzzMessage,
+}
+
+/// Checks the records that reach the loopback sink against the values the generator emitted,
+/// in order. A record matches the first expected value that classifies to it, and any expected
+/// before that one were lost on the way: dropped as showstoppers, dead-lettered or undrained.
+/// Fizz, Buzz and FizzBuzz records do not carry their value, so a lost one of those is blamed
+/// on the next value of its kind instead; the counts come out the same.
+pub(crate) struct LoopbackCheck {
+    queue: LoopbackQueue,
+    rules: ValidationRules,
+    /// The `--rule` list the worker classifies by, and the logger names records by.
+    classes: RuleSet,
+    expected: VecDeque<Expected>,
+    pub(crate) matched: u64,
+    pub(crate) missing: u64,
+    pub(crate) mismatched: u64,
+}
+
+impl LoopbackCheck {
+    pub(crate) fn new(queue: LoopbackQueue, rules: ValidationRules, classes: RuleSet) -> Self {
+        LoopbackCheck { queue, rules, classes, expected: VecDeque::new(), matched: 0, missing: 0, mismatched: 0 }
+    }
+
+    /// Notes a value the generator emitted. One the worker's rules reject never reaches the sink.
+    pub(crate) fn emitted(&mut self, value: u64) {
+        if self.rules.check(value).is_none() {
+            self.expected.push_back(Expected { value, message: self.classes.classify(value) });
+        }
+    }
+
+    /// Checks every record the sink has accepted since the last call.
+    pub(crate) fn verify_queued(&mut self) {
+        for record in self.queue.take_all() {
+            self.verify(&record);
+        }
+    }
+
+    fn verify(&mut self, record: &str) {
+        let name = record_name(record);
+        match name.and_then(|name| self.expected.iter().position(|e| self.classes.name(e.message) == name)) {
+            Some(at) => {
+                for lost in self.expected.drain(..at) {
+                    info!("Loopback: {} for {} never reached the sink", self.classes.name(lost.message), lost.value);
+                    self.missing += 1;
+                }
+                self.expected.pop_front();
+                self.matched += 1;
+            }
+            None => {
+                self.mismatched += 1;
+                let next = self.expected.front().map(|e| format!("{} for {}", self.classes.name(e.message), e.value));
+                error!("Loopback mismatch: the sink got '{}', expected {}", record, next.as_deref().unwrap_or("nothing"));
+                events::emit("alert", json!({"kind": "loopback_mismatch", "record": record, "expected": next}));
+            }
+        }
+    }
+
+    /// Called once the pipeline has stopped: whatever is still expected never arrived.
+    pub(crate) fn finish(&mut self) {
+        self.verify_queued();
+        self.missing += self.expected.len() as u64;
+        self.expected.clear();
+    }
+}
+
+/// The name of the message a record stands for, from the `Msg <name> (...)` that starts it.
+fn record_name(record: &str) -> Option<&str> {
+    let (name, _) = record.strip_prefix("Msg ")?.split_once(" (")?;
+    Some(name)
+}
+
+#[cfg(test)]
+pub(crate) mod loopback_tests {
+    use super::*;
+
+    #[test]
+    fn test_check_matches_in_order() {
+        let queue = LoopbackQueue::default();
+        let rules = ValidationRules { reject_multiples_of: vec![7], ..Default::default() };
+        let mut check = LoopbackCheck::new(queue.clone(), rules, RuleSet::default());
+        let mut sink = LoopbackSink(queue.clone());
+        (1..=8).for_each(|value| check.emitted(value));
+        // 7 is rejected and 3 dropped on the way; the tenant and the totals do not matter
+        for record in ["Msg Value(1) (Value total: 1)", "Msg Value(2) (Value total: 2) tenant=acme", "Msg Value(4) (Value total: 3)",
+                       "Msg Buzz (Buzz total: 1)", "Msg Fizz (Fizz total: 1)", "Msg Value(9) (Value total: 4)", "Msg Value(8) (Value total: 4)"] {
+            sink.write_record(record).expect("room");
+        }
+        check.verify_queued();
+        assert_eq!((check.matched, check.missing, check.mismatched), (6, 1, 1));
+        assert!(queue.take_all().is_empty());
+
+        check.emitted(10);
+        check.finish();
+        assert_eq!((check.matched, check.missing, check.mismatched), (6, 2, 1));
+        assert_eq!(record_name("Rejected 7"), None);
+    }
+
+    #[test]
+    fn test_sink_pushes_back_when_full() {
+        let mut sink = LoopbackSink(LoopbackQueue::default());
+        (0..QUEUE_CAPACITY).for_each(|_| sink.write_record("Msg Fizz (Fizz total: 1)").expect("room"));
+        let err = sink.write_record("Msg Fizz (Fizz total: 1)").expect_err("full");
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        assert_eq!(sink.0.take_all().len(), QUEUE_CAPACITY);
+        assert!(sink.write_record("Msg Buzz (Buzz total: 1)").is_ok());
+    }
+}
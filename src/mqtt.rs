@@ -0,0 +1,214 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use rumqttc::{Client, Event, MqttOptions, Packet, Publish, QoS};
+#[cfg(feature = "tls")]
+use rumqttc::{TlsConfiguration, Transport};
+use steady_state::*;
+use crate::sink::Sink;
+
+/// Requests rumqttc may queue toward the broker before publishing reports backpressure.
+const REQUEST_QUEUE: usize = 64;
+
+/// Pause between reconnect attempts while the broker is unreachable.
+const RECONNECT_BACKOFF: Duration = Duration::from_millis(500);
+
+/// A broker and topic, written `host:port/topic`. An IPv6 host keeps its brackets, as in
+/// `[::1]:1883/topic`, which is how rumqttc wants it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MqttEndpoint {
+    pub(crate) host: String,
+    pub(crate) port: u16,
+    pub(crate) topic: String,
+}
+
+/// Parses `host:port/topic`; the topic may itself contain slashes.
+pub(crate) fn parse_endpoint(text: &str) -> Result<MqttEndpoint, String> {
+    let bad = || format!("expected 'host:port/topic', got '{}'", text);
+    let (addr, topic) = text.split_once('/').ok_or_else(bad)?;
+    let (host, port) = addr.rsplit_once(':').ok_or_else(bad)?;
+    if host.is_empty() || topic.is_empty() {
+        return Err(bad());
+    }
+    let ipv6 = host.strip_prefix('[').and_then(|h| h.strip_suffix(']'));
+    if (host.starts_with('[') || host.contains(':')) && ipv6.is_none_or(|h| h.parse::<std::net::Ipv6Addr>().is_err()) {
+        return Err(format!("an IPv6 broker goes in brackets, as in '[::1]:1883/topic', got '{}'", text));
+    }
+    let port = port.parse().map_err(|e| format!("bad port in '{}': {}", text, e))?;
+    Ok(MqttEndpoint { host: host.to_string(), port, topic: topic.to_string() })
+}
+
+/// A broker connection whose event loop runs on its own thread.
+/// The session is persistent (fixed client id, clean session off), so while we are
+/// away the broker keeps our subscription and any unacknowledged QoS 1 messages,
+/// and the event loop reconnects by itself after a drop. Whoever owns the link
+/// keeps it across actor restarts, so a restart never opens a second session.
+pub(crate) struct MqttLink {
+    pub(crate) client: Client,
+    pub(crate) endpoint: MqttEndpoint,
+    connected: Arc<AtomicBool>,
+    connects: Arc<AtomicU64>,
+    /// Messages delivered on a subscribed link. They are acknowledged by hand once
+    /// handed on, and the broker stops sending when too many are unacknowledged,
+    /// so this never grows past its in-flight window.
+    pub(crate) incoming: flume::Receiver<Publish>,
+}
+
+impl MqttLink {
+    /// Connects as `client_id`, subscribing to the endpoint's topic when `subscribe` is set.
+    /// With `--tls-ca` the connection is TLS, and each reconnect reads the certificates again.
+    pub(crate) fn start(endpoint: &MqttEndpoint, client_id: &str, subscribe: bool, args: &crate::MainArg) -> io::Result<Self> {
+        let mut options = MqttOptions::new(client_id, endpoint.host.clone(), endpoint.port);
+        options
+            .set_clean_session(false)
+            .set_manual_acks(true)
+            .set_keep_alive(Duration::from_secs(5));
+        #[cfg(feature = "tls")]
+        let tls = crate::tls::TlsFiles::from_args(args);
+        #[cfg(feature = "tls")]
+        if let Some(config) = tls.client()? {
+            // rumqttc names the server by the host as written, and brackets are no server name
+            if endpoint.host.starts_with('[') {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "over TLS give the MQTT broker by a name its certificate holds, not an IPv6 address"));
+            }
+            options.set_transport(Transport::tls_with_config(TlsConfiguration::Rustls(config)));
+        }
+        let _ = args; // only read with the tls feature
+        let (client, mut connection) = Client::new(options, REQUEST_QUEUE);
+        let (deliver, incoming) = flume::unbounded();
+        let link = MqttLink {
+            client: client.clone(),
+            endpoint: endpoint.clone(),
+            connected: Arc::new(AtomicBool::new(false)),
+            connects: Arc::new(AtomicU64::new(0)),
+            incoming,
+        };
+
+        let (connected, connects) = (link.connected.clone(), link.connects.clone());
+        let broker = format!("{}:{}", endpoint.host, endpoint.port);
+        let topic = endpoint.topic.clone();
+        let name = client_id.to_string();
+        std::thread::Builder::new()
+            .name(format!("mqtt-{}", client_id))
+            .spawn(move || {
+                while let Ok(event) = connection.recv() {
+                    match event {
+                        Ok(Event::Incoming(Packet::ConnAck(ack))) => {
+                            connected.store(true, Ordering::SeqCst);
+                            let n = connects.fetch_add(1, Ordering::SeqCst);
+                            info!("MQTT {} connected to {} (reconnects: {}, session present: {})", name, broker, n, ack.session_present);
+                            // A broker that lost our session has lost the subscription with it.
+                            if subscribe && !ack.session_present
+                                && let Err(e) = client.try_subscribe(topic.clone(), QoS::AtLeastOnce) {
+                                warn!("MQTT {} could not subscribe to {}: {}", name, topic, e);
+                            }
+                        }
+                        Ok(Event::Incoming(Packet::Publish(publish))) => {
+                            if deliver.send(publish).is_err() {
+                                break;
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(e) => {
+                            if connected.swap(false, Ordering::SeqCst) {
+                                warn!("MQTT {} lost connection to {}: {}", name, broker, e);
+                            }
+                            #[cfg(feature = "tls")]
+                            tls.refresh(&mut connection.eventloop.mqtt_options);
+                            std::thread::sleep(RECONNECT_BACKOFF);
+                        }
+                    }
+                }
+            })?;
+        Ok(link)
+    }
+
+    pub(crate) fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    /// Times the session had to be re-established after the first connect.
+    pub(crate) fn reconnects(&self) -> u64 {
+        self.connects.load(Ordering::SeqCst).saturating_sub(1)
+    }
+}
+
+/// The sink's link, shared by every logger start so a restart reuses the live session.
+static SINK_LINK: OnceLock<MqttLink> = OnceLock::new();
+
+/// Publishes each record at QoS 1. While the broker is unreachable, or rumqttc's
+/// queue is full, writes fail so the logger holds or replays them like any other outage.
+pub(crate) struct MqttSink {
+    link: &'static MqttLink,
+}
+
+impl MqttSink {
+    pub(crate) fn open(endpoint: &MqttEndpoint, client_id: &str, args: &crate::MainArg) -> io::Result<Self> {
+        let link = match SINK_LINK.get() {
+            Some(link) => link,
+            None => {
+                let link = MqttLink::start(endpoint, client_id, false, args)?;
+                SINK_LINK.get_or_init(|| link)
+            }
+        };
+        Ok(MqttSink { link })
+    }
+}
+
+impl Sink for MqttSink {
+    fn write_record(&mut self, record: &str) -> io::Result<()> {
+        if !self.link.is_connected() {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "MQTT broker unreachable"));
+        }
+        self.link.client
+            .try_publish(self.link.endpoint.topic.clone(), QoS::AtLeastOnce, false, record.as_bytes().to_vec())
+            .map_err(|e| io::Error::new(io::ErrorKind::WouldBlock, e))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod mqtt_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_endpoint() {
+        assert_eq!(
+            parse_endpoint("localhost:1883/robust/values"),
+            Ok(MqttEndpoint { host: "localhost".into(), port: 1883, topic: "robust/values".into() })
+        );
+        assert!(parse_endpoint("localhost:1883").is_err());
+        assert!(parse_endpoint("localhost/values").is_err());
+        assert!(parse_endpoint(":1883/values").is_err());
+        assert!(parse_endpoint("localhost:http/values").is_err());
+        assert_eq!(parse_endpoint("[::1]:8883/values").map(|e| (e.host, e.port)), Ok(("[::1]".into(), 8883)));
+        assert!(parse_endpoint("::1:8883/values").is_err());
+        assert!(parse_endpoint("[localhost]:8883/values").is_err());
+    }
+
+    #[test]
+    fn test_sink_fails_while_broker_unreachable() {
+        // nothing listens on port 1, so the link never connects
+        let endpoint = parse_endpoint("127.0.0.1:1/robust/results").expect("valid endpoint");
+        let mut sink = MqttSink::open(&endpoint, "robust-test-sink", &crate::MainArg::default()).expect("link started");
+        let err = sink.write_record("Fizz").expect_err("no broker");
+        assert_eq!(err.kind(), io::ErrorKind::NotConnected);
+        assert_eq!(sink.link.reconnects(), 0);
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn test_tls_link_needs_a_broker_name() {
+        let files = crate::tls::tls_tests::test_files(true);
+        let (cert, key) = files.identity.expect("client identity");
+        let args = crate::MainArg { tls_cert: Some(cert), tls_key: Some(key), tls_ca: files.roots, ..Default::default() };
+        let by_address = parse_endpoint("[::1]:1/robust/results").expect("valid endpoint");
+        let err = MqttLink::start(&by_address, "robust-test-tls-v6", false, &args).err().expect("no server name");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        // nothing listens on port 1; the link starts and keeps trying over TLS
+        let by_name = parse_endpoint("localhost:1/robust/results").expect("valid endpoint");
+        let link = MqttLink::start(&by_name, "robust-test-tls", false, &args).expect("link started");
+        assert!(!link.is_connected());
+    }
+}
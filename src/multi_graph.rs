@@ -0,0 +1,119 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use steady_state::*;
+use crate::state_lock::settled_within;
+use crate::wiring::{RobustActor, Wiring};
+use crate::MainArg;
+
+/// How long the summary of the ingest graph waits for the queue writer to let go of its state.
+const PATIENCE: Duration = Duration::from_millis(250);
+
+/// Set once the pipeline has stopped, for the ingest graph beside it to stop too.
+static PIPELINE_STOPPED: AtomicBool = AtomicBool::new(false);
+
+/// Which graph a set of arguments builds. A `multi-graph` run builds both from the same
+/// arguments, so every option means the same in each; this alone tells them apart.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) enum GraphRole {
+    /// The pipeline, fed by the generator or by whatever takes its place.
+    #[default]
+    Pipeline,
+    /// The generator alone, appending what it generates to the `--queue-dir` queue.
+    Ingest,
+}
+
+/// True once the pipeline of this process has stopped.
+pub(crate) fn pipeline_stopped() -> bool {
+    PIPELINE_STOPPED.load(Ordering::SeqCst)
+}
+
+/// Turns a `multi-graph` subcommand into the pipeline run it stands for, reading the queue in
+/// `queue_dir`, and returns the arguments of the ingest graph that fills it.
+pub(crate) fn enable(args: &mut MainArg, queue_dir: PathBuf) -> MainArg {
+    args.queue_dir = Some(queue_dir);
+    args.command = None;
+    MainArg { graph: GraphRole::Ingest, ..args.clone() }
+}
+
+/// The stages of the ingest graph, in the order they are built.
+pub(crate) fn ingest_stages() -> Vec<Box<dyn RobustActor>> {
+    vec![
+        Box::new(crate::actor::generator::Generator),
+        Box::new(crate::actor::queue_writer::QueueWriter),
+    ]
+}
+
+/// Builds the ingest graph: the generator and the queue writer, with the same channels,
+/// states and startup order as the pipeline's stages, of which it uses only these two.
+pub(crate) fn build_ingest_graph(graph: &mut Graph) -> crate::PipelineState {
+    let args = graph.args::<MainArg>().cloned().unwrap_or_default();
+    let channel_builder = graph.channel_builder();
+    let wiring = Wiring::new(&channel_builder, args);
+    let actor_builder = graph.actor_builder()
+        .with_thread_info()
+        .with_load_avg()
+        .with_mcpu_avg();
+    crate::build_stages(graph, &actor_builder, &wiring, &ingest_stages());
+    wiring.state
+}
+
+/// Runs the ingest graph on a thread of its own until it has queued `--beats` values, or
+/// until `stop` is called once the pipeline is done.
+pub(crate) fn spawn_ingest(args: MainArg) -> JoinHandle<Result<(), String>> {
+    let stop_timeout = Duration::from_millis(args.drain_worker_ms) + crate::SHUTDOWN_GRACE;
+    std::thread::spawn(move || {
+        SteadyRunner::release_build()
+            .with_telemetry_rate_ms(200)
+            .run(args, move |mut graph| {
+                let state = build_ingest_graph(&mut graph);
+                graph.start();
+                let result = graph.block_until_stopped(stop_timeout);
+                let generated = state.stats().generated;
+                match settled_within(&state.queue_writer, PATIENCE) {
+                    Some(s) => info!("Ingest graph stopped: generated {}, queued {} (queue now holds {}, writer starts: {}, syncs: {})",
+                                     generated, s.written, s.queued, s.restart_count, s.fsync.syncs),
+                    None => warn!("Ingest graph stopped: generated {}, queue writer state unreadable", generated),
+                }
+                let (left, _) = state.channels.residue();
+                if !left.is_empty() {
+                    let left: Vec<String> = left.iter().map(|channel| channel.to_string()).collect();
+                    warn!("Items left in ingest graph channels at shutdown: {}", left.join("; "));
+                }
+                result
+            })
+            .map_err(|e| e.to_string())
+    })
+}
+
+/// Stops the ingest graph once the pipeline has stopped, and waits for it.
+pub(crate) fn stop(ingest: JoinHandle<Result<(), String>>) -> Result<(), Box<dyn Error>> {
+    PIPELINE_STOPPED.store(true, Ordering::SeqCst);
+    match ingest.join() {
+        Ok(result) => result.map_err(|e| format!("ingest graph failed: {}", e).into()),
+        Err(_) => Err("ingest graph panicked".into()),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod multi_graph_tests {
+    use super::*;
+
+    /// Each graph of a multi-graph run has every link its stages read written by another.
+    #[test]
+    fn test_graphs_split_at_the_queue() {
+        let mut args = MainArg::default();
+        let ingest = enable(&mut args, "queue".into());
+        assert_eq!((args.graph, ingest.graph, args.command.is_none()), (GraphRole::Pipeline, GraphRole::Ingest, true));
+        assert_eq!(crate::wiring::source(&args), crate::wiring::Source::Queue);
+
+        let names = |stages: Vec<Box<dyn RobustActor>>, args: &MainArg| -> Vec<&'static str> {
+            stages.into_iter().filter(|stage| stage.enabled(args)).map(|stage| stage.name()).collect()
+        };
+        assert_eq!(names(ingest_stages(), &ingest), ["GENERATOR", "QUEUE_WRITER"]);
+        assert_eq!(crate::startup::check(&ingest_stages(), &ingest), Ok(()));
+        let pipeline = names(crate::wiring::stages(), &args);
+        assert_eq!(&pipeline[..3], ["HEARTBEAT", "QUEUE_SOURCE", "WORKER"]);
+        assert_eq!(crate::startup::check(&crate::wiring::stages(), &args), Ok(()));
+    }
+}
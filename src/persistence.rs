@@ -0,0 +1,115 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use steady_state::*;
+use crate::checkpoint::Checkpoint;
+use crate::MainArg;
+
+/// The file in the `--state-dir` holding the last consistent checkpoint.
+pub(crate) const SNAPSHOT_FILE: &str = "checkpoint.json";
+
+/// The checkpoint this process resumed from, read once at startup so every stage seeds its
+/// state from the same cut however late it first starts.
+static RESTORED: Mutex<Option<Snapshot>> = Mutex::new(None);
+
+/// What a `--state-dir` keeps: the last consistent checkpoint of the pipeline and the
+/// `--beats` the run was first started with, which a resumed run still stops at.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Snapshot {
+    pub(crate) beats: u64,
+    pub(crate) checkpoint: Checkpoint,
+}
+
+/// Reads a JSON file, None when it does not exist yet, as on a first run.
+pub(crate) fn load<T: DeserializeOwned>(path: &Path) -> io::Result<Option<T>> {
+    match std::fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes).map(Some).map_err(io::Error::from),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Replaces a JSON file through a temporary file and a rename, so a process killed
+/// mid-write leaves the previous contents rather than half of the new ones.
+pub(crate) fn save<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    std::fs::write(&partial, serde_json::to_vec(value)?)?;
+    std::fs::rename(&partial, path)
+}
+
+/// Reads the snapshot in a state directory, None when the directory has none yet.
+pub(crate) fn load_snapshot(dir: &Path) -> io::Result<Option<Snapshot>> {
+    load(&dir.join(SNAPSHOT_FILE))
+}
+
+/// Creates the `--state-dir` if need be and reads the checkpoint left there by an earlier
+/// process, which `restored` then hands to each stage as it first starts.
+pub(crate) fn init(args: &MainArg) -> io::Result<()> {
+    let snapshot = match &args.state_dir {
+        Some(dir) => {
+            std::fs::create_dir_all(dir)?;
+            load_snapshot(dir)?
+        }
+        None => None,
+    };
+    if let Some(snapshot) = snapshot {
+        info!("Resuming from checkpoint {} in {:?}: generator at {}, logger logged {}, stopping at {} beats",
+              snapshot.checkpoint.barrier, args.state_dir.as_deref().unwrap_or(Path::new("")),
+              snapshot.checkpoint.worker.values_processed, snapshot.checkpoint.messages_logged, snapshot.beats);
+    }
+    *RESTORED.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = snapshot;
+    Ok(())
+}
+
+/// The checkpoint this process resumed from, if any.
+pub(crate) fn restored() -> Option<Snapshot> {
+    *RESTORED.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Where the logger writes each consistent checkpoint under `--state-dir`.
+#[derive(Clone, Debug)]
+pub(crate) struct StateDir {
+    file: PathBuf,
+    beats: u64,
+}
+
+impl StateDir {
+    pub(crate) fn from_args(args: &MainArg) -> Option<Self> {
+        let beats = restored().map_or(args.beats, |snapshot| snapshot.beats);
+        args.state_dir.as_ref().map(|dir| StateDir { file: dir.join(SNAPSHOT_FILE), beats })
+    }
+
+    pub(crate) fn save(&self, checkpoint: Checkpoint) -> io::Result<()> {
+        save(&self.file, &Snapshot { beats: self.beats, checkpoint })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod persistence_tests {
+    use super::*;
+    use crate::checkpoint::WorkerCheckpoint;
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let dir = std::env::temp_dir().join(format!("robust-state-{}", crate::arg::new_run_id()));
+        std::fs::create_dir_all(&dir).expect("create");
+        assert_eq!(load_snapshot(&dir).expect("missing is fine"), None);
+        let args = MainArg { state_dir: Some(dir.clone()), beats: 40, ..Default::default() };
+        let state_dir = StateDir::from_args(&args).expect("configured");
+        let checkpoint = Checkpoint {
+            barrier: 20,
+            worker: WorkerCheckpoint { barrier: 20, values_processed: 19, messages_sent: 18, values_rejected: 1, seq: 20, ..Default::default() },
+            messages_logged: 18,
+            ..Default::default()
+        };
+        state_dir.save(Checkpoint { barrier: 10, ..checkpoint }).expect("save");
+        state_dir.save(checkpoint).expect("save");
+        assert_eq!(load_snapshot(&dir).expect("load"), Some(Snapshot { beats: 40, checkpoint }));
+        std::fs::write(dir.join(SNAPSHOT_FILE), "{\"beats\": 40").expect("write");
+        assert!(load_snapshot(&dir).is_err());
+        std::fs::remove_dir_all(&dir).expect("remove");
+    }
+}
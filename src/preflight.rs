@@ -0,0 +1,223 @@
+use std::fmt;
+use steady_state::*;
+use crate::deadline::DeadlineAction;
+use crate::fsync::FsyncPolicy;
+use crate::input::GeneratorSource;
+use crate::record::RecordFormat;
+use crate::sink::SinkSpec;
+use crate::MainArg;
+
+/// Everything wrong with a set of arguments, found together so one attempt reports all of it.
+/// clap checks each option alone; these are the combinations and limits it cannot express.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ArgProblems(pub(crate) Vec<String>);
+
+impl fmt::Display for ArgProblems {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} problem{} with the arguments:", self.0.len(), if self.0.len() == 1 { "" } else { "s" })?;
+        for problem in &self.0 {
+            write!(f, "\n  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ArgProblems {}
+
+/// Checks the arguments of a pipeline run before anything is started.
+pub(crate) fn check(args: &MainArg) -> Result<(), ArgProblems> {
+    let mut problems = Vec::new();
+    let mut conflict = |broken: bool, problem: &str| {
+        if broken {
+            problems.push(problem.to_string());
+        }
+    };
+
+    conflict(args.no_heartbeat && args.beats == 0,
+             "--no-heartbeat with --beats 0 would end the run at once; without the heartbeat the run lasts --rate x --beats ms");
+    conflict(args.no_heartbeat && args.checkpoint_every > 0,
+             "--checkpoint-every needs the heartbeat, barriers ride on heartbeats; drop it or --no-heartbeat");
+    conflict(args.no_heartbeat && args.restore_from.is_some(),
+             "--restore-from resumes the heartbeat's count; drop it or --no-heartbeat");
+    if let Some(path) = &args.restore_from
+        && let Err(e) = crate::resume::load(path) {
+        conflict(true, &format!("--restore-from {:?} cannot be resumed from: {}", path, e));
+    }
+    if let Some(dir) = &args.state_dir {
+        conflict(args.checkpoint_every == 0, "--state-dir saves checkpoints, so it needs --checkpoint-every");
+        conflict(args.restore_from.is_some(), "--restore-from and --state-dir both resume the heartbeat; --state-dir already keeps its count");
+        conflict(args.backfill_range.is_some(), "--state-dir resumes the generator's live sequence, which --backfill replaces at startup");
+        conflict(args.source != GeneratorSource::Sequence, "--state-dir resumes the generator's live sequence, which --source replaces");
+        conflict(external_source(args) || args.queue_dir.is_some(),
+                 "--state-dir resumes the built-in generator, which a TCP, gRPC or MQTT source or --queue-dir replaces");
+        if let Err(e) = crate::persistence::load_snapshot(dir) {
+            conflict(true, &format!("--state-dir {:?} cannot be resumed from: {}", dir, e));
+        }
+    }
+    conflict(args.no_heartbeat && args.event_window_ms > 0,
+             "--event-window-ms needs the heartbeat, event time comes from heartbeats; drop it or --no-heartbeat");
+    conflict(args.processing_deadline_ms.is_none() && args.deadline_action != DeadlineAction::Log,
+             "--deadline-action only applies with --processing-deadline-ms");
+    conflict(args.fsync != FsyncPolicy::Never && args.wal.is_none() && !matches!(args.sink, SinkSpec::File(_)) && args.queue_dir.is_none(),
+             "--fsync applies to the file sink, the WAL and a multi-graph queue; add --sink file:<path> or --wal");
+    conflict(args.sink_retry_ms == 0, "--sink-retry-ms must be at least 1, or a failing sink is retried in a busy loop");
+    conflict(args.reconcile_ms == 0, "--reconcile-ms must be at least 1");
+    conflict(crate::rules::RuleSet::from_args(args).len() > crate::rules::MAX_RULES,
+             &format!("--rule and --rules-file give more than {} rules between them", crate::rules::MAX_RULES));
+    for setting in &args.wait {
+        conflict(!crate::wait::WAIT_ACTORS.contains(&setting.actor.as_str()) && !crate::NAME_POOL.contains(&setting.actor.as_str()),
+                 &format!("--wait {} names an actor without a configurable wait; it applies to {} and the pool workers",
+                          setting.actor.to_ascii_lowercase(), crate::wait::WAIT_ACTORS.join(", ").to_ascii_lowercase()));
+    }
+    conflict(args.clock_report_ms == 0, "--clock-report-ms must be at least 1");
+    conflict(args.latency_slo_ms.is_some() && args.latency_slo_window_ms == 0, "--latency-slo-window-ms must be at least 1");
+    conflict(args.anomaly_sigma.is_some_and(|sigma| !(sigma.is_finite() && sigma > 0.0)), "--anomaly-sigma must be a number above 0");
+    conflict(args.anomaly_sigma.is_some() && args.anomaly_alpha == 0.0, "--anomaly-alpha must be above 0, or the moving average never moves");
+    conflict(!(args.flow_kp.is_finite() && args.flow_kp >= 0.0), "--flow-kp must be a number, 0 or more");
+    conflict(!(args.flow_ki.is_finite() && args.flow_ki >= 0.0), "--flow-ki must be a number, 0 or more");
+    conflict(!args.tenant_quota.is_empty() && args.tenant_partitions == 0 && !external_source(args),
+             "--tenant-quota only limits labeled values; add --tenant-partitions, or feed the pipeline from a TCP, gRPC or MQTT source");
+    conflict(args.flow_target_inflight.is_some() && external_source(args),
+             "--flow-target-inflight paces the built-in generator, which a TCP, gRPC or MQTT source replaces");
+    conflict(args.queue_dir.is_some() && external_source(args),
+             "--queue-dir and a TCP, gRPC or MQTT source both replace the generator; pick one");
+    conflict(args.source != GeneratorSource::Sequence && external_source(args),
+             "--source feeds the built-in generator, which a TCP, gRPC or MQTT source replaces");
+    conflict(args.source != GeneratorSource::Sequence && args.backfill.is_some(),
+             "--backfill replays history before the generator's live sequence, which --source replaces; put the history first in the input instead");
+    conflict(args.priority_window > 0 && args.batch_size == 0, "--priority-window picks each batch the worker flushes, so it needs --batch-size");
+    conflict(args.priority_window > 0 && args.priority_window < args.batch_size,
+             "--priority-window must hold at least --batch-size values, the batch is picked from it");
+    conflict(args.priority_window > 0 && args.priority.is_empty(), "--priority-window ranks values by their --priority; add --priority");
+    conflict(!args.priority.is_empty() && external_source(args),
+             "--priority tags the built-in generator's values, which a TCP, gRPC or MQTT source replaces");
+    conflict(args.priority_aging_ms == 0, "--priority-aging-ms must be at least 1");
+    conflict(args.flow_target_inflight.is_some() && args.queue_dir.is_some(),
+             "--flow-target-inflight paces the generator by the logger's commits, which --queue-dir puts in another graph");
+    conflict(matches!(args.sink, SinkSpec::Loopback(_)) && args.record_format != RecordFormat::Text,
+             "--sink loopback reads the text records back; drop --record-format or use text");
+    conflict(matches!(args.sink, SinkSpec::Loopback(_)) && (external_source(args) || args.source != GeneratorSource::Sequence),
+             "--sink loopback checks the records against the built-in generator's sequence, which --source or a TCP, gRPC or MQTT source replaces");
+    #[cfg(all(feature = "grpc", feature = "mqtt"))]
+    conflict(args.grpc_listen.is_some() && args.mqtt_source.is_some(), "--grpc-listen and --mqtt-source both replace the generator; pick one");
+    #[cfg(feature = "grpc")]
+    conflict(args.tcp_listen.is_some() && args.grpc_listen.is_some(), "--tcp-listen and --grpc-listen both replace the generator; pick one");
+    #[cfg(feature = "mqtt")]
+    conflict(args.tcp_listen.is_some() && args.mqtt_source.is_some(), "--tcp-listen and --mqtt-source both replace the generator; pick one");
+    #[cfg(any(feature = "grpc", feature = "mqtt"))]
+    conflict(args.ack_after_commit && args.tcp_listen.is_some(),
+             "--ack-after-commit answers gRPC calls and MQTT messages; the TCP source sends its clients nothing back");
+    #[cfg(all(feature = "tls", any(feature = "grpc", feature = "mqtt")))]
+    conflict(args.tcp_listen.is_some() && (args.tls_cert.is_some() || args.tls_ca.is_some()) && !mqtt_sink(args),
+             "--tls-cert, --tls-key and --tls-ca secure the gRPC source and the MQTT links; the TCP source only reads plain text");
+    #[cfg(feature = "zstd")]
+    conflict(args.wal_zstd_level.is_some() && args.wal.is_none(), "--wal-zstd-level only applies with --wal");
+    if let Err(e) = crate::startup::check(&crate::wiring::stages(), args) {
+        problems.push(e.to_string());
+    }
+
+    if problems.is_empty() { Ok(()) } else { Err(ArgProblems(problems)) }
+}
+
+/// True when a TCP, gRPC or MQTT source takes the generator's place.
+fn external_source(args: &MainArg) -> bool {
+    if args.tcp_listen.is_some() {
+        return true;
+    }
+    #[cfg(feature = "grpc")]
+    if args.grpc_listen.is_some() {
+        return true;
+    }
+    #[cfg(feature = "mqtt")]
+    if args.mqtt_source.is_some() {
+        return true;
+    }
+    false
+}
+
+/// True when the records go to an MQTT broker, whose link takes the TLS options.
+#[cfg(all(feature = "tls", any(feature = "grpc", feature = "mqtt")))]
+fn mqtt_sink(args: &MainArg) -> bool {
+    #[cfg(feature = "mqtt")]
+    if matches!(args.sink, SinkSpec::Mqtt(_)) {
+        return true;
+    }
+    let _ = args; // only read when the MQTT sink is compiled in
+    false
+}
+
+#[cfg(test)]
+pub(crate) mod preflight_tests {
+    use super::*;
+    use crate::quota::parse_tenant_quota;
+
+    #[test]
+    fn test_check_reports_every_problem() {
+        assert_eq!(check(&MainArg::default()), Ok(()));
+
+        let args = MainArg {
+            no_heartbeat: true,
+            beats: 0,
+            checkpoint_every: 10,
+            deadline_action: DeadlineAction::DeadLetter,
+            sink_retry_ms: 0,
+            tenant_quota: vec![parse_tenant_quota("*=5").expect("quota")],
+            ..Default::default()
+        };
+        let problems = check(&args).expect_err("five problems");
+        assert_eq!(problems.0.len(), 5);
+        let text = problems.to_string();
+        assert!(text.starts_with("5 problems with the arguments:\n  - --no-heartbeat with --beats 0"));
+        assert!(text.contains("\n  - --tenant-quota only limits labeled values"));
+
+        // labeled values make the quota meaningful
+        let labeled = MainArg { tenant_partitions: 4, tenant_quota: args.tenant_quota.clone(), ..Default::default() };
+        assert_eq!(check(&labeled), Ok(()));
+
+        // the heartbeat's wait is its beat, so only stages that wait on channels take a strategy
+        let wait = |spec| crate::wait::parse_wait(spec).expect("wait");
+        let waits = MainArg { wait: vec![wait("logger=poll:50"), wait("worker_2=hybrid:5"), wait("heartbeat=poll:5")], ..Default::default() };
+        let problems = check(&waits).expect_err("one problem");
+        assert_eq!(problems.0.len(), 1);
+        assert!(problems.0[0].starts_with("--wait heartbeat names an actor"));
+
+        let state_dir = MainArg { state_dir: Some(std::env::temp_dir()), restore_from: Some("beats.json".into()), ..Default::default() };
+        let problems = check(&state_dir).expect_err("two problems");
+        assert_eq!(problems.0.len(), 2);
+        assert!(problems.0[0].contains("needs --checkpoint-every"));
+        assert_eq!(check(&MainArg { checkpoint_every: 10, restore_from: None, ..state_dir }), Ok(()));
+
+        // nothing to sync without a file
+        let fsync = MainArg { fsync: FsyncPolicy::EveryMessage, ..Default::default() };
+        assert!(check(&fsync).expect_err("one problem").0[0].starts_with("--fsync applies to the file sink"));
+        assert_eq!(check(&MainArg { sink: SinkSpec::File("out.txt".into()), ..fsync.clone() }), Ok(()));
+
+        // the queue stands in for the generator, so nothing resumes or paces it
+        let queue = MainArg { queue_dir: Some(std::env::temp_dir()), ..fsync };
+        assert_eq!(check(&queue), Ok(()));
+        let problems = check(&MainArg { state_dir: Some(std::env::temp_dir()), checkpoint_every: 10, flow_target_inflight: Some(8), ..queue }).expect_err("two problems");
+        assert_eq!(problems.0.len(), 2);
+
+        // read values replace the sequence that a backfill and the loopback sink assume
+        let stdin = MainArg { source: GeneratorSource::Stdin, backfill: Some("history.txt".into()), sink: SinkSpec::Loopback(Default::default()), ..Default::default() };
+        assert_eq!(check(&stdin).expect_err("two problems").0.len(), 2);
+
+        // the window is ranked by priority and the batch is picked from it
+        let window = MainArg { priority_window: 4, batch_size: 8, ..Default::default() };
+        assert_eq!(check(&window).expect_err("two problems").0.len(), 2);
+        let priority = vec![crate::urgency::parse_priority_rule("15=9").expect("rule")];
+        assert_eq!(check(&MainArg { priority_window: 32, priority, ..window }), Ok(()));
+
+        // the TCP source neither acknowledges its clients nor speaks TLS
+        #[cfg(any(feature = "grpc", feature = "mqtt"))]
+        {
+            let tcp = MainArg { tcp_listen: Some("127.0.0.1:7000".parse().expect("address")), ack_after_commit: true, ..Default::default() };
+            assert!(check(&tcp).expect_err("one problem").0[0].starts_with("--ack-after-commit answers"));
+        }
+        #[cfg(all(feature = "tls", any(feature = "grpc", feature = "mqtt")))]
+        {
+            let tcp = MainArg { tcp_listen: Some("127.0.0.1:7000".parse().expect("address")), tls_ca: Some("ca.pem".into()), ..Default::default() };
+            assert!(check(&tcp).expect_err("one problem").0[0].starts_with("--tls-cert, --tls-key and --tls-ca"));
+        }
+    }
+}
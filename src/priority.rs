@@ -0,0 +1,100 @@
+use steady_state::*;
+use crate::MainArg;
+
+/// One `--nice ACTOR=N` setting.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct NiceSetting {
+    /// Actor name as used in the graph, upper case.
+    pub(crate) actor: String,
+    pub(crate) nice: i32,
+}
+
+/// Parses `ACTOR=N`, with the actor name in any case and N from -20 (most favored) to 19.
+pub(crate) fn parse_nice(text: &str) -> Result<NiceSetting, String> {
+    let (actor, nice) = text
+        .split_once('=')
+        .ok_or_else(|| format!("expected ACTOR=NICE, got '{}'", text))?;
+    let actor = actor.trim().to_ascii_uppercase();
+    if actor.is_empty() {
+        return Err(format!("missing actor name in '{}'", text));
+    }
+    let nice: i32 = nice.trim().parse().map_err(|e| format!("bad niceness in '{}': {}", text, e))?;
+    if !(-20..=19).contains(&nice) {
+        return Err(format!("niceness in '{}' must be between -20 and 19", text));
+    }
+    Ok(NiceSetting { actor, nice })
+}
+
+/// The niceness configured for an actor; the last setting given for it wins.
+pub(crate) fn nice_for(args: &MainArg, actor: &str) -> Option<i32> {
+    args.nice.iter().rev().find(|s| s.actor == actor).map(|s| s.nice)
+}
+
+/// Applies the actor's configured niceness to the calling thread, which must be
+/// the actor's own, and logs the value the OS reports back. Called again after a
+/// restart, so a restarted actor keeps its priority.
+pub(crate) fn apply<A: SteadyActor>(context: &A, actor: &str) {
+    let Some(nice) = context.args::<MainArg>().and_then(|args| nice_for(args, actor)) else {
+        return;
+    };
+    match set_thread_nice(nice) {
+        Ok(effective) => info!("{} thread niceness set to {} (effective: {})", actor, nice, effective),
+        Err(e) => warn!("{} thread niceness {} not applied: {}", actor, nice, e),
+    }
+}
+
+/// Linux gives every thread its own niceness, addressed by thread id.
+/// Raising priority (a negative change) needs CAP_SYS_NICE; lowering it never does.
+#[cfg(target_os = "linux")]
+fn set_thread_nice(nice: i32) -> std::io::Result<i32> {
+    // SAFETY: plain syscalls on the current thread's id, with no pointers involved.
+    unsafe {
+        let tid = libc::gettid() as libc::id_t;
+        if libc::setpriority(libc::PRIO_PROCESS, tid, nice) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        // -1 is a legal niceness, so errno has to be cleared to tell it from an error.
+        *libc::__errno_location() = 0;
+        let effective = libc::getpriority(libc::PRIO_PROCESS, tid);
+        if effective == -1 && *libc::__errno_location() != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(effective)
+    }
+}
+
+/// Elsewhere the same call would renice the whole process, so it is not attempted.
+#[cfg(not(target_os = "linux"))]
+fn set_thread_nice(_nice: i32) -> std::io::Result<i32> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "per-thread niceness is only supported on Linux"))
+}
+
+#[cfg(test)]
+pub(crate) mod priority_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_nice() {
+        assert_eq!(parse_nice("logger=10"), Ok(NiceSetting { actor: "LOGGER".into(), nice: 10 }));
+        assert_eq!(parse_nice("WORKER=-5"), Ok(NiceSetting { actor: "WORKER".into(), nice: -5 }));
+        assert!(parse_nice("logger").is_err());
+        assert!(parse_nice("=3").is_err());
+        assert!(parse_nice("logger=20").is_err());
+        assert!(parse_nice("logger=low").is_err());
+
+        let args = MainArg {
+            nice: vec![parse_nice("logger=5").unwrap(), parse_nice("logger=10").unwrap()],
+            ..Default::default()
+        };
+        assert_eq!(nice_for(&args, "LOGGER"), Some(10));
+        assert_eq!(nice_for(&args, "WORKER"), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_lowering_priority_on_own_thread() {
+        // lowering priority needs no privileges and only touches this thread
+        let effective = std::thread::spawn(|| set_thread_nice(19)).join().expect("thread ran");
+        assert_eq!(effective.ok(), Some(19));
+    }
+}
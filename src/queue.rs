@@ -0,0 +1,179 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::tenant;
+use crate::timing::Envelope;
+use crate::wal::{Wal, WalCodec};
+
+/// The log of queued values in a `--queue-dir`, one `<seq> <value> [tenant]` line each.
+pub(crate) const LOG_FILE: &str = "queue.log";
+/// How far the pipeline has read the log, replaced atomically as it reads.
+pub(crate) const CURSOR_FILE: &str = "cursor.json";
+
+/// Bytes read from the log at a time while looking for the next whole entry.
+const READ_CHUNK: usize = 4096;
+
+/// The producing end of a queue directory. Appends go through a WAL, so a producer killed
+/// mid-append leaves a torn entry that the next one to open the queue cuts off.
+pub(crate) struct QueueLog {
+    wal: Wal,
+}
+
+impl QueueLog {
+    /// Creates the directory if need be and opens the log to append after what is already queued.
+    pub(crate) fn open(dir: &Path) -> io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        Ok(QueueLog { wal: Wal::open_end(&dir.join(LOG_FILE), WalCodec::Plain)? })
+    }
+
+    /// Appends one value, flushed to the operating system, returning its sequence number.
+    pub(crate) fn append(&mut self, envelope: &Envelope<u64>) -> io::Result<u64> {
+        let entry = match envelope.tenant {
+            tenant::UNTAGGED => envelope.payload.to_string(),
+            id => format!("{} {}", envelope.payload, tenant::name(id)),
+        };
+        self.wal.append(&entry)
+    }
+
+    pub(crate) fn sync(&mut self) -> io::Result<()> {
+        self.wal.sync()
+    }
+
+    /// Values in the queue, read or not.
+    pub(crate) fn len(&self) -> u64 {
+        self.wal.next_seq()
+    }
+}
+
+/// Where the consuming end has got to: the sequence of the next entry to read and where it starts.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct QueueCursor {
+    pub(crate) next_seq: u64,
+    pub(crate) offset: u64,
+}
+
+/// Reads the cursor saved in a queue directory, the start of the log when there is none yet.
+pub(crate) fn load_cursor(dir: &Path) -> io::Result<QueueCursor> {
+    crate::persistence::load(&dir.join(CURSOR_FILE)).map(Option::unwrap_or_default)
+}
+
+/// The consuming end of a queue directory. It follows the log as the producer appends to it,
+/// handing out each entry only once the whole line is there.
+pub(crate) struct QueueReader {
+    dir: PathBuf,
+    file: File,
+    cursor: QueueCursor,
+    /// Bytes read past the cursor, not yet a whole entry or not yet handed on.
+    pending: Vec<u8>,
+    /// The entry at the cursor once parsed, and its length in the log.
+    next: Option<(Envelope<u64>, u64)>,
+}
+
+impl QueueReader {
+    /// Opens the log at `cursor`, creating the directory and an empty log if the producer has
+    /// not started yet.
+    pub(crate) fn open(dir: &Path, cursor: QueueCursor) -> io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let mut file = OpenOptions::new().read(true).append(true).create(true).open(dir.join(LOG_FILE))?;
+        file.seek(SeekFrom::Start(cursor.offset))?;
+        Ok(QueueReader { dir: dir.to_path_buf(), file, cursor, pending: Vec::new(), next: None })
+    }
+
+    pub(crate) fn cursor(&self) -> QueueCursor {
+        self.cursor
+    }
+
+    /// The entry at the cursor, None until the producer has appended all of it. Reading it does
+    /// not move the cursor; `commit` does, once the value has been handed on.
+    pub(crate) fn peek(&mut self) -> io::Result<Option<Envelope<u64>>> {
+        if let Some((envelope, _)) = self.next {
+            return Ok(Some(envelope));
+        }
+        loop {
+            if let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+                let parsed = std::str::from_utf8(&self.pending[..end]).ok().and_then(parse_entry);
+                return match parsed {
+                    Some((seq, envelope)) if seq == self.cursor.next_seq => {
+                        self.next = Some((envelope, end as u64 + 1));
+                        Ok(Some(envelope))
+                    }
+                    // a torn entry the producer has since cut off and written over; read again from the cursor
+                    _ => {
+                        self.pending.clear();
+                        self.file.seek(SeekFrom::Start(self.cursor.offset))?;
+                        Err(io::Error::new(io::ErrorKind::InvalidData,
+                                           format!("queue entry at byte {} is not seq {}", self.cursor.offset, self.cursor.next_seq)))
+                    }
+                };
+            }
+            let mut chunk = [0u8; READ_CHUNK];
+            let read = self.file.read(&mut chunk)?;
+            if read == 0 {
+                return Ok(None);
+            }
+            self.pending.extend_from_slice(&chunk[..read]);
+        }
+    }
+
+    /// Moves the cursor past the entry `peek` returned and saves it, so a later start reads on
+    /// from the next one.
+    pub(crate) fn commit(&mut self) -> io::Result<()> {
+        let Some((_, len)) = self.next.take() else {
+            return Ok(());
+        };
+        self.pending.drain(..len as usize);
+        self.cursor = QueueCursor { next_seq: self.cursor.next_seq + 1, offset: self.cursor.offset + len };
+        crate::persistence::save(&self.dir.join(CURSOR_FILE), &self.cursor)
+    }
+}
+
+/// Parses one log line, stamping the value now, as it enters this graph.
+fn parse_entry(line: &str) -> Option<(u64, Envelope<u64>)> {
+    let (seq, entry) = line.split_once(' ')?;
+    let (value, tenant) = match entry.split_once(' ') {
+        Some((value, name)) => (value, tenant::id(name)),
+        None => (entry, tenant::UNTAGGED),
+    };
+    Some((seq.parse().ok()?, Envelope::new(value.parse().ok()?).with_tenant(tenant)))
+}
+
+#[cfg(test)]
+pub(crate) mod queue_tests {
+    use std::io::Write;
+    use super::*;
+
+    /// Values cross the queue in order, a torn entry is cut off when the producer reopens, and a
+    /// reader opened at the saved cursor carries on after the last value committed.
+    #[test]
+    fn test_queue_round_trip() -> io::Result<()> {
+        let dir = std::env::temp_dir().join(format!("robust-queue-{}", crate::arg::new_run_id()));
+        let mut reader = QueueReader::open(&dir, load_cursor(&dir)?)?;
+        assert_eq!(reader.peek()?, None);
+        let mut log = QueueLog::open(&dir)?;
+        for value in [3, 5] {
+            log.append(&Envelope::new(value))?;
+        }
+        assert_eq!(reader.peek()?.map(|e| e.payload), Some(3));
+        assert_eq!(reader.peek()?.map(|e| e.payload), Some(3));
+        reader.commit()?;
+        drop(log);
+
+        // a producer killed partway through seq 2
+        OpenOptions::new().append(true).open(dir.join(LOG_FILE))?.write_all(b"2 1")?;
+        let mut log = QueueLog::open(&dir)?;
+        assert_eq!(log.len(), 2);
+        log.append(&Envelope::new(15))?;
+
+        let mut reader = QueueReader::open(&dir, load_cursor(&dir)?)?;
+        assert_eq!(reader.cursor(), QueueCursor { next_seq: 1, offset: 4 });
+        let mut read = Vec::new();
+        while let Some(envelope) = reader.peek()? {
+            read.push(envelope.payload);
+            reader.commit()?;
+        }
+        assert_eq!(read, [5, 15]);
+        assert_eq!(load_cursor(&dir)?.next_seq, 3);
+        std::fs::remove_dir_all(&dir)
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use crate::rate_limit::TokenBucket;
+use crate::tenant;
+use crate::timing::Envelope;
+use crate::MainArg;
+
+/// Tenant name in `--tenant-quota` that stands for every tenant without a quota of its own.
+const ANY_TENANT: &str = "*";
+
+/// Over-quota values the generator holds across all tenants before it stops generating.
+pub(crate) const THROTTLED_CAPACITY: usize = 1024;
+
+/// One `--tenant-quota TENANT=N` setting: at most N values a second from that tenant.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct TenantQuota {
+    pub(crate) tenant: String,
+    pub(crate) per_sec: u32,
+}
+
+/// Parses `TENANT=N`, with `*` as the tenant for a default quota.
+pub(crate) fn parse_tenant_quota(text: &str) -> Result<TenantQuota, String> {
+    let (tenant, per_sec) = text
+        .rsplit_once('=')
+        .ok_or_else(|| format!("expected TENANT=PER_SEC, got '{}'", text))?;
+    let tenant = tenant.trim();
+    if tenant.is_empty() {
+        return Err(format!("missing tenant in '{}'", text));
+    }
+    let per_sec = match per_sec.trim().parse() {
+        Ok(0) => return Err(format!("quota in '{}' must be at least 1", text)),
+        Ok(n) => n,
+        Err(e) => return Err(format!("bad quota in '{}': {}", text, e)),
+    };
+    Ok(TenantQuota { tenant: tenant.to_string(), per_sec })
+}
+
+/// What a source does with a value whose tenant is over its quota.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) enum OverQuota {
+    /// Hold it until the tenant's allowance returns: the generator queues it, MQTT leaves it unacknowledged.
+    #[default]
+    Throttle,
+    /// Count it and drop it before it enters the pipeline.
+    Reject,
+}
+
+pub(crate) fn parse_over_quota(text: &str) -> Result<OverQuota, String> {
+    match text.trim().to_ascii_lowercase().as_str() {
+        "throttle" => Ok(OverQuota::Throttle),
+        "reject" => Ok(OverQuota::Reject),
+        other => Err(format!("unknown over-quota action '{}', expected throttle or reject", other)),
+    }
+}
+
+/// Per-tenant ingest quotas for a source, one token bucket per tenant seen.
+/// Lives in the source actor's state, so a restart neither refills the buckets nor loses counts.
+/// Unlabeled values are never limited.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Quotas {
+    pub(crate) action: OverQuota,
+    /// Values dropped or refused for being over their tenant's quota.
+    pub(crate) rejected: u64,
+    /// Values held back until their tenant's quota allowed them.
+    pub(crate) throttled: u64,
+    limits: HashMap<u32, u32>,
+    default: Option<u32>,
+    buckets: HashMap<u32, TokenBucket>,
+}
+
+impl Quotas {
+    /// The `--tenant-quota` settings, the last one for a tenant winning.
+    pub(crate) fn from_args(args: &MainArg) -> Self {
+        let mut quotas = Quotas { action: args.over_quota, ..Default::default() };
+        for quota in &args.tenant_quota {
+            if quota.tenant == ANY_TENANT {
+                quotas.default = Some(quota.per_sec);
+            } else {
+                quotas.limits.insert(tenant::id(&quota.tenant), quota.per_sec);
+            }
+        }
+        quotas
+    }
+
+    fn bucket(&mut self, tenant: u32) -> Option<&mut TokenBucket> {
+        if tenant == tenant::UNTAGGED {
+            return None;
+        }
+        let per_sec = self.limits.get(&tenant).copied().or(self.default)?;
+        Some(self.buckets.entry(tenant).or_insert_with(|| TokenBucket::new(per_sec)))
+    }
+
+    /// Spends one of the tenant's allowance if it has any left at `now`;
+    /// otherwise returns how long until it will.
+    pub(crate) fn admit(&mut self, tenant: u32, now: Instant) -> Result<(), Duration> {
+        let Some(bucket) = self.bucket(tenant) else {
+            return Ok(());
+        };
+        if bucket.try_take(now) { Ok(()) } else { Err(bucket.delay()) }
+    }
+
+    /// How long until the tenant has allowance again; zero if it has some or no quota.
+    pub(crate) fn delay(&mut self, tenant: u32) -> Duration {
+        self.bucket(tenant).map_or(Duration::ZERO, |bucket| bucket.delay())
+    }
+
+    /// `rejected/throttled` for the logs.
+    pub(crate) fn summary(&self) -> String {
+        format!("{}/{}", self.rejected, self.throttled)
+    }
+}
+
+/// Over-quota values the generator holds for later, one queue per tenant so a throttled
+/// tenant never delays the others. Kept in the generator's state so a restart loses none.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Throttled {
+    queues: BTreeMap<u32, VecDeque<Envelope<u64>>>,
+    len: usize,
+    /// Most values held at once.
+    pub(crate) len_max: usize,
+}
+
+impl Throttled {
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.len >= THROTTLED_CAPACITY
+    }
+
+    /// Whether the tenant already has values waiting; new ones queue behind them to keep order.
+    pub(crate) fn holds(&self, tenant: u32) -> bool {
+        self.queues.get(&tenant).is_some_and(|queue| !queue.is_empty())
+    }
+
+    pub(crate) fn push(&mut self, envelope: Envelope<u64>) {
+        self.queues.entry(envelope.tenant).or_default().push_back(envelope);
+        self.len += 1;
+        self.len_max = self.len_max.max(self.len);
+    }
+
+    /// The oldest held value of the first tenant with allowance again, spending it.
+    /// Stays queued until `pop` confirms it was sent.
+    pub(crate) fn next_ready(&self, quotas: &mut Quotas, now: Instant) -> Option<Envelope<u64>> {
+        self.queues
+            .values()
+            .filter_map(|queue| queue.front())
+            .find(|envelope| quotas.admit(envelope.tenant, now).is_ok())
+            .copied()
+    }
+
+    pub(crate) fn pop(&mut self, tenant: u32) {
+        if let Some(queue) = self.queues.get_mut(&tenant)
+            && queue.pop_front().is_some() {
+            self.len -= 1;
+        }
+    }
+
+    /// The oldest held value of any tenant regardless of quota, for a stopping generator to flush.
+    pub(crate) fn front(&self) -> Option<Envelope<u64>> {
+        self.queues.values().find_map(|queue| queue.front()).copied()
+    }
+
+    /// How long until some held tenant has allowance again.
+    pub(crate) fn next_release(&self, quotas: &mut Quotas) -> Duration {
+        self.queues
+            .iter()
+            .filter(|(_, queue)| !queue.is_empty())
+            .map(|(&tenant, _)| quotas.delay(tenant))
+            .min()
+            .unwrap_or(Duration::ZERO)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod quota_tests {
+    use super::*;
+
+    #[test]
+    fn test_tenant_quotas() {
+        assert_eq!(parse_tenant_quota("acme=5"), Ok(TenantQuota { tenant: "acme".to_string(), per_sec: 5 }));
+        assert_eq!(parse_tenant_quota("10.0.0.1=2").map(|q| q.tenant), Ok("10.0.0.1".to_string()));
+        assert!(parse_tenant_quota("acme=0").is_err());
+        assert!(parse_tenant_quota("=3").is_err());
+        assert_eq!(parse_over_quota("Reject"), Ok(OverQuota::Reject));
+
+        let args = MainArg {
+            tenant_quota: ["quota-test-acme=2", "*=1"].map(|q| parse_tenant_quota(q).expect("valid")).to_vec(),
+            ..Default::default()
+        };
+        let mut quotas = Quotas::from_args(&args);
+        let (acme, other, now) = (tenant::id("quota-test-acme"), tenant::id("quota-test-other"), Instant::now());
+        assert!(quotas.admit(acme, now).is_ok() && quotas.admit(acme, now).is_ok());
+        assert_eq!(quotas.admit(acme, now), Err(Duration::from_millis(500)));
+        // the default quota covers the rest, and unlabeled values are never limited
+        assert!(quotas.admit(other, now).is_ok() && quotas.admit(other, now).is_err());
+        assert!((0..10).all(|_| quotas.admit(tenant::UNTAGGED, now).is_ok()));
+
+        let mut throttled = Throttled::default();
+        throttled.push(Envelope::unstamped(1).with_tenant(acme));
+        throttled.push(Envelope::unstamped(2).with_tenant(acme));
+        assert!(throttled.holds(acme) && !throttled.holds(other));
+        assert_eq!(throttled.next_ready(&mut quotas, now), None);
+        assert_eq!(throttled.next_release(&mut quotas), Duration::from_millis(500));
+        let later = now + Duration::from_millis(500);
+        assert_eq!(throttled.next_ready(&mut quotas, later).map(|e| e.payload), Some(1));
+        throttled.pop(acme);
+        assert_eq!((throttled.len(), throttled.len_max), (1, 2));
+        assert_eq!(throttled.front().map(|e| e.payload), Some(2));
+        throttled.pop(acme);
+        assert_eq!((throttled.len(), throttled.front()), (0, None));
+    }
+}
@@ -0,0 +1,104 @@
+use std::time::{Duration, Instant};
+use crate::classify::Filing;
+use crate::MainArg;
+
+/// The record kinds the logger can rate-limit independently.
+pub(crate) const RECORD_KINDS: [&str; 5] = ["fizz", "buzz", "fizzbuzz", "value", "rule"];
+
+/// One `--log-rate-limit KIND=N` setting: at most N records of that kind per second.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct RateLimit {
+    /// Index into `RECORD_KINDS`.
+    pub(crate) kind: usize,
+    pub(crate) per_sec: u32,
+}
+
+/// Parses `KIND=N`, with the kind in any case.
+pub(crate) fn parse_rate_limit(text: &str) -> Result<RateLimit, String> {
+    let (kind, per_sec) = text
+        .split_once('=')
+        .ok_or_else(|| format!("expected KIND=PER_SEC, got '{}'", text))?;
+    let kind = kind.trim().to_ascii_lowercase();
+    let kind = RECORD_KINDS
+        .iter()
+        .position(|&k| k == kind)
+        .ok_or_else(|| format!("unknown record kind '{}', expected one of {}", kind, RECORD_KINDS.join(", ")))?;
+    let per_sec = per_sec.trim().parse().map_err(|e| format!("bad rate in '{}': {}", text, e))?;
+    Ok(RateLimit { kind, per_sec })
+}
+
+/// Index into `RECORD_KINDS` for a record; barriers are never records.
+pub(crate) fn kind_of(filing: Filing) -> Option<usize> {
+    match filing {
+        Filing::Record { kind, .. } => Some(kind),
+        Filing::Matched(_) => Some(4),
+        Filing::Barrier(_) => None,
+    }
+}
+
+/// Token bucket allowing `per_sec` records a second, with a burst of up to one second's worth.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct TokenBucket {
+    per_sec: f64,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(per_sec: u32) -> Self {
+        TokenBucket { per_sec: per_sec as f64, tokens: per_sec as f64, refilled: Instant::now() }
+    }
+
+    /// Spends a token if one is available at `now`.
+    pub(crate) fn try_take(&mut self, now: Instant) -> bool {
+        let earned = now.saturating_duration_since(self.refilled).as_secs_f64() * self.per_sec;
+        self.tokens = (self.tokens + earned).min(self.per_sec);
+        self.refilled = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// How long until the next token is earned; zero if one is available.
+    pub(crate) fn delay(&self) -> Duration {
+        Duration::from_secs_f64((1.0 - self.tokens).max(0.0) / self.per_sec)
+    }
+}
+
+/// One bucket per record kind, `None` where that kind is unlimited; the last setting for a kind wins.
+pub(crate) fn buckets_from_args(args: &MainArg) -> [Option<TokenBucket>; RECORD_KINDS.len()] {
+    let mut buckets = [None; RECORD_KINDS.len()];
+    for limit in &args.log_rate_limit {
+        buckets[limit.kind] = Some(TokenBucket::new(limit.per_sec));
+    }
+    buckets
+}
+
+#[cfg(test)]
+pub(crate) mod rate_limit_tests {
+    use std::time::Duration;
+    use super::*;
+
+    #[test]
+    fn test_parse_and_bucket() {
+        assert_eq!(parse_rate_limit("Value=10"), Ok(RateLimit { kind: 3, per_sec: 10 }));
+        assert_eq!(parse_rate_limit("fizzbuzz=0"), Ok(RateLimit { kind: 2, per_sec: 0 }));
+        assert!(parse_rate_limit("barrier=5").is_err());
+        assert!(parse_rate_limit("value").is_err());
+        assert!(parse_rate_limit("value=-1").is_err());
+
+        let start = Instant::now();
+        let mut bucket = TokenBucket { per_sec: 2.0, tokens: 2.0, refilled: start };
+        assert!(bucket.try_take(start));
+        assert!(bucket.try_take(start));
+        assert!(!bucket.try_take(start));
+        // half a second earns one token back, and idling never banks more than a second's worth
+        assert!(bucket.try_take(start + Duration::from_millis(500)));
+        assert!(!bucket.try_take(start + Duration::from_millis(500)));
+        let later = start + Duration::from_secs(60);
+        assert!(bucket.try_take(later) && bucket.try_take(later) && !bucket.try_take(later));
+    }
+}
@@ -0,0 +1,117 @@
+use chrono::{DateTime, SecondsFormat};
+use serde::Serialize;
+use crate::enrich::Derived;
+
+/// How the logger writes each record to its sink, and so to the WAL that feeds it.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) enum RecordFormat {
+    /// `Msg Fizz (Fizz total: 3)` lines for people, as the logger always wrote them.
+    #[default]
+    Text,
+    /// One JSON object per record.
+    Json,
+    /// One comma-separated row per record, in the order of `CSV_COLUMNS`.
+    Csv,
+}
+
+/// Parses `text`, `json` or `csv`.
+pub(crate) fn parse_record_format(text: &str) -> Result<RecordFormat, String> {
+    match text.trim().to_ascii_lowercase().as_str() {
+        "text" => Ok(RecordFormat::Text),
+        "json" => Ok(RecordFormat::Json),
+        "csv" => Ok(RecordFormat::Csv),
+        other => Err(format!("unknown record format '{}', expected text, json or csv", other)),
+    }
+}
+
+/// The columns of a CSV record. No header row is written, since a restarted logger appends to
+/// the same file or stream.
+pub(crate) const CSV_COLUMNS: [&str; 8] = ["seq", "kind", "value", "total", "logged", "ts", "tenant", "derived"];
+
+/// One committed record as the structured formats write it.
+#[derive(Debug, Serialize)]
+pub(crate) struct RecordFields {
+    /// The worker's sequence number for the message, 0 when it was not sequenced.
+    pub(crate) seq: u64,
+    /// `Fizz`, `Buzz`, `FizzBuzz`, `Value`, or the labels of the `--rule` rules it matched.
+    pub(crate) kind: String,
+    /// The value itself, for the kinds that carry it.
+    pub(crate) value: Option<u64>,
+    /// Records of this kind so far, this one included, and records of every kind.
+    pub(crate) total: u64,
+    pub(crate) logged: u64,
+    /// When the logger wrote the record, RFC 3339 in UTC.
+    pub(crate) ts: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) tenant: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) derived: Option<Derived>,
+}
+
+impl RecordFields {
+    /// Formats a wall-clock time in microseconds for the `ts` field.
+    pub(crate) fn timestamp(wall_micros: u64) -> String {
+        DateTime::from_timestamp_micros(wall_micros as i64)
+            .map_or_else(|| wall_micros.to_string(), |t| t.to_rfc3339_opts(SecondsFormat::Micros, true))
+    }
+
+    pub(crate) fn json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    pub(crate) fn csv(&self) -> String {
+        let optional = |value: Option<String>| value.unwrap_or_default();
+        [
+            self.seq.to_string(),
+            csv_field(&self.kind),
+            optional(self.value.map(|v| v.to_string())),
+            self.total.to_string(),
+            self.logged.to_string(),
+            self.ts.clone(),
+            csv_field(&optional(self.tenant.clone())),
+            csv_field(&optional(self.derived.map(|d| d.json()))),
+        ].join(",")
+    }
+}
+
+/// Quotes a CSV field that holds a comma, a quote or a line break, doubling its quotes.
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod record_tests {
+    use super::*;
+
+    #[test]
+    fn test_structured_records() {
+        assert_eq!(parse_record_format("JSON"), Ok(RecordFormat::Json));
+        assert_eq!(parse_record_format("csv"), Ok(RecordFormat::Csv));
+        assert!(parse_record_format("xml").is_err());
+
+        let fields = RecordFields {
+            seq: 7,
+            kind: "Value".to_string(),
+            value: Some(4),
+            total: 3,
+            logged: 6,
+            ts: RecordFields::timestamp(1_700_000_000_123_456),
+            tenant: None,
+            derived: None,
+        };
+        assert_eq!(fields.ts, "2023-11-14T22:13:20.123456Z");
+        assert_eq!(fields.json(), r#"{"seq":7,"kind":"Value","value":4,"total":3,"logged":6,"ts":"2023-11-14T22:13:20.123456Z"}"#);
+        assert_eq!(fields.csv(), "7,Value,4,3,6,2023-11-14T22:13:20.123456Z,,");
+        assert_eq!(fields.csv().split(',').count(), CSV_COLUMNS.len());
+
+        let fizz = RecordFields { kind: "Fizz".to_string(), value: None, tenant: Some("acme, inc".to_string()), ..fields };
+        assert!(fizz.json().contains(r#""value":null,"#));
+        assert!(fizz.json().ends_with(r#","tenant":"acme, inc"}"#));
+        assert!(fizz.csv().starts_with("7,Fizz,,3,6,"));
+        assert!(fizz.csv().ends_with(",\"acme, inc\","));
+    }
+}
@@ -0,0 +1,664 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use steady_state::*;
+use crate::actor::reconciler::DEPTH_CHANNELS;
+use crate::build_info::BUILD;
+use crate::checkpoint::Checkpoint;
+use crate::fault::FaultCounts;
+use crate::residue::ChannelResidue;
+use crate::restart::{RestartCauses, StartSummary, StartTimes};
+use crate::stage_time::{StageTime, StageTimeSummary};
+use crate::tenant;
+use crate::troupe::Fairness;
+use crate::PipelineState;
+
+/// Final summary of a run, assembled from each actor's persistent state
+/// after the graph has stopped. Every field survives actor restarts,
+/// so these numbers are the authoritative totals for the run.
+/// Saved as JSON with `--report-file`, for `compare-runs`; fields missing from an older file read as zero.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct RunReport {
+    pub(crate) run_id: String,
+    /// Version, commit, profile and build time of the binary that ran.
+    pub(crate) build: String,
+    /// Wall time from startup until the graph stopped.
+    pub(crate) duration_ms: u64,
+    pub(crate) beats_sent: u64,
+    /// The beat count a `--restore-from` file or a `--state-dir` checkpoint resumed the heartbeat at.
+    pub(crate) beats_resumed_from: Option<u64>,
+    /// Beats the heartbeat left out or sent twice under `--heartbeat-fault`, and the gaps and
+    /// repeats the worker saw in the beat numbers.
+    pub(crate) beats_skipped: u64,
+    pub(crate) beats_doubled: u64,
+    /// Wall-clock jumps the heartbeat saw and re-anchored beat times across.
+    pub(crate) clock_jumps: u64,
+    pub(crate) worker_beats_missed: u64,
+    pub(crate) worker_beats_repeated: u64,
+    /// Beats the workers of a `--workers` pool spent with none of their values waiting.
+    pub(crate) worker_beats_idle: u64,
+    pub(crate) generated: u64,
+    /// SubmitValue calls refused with RESOURCE_EXHAUSTED when the gRPC source fed the pipeline.
+    pub(crate) ingest_refused: u64,
+    /// MQTT payloads dropped as non-numeric, and broker sessions re-established, when the MQTT source fed the pipeline.
+    pub(crate) ingest_invalid: u64,
+    pub(crate) ingest_reconnects: u64,
+    /// Values refused (gRPC) or held back (MQTT) because their client was over `--ingest-rate`.
+    pub(crate) ingest_over_rate: u64,
+    /// With `--queue-dir`, the queue sequence the pipeline started reading at.
+    pub(crate) queue_read_from: Option<u64>,
+    /// Values dropped or refused, and values held back, for being over their tenant's `--tenant-quota`,
+    /// and the most the generator held at once.
+    pub(crate) quota_rejected: u64,
+    pub(crate) quota_throttled: u64,
+    pub(crate) quota_held_max: u64,
+    /// Generator values in flight to the logger under `--flow-target-inflight`, averaged over
+    /// the logger's feedback samples and at their deepest, and the rate the throttle ended at.
+    pub(crate) inflight_avg: f64,
+    pub(crate) inflight_max: u64,
+    pub(crate) throttle_rate: f64,
+    pub(crate) worker_values_processed: u64,
+    pub(crate) worker_messages_sent: u64,
+    pub(crate) worker_values_rejected: u64,
+    pub(crate) worker_batches: u64,
+    /// Values the worker dropped as showstoppers, and checkpoint barriers it forwarded.
+    pub(crate) worker_showstoppers: u64,
+    pub(crate) worker_barriers: u64,
+    /// Times the worker waited past `--worker-stall-ms` for its inputs.
+    pub(crate) worker_stalls: u64,
+    /// Values the worker picked up past `--processing-deadline-ms`, by the action taken on them.
+    pub(crate) worker_overdue_fast_path: u64,
+    pub(crate) worker_overdue_dead_lettered: u64,
+    pub(crate) worker_overdue_logged: u64,
+    /// Lookups the enricher answered for the worker within `--enricher-timeout-ms`, and those it did not.
+    pub(crate) worker_lookups_answered: u64,
+    pub(crate) worker_lookups_timed_out: u64,
+    /// With `--workers`, values each worker of the pool processed, in shard order, and the re-sends
+    /// the fan-in dropped and the messages it could only send on out of order.
+    pub(crate) pool_processed: Vec<u64>,
+    pub(crate) fan_in_duplicates: u64,
+    pub(crate) fan_in_out_of_order: u64,
+    pub(crate) logged: u64,
+    pub(crate) fizz: u64,
+    pub(crate) buzz: u64,
+    pub(crate) fizzbuzz: u64,
+    pub(crate) values: u64,
+    /// Records classified by `--rule` rules rather than as Fizz, Buzz, FizzBuzz or a value.
+    pub(crate) rule_matched: u64,
+    pub(crate) rejected: u64,
+    pub(crate) logger_showstoppers: u64,
+    /// Re-sent worker messages the logger recognized by sequence number and dropped.
+    pub(crate) logger_duplicates: u64,
+    /// Records counted but not written because their kind was over its `--log-rate-limit`.
+    pub(crate) logger_rate_limited: u64,
+    /// Records committed to the WAL and how many of those the sink has accepted.
+    pub(crate) wal_written: u64,
+    pub(crate) sink_delivered: u64,
+    pub(crate) sink_replayed: u64,
+    pub(crate) sink_failures: u64,
+    /// The `--fsync` policy, the syncs it made, and the committed records a power failure could
+    /// have taken: the most at any one time, and those still unsynced when the logger stopped.
+    pub(crate) fsync: String,
+    pub(crate) fsync_syncs: u64,
+    pub(crate) fsync_at_risk_max: u64,
+    pub(crate) fsync_at_risk: u64,
+    /// Times the logger held the heartbeat and generator for a sink outage.
+    pub(crate) sink_outage_pauses: u64,
+    /// Operator pauses of the logger's sink writes, and the deepest its channel got during one.
+    pub(crate) logger_pauses: u64,
+    pub(crate) logger_paused_backlog_max: u64,
+    /// Messages the logger wrote after an operator `drain` reached it; None when the run was not drained.
+    pub(crate) drain_flushed: Option<u64>,
+    /// Showstoppers the dead-letter actor received from the worker and the logger, and how many
+    /// of them reached the `--dead-letter-file`.
+    pub(crate) dead_letters: u64,
+    pub(crate) dead_letters_persisted: u64,
+    /// Messages logged per tenant, by tenant name, for values a source labeled.
+    pub(crate) tenants: BTreeMap<String, u64>,
+    /// Items left in the worker's and the logger's input when their drain deadlines passed.
+    pub(crate) worker_residue: u64,
+    pub(crate) logger_residue: u64,
+    /// Items still in a channel once the graph stopped, by channel, and the channels a stage
+    /// that never stopped still held so they could not be read.
+    pub(crate) channel_residue: Vec<ChannelResidue>,
+    pub(crate) channels_unread: u64,
+    /// Latency SLO results: messages late out of measured, and windows failed out of evaluated.
+    pub(crate) slo_measured: u64,
+    pub(crate) slo_late: u64,
+    pub(crate) slo_windows: u64,
+    pub(crate) slo_windows_failed: u64,
+    /// Generation-to-commit latency of logged messages, in microseconds, to within 12.5%.
+    pub(crate) latency_p50_us: u64,
+    pub(crate) latency_p99_us: u64,
+    pub(crate) latency_max_us: u64,
+    /// The same by the `--priority` of the messages, when any carried one.
+    pub(crate) latency_by_priority: BTreeMap<u8, PriorityLatency>,
+    /// Event-time windows the logger closed under `--event-window-ms`, and messages that arrived after theirs had closed.
+    pub(crate) event_windows: u64,
+    pub(crate) event_late: u64,
+    /// Coordinated checkpoints completed, how many were torn, and the barrier of the last one.
+    pub(crate) checkpoints: u64,
+    pub(crate) checkpoints_torn: u64,
+    pub(crate) last_checkpoint_barrier: Option<u64>,
+    /// What the `--state-dir` checkpoint this process resumed from had already counted.
+    pub(crate) resumed: Option<Carried>,
+    /// Deepest each sampled channel got, beside its capacity.
+    pub(crate) channel_high_water: Vec<ChannelHighWater>,
+    /// Bytes written to the generated and classified channels, the most waiting in each at once,
+    /// and the fastest each was written, in bytes per second.
+    pub(crate) generated_bytes: u64,
+    pub(crate) classified_bytes: u64,
+    pub(crate) generated_in_flight_bytes_max: u64,
+    pub(crate) classified_in_flight_bytes_max: u64,
+    pub(crate) generated_bytes_per_sec_max: u64,
+    pub(crate) classified_bytes_per_sec_max: u64,
+    /// How the worker's and the logger's loop iterations split between working and waiting.
+    pub(crate) worker_time: StageTimeSummary,
+    pub(crate) logger_time: StageTimeSummary,
+    /// Entries the bounded tables dropped to stay under their caps: network clients tracked for
+    /// admission, values waiting on the worker's acknowledgement, and tenant names past the registry's limit.
+    pub(crate) clients_evicted: u64,
+    pub(crate) acks_evicted: u64,
+    pub(crate) tenants_overflowed: u64,
+    /// How the generator and the logger used their thread, shared with `--troupe`.
+    pub(crate) generator_fairness: Fairness,
+    pub(crate) logger_fairness: Fairness,
+    /// Records the loopback verifier matched to a generated value, values it never got back,
+    /// and records no generated value explained, under `--sink loopback`.
+    pub(crate) loopback_matched: u64,
+    pub(crate) loopback_missing: u64,
+    pub(crate) loopback_mismatched: u64,
+    /// Edge checks performed by the reconciler and how many failed.
+    pub(crate) reconcile_checks: u64,
+    pub(crate) reconcile_inconsistencies: u64,
+    /// Sudden throughput or restart-rate changes the reconciler flagged under `--anomaly-sigma`.
+    pub(crate) anomalies: u64,
+    /// Most messages the reconciler saw between generation and logger commit, and how often
+    /// that rose past `--inflight-alert`.
+    pub(crate) pipeline_in_flight_max: u64,
+    pub(crate) in_flight_alerts: u64,
+    /// Times a channel stayed past its `--fill-alert` threshold for the hold.
+    pub(crate) fill_alerts: u64,
+    /// Whether the `--failure-budget` was used up, turning the demonstration failures off.
+    pub(crate) failure_budget_spent: bool,
+    /// Faults injected into the heartbeat, generator, worker and logger, `--fail-at` panics included.
+    pub(crate) faults_injected: FaultCounts,
+    /// Number of starts per actor; one means the actor never restarted.
+    pub(crate) heartbeat_starts: u64,
+    pub(crate) worker_starts: u64,
+    pub(crate) logger_starts: u64,
+    /// Restarts per actor, broken down by what ended the instance before.
+    pub(crate) heartbeat_restarts: RestartCauses,
+    pub(crate) generator_restarts: RestartCauses,
+    pub(crate) worker_restarts: RestartCauses,
+    pub(crate) logger_restarts: RestartCauses,
+    /// Cold and warm starts per actor, with how long each kind took to get its first message out.
+    pub(crate) heartbeat_start_times: StartSummary,
+    pub(crate) generator_start_times: StartSummary,
+    pub(crate) worker_start_times: StartSummary,
+    pub(crate) logger_start_times: StartSummary,
+    /// Stages whose final state could not be read, and so show as zeros.
+    pub(crate) unread: Vec<String>,
+}
+
+/// Counters a `--state-dir` checkpoint carried into the run. The process that counted them
+/// accounted for those values, so the conservation check leaves them out.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Carried {
+    pub(crate) barrier: u64,
+    pub(crate) generated: u64,
+    pub(crate) barriers: u64,
+    pub(crate) logged: u64,
+    pub(crate) showstoppers: u64,
+}
+
+impl Carried {
+    pub(crate) fn from_checkpoint(checkpoint: &Checkpoint) -> Self {
+        Carried {
+            barrier: checkpoint.barrier,
+            generated: checkpoint.worker.values_processed,
+            barriers: checkpoint.worker.seq.saturating_sub(checkpoint.worker.messages_sent),
+            logged: checkpoint.messages_logged,
+            showstoppers: checkpoint.showstoppers_dropped,
+        }
+    }
+}
+
+/// Generation-to-commit latency of the messages of one priority. A max that stays bounded for
+/// the lowest priority under load is the aging at work.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct PriorityLatency {
+    pub(crate) logged: u64,
+    pub(crate) p50_us: u64,
+    pub(crate) p99_us: u64,
+    pub(crate) max_us: u64,
+}
+
+impl fmt::Display for PriorityLatency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} logged, p50/p99/max {}/{}/{}us", self.logged, self.p50_us, self.p99_us, self.max_us)
+    }
+}
+
+/// The most items one channel held at a sample over the run, for sizing its capacity. With
+/// `--workers` it is the deepest of the pool's channels, each of `capacity`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct ChannelHighWater {
+    pub(crate) channel: String,
+    pub(crate) items: u64,
+    pub(crate) capacity: u64,
+}
+
+impl fmt::Display for ChannelHighWater {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}/{}", self.channel, self.items, self.capacity)
+    }
+}
+
+/// A run whose final counters break the conservation promise: some generated values are
+/// unaccounted for, or more came out than went in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ConservationError {
+    pub(crate) generated: u64,
+    pub(crate) barriers: u64,
+    pub(crate) logged: u64,
+    pub(crate) rejected: u64,
+    pub(crate) showstoppers: u64,
+    pub(crate) checkpoints: u64,
+    pub(crate) undrained: u64,
+}
+
+impl fmt::Display for ConservationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "conservation check failed: generated {} + barriers {} != logged {} + rejected {} + showstoppers {} + checkpoints {} + undrained {}",
+            self.generated, self.barriers, self.logged, self.rejected, self.showstoppers, self.checkpoints, self.undrained
+        )
+    }
+}
+
+impl Error for ConservationError {}
+
+impl RunReport {
+    /// Reads the final state of every actor. Actors that never ran report zeros.
+    pub(crate) fn collect(run_id: &str, state: &PipelineState) -> Self {
+        // The headline counters come from the typed stats, which also waits out actors still letting go.
+        let stats = state.stats();
+        let mut report = RunReport {
+            run_id: run_id.to_string(),
+            build: BUILD.summary(),
+            beats_sent: stats.beats_sent,
+            generated: stats.generated,
+            worker_values_processed: stats.processed,
+            worker_messages_sent: stats.classified,
+            worker_values_rejected: stats.rejected,
+            fizz: stats.logged.fizz,
+            buzz: stats.logged.buzz,
+            fizzbuzz: stats.logged.fizzbuzz,
+            values: stats.logged.value,
+            rule_matched: stats.logged.rule,
+            rejected: stats.rejects_logged,
+            logger_duplicates: stats.duplicates_suppressed,
+            tenants_overflowed: tenant::overflowed(),
+            resumed: crate::persistence::restored().map(|snapshot| Carried::from_checkpoint(&snapshot.checkpoint)),
+            ..Default::default()
+        };
+        (report.channel_residue, report.channels_unread) = state.channels.residue();
+        let mut source_read = false;
+        if let Some(s) = state.heartbeat.try_lock_sync() {
+            report.beats_resumed_from = s.resumed_from;
+            report.beats_skipped = s.beats_skipped;
+            report.beats_doubled = s.beats_doubled;
+            report.clock_jumps = s.clock.jumps;
+            report.heartbeat_starts = s.restart_count;
+            report.heartbeat_restarts = s.restarts;
+            report.heartbeat_start_times = s.starts.summary();
+            report.faults_injected.add(s.faults.injected);
+        }
+        if let Some(s) = state.generator.try_lock_sync() {
+            source_read = true;
+            report.generator_restarts = s.restarts;
+            report.generator_start_times = s.starts.summary();
+            report.faults_injected.add(s.faults.injected);
+            report.quota_rejected = s.quotas.rejected;
+            report.quota_throttled = s.quotas.throttled;
+            report.quota_held_max = s.throttled.len_max as u64;
+            report.generator_fairness = s.fairness;
+            if let Some(throttle) = s.throttle {
+                report.inflight_avg = throttle.inflight_avg();
+                report.inflight_max = throttle.inflight_max;
+                report.throttle_rate = throttle.rate;
+            }
+        }
+        if let Some(s) = state.queue_source.try_lock_sync() {
+            source_read = true;
+            report.queue_read_from = Some(s.started_at.next_seq);
+        }
+        if let Some(s) = state.tcp_source.try_lock_sync() {
+            source_read = true;
+            report.ingest_invalid = s.invalid;
+            report.quota_rejected = s.quotas.rejected;
+            report.quota_throttled = s.quotas.throttled;
+        }
+        #[cfg(feature = "grpc")]
+        if let Some(s) = state.grpc_source.try_lock_sync() {
+            source_read = true;
+            report.ingest_refused = s.refused;
+            report.ingest_over_rate = s.over_rate;
+            report.quota_rejected = s.quotas.rejected;
+            report.clients_evicted = s.admission.evicted;
+            report.acks_evicted = s.awaiting.evicted;
+        }
+        #[cfg(feature = "mqtt")]
+        if let Some(s) = state.mqtt_source.try_lock_sync() {
+            source_read = true;
+            report.ingest_invalid = s.invalid;
+            report.ingest_reconnects = s.reconnects;
+            report.ingest_over_rate = s.over_rate;
+            report.quota_rejected = s.quotas.rejected;
+            report.quota_throttled = s.quotas.throttled;
+            report.clients_evicted = s.admission.evicted;
+            report.acks_evicted = s.awaiting.evicted;
+        }
+        // A --workers pool reports as one worker, its counts added up.
+        let (mut worker_time, mut worker_start_times) = (StageTime::default(), StartTimes::default());
+        for worker in state.workers() {
+            let Some(s) = worker.try_lock_sync() else {
+                if !report.unread.iter().any(|name| name == "worker") {
+                    report.unread.push("worker".to_string());
+                }
+                continue;
+            };
+            report.worker_batches += s.batches_flushed;
+            report.worker_showstoppers += s.showstoppers_dropped;
+            report.worker_barriers += s.barriers_sent;
+            report.worker_stalls += s.stalls;
+            report.worker_beats_missed += s.beats_missed;
+            report.worker_beats_repeated += s.beats_repeated;
+            report.worker_beats_idle += s.beats_idle;
+            report.worker_overdue_fast_path += s.overdue.fast_path;
+            report.worker_overdue_dead_lettered += s.overdue.dead_lettered;
+            report.worker_overdue_logged += s.overdue.logged;
+            report.worker_lookups_answered += s.lookups_answered;
+            report.worker_lookups_timed_out += s.lookups_timed_out;
+            report.worker_residue += s.residue;
+            worker_time.merge(&s.time);
+            report.worker_starts += s.restart_count;
+            report.worker_restarts.merge(s.restarts);
+            worker_start_times.merge(&s.starts);
+            report.faults_injected.add(s.faults.injected);
+            if !state.pool.is_empty() {
+                report.pool_processed.push(s.values_processed);
+            }
+        }
+        report.worker_time = worker_time.summary();
+        report.worker_start_times = worker_start_times.summary();
+        if !state.pool.is_empty() && let Some(s) = state.fan_in.try_lock_sync() {
+            report.fan_in_duplicates = s.duplicates;
+            report.fan_in_out_of_order = s.out_of_order;
+        }
+        if let Some(s) = state.logger.try_lock_sync() {
+            report.logged = s.messages_logged;
+            report.faults_injected.add(s.faults.injected);
+            report.logger_showstoppers = s.showstoppers_dropped;
+            report.logger_rate_limited = s.rate_limited;
+            report.wal_written = s.wal_seq;
+            report.sink_delivered = s.sink_seq;
+            report.sink_replayed = s.replayed;
+            report.sink_failures = s.sink_failures;
+            report.fsync = s.fsync.policy.to_string();
+            report.fsync_syncs = s.fsync.syncs;
+            report.fsync_at_risk_max = s.fsync.at_risk_max;
+            report.fsync_at_risk = s.fsync.at_risk;
+            report.sink_outage_pauses = s.flow_pauses;
+            report.logger_pauses = s.sink_pauses;
+            report.logger_paused_backlog_max = s.paused_backlog_max;
+            report.drain_flushed = s.drain_from.map(|from| s.messages_logged.saturating_sub(from));
+            report.tenants = s.tenant_logged.iter().map(|(&t, &logged)| (tenant::name(t), logged)).collect();
+            report.logger_residue = s.residue;
+            report.slo_measured = s.slo_measured;
+            report.slo_late = s.slo_late;
+            report.slo_windows = s.slo_windows;
+            report.slo_windows_failed = s.slo_windows_failed;
+            report.latency_p50_us = s.latency.quantile(0.5);
+            report.latency_p99_us = s.latency.quantile(0.99);
+            report.latency_max_us = s.latency.quantile(1.0);
+            if s.latency_by_priority.keys().any(|&priority| priority > 0) {
+                report.latency_by_priority = s.latency_by_priority.iter().map(|(&priority, latency)| (priority, PriorityLatency {
+                    logged: latency.count(),
+                    p50_us: latency.quantile(0.5),
+                    p99_us: latency.quantile(0.99),
+                    max_us: latency.quantile(1.0),
+                })).collect();
+            }
+            report.logger_time = s.time.summary();
+            report.logger_fairness = s.fairness;
+            report.event_windows = s.event_windows.closed;
+            report.event_late = s.event_windows.late;
+            report.checkpoints = s.checkpoints_completed;
+            report.checkpoints_torn = s.checkpoints_torn;
+            report.last_checkpoint_barrier = s.last_checkpoint.map(|c| c.barrier);
+            report.logger_starts = s.restart_count;
+            report.logger_restarts = s.restarts;
+            report.logger_start_times = s.starts.summary();
+        } else {
+            report.unread.push("logger".to_string());
+        }
+        if !source_read {
+            report.unread.insert(0, "source".to_string());
+        }
+        if let Some(s) = state.dead_letter.try_lock_sync() {
+            report.dead_letters = s.received;
+            report.dead_letters_persisted = s.persisted;
+        }
+        if let Some(s) = state.reconciler.try_lock_sync() {
+            report.reconcile_checks = s.checks;
+            report.reconcile_inconsistencies = s.inconsistencies;
+            report.anomalies = s.anomalies;
+            report.pipeline_in_flight_max = s.in_flight_max;
+            report.in_flight_alerts = s.in_flight_alerts;
+            report.fill_alerts = s.fill_alerts;
+            report.failure_budget_spent = s.budget_spent;
+            // A channel never sampled, its reader not running, has no capacity to show.
+            report.channel_high_water = DEPTH_CHANNELS.iter().zip(s.depth_max)
+                .filter(|(_, depth)| depth.capacity > 0)
+                .map(|(&(channel, _, _), depth)| ChannelHighWater { channel: channel.to_string(), items: depth.items, capacity: depth.capacity })
+                .collect();
+            let [generated, classified] = s.channels;
+            (report.generated_bytes, report.classified_bytes) = (generated.produced, classified.produced);
+            (report.generated_in_flight_bytes_max, report.classified_in_flight_bytes_max) = (generated.in_flight_max, classified.in_flight_max);
+            (report.generated_bytes_per_sec_max, report.classified_bytes_per_sec_max) = (generated.per_sec_max, classified.per_sec_max);
+        }
+        // The logger stops after the verifier, so the last records are checked here.
+        if let Some(mut s) = state.loopback.try_lock_sync() {
+            s.check.finish();
+            report.loopback_matched = s.check.matched;
+            report.loopback_missing = s.check.missing;
+            report.loopback_mismatched = s.check.mismatched;
+        }
+        report
+    }
+
+    /// Pass/fail summary for soak tests; a run with no measured messages has nothing to judge.
+    pub(crate) fn slo_verdict(&self) -> &'static str {
+        match (self.slo_measured, self.slo_windows_failed) {
+            (0, _) => "n/a",
+            (_, 0) => "PASS",
+            _ => "FAIL",
+        }
+    }
+
+    /// Checks the crate's core promise on a run that stopped cleanly: every generated value was
+    /// logged, rejected, dropped as a showstopper or left undrained, exactly once. Checkpoint
+    /// barriers share the worker-to-logger channel, so those the worker sent are added to the inflow
+    /// and the logger's completed checkpoints to the outflow. A run resumed from `--state-dir`
+    /// is checked on what this process counted past the checkpoint it resumed from.
+    pub(crate) fn conservation(&self) -> Result<(), ConservationError> {
+        let carried = self.resumed.unwrap_or_default();
+        let error = ConservationError {
+            generated: self.generated.saturating_sub(carried.generated),
+            barriers: self.worker_barriers.saturating_sub(carried.barriers),
+            logged: self.logged.saturating_sub(carried.logged),
+            rejected: self.rejected,
+            showstoppers: (self.worker_showstoppers + self.logger_showstoppers).saturating_sub(carried.showstoppers),
+            checkpoints: self.checkpoints,
+            undrained: self.worker_residue + self.logger_residue,
+        };
+        let inflow = error.generated + error.barriers;
+        let outflow = error.logged + error.rejected + error.showstoppers + error.checkpoints + error.undrained;
+        if inflow == outflow { Ok(()) } else { Err(error) }
+    }
+
+    /// Logged messages per second over the run, counting only this process's under `--state-dir`, or zero when the duration is unknown.
+    pub(crate) fn throughput(&self) -> f64 {
+        let logged = self.logged.saturating_sub(self.resumed.map_or(0, |carried| carried.logged));
+        if self.duration_ms == 0 { 0.0 } else { logged as f64 * 1000.0 / self.duration_ms as f64 }
+    }
+
+    /// Generated values that never reached the output, as logged or rejected records.
+    pub(crate) fn lost(&self) -> u64 {
+        let carried = self.resumed.unwrap_or_default();
+        self.generated.saturating_sub(carried.generated).saturating_sub(self.logged.saturating_sub(carried.logged) + self.rejected)
+    }
+
+    pub(crate) fn restarts(&self) -> u64 {
+        [self.heartbeat_restarts, self.generator_restarts, self.worker_restarts, self.logger_restarts]
+            .iter()
+            .map(RestartCauses::total)
+            .sum()
+    }
+
+    pub(crate) fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub(crate) fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("cannot read report {:?}: {}", path, e))?;
+        Ok(serde_json::from_str(&text).map_err(|e| format!("bad report {:?}: {}", path, e))?)
+    }
+
+    /// Writes the report to the log, one line per section.
+    pub(crate) fn log(&self) {
+        info!("Final report for run {} on build {}: duration: {}ms", self.run_id, self.build, self.duration_ms);
+        info!("Report heartbeat: beats: {} (resumed from: {:?}, skipped/doubled: {}/{}, clock jumps: {}, worker missed/repeated/idle: {}/{}/{})",
+              self.beats_sent, self.beats_resumed_from, self.beats_skipped, self.beats_doubled, self.clock_jumps,
+              self.worker_beats_missed, self.worker_beats_repeated, self.worker_beats_idle);
+        info!("Report source: generated: {} (ingest refused/invalid/reconnects/over rate: {}/{}/{}/{}; queue read from: {:?}; over quota rejected/throttled: {}/{}, held max: {}), in flight avg/max: {:.1}/{} (throttle {:.0}/s), evicted (clients/awaiting acks/tenant overflow): {}/{}/{}",
+              self.generated, self.ingest_refused, self.ingest_invalid, self.ingest_reconnects, self.ingest_over_rate, self.queue_read_from,
+              self.quota_rejected, self.quota_throttled, self.quota_held_max,
+              self.inflight_avg, self.inflight_max, self.throttle_rate, self.clients_evicted, self.acks_evicted, self.tenants_overflowed);
+        info!("Report worker: processed: {}, sent: {}, rejected: {}, batches: {}, stalls: {}, overdue (fast-path/dead-letter/log): {}/{}/{}, lookups (answered/timed out): {}/{}, pool processed: {:?} (fan-in duplicates/out of order: {}/{}), showstoppers: {}",
+              self.worker_values_processed, self.worker_messages_sent, self.worker_values_rejected, self.worker_batches, self.worker_stalls,
+              self.worker_overdue_fast_path, self.worker_overdue_dead_lettered, self.worker_overdue_logged,
+              self.worker_lookups_answered, self.worker_lookups_timed_out, self.pool_processed, self.fan_in_duplicates, self.fan_in_out_of_order,
+              self.worker_showstoppers);
+        let tenants: Vec<String> = self.tenants.iter().map(|(tenant, logged)| format!("{}: {}", tenant, logged)).collect();
+        let tenants = if tenants.is_empty() { "none".to_string() } else { tenants.join(", ") };
+        info!("Report logger: logged: {} (F:{}, B:{}, FB:{}, V:{}, R:{}; by tenant: {}), rejects logged: {}, showstoppers: {}, dead letters: {} (persisted: {}), duplicates suppressed: {}, rate-limited: {}, pauses: {} (peak backlog: {})",
+              self.logged, self.fizz, self.buzz, self.fizzbuzz, self.values, self.rule_matched, tenants, self.rejected,
+              self.logger_showstoppers, self.dead_letters, self.dead_letters_persisted, self.logger_duplicates, self.logger_rate_limited,
+              self.logger_pauses, self.logger_paused_backlog_max);
+        info!("Report WAL and sinks: wal/sink/replayed: {}/{}/{}, sink failures: {} (outage pauses: {}), fsync: {} (syncs: {}, records at risk on power failure max/at stop: {}/{})",
+              self.wal_written, self.sink_delivered, self.sink_replayed, self.sink_failures, self.sink_outage_pauses,
+              self.fsync, self.fsync_syncs, self.fsync_at_risk_max, self.fsync_at_risk);
+        info!("Report latency: SLO: {} ({} of {} late, {} of {} windows failed), p50/p99/max: {}/{}/{}us, event windows: {} (late: {})",
+              self.slo_verdict(), self.slo_late, self.slo_measured, self.slo_windows_failed, self.slo_windows,
+              self.latency_p50_us, self.latency_p99_us, self.latency_max_us, self.event_windows, self.event_late);
+        if !self.latency_by_priority.is_empty() {
+            let by_priority: Vec<String> = self.latency_by_priority.iter().rev().map(|(priority, latency)| format!("{}: {}", priority, latency)).collect();
+            info!("Report latency by priority, highest first: {}", by_priority.join("; "));
+        }
+        info!("Report conservation: undrained (worker/logger): {}/{}, checkpoints: {} (torn: {}, last barrier: {:?}, resumed from: {:?}), loopback matched/missing/mismatched: {}/{}/{}",
+              self.worker_residue, self.logger_residue, self.checkpoints, self.checkpoints_torn, self.last_checkpoint_barrier,
+              self.resumed.map(|carried| carried.barrier), self.loopback_matched, self.loopback_missing, self.loopback_mismatched);
+        info!("Report control: reconcile checks: {} (inconsistent: {}), anomalies: {}, pipeline in flight max: {} (alerts: {}), failure budget spent: {}, faults injected (panics/delays/send failures): {}/{}/{}",
+              self.reconcile_checks, self.reconcile_inconsistencies, self.anomalies, self.pipeline_in_flight_max, self.in_flight_alerts,
+              self.failure_budget_spent, self.faults_injected.panics, self.faults_injected.delays, self.faults_injected.send_failures);
+        let high_water: Vec<String> = self.channel_high_water.iter().map(ChannelHighWater::to_string).collect();
+        info!("Report channels: high-water/capacity: {}, fill alerts: {}, bytes (generated/classified): {}/{} (in flight max: {}/{}, peak rate: {}/{} B/s)",
+              if high_water.is_empty() { "none".to_string() } else { high_water.join(", ") }, self.fill_alerts, self.generated_bytes, self.classified_bytes,
+              self.generated_in_flight_bytes_max, self.classified_in_flight_bytes_max,
+              self.generated_bytes_per_sec_max, self.classified_bytes_per_sec_max);
+        info!("Report stage time: worker: {}, logger: {}, thread use (generator: {}; logger: {})",
+              self.worker_time, self.logger_time, self.generator_fairness, self.logger_fairness);
+        info!("Report restarts: starts (heartbeat/worker/logger): {}/{}/{}, by cause: heartbeat {}; generator {}; worker {}; logger {}, start times: heartbeat {}; generator {}; worker {}; logger {}",
+              self.heartbeat_starts, self.worker_starts, self.logger_starts,
+              self.heartbeat_restarts, self.generator_restarts, self.worker_restarts, self.logger_restarts,
+              self.heartbeat_start_times, self.generator_start_times, self.worker_start_times, self.logger_start_times);
+        // As a warning, so a residue stands out with the edge it was left on.
+        if !self.channel_residue.is_empty() {
+            let left: Vec<String> = self.channel_residue.iter().map(ChannelResidue::to_string).collect();
+            warn!("Items left in channels at shutdown: {}", left.join("; "));
+        }
+        if self.channels_unread > 0 {
+            warn!("{} channels still held by a running stage at shutdown, their residue unknown", self.channels_unread);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod report_tests {
+    use super::*;
+
+    #[test]
+    fn test_collect_before_any_actor_ran() {
+        let state = PipelineState {
+            heartbeat: new_state(),
+            generator: new_state(),
+            worker: new_state(),
+            logger: new_state(),
+            reconciler: new_state(),
+            loopback: new_state(),
+            dead_letter: new_state(),
+            enricher: new_state(),
+            router: new_state(),
+            fan_in: new_state(),
+            queue_source: new_state(),
+            queue_writer: new_state(),
+            tcp_source: new_state(),
+            pool: Vec::new(),
+            channels: Default::default(),
+            #[cfg(unix)]
+            control: new_state(),
+            #[cfg(feature = "grpc")]
+            grpc_source: new_state(),
+            #[cfg(feature = "mqtt")]
+            mqtt_source: new_state(),
+        };
+        let report = RunReport::collect("abc123", &state);
+        let unread = ["source", "worker", "logger"].map(String::from).to_vec();
+        assert_eq!(report, RunReport { run_id: "abc123".to_string(), build: BUILD.summary(), unread, ..Default::default() });
+        // all zeros balance, but unread stages mean the check proves nothing; main skips it then
+        assert_eq!(report.conservation(), Ok(()));
+    }
+
+    #[test]
+    fn test_conservation() {
+        // 100 values: 82 logged, 10 rejected, one dropped by each stage, 6 undrained; 4 barriers, all checkpointed
+        let balanced = RunReport {
+            generated: 100, logged: 82, rejected: 10, worker_showstoppers: 1, logger_showstoppers: 1,
+            worker_residue: 4, logger_residue: 2, worker_barriers: 4, checkpoints: 4,
+            ..Default::default()
+        };
+        assert_eq!(balanced.conservation(), Ok(()));
+
+        let lost = RunReport { logged: 81, ..balanced.clone() };
+        let error = lost.conservation().expect_err("one value unaccounted for");
+        assert_eq!((error.generated, error.logged, error.showstoppers, error.undrained), (100, 81, 2, 6));
+        assert!(error.to_string().starts_with("conservation check failed: generated 100 + barriers 4 != logged 81"));
+
+        // resumed from a checkpoint that had 40 values in: 37 logged, 2 rejected, 1 dropped, 3 barriers
+        let carried = Carried { barrier: 30, generated: 40, barriers: 3, logged: 37, showstoppers: 1 };
+        let resumed = RunReport {
+            generated: 140, logged: 119, worker_barriers: 7, logger_showstoppers: 2,
+            resumed: Some(carried), ..balanced.clone()
+        };
+        assert_eq!(resumed.conservation(), Ok(()));
+        assert_eq!(resumed.lost(), 8);
+    }
+}
@@ -0,0 +1,100 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use serde::{Deserialize, Serialize};
+use steady_state::*;
+
+/// A channel holding at most this many items has their sequence numbers listed.
+const LISTED_MAX: usize = 16;
+
+/// What one channel still held once the graph stopped. After a clean drain every channel is
+/// empty, so any of these points at the edge where items were left behind.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct ChannelResidue {
+    /// The link by its `Wiring` field name, with the lane or the pool worker where there are several.
+    pub(crate) channel: String,
+    /// The stages writing and reading it.
+    pub(crate) from: String,
+    pub(crate) to: String,
+    pub(crate) items: u64,
+    /// The items' sequence numbers, oldest first, when there are few and they carry one: the
+    /// worker's seq on its output, the value itself on the generator's, the beat on the heartbeat's.
+    pub(crate) seqs: Vec<u64>,
+}
+
+impl fmt::Display for ChannelResidue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({} -> {}): {}", self.channel, self.from, self.to, self.items)?;
+        if !self.seqs.is_empty() {
+            write!(f, " {:?}", self.seqs)?;
+        }
+        Ok(())
+    }
+}
+
+/// Counts the items in a channel and lists their numbers, None while a stage still holds it.
+type Count = Box<dyn Fn() -> Option<(u64, Vec<u64>)> + Send + Sync>;
+
+struct Probe {
+    residue: ChannelResidue,
+    count: Count,
+}
+
+/// The channels of a graph, added as its stages are built, so what they still hold can be read
+/// once it stops. Clones share the one list.
+#[derive(Clone, Default)]
+pub struct ChannelProbes(Arc<Mutex<Vec<Probe>>>);
+
+impl ChannelProbes {
+    /// Adds the channel `rx` reads, numbering its items with `seq`.
+    pub(crate) fn add<T: Send + 'static>(&self, channel: String, from: &str, to: &str, rx: SteadyRx<T>, seq: fn(&T) -> Option<u64>) {
+        let residue = ChannelResidue { channel, from: from.to_string(), to: to.to_string(), ..Default::default() };
+        let count: Count = Box::new(move || {
+            let mut rx = rx.try_lock()?;
+            let items = rx.avail_units();
+            let seqs = if items <= LISTED_MAX { rx.try_peek_iter().filter_map(seq).collect() } else { Vec::new() };
+            Some((items as u64, seqs))
+        });
+        self.probes().push(Probe { residue, count });
+    }
+
+    /// The channels still holding items, in the order they were added, and how many channels
+    /// could not be read because a stage that did not stop still has them.
+    pub(crate) fn residue(&self) -> (Vec<ChannelResidue>, u64) {
+        let (mut left, mut unread) = (Vec::new(), 0);
+        for probe in self.probes().iter() {
+            match (probe.count)() {
+                Some((0, _)) => {}
+                Some((items, seqs)) => left.push(ChannelResidue { items, seqs, ..probe.residue.clone() }),
+                None => unread += 1,
+            }
+        }
+        (left, unread)
+    }
+
+    fn probes(&self) -> std::sync::MutexGuard<'_, Vec<Probe>> {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod residue_tests {
+    use crate::timing::Envelope;
+    use super::*;
+
+    #[test]
+    fn test_residue_names_the_edge() {
+        let mut graph = GraphBuilder::for_testing().build(crate::MainArg::default());
+        let (generated_tx, generated_rx) = graph.channel_builder().build::<Envelope<u64>>();
+        let (_empty_tx, empty_rx) = graph.channel_builder().build::<Envelope<u64>>();
+        let probes = ChannelProbes::default();
+        probes.add("generated".to_string(), "GENERATOR", "WORKER", generated_rx.clone(), |e| Some(e.payload));
+        probes.add("classified".to_string(), "WORKER", "LOGGER", empty_rx.clone(), |e| Some(e.seq));
+        generated_tx.testing_send_all(vec![Envelope::new(7u64), Envelope::new(8u64)], true);
+
+        let (left, unread) = probes.residue();
+        assert_eq!(unread, 0);
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].to_string(), "generated (GENERATOR -> WORKER): 2 [7, 8]");
+    }
+}
@@ -0,0 +1,185 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use crate::events;
+use steady_state::*;
+
+/// Why the previous instance of an actor ended and the framework started another.
+/// Returning `Ok` ends an actor for good, so a restart always follows a panic or an `Err`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RestartCause {
+    /// The instance panicked while it held its state.
+    Panic,
+    /// The instance returned an error, such as a sink or WAL failure in the logger.
+    Error,
+}
+
+/// Restarts of one actor, counted by cause.
+/// Lives in the actor's state, so the counts cover the whole run.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RestartCauses {
+    pub panics: u64,
+    pub errors: u64,
+}
+
+impl RestartCauses {
+    /// Counts one restart and logs it as a `restart` event.
+    pub fn record(&mut self, actor: &str, cause: RestartCause, starts: u64) {
+        match cause {
+            RestartCause::Panic => self.panics += 1,
+            RestartCause::Error => self.errors += 1,
+        }
+        warn!("restart actor={} cause={:?} starts={}", actor, cause, starts);
+        let cause = match cause {
+            RestartCause::Panic => "panic",
+            RestartCause::Error => "error",
+        };
+        events::emit("restart", json!({"actor": actor, "cause": cause, "starts": starts}));
+    }
+}
+
+impl RestartCauses {
+    pub fn total(&self) -> u64 {
+        self.panics + self.errors
+    }
+
+    pub fn merge(&mut self, other: RestartCauses) {
+        self.panics += other.panics;
+        self.errors += other.errors;
+    }
+}
+
+impl fmt::Display for RestartCauses {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} panic, {} error", self.panics, self.errors)
+    }
+}
+
+/// Starts of one actor, cold or warm, and how soon each got its first message out.
+/// A cold start built its state from scratch; a warm one found the state a previous
+/// instance left, so how much sooner it gets going is what keeping state buys.
+/// Lives in the actor's state, like `RestartCauses`.
+#[derive(Clone, Debug, Default)]
+pub struct StartTimes {
+    cold: u64,
+    warm: u64,
+    cold_first_message: Option<Duration>,
+    warm_first_message_total: Duration,
+    warm_first_message_max: Option<Duration>,
+    warm_measured: u64,
+    /// The start still waiting for its first message: when it began, whether it was warm,
+    /// and the actor's message count at that point.
+    pending: Option<(Instant, bool, u64)>,
+}
+
+impl StartTimes {
+    /// Counts a start, with the actor's running count of messages out.
+    pub fn begin(&mut self, actor: &str, warm: bool, sent: u64) {
+        if warm { self.warm += 1 } else { self.cold += 1 }
+        info!("start actor={} kind={}", actor, if warm { "warm" } else { "cold" });
+        self.pending = Some((Instant::now(), warm, sent));
+    }
+
+    /// Called on every pass with the actor's running count; the first pass it has moved
+    /// since the start records that start's time to its first message.
+    pub fn progress(&mut self, actor: &str, sent: u64) {
+        let Some((since, warm, at_start)) = self.pending else {
+            return;
+        };
+        if sent == at_start {
+            return;
+        }
+        self.pending = None;
+        let took = since.elapsed();
+        if warm {
+            self.warm_first_message_total += took;
+            self.warm_first_message_max = self.warm_first_message_max.max(Some(took));
+            self.warm_measured += 1;
+        } else {
+            self.cold_first_message = Some(took);
+        }
+        info!("first-message actor={} kind={} after_ms={}", actor, if warm { "warm" } else { "cold" }, took.as_millis());
+    }
+
+    /// Adds the starts of another actor, to summarize a `--workers` pool as one worker.
+    /// The first message of the slowest cold start stands for them all.
+    pub fn merge(&mut self, other: &StartTimes) {
+        self.cold += other.cold;
+        self.warm += other.warm;
+        self.cold_first_message = self.cold_first_message.max(other.cold_first_message);
+        self.warm_first_message_total += other.warm_first_message_total;
+        self.warm_first_message_max = self.warm_first_message_max.max(other.warm_first_message_max);
+        self.warm_measured += other.warm_measured;
+    }
+
+    pub fn summary(&self) -> StartSummary {
+        StartSummary {
+            cold: self.cold,
+            warm: self.warm,
+            cold_first_message_ms: self.cold_first_message.map(|d| d.as_millis() as u64),
+            warm_first_message_ms_avg: (self.warm_measured > 0)
+                .then(|| (self.warm_first_message_total / self.warm_measured as u32).as_millis() as u64),
+            warm_first_message_ms_max: self.warm_first_message_max.map(|d| d.as_millis() as u64),
+        }
+    }
+}
+
+/// One actor's starts as the final report gives them. A time is None when no start of
+/// that kind got a message out.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StartSummary {
+    pub cold: u64,
+    pub warm: u64,
+    pub cold_first_message_ms: Option<u64>,
+    pub warm_first_message_ms_avg: Option<u64>,
+    pub warm_first_message_ms_max: Option<u64>,
+}
+
+impl fmt::Display for StartSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |ms: Option<u64>| ms.map_or("-".to_string(), |ms| format!("{}ms", ms));
+        write!(
+            f, "{} cold (first message {}), {} warm (first message avg/max {}/{})",
+            self.cold, ms(self.cold_first_message_ms), self.warm, ms(self.warm_first_message_ms_avg), ms(self.warm_first_message_ms_max)
+        )
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod restart_tests {
+    use super::*;
+
+    #[test]
+    fn test_causes_are_counted_apart() {
+        let mut causes = RestartCauses::default();
+        causes.record("TEST", RestartCause::Panic, 2);
+        causes.record("TEST", RestartCause::Error, 3);
+        causes.record("TEST", RestartCause::Panic, 4);
+        assert_eq!(causes, RestartCauses { panics: 2, errors: 1 });
+        assert_eq!(causes.to_string(), "2 panic, 1 error");
+    }
+
+    #[test]
+    fn test_starts_time_their_first_message() {
+        let mut starts = StartTimes::default();
+        assert_eq!(starts.summary().to_string(), "0 cold (first message -), 0 warm (first message avg/max -/-)");
+        starts.begin("TEST", false, 0);
+        starts.progress("TEST", 0);
+        assert_eq!(starts.summary().cold_first_message_ms, None);
+        starts.progress("TEST", 2);
+        assert!(starts.summary().cold_first_message_ms.is_some());
+
+        // a warm start counts from wherever the previous instance left off
+        starts.begin("TEST", true, 5);
+        std::thread::sleep(Duration::from_millis(20));
+        starts.progress("TEST", 5);
+        starts.progress("TEST", 6);
+        starts.progress("TEST", 7);
+        starts.begin("TEST", true, 7);
+        let summary = starts.summary();
+        assert_eq!((summary.cold, summary.warm), (1, 2));
+        assert!(summary.warm_first_message_ms_max >= Some(20), "{:?}", summary);
+        assert_eq!(summary.warm_first_message_ms_avg, summary.warm_first_message_ms_max);
+    }
+}
@@ -0,0 +1,41 @@
+use std::io;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::persistence;
+
+/// Where the heartbeat left off, kept in the `--restore-from` file after every beat so a later
+/// process started with the same file carries on from there instead of from beat zero.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ResumePoint {
+    /// Beats counted so far, which is also the number of the next beat.
+    pub(crate) count: u64,
+    /// The `--beats` the run was first started with; a resumed run still stops there.
+    pub(crate) beats: u64,
+}
+
+/// Reads the resume point, None when the file does not exist yet, as on a first run.
+pub(crate) fn load(path: &Path) -> io::Result<Option<ResumePoint>> {
+    persistence::load(path)
+}
+
+/// Replaces the resume point atomically, so a process killed mid-write leaves the previous point.
+pub(crate) fn save(path: &Path, point: ResumePoint) -> io::Result<()> {
+    persistence::save(path, &point)
+}
+
+#[cfg(test)]
+pub(crate) mod resume_tests {
+    use super::*;
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("robust-resume-{}.json", crate::arg::new_run_id()));
+        assert_eq!(load(&path).expect("missing is fine"), None);
+        save(&path, ResumePoint { count: 5, beats: 7 }).expect("save");
+        save(&path, ResumePoint { count: 6, beats: 7 }).expect("save");
+        assert_eq!(load(&path).expect("load"), Some(ResumePoint { count: 6, beats: 7 }));
+        std::fs::write(&path, "{\"count\": 6").expect("write");
+        assert!(load(&path).is_err());
+        std::fs::remove_file(&path).expect("remove");
+    }
+}
@@ -0,0 +1,166 @@
+use std::path::PathBuf;
+use serde::Deserialize;
+use crate::actor::worker::FizzBuzzMessage;
+use crate::MainArg;
+
+/// Most rules one run may have: a match is carried as one bit per rule.
+pub(crate) const MAX_RULES: usize = 16;
+
+/// One divisor and the label its multiples get, e.g. `7=Bazz`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct Rule {
+    pub(crate) divisor: u64,
+    pub(crate) label: String,
+}
+
+impl Rule {
+    fn checked(self) -> Result<Self, String> {
+        if self.divisor == 0 {
+            return Err(format!("divisor of '{}' must be at least 1", self.label));
+        }
+        // Records read `Msg <label> (...)`, which the loopback check parses back.
+        if self.label.is_empty() || self.label.contains(|c: char| c.is_whitespace() || c == '(' || c == ')') {
+            return Err(format!("label '{}' must be a single word without parentheses", self.label));
+        }
+        Ok(self)
+    }
+}
+
+/// Parses `DIVISOR=LABEL`, e.g. `7=Bazz`.
+pub(crate) fn parse_rule(text: &str) -> Result<Rule, String> {
+    let (divisor, label) = text
+        .split_once('=')
+        .ok_or_else(|| format!("expected DIVISOR=LABEL, got '{}'", text))?;
+    let divisor = divisor.trim().parse().map_err(|e| format!("bad divisor in '{}': {}", text, e))?;
+    Rule { divisor, label: label.trim().to_string() }.checked()
+}
+
+/// The rules of a `--rules-file`, read when the arguments are parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct RulesFile {
+    pub(crate) path: PathBuf,
+    pub(crate) rules: Vec<Rule>,
+}
+
+#[derive(Deserialize)]
+struct RulesToml {
+    #[serde(default)]
+    rule: Vec<Rule>,
+}
+
+/// Reads a rules file: a TOML array of `[[rule]]` tables, each with a `divisor` and a `label`.
+pub(crate) fn parse_rules_file(path: &str) -> Result<RulesFile, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("cannot read rules file {:?}: {}", path, e))?;
+    let parsed: RulesToml = basic_toml::from_str(&text).map_err(|e| format!("{}: {}", path, e))?;
+    let rules = parsed.rule.into_iter().map(Rule::checked).collect::<Result<_, _>>().map_err(|e| format!("{}: {}", path, e))?;
+    Ok(RulesFile { path: PathBuf::from(path), rules })
+}
+
+/// The rules a value matched, one bit per rule in rule order.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RuleMatches(pub(crate) u16);
+
+impl RuleMatches {
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    fn contains(self, rule: usize) -> bool {
+        self.0 & (1 << rule) != 0
+    }
+}
+
+/// The ordered rules the worker classifies by. Empty means the built-in 3 => Fizz and
+/// 5 => Buzz, which keep their own message variants; any `--rule` or `--rules-file` replaces
+/// them all. A value gets the labels of every rule it matches, joined in rule order, so with
+/// 3 => Fizz, 5 => Buzz and 7 => Bazz, 105 is `FizzBuzzBazz`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    pub(crate) fn new(rules: Vec<Rule>) -> Self {
+        debug_assert!(rules.len() <= MAX_RULES, "preflight caps the rules at {}", MAX_RULES);
+        RuleSet { rules }
+    }
+
+    /// The rules file's rules first, then each `--rule` in the order given.
+    pub(crate) fn from_args(args: &MainArg) -> Self {
+        RuleSet::new(args.rules_file.iter().flat_map(|file| file.rules.iter()).chain(args.rule.iter()).cloned().collect())
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn matches(&self, value: u64) -> RuleMatches {
+        RuleMatches(self.rules.iter().enumerate()
+            .filter(|(_, rule)| value.is_multiple_of(rule.divisor))
+            .fold(0, |bits, (i, _)| bits | 1 << i))
+    }
+
+    pub fn classify(&self, value: u64) -> FizzBuzzMessage {
+        if self.rules.is_empty() {
+            return FizzBuzzMessage::new(value);
+        }
+        let matches = self.matches(value);
+        if matches.is_empty() { FizzBuzzMessage::Value(value) } else { FizzBuzzMessage::Matched(matches) }
+    }
+
+    /// The labels of the matched rules, joined in rule order.
+    pub fn label(&self, matches: RuleMatches) -> String {
+        self.rules.iter().enumerate()
+            .filter(|&(i, _)| matches.contains(i))
+            .map(|(_, rule)| rule.label.as_str())
+            .collect()
+    }
+
+    /// How a message reads in a record: its label when rules matched, its variant otherwise.
+    pub fn name(&self, message: FizzBuzzMessage) -> String {
+        match message {
+            FizzBuzzMessage::Matched(matches) => self.label(matches),
+            other => format!("{:?}", other),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod rules_tests {
+    use super::*;
+
+    fn rules(specs: &[&str]) -> RuleSet {
+        RuleSet::new(specs.iter().map(|spec| parse_rule(spec).expect("valid rule")).collect())
+    }
+
+    #[test]
+    fn test_rules_combine_in_order() {
+        let set = rules(&["3=Fizz", "5=Buzz", "7=Bazz"]);
+        assert_eq!(set.classify(1), FizzBuzzMessage::Value(1));
+        let name = |value| set.name(set.classify(value));
+        assert_eq!([name(3), name(5), name(7), name(15), name(21), name(35), name(105)],
+                   ["Fizz", "Buzz", "Bazz", "FizzBuzz", "FizzBazz", "BuzzBazz", "FizzBuzzBazz"]);
+        // the order of the rules, not the size of the divisors, orders the labels
+        let set = rules(&["7=Bazz", "2=Even"]);
+        assert_eq!(set.name(set.classify(14)), "BazzEven");
+        // no rules keeps the built-in variants
+        assert_eq!(RuleSet::default().classify(15), FizzBuzzMessage::FizzBuzz);
+        assert_eq!(RuleSet::default().name(FizzBuzzMessage::Value(4)), "Value(4)");
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        assert_eq!(parse_rule(" 7 = Bazz"), Ok(Rule { divisor: 7, label: "Bazz".to_string() }));
+        assert!(parse_rule("Bazz").is_err());
+        assert!(parse_rule("0=Never").is_err());
+        assert!(parse_rule("7=Two words").is_err());
+
+        let path = std::env::temp_dir().join(format!("robust-rules-{}.toml", crate::arg::new_run_id()));
+        std::fs::write(&path, "[[rule]]\ndivisor = 3\nlabel = \"Fizz\"\n\n[[rule]]\ndivisor = 7\nlabel = \"Bazz\"\n").expect("write");
+        let file = parse_rules_file(path.to_str().expect("utf-8")).expect("valid file");
+        assert_eq!(RuleSet::new(file.rules).name(FizzBuzzMessage::Matched(RuleMatches(0b11))), "FizzBazz");
+        std::fs::write(&path, "[[rule]]\ndivisor = 0\nlabel = \"Never\"\n").expect("write");
+        assert!(parse_rules_file(path.to_str().expect("utf-8")).expect_err("zero divisor").contains("at least 1"));
+        std::fs::remove_file(&path).expect("remove");
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use steady_state::*;
+use serde_json::json;
+use crate::events;
+use crate::report::RunReport;
+use crate::PipelineState;
+
+/// A second Ctrl-C within this long of the first aborts instead of waiting for the drain.
+pub(crate) const ABORT_WINDOW: Duration = Duration::from_secs(3);
+
+/// Exit status for a run cut short by SIGINT, as shells report it.
+const ABORT_EXIT_CODE: i32 = 130;
+
+/// Set by the first Ctrl-C, or by a stage giving up; the heartbeat checks it every beat, and every
+/// poll while its channel is full, and stops the graph.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// The run whose report an abort writes, registered once the graph is built.
+static RUN: OnceLock<(String, PipelineState)> = OnceLock::new();
+
+/// True once Ctrl-C, or a stage giving up, has asked for a graceful stop.
+pub(crate) fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Asks for the same graceful stop as a first Ctrl-C, from inside the pipeline.
+pub(crate) fn request_stop() {
+    if !INTERRUPTED.swap(true, Ordering::SeqCst) {
+        events::emit("shutdown", json!({"phase": "requested", "reason": "stage_gave_up"}));
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Interrupt {
+    Drain,
+    Abort,
+}
+
+/// Decides what a Ctrl-C at `now` means. A press outside the window of the one
+/// before it starts a new window, so a slow second press never aborts by surprise.
+fn classify(first: &mut Option<Instant>, now: Instant) -> Interrupt {
+    match *first {
+        Some(at) if now.duration_since(at) < ABORT_WINDOW => Interrupt::Abort,
+        _ => {
+            *first = Some(now);
+            Interrupt::Drain
+        }
+    }
+}
+
+/// Installs the Ctrl-C handler. The first press drains the pipeline like a normal
+/// stop. A second press within the window logs what can be read of the final
+/// report without waiting, then exits at once.
+/// Only one handler can exist per process, so this must run before the graph is
+/// built; steady_state's own handler, which drains on every press, then stands aside.
+pub(crate) fn install() -> Result<(), ctrlc::Error> {
+    let mut first = None;
+    ctrlc::set_handler(move || match classify(&mut first, Instant::now()) {
+        Interrupt::Drain => {
+            INTERRUPTED.store(true, Ordering::SeqCst);
+            events::emit("shutdown", json!({"phase": "requested", "reason": "interrupt"}));
+            warn!("Interrupted, draining the pipeline; press Ctrl-C again within {:?} to abort", ABORT_WINDOW);
+        }
+        Interrupt::Abort => {
+            // Stages still running hold their state, so they show as zeros here.
+            error!("Interrupted again, aborting without waiting for the drain; the report below is best effort");
+            events::emit("shutdown", json!({"phase": "aborted", "reason": "interrupt"}));
+            if let Some((run_id, state)) = RUN.get() {
+                RunReport::collect(run_id, state).log();
+            }
+            std::process::exit(ABORT_EXIT_CODE);
+        }
+    })
+}
+
+/// Registers the pipeline whose final report an abort should write.
+pub(crate) fn watch(run_id: &str, state: &PipelineState) {
+    let _ = RUN.set((run_id.to_string(), state.clone()));
+}
+
+#[cfg(test)]
+pub(crate) mod signals_tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_presses() {
+        let start = Instant::now();
+        let mut first = None;
+        assert_eq!(classify(&mut first, start), Interrupt::Drain);
+        assert_eq!(classify(&mut first, start + Duration::from_secs(2)), Interrupt::Abort);
+
+        // too slow: the late press opens a new window instead
+        let mut first = None;
+        assert_eq!(classify(&mut first, start), Interrupt::Drain);
+        assert_eq!(classify(&mut first, start + ABORT_WINDOW), Interrupt::Drain);
+        assert_eq!(classify(&mut first, start + ABORT_WINDOW + Duration::from_secs(1)), Interrupt::Abort);
+    }
+}
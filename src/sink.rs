@@ -0,0 +1,301 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use steady_state::*;
+use crate::loopback::{LoopbackQueue, LoopbackSink};
+use crate::MainArg;
+
+/// Where the logger delivers its classified records.
+/// A failed write leaves the record undelivered; the logger decides whether to
+/// hold it in the channel or replay it later from the WAL.
+pub(crate) trait Sink: Send {
+    fn write_record(&mut self, record: &str) -> io::Result<()>;
+
+    /// True when the sink throws records away, letting the logger skip formatting them.
+    fn discards(&self) -> bool {
+        false
+    }
+
+    /// True for sinks a person reads as it scrolls by, the only ones `--log-rate-limit` thins out.
+    fn is_console(&self) -> bool {
+        false
+    }
+
+    /// Forces the records written so far onto disk, for sinks that write a file.
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Sink selected on the command line with `--sink`.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub(crate) enum SinkSpec {
+    /// Records go to the application log, the original behavior.
+    #[default]
+    Log,
+    /// Bare records go to stdout, one per line, while diagnostics stay on stderr,
+    /// so the output can feed a shell pipeline.
+    Stdout,
+    /// Records are appended to a file, one per line.
+    File(PathBuf),
+    /// Records are counted but discarded, for benchmarking the pipeline itself.
+    Null,
+    /// Records are published to an MQTT topic.
+    #[cfg(feature = "mqtt")]
+    Mqtt(crate::mqtt::MqttEndpoint),
+    /// Records go back to the loopback verifier, which checks them against what the generator emitted.
+    Loopback(LoopbackQueue),
+}
+
+impl SinkSpec {
+    /// The kind of sink, as `--sink-failure` names it.
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            SinkSpec::Log => "log",
+            SinkSpec::Stdout => "stdout",
+            SinkSpec::File(_) => "file",
+            SinkSpec::Null => "null",
+            #[cfg(feature = "mqtt")]
+            SinkSpec::Mqtt(_) => "mqtt",
+            SinkSpec::Loopback(_) => "loopback",
+        }
+    }
+
+    /// Opens the sink. Called on every logger start so a restart gets a fresh handle.
+    pub(crate) fn open(&self, _args: &MainArg) -> io::Result<Box<dyn Sink>> {
+        match self {
+            SinkSpec::Log => Ok(Box::new(LogSink)),
+            SinkSpec::Stdout => Ok(Box::new(StdoutSink)),
+            SinkSpec::File(path) => Ok(Box::new(FileSink::open(path.clone())?)),
+            SinkSpec::Null => Ok(Box::new(NullSink)),
+            #[cfg(feature = "mqtt")]
+            SinkSpec::Mqtt(endpoint) => {
+                let client_id = format!("{}-sink", _args.mqtt_client_id);
+                Ok(Box::new(crate::mqtt::MqttSink::open(endpoint, &client_id, _args)?))
+            }
+            SinkSpec::Loopback(queue) => Ok(Box::new(LoopbackSink(queue.clone()))),
+        }
+    }
+}
+
+/// Parses `--sink log`, `--sink stdout`, `--sink null`, `--sink loopback` or `--sink file:<path>`, and
+/// `--sink mqtt:<host:port/topic>` when built with the `mqtt` feature.
+/// `none` and `/dev/null`, bare or as a file path, mean the null sink so nothing is formatted for it.
+pub(crate) fn parse_sink(text: &str) -> Result<SinkSpec, String> {
+    #[cfg(feature = "mqtt")]
+    if let Some(endpoint) = text.strip_prefix("mqtt:") {
+        return crate::mqtt::parse_endpoint(endpoint).map(SinkSpec::Mqtt);
+    }
+    match text {
+        "log" => Ok(SinkSpec::Log),
+        "stdout" => Ok(SinkSpec::Stdout),
+        "null" | "none" | "/dev/null" | "file:/dev/null" => Ok(SinkSpec::Null),
+        "loopback" => Ok(SinkSpec::Loopback(LoopbackQueue::default())),
+        _ => match text.strip_prefix("file:") {
+            Some(path) if !path.is_empty() => Ok(SinkSpec::File(PathBuf::from(path))),
+            _ => Err(format!("expected 'log', 'stdout', 'null', 'loopback' or 'file:<path>', got '{}'", text)),
+        },
+    }
+}
+
+/// The sink kinds `--sink-failure` accepts, whether or not this build has them all.
+const SINK_KINDS: [&str; 6] = ["log", "stdout", "file", "null", "mqtt", "loopback"];
+
+/// Failed writes in a row before a `panic` sink failure strategy gives up, when none is given.
+const PANIC_AFTER: u32 = 3;
+
+/// What the logger does about a sink that keeps failing. Both are valid: degrading keeps the
+/// actor up and leans on the WAL, `--pause-on-sink-outage-ms` and the drain deadline; panicking
+/// restarts it with a freshly opened sink, but a record re-peeked by every restart is dropped as
+/// a showstopper once it has caused three.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub(crate) enum SinkFailure {
+    /// Holds the record and retries every `--sink-retry-ms`, as the logger always has.
+    #[default]
+    Degrade,
+    /// Panics once this many writes in a row have failed.
+    Panic(u32),
+}
+
+impl SinkFailure {
+    /// The strategy `--sink-failure` gives the sink in use; the last setting for its kind wins.
+    pub(crate) fn from_args(args: &MainArg) -> Self {
+        args.sink_failure.iter().rev()
+            .find(|policy| policy.sink == args.sink.kind())
+            .map_or(SinkFailure::Degrade, |policy| policy.strategy)
+    }
+}
+
+/// One `--sink-failure SINK=STRATEGY` setting.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct SinkFailurePolicy {
+    pub(crate) sink: &'static str,
+    pub(crate) strategy: SinkFailure,
+}
+
+/// Parses `SINK=degrade`, `SINK=panic` or `SINK=panic:N`, e.g. `file=panic:5`.
+pub(crate) fn parse_sink_failure(text: &str) -> Result<SinkFailurePolicy, String> {
+    let (sink, strategy) = text
+        .split_once('=')
+        .ok_or_else(|| format!("expected SINK=STRATEGY, got '{}'", text))?;
+    let sink = sink.trim().to_ascii_lowercase();
+    let sink = SINK_KINDS
+        .into_iter()
+        .find(|&kind| kind == sink)
+        .ok_or_else(|| format!("unknown sink '{}', expected one of {}", sink, SINK_KINDS.join(", ")))?;
+    let strategy = match strategy.trim().to_ascii_lowercase().as_str() {
+        "degrade" => SinkFailure::Degrade,
+        "panic" => SinkFailure::Panic(PANIC_AFTER),
+        other => match other.strip_prefix("panic:").map(str::parse::<u32>) {
+            Some(Ok(after)) if after > 0 => SinkFailure::Panic(after),
+            _ => return Err(format!("expected 'degrade', 'panic' or 'panic:N' with N > 0, got '{}'", strategy)),
+        },
+    };
+    Ok(SinkFailurePolicy { sink, strategy })
+}
+
+/// Writes each record as an info line, exactly as the logger always has.
+pub(crate) struct LogSink;
+
+impl Sink for LogSink {
+    fn write_record(&mut self, record: &str) -> io::Result<()> {
+        info!("{}", record);
+        Ok(())
+    }
+
+    fn is_console(&self) -> bool {
+        true
+    }
+}
+
+/// The process's stdout, set aside for records by `claim_stdout`.
+static DATA_OUT: OnceLock<File> = OnceLock::new();
+
+/// With `--data-stream stdout` the records own stdout. Its descriptor is kept aside for the
+/// sink, and stdout itself is pointed at stderr, so the lines the telemetry server prints at
+/// startup, and anything else printed, stay out of the data.
+#[cfg(unix)]
+pub(crate) fn claim_stdout() -> io::Result<()> {
+    use std::os::fd::{AsRawFd, FromRawFd};
+    io::stdout().flush()?;
+    let (stdout, stderr) = (io::stdout().as_raw_fd(), io::stderr().as_raw_fd());
+    // SAFETY: dup and dup2 on the standard descriptors; the duplicate is owned by the File alone.
+    let data = unsafe {
+        let fd = libc::dup(stdout);
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let data = File::from_raw_fd(fd);
+        if libc::dup2(stderr, stdout) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        data
+    };
+    let _ = DATA_OUT.set(data);
+    Ok(())
+}
+
+/// Elsewhere stdout is left as it is, telemetry lines and all.
+#[cfg(not(unix))]
+pub(crate) fn claim_stdout() -> io::Result<()> {
+    Ok(())
+}
+
+/// Writes each bare record as a line on stdout. A closed pipe fails the write like any
+/// other sink outage, so the logger holds or spills records as it would for a file.
+pub(crate) struct StdoutSink;
+
+impl Sink for StdoutSink {
+    fn write_record(&mut self, record: &str) -> io::Result<()> {
+        if let Some(mut out) = DATA_OUT.get() {
+            writeln!(out, "{}", record)?;
+            return out.flush();
+        }
+        let mut out = io::stdout().lock();
+        writeln!(out, "{}", record)?;
+        out.flush()
+    }
+
+    fn is_console(&self) -> bool {
+        true
+    }
+}
+
+/// Accepts and drops every record; the logger's counters are all that remain.
+pub(crate) struct NullSink;
+
+impl Sink for NullSink {
+    fn write_record(&mut self, _record: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn discards(&self) -> bool {
+        true
+    }
+}
+
+/// Appends each record to a file and flushes it, so a reported success is on disk.
+pub(crate) struct FileSink {
+    out: BufWriter<File>,
+}
+
+impl FileSink {
+    pub(crate) fn open(path: PathBuf) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(FileSink { out: BufWriter::new(file) })
+    }
+}
+
+impl Sink for FileSink {
+    fn write_record(&mut self, record: &str) -> io::Result<()> {
+        writeln!(self.out, "{}", record)?;
+        self.out.flush()
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.out.get_ref().sync_data()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod sink_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sink() {
+        assert_eq!(parse_sink("log"), Ok(SinkSpec::Log));
+        assert_eq!(parse_sink("stdout"), Ok(SinkSpec::Stdout));
+        assert_eq!(parse_sink("none"), Ok(SinkSpec::Null));
+        assert_eq!(parse_sink("file:out.txt"), Ok(SinkSpec::File(PathBuf::from("out.txt"))));
+        assert_eq!(parse_sink("null"), Ok(SinkSpec::Null));
+        assert_eq!(parse_sink("file:/dev/null"), Ok(SinkSpec::Null));
+        assert!(matches!(parse_sink("loopback"), Ok(SinkSpec::Loopback(_))));
+        assert!(parse_sink("file:").is_err());
+        assert!(parse_sink("kafka").is_err());
+        #[cfg(feature = "mqtt")]
+        {
+            assert!(matches!(parse_sink("mqtt:localhost:1883/robust/results"), Ok(SinkSpec::Mqtt(_))));
+            assert!(parse_sink("mqtt:localhost").is_err());
+        }
+    }
+
+    #[test]
+    fn test_parse_sink_failure() {
+        assert_eq!(parse_sink_failure("file=panic"), Ok(SinkFailurePolicy { sink: "file", strategy: SinkFailure::Panic(3) }));
+        assert_eq!(parse_sink_failure("MQTT=panic:5").map(|p| p.strategy), Ok(SinkFailure::Panic(5)));
+        assert_eq!(parse_sink_failure("stdout=degrade").map(|p| p.strategy), Ok(SinkFailure::Degrade));
+        assert!(parse_sink_failure("file=panic:0").is_err());
+        assert!(parse_sink_failure("kafka=panic").is_err());
+        assert!(parse_sink_failure("file").is_err());
+
+        // only the setting for the sink in use applies
+        let args = MainArg {
+            sink: SinkSpec::Stdout,
+            sink_failure: ["file=panic", "stdout=panic:2"].map(|p| parse_sink_failure(p).expect("valid")).to_vec(),
+            ..Default::default()
+        };
+        assert_eq!(SinkFailure::from_args(&args), SinkFailure::Panic(2));
+        assert_eq!(SinkFailure::from_args(&MainArg { sink: SinkSpec::Null, ..args }), SinkFailure::Degrade);
+    }
+}
@@ -0,0 +1,228 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+use crate::MainArg;
+
+/// Outcome of one closed SLO window.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct SloVerdict {
+    pub(crate) measured: u64,
+    pub(crate) late: u64,
+    /// Fraction of measured messages that missed the SLO.
+    pub(crate) ratio: f64,
+    pub(crate) passed: bool,
+}
+
+/// Rolling latency SLO evaluation for `--latency-slo-ms`.
+/// Messages are counted into fixed windows; when a window closes, the share of
+/// late messages is compared with the allowed ratio to give a pass or fail.
+/// Held as a local, so a restart simply starts a fresh window.
+pub(crate) struct LatencySlo {
+    pub(crate) slo: Duration,
+    pub(crate) max_ratio: f64,
+    pub(crate) window: Duration,
+    started: Instant,
+    measured: u64,
+    late: u64,
+}
+
+impl LatencySlo {
+    pub(crate) fn new(slo: Duration, max_ratio: f64, window: Duration) -> Self {
+        LatencySlo { slo, max_ratio, window, started: Instant::now(), measured: 0, late: 0 }
+    }
+
+    /// None unless `--latency-slo-ms` was given.
+    pub(crate) fn from_args(args: &MainArg) -> Option<Self> {
+        args.latency_slo_ms.map(|ms| LatencySlo::new(
+            Duration::from_millis(ms),
+            args.latency_slo_ratio,
+            Duration::from_millis(args.latency_slo_window_ms),
+        ))
+    }
+
+    /// Counts one message against the SLO, returning true if it was late.
+    pub(crate) fn observe(&mut self, latency: Duration) -> bool {
+        self.measured += 1;
+        let late = latency > self.slo;
+        if late {
+            self.late += 1;
+        }
+        late
+    }
+
+    /// Closes the window once it has run its length. Windows without traffic
+    /// prove nothing either way and yield no verdict.
+    pub(crate) fn roll(&mut self) -> Option<SloVerdict> {
+        if self.started.elapsed() < self.window {
+            return None;
+        }
+        let (measured, late) = (self.measured, self.late);
+        self.started = Instant::now();
+        self.measured = 0;
+        self.late = 0;
+        if measured == 0 {
+            return None;
+        }
+        let ratio = late as f64 / measured as f64;
+        Some(SloVerdict { measured, late, ratio, passed: ratio <= self.max_ratio })
+    }
+}
+
+/// One periodic `--latency-report-ms` report: the messages committed over the period and their
+/// percentiles, beside those of the run so far, in microseconds.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct LatencyReport {
+    pub(crate) messages: u64,
+    pub(crate) p50_us: u64,
+    pub(crate) p99_us: u64,
+    pub(crate) run_messages: u64,
+    pub(crate) run_p50_us: u64,
+    pub(crate) run_p99_us: u64,
+}
+
+impl LatencyReport {
+    pub(crate) fn of(period: &LatencyHistogram, run: &LatencyHistogram) -> Self {
+        LatencyReport {
+            messages: period.count(),
+            p50_us: period.quantile(0.5),
+            p99_us: period.quantile(0.99),
+            run_messages: run.count(),
+            run_p50_us: run.quantile(0.5),
+            run_p99_us: run.quantile(0.99),
+        }
+    }
+}
+
+impl fmt::Display for LatencyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "last period: {} messages, p50/p99 {}/{}us; run so far: {} messages, p50/p99 {}/{}us",
+               self.messages, self.p50_us, self.p99_us, self.run_messages, self.run_p50_us, self.run_p99_us)
+    }
+}
+
+/// Buckets per doubling of latency, so a bucket spans at most an eighth of its values.
+const SUB_BUCKETS: u64 = 8;
+
+/// End-to-end latencies of every committed message over the whole run, for the percentiles
+/// in the final report. Log-linear buckets of microseconds keep it a fixed few kilobytes
+/// at any volume, within 12.5% of the true value. Lives in the logger's state, so it spans restarts.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct LatencyHistogram {
+    buckets: Vec<u64>,
+    count: u64,
+}
+
+impl LatencyHistogram {
+    pub(crate) fn record(&mut self, latency: Duration) {
+        let index = bucket_of(latency.as_micros().min(u64::MAX as u128) as u64);
+        if self.buckets.len() <= index {
+            self.buckets.resize(index + 1, 0);
+        }
+        self.buckets[index] += 1;
+        self.count += 1;
+    }
+
+    pub(crate) fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Adds the counts of another histogram, as if its latencies had been recorded here.
+    pub(crate) fn merge(&mut self, other: &LatencyHistogram) {
+        if self.buckets.len() < other.buckets.len() {
+            self.buckets.resize(other.buckets.len(), 0);
+        }
+        for (mine, theirs) in self.buckets.iter_mut().zip(&other.buckets) {
+            *mine += theirs;
+        }
+        self.count += other.count;
+    }
+
+    /// The latency in microseconds that `q` of the recorded messages came in under,
+    /// as the upper edge of its bucket; zero before anything was recorded.
+    pub(crate) fn quantile(&self, q: f64) -> u64 {
+        let rank = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, &n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return bucket_top(index);
+            }
+        }
+        0
+    }
+}
+
+/// Exact below `SUB_BUCKETS` microseconds, then `SUB_BUCKETS` equal buckets per power of two.
+fn bucket_of(micros: u64) -> usize {
+    if micros < SUB_BUCKETS {
+        return micros as usize;
+    }
+    let exp = 63 - micros.leading_zeros() as u64;
+    let shift = exp - SUB_BUCKETS.trailing_zeros() as u64;
+    ((shift + 1) * SUB_BUCKETS + ((micros >> shift) & (SUB_BUCKETS - 1))) as usize
+}
+
+/// Largest latency that falls into `index`.
+fn bucket_top(index: usize) -> u64 {
+    let index = index as u64;
+    if index < SUB_BUCKETS {
+        return index;
+    }
+    let shift = index / SUB_BUCKETS - 1;
+    let low = (SUB_BUCKETS + index % SUB_BUCKETS) << shift;
+    low + ((1u64 << shift) - 1)
+}
+
+/// Parses `--latency-slo-ratio`, a fraction between 0 and 1.
+pub(crate) fn parse_ratio(text: &str) -> Result<f64, String> {
+    let ratio: f64 = text.parse().map_err(|e| format!("bad ratio '{}': {}", text, e))?;
+    if (0.0..=1.0).contains(&ratio) {
+        Ok(ratio)
+    } else {
+        Err(format!("ratio '{}' must be between 0 and 1", text))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod slo_tests {
+    use super::*;
+
+    #[test]
+    fn test_window_verdicts() {
+        let mut slo = LatencySlo::new(Duration::from_millis(50), 0.25, Duration::ZERO);
+        assert_eq!(slo.roll(), None);
+
+        for ms in [10, 20, 30, 60] {
+            slo.observe(Duration::from_millis(ms));
+        }
+        let verdict = slo.roll().expect("window closed");
+        assert_eq!((verdict.measured, verdict.late, verdict.passed), (4, 1, true));
+
+        slo.observe(Duration::from_millis(70));
+        slo.observe(Duration::from_millis(10));
+        let verdict = slo.roll().expect("window closed");
+        assert_eq!((verdict.ratio, verdict.passed), (0.5, false));
+
+        assert_eq!(parse_ratio("0.01"), Ok(0.01));
+        assert!(parse_ratio("1.5").is_err());
+    }
+
+    #[test]
+    fn test_latency_percentiles() {
+        let mut histogram = LatencyHistogram::default();
+        assert_eq!(histogram.quantile(0.99), 0);
+        for micros in 1..=100 {
+            histogram.record(Duration::from_micros(micros));
+        }
+        histogram.record(Duration::from_millis(40));
+        assert_eq!(histogram.quantile(0.0), 1);
+        assert_eq!(histogram.quantile(0.5), 51);
+        assert_eq!(histogram.quantile(0.99), 103);
+        assert_eq!(histogram.quantile(1.0), 40_959);
+
+        for micros in [0, 7, 8, 15, 16, 1_000, 123_456_789, u64::MAX] {
+            let index = bucket_of(micros);
+            assert!(micros <= bucket_top(index), "{} above its bucket", micros);
+            assert!(index == 0 || micros > bucket_top(index - 1), "{} below its bucket", micros);
+        }
+    }
+}
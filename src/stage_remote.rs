@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use steady_state::*;
+use steady_state::graph_testing::*;
+use crate::actor::worker::FizzBuzzMessage;
+use crate::event_time::BeatTime;
+use crate::timing::Envelope;
+use crate::validation::{RejectReason, RejectedValue};
+use crate::{NAME_GENERATOR, NAME_HEARTBEAT, NAME_LOGGER};
+
+/// How long a `wait` gives the logger when the command names no timeout.
+const DEFAULT_WAIT: Duration = Duration::from_secs(2);
+
+/// Parses the `--stage-listen` address. Staging drives the pipeline without any authentication,
+/// so only loopback addresses are accepted.
+pub(crate) fn parse_stage_listen(text: &str) -> Result<SocketAddr, String> {
+    let addr: SocketAddr = text.parse().map_err(|e| format!("bad address '{}': {}", text, e))?;
+    if !addr.ip().is_loopback() {
+        return Err(format!("{} is not a loopback address; staging is for local test harnesses only", addr));
+    }
+    Ok(addr)
+}
+
+/// One line of the staging protocol.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum StageCommand {
+    /// `perform generator VALUE`: the simulated generator sends VALUE.
+    Generate(u64),
+    /// `perform heartbeat BEAT`: the simulated heartbeat sends BEAT.
+    Beat(u64),
+    /// `wait logger KIND VALUE SEQ [TIMEOUT_MS]`: the logger receives VALUE classified as KIND,
+    /// as the SEQ-th message of the worker's output.
+    Logged { message: FizzBuzzMessage, value: u64, seq: u64, timeout: Duration },
+    /// `wait logger reject VALUE REASON [TIMEOUT_MS]`: the logger receives VALUE on the rejects side channel.
+    Rejected { rejected: RejectedValue, timeout: Duration },
+    /// `bow`: ends staging and stops the run.
+    Bow,
+}
+
+/// Parses one protocol line, words separated by whitespace.
+pub(crate) fn parse_command(line: &str) -> Result<StageCommand, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let number = |i: usize| -> Result<u64, String> {
+        let word = words.get(i).ok_or("missing argument")?;
+        word.parse().map_err(|e| format!("bad number '{}': {}", word, e))
+    };
+    let timeout = |i: usize| -> Result<Duration, String> {
+        if words.len() > i { number(i).map(Duration::from_millis) } else { Ok(DEFAULT_WAIT) }
+    };
+    match words.as_slice() {
+        ["perform", "generator", ..] => Ok(StageCommand::Generate(number(2)?)),
+        ["perform", "heartbeat", ..] => Ok(StageCommand::Beat(number(2)?)),
+        ["wait", "logger", "reject", _, reason, ..] => {
+            let rejected = RejectedValue::new(number(3)?, parse_reason(reason)?);
+            Ok(StageCommand::Rejected { rejected, timeout: timeout(5)? })
+        }
+        ["wait", "logger", kind, ..] => {
+            let value = number(3)?;
+            let message = match *kind {
+                "fizz" => FizzBuzzMessage::Fizz,
+                "buzz" => FizzBuzzMessage::Buzz,
+                "fizzbuzz" => FizzBuzzMessage::FizzBuzz,
+                "value" => FizzBuzzMessage::Value(value),
+                other => return Err(format!("unknown kind '{}', expected fizz, buzz, fizzbuzz, value or reject", other)),
+            };
+            Ok(StageCommand::Logged { message, value, seq: number(4)?, timeout: timeout(5)? })
+        }
+        ["bow"] => Ok(StageCommand::Bow),
+        _ => Err(format!("unknown command '{}'", line.trim())),
+    }
+}
+
+/// Reject reasons as the protocol spells them: `out-of-range`, `multiple-of-N` or `expired`.
+fn parse_reason(text: &str) -> Result<RejectReason, String> {
+    match text {
+        "out-of-range" => Ok(RejectReason::OutOfRange),
+        "expired" => Ok(RejectReason::Expired),
+        _ => text
+            .strip_prefix("multiple-of-")
+            .and_then(|n| n.parse().ok())
+            .map(RejectReason::MultipleOf)
+            .ok_or_else(|| format!("unknown reject reason '{}'", text)),
+    }
+}
+
+/// Runs staging commands against the graph's stage manager.
+/// Remembers each value the generator was told to send, because the logger sees that exact
+/// envelope again, generation stamp included, and a harness cannot know the stamp.
+#[derive(Default)]
+struct Stage {
+    generated: HashMap<u64, Envelope<u64>>,
+}
+
+impl Stage {
+    fn run(&mut self, stage_manager: &StageManager, command: StageCommand) -> Result<(), Box<dyn Error>> {
+        match command {
+            StageCommand::Generate(value) => {
+                let envelope = Envelope::new(value);
+                stage_manager.actor_perform(NAME_GENERATOR, StageDirection::Echo(envelope))?;
+                self.generated.insert(value, envelope);
+            }
+            StageCommand::Beat(beat) => {
+                stage_manager.actor_perform(NAME_HEARTBEAT, StageDirection::Echo(BeatTime::untimed(beat)))?;
+            }
+            StageCommand::Logged { message, value, seq, timeout } => {
+                let generated = self.generated.get(&value).copied().unwrap_or_else(|| Envelope::unstamped(value));
+                stage_manager.actor_perform(NAME_LOGGER, StageWaitFor::Message(generated.carry(message).with_seq(seq), timeout))?;
+            }
+            StageCommand::Rejected { rejected, timeout } => {
+                stage_manager.actor_perform(NAME_LOGGER, StageWaitFor::Message(rejected, timeout))?;
+            }
+            StageCommand::Bow => {}
+        }
+        Ok(())
+    }
+}
+
+/// Serves the staging protocol to one connection at a time until a harness sends `bow`.
+/// Every line gets a one-line reply, `ok` or `error <reason>`, once the command has finished,
+/// so a harness can simply write a command and read a line.
+pub(crate) fn serve(stage_manager: &StageManager, listener: TcpListener) -> Result<(), Box<dyn Error>> {
+    info!("Staging on {}; send `bow` to stop", listener.local_addr()?);
+    let mut stage = Stage::default();
+    for stream in listener.incoming() {
+        match session(stage_manager, &mut stage, stream?) {
+            Ok(true) => break,
+            Ok(false) => {}
+            Err(e) => warn!("Staging connection failed: {}", e),
+        }
+    }
+    Ok(())
+}
+
+/// One harness connection; true once it has sent `bow`.
+fn session(stage_manager: &StageManager, stage: &mut Stage, stream: TcpStream) -> Result<bool, Box<dyn Error>> {
+    let mut reply = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let command = parse_command(&line);
+        let bow = command == Ok(StageCommand::Bow);
+        match command.map_err(Box::<dyn Error>::from).and_then(|command| stage.run(stage_manager, command)) {
+            Ok(()) => writeln!(reply, "ok")?,
+            Err(e) => writeln!(reply, "error {}", e)?,
+        }
+        if bow {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+pub(crate) mod stage_remote_tests {
+    use std::thread;
+    use super::*;
+    use crate::arg::MainArg;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command("perform generator 15"), Ok(StageCommand::Generate(15)));
+        assert_eq!(parse_command(" perform  heartbeat 2 "), Ok(StageCommand::Beat(2)));
+        assert_eq!(parse_command("wait logger value 7 3 500"), Ok(StageCommand::Logged {
+            message: FizzBuzzMessage::Value(7), value: 7, seq: 3, timeout: Duration::from_millis(500),
+        }));
+        assert_eq!(parse_command("wait logger reject 14 multiple-of-7"), Ok(StageCommand::Rejected {
+            rejected: RejectedValue::new(14, RejectReason::MultipleOf(7)), timeout: DEFAULT_WAIT,
+        }));
+        assert!(parse_command("wait logger fizz 3").is_err());
+        assert!(parse_command("wait logger reject 3 odd").is_err());
+        assert!(parse_command("perform worker 1").is_err());
+        assert!(parse_stage_listen("127.0.0.1:7400").is_ok());
+        assert!(parse_stage_listen("0.0.0.0:7400").is_err());
+    }
+
+    /// A harness at the other end of a socket drives the same scenario as `graph_test`.
+    #[test]
+    fn test_stage_over_tcp() -> Result<(), Box<dyn Error>> {
+        SteadyRunner::test_build()
+            .with_logging(LogLevel::Info)
+            .with_telemetry_rate_ms(200)
+            .run(MainArg::default(), move |mut graph| {
+                crate::build_graph(&mut graph);
+                graph.start();
+
+                let listener = TcpListener::bind("127.0.0.1:0")?;
+                let addr = listener.local_addr()?;
+                let harness = thread::spawn(move || -> Vec<String> {
+                    let stream = TcpStream::connect(addr).expect("connect");
+                    let mut writer = stream.try_clone().expect("clone");
+                    let mut replies = BufReader::new(stream).lines();
+                    [
+                        "perform generator 15", "perform heartbeat 100", "wait logger fizzbuzz 15 1",
+                        "perform worker 9", "perform generator 9", "perform heartbeat 101", "wait logger fizz 9 2", "bow",
+                    ]
+                        .iter()
+                        .map(|command| {
+                            writeln!(writer, "{}", command).expect("write");
+                            replies.next().expect("reply").expect("read")
+                        })
+                        .collect()
+                });
+                let stage_manager = graph.stage_manager();
+                serve(&stage_manager, listener)?;
+                stage_manager.final_bow();
+
+                let replies = harness.join().expect("harness");
+                assert_eq!(replies[3], "error unknown command 'perform worker 9'");
+                assert!(replies.iter().enumerate().all(|(i, reply)| i == 3 || reply == "ok"), "{:?}", replies);
+
+                graph.request_shutdown();
+                graph.block_until_stopped(Duration::from_secs(5))
+            })
+    }
+}
@@ -0,0 +1,118 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use steady_state::*;
+use crate::slo::LatencyHistogram;
+
+/// How one stage's loop iterations split between waiting on its channels and working,
+/// to tell a compute-bound stage from one starved or blocked by its neighbours.
+/// Lives in the actor's state, so it covers the whole run.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct StageTime {
+    pub(crate) busy: LatencyHistogram,
+    pub(crate) waiting: LatencyHistogram,
+    busy_micros: u64,
+    waiting_micros: u64,
+}
+
+impl StageTime {
+    pub(crate) fn record(&mut self, busy: Duration, waiting: Duration) {
+        self.busy.record(busy);
+        self.waiting.record(waiting);
+        self.busy_micros += busy.as_micros() as u64;
+        self.waiting_micros += waiting.as_micros() as u64;
+    }
+
+    /// Adds another stage's iterations, to summarize a `--workers` pool as one stage.
+    pub(crate) fn merge(&mut self, other: &StageTime) {
+        self.busy.merge(&other.busy);
+        self.waiting.merge(&other.waiting);
+        self.busy_micros += other.busy_micros;
+        self.waiting_micros += other.waiting_micros;
+    }
+
+    pub(crate) fn summary(&self) -> StageTimeSummary {
+        let total = self.busy_micros + self.waiting_micros;
+        StageTimeSummary {
+            busy_pct: if total == 0 { 0.0 } else { self.busy_micros as f64 * 100.0 / total as f64 },
+            busy_p50_us: self.busy.quantile(0.5),
+            busy_p99_us: self.busy.quantile(0.99),
+            wait_p50_us: self.waiting.quantile(0.5),
+            wait_p99_us: self.waiting.quantile(0.99),
+        }
+    }
+}
+
+/// Per-iteration busy and wait times of a stage in the final report.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct StageTimeSummary {
+    /// Share of the stage's loop time spent working rather than waiting.
+    pub(crate) busy_pct: f64,
+    pub(crate) busy_p50_us: u64,
+    pub(crate) busy_p99_us: u64,
+    pub(crate) wait_p50_us: u64,
+    pub(crate) wait_p99_us: u64,
+}
+
+impl fmt::Display for StageTimeSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f, "busy {:.1}% (p50/p99 {}/{}us), waiting p50/p99 {}/{}us",
+            self.busy_pct, self.busy_p50_us, self.busy_p99_us, self.wait_p50_us, self.wait_p99_us
+        )
+    }
+}
+
+/// Times the iterations of an actor's loop. Every wait is timed with `waited`, and `lap`
+/// at the top of the loop books the iteration just finished: its waits as waiting, the rest as busy.
+/// Held as a local, so the iteration a restart interrupted is simply not counted.
+pub(crate) struct IterationClock {
+    started: Option<Instant>,
+    waited: Duration,
+}
+
+impl IterationClock {
+    pub(crate) fn new() -> Self {
+        IterationClock { started: None, waited: Duration::ZERO }
+    }
+
+    pub(crate) fn lap(&mut self, time: &mut StageTime) {
+        let now = Instant::now();
+        if let Some(started) = self.started {
+            let total = now.duration_since(started);
+            time.record(total.saturating_sub(self.waited), self.waited.min(total));
+        }
+        self.started = Some(now);
+        self.waited = Duration::ZERO;
+    }
+
+    /// Counts the time since `since`, when a wait began, as waiting.
+    pub(crate) fn waited(&mut self, since: Instant) {
+        self.waited += since.elapsed();
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod stage_time_tests {
+    use super::*;
+
+    #[test]
+    fn test_iterations_split_busy_from_waiting() {
+        let mut time = StageTime::default();
+        let mut clock = IterationClock::new();
+        clock.lap(&mut time);
+        assert_eq!(time.summary(), StageTimeSummary::default());
+        for _ in 0..3 {
+            let wait = Instant::now();
+            std::thread::sleep(Duration::from_millis(30));
+            clock.waited(wait);
+            std::thread::sleep(Duration::from_millis(10));
+            clock.lap(&mut time);
+        }
+        let summary = time.summary();
+        assert!((15.0..40.0).contains(&summary.busy_pct), "{:?}", summary);
+        assert!(summary.wait_p50_us >= 30_000 && summary.busy_p50_us >= 10_000, "{:?}", summary);
+        assert!(summary.busy_p50_us < summary.wait_p50_us, "{:?}", summary);
+        assert!(summary.to_string().starts_with("busy "));
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use steady_state::*;
+use crate::wiring::RobustActor;
+use crate::MainArg;
+
+/// How often a stage held at its start gate looks again.
+const GATE_POLL: Duration = Duration::from_millis(10);
+
+/// A startup order the stages cannot follow, found before any of them is built.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StartupError {
+    /// A stage depends on one that is not part of the pipeline with these arguments.
+    Unknown { stage: &'static str, dependency: &'static str },
+    /// Each stage waits for the next, and the last for the first, so none would start.
+    Cycle(Vec<&'static str>),
+}
+
+impl fmt::Display for StartupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StartupError::Unknown { stage, dependency } => {
+                write!(f, "{} starts after {}, which is not in the pipeline", stage, dependency)
+            }
+            StartupError::Cycle(stages) => write!(f, "startup dependencies form a cycle: {}", stages.join(" -> ")),
+        }
+    }
+}
+
+impl Error for StartupError {}
+
+/// Checks the startup dependencies of the stages enabled with `args`: each must name an enabled
+/// stage, and following them must never lead back to where it started.
+pub fn check(stages: &[Box<dyn RobustActor>], args: &MainArg) -> Result<(), StartupError> {
+    let enabled: BTreeMap<&'static str, &'static [&'static str]> = stages
+        .iter()
+        .filter(|stage| stage.enabled(args))
+        .map(|stage| (stage.name(), stage.depends_on(args)))
+        .collect();
+    for (&stage, &dependencies) in &enabled {
+        if let Some(&dependency) = dependencies.iter().find(|dependency| !enabled.contains_key(*dependency)) {
+            return Err(StartupError::Unknown { stage, dependency });
+        }
+    }
+    // Depth first, keeping the path walked so far; meeting a stage already on it closes a cycle.
+    fn visit(
+        stage: &'static str,
+        enabled: &BTreeMap<&'static str, &'static [&'static str]>,
+        path: &mut Vec<&'static str>,
+        done: &mut BTreeSet<&'static str>,
+    ) -> Result<(), StartupError> {
+        if let Some(at) = path.iter().position(|&s| s == stage) {
+            let mut cycle = path[at..].to_vec();
+            cycle.push(stage);
+            return Err(StartupError::Cycle(cycle));
+        }
+        if done.contains(stage) {
+            return Ok(());
+        }
+        path.push(stage);
+        for &dependency in enabled[stage] {
+            visit(dependency, enabled, path, done)?;
+        }
+        path.pop();
+        done.insert(stage);
+        Ok(())
+    }
+    let mut done = BTreeSet::new();
+    for &stage in enabled.keys() {
+        visit(stage, &enabled, &mut Vec::new(), &mut done)?;
+    }
+    Ok(())
+}
+
+/// The stages of one graph that are ready for those started after them. A stage that announces
+/// its own readiness joins once it says so; every other stage counts as ready from the start.
+/// Readiness is only for startup: a stage that restarts later stays ready.
+#[derive(Clone, Debug, Default)]
+pub struct Readiness(Arc<Mutex<BTreeSet<&'static str>>>);
+
+impl Readiness {
+    /// Marks the stages with nothing to announce ready, once the graph's stages are known.
+    pub(crate) fn prepare(&self, stages: &[Box<dyn RobustActor>], args: &MainArg) {
+        let mut ready = self.ready();
+        ready.extend(stages.iter().filter(|stage| stage.enabled(args) && !stage.announces_ready()).map(|stage| stage.name()));
+    }
+
+    /// The start gate for `stage`, held shut until all of `after` are ready.
+    pub(crate) fn gate(&self, stage: &'static str, after: &'static [&'static str]) -> StartGate {
+        StartGate { stage, after, readiness: self.clone() }
+    }
+
+    fn ready(&self) -> std::sync::MutexGuard<'_, BTreeSet<&'static str>> {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// One stage's place in the startup order: the stages it waits for, and its own readiness to announce.
+#[derive(Clone, Debug)]
+pub struct StartGate {
+    stage: &'static str,
+    after: &'static [&'static str],
+    readiness: Readiness,
+}
+
+impl StartGate {
+    /// Waits until every stage this one depends on is ready, or shutdown is requested first, in
+    /// which case the stage goes on to stop the way it normally would.
+    pub async fn wait<A: SteadyActor>(&self, actor: &A) {
+        let waiting = |gate: &StartGate| -> Vec<&'static str> {
+            let ready = gate.readiness.ready();
+            gate.after.iter().copied().filter(|dependency| !ready.contains(dependency)).collect()
+        };
+        let pending = waiting(self);
+        if pending.is_empty() {
+            return;
+        }
+        let started = Instant::now();
+        info!("{} waiting for {} to be ready before it starts", self.stage, pending.join(", "));
+        while !waiting(self).is_empty() && !actor.is_liveliness_stop_requested() {
+            actor.wait(GATE_POLL).await;
+        }
+        info!("{} starting after {:?} at its start gate", self.stage, started.elapsed());
+    }
+
+    /// Announces the stage ready, releasing the stages that wait for it.
+    pub fn ready(&self) {
+        if self.readiness.ready().insert(self.stage) {
+            info!("{} ready", self.stage);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod startup_tests {
+    use steady_state::actor_builder::{ActorBuilder, ScheduleAs};
+    use crate::wiring::Wiring;
+    use super::*;
+
+    /// A stage that only declares its startup dependencies.
+    struct Declared(&'static str, &'static [&'static str]);
+
+    impl RobustActor for Declared {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+        fn inputs(&self) -> &'static [&'static str] {
+            &[]
+        }
+        fn outputs(&self) -> &'static [&'static str] {
+            &[]
+        }
+        fn depends_on(&self, _args: &MainArg) -> &'static [&'static str] {
+            self.1
+        }
+        fn announces_ready(&self) -> bool {
+            self.0 == "SINK"
+        }
+        fn build(&self, _builder: ActorBuilder, _wiring: &Wiring, _schedule: ScheduleAs<'_>) {}
+    }
+
+    #[test]
+    fn test_dependency_graph_is_checked() {
+        let args = MainArg::default();
+        assert_eq!(check(&crate::wiring::stages(), &args), Ok(()));
+
+        let stages = |declared: Vec<Declared>| declared.into_iter().map(|d| Box::new(d) as Box<dyn RobustActor>).collect::<Vec<_>>();
+        let ok = stages(vec![Declared("SOURCE", &["SINK"]), Declared("WORKER", &["SINK", "SOURCE"]), Declared("SINK", &[])]);
+        assert_eq!(check(&ok, &args), Ok(()));
+        let unknown = stages(vec![Declared("WORKER", &["SINK"])]);
+        assert_eq!(check(&unknown, &args).map_err(|e| e.to_string()), Err("WORKER starts after SINK, which is not in the pipeline".to_string()));
+        let cycle = stages(vec![Declared("A", &["B"]), Declared("B", &["C"]), Declared("C", &["A"]), Declared("D", &["A"])]);
+        assert_eq!(check(&cycle, &args), Err(StartupError::Cycle(vec!["A", "B", "C", "A"])));
+        assert_eq!(check(&stages(vec![Declared("A", &["A"])]), &args).map_err(|e| e.to_string()),
+                   Err("startup dependencies form a cycle: A -> A".to_string()));
+
+        // only a stage that announces itself has to
+        let readiness = Readiness::default();
+        readiness.prepare(&ok, &args);
+        let worker = readiness.gate("WORKER", &["SINK", "SOURCE"]);
+        assert_eq!(worker.readiness.ready().iter().copied().collect::<Vec<_>>(), ["SOURCE", "WORKER"]);
+        readiness.gate("SINK", &[]).ready();
+        assert!(worker.readiness.ready().contains("SINK"));
+    }
+}
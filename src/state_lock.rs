@@ -0,0 +1,193 @@
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Mutex, OnceLock};
+use steady_state::*;
+use serde_json::json;
+use crate::events;
+use crate::restart::RestartCause;
+use crate::MainArg;
+
+/// What an actor does when its state is slow to lock or was left by an instance that panicked.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum LockPolicy {
+    /// Report it and carry on: keep waiting for the lock, or resume from the state as left.
+    #[default]
+    Recover,
+    /// Report it, keep the actor down and stop the pipeline as Ctrl-C would.
+    Abort,
+}
+
+pub(crate) fn parse_lock_policy(text: &str) -> Result<LockPolicy, String> {
+    match text.trim().to_ascii_lowercase().as_str() {
+        "recover" => Ok(LockPolicy::Recover),
+        "abort" => Ok(LockPolicy::Abort),
+        other => Err(format!("unknown state lock policy '{}', expected recover or abort", other)),
+    }
+}
+
+/// Actors whose state was in use when their thread panicked, until they lock it again.
+fn poisoned() -> &'static Mutex<HashSet<&'static str>> {
+    static POISONED: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+    POISONED.get_or_init(Default::default)
+}
+
+thread_local! {
+    /// The actor that last touched its state on this thread. On a troupe thread that is the
+    /// member being polled when it panics, as long as it had used its state by then.
+    static ACTIVE: Cell<Option<&'static str>> = const { Cell::new(None) };
+}
+
+/// The actor that last touched its state on the calling thread, if any has.
+pub(crate) fn active_actor() -> Option<&'static str> {
+    ACTIVE.get()
+}
+
+/// Marks the state of whichever actor was using it when a panic strikes, so its next
+/// lock can tell it resumes from a half-finished update. The mutex inside SteadyState
+/// does not poison, which is why the panic hook has to notice instead.
+/// Chains to the hook already installed, so steady_state's own reporting still runs.
+pub fn install_panic_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if let Some(name) = ACTIVE.get()
+            && let Ok(mut poisoned) = poisoned().lock() {
+            poisoned.insert(name);
+        }
+        previous(info)
+    }));
+}
+
+/// An actor's locked state. Every access through it records the actor as the one active on its thread.
+pub struct Held<'a, S> {
+    guard: StateGuard<'a, S>,
+    name: &'static str,
+    poisoned: bool,
+    warm: bool,
+}
+
+impl<S> Held<'_, S> {
+    /// Why the instance before this one ended, for an actor that is restarting:
+    /// a panic if it left the state poisoned, otherwise an error return.
+    pub fn restart_cause(&self) -> RestartCause {
+        if self.poisoned { RestartCause::Panic } else { RestartCause::Error }
+    }
+
+    /// True when the lock found the state a previous instance left, false when it had to build it.
+    pub fn is_warm(&self) -> bool {
+        self.warm
+    }
+}
+
+impl<S> Deref for Held<'_, S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        ACTIVE.set(Some(self.name));
+        &self.guard
+    }
+}
+
+impl<S> DerefMut for Held<'_, S> {
+    fn deref_mut(&mut self) -> &mut S {
+        ACTIVE.set(Some(self.name));
+        &mut self.guard
+    }
+}
+
+/// Locks an actor's state like `SteadyState::lock`, but never silently. Each
+/// `--state-lock-timeout-ms` spent waiting is reported, as is a state left by an instance
+/// that panicked while using it, and `--state-lock-policy` decides what follows.
+/// Returns None when the policy is to abort; the actor should then return at once.
+pub async fn lock_state<'s, A: SteadyActor, S: Send>(
+    actor: &A,
+    name: &'static str,
+    state: &'s SteadyState<S>,
+    init: impl FnOnce() -> S,
+) -> Option<Held<'s, S>> {
+    let args = actor.args::<MainArg>().expect("unable to downcast");
+    let (timeout, policy) = (Duration::from_millis(args.state_lock_timeout_ms), args.state_lock_policy);
+
+    let started = Instant::now();
+    let cold = Cell::new(false);
+    let init = || {
+        cold.set(true);
+        init()
+    };
+    let mut lock = std::pin::pin!(steady_fuse_future(async { Some(state.lock(init).await) }));
+    let guard = loop {
+        let expiry = steady_fuse_future(async {
+            Delay::new(timeout).await;
+            None
+        });
+        if let Some(guard) = steady_select_two(lock.as_mut(), expiry).await {
+            break guard;
+        }
+        error!("state-lock event=timeout actor={} waited_ms={} policy={:?}", name, started.elapsed().as_millis(), policy);
+        events::emit("alert", json!({"kind": "state_lock_timeout", "actor": name, "waited_ms": started.elapsed().as_millis() as u64}));
+        if policy == LockPolicy::Abort {
+            crate::signals::request_stop();
+            return None;
+        }
+    };
+
+    let was_poisoned = poisoned().lock().map(|mut set| set.remove(name)).unwrap_or(false);
+    if was_poisoned {
+        match policy {
+            LockPolicy::Recover => warn!("state-lock event=poisoned actor={} policy=Recover, resuming from the state as the panicked instance left it", name),
+            LockPolicy::Abort => {
+                error!("state-lock event=poisoned actor={} policy=Abort, keeping the actor down and stopping the pipeline", name);
+                events::emit("alert", json!({"kind": "state_poisoned", "actor": name}));
+                crate::signals::request_stop();
+                return None;
+            }
+        }
+    }
+    Some(Held { guard, name, poisoned: was_poisoned, warm: !cold.get() })
+}
+
+/// An actor's state once the graph has stopped, for tests to inspect. `block_until_stopped`
+/// returns as soon as every actor has voted to stop, which can be a moment before the
+/// actor returns and lets go of its state, so the lock is retried for a while.
+#[cfg(test)]
+pub(crate) fn settled<S: Send>(state: &SteadyState<S>) -> Option<StateGuard<'_, S>> {
+    settled_within(state, Duration::from_secs(2))
+}
+
+/// Like `settled`, giving up after `within`. None as well for an actor that never ran,
+/// since its state was never created.
+pub fn settled_within<S: Send>(state: &SteadyState<S>, within: Duration) -> Option<StateGuard<'_, S>> {
+    let until = Instant::now() + within;
+    loop {
+        if let Some(guard) = state.try_lock_sync() {
+            return Some(guard);
+        }
+        if Instant::now() >= until {
+            return None;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod state_lock_tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_and_poison_marking() {
+        assert_eq!(parse_lock_policy("Abort"), Ok(LockPolicy::Abort));
+        assert!(parse_lock_policy("retry").is_err());
+
+        install_panic_hook();
+        // a thread that panics before touching any state poisons nothing
+        assert!(std::thread::spawn(|| panic!("died before any update")).join().is_err());
+        assert!(!poisoned().lock().expect("poison set").contains("POISON_TEST"));
+
+        let outcome = std::thread::spawn(|| {
+            ACTIVE.set(Some("POISON_TEST"));
+            panic!("died mid-update");
+        });
+        assert!(outcome.join().is_err());
+        assert!(poisoned().lock().expect("poison set").remove("POISON_TEST"));
+    }
+}
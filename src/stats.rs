@@ -0,0 +1,102 @@
+use steady_state::*;
+use crate::state_lock::settled_within;
+use crate::PipelineState;
+
+/// How long `stats` waits for an actor still letting go of its state.
+const PATIENCE: Duration = Duration::from_millis(250);
+
+/// Records the logger wrote, by kind.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct VariantCounts {
+    pub fizz: u64,
+    pub buzz: u64,
+    pub fizzbuzz: u64,
+    pub value: u64,
+    /// Records classified by `--rule` rules.
+    pub rule: u64,
+}
+
+/// The outcome of a run as typed counters, for code that embeds the pipeline or tests it
+/// and wants to check results without parsing the final report out of the log.
+/// Actors that never ran count zero. The worker and logger always run in a live pipeline, so
+/// when their state cannot be read they are named in `unread` and their counters mean nothing.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PipelineStats {
+    pub beats_sent: u64,
+    /// Values the source produced, whichever source fed the pipeline.
+    pub generated: u64,
+    /// Values the worker took off the generator channel, and those it classified or rejected.
+    pub processed: u64,
+    pub classified: u64,
+    pub rejected: u64,
+    pub logged: VariantCounts,
+    /// Rejects the logger wrote from the side channel.
+    pub rejects_logged: u64,
+    /// Values dropped as showstoppers by the worker and the logger together.
+    pub showstoppers: u64,
+    pub duplicates_suppressed: u64,
+    /// Values left behind when a drain deadline passed, by the worker and the logger together.
+    pub undrained: u64,
+    /// Restarts of the heartbeat, generator, worker and logger together, first starts not counted.
+    pub restarts: u64,
+    pub unread: Vec<&'static str>,
+}
+
+impl PipelineState {
+    /// Reads the counters out of every actor's state. Meant for after `block_until_stopped`,
+    /// when the totals are final; an actor still letting go of its state is given a moment.
+    pub fn stats(&self) -> PipelineStats {
+        let mut stats = PipelineStats::default();
+        let mut unread = Vec::new();
+        if let Some(s) = settled_within(&self.heartbeat, PATIENCE) {
+            stats.beats_sent = s.beats_sent;
+            stats.restarts += s.restart_count.saturating_sub(1);
+        }
+        if let Some(s) = settled_within(&self.generator, PATIENCE) {
+            stats.generated = s.messages_sent;
+            stats.restarts += s.restart_count.saturating_sub(1);
+        }
+        if let Some(s) = settled_within(&self.queue_source, PATIENCE) {
+            stats.generated = s.accepted;
+        }
+        if let Some(s) = settled_within(&self.tcp_source, PATIENCE) {
+            stats.generated = s.accepted;
+        }
+        #[cfg(feature = "grpc")]
+        if let Some(s) = settled_within(&self.grpc_source, PATIENCE) {
+            stats.generated = s.accepted;
+        }
+        #[cfg(feature = "mqtt")]
+        if let Some(s) = settled_within(&self.mqtt_source, PATIENCE) {
+            stats.generated = s.accepted;
+        }
+        // A --workers pool counts as one worker.
+        for worker in self.workers() {
+            match settled_within(worker, PATIENCE) {
+                Some(s) => {
+                    stats.processed += s.values_processed;
+                    stats.classified += s.messages_sent;
+                    stats.rejected += s.values_rejected;
+                    stats.showstoppers += s.showstoppers_dropped;
+                    stats.undrained += s.residue;
+                    stats.restarts += s.restart_count.saturating_sub(1);
+                }
+                None if !unread.contains(&"worker") => unread.push("worker"),
+                None => {}
+            }
+        }
+        match settled_within(&self.logger, PATIENCE) {
+            Some(s) => {
+                stats.logged = VariantCounts { fizz: s.fizz_count, buzz: s.buzz_count, fizzbuzz: s.fizzbuzz_count, value: s.value_count, rule: s.rule_count };
+                stats.rejects_logged = s.rejected_count;
+                stats.showstoppers += s.showstoppers_dropped;
+                stats.duplicates_suppressed = s.duplicates_suppressed;
+                stats.undrained += s.residue;
+                stats.restarts += s.restart_count.saturating_sub(1);
+            }
+            None => unread.push("logger"),
+        }
+        stats.unread = unread;
+        stats
+    }
+}
@@ -0,0 +1,175 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::sync::atomic::{AtomicBool, Ordering};
+use steady_state::*;
+use crate::preflight;
+
+/// How `supervise` restarts a pipeline that exited abnormally.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct RestartPolicy {
+    /// Abnormal exits in a row before the supervisor gives up.
+    pub(crate) max_restarts: u32,
+    pub(crate) backoff: Duration,
+    pub(crate) backoff_max: Duration,
+    /// A child that stayed up this long was healthy, so its exit starts the count and backoff over.
+    pub(crate) healthy_after: Duration,
+    /// Crash dumps kept in the child's `--crash-dir`, newest first.
+    pub(crate) keep_crash_dumps: usize,
+}
+
+/// Restarts in a row and the wait before the next one, doubling up to the policy's cap.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Backoff {
+    policy: RestartPolicy,
+    pub(crate) restarts: u32,
+    next: Duration,
+}
+
+impl Backoff {
+    pub(crate) fn new(policy: RestartPolicy) -> Self {
+        Backoff { policy, restarts: 0, next: policy.backoff }
+    }
+
+    /// The wait before restarting a child that failed after running `uptime`,
+    /// or None once the restarts in a row are used up.
+    pub(crate) fn after_failure(&mut self, uptime: Duration) -> Option<Duration> {
+        if uptime >= self.policy.healthy_after {
+            self.restarts = 0;
+            self.next = self.policy.backoff;
+        }
+        if self.restarts >= self.policy.max_restarts {
+            return None;
+        }
+        self.restarts += 1;
+        let wait = self.next;
+        self.next = (self.next * 2).min(self.policy.backoff_max);
+        Some(wait)
+    }
+}
+
+/// Set by Ctrl-C. The child gets the same signal and drains on its own, so the supervisor only stops restarting it.
+static STOPPING: AtomicBool = AtomicBool::new(false);
+
+/// The `supervise` subcommand: runs the pipeline with `child_args` as a child process, restarting it
+/// after an abnormal exit until it exits cleanly, is interrupted, or fails too often in a row.
+/// Returns the exit code to leave with, the child's last one.
+pub(crate) fn run(policy: RestartPolicy, child_args: &[String]) -> Result<i32, Box<dyn Error>> {
+    // Arguments the child would reject are reported now, not after the first restart.
+    let child = crate::config::parse_from(std::iter::once("robust").chain(child_args.iter().map(String::as_str)))
+        .map_err(|e| format!("bad pipeline arguments: {}", e.render()))?;
+    if child.command.is_some() {
+        return Err("supervise runs the pipeline; give its arguments after --, without a subcommand".into());
+    }
+    preflight::check(&child).map_err(|e| format!("bad pipeline arguments: {}", e))?;
+    ctrlc::set_handler(|| {
+        STOPPING.store(true, Ordering::SeqCst);
+        warn!("Interrupted, the pipeline is draining and will not be restarted");
+    })?;
+    let exe = std::env::current_exe()?;
+    let mut backoff = Backoff::new(policy);
+    let mut starts = 0u64;
+    loop {
+        starts += 1;
+        info!("Supervisor starting the pipeline (start #{})", starts);
+        let started = Instant::now();
+        let status = std::process::Command::new(&exe).args(child_args).status()?;
+        let uptime = started.elapsed();
+        if let Some(dir) = &child.crash_dir {
+            rotate_crash_dumps(dir, policy.keep_crash_dumps);
+        }
+        if status.success() {
+            info!("Pipeline exited cleanly after {:?}", uptime);
+            return Ok(0);
+        }
+        if STOPPING.load(Ordering::SeqCst) {
+            info!("Pipeline stopped after the interrupt ({})", status);
+            return Ok(exit_code(status));
+        }
+        let Some(wait) = backoff.after_failure(uptime) else {
+            error!("Pipeline exited abnormally ({}) {} times in a row, giving up", status, backoff.restarts + 1);
+            return Ok(exit_code(status));
+        };
+        warn!(
+            "Pipeline exited abnormally ({}) after {:?}, restart {} of {} in {:?}",
+            status, uptime, backoff.restarts, policy.max_restarts, wait
+        );
+        // Short naps, so Ctrl-C during the backoff does not wait it out.
+        let until = Instant::now() + wait;
+        while Instant::now() < until {
+            if STOPPING.load(Ordering::SeqCst) {
+                return Ok(exit_code(status));
+            }
+            std::thread::sleep(Duration::from_millis(100).min(until - Instant::now()));
+        }
+    }
+}
+
+/// The child's exit code, or the shell's 128 + signal for a child a signal killed.
+fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        return 128 + signal;
+    }
+    status.code().unwrap_or(1)
+}
+
+/// Deletes all but the newest `keep` crash dumps in `dir`, so a crash loop cannot fill the disk.
+pub(crate) fn rotate_crash_dumps(dir: &Path, keep: usize) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut dumps: Vec<(std::time::SystemTime, PathBuf)> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_name().to_str().is_some_and(|name| name.starts_with("crash-") && name.ends_with(".txt")))
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .collect();
+    dumps.sort_by(|a, b| b.cmp(a));
+    for (_, path) in dumps.into_iter().skip(keep) {
+        match fs::remove_file(&path) {
+            Ok(()) => info!("Removed old crash dump {}", path.display()),
+            Err(e) => warn!("Could not remove old crash dump {}: {}", path.display(), e),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod supervise_tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let policy = RestartPolicy {
+            max_restarts: 3,
+            backoff: Duration::from_millis(100),
+            backoff_max: Duration::from_millis(250),
+            healthy_after: Duration::from_secs(10),
+            keep_crash_dumps: 2,
+        };
+        let mut backoff = Backoff::new(policy);
+        let quick = Duration::from_secs(1);
+        assert_eq!(backoff.after_failure(quick), Some(Duration::from_millis(100)));
+        assert_eq!(backoff.after_failure(quick), Some(Duration::from_millis(200)));
+        assert_eq!(backoff.after_failure(quick), Some(Duration::from_millis(250)));
+        assert_eq!(backoff.after_failure(quick), None);
+        // a child that ran long enough was healthy, so its failure starts over
+        assert_eq!(backoff.after_failure(Duration::from_secs(10)), Some(Duration::from_millis(100)));
+        assert_eq!(backoff.restarts, 1);
+    }
+
+    #[test]
+    fn test_rotate_crash_dumps() {
+        let dir = std::env::temp_dir().join(format!("robust-supervise-{}", crate::arg::new_run_id()));
+        fs::create_dir_all(&dir).expect("dir");
+        for n in 0..4 {
+            fs::write(dir.join(format!("crash-run-WORKER-{}.txt", n)), "dump").expect("write");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        fs::write(dir.join("notes.txt"), "kept").expect("write");
+        rotate_crash_dumps(&dir, 2);
+        let mut left: Vec<_> = fs::read_dir(&dir).expect("read").filter_map(|e| e.ok()?.file_name().into_string().ok()).collect();
+        left.sort();
+        assert_eq!(left, ["crash-run-WORKER-2.txt", "crash-run-WORKER-3.txt", "notes.txt"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
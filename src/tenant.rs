@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::MainArg;
+
+/// Tenant id of a value no source labeled.
+pub(crate) const UNTAGGED: u32 = 0;
+
+/// Distinct tenant names kept at once; later ones share the overflow tenant.
+const MAX_TENANTS: usize = 1024;
+const OVERFLOW: &str = "other";
+
+/// Tenant names by id, id 1 first. Envelopes stay `Copy` by carrying the id, not the name.
+static NAMES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Lookups of a name that found the registry full and were given the overflow tenant.
+static OVERFLOWED: AtomicU64 = AtomicU64::new(0);
+
+/// The id for a tenant name, handing out the next one the first time a name is seen.
+pub(crate) fn id(name: &str) -> u32 {
+    let mut names = NAMES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let name = if names.len() >= MAX_TENANTS && !names.iter().any(|n| n == name) {
+        OVERFLOWED.fetch_add(1, Ordering::Relaxed);
+        OVERFLOW
+    } else {
+        name
+    };
+    let index = match names.iter().position(|n| n == name) {
+        Some(index) => index,
+        None => {
+            names.push(name.to_string());
+            names.len() - 1
+        }
+    };
+    index as u32 + 1
+}
+
+/// How many lookups were folded into the overflow tenant since the process started.
+pub(crate) fn overflowed() -> u64 {
+    OVERFLOWED.load(Ordering::Relaxed)
+}
+
+/// The name behind a tenant id, `-` for untagged values.
+pub(crate) fn name(id: u32) -> String {
+    if id == UNTAGGED {
+        return "-".to_string();
+    }
+    let names = NAMES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    names.get(id as usize - 1).cloned().unwrap_or_else(|| format!("#{}", id))
+}
+
+/// Labels the generator's values with one of `--tenant-partitions` partitions, `partition-K`
+/// for value modulo the count. The ids are looked up once, so labeling costs nothing per value.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Partitions {
+    ids: Vec<u32>,
+}
+
+impl Partitions {
+    pub(crate) fn from_args(args: &MainArg) -> Self {
+        Partitions { ids: (0..args.tenant_partitions).map(|k| id(&format!("partition-{}", k))).collect() }
+    }
+
+    /// How many tenants the generator labels with; 0 when it labels none.
+    pub(crate) fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub(crate) fn tenant_of(&self, value: u64) -> u32 {
+        if self.ids.is_empty() { UNTAGGED } else { self.ids[(value % self.ids.len() as u64) as usize] }
+    }
+}
+
+/// Messages one tenant has had committed, as the logger publishes it to the reconciler.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TenantSample {
+    pub(crate) tenant: u32,
+    pub(crate) logged: u64,
+}
+
+/// `name: count` pairs for the logs, by tenant name.
+pub(crate) fn summary(counts: &BTreeMap<u32, u64>) -> String {
+    let mut entries: Vec<(String, u64)> = counts.iter().map(|(&tenant, &count)| (name(tenant), count)).collect();
+    entries.sort();
+    let entries: Vec<String> = entries.into_iter().map(|(tenant, count)| format!("{}: {}", tenant, count)).collect();
+    if entries.is_empty() { "none".to_string() } else { entries.join(", ") }
+}
+
+#[cfg(test)]
+pub(crate) mod tenant_tests {
+    use super::*;
+
+    #[test]
+    fn test_tenant_ids() {
+        let acme = id("tenant-test-acme");
+        assert_eq!(id("tenant-test-acme"), acme);
+        let globex = id("tenant-test-globex");
+        assert_ne!(globex, acme);
+        assert_eq!(name(acme), "tenant-test-acme");
+        assert_eq!(name(UNTAGGED), "-");
+        assert_eq!(summary(&BTreeMap::from([(globex, 2), (acme, 5)])), "tenant-test-acme: 5, tenant-test-globex: 2");
+
+        let partitions = Partitions::from_args(&MainArg { tenant_partitions: 3, ..Default::default() });
+        assert_eq!(name(partitions.tenant_of(7)), "partition-1");
+        assert_eq!(partitions.tenant_of(9), partitions.tenant_of(0));
+        assert_eq!(Partitions::default().tenant_of(7), UNTAGGED);
+    }
+}
@@ -0,0 +1,124 @@
+use std::time::{Duration, Instant};
+use crate::MainArg;
+
+/// Slowest the throttle ever lets the generator go, so it always probes for recovery.
+const MIN_RATE: f64 = 10.0;
+
+/// What the logger reports back to the generator for `--flow-target-inflight`:
+/// how many generated values it has settled in total, and how fast it settled them lately.
+#[derive(Copy, Clone, Default, Debug, PartialEq)]
+pub struct CommitFeedback {
+    /// Values logged, rejected, dropped or spilled, since the start of the run.
+    pub(crate) settled: u64,
+    /// Settle rate over the logger's last sample interval, in values per second.
+    pub(crate) per_sec: f64,
+}
+
+/// PI controller pacing the generator so the values it has sent but the logger has not
+/// yet settled stay near a target. The rate follows the logger's commit rate, pushed up
+/// when in flight is under the target and down when over it.
+/// Lives in the generator's state, so a restart keeps the rate it had learned.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Throttle {
+    target: f64,
+    kp: f64,
+    ki: f64,
+    integral: f64,
+    /// Current production rate, in values per second.
+    pub(crate) rate: f64,
+    allowance: f64,
+    refilled: Instant,
+    /// Observed in-flight depth: the latest reading, the deepest, and a running sum for the mean.
+    pub(crate) inflight: u64,
+    pub(crate) inflight_max: u64,
+    inflight_sum: u64,
+    pub(crate) readings: u64,
+}
+
+impl Throttle {
+    pub(crate) fn from_args(args: &MainArg) -> Option<Self> {
+        args.flow_target_inflight.map(|target| Throttle::new(target, args.flow_kp, args.flow_ki))
+    }
+
+    pub(crate) fn new(target: u64, kp: f64, ki: f64) -> Self {
+        let target = target as f64;
+        Throttle {
+            target, kp, ki,
+            integral: 0.0,
+            // one target's worth per second until the logger first reports back
+            rate: target.max(MIN_RATE),
+            allowance: 0.0,
+            refilled: Instant::now(),
+            inflight: 0,
+            inflight_max: 0,
+            inflight_sum: 0,
+            readings: 0,
+        }
+    }
+
+    /// Retunes the rate from one feedback sample, given how many values the generator has sent.
+    pub(crate) fn update(&mut self, sent: u64, feedback: CommitFeedback) {
+        let inflight = sent.saturating_sub(feedback.settled);
+        self.inflight = inflight;
+        self.inflight_max = self.inflight_max.max(inflight);
+        self.inflight_sum += inflight;
+        self.readings += 1;
+
+        let error = self.target - inflight as f64;
+        // Bounded so a long stall cannot wind the integral up into a flood afterwards.
+        self.integral = (self.integral + error).clamp(-10.0 * self.target, 10.0 * self.target);
+        self.rate = (feedback.per_sec + self.kp * error + self.ki * self.integral).max(MIN_RATE);
+    }
+
+    /// Spends one send if the current rate allows it at `now`. Bursts are capped at 100ms worth,
+    /// enough to cover a coarse timer waking the generator late without letting it flood.
+    pub(crate) fn try_take(&mut self, now: Instant) -> bool {
+        let earned = now.saturating_duration_since(self.refilled).as_secs_f64() * self.rate;
+        self.allowance = (self.allowance + earned).min((self.rate / 10.0).max(1.0));
+        self.refilled = now;
+        if self.allowance >= 1.0 {
+            self.allowance -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// How long until the next send is allowed.
+    pub(crate) fn delay(&self) -> Duration {
+        Duration::from_secs_f64((1.0 - self.allowance).max(0.0) / self.rate)
+    }
+
+    pub(crate) fn inflight_avg(&self) -> f64 {
+        if self.readings == 0 { 0.0 } else { self.inflight_sum as f64 / self.readings as f64 }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod throttle_tests {
+    use super::*;
+
+    #[test]
+    fn test_controller_tracks_target() {
+        let mut throttle = Throttle::new(100, 1.0, 0.0);
+        // under target: faster than the logger, to fill the pipeline up to the target
+        throttle.update(1_000, CommitFeedback { settled: 950, per_sec: 500.0 });
+        assert_eq!(throttle.inflight, 50);
+        assert_eq!(throttle.rate, 550.0);
+        // over target: slower than the logger, to let in flight fall back
+        throttle.update(2_000, CommitFeedback { settled: 1_700, per_sec: 500.0 });
+        assert_eq!(throttle.rate, 300.0);
+        // never stalls outright
+        throttle.update(5_000, CommitFeedback { settled: 1_700, per_sec: 0.0 });
+        assert_eq!(throttle.rate, MIN_RATE);
+        assert_eq!((throttle.inflight_max, throttle.readings), (3_300, 3));
+        assert_eq!(throttle.inflight_avg(), (50.0 + 300.0 + 3_300.0) / 3.0);
+
+        let start = Instant::now();
+        let mut paced = Throttle::new(100, 1.0, 0.0);
+        paced.refilled = start;
+        assert!(!paced.try_take(start));
+        assert!(paced.try_take(start + Duration::from_millis(10)));
+        assert!(paced.delay() > Duration::ZERO);
+    }
+}
@@ -0,0 +1,174 @@
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::classify::Payload;
+use crate::enrich::Derived;
+
+/// Both clocks read together once, the first time anything is stamped.
+/// Monotonic stamps are offsets from this instant, which keeps them plain integers.
+static ORIGIN: OnceLock<(Instant, SystemTime)> = OnceLock::new();
+
+fn origin() -> (Instant, SystemTime) {
+    *ORIGIN.get_or_init(|| (Instant::now(), SystemTime::now()))
+}
+
+/// When a message was generated, by both clocks.
+/// The monotonic side is for latency math and never jumps; the wall side is for
+/// people and exports, and may be stepped by NTP or an operator.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub struct Stamp {
+    /// Nanoseconds since the process origin on the monotonic clock.
+    pub mono_nanos: u64,
+    /// Microseconds since the Unix epoch on the wall clock.
+    pub wall_micros: u64,
+}
+
+impl Stamp {
+    pub fn now() -> Self {
+        let (start, _) = origin();
+        Stamp {
+            mono_nanos: start.elapsed().as_nanos() as u64,
+            wall_micros: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_micros() as u64)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Time since the stamp was taken, on the monotonic clock.
+    pub fn elapsed(&self) -> Duration {
+        let (start, _) = origin();
+        start.elapsed().saturating_sub(Duration::from_nanos(self.mono_nanos))
+    }
+
+    pub fn wall_time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_micros(self.wall_micros)
+    }
+}
+
+/// Minutes since local midnight on the wall clock, for schedules written as times of day.
+pub(crate) fn local_minute_of_day() -> u32 {
+    use chrono::Timelike;
+    let now = chrono::Local::now();
+    now.hour() * 60 + now.minute()
+}
+
+/// How far the wall clock has drifted from the monotonic clock since the origin,
+/// in microseconds; positive when the wall clock has run ahead.
+pub(crate) fn clock_skew_micros() -> i64 {
+    let (start, wall_start) = origin();
+    let mono = start.elapsed().as_micros() as i64;
+    let wall = match SystemTime::now().duration_since(wall_start) {
+        Ok(ahead) => ahead.as_micros() as i64,
+        Err(behind) => -(behind.duration().as_micros() as i64),
+    };
+    wall - mono
+}
+
+/// A payload with the stamp of the value it came from.
+/// Stages that transform the payload keep the original stamp, so the logger
+/// measures latency from generation, not from the last hop.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub struct Envelope<T> {
+    pub payload: T,
+    pub stamp: Stamp,
+    /// Position in the worker's output stream, counting from 1; 0 when not sequenced.
+    /// Lets the logger recognize a message it already handled.
+    pub seq: u64,
+    /// Trace id for `--trace-sample`, nonzero only on sampled messages. Carried unchanged
+    /// through every stage so one message's trace events can be joined.
+    pub trace: u64,
+    /// Results of the `--compute` modules, filled in by the worker when it classifies.
+    pub(crate) derived: Derived,
+    /// Tenant the source labeled the value with, an id from the `tenant` registry; 0 when unlabeled.
+    pub tenant: u32,
+    /// Event time the worker assigned from the heartbeat that released the value, in wall
+    /// microseconds; 0 without `--event-window-ms`.
+    pub event_micros: u64,
+    /// Position the router gave the value with `--workers`, counting from 1; 0 otherwise.
+    /// The fan-in puts the pool's output back in this order.
+    pub route: u64,
+    /// Priority the source gave the value with `--priority`, higher first; 0 when it gave none.
+    pub priority: u8,
+}
+
+impl<T> Envelope<T> {
+    /// Wraps a freshly generated payload, stamping it now.
+    pub fn new(payload: T) -> Self {
+        Envelope { payload, stamp: Stamp::now(), seq: 0, trace: 0, derived: Derived::default(), tenant: 0, event_micros: 0, route: 0, priority: 0 }
+    }
+
+    /// Wraps a payload with a zero stamp, so tests and staging harnesses can compare envelopes exactly.
+    #[cfg(any(test, feature = "stage-control"))]
+    pub fn unstamped(payload: T) -> Self {
+        Envelope { payload, stamp: Stamp::default(), seq: 0, trace: 0, derived: Derived::default(), tenant: 0, event_micros: 0, route: 0, priority: 0 }
+    }
+
+    /// Replaces the payload, keeping the generation stamp, sequence, trace id, derived data, tenant, event time, route and priority.
+    pub fn carry<U>(&self, payload: U) -> Envelope<U> {
+        Envelope {
+            payload, stamp: self.stamp, seq: self.seq, trace: self.trace, derived: self.derived, tenant: self.tenant,
+            event_micros: self.event_micros, route: self.route, priority: self.priority,
+        }
+    }
+
+    pub fn with_seq(mut self, seq: u64) -> Self {
+        self.seq = seq;
+        self
+    }
+
+    pub fn with_trace(mut self, trace: u64) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    pub(crate) fn with_derived(mut self, derived: Derived) -> Self {
+        self.derived = derived;
+        self
+    }
+
+    pub fn with_tenant(mut self, tenant: u32) -> Self {
+        self.tenant = tenant;
+        self
+    }
+
+    pub fn with_event_time(mut self, event_micros: u64) -> Self {
+        self.event_micros = event_micros;
+        self
+    }
+
+    pub(crate) fn with_route(mut self, route: u64) -> Self {
+        self.route = route;
+        self
+    }
+
+    pub fn with_priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+impl<T: Payload> Envelope<T> {
+    /// Bytes this envelope stands for in a channel: its slot, with the payload counted by
+    /// `Payload::wire_bytes` so data the payload refers to is included.
+    pub fn wire_bytes(&self) -> u64 {
+        (size_of::<Self>() - size_of::<T>()) as u64 + self.payload.wire_bytes()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod timing_tests {
+    use super::*;
+
+    #[test]
+    fn test_stamp_and_carry() {
+        let sent = Envelope::new(15u64);
+        std::thread::sleep(Duration::from_millis(5));
+        let later = Stamp::now();
+        assert!(later.mono_nanos > sent.stamp.mono_nanos);
+        assert!(sent.stamp.elapsed() >= Duration::from_millis(5));
+        assert!(sent.stamp.wall_time() > UNIX_EPOCH);
+        assert_eq!(sent.carry("FizzBuzz").stamp, sent.stamp);
+        // the two clocks were read together at the origin, so they barely disagree
+        assert!(clock_skew_micros().abs() < 1_000_000);
+    }
+}
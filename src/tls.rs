@@ -0,0 +1,143 @@
+use std::io;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "mqtt")]
+use std::sync::Arc;
+#[cfg(feature = "mqtt")]
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject};
+#[cfg(feature = "mqtt")]
+use steady_state::*;
+use crate::MainArg;
+
+/// The certificate files the network endpoints use. They are read again each time the gRPC
+/// server starts or an MQTT link reconnects, so certificates replaced on disk take effect then.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct TlsFiles {
+    /// From `--tls-cert` and `--tls-key`: what the gRPC source serves and the MQTT links present.
+    pub(crate) identity: Option<(PathBuf, PathBuf)>,
+    /// From `--tls-ca`: MQTT brokers must chain to these, and so must gRPC clients.
+    pub(crate) roots: Option<PathBuf>,
+}
+
+impl TlsFiles {
+    pub(crate) fn from_args(args: &MainArg) -> Self {
+        TlsFiles {
+            identity: args.tls_cert.clone().zip(args.tls_key.clone()),
+            roots: args.tls_ca.clone(),
+        }
+    }
+
+    /// The gRPC source's TLS settings, None to serve plain text. With roots it also asks every
+    /// client for a certificate and refuses those that do not chain to them.
+    #[cfg(feature = "grpc")]
+    pub(crate) fn server(&self) -> io::Result<Option<tonic::transport::ServerTlsConfig>> {
+        use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+        let Some((cert, key)) = &self.identity else {
+            return Ok(None);
+        };
+        let mut config = ServerTlsConfig::new().identity(Identity::from_pem(read(cert)?, read(key)?));
+        if let Some(roots) = &self.roots {
+            config = config.client_ca_root(Certificate::from_pem(read(roots)?));
+        }
+        Ok(Some(config))
+    }
+
+    /// The MQTT links' TLS settings, None without roots to check the broker against. Sessions
+    /// are never resumed, so every reconnect checks the broker's certificate in full.
+    #[cfg(feature = "mqtt")]
+    pub(crate) fn client(&self) -> io::Result<Option<Arc<rustls::ClientConfig>>> {
+        let Some(roots_path) = &self.roots else {
+            return Ok(None);
+        };
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in certificates(roots_path)? {
+            roots.add(cert).map_err(|e| invalid(roots_path, e))?;
+        }
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = rustls::ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(io::Error::other)?
+            .with_root_certificates(roots);
+        let mut config = match &self.identity {
+            Some((cert, key)) => {
+                let key_der = PrivateKeyDer::from_pem_file(key).map_err(|e| invalid(key, e))?;
+                builder.with_client_auth_cert(certificates(cert)?, key_der).map_err(|e| invalid(cert, e))?
+            }
+            None => builder.with_no_client_auth(),
+        };
+        config.resumption = rustls::client::Resumption::disabled();
+        Ok(Some(Arc::new(config)))
+    }
+
+    /// Called by an MQTT link between connection attempts: reads the files again for the next
+    /// one. If they cannot be read, say halfway through being replaced, the last good settings stay.
+    #[cfg(feature = "mqtt")]
+    pub(crate) fn refresh(&self, options: &mut rumqttc::MqttOptions) {
+        match self.client() {
+            Ok(Some(config)) => {
+                options.set_transport(rumqttc::Transport::tls_with_config(rumqttc::TlsConfiguration::Rustls(config)));
+            }
+            Ok(None) => {}
+            Err(e) => warn!("MQTT keeps its previous TLS settings, the certificate files could not be read: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "grpc")]
+fn read(path: &Path) -> io::Result<Vec<u8>> {
+    std::fs::read(path).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+}
+
+#[cfg(feature = "mqtt")]
+fn certificates(path: &Path) -> io::Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| invalid(path, e))?;
+    if certs.is_empty() {
+        return Err(invalid(path, "no certificates in the file"));
+    }
+    Ok(certs)
+}
+
+#[cfg(feature = "mqtt")]
+fn invalid(path: &Path, e: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+pub(crate) mod tls_tests {
+    use super::*;
+
+    /// A CA, and a server and a client certificate it signed, valid for a century.
+    /// The server's names are localhost, 127.0.0.1 and ::1.
+    pub(crate) fn test_files(with_client_cert: bool) -> TlsFiles {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/tls");
+        let who = if with_client_cert { "client" } else { "server" };
+        TlsFiles {
+            identity: Some((dir.join(format!("{}.pem", who)), dir.join(format!("{}.key", who)))),
+            roots: Some(dir.join("ca.pem")),
+        }
+    }
+
+    #[test]
+    fn test_files_are_read_or_reported() {
+        assert_eq!(TlsFiles::from_args(&MainArg::default()), TlsFiles::default());
+        let missing = || TlsFiles { identity: Some(("no-such.pem".into(), "no-such.key".into())), roots: Some("no-such-ca.pem".into()) };
+        #[cfg(feature = "grpc")]
+        {
+            assert!(TlsFiles::default().server().expect("nothing to read").is_none());
+            let err = missing().server().expect_err("missing files");
+            assert!(err.to_string().starts_with("no-such.pem"), "{}", err);
+            assert!(test_files(false).server().expect("readable").is_some());
+        }
+        #[cfg(feature = "mqtt")]
+        {
+            assert!(TlsFiles::default().client().expect("nothing to read").is_none());
+            assert!(missing().client().is_err());
+            assert!(test_files(true).client().expect("readable").is_some());
+            // a key where the roots belong holds no certificates
+            let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/tls");
+            let wrong = TlsFiles { identity: None, roots: Some(dir.join("server.key")) };
+            assert_eq!(wrong.client().expect_err("no certificates").kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use std::fmt::Debug;
+use steady_state::*;
+use crate::timing::Envelope;
+use crate::MainArg;
+
+/// `--trace-sample K/N`: K of every N generated messages are traced.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct TraceSample {
+    pub(crate) marked: u64,
+    pub(crate) every: u64,
+}
+
+/// Parses `K/N` with 0 < K <= N, or a bare `N` meaning one in N.
+pub(crate) fn parse_trace_sample(text: &str) -> Result<TraceSample, String> {
+    let (marked, every) = text.split_once('/').unwrap_or(("1", text));
+    let parse = |part: &str| part.trim().parse::<u64>().map_err(|e| format!("bad sample rate '{}': {}", text, e));
+    let (marked, every) = (parse(marked)?, parse(every)?);
+    if marked == 0 || marked > every {
+        return Err(format!("sample rate '{}' must be K/N with 0 < K <= N", text));
+    }
+    Ok(TraceSample { marked, every })
+}
+
+impl TraceSample {
+    pub(crate) fn from_args(args: &MainArg) -> Option<Self> {
+        args.trace_sample
+    }
+
+    /// Trace id for the message at `ordinal` (counting from 0) in a source's output, or 0 if it is
+    /// not sampled. Sampling by position, not at random, keeps a rerun tracing the same messages.
+    pub(crate) fn trace_id(&self, ordinal: u64) -> u64 {
+        if ordinal % self.every < self.marked { ordinal + 1 } else { 0 }
+    }
+}
+
+/// Logs one structured event for a traced message and nothing for the rest.
+/// Every event carries the trace id and generation stamp, so grepping the id
+/// reconstructs the message's path and the time it took to reach each stage.
+pub(crate) fn event<T: Debug>(stage: &str, event: &str, envelope: &Envelope<T>) {
+    if envelope.trace != 0 {
+        info!(
+            "trace id={} stage={} event={} payload={:?} seq={} generated_us={} age_us={}",
+            envelope.trace, stage, event, envelope.payload, envelope.seq,
+            envelope.stamp.wall_micros, envelope.stamp.elapsed().as_micros()
+        );
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod trace_tests {
+    use super::*;
+
+    #[test]
+    fn test_sampling() {
+        assert_eq!(parse_trace_sample("1/1000"), Ok(TraceSample { marked: 1, every: 1000 }));
+        assert_eq!(parse_trace_sample("100"), Ok(TraceSample { marked: 1, every: 100 }));
+        assert!(parse_trace_sample("0/10").is_err());
+        assert!(parse_trace_sample("3/2").is_err());
+        assert!(parse_trace_sample("1/x").is_err());
+
+        let sample = parse_trace_sample("2/5").expect("valid");
+        let ids: Vec<u64> = (0..10).map(|n| sample.trace_id(n)).collect();
+        assert_eq!(ids, [1, 2, 0, 0, 0, 6, 7, 0, 0, 0]);
+    }
+}
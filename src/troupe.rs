@@ -0,0 +1,92 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use steady_state::*;
+use crate::MainArg;
+
+/// How a troupe member used the shared thread. Lives in the actor's state, so it covers the
+/// whole run; the final report shows it for each member to check that none held the thread.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Fairness {
+    pub(crate) iterations: u64,
+    /// Iterations that found work ready and so did not give the thread up while waiting.
+    pub(crate) busy_iterations: u64,
+    /// Times the member used up its `--troupe-budget` and yielded to the others.
+    pub(crate) forced_yields: u64,
+    /// The longest run of busy iterations, and how long it held the thread.
+    pub(crate) longest_run: u64,
+    pub(crate) longest_run_us: u64,
+}
+
+impl fmt::Display for Fairness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f, "{} iterations ({} busy), {} yields over budget, longest run {} ({}us)",
+            self.iterations, self.busy_iterations, self.forced_yields, self.longest_run, self.longest_run_us
+        )
+    }
+}
+
+/// A troupe member's iteration budget: how many busy iterations it may run in a row before it
+/// yields the thread, whether or not it still has work. Held as a local, so a restart starts
+/// a fresh run.
+pub(crate) struct TurnBudget {
+    /// From `--troupe-budget`; None outside troupe mode.
+    limit: Option<u64>,
+    run: u64,
+    run_started: Instant,
+}
+
+impl TurnBudget {
+    pub(crate) fn from_args(args: &MainArg) -> Self {
+        TurnBudget {
+            limit: (args.troupe && args.troupe_budget > 0).then_some(args.troupe_budget),
+            run: 0,
+            run_started: Instant::now(),
+        }
+    }
+
+    /// Books one iteration, `ready` when it had work without waiting for any. True once the
+    /// budget is spent; the caller then yields, which starts the next run.
+    pub(crate) fn spend(&mut self, ready: bool, fairness: &mut Fairness) -> bool {
+        fairness.iterations += 1;
+        if !ready {
+            self.run = 0;
+            return false;
+        }
+        fairness.busy_iterations += 1;
+        if self.run == 0 {
+            self.run_started = Instant::now();
+        }
+        self.run += 1;
+        fairness.longest_run = fairness.longest_run.max(self.run);
+        fairness.longest_run_us = fairness.longest_run_us.max(self.run_started.elapsed().as_micros() as u64);
+        if self.limit.is_some_and(|limit| self.run >= limit) {
+            fairness.forced_yields += 1;
+            self.run = 0;
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod troupe_tests {
+    use super::*;
+
+    #[test]
+    fn test_budget_yields_after_limit() {
+        let mut fairness = Fairness::default();
+        let mut budget = TurnBudget::from_args(&MainArg { troupe: true, troupe_budget: 3, ..Default::default() });
+        let yields: Vec<bool> = [true, true, true, true, false, true, true, true]
+            .into_iter().map(|ready| budget.spend(ready, &mut fairness)).collect();
+        assert_eq!(yields, [false, false, true, false, false, false, false, true]);
+        assert_eq!((fairness.iterations, fairness.busy_iterations, fairness.forced_yields, fairness.longest_run), (8, 7, 2, 3));
+
+        // outside troupe mode the thread is the member's own: counted, never yielded
+        let mut solo = TurnBudget::from_args(&MainArg { troupe_budget: 3, ..Default::default() });
+        let mut fairness = Fairness::default();
+        assert!((0..10).all(|_| !solo.spend(true, &mut fairness)));
+        assert_eq!(fairness.longest_run, 10);
+    }
+}
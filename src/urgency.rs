@@ -0,0 +1,170 @@
+use std::any::Any;
+use std::cmp::Reverse;
+use crate::classify::Payload;
+use crate::timing::Envelope;
+use crate::MainArg;
+
+/// One `--priority` setting: values that are multiples of `multiple` get `level`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct PriorityRule {
+    pub(crate) multiple: u64,
+    pub(crate) level: u8,
+}
+
+/// Parses `MULTIPLE=LEVEL`, e.g. `15=9`.
+pub(crate) fn parse_priority_rule(text: &str) -> Result<PriorityRule, String> {
+    let (multiple, level) = text
+        .split_once('=')
+        .ok_or_else(|| format!("expected MULTIPLE=LEVEL, got '{}'", text))?;
+    let multiple = match multiple.trim().parse() {
+        Ok(0) => return Err(format!("multiple must be at least 1 in '{}'", text)),
+        Ok(multiple) => multiple,
+        Err(e) => return Err(format!("bad multiple in '{}': {}", text, e)),
+    };
+    let level = level.trim().parse().map_err(|e| format!("bad level in '{}', expected 0 to 255: {}", text, e))?;
+    Ok(PriorityRule { multiple, level })
+}
+
+/// The priorities the generator gives its values: the highest level of the rules a value
+/// matches, 0 when it matches none.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Priorities(Vec<PriorityRule>);
+
+impl Priorities {
+    pub(crate) fn from_args(args: &MainArg) -> Self {
+        Priorities(args.priority.clone())
+    }
+
+    pub(crate) fn of(&self, value: u64) -> u8 {
+        self.0.iter().filter(|rule| value.is_multiple_of(rule.multiple)).map(|rule| rule.level).max().unwrap_or(0)
+    }
+}
+
+/// How the worker ranks values: by priority, raised a level for every `--priority-aging-ms`
+/// since the value was generated. A waiting value's rank keeps rising until it passes that of
+/// anything newer, so however many higher priority values arrive, a low priority one still goes
+/// within (255 - its priority + 1) x the aging step plus the time to send what was ranked above it.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Aging {
+    step_ms: u64,
+}
+
+impl Aging {
+    pub(crate) fn from_args(args: &MainArg) -> Self {
+        Aging { step_ms: args.priority_aging_ms.max(1) }
+    }
+
+    pub(crate) fn rank<T>(&self, envelope: &Envelope<T>) -> u64 {
+        envelope.priority as u64 + envelope.stamp.elapsed().as_millis() as u64 / self.step_ms
+    }
+}
+
+/// Values the worker has taken off its input with `--priority-window`, waiting for their turn.
+pub(crate) struct Waiting<P> {
+    values: Vec<Envelope<P>>,
+}
+
+impl<P> Default for Waiting<P> {
+    fn default() -> Self {
+        Waiting { values: Vec::new() }
+    }
+}
+
+impl<P: Payload> Waiting<P> {
+    pub(crate) fn push(&mut self, envelope: Envelope<P>) {
+        self.values.push(envelope);
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub(crate) fn get(&self, index: usize) -> Envelope<P> {
+        self.values[index]
+    }
+
+    /// The indexes of the `n` values that go next, best ranked first and, among equals, in the
+    /// order they arrived.
+    pub(crate) fn next(&self, n: usize, aging: Aging) -> Vec<usize> {
+        let mut ranked: Vec<(u64, usize)> = self.values.iter().enumerate().map(|(index, envelope)| (aging.rank(envelope), index)).collect();
+        ranked.sort_by_key(|&(rank, index)| (Reverse(rank), index));
+        ranked.into_iter().take(n).map(|(_, index)| index).collect()
+    }
+
+    /// Removes the values at `indexes`, once they have been sent.
+    pub(crate) fn remove(&mut self, indexes: &[usize]) {
+        let mut index = 0;
+        self.values.retain(|_| {
+            index += 1;
+            !indexes.contains(&(index - 1))
+        });
+    }
+
+    pub(crate) fn take_all(&mut self) -> Vec<Envelope<P>> {
+        std::mem::take(&mut self.values)
+    }
+}
+
+/// A `Waiting` of some payload, so the worker's state can hold one without being typed by it.
+trait Held: Send {
+    fn len(&self) -> usize;
+    fn as_any(&mut self) -> &mut dyn Any;
+}
+
+impl<P: Payload> Held for Waiting<P> {
+    fn len(&self) -> usize {
+        Waiting::len(self)
+    }
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Where the worker keeps its `Waiting` values, empty until the first one arrives.
+#[derive(Default)]
+pub(crate) struct WaitingSlot(Option<Box<dyn Held>>);
+
+impl WaitingSlot {
+    pub(crate) fn get<P: Payload>(&mut self) -> &mut Waiting<P> {
+        self.0.get_or_insert_with(|| Box::new(Waiting::<P>::default()))
+            .as_any()
+            .downcast_mut()
+            .expect("the worker's payload does not change")
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.0.as_ref().map_or(0, |held| held.len())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod urgency_tests {
+    use steady_state::*;
+    use super::*;
+
+    #[test]
+    fn test_aged_values_overtake_fresh_priorities() {
+        assert_eq!(parse_priority_rule("15=9"), Ok(PriorityRule { multiple: 15, level: 9 }));
+        assert!(parse_priority_rule("0=1").is_err());
+        assert!(parse_priority_rule("3=256").is_err());
+        let priorities = Priorities(vec![parse_priority_rule("3=2").expect("rule"), parse_priority_rule("5=7").expect("rule")]);
+        assert_eq!((priorities.of(9), priorities.of(15), priorities.of(7)), (2, 7, 0));
+
+        let aging = Aging { step_ms: 10 };
+        let mut slot = WaitingSlot::default();
+        let waiting = slot.get::<u64>();
+        waiting.push(Envelope::new(1));
+        waiting.push(Envelope::new(2).with_priority(1));
+        waiting.push(Envelope::new(3));
+        assert_eq!(waiting.next(2, aging), [1, 0]);
+
+        // aged three steps, the old values outrank a fresh priority 2
+        std::thread::sleep(Duration::from_millis(35));
+        waiting.push(Envelope::new(4).with_priority(2));
+        assert_eq!(waiting.next(4, aging), [1, 0, 2, 3]);
+        waiting.remove(&[1, 3]);
+        assert_eq!(slot.len(), 2);
+        let left: Vec<u64> = slot.get::<u64>().take_all().iter().map(|e| e.payload).collect();
+        assert_eq!(left, [1, 3]);
+    }
+}
@@ -0,0 +1,114 @@
+use std::ops::RangeInclusive;
+use crate::MainArg;
+
+/// Why the worker refused a value before classifying it.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub enum RejectReason {
+    /// The value fell outside `--accept-range`.
+    #[default]
+    OutOfRange,
+    /// The value is a multiple of one of the `--reject-multiples-of` divisors.
+    MultipleOf(u64),
+    /// The value waited past `--processing-deadline-ms` and the deadline action is `dead-letter`.
+    Expired,
+}
+
+/// A value diverted to the side output channel instead of the logger.
+/// Kept `Copy` and small so it travels the channel as cheaply as the main stream.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub struct RejectedValue {
+    pub value: u64,
+    pub reason: RejectReason,
+    /// The value's route with `--workers`, telling the fan-in not to wait for it; 0 otherwise.
+    pub route: u64,
+}
+
+impl RejectedValue {
+    pub fn new(value: u64, reason: RejectReason) -> Self {
+        RejectedValue { value, reason, route: 0 }
+    }
+
+    pub(crate) fn with_route(mut self, route: u64) -> Self {
+        self.route = route;
+        self
+    }
+}
+
+/// Validation rules the worker evaluates before classification.
+/// With no rules configured every value is accepted.
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct ValidationRules {
+    pub accept_range: Option<RangeInclusive<u64>>,
+    pub reject_multiples_of: Vec<u64>,
+}
+
+impl ValidationRules {
+    pub fn from_args(args: &MainArg) -> Self {
+        ValidationRules {
+            accept_range: args.accept_range.clone(),
+            reject_multiples_of: args.reject_multiples_of.clone(),
+        }
+    }
+
+    /// Returns the first rule the value breaks, range first, then divisors in the order given.
+    pub fn check(&self, value: u64) -> Option<RejectReason> {
+        if let Some(range) = &self.accept_range
+            && !range.contains(&value) {
+            return Some(RejectReason::OutOfRange);
+        }
+        self.reject_multiples_of
+            .iter()
+            .find(|&&divisor| value.is_multiple_of(divisor))
+            .map(|&divisor| RejectReason::MultipleOf(divisor))
+    }
+}
+
+/// Parses `--accept-range` written as a Rust range, either `start..end` or `start..=end`.
+pub(crate) fn parse_accept_range(text: &str) -> Result<RangeInclusive<u64>, String> {
+    let (start, end, inclusive) = if let Some((s, e)) = text.split_once("..=") {
+        (s, e, true)
+    } else if let Some((s, e)) = text.split_once("..") {
+        (s, e, false)
+    } else {
+        return Err(format!("expected START..END or START..=END, got '{}'", text));
+    };
+    let start: u64 = start.trim().parse().map_err(|e| format!("bad range start '{}': {}", start, e))?;
+    let end: u64 = end.trim().parse().map_err(|e| format!("bad range end '{}': {}", end, e))?;
+    let end = if inclusive {
+        end
+    } else {
+        end.checked_sub(1).ok_or_else(|| format!("range '{}' is empty", text))?
+    };
+    if start > end {
+        return Err(format!("range '{}' is empty", text));
+    }
+    Ok(start..=end)
+}
+
+#[cfg(test)]
+pub(crate) mod validation_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_accept_range() {
+        assert_eq!(parse_accept_range("0..1000000"), Ok(0..=999_999));
+        assert_eq!(parse_accept_range("10..=20"), Ok(10..=20));
+        assert!(parse_accept_range("5..5").is_err());
+        assert!(parse_accept_range("9..=3").is_err());
+        assert!(parse_accept_range("seven").is_err());
+    }
+
+    #[test]
+    fn test_check_rules() {
+        let rules = ValidationRules {
+            accept_range: Some(1..=100),
+            reject_multiples_of: vec![11, 7],
+        };
+        assert_eq!(rules.check(0), Some(RejectReason::OutOfRange));
+        assert_eq!(rules.check(101), Some(RejectReason::OutOfRange));
+        assert_eq!(rules.check(77), Some(RejectReason::MultipleOf(11)));
+        assert_eq!(rules.check(14), Some(RejectReason::MultipleOf(7)));
+        assert_eq!(rules.check(15), None);
+        assert_eq!(ValidationRules::default().check(0), None);
+    }
+}
@@ -0,0 +1,123 @@
+use std::fmt;
+use std::future::Future;
+use steady_state::*;
+use crate::MainArg;
+
+/// The stages whose idle wait a `--wait` setting can change; pool workers go by their shard name.
+pub(crate) const WAIT_ACTORS: [&str; 5] = [
+    crate::NAME_GENERATOR, crate::NAME_WORKER, crate::NAME_LOGGER, crate::NAME_DEAD_LETTER, crate::NAME_ENRICHER,
+];
+
+/// How an actor waits for work between iterations of its loop.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) enum WaitStrategy {
+    /// Wakes only when its channels are ready, as every actor does by default.
+    #[default]
+    Event,
+    /// Wakes on a fixed interval and ignores its channels, for a loop that also looks at
+    /// something outside the graph that cannot wake it.
+    Poll(Duration),
+    /// Wakes when its channels are ready or once the interval has passed, whichever comes first.
+    Hybrid(Duration),
+}
+
+impl WaitStrategy {
+    /// Waits as the strategy says, `event` being the wait the actor would do on its own. A wait
+    /// the interval ended returns true like one whose channels became ready, so the loop looks
+    /// at its channels as it does after any wait and finds whatever is there.
+    pub(crate) async fn wait<A: SteadyActor>(self, actor: &A, event: impl Future<Output = bool>) -> bool {
+        match self {
+            WaitStrategy::Event => event.await,
+            WaitStrategy::Poll(every) => actor.wait_periodic(every).await,
+            WaitStrategy::Hybrid(every) => await_for_any!(event, actor.wait_periodic(every)),
+        }
+    }
+}
+
+impl fmt::Display for WaitStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WaitStrategy::Event => write!(f, "event-driven"),
+            WaitStrategy::Poll(every) => write!(f, "polling every {:?}", every),
+            WaitStrategy::Hybrid(every) => write!(f, "event-driven, polling at least every {:?}", every),
+        }
+    }
+}
+
+/// One `--wait ACTOR=STRATEGY` setting.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct WaitSetting {
+    /// Actor name as used in the graph, upper case.
+    pub(crate) actor: String,
+    pub(crate) strategy: WaitStrategy,
+}
+
+/// Parses `ACTOR=event`, `ACTOR=poll:MS` or `ACTOR=hybrid:MS`, with the actor name in any case.
+pub(crate) fn parse_wait(text: &str) -> Result<WaitSetting, String> {
+    let (actor, strategy) = text
+        .split_once('=')
+        .ok_or_else(|| format!("expected ACTOR=STRATEGY, got '{}'", text))?;
+    let actor = actor.trim().to_ascii_uppercase();
+    if actor.is_empty() {
+        return Err(format!("missing actor name in '{}'", text));
+    }
+    let (kind, interval) = match strategy.trim().split_once(':') {
+        Some((kind, ms)) => {
+            let ms: u64 = ms.trim().parse().map_err(|e| format!("bad interval in '{}': {}", text, e))?;
+            if ms == 0 {
+                return Err(format!("interval in '{}' must be at least 1 ms", text));
+            }
+            (kind.trim(), Some(Duration::from_millis(ms)))
+        }
+        None => (strategy.trim(), None),
+    };
+    let strategy = match (kind, interval) {
+        ("event", None) => WaitStrategy::Event,
+        ("poll", Some(every)) => WaitStrategy::Poll(every),
+        ("hybrid", Some(every)) => WaitStrategy::Hybrid(every),
+        ("event", Some(_)) => return Err(format!("'event' in '{}' takes no interval", text)),
+        ("poll" | "hybrid", None) => return Err(format!("'{}' in '{}' needs an interval, e.g. {}:50", kind, text, kind)),
+        _ => return Err(format!("unknown wait strategy '{}' in '{}', expected event, poll:MS or hybrid:MS", kind, text)),
+    };
+    Ok(WaitSetting { actor, strategy })
+}
+
+/// The wait strategy configured for an actor; the last setting given for it wins.
+pub(crate) fn strategy_for(args: &MainArg, actor: &str) -> WaitStrategy {
+    args.wait.iter().rev().find(|s| s.actor == actor).map(|s| s.strategy).unwrap_or_default()
+}
+
+/// Looks up the wait strategy for the actor by the name it was built under, which tells the
+/// workers of a pool apart, and logs it. Called on every start, so a restarted actor keeps its
+/// strategy and says so again.
+pub(crate) fn strategy<A: SteadyActor>(context: &A) -> WaitStrategy {
+    let actor = context.identity().label.name;
+    let strategy = context.args::<MainArg>().map(|args| strategy_for(args, actor)).unwrap_or_default();
+    info!("{} wait strategy: {}", actor, strategy);
+    strategy
+}
+
+#[cfg(test)]
+pub(crate) mod wait_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_wait() {
+        assert_eq!(parse_wait("logger=poll:50"), Ok(WaitSetting { actor: "LOGGER".into(), strategy: WaitStrategy::Poll(Duration::from_millis(50)) }));
+        assert_eq!(parse_wait("WORKER = hybrid:20").map(|s| s.strategy), Ok(WaitStrategy::Hybrid(Duration::from_millis(20))));
+        assert_eq!(parse_wait("worker_1=event").map(|s| s.strategy), Ok(WaitStrategy::Event));
+        assert!(parse_wait("logger").is_err());
+        assert!(parse_wait("=poll:5").is_err());
+        assert!(parse_wait("logger=poll").is_err());
+        assert!(parse_wait("logger=poll:0").is_err());
+        assert!(parse_wait("logger=event:5").is_err());
+        assert!(parse_wait("logger=spin").is_err());
+
+        let args = MainArg {
+            wait: vec![parse_wait("logger=poll:5").unwrap(), parse_wait("logger=hybrid:10").unwrap()],
+            ..Default::default()
+        };
+        assert_eq!(strategy_for(&args, "LOGGER"), WaitStrategy::Hybrid(Duration::from_millis(10)));
+        assert_eq!(strategy_for(&args, "WORKER"), WaitStrategy::Event);
+    }
+}
@@ -0,0 +1,365 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use steady_state::*;
+
+/// How WAL entries are stored on disk.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) enum WalCodec {
+    /// One text line per entry.
+    #[default]
+    Plain,
+    /// One zstd frame per entry at this level, so every frame ends at a commit point.
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+}
+
+impl WalCodec {
+    pub(crate) fn from_args(_args: &crate::MainArg) -> Self {
+        #[cfg(feature = "zstd")]
+        if let Some(level) = _args.wal_zstd_level {
+            return WalCodec::Zstd(level);
+        }
+        WalCodec::Plain
+    }
+
+    /// Bytes of one entry as written to the file.
+    fn encode(self, line: &str) -> io::Result<Vec<u8>> {
+        match self {
+            WalCodec::Plain => Ok(line.as_bytes().to_vec()),
+            #[cfg(feature = "zstd")]
+            WalCodec::Zstd(level) => zstd::bulk::compress(line.as_bytes(), level),
+        }
+    }
+
+    /// Length and text of the first whole entry in `data`, or None when `data` starts
+    /// with an entry a crash cut short.
+    fn decode_first(self, data: &[u8]) -> Option<(usize, String)> {
+        match self {
+            WalCodec::Plain => {
+                let end = data.iter().position(|&b| b == b'\n')? + 1;
+                Some((end, String::from_utf8(data[..end].to_vec()).ok()?))
+            }
+            #[cfg(feature = "zstd")]
+            WalCodec::Zstd(_) => {
+                let end = zstd::zstd_safe::find_frame_compressed_size(data).ok()?;
+                let line = zstd::stream::decode_all(&data[..end]).ok()?;
+                Some((end, String::from_utf8(line).ok()?))
+            }
+        }
+    }
+
+    fn reader(self, file: File) -> io::Result<Box<dyn Read>> {
+        match self {
+            WalCodec::Plain => Ok(Box::new(file)),
+            // the decoder runs on across frame boundaries, so the entries read back as one text stream
+            #[cfg(feature = "zstd")]
+            WalCodec::Zstd(_) => Ok(Box::new(zstd::stream::read::Decoder::new(file)?)),
+        }
+    }
+}
+
+/// Every this many entries the WAL notes where the next one starts, so readers and recovery
+/// seek close to a sequence instead of scanning the whole file.
+const INDEX_EVERY: u64 = 1024;
+
+/// Append-only write-ahead log of logger records.
+/// Each line is `<seq> <record>`, with sequence numbers starting at zero and
+/// increasing by one, so a reader can resume delivery from any sequence.
+/// A sidecar `<path>.idx` holds a `<seq> <offset>` line for every `INDEX_EVERY`th entry.
+pub(crate) struct Wal {
+    path: PathBuf,
+    out: BufWriter<File>,
+    next_seq: u64,
+    codec: WalCodec,
+    /// Bytes in the file, where the next entry starts.
+    len: u64,
+    index: Vec<(u64, u64)>,
+    index_out: BufWriter<File>,
+}
+
+impl Wal {
+    /// Opens the WAL for appending. A `next_seq` of zero starts a fresh log;
+    /// otherwise the existing file is continued, as after a logger restart.
+    pub(crate) fn open(path: &Path, next_seq: u64, codec: WalCodec) -> io::Result<Self> {
+        let index_path = index_path(path);
+        let mut options = OpenOptions::new();
+        options.create(true);
+        let (len, index) = if next_seq == 0 {
+            options.write(true).truncate(true);
+            (0, Vec::new())
+        } else {
+            options.append(true);
+            let recovered = recover(path, &index_path, next_seq, codec)?;
+            (recovered.len, recovered.index)
+        };
+        let file = options.open(path)?;
+        // rewritten whole, so it never points past what recovery kept
+        let mut index_out = BufWriter::new(File::create(&index_path)?);
+        for (seq, offset) in &index {
+            writeln!(index_out, "{} {}", seq, offset)?;
+        }
+        index_out.flush()?;
+        Ok(Wal { path: path.to_path_buf(), out: BufWriter::new(file), next_seq, codec, len, index, index_out })
+    }
+
+    /// Opens the WAL to append after every whole entry already in it, as the producer of a
+    /// queue does on each start; an entry a crash cut short at the end is dropped.
+    pub(crate) fn open_end(path: &Path, codec: WalCodec) -> io::Result<Self> {
+        Self::open_before(path, u64::MAX, codec)
+    }
+
+    /// Like `open_end`, but also drops the entries from `limit` on, as a run resumed from a
+    /// checkpoint does with what it logged past the cut and will log again.
+    pub(crate) fn open_before(path: &Path, limit: u64, codec: WalCodec) -> io::Result<Self> {
+        let recovered = recover(path, &index_path(path), limit, codec)?;
+        Self::open(path, recovered.next_seq, codec)
+    }
+
+    /// Appends one record and flushes it, returning its sequence number.
+    pub(crate) fn append(&mut self, record: &str) -> io::Result<u64> {
+        let seq = self.next_seq;
+        let entry = self.codec.encode(&format!("{} {}\n", seq, record))?;
+        self.out.write_all(&entry)?;
+        self.out.flush()?;
+        // noted only once the entry is out, so the index never leads the log
+        if seq.is_multiple_of(INDEX_EVERY) {
+            writeln!(self.index_out, "{} {}", seq, self.len)?;
+            self.index_out.flush()?;
+            self.index.push((seq, self.len));
+        }
+        self.len += entry.len() as u64;
+        self.next_seq += 1;
+        Ok(seq)
+    }
+
+    /// Forces the appended entries onto disk; appends only flush them to the operating system.
+    pub(crate) fn sync(&mut self) -> io::Result<()> {
+        self.out.get_ref().sync_data()
+    }
+
+    /// Sequence number the next append will receive.
+    pub(crate) fn next_seq(&self) -> u64 {
+        self.next_seq
+    }
+
+    /// Reads the log in order starting at `seq`, from the nearest indexed entry at or before it.
+    pub(crate) fn reader_from(&self, seq: u64) -> io::Result<WalReader> {
+        let start = self.index.iter().rev().find(|&&(indexed, _)| indexed <= seq).map_or(0, |&(_, offset)| offset);
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(start))?;
+        Ok(WalReader {
+            lines: BufReader::new(self.codec.reader(file)?).lines(),
+            from: seq,
+        })
+    }
+}
+
+fn index_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".idx");
+    PathBuf::from(name)
+}
+
+/// Index entries from the sidecar, in order; a missing or damaged sidecar reads as empty.
+fn read_index(path: &Path) -> Vec<(u64, u64)> {
+    let Ok(text) = std::fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    let parse = |line: &str| {
+        let (seq, offset) = line.split_once(' ')?;
+        Some((seq.parse().ok()?, offset.parse().ok()?))
+    };
+    text.lines().map(parse).collect::<Option<Vec<_>>>().unwrap_or_default()
+}
+
+/// What recovery kept of a log: its length, the index that covers it, and the sequence after
+/// the last entry.
+struct Recovered {
+    len: u64,
+    index: Vec<(u64, u64)>,
+    next_seq: u64,
+}
+
+/// Cuts the log back to the entries below `next_seq` before a restart appends to it.
+/// A crash can leave the last entry half written, or written but never committed by the
+/// logger; either would otherwise sit in front of the entry that reuses its sequence.
+/// Scanning starts at the last indexed entry that is still committed, or at the top when
+/// the index does not match the log.
+fn recover(path: &Path, index_path: &Path, next_seq: u64, codec: WalCodec) -> io::Result<Recovered> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Recovered { len: 0, index: Vec::new(), next_seq: 0 }),
+        Err(e) => return Err(e),
+    };
+    let file_len = file.metadata()?.len();
+    let mut index: Vec<_> = read_index(index_path).into_iter()
+        .filter(|&(seq, offset)| seq < next_seq && offset < file_len)
+        .collect();
+    loop {
+        let (start_seq, start) = index.last().copied().unwrap_or((0, 0));
+        file.seek(SeekFrom::Start(start))?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        let mut keep = 0;
+        let mut expected = start_seq;
+        while let Some((len, line)) = codec.decode_first(&data[keep..]) {
+            match line.split_once(' ').and_then(|(seq, _)| seq.parse::<u64>().ok()) {
+                Some(seq) if seq == expected && seq < next_seq => {
+                    if seq.is_multiple_of(INDEX_EVERY) && index.last().is_none_or(|&(last, _)| seq > last) {
+                        index.push((seq, start + keep as u64));
+                    }
+                    keep += len;
+                    expected += 1;
+                }
+                _ => break,
+            }
+        }
+        // an index entry that does not land on its sequence is stale; start over from the top
+        if keep == 0 && start > 0 {
+            warn!("WAL index {:?} does not match the log, scanning from the start", index_path);
+            index.clear();
+            continue;
+        }
+        let kept = start + keep as u64;
+        if kept < file_len {
+            warn!("WAL {:?} truncated from {} to {} bytes, dropping entries from seq {} on", path, file_len, kept, expected);
+            OpenOptions::new().write(true).open(path)?.set_len(kept)?;
+        }
+        return Ok(Recovered { len: kept, index, next_seq: expected });
+    }
+}
+
+/// Sequential reader over WAL entries at or after a starting sequence.
+pub(crate) struct WalReader {
+    lines: io::Lines<BufReader<Box<dyn Read>>>,
+    from: u64,
+}
+
+impl Iterator for WalReader {
+    type Item = io::Result<(u64, String)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
+            let Some((seq, record)) = line.split_once(' ') else {
+                return Some(Err(io::Error::new(io::ErrorKind::InvalidData, format!("malformed WAL line '{}'", line))));
+            };
+            let seq: u64 = match seq.parse() {
+                Ok(seq) => seq,
+                Err(e) => return Some(Err(io::Error::new(io::ErrorKind::InvalidData, e))),
+            };
+            if seq >= self.from {
+                return Some(Ok((seq, record.to_string())));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod wal_tests {
+    use super::*;
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("robust-wal-{}.log", crate::arg::new_run_id()))
+    }
+
+    #[test]
+    fn test_append_and_read_back() -> io::Result<()> {
+        let path = temp_path();
+        let mut wal = Wal::open(&path, 0, WalCodec::Plain)?;
+        assert_eq!(wal.append("Msg Fizz")?, 0);
+        assert_eq!(wal.append("Msg Buzz")?, 1);
+        drop(wal);
+
+        // reopening continues the sequence instead of truncating
+        let mut wal = Wal::open(&path, 2, WalCodec::Plain)?;
+        assert_eq!(wal.append("Msg FizzBuzz")?, 2);
+        let tail: Vec<_> = wal.reader_from(1)?.collect::<io::Result<_>>()?;
+        assert_eq!(tail, vec![(1, "Msg Buzz".to_string()), (2, "Msg FizzBuzz".to_string())]);
+        std::fs::remove_file(index_path(&path))?;
+        std::fs::remove_file(path)
+    }
+
+    /// What a crash leaves past the last commit is cut off when the log is reopened.
+    fn check_recovery(codec: WalCodec) -> io::Result<()> {
+        let path = temp_path();
+        let mut wal = Wal::open(&path, 0, codec)?;
+        wal.append("Msg Fizz")?;
+        wal.append("Msg Buzz")?;
+        drop(wal);
+        // seq 1 was written but never committed, and seq 2 was cut short
+        let torn = codec.encode("2 Msg FizzBuzz\n")?;
+        OpenOptions::new().append(true).open(&path)?.write_all(&torn[..torn.len() / 2])?;
+
+        let mut wal = Wal::open(&path, 1, codec)?;
+        assert_eq!(wal.append("Msg Value(7)")?, 1);
+        let all: Vec<_> = wal.reader_from(0)?.collect::<io::Result<_>>()?;
+        assert_eq!(all, vec![(0, "Msg Fizz".to_string()), (1, "Msg Value(7)".to_string())]);
+        std::fs::remove_file(index_path(&path))?;
+        std::fs::remove_file(path)
+    }
+
+    #[test]
+    fn test_index_seeks_and_survives_recovery() -> io::Result<()> {
+        let path = temp_path();
+        let mut wal = Wal::open(&path, 0, WalCodec::Plain)?;
+        for value in 0..INDEX_EVERY * 2 + 10 {
+            wal.append(&format!("Msg Value({})", value))?;
+        }
+        assert_eq!(wal.index.iter().map(|&(seq, _)| seq).collect::<Vec<_>>(), [0, INDEX_EVERY, INDEX_EVERY * 2]);
+        let from = INDEX_EVERY * 2 + 5;
+        assert_eq!(wal.reader_from(from)?.next().transpose()?, Some((from, format!("Msg Value({})", from))));
+        drop(wal);
+
+        // entries from INDEX_EVERY + 3 on were never committed; the index entry past them goes too
+        let wal = Wal::open(&path, INDEX_EVERY + 3, WalCodec::Plain)?;
+        assert_eq!(wal.index, read_index(&index_path(&path)));
+        assert_eq!(wal.index.iter().map(|&(seq, _)| seq).collect::<Vec<_>>(), [0, INDEX_EVERY]);
+        let tail: Vec<_> = wal.reader_from(INDEX_EVERY + 1)?.map(|entry| entry.map(|(seq, _)| seq)).collect::<io::Result<_>>()?;
+        assert_eq!(tail, [INDEX_EVERY + 1, INDEX_EVERY + 2]);
+
+        // a sidecar that points at the wrong place is ignored and rebuilt
+        std::fs::write(index_path(&path), format!("0 0\n{} 7\n", INDEX_EVERY))?;
+        let wal = Wal::open(&path, INDEX_EVERY + 3, WalCodec::Plain)?;
+        assert_eq!(wal.index.iter().map(|&(seq, _)| seq).collect::<Vec<_>>(), [0, INDEX_EVERY]);
+        std::fs::remove_file(index_path(&path))?;
+        std::fs::remove_file(path)
+    }
+
+    #[test]
+    fn test_open_before_keeps_the_log_up_to_a_cut() -> io::Result<()> {
+        let path = temp_path();
+        let mut wal = Wal::open(&path, 0, WalCodec::Plain)?;
+        for value in 0..5 {
+            wal.append(&format!("Msg Value({})", value))?;
+        }
+        drop(wal);
+
+        // a resumed run keeps what came before its cut, and carries on after it
+        let mut wal = Wal::open_before(&path, 3, WalCodec::Plain)?;
+        assert_eq!(wal.append("Msg Fizz")?, 3);
+        drop(wal);
+        // a cut past the end of a log that lost its tail continues from what is there
+        let wal = Wal::open_before(&path, 10, WalCodec::Plain)?;
+        assert_eq!(wal.next_seq(), 4);
+        let all: Vec<_> = wal.reader_from(0)?.map(|entry| entry.map(|(_, record)| record)).collect::<io::Result<_>>()?;
+        assert_eq!(all, ["Msg Value(0)", "Msg Value(1)", "Msg Value(2)", "Msg Fizz"]);
+        std::fs::remove_file(index_path(&path))?;
+        std::fs::remove_file(path)
+    }
+
+    #[test]
+    fn test_recovery_truncates_past_commit() -> io::Result<()> {
+        check_recovery(WalCodec::Plain)
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_recovery_truncates_partial_frame() -> io::Result<()> {
+        check_recovery(WalCodec::Zstd(3))
+    }
+}
@@ -0,0 +1,91 @@
+use std::time::{Duration, Instant};
+use crate::timing::local_minute_of_day;
+use crate::MainArg;
+
+/// How often a gate rereads the wall clock; a window edge is honoured within this long.
+const RECHECK: Duration = Duration::from_secs(1);
+
+/// `--active-window HH:MM-HH:MM` in local time. The start is inside the window and the end
+/// is not; a start after the end wraps past midnight, so `22:00-06:00` covers the night.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ActiveWindow {
+    start: u32,
+    end: u32,
+}
+
+fn parse_time_of_day(text: &str) -> Result<u32, String> {
+    let (hours, minutes) = text.trim().split_once(':').ok_or_else(|| format!("expected HH:MM, got '{}'", text))?;
+    let hours: u32 = hours.parse().map_err(|e| format!("bad hour in '{}': {}", text, e))?;
+    let minutes: u32 = minutes.parse().map_err(|e| format!("bad minute in '{}': {}", text, e))?;
+    if hours > 23 || minutes > 59 {
+        return Err(format!("'{}' is not a time of day", text));
+    }
+    Ok(hours * 60 + minutes)
+}
+
+/// Parses `HH:MM-HH:MM`; a window that starts where it ends is refused as ambiguous.
+pub(crate) fn parse_active_window(text: &str) -> Result<ActiveWindow, String> {
+    let (start, end) = text.split_once('-').ok_or_else(|| format!("expected HH:MM-HH:MM, got '{}'", text))?;
+    let (start, end) = (parse_time_of_day(start)?, parse_time_of_day(end)?);
+    if start == end {
+        return Err(format!("window '{}' starts where it ends", text));
+    }
+    Ok(ActiveWindow { start, end })
+}
+
+impl ActiveWindow {
+    pub(crate) fn contains(&self, minute_of_day: u32) -> bool {
+        if self.start < self.end {
+            (self.start..self.end).contains(&minute_of_day)
+        } else {
+            minute_of_day >= self.start || minute_of_day < self.end
+        }
+    }
+}
+
+/// Tells an actor whether the operating window is open, rereading the clock at most once a second.
+/// Always open when no window is configured.
+pub(crate) struct WindowGate {
+    window: Option<ActiveWindow>,
+    checked: Option<Instant>,
+    open: bool,
+}
+
+impl WindowGate {
+    pub(crate) fn from_args(args: &MainArg) -> Self {
+        WindowGate { window: args.active_window, checked: None, open: true }
+    }
+
+    pub(crate) fn is_open(&mut self) -> bool {
+        if let Some(window) = self.window
+            && self.checked.is_none_or(|at| at.elapsed() >= RECHECK) {
+            self.checked = Some(Instant::now());
+            self.open = window.contains(local_minute_of_day());
+        }
+        self.open
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod window_tests {
+    use super::*;
+
+    #[test]
+    fn test_window_contains() {
+        let day = parse_active_window("08:00-18:00").expect("valid");
+        assert!(!day.contains(7 * 60 + 59));
+        assert!(day.contains(8 * 60));
+        assert!(day.contains(17 * 60 + 59));
+        assert!(!day.contains(18 * 60));
+
+        let night = parse_active_window("22:00-06:30").expect("valid");
+        assert!(night.contains(23 * 60));
+        assert!(night.contains(0));
+        assert!(night.contains(6 * 60 + 29));
+        assert!(!night.contains(12 * 60));
+
+        assert!(parse_active_window("08:00-08:00").is_err());
+        assert!(parse_active_window("24:00-06:00").is_err());
+        assert!(parse_active_window("8-18").is_err());
+    }
+}
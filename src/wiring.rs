@@ -0,0 +1,352 @@
+use steady_state::*;
+use steady_state::actor_builder::{ActorBuilder, ScheduleAs};
+use steady_state::channel_builder::ChannelBuilder;
+use crate::ack::SourceAck;
+use crate::actor::dead_letter::DeadLetter;
+use crate::actor::enricher::{LookupReply, LookupRequest};
+use crate::actor::heartbeat::PipelineCommand;
+use crate::actor::reconciler::{CounterSample, StatusReply, StatusRequest, LANE_NAMES, METRICS_GENERATOR, METRICS_LANES, METRICS_WORKER};
+use crate::actor::worker::FizzBuzzMessage;
+use crate::checkpoint::WorkerCheckpoint;
+use crate::event_time::BeatTime;
+use crate::multi_graph::GraphRole;
+use crate::startup::{Readiness, StartGate};
+use crate::tenant::TenantSample;
+use crate::throttle::CommitFeedback;
+use crate::timing::Envelope;
+use crate::validation::RejectedValue;
+use crate::{MainArg, PipelineState};
+
+/// One stage of the pipeline as `build_graph` sees it. Each actor module implements it for a
+/// unit struct, and `stages` lists them, so adding a stage means implementing this trait and
+/// adding one line there rather than editing the wiring.
+pub trait RobustActor {
+    /// The name the actor is built under, also the key for `--nice`.
+    fn name(&self) -> &'static str;
+    /// The links of `Wiring` it reads and writes, by field name.
+    fn inputs(&self) -> &'static [&'static str];
+    fn outputs(&self) -> &'static [&'static str];
+    /// Whether the stage is part of the pipeline with these arguments. Most always are.
+    fn enabled(&self, _args: &MainArg) -> bool {
+        true
+    }
+    /// Whether it may share a thread with the other stages that do, unless given a niceness of its own.
+    fn shares_thread(&self, _args: &MainArg) -> bool {
+        false
+    }
+    /// The stages that must be ready before it starts: `build_graph` refuses an order that
+    /// names a stage left out of the pipeline or goes round in a cycle.
+    fn depends_on(&self, _args: &MainArg) -> &'static [&'static str] {
+        &[]
+    }
+    /// Whether it announces its own readiness through its start gate, rather than being ready
+    /// for the stages after it as soon as it is built.
+    fn announces_ready(&self) -> bool {
+        false
+    }
+    /// Builds the actor on `builder`, which already carries its name, taking its channel ends
+    /// and its persistent state from `wiring`.
+    fn build(&self, builder: ActorBuilder, wiring: &Wiring, schedule: ScheduleAs<'_>);
+}
+
+/// Both ends of one channel.
+pub struct Link<T> {
+    pub tx: LazySteadyTx<T>,
+    pub rx: LazySteadyRx<T>,
+}
+
+impl<T> Link<T> {
+    fn new(channel_builder: &ChannelBuilder) -> Self {
+        let (tx, rx) = channel_builder.build();
+        Link { tx, rx }
+    }
+}
+
+/// Every channel in the pipeline and every actor's state, made once by `build_graph` before
+/// any stage is built. A link whose writer or reader is left out just stays empty.
+pub struct Wiring {
+    pub args: MainArg,
+    pub state: PipelineState,
+    /// Which stages are ready for those that start after them.
+    pub readiness: Readiness,
+    /// Operator commands for the heartbeat and the logger, and status queries answered by the reconciler.
+    pub control: Link<PipelineCommand>,
+    pub logger_control: Link<PipelineCommand>,
+    pub status_request: Link<StatusRequest>,
+    pub status_reply: Link<StatusReply>,
+    /// The logger holds the heartbeat and generator through a long sink outage when asked to.
+    pub heartbeat_flow: Link<PipelineCommand>,
+    pub generator_flow: Link<PipelineCommand>,
+    /// With --flow-target-inflight the logger reports its commit rate for the generator to steer by.
+    pub commit_feedback: Link<CommitFeedback>,
+    /// Per-tenant logged counts, published by the logger for the reconciler.
+    pub tenants: Link<TenantSample>,
+    pub heartbeat: Link<BeatTime>,
+    pub generated: Link<Envelope<u64>>,
+    pub classified: Link<Envelope<FizzBuzzMessage>>,
+    pub rejected: Link<RejectedValue>,
+    /// Worker snapshots travel beside the data stream and meet their barrier marker at the logger.
+    pub checkpoint: Link<WorkerCheckpoint>,
+    /// With --ack-after-commit the worker tells a network source what became of each value it sent.
+    pub acks: Link<SourceAck>,
+    /// Showstoppers the worker and the logger drop, one link each, for the dead-letter actor.
+    pub worker_dead_letter: Link<DeadLetter>,
+    pub logger_dead_letter: Link<DeadLetter>,
+    /// With --enricher-timeout-ms the worker asks the enricher on one link and hears back on the other.
+    pub lookup_request: Link<LookupRequest>,
+    pub lookup_reply: Link<LookupReply>,
+    /// With --workers, the links of each worker in the pool, in shard order.
+    pub pool: Vec<PoolLinks>,
+    /// One metrics lane per counting actor, all read by the reconciler.
+    pub metrics_tx: LazySteadyTxBundle<CounterSample, METRICS_LANES>,
+    pub metrics_rx: LazySteadyRxBundle<CounterSample, METRICS_LANES>,
+}
+
+impl Wiring {
+    pub fn new(channel_builder: &ChannelBuilder, args: MainArg) -> Self {
+        let (metrics_tx, metrics_rx) = channel_builder.build_channel_bundle();
+        let workers = if args.workers > 1 { args.workers as usize } else { 0 };
+        Wiring {
+            state: PipelineState::new(workers),
+            pool: (0..workers).map(|_| PoolLinks::new(channel_builder)).collect(),
+            args,
+            readiness: Readiness::default(),
+            control: Link::new(channel_builder),
+            logger_control: Link::new(channel_builder),
+            status_request: Link::new(channel_builder),
+            status_reply: Link::new(channel_builder),
+            heartbeat_flow: Link::new(channel_builder),
+            generator_flow: Link::new(channel_builder),
+            commit_feedback: Link::new(channel_builder),
+            tenants: Link::new(channel_builder),
+            heartbeat: Link::new(channel_builder),
+            generated: Link::new(channel_builder),
+            classified: Link::new(channel_builder),
+            rejected: Link::new(channel_builder),
+            checkpoint: Link::new(channel_builder),
+            acks: Link::new(channel_builder),
+            worker_dead_letter: Link::new(channel_builder),
+            logger_dead_letter: Link::new(channel_builder),
+            lookup_request: Link::new(channel_builder),
+            lookup_reply: Link::new(channel_builder),
+            metrics_tx,
+            metrics_rx,
+        }
+    }
+
+    /// The start gate for `stage`, shut until the stages it depends on are ready.
+    pub fn start_gate(&self, stage: &dyn RobustActor) -> StartGate {
+        self.readiness.gate(stage.name(), stage.depends_on(&self.args))
+    }
+
+    /// Adds every link the enabled `stages` read to the state's channel probes, named for the
+    /// stages at each end, so the final report can say which edge anything was left on.
+    pub(crate) fn probe_residue(&self, stages: &[Box<dyn RobustActor>]) {
+        let enabled: Vec<&dyn RobustActor> = stages.iter().map(|stage| stage.as_ref()).filter(|stage| stage.enabled(&self.args)).collect();
+        let ends = |name: &str| -> [String; 2] {
+            let at = |side: fn(&dyn RobustActor) -> &'static [&'static str]| -> String {
+                enabled.iter().filter(|stage| side(**stage).contains(&name)).map(|stage| stage.name()).collect::<Vec<_>>().join("+")
+            };
+            [at(|stage| stage.outputs()), at(|stage| stage.inputs())]
+        };
+        let probes = &self.state.channels;
+        let mut read: Vec<&str> = Vec::new();
+        for name in enabled.iter().flat_map(|stage| stage.inputs().iter().copied()) {
+            if !read.contains(&name) {
+                read.push(name);
+            }
+        }
+        for name in read {
+            let [from, to] = ends(name);
+            let channel = name.to_string();
+            match name {
+                "control" => probes.add(channel, &from, &to, self.control.rx.clone(), |_| None),
+                "logger_control" => probes.add(channel, &from, &to, self.logger_control.rx.clone(), |_| None),
+                "status_request" => probes.add(channel, &from, &to, self.status_request.rx.clone(), |_| None),
+                "status_reply" => probes.add(channel, &from, &to, self.status_reply.rx.clone(), |_| None),
+                "heartbeat_flow" => probes.add(channel, &from, &to, self.heartbeat_flow.rx.clone(), |_| None),
+                "generator_flow" => probes.add(channel, &from, &to, self.generator_flow.rx.clone(), |_| None),
+                "commit_feedback" => probes.add(channel, &from, &to, self.commit_feedback.rx.clone(), |_| None),
+                "tenants" => probes.add(channel, &from, &to, self.tenants.rx.clone(), |_| None),
+                "heartbeat" => probes.add(channel, &from, &to, self.heartbeat.rx.clone(), |beat| Some(beat.beat)),
+                "generated" => probes.add(channel, &from, &to, self.generated.rx.clone(), |e| Some(e.payload)),
+                "classified" => probes.add(channel, &from, &to, self.classified.rx.clone(), |e| Some(e.seq)),
+                "rejected" => probes.add(channel, &from, &to, self.rejected.rx.clone(), |r| Some(r.value)),
+                "checkpoint" => probes.add(channel, &from, &to, self.checkpoint.rx.clone(), |c| Some(c.seq)),
+                "acks" => probes.add(channel, &from, &to, self.acks.rx.clone(), |a| Some(a.seq)),
+                "worker_dead_letter" => probes.add(channel, &from, &to, self.worker_dead_letter.rx.clone(), |d| Some(d.seq)),
+                "logger_dead_letter" => probes.add(channel, &from, &to, self.logger_dead_letter.rx.clone(), |d| Some(d.seq)),
+                "lookup_request" => probes.add(channel, &from, &to, self.lookup_request.rx.clone(), |l| Some(l.id)),
+                "lookup_reply" => probes.add(channel, &from, &to, self.lookup_reply.rx.clone(), |l| Some(l.id)),
+                // each lane is written by whatever writes the stream it counts
+                "metrics" => for (lane, rx) in self.metrics_rx.iter().enumerate() {
+                    let [from, _] = match lane {
+                        METRICS_GENERATOR => ends("generated"),
+                        METRICS_WORKER => ends("classified"),
+                        _ => [crate::NAME_LOGGER.to_string(), String::new()],
+                    };
+                    probes.add(format!("metrics[{}]", LANE_NAMES[lane]), &from, &to, rx.clone(), |_| None);
+                },
+                // each worker of the pool has its own links, between it and the router or the fan-in
+                shard => for (links, worker) in self.pool.iter().zip(crate::NAME_POOL) {
+                    let [from, to] = if enabled.iter().any(|stage| stage.name() == worker && stage.outputs().contains(&shard)) {
+                        [worker.to_string(), to.clone()]
+                    } else {
+                        [from.clone(), worker.to_string()]
+                    };
+                    let channel = format!("{}[{}]", shard, worker);
+                    match shard {
+                        "shard_heartbeat" => probes.add(channel, &from, &to, links.heartbeat.rx.clone(), |beat| Some(beat.beat)),
+                        "shard" => probes.add(channel, &from, &to, links.generated.rx.clone(), |e| Some(e.payload)),
+                        "shard_classified" => probes.add(channel, &from, &to, links.classified.rx.clone(), |e| Some(e.seq)),
+                        "shard_rejected" => probes.add(channel, &from, &to, links.rejected.rx.clone(), |r| Some(r.value)),
+                        "shard_metrics" => probes.add(channel, &from, &to, links.metrics.rx.clone(), |_| None),
+                        "shard_dead_letter" => probes.add(channel, &from, &to, links.dead_letter.rx.clone(), |d| Some(d.seq)),
+                        _ => {}
+                    }
+                },
+            }
+        }
+    }
+}
+
+/// The links of one worker in a `--workers` pool: the router feeds it and the fan-in drains it.
+/// Its checkpoint, ack and lookup links stay empty, as those options are refused with a pool.
+pub struct PoolLinks {
+    pub heartbeat: Link<BeatTime>,
+    pub generated: Link<Envelope<u64>>,
+    pub classified: Link<Envelope<FizzBuzzMessage>>,
+    pub rejected: Link<RejectedValue>,
+    pub metrics: Link<CounterSample>,
+    pub dead_letter: Link<DeadLetter>,
+    pub checkpoint: Link<WorkerCheckpoint>,
+    pub acks: Link<SourceAck>,
+    pub lookup_request: Link<LookupRequest>,
+    pub lookup_reply: Link<LookupReply>,
+}
+
+impl PoolLinks {
+    fn new(channel_builder: &ChannelBuilder) -> Self {
+        PoolLinks {
+            heartbeat: Link::new(channel_builder),
+            generated: Link::new(channel_builder),
+            classified: Link::new(channel_builder),
+            rejected: Link::new(channel_builder),
+            metrics: Link::new(channel_builder),
+            dead_letter: Link::new(channel_builder),
+            checkpoint: Link::new(channel_builder),
+            acks: Link::new(channel_builder),
+            lookup_request: Link::new(channel_builder),
+            lookup_reply: Link::new(channel_builder),
+        }
+    }
+}
+
+/// What feeds the worker: an external source when one is configured, gRPC first, then the
+/// `--queue-dir` queue, else the generator.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Source {
+    Generator,
+    Queue,
+    Tcp,
+    #[cfg(feature = "grpc")]
+    Grpc,
+    #[cfg(feature = "mqtt")]
+    Mqtt,
+}
+
+pub(crate) fn source(args: &MainArg) -> Source {
+    #[cfg(feature = "grpc")]
+    if args.grpc_listen.is_some() {
+        return Source::Grpc;
+    }
+    #[cfg(feature = "mqtt")]
+    if args.mqtt_source.is_some() {
+        return Source::Mqtt;
+    }
+    if args.tcp_listen.is_some() {
+        return Source::Tcp;
+    }
+    // the ingest graph of a multi-graph run is the generator filling the queue, not reading it
+    if args.queue_dir.is_some() && args.graph == GraphRole::Pipeline {
+        return Source::Queue;
+    }
+    Source::Generator
+}
+
+/// Every stage there is, in the order `build_graph` builds them; each decides whether it is enabled.
+pub fn stages() -> Vec<Box<dyn RobustActor>> {
+    let mut stages: Vec<Box<dyn RobustActor>> = vec![
+        Box::new(crate::actor::heartbeat::Heartbeat),
+        Box::new(crate::actor::generator::Generator),
+        #[cfg(feature = "grpc")]
+        Box::new(crate::actor::grpc_source::GrpcSource),
+        #[cfg(feature = "mqtt")]
+        Box::new(crate::actor::mqtt_source::MqttSource),
+        Box::new(crate::actor::queue_source::QueueSource),
+        Box::new(crate::actor::tcp_source::TcpSource),
+        Box::new(crate::actor::worker::Worker),
+        Box::new(crate::actor::router::Router),
+    ];
+    for shard in 0..crate::NAME_POOL.len() {
+        stages.push(Box::new(crate::actor::worker::PoolWorker(shard)));
+    }
+    stages.extend::<Vec<Box<dyn RobustActor>>>(vec![
+        Box::new(crate::actor::fan_in::FanIn),
+        Box::new(crate::actor::enricher::Enricher),
+        Box::new(crate::actor::logger::Logger),
+        Box::new(crate::actor::dead_letter::DeadLetters),
+        Box::new(crate::actor::reconciler::Reconciler),
+        #[cfg(unix)]
+        Box::new(crate::actor::control::Control),
+    ]);
+    stages
+}
+
+#[cfg(test)]
+pub(crate) mod wiring_tests {
+    use super::*;
+
+    /// With every optional stage in, each link a stage reads has a writer and each link it
+    /// writes has a reader.
+    #[cfg(unix)]
+    #[test]
+    fn test_every_link_has_both_ends() {
+        let args = MainArg { control_socket: Some("robust.sock".into()), enricher_timeout_ms: Some(50), ..Default::default() };
+        let stages: Vec<_> = stages().into_iter().filter(|stage| stage.enabled(&args)).collect();
+        let names: Vec<_> = stages.iter().map(|stage| stage.name()).collect();
+        assert_eq!(names, ["HEARTBEAT", "GENERATOR", "WORKER", "ENRICHER", "LOGGER", "DEAD_LETTER", "RECONCILER", "CONTROL"]);
+        for stage in &stages {
+            for input in stage.inputs() {
+                assert!(stages.iter().any(|s| s.outputs().contains(input)), "nothing writes {} for {}", input, stage.name());
+            }
+            for output in stage.outputs() {
+                if *output == "acks" {
+                    continue; // read only by a network source
+                }
+                if *output == "shard_heartbeat" {
+                    continue; // read only by a pool
+                }
+                assert!(stages.iter().any(|s| s.inputs().contains(output)), "nothing reads {} from {}", output, stage.name());
+            }
+        }
+    }
+
+    /// With a pool the router takes the single worker's place and the fan-in feeds the logger.
+    #[cfg(unix)]
+    #[test]
+    fn test_workers_run_as_a_pool() {
+        let args = MainArg { control_socket: Some("robust.sock".into()), workers: 3, ..Default::default() };
+        let stages: Vec<_> = stages().into_iter().filter(|stage| stage.enabled(&args)).collect();
+        let names: Vec<_> = stages.iter().map(|stage| stage.name()).collect();
+        assert_eq!(names, ["HEARTBEAT", "GENERATOR", "ROUTER", "WORKER_0", "WORKER_1", "WORKER_2", "FANIN", "LOGGER", "DEAD_LETTER", "RECONCILER", "CONTROL"]);
+        for stage in &stages {
+            for input in stage.inputs() {
+                if *input == "checkpoint" {
+                    continue; // checkpoints are refused with a pool
+                }
+                assert!(stages.iter().any(|s| s.outputs().contains(input)), "nothing writes {} for {}", input, stage.name());
+            }
+        }
+    }
+}